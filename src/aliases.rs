@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+
+/// The aliases defined with `alias`, which stand in for the first word of a
+/// command.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Aliases {
+    aliases: BTreeMap<String, String>,
+}
+
+impl Aliases {
+    pub(crate) fn get(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(String::as_str)
+    }
+
+    pub(crate) fn set(&mut self, name: &str, value: &str) {
+        self.aliases.insert(name.to_owned(), value.to_owned());
+    }
+
+    /// Removes `name`, returning whether it was defined.
+    pub(crate) fn remove(&mut self, name: &str) -> bool {
+        self.aliases.remove(name).is_some()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.aliases.clear();
+    }
+
+    /// Every alias and its value, sorted by name.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.aliases
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+/// Whether `name` can be defined as an alias. It has to read as a single
+/// unquoted word, with no `=` to split it from its value.
+pub(crate) fn is_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains([
+            ' ', '\t', '\n', '=', '\'', '"', '\\', '$', '`', '/', '<', '>', '|', '&', ';', '(', ')',
+        ])
+}
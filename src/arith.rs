@@ -0,0 +1,188 @@
+use crate::variables::Variables;
+
+/// How many variables deep a name may refer to another before the
+/// evaluation gives up, so `a=b b=a` doesn't recurse forever.
+const MAX_DEPTH: usize = 32;
+
+/// Evaluates `expr` as integer arithmetic, the way a variable declared with
+/// `declare -i` reads the values given to it.
+///
+/// It knows decimal numbers, `+`, `-`, `*`, `/`, and `%` with the usual
+/// precedence, unary signs, and parentheses. A variable name stands for its
+/// own value evaluated the same way, or 0 if it is unset or empty. An error
+/// is the message to show.
+pub(crate) fn evaluate(expr: &str, vars: &Variables) -> Result<i64, String> {
+    evaluate_at(expr, vars, 0)
+}
+
+fn evaluate_at(expr: &str, vars: &Variables, depth: usize) -> Result<i64, String> {
+    if depth > MAX_DEPTH {
+        return Err("expression recursion level exceeded".into());
+    }
+    let mut parser = Parser {
+        rest: expr,
+        vars,
+        depth,
+    };
+    if parser.at_end() {
+        return Ok(0);
+    }
+    let value = parser.sum()?;
+    match parser.at_end() {
+        true => Ok(value),
+        false => Err(format!(
+            "syntax error in expression (error token is \"{}\")",
+            parser.rest.trim()
+        )),
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+    vars: &'a Variables,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn at_end(&mut self) -> bool {
+        self.rest = self.rest.trim_start();
+        self.rest.is_empty()
+    }
+
+    /// Moves past `c` if it comes next.
+    fn eat(&mut self, c: char) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn sum(&mut self) -> Result<i64, String> {
+        let mut value = self.product()?;
+        loop {
+            if self.eat('+') {
+                value = value.wrapping_add(self.product()?);
+            } else if self.eat('-') {
+                value = value.wrapping_sub(self.product()?);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<i64, String> {
+        let mut value = self.unary()?;
+        loop {
+            let op = match () {
+                _ if self.eat('*') => '*',
+                _ if self.eat('/') => '/',
+                _ if self.eat('%') => '%',
+                _ => return Ok(value),
+            };
+            let operand = self.unary()?;
+            value = match op {
+                '*' => value.wrapping_mul(operand),
+                _ if operand == 0 => return Err("division by 0".into()),
+                '/' => value.wrapping_div(operand),
+                _ => value.wrapping_rem(operand),
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        if self.eat('-') {
+            return Ok(self.unary()?.wrapping_neg());
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+        if self.eat('(') {
+            let value = self.sum()?;
+            if !self.eat(')') {
+                return Err("missing `)'".into());
+            }
+            return Ok(value);
+        }
+        self.operand()
+    }
+
+    /// A number or a variable name.
+    fn operand(&mut self) -> Result<i64, String> {
+        self.rest = self.rest.trim_start();
+        let len = self
+            .rest
+            .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+            .unwrap_or(self.rest.len());
+        let (word, rest) = self.rest.split_at(len);
+        if word.is_empty() {
+            return Err(match self.rest.is_empty() {
+                true => "syntax error: operand expected".into(),
+                false => format!(
+                    "syntax error: operand expected (error token is \"{}\")",
+                    self.rest
+                ),
+            });
+        }
+        self.rest = rest;
+
+        if word.starts_with(|c: char| c.is_ascii_digit()) {
+            return word
+                .parse()
+                .map_err(|_| format!("{word}: value too great for base"));
+        }
+        let value = self.vars.get(word).unwrap_or_default();
+        evaluate_at(&value, self.vars, self.depth + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> Result<i64, String> {
+        evaluate(expr, &Variables::default())
+    }
+
+    #[test]
+    fn precedence_and_parentheses() {
+        for (expr, value) in [
+            ("", 0),
+            ("42", 42),
+            ("1 + 2 * 3", 7),
+            ("(1 + 2) * 3", 9),
+            ("10 - 4 - 3", 3),
+            ("-7 / 2", -3),
+            ("-7 % 3", -1),
+            ("- -5", 5),
+        ] {
+            assert_eq!(eval(expr), Ok(value), "{expr:?}");
+        }
+    }
+
+    #[test]
+    fn names_stand_for_their_values() {
+        let mut vars = Variables::default();
+        vars.set("RUSH_ARITH_TEST_A", "4");
+        vars.set("RUSH_ARITH_TEST_B", "RUSH_ARITH_TEST_A * 2");
+        assert_eq!(evaluate("RUSH_ARITH_TEST_B + 1", &vars), Ok(9));
+        assert_eq!(evaluate("RUSH_ARITH_TEST_UNSET", &vars), Ok(0));
+
+        vars.set("RUSH_ARITH_TEST_A", "RUSH_ARITH_TEST_A");
+        assert!(evaluate("RUSH_ARITH_TEST_A", &vars).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(eval("1 / 0"), Err("division by 0".into()));
+        assert_eq!(eval("(1"), Err("missing `)'".into()));
+        assert_eq!(eval("1 +"), Err("syntax error: operand expected".into()));
+        assert_eq!(
+            eval("2 3"),
+            Err("syntax error in expression (error token is \"3\")".into())
+        );
+    }
+}
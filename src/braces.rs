@@ -0,0 +1,261 @@
+use std::borrow::Cow;
+
+use crate::util::{Word, WordPart};
+
+/// One unit of a word as brace expansion sees it: an unquoted character,
+/// which may be part of a brace construct, or anything else, which is carried
+/// through untouched.
+#[derive(Clone, Debug, PartialEq)]
+enum Atom<'a> {
+    Char(char),
+    Part(WordPart<'a>),
+}
+
+/// Expands the brace constructs in `word` into the words they stand for, so
+/// `a{b,c}d` becomes `abd acd` and `{1..3}` becomes `1 2 3`.
+///
+/// Only unquoted braces count. A brace pair that is neither a list nor a
+/// sequence, like `{}` or `{a}`, is left as it is.
+pub(crate) fn expand<'a>(word: &Word<'a>) -> Vec<Word<'a>> {
+    let atoms = atoms(word);
+    if !atoms.contains(&Atom::Char('{')) {
+        return vec![word.clone()];
+    }
+
+    expand_atoms(&atoms).into_iter().map(to_word).collect()
+}
+
+fn atoms<'a>(word: &Word<'a>) -> Vec<Atom<'a>> {
+    let mut atoms = Vec::new();
+    for part in word.parts() {
+        match part {
+            WordPart::Literal(text) => atoms.extend(text.chars().map(Atom::Char)),
+            part => atoms.push(Atom::Part(part.clone())),
+        }
+    }
+    atoms
+}
+
+fn to_word(atoms: Vec<Atom<'_>>) -> Word<'_> {
+    Word::from_parts(
+        atoms
+            .into_iter()
+            .map(|atom| match atom {
+                Atom::Char(c) => WordPart::Literal(Cow::Owned(c.into())),
+                Atom::Part(part) => part,
+            })
+            .collect(),
+    )
+}
+
+fn expand_atoms<'a>(atoms: &[Atom<'a>]) -> Vec<Vec<Atom<'a>>> {
+    for (open, atom) in atoms.iter().enumerate() {
+        if *atom != Atom::Char('{') {
+            continue;
+        }
+        let Some(close) = find_close(atoms, open) else {
+            continue;
+        };
+        let Some(alternatives) = alternatives(&atoms[open + 1..close]) else {
+            continue;
+        };
+
+        let prefix = &atoms[..open];
+        let suffixes = expand_atoms(&atoms[close + 1..]);
+        let mut words = Vec::new();
+
+        for alternative in alternatives {
+            for middle in expand_atoms(&alternative) {
+                for suffix in &suffixes {
+                    words.push([prefix, &middle, suffix].concat());
+                }
+            }
+        }
+        return words;
+    }
+
+    vec![atoms.to_vec()]
+}
+
+/// Returns the index of the `}` matching the `{` at `open`.
+fn find_close(atoms: &[Atom], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, atom) in atoms.iter().enumerate().skip(open) {
+        match atom {
+            Atom::Char('{') => depth += 1,
+            Atom::Char('}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits the inside of a brace pair into the alternatives it expands to, or
+/// returns `None` if it isn't a brace expansion at all.
+fn alternatives<'a>(body: &[Atom<'a>]) -> Option<Vec<Vec<Atom<'a>>>> {
+    let mut pieces = vec![Vec::new()];
+    let mut depth = 0;
+
+    for atom in body {
+        match atom {
+            Atom::Char('{') => depth += 1,
+            Atom::Char('}') => depth -= 1,
+            Atom::Char(',') if depth == 0 => {
+                pieces.push(Vec::new());
+                continue;
+            }
+            _ => {}
+        }
+        pieces.last_mut().unwrap().push(atom.clone());
+    }
+
+    if pieces.len() > 1 {
+        return Some(pieces);
+    }
+
+    let text = body
+        .iter()
+        .map(|atom| match atom {
+            Atom::Char(c) => Some(*c),
+            Atom::Part(_) => None,
+        })
+        .collect::<Option<String>>()?;
+
+    let items = sequence(&text)?;
+    Some(
+        items
+            .into_iter()
+            .map(|item| item.chars().map(Atom::Char).collect())
+            .collect(),
+    )
+}
+
+/// Expands a sequence like `1..5`, `a..e`, or `01..10..3` into its items.
+fn sequence(text: &str) -> Option<Vec<String>> {
+    let mut bounds = text.split("..");
+    let (start, end) = (bounds.next()?, bounds.next()?);
+    let step = match bounds.next() {
+        Some(step) => step.parse::<i64>().ok()?.unsigned_abs().max(1),
+        None => 1,
+    };
+    if bounds.next().is_some() {
+        return None;
+    }
+
+    if let (Ok(from), Ok(to)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        // A leading zero on either bound pads every item to the same width
+        let padded = |s: &str| {
+            s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0')
+        };
+        let width = match padded(start) || padded(end) {
+            true => start.len().max(end.len()),
+            false => 0,
+        };
+        let items = range(from, to, step)
+            .map(|n| match n < 0 {
+                true => format!("-{:0>1$}", n.unsigned_abs(), width.saturating_sub(1)),
+                false => format!("{n:0width$}"),
+            })
+            .collect();
+        return Some(items);
+    }
+
+    let (mut start_chars, mut end_chars) = (start.chars(), end.chars());
+    match (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) {
+        (Some(from), None, Some(to), None) => Some(
+            range(u32::from(from).into(), u32::from(to).into(), step)
+                .filter_map(|n| char::from_u32(n as u32).map(String::from))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// Counts from `from` to `to` inclusive by `step`, downwards if `to` is
+/// smaller.
+fn range(from: i64, to: i64, step: u64) -> impl Iterator<Item = i64> {
+    let count = from.abs_diff(to) / step + 1;
+    let step = if to < from {
+        -(step as i64)
+    } else {
+        step as i64
+    };
+    (0..count as i64).map(move |i| from + i * step)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::SimpleCommand;
+
+    fn braces(input: &str) -> Vec<String> {
+        let words = SimpleCommand::parse(input).unwrap().words;
+        words
+            .iter()
+            .flat_map(expand)
+            .map(|word| word.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            braces("mkdir -p src/{bin,lib}"),
+            ["mkdir", "-p", "src/bin", "src/lib"]
+        );
+        assert_eq!(braces("x{a,,b}y"), ["xay", "xy", "xby"]);
+        assert_eq!(braces("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+    }
+
+    #[test]
+    fn nested() {
+        assert_eq!(braces("{a,b{1,2},c}"), ["a", "b1", "b2", "c"]);
+        assert_eq!(braces("{a{x,y}}"), ["{ax}", "{ay}"]);
+    }
+
+    #[test]
+    fn numeric_sequences() {
+        assert_eq!(
+            braces("file{1..3}.txt"),
+            ["file1.txt", "file2.txt", "file3.txt"]
+        );
+        assert_eq!(braces("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(braces("{-1..1}"), ["-1", "0", "1"]);
+        assert_eq!(braces("{1..10..4}"), ["1", "5", "9"]);
+        assert_eq!(braces("{08..10}"), ["08", "09", "10"]);
+    }
+
+    #[test]
+    fn character_sequences() {
+        assert_eq!(braces("{a..e..2}"), ["a", "c", "e"]);
+        assert_eq!(braces("{C..A}"), ["C", "B", "A"]);
+    }
+
+    #[test]
+    fn not_brace_expansions() {
+        for input in ["{}", "{a}", "{a", "a}", "{1..}", "{a..bc}", "{1..2..3..4}"] {
+            assert_eq!(braces(input), [input], "{input}");
+        }
+    }
+
+    #[test]
+    fn quoted_braces_are_literal() {
+        assert_eq!(braces("'{a,b}'"), ["{a,b}"]);
+        assert_eq!(braces("{a,'b c'}"), ["a", "b c"]);
+    }
+
+    #[test]
+    fn expansions_are_carried_through() {
+        assert_eq!(braces("{$A,b}"), ["$A", "b"]);
+    }
+}
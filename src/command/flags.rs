@@ -0,0 +1,250 @@
+use crate::{command::CommandType, status::ExitStatus, util::RushError};
+
+/// The options a builtin was invoked with, in the order they appeared.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Flags {
+    opts: Vec<(char, Option<String>)>,
+}
+
+impl Flags {
+    pub(crate) fn has(&self, flag: char) -> bool {
+        self.opts.iter().any(|(c, _)| *c == flag)
+    }
+
+    /// Returns whichever of `flags` was given last, for options that
+    /// override each other like `-L` and `-P`.
+    pub(crate) fn last_of(&self, flags: &str) -> Option<char> {
+        self.opts
+            .iter()
+            .rev()
+            .map(|(c, _)| *c)
+            .find(|c| flags.contains(*c))
+    }
+
+    /// Returns the argument given to `flag`. When the flag is repeated, the
+    /// last one wins.
+    pub(crate) fn value(&self, flag: char) -> Option<&str> {
+        self.opts
+            .iter()
+            .rev()
+            .find(|(c, _)| *c == flag)
+            .and_then(|(_, value)| value.as_deref())
+    }
+}
+
+/// Splits `args` (including the command name at index 0) into options and
+/// operands, getopt style. `spec` lists the accepted option characters, with a
+/// trailing `:` marking options that take an argument (e.g. `"ab:"`).
+///
+/// Option parsing stops at the first operand or at `--`, which is consumed.
+/// Anything after that point is an operand, so a `--` appearing where an
+/// operand is already expected is passed through literally.
+pub(crate) fn parse_flags<'a>(
+    type_: &CommandType,
+    args: &'a [String],
+    spec: &str,
+) -> Result<(Flags, &'a [String]), RushError> {
+    parse(type_, args, spec, false)
+}
+
+/// Like [`parse_flags`], but an argument containing an unknown option is
+/// treated as the first operand instead of an error. This is how `echo`
+/// behaves in other shells.
+pub(crate) fn parse_flags_lenient<'a>(
+    type_: &CommandType,
+    args: &'a [String],
+    spec: &str,
+) -> Result<(Flags, &'a [String]), RushError> {
+    parse(type_, args, spec, true)
+}
+
+fn parse<'a>(
+    type_: &CommandType,
+    args: &'a [String],
+    spec: &str,
+    lenient: bool,
+) -> Result<(Flags, &'a [String]), RushError> {
+    let usage_error = |msg: String| RushError::CommandError {
+        type_: type_.clone(),
+        msg,
+        status: ExitStatus::USAGE,
+    };
+
+    let mut flags = Flags::default();
+    let mut i = 1;
+
+    while let Some(arg) = args.get(i) {
+        if arg == "--" {
+            i += 1;
+            break;
+        }
+
+        // A lone `-` and anything not starting with `-` are operands
+        let Some(chars) = arg.strip_prefix('-').filter(|rest| !rest.is_empty()) else {
+            break;
+        };
+
+        if lenient && !chars.chars().all(|c| takes_value(spec, c) == Some(false)) {
+            break;
+        }
+
+        let mut parsed = Vec::new();
+        for (offset, c) in chars.char_indices() {
+            match takes_value(spec, c) {
+                Some(false) => parsed.push((c, None)),
+                Some(true) => {
+                    // The value is either the rest of this argument or the next one
+                    let rest = &chars[offset + c.len_utf8()..];
+                    let value = if !rest.is_empty() {
+                        rest.to_owned()
+                    } else {
+                        i += 1;
+                        args.get(i).cloned().ok_or_else(|| {
+                            usage_error(format!("-{c}: option requires an argument"))
+                        })?
+                    };
+                    parsed.push((c, Some(value)));
+                    break;
+                }
+                None => return Err(usage_error(format!("-{c}: invalid option"))),
+            }
+        }
+
+        flags.opts.extend(parsed);
+        i += 1;
+    }
+
+    Ok((flags, args.get(i..).unwrap_or_default()))
+}
+
+/// Looks `flag` up in `spec`, returning whether it takes an argument, or `None`
+/// if the option is not accepted at all.
+fn takes_value(spec: &str, flag: char) -> Option<bool> {
+    if flag == ':' {
+        return None;
+    }
+
+    let mut chars = spec.chars().peekable();
+    while let Some(c) = chars.next() {
+        let has_value = chars.next_if_eq(&':').is_some();
+        if c == flag {
+            return Some(has_value);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(input: &[&str]) -> Vec<String> {
+        input.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn value(flags: &Flags, flag: char) -> Option<&str> {
+        flags
+            .opts
+            .iter()
+            .find(|(c, _)| *c == flag)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    #[test]
+    fn no_flags() {
+        let args = args(&["cmd", "a", "b"]);
+        let (flags, operands) = parse_flags(&CommandType::Echo, &args, "n").unwrap();
+        assert_eq!(flags, Flags::default());
+        assert_eq!(operands, &["a", "b"]);
+    }
+
+    #[test]
+    fn combined_short_flags() {
+        let args = args(&["cmd", "-ab", "-c", "x"]);
+        let (flags, operands) = parse_flags(&CommandType::Echo, &args, "abc").unwrap();
+        assert!(flags.has('a') && flags.has('b') && flags.has('c'));
+        assert_eq!(operands, &["x"]);
+    }
+
+    #[test]
+    fn option_values() {
+        let args = args(&["cmd", "-s", "TERM", "-uvalue", "x"]);
+        let (flags, operands) = parse_flags(&CommandType::Echo, &args, "s:u:").unwrap();
+        assert_eq!(value(&flags, 's'), Some("TERM"));
+        assert_eq!(value(&flags, 'u'), Some("value"));
+        assert_eq!(operands, &["x"]);
+    }
+
+    #[test]
+    fn missing_option_value() {
+        let args = args(&["cmd", "-s"]);
+        let error = parse_flags(&CommandType::Echo, &args, "s:").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("-s: option requires an argument")
+        );
+    }
+
+    #[test]
+    fn invalid_option() {
+        let args = args(&["cmd", "-x"]);
+        let error = parse_flags(&CommandType::Cd, &args, "").unwrap_err();
+        assert_eq!(error.to_string(), "cd: -x: invalid option");
+        assert!(matches!(
+            error,
+            RushError::CommandError {
+                status: ExitStatus::USAGE,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let args = args(&["cmd", "-a", "--", "-b", "--"]);
+        let (flags, operands) = parse_flags(&CommandType::Echo, &args, "ab").unwrap();
+        assert!(flags.has('a'));
+        assert!(!flags.has('b'));
+        assert_eq!(operands, &["-b", "--"]);
+    }
+
+    #[test]
+    fn double_dash_after_operand_is_an_operand() {
+        let args = args(&["cmd", "a", "--", "b"]);
+        let (_, operands) = parse_flags(&CommandType::Echo, &args, "").unwrap();
+        assert_eq!(operands, &["a", "--", "b"]);
+    }
+
+    #[test]
+    fn lone_dash_is_an_operand() {
+        let args = args(&["cmd", "-", "-a"]);
+        let (flags, operands) = parse_flags(&CommandType::Echo, &args, "a").unwrap();
+        assert!(!flags.has('a'));
+        assert_eq!(operands, &["-", "-a"]);
+    }
+
+    #[test]
+    fn lenient_treats_unknown_options_as_operands() {
+        let args = args(&["cmd", "-n", "-x", "-n"]);
+        let (flags, operands) = parse_flags_lenient(&CommandType::Echo, &args, "n").unwrap();
+        assert!(flags.has('n'));
+        assert_eq!(operands, &["-x", "-n"]);
+    }
+
+    #[test]
+    fn lenient_honors_double_dash() {
+        let args = args(&["cmd", "--", "-n"]);
+        let (flags, operands) = parse_flags_lenient(&CommandType::Echo, &args, "n").unwrap();
+        assert!(!flags.has('n'));
+        assert_eq!(operands, &["-n"]);
+    }
+
+    #[test]
+    fn empty_args() {
+        let args = args(&["cmd"]);
+        let (_, operands) = parse_flags(&CommandType::Echo, &args, "").unwrap();
+        assert!(operands.is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{
+    aliases::is_alias_name,
+    command::{CommandType, Context, flags::parse_flags},
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// Defines each `name=value` operand as an alias and shows the value of each
+/// `name` operand. Without operands, shows every alias.
+pub(crate) fn handle_alias(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let (_, operands) = parse_flags(&CommandType::Alias, args, "p")?;
+    let write_error = |error| RushError::io(CommandType::Alias, error);
+    let error = |msg: String| RushError::CommandError {
+        type_: CommandType::Alias,
+        msg,
+        status: ExitStatus::FAILURE,
+    };
+
+    if operands.is_empty() {
+        for (name, value) in state.aliases.iter() {
+            writeln!(ctx.stdout, "alias {name}={}", single_quote(value)).map_err(write_error)?;
+        }
+        return Ok(());
+    }
+
+    // A bad operand doesn't stop the ones after it
+    let mut result = Ok(());
+    for operand in operands {
+        match operand.split_once('=') {
+            Some((name, _)) if !is_alias_name(name) => {
+                result = Err(ctx.complain(error(format!("`{name}': invalid alias name"))));
+            }
+            Some((name, value)) => state.aliases.set(name, value),
+            None => match state.aliases.get(operand) {
+                Some(value) => {
+                    writeln!(ctx.stdout, "alias {operand}={}", single_quote(value))
+                        .map_err(write_error)?;
+                }
+                None => result = Err(ctx.complain(error(format!("{operand}: not found")))),
+            },
+        }
+    }
+    result
+}
+
+/// Removes each alias named, or every alias with `-a`.
+pub(crate) fn handle_unalias(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let (flags, operands) = parse_flags(&CommandType::Unalias, args, "a")?;
+
+    if flags.has('a') {
+        state.aliases.clear();
+        return Ok(());
+    }
+    if operands.is_empty() {
+        return Err(RushError::CommandError {
+            type_: CommandType::Unalias,
+            msg: "usage: unalias [-a] name [name ...]".into(),
+            status: ExitStatus::USAGE,
+        });
+    }
+
+    let mut result = Ok(());
+    for name in operands {
+        if !state.aliases.remove(name) {
+            result = Err(ctx.complain(RushError::CommandError {
+                type_: CommandType::Unalias,
+                msg: format!("{name}: not found"),
+                status: ExitStatus::FAILURE,
+            }));
+        }
+    }
+    result
+}
+
+/// Quotes `value` so the shell reads it back as the same word.
+pub(crate) fn single_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::handlers::test_support::{fail, run};
+    use crate::eval::eval;
+    use crate::state::ShellState;
+
+    #[test]
+    fn defines_and_lists_aliases() {
+        let mut state = ShellState::default();
+        run("alias ll='ls -la' q=\"it's\"", &mut state).unwrap();
+        assert_eq!(state.aliases.get("ll"), Some("ls -la"));
+
+        assert_eq!(
+            run("alias", &mut state).unwrap(),
+            "alias ll='ls -la'\nalias q='it'\\''s'\n"
+        );
+        assert_eq!(run("alias ll", &mut state).unwrap(), "alias ll='ls -la'\n");
+    }
+
+    #[test]
+    fn reports_missing_and_invalid_names() {
+        let mut state = ShellState::default();
+        assert_eq!(
+            fail("alias nope a/b=c ok=1", &mut state),
+            (
+                "alias: nope: not found\nalias: `a/b': invalid alias name\n".into(),
+                1
+            )
+        );
+        assert_eq!(state.aliases.get("ok"), Some("1"));
+    }
+
+    #[test]
+    fn unalias_removes_them() {
+        let mut state = ShellState::default();
+        run("alias a=x b=y c=z", &mut state).unwrap();
+        run("unalias a b", &mut state).unwrap();
+        assert_eq!(state.aliases.get("a"), None);
+        assert_eq!(state.aliases.get("c"), Some("z"));
+
+        assert_eq!(
+            fail("unalias a c", &mut state),
+            ("unalias: a: not found\n".into(), 1)
+        );
+        assert_eq!(state.aliases.get("c"), None);
+
+        run("unalias -a", &mut state).unwrap();
+        assert_eq!(state.aliases.iter().count(), 0);
+        assert_eq!(fail("unalias", &mut state).1, 2);
+    }
+
+    #[test]
+    fn aliases_expand_in_later_lines() {
+        let mut state = ShellState::default();
+        eval("alias rush_alias_test_fail='false;'", &mut state).unwrap();
+        eval("rush_alias_test_fail true", &mut state).unwrap();
+        assert_eq!(state.last_status.code(), 0);
+        assert_eq!(eval("rush_alias_test_fail", &mut state).unwrap().code(), 1);
+        assert_eq!(state.last_status.code(), 1);
+    }
+}
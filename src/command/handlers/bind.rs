@@ -0,0 +1,257 @@
+use crate::{
+    command::{CommandType, Context, flags::parse_flags},
+    editor::keymap::{parse_key, parse_readline},
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+pub(crate) fn handle_bind(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let error = |msg: String| RushError::CommandError {
+        type_: CommandType::Bind,
+        msg,
+        status: ExitStatus::FAILURE,
+    };
+
+    let write_error = |error| RushError::io(CommandType::Bind, error);
+
+    let (flags, operands) = parse_flags(&CommandType::Bind, args, "lrxX")?;
+
+    if flags.has('l') {
+        for (chord, action) in state.keymap.bindings() {
+            writeln!(ctx.stdout, "{:<16}{}", chord.to_string(), action.name())
+                .map_err(write_error)?;
+        }
+        return Ok(());
+    }
+    if flags.has('X') {
+        for (chord, command) in state.keymap.commands() {
+            writeln!(ctx.stdout, "{:<16}{command}", chord.to_string()).map_err(write_error)?;
+        }
+        return Ok(());
+    }
+    if flags.has('r') {
+        for key in operands {
+            let chord = parse_key(key).map_err(error)?;
+            if !state.keymap.unbind(&chord) {
+                return Err(error(format!("{chord}: not bound")));
+            }
+        }
+        return Ok(());
+    }
+
+    match operands {
+        // A lone key is a question about what it's bound to, and anything
+        // else is a binding in readline's syntax
+        [key]
+            if !flags.has('x')
+                && let Ok(chord) = parse_key(key) =>
+        {
+            match (state.keymap.lookup(&chord), state.keymap.command(&chord)) {
+                (Some(action), _) => writeln!(ctx.stdout, "{chord} is bound to {}", action.name())
+                    .map_err(write_error),
+                (None, Some(command)) => {
+                    writeln!(ctx.stdout, "{chord} runs {command}").map_err(write_error)
+                }
+                (None, None) => Err(error(format!("{chord}: not bound"))),
+            }
+        }
+        [spec] => {
+            let (chord, value) = parse_readline(spec).map_err(error)?;
+            match flags.has('x') {
+                true => {
+                    let command = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'));
+                    state.keymap.bind_command(chord, command.unwrap_or(value));
+                }
+                false => state.keymap.bind(chord, value.parse().map_err(error)?),
+            }
+            Ok(())
+        }
+        [key, command] if flags.has('x') => {
+            let chord = parse_key(key).map_err(error)?;
+            state.keymap.bind_command(chord, command);
+            Ok(())
+        }
+        [key, action] => {
+            let chord = parse_key(key).map_err(error)?;
+            state.keymap.bind(chord, action.parse().map_err(error)?);
+            Ok(())
+        }
+        [] => Err(RushError::CommandError {
+            type_: CommandType::Bind,
+            msg: "usage: bind [-lX] [-x] [key [action|command] | keyseq:action] | bind -r key"
+                .into(),
+            status: ExitStatus::USAGE,
+        }),
+        _ => Err(error("too many arguments".into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::Command;
+    use crate::command::handlers::test_support::{command, fail};
+    use crate::editor::keymap::{Action, Chord, Key};
+    use crate::state::ShellState;
+    use crate::status::ExitStatus;
+    use crate::util::RushError;
+
+    // Test helper to simplify command creation
+    fn parse_cmd(input: &str) -> Result<Command, RushError> {
+        command(input, &mut ShellState::default())
+    }
+
+    #[test]
+    fn binds_chord_to_action() {
+        let mut state = ShellState::default();
+        let cmd = parse_cmd("bind f5 clear-screen").unwrap();
+        assert_eq!(cmd.run(&mut state).unwrap(), ExitStatus::SUCCESS);
+        assert_eq!(
+            state.keymap.lookup(&Chord::new(Key::Function(5))),
+            Some(Action::ClearScreen)
+        );
+    }
+
+    #[test]
+    fn rebinding_overrides() {
+        let mut state = ShellState::default();
+        parse_cmd("bind ctrl-w complete")
+            .unwrap()
+            .run(&mut state)
+            .unwrap();
+        assert_eq!(
+            state.keymap.lookup(&Chord::ctrl('w')),
+            Some(Action::Complete)
+        );
+    }
+
+    #[test]
+    fn list_bindings() {
+        let mut state = ShellState::default();
+        parse_cmd("bind f5 clear-screen")
+            .unwrap()
+            .run(&mut state)
+            .unwrap();
+        let mut out = Vec::new();
+        parse_cmd("bind -l")
+            .unwrap()
+            .run_with_output(&mut state, &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), state.keymap.bindings().count());
+        for line in [
+            "ctrl-a          beginning-of-line",
+            "ctrl-w          backward-kill-word",
+            "alt-.           insert-last-argument",
+            "f5              clear-screen",
+        ] {
+            assert!(lines.contains(&line), "{line:?} not in:\n{out}");
+        }
+    }
+
+    #[test]
+    fn query_single_chord() {
+        let mut state = ShellState::default();
+        assert_eq!(
+            parse_cmd("bind alt-.").unwrap().run(&mut state).unwrap(),
+            ExitStatus::SUCCESS
+        );
+
+        assert_eq!(
+            fail("bind f9", &mut state),
+            ("bind: f9: not bound\n".into(), 1)
+        );
+    }
+
+    #[test]
+    fn unknown_action_is_reported() {
+        let mut state = ShellState::default();
+        assert_eq!(
+            fail("bind ctrl-w explode", &mut state).0,
+            "bind: explode: unknown action\n"
+        );
+        assert_eq!(state.keymap, Default::default());
+    }
+
+    #[test]
+    fn unparseable_chord_is_reported() {
+        assert_eq!(
+            fail("bind hyper-x complete", &mut ShellState::default()).0,
+            "bind: hyper-x: invalid key chord\n"
+        );
+    }
+
+    #[test]
+    fn readline_syntax() {
+        let mut state = ShellState::default();
+        parse_cmd(r#"bind '"\C-g": clear-screen'"#)
+            .unwrap()
+            .run(&mut state)
+            .unwrap();
+        parse_cmd("bind Meta-b:complete")
+            .unwrap()
+            .run(&mut state)
+            .unwrap();
+        assert_eq!(
+            state.keymap.lookup(&Chord::ctrl('g')),
+            Some(Action::ClearScreen)
+        );
+        assert_eq!(
+            state.keymap.lookup(&Chord::alt('b')),
+            Some(Action::Complete)
+        );
+
+        assert_eq!(
+            fail(r#"bind '"\C-g" clear-screen'"#, &mut state).0,
+            "bind: \"\\C-g\" clear-screen: expected \"keyseq\": action\n"
+        );
+    }
+
+    #[test]
+    fn commands_and_removal() {
+        let mut state = ShellState::default();
+        parse_cmd(r#"bind -x '"\C-g": "git status"'"#)
+            .unwrap()
+            .run(&mut state)
+            .unwrap();
+        parse_cmd("bind -x f5 'ls -l'")
+            .unwrap()
+            .run(&mut state)
+            .unwrap();
+        assert_eq!(state.keymap.command(&Chord::ctrl('g')), Some("git status"));
+
+        let mut out = Vec::new();
+        for line in ["bind -X", "bind ctrl-g"] {
+            parse_cmd(line)
+                .unwrap()
+                .run_with_output(&mut state, &mut out)
+                .unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "f5              ls -l\nctrl-g          git status\nctrl-g runs git status\n"
+        );
+
+        parse_cmd(r#"bind -r '"\C-g"' ctrl-w"#)
+            .unwrap()
+            .run(&mut state)
+            .unwrap();
+        assert_eq!(state.keymap.command(&Chord::ctrl('g')), None);
+        assert_eq!(state.keymap.lookup(&Chord::ctrl('w')), None);
+        assert_eq!(
+            fail("bind -r ctrl-w", &mut state).0,
+            "bind: ctrl-w: not bound\n"
+        );
+    }
+
+    #[test]
+    fn missing_arguments() {
+        let (_, status) = fail("bind", &mut ShellState::default());
+        assert_eq!(status, ExitStatus::USAGE.code());
+    }
+}
@@ -1,51 +1,154 @@
-use std::{env, path::Path};
-
-use crate::{command::CommandType, util::RushError};
-
-pub(crate) fn handle_cd(args: &[String]) -> Result<(), RushError> {
-    // A helper function that attempts to cd to the HOME directory
-    fn cd_home_dir() -> Result<(), RushError> {
-        let home_dir = env::home_dir().ok_or_else(|| RushError::CommandError {
-            type_: CommandType::Cd,
-            msg: "failed to locate home directory".into(),
-            status: Some(1),
-        })?;
-
-        env::set_current_dir(&Path::new(&home_dir)).map_err(|error| RushError::CommandError {
-            type_: CommandType::Cd,
-            msg: error.to_string(),
-            status: error.raw_os_error(),
-        })
-    }
-
-    if let Some(target_dir) = &args.get(1) {
-        return match target_dir.as_str() {
-            "~" => cd_home_dir(),
-            target_dir => {
-                return env::set_current_dir(&Path::new(target_dir)).map_err(|error| {
-                    RushError::CommandError {
-                        type_: CommandType::Cd,
-                        msg: format!("{}: No such file or directory", target_dir),
-                        status: error.raw_os_error(),
-                    }
-                });
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    command::{
+        CommandType, Context,
+        flags::parse_flags,
+        handlers::pwd::{logical_dir, normalize},
+        redirect::describe,
+    },
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// Changes the current directory to the operand, or to the home directory
+/// without one, and updates `$PWD` and `$OLDPWD` to match.
+///
+/// A relative operand that doesn't start with `.` or `..` is looked for
+/// under each directory on `CDPATH` first, and the directory it's found in
+/// is shown.
+///
+/// By default, or with `-L`, a relative path is followed from `$PWD`, so
+/// `..` leads back out of a symbolic link the shell came in through. With
+/// `-P`, symbolic links are resolved the way the file system sees them.
+pub(crate) fn handle_cd(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let (flags, operands) = parse_flags(&CommandType::Cd, args, "LP")?;
+    let physical = flags.last_of("LP") == Some('P');
+
+    let target = match operands.first().map(String::as_str) {
+        None => (state.vars.get("HOME"))
+            .filter(|home| !home.is_empty())
+            .map(PathBuf::from)
+            .ok_or_else(|| RushError::CommandError {
+                type_: CommandType::Cd,
+                msg: "failed to locate home directory".into(),
+                status: ExitStatus::FAILURE,
+            })?,
+        Some(target) => PathBuf::from(target),
+    };
+    if let Some(found) = search_cdpath(&target, state) {
+        change_dir(CommandType::Cd, &found, physical, state)?;
+        let pwd = state.vars.get("PWD").unwrap_or_default();
+        return writeln!(ctx.stdout, "{pwd}")
+            .map_err(|error| RushError::io(CommandType::Cd, error));
+    }
+    change_dir(CommandType::Cd, &target, physical, state)
+}
+
+/// The directory under one of the `CDPATH` entries that `target` names,
+/// if it is relative and doesn't start with `.` or `..`. An empty entry
+/// stands for the current directory, which `cd` falls back on anyway, so
+/// finding it there is `None`.
+fn search_cdpath(target: &Path, state: &ShellState) -> Option<PathBuf> {
+    let first = target.components().next()?;
+    if !matches!(first, Component::Normal(_)) {
+        return None;
+    }
+    for base in state.vars.get("CDPATH")?.split(':') {
+        if base.is_empty() {
+            if state.cwd.resolve(target).is_dir() {
+                return None;
             }
-        };
+            continue;
+        }
+        let dir = Path::new(base).join(target);
+        if state.cwd.resolve(&dir).is_dir() {
+            return Some(dir);
+        }
     }
+    None
+}
+
+/// Changes to `target` the way `cd` does, reporting a failure as `type_`'s.
+pub(crate) fn change_dir(
+    type_: CommandType,
+    target: &Path,
+    physical: bool,
+    state: &mut ShellState,
+) -> Result<(), RushError> {
+    let error = |msg: String| RushError::CommandError {
+        type_: type_.clone(),
+        msg,
+        status: ExitStatus::FAILURE,
+    };
+
+    let old = logical_dir(state);
+    let logical = normalize(&old.join(target)).filter(|dir| enterable(dir));
+    let entered = match logical {
+        Some(logical) if !physical => logical,
+        // The logical path may not exist when `..` leaves a link whose
+        // target isn't where the link is, so fall back on the real one
+        _ => {
+            let dir = state.cwd.resolve(target);
+            if let Err(cause) = fs::metadata(dir.join(".")) {
+                return Err(error(format!("{}: {}", target.display(), describe(&cause))));
+            }
+            dir.canonicalize().unwrap_or(dir)
+        }
+    };
 
-    cd_home_dir()
+    state.cwd.set(entered.clone());
+    state.vars.export("OLDPWD", Some(&old.to_string_lossy()));
+    state.vars.export("PWD", Some(&entered.to_string_lossy()));
+    state.dir_changed = !state.in_chpwd;
+    Ok(())
+}
+
+/// Whether `dir` is a directory that can be changed to. Looking up `.` in
+/// it takes the same search permission changing to it does.
+fn enterable(dir: &Path) -> bool {
+    dir.join(".").is_dir()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::command::Command;
-    use serial_test::serial;
-    use std::io;
+    use crate::command::handlers::test_support::command;
+    use crate::state::ShellState;
+    use std::{env, io};
 
     // Test helper to simplify command creation
     fn parse_cmd(input: &str) -> Result<Command, RushError> {
-        Command::new(io::Cursor::new(input))
+        command(input, &mut ShellState::default())
+    }
+
+    // Runs `line` straight through the builtin, giving the error it fails
+    // with rather than just the status the command finishes with
+    fn cd(line: &str, state: &mut ShellState) -> Result<(), RushError> {
+        let cmd = parse_cmd(line).unwrap();
+        let mut ctx = Context {
+            stdout: &mut io::sink(),
+            stderr: &mut io::sink(),
+        };
+        handle_cd(&cmd.args, state, &mut ctx)
+    }
+
+    // Runs `line` in a shell that starts out in `start`, giving its result
+    // and the directory it leaves the shell in
+    fn cd_from(start: &str, line: &str) -> (Result<(), RushError>, PathBuf) {
+        let mut state = ShellState::default();
+        state.cwd.set(start.into());
+        let result = cd(line, &mut state);
+        (result, state.cwd.path().to_owned())
     }
 
     #[test]
@@ -56,16 +159,8 @@ mod tests {
     }
 
     #[test]
-    #[serial]
     fn cd_to_absolute_path() {
-        let original_dir = env::current_dir().unwrap();
-
-        let cmd = parse_cmd("cd /tmp").unwrap();
-        let result = cmd.run();
-        let current = env::current_dir().unwrap();
-
-        // Restore original directory before assertions
-        env::set_current_dir(&original_dir).unwrap();
+        let (result, current) = cd_from("/", "cd /tmp");
 
         assert!(result.is_ok());
         assert!(
@@ -77,25 +172,29 @@ mod tests {
     }
 
     #[test]
-    #[serial]
     fn cd_to_root() {
-        let original_dir = env::current_dir().unwrap();
+        let (result, current) = cd_from("/tmp", "cd /");
 
-        let cmd = parse_cmd("cd /").unwrap();
-        let result = cmd.run();
-        let current = env::current_dir().unwrap();
+        assert!(result.is_ok());
+        assert_eq!(current, Path::new("/"));
+    }
 
-        // Restore original directory before assertions
-        env::set_current_dir(&original_dir).unwrap();
+    #[test]
+    fn cd_leaves_the_process_directory_alone() {
+        let original_dir = env::current_dir().unwrap();
+        let (result, current) = cd_from("/tmp", "cd /");
 
         assert!(result.is_ok());
         assert_eq!(current, Path::new("/"));
+        assert_eq!(env::current_dir().unwrap(), original_dir);
     }
 
     #[test]
     fn cd_to_nonexistent_directory() {
-        let cmd = parse_cmd("cd /nonexistent_directory_12345").unwrap();
-        let result = cmd.run();
+        let result = cd(
+            "cd /nonexistent_directory_12345",
+            &mut ShellState::default(),
+        );
         assert!(result.is_err());
 
         if let Err(RushError::CommandError { type_, msg, .. }) = result {
@@ -109,8 +208,7 @@ mod tests {
     #[test]
     fn cd_to_file_not_directory() {
         // Try to cd to /etc/hosts which is a file
-        let cmd = parse_cmd("cd /etc/hosts").unwrap();
-        let result = cmd.run();
+        let result = cd("cd /etc/hosts", &mut ShellState::default());
         assert!(result.is_err());
 
         if let Err(RushError::CommandError { type_, .. }) = result {
@@ -120,56 +218,50 @@ mod tests {
         }
     }
 
+    #[cfg(unix)]
     #[test]
-    #[serial]
-    fn cd_with_no_arguments() {
-        let original_dir = env::current_dir().unwrap();
-
-        let cmd = parse_cmd("cd").unwrap();
-        let result = cmd.run();
-        let _current = env::current_dir().unwrap();
-
-        // Restore original directory before assertions
-        env::set_current_dir(&original_dir).unwrap();
+    fn dot_dot_only_leaves_directories() {
+        let dir = env::temp_dir().join(format!("rush-cd-dot-dot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("file"), "").unwrap();
+        let start = dir.to_string_lossy();
+        let error = |line: &str| cd_from(&start, line).0.unwrap_err().to_string();
+
+        assert_eq!(
+            error("cd missing/.."),
+            "cd: missing/..: No such file or directory"
+        );
+        assert_eq!(error("cd file/.."), "cd: file/..: Not a directory");
+        assert_eq!(error("cd file/../.."), "cd: file/../..: Not a directory");
+        let missing = format!("{start}/missing/../..");
+        assert_eq!(
+            error(&format!("cd {missing}")),
+            format!("cd: {missing}: No such file or directory")
+        );
+        assert_eq!(error("cd file"), "cd: file: Not a directory");
+        assert_eq!(cd_from(&start, "cd ./..").1, dir.parent().unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 
+    #[test]
+    fn cd_with_no_arguments() {
+        let (result, _) = cd_from("/", "cd");
         assert!(result.is_ok());
     }
 
     #[test]
-    #[serial]
     fn cd_with_multiple_path_segments() {
-        let original_dir = env::current_dir().unwrap();
-
-        // Test with a path that has multiple segments
-        let cmd = parse_cmd("cd /usr/local").unwrap();
-        let result = cmd.run();
-
         // This might fail on some systems if /usr/local doesn't exist
-        let current = if result.is_ok() {
-            Some(env::current_dir().unwrap())
-        } else {
-            None
-        };
-
-        env::set_current_dir(&original_dir).unwrap();
-
-        if let Some(current) = current {
+        let (result, current) = cd_from("/", "cd /usr/local");
+        if result.is_ok() {
             assert_eq!(current, Path::new("/usr/local"));
         }
     }
 
     #[test]
-    #[serial]
     fn cd_preserves_trailing_slash() {
-        let original_dir = env::current_dir().unwrap();
-
-        let cmd = parse_cmd("cd /tmp/").unwrap();
-        let result = cmd.run();
-
         // Should still change to /tmp even with trailing slash
-        let current = env::current_dir().unwrap();
-
-        env::set_current_dir(&original_dir).unwrap();
+        let (result, current) = cd_from("/", "cd /tmp/");
 
         assert!(result.is_ok());
         assert!(
@@ -180,6 +272,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn cd_to_dash_prefixed_directory_after_double_dash() {
+        let temp = env::temp_dir();
+        let dir = temp.join("-rush-cd-dash-test");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let (result, current) = cd_from(&temp.to_string_lossy(), "cd -- -rush-cd-dash-test");
+        std::fs::remove_dir(&dir).ok();
+
+        assert!(result.is_ok());
+        assert!(current.ends_with("-rush-cd-dash-test"));
+    }
+
+    #[test]
+    fn cd_rejects_unknown_option() {
+        let error = cd("cd -rush-cd-dash-test", &mut ShellState::default()).unwrap_err();
+        assert_eq!(error.to_string(), "cd: -r: invalid option");
+    }
+
     #[test]
     fn cd_is_recognized_as_builtin() {
         use crate::command::path::is_builtin;
@@ -192,33 +303,16 @@ mod tests {
     }
 
     #[test]
-    #[serial]
     fn cd_to_current_directory() {
-        let original_dir = env::current_dir().unwrap();
-
-        let cmd = parse_cmd("cd .").unwrap();
-        let result = cmd.run();
-        let current = env::current_dir().unwrap();
-
-        env::set_current_dir(&original_dir).unwrap();
+        let (result, current) = cd_from("/usr", "cd .");
 
         assert!(result.is_ok());
-        assert_eq!(current, original_dir);
+        assert_eq!(current, Path::new("/usr"));
     }
 
     #[test]
-    #[serial]
     fn cd_to_parent_directory() {
-        let original_dir = env::current_dir().unwrap();
-
-        // First cd to /tmp to have a known starting point
-        env::set_current_dir("/tmp").unwrap();
-
-        let cmd = parse_cmd("cd ..").unwrap();
-        let result = cmd.run();
-        let current = env::current_dir().unwrap();
-
-        env::set_current_dir(&original_dir).unwrap();
+        let (result, current) = cd_from("/tmp", "cd ..");
 
         assert!(result.is_ok());
         // On macOS, /tmp is /private/tmp, so .. should be /private
@@ -231,102 +325,52 @@ mod tests {
     }
 
     #[test]
-    #[serial]
     fn cd_to_grandparent_directory() {
-        let original_dir = env::current_dir().unwrap();
-
         // Start from a known deep path
         if Path::new("/usr/local/bin").exists() {
-            env::set_current_dir("/usr/local/bin").unwrap();
-
-            let cmd = parse_cmd("cd ../..").unwrap();
-            let result = cmd.run();
-            let current = env::current_dir().unwrap();
-
-            env::set_current_dir(&original_dir).unwrap();
+            let (result, current) = cd_from("/usr/local/bin", "cd ../..");
 
             assert!(result.is_ok());
             assert_eq!(current, Path::new("/usr"));
-        } else {
-            env::set_current_dir(&original_dir).unwrap();
         }
     }
 
     #[test]
-    #[serial]
     fn cd_to_relative_subdirectory() {
-        let original_dir = env::current_dir().unwrap();
-
-        // Change to /usr which should have a 'local' subdirectory
+        // Start from /usr which should have a 'local' subdirectory
         if Path::new("/usr/local").exists() {
-            env::set_current_dir("/usr").unwrap();
-
-            let cmd = parse_cmd("cd local").unwrap();
-            let result = cmd.run();
-            let current = env::current_dir().unwrap();
-
-            env::set_current_dir(&original_dir).unwrap();
+            let (result, current) = cd_from("/usr", "cd local");
 
             assert!(result.is_ok());
             assert_eq!(current, Path::new("/usr/local"));
-        } else {
-            env::set_current_dir(&original_dir).unwrap();
         }
     }
 
     #[test]
-    #[serial]
     fn cd_to_relative_path_with_current_dir() {
-        let original_dir = env::current_dir().unwrap();
-
-        // Change to /usr which should have a 'local' subdirectory
+        // Start from /usr which should have a 'local' subdirectory
         if Path::new("/usr/local").exists() {
-            env::set_current_dir("/usr").unwrap();
-
-            let cmd = parse_cmd("cd ./local").unwrap();
-            let result = cmd.run();
-            let current = env::current_dir().unwrap();
-
-            env::set_current_dir(&original_dir).unwrap();
+            let (result, current) = cd_from("/usr", "cd ./local");
 
             assert!(result.is_ok());
             assert_eq!(current, Path::new("/usr/local"));
-        } else {
-            env::set_current_dir(&original_dir).unwrap();
         }
     }
 
     #[test]
-    #[serial]
     fn cd_to_complex_relative_path() {
-        let original_dir = env::current_dir().unwrap();
-
         // Test navigating up and then down: ../sibling pattern
         if Path::new("/usr/local").exists() && Path::new("/usr/bin").exists() {
-            env::set_current_dir("/usr/local").unwrap();
-
-            let cmd = parse_cmd("cd ../bin").unwrap();
-            let result = cmd.run();
-            let current = env::current_dir().unwrap();
-
-            env::set_current_dir(&original_dir).unwrap();
+            let (result, current) = cd_from("/usr/local", "cd ../bin");
 
             assert!(result.is_ok());
             assert_eq!(current, Path::new("/usr/bin"));
-        } else {
-            env::set_current_dir(&original_dir).unwrap();
         }
     }
 
     #[test]
-    #[serial]
     fn cd_to_nonexistent_relative_path() {
-        let original_dir = env::current_dir().unwrap();
-
-        let cmd = parse_cmd("cd ./nonexistent_subdir_12345").unwrap();
-        let result = cmd.run();
-
-        env::set_current_dir(&original_dir).unwrap();
+        let (result, _) = cd_from("/", "cd ./nonexistent_subdir_12345");
 
         assert!(result.is_err());
         if let Err(RushError::CommandError { type_, msg, .. }) = result {
@@ -338,82 +382,39 @@ mod tests {
     }
 
     #[test]
-    #[serial]
     fn cd_parent_from_root() {
-        let original_dir = env::current_dir().unwrap();
-
-        // cd to root first
-        env::set_current_dir("/").unwrap();
-
-        // Try to go to parent of root (should stay at root)
-        let cmd = parse_cmd("cd ..").unwrap();
-        let result = cmd.run();
-        let current = env::current_dir().unwrap();
-
-        env::set_current_dir(&original_dir).unwrap();
+        // Going to the parent of root should stay at root
+        let (result, current) = cd_from("/", "cd ..");
 
         assert!(result.is_ok());
         assert_eq!(current, Path::new("/"));
     }
 
     #[test]
-    #[serial]
     fn cd_to_deeply_nested_relative_path() {
-        let original_dir = env::current_dir().unwrap();
-
         // Test ../../.. navigation
         if Path::new("/usr/local/bin").exists() {
-            env::set_current_dir("/usr/local/bin").unwrap();
-
-            let cmd = parse_cmd("cd ../../..").unwrap();
-            let result = cmd.run();
-            let current = env::current_dir().unwrap();
-
-            env::set_current_dir(&original_dir).unwrap();
+            let (result, current) = cd_from("/usr/local/bin", "cd ../../..");
 
             assert!(result.is_ok());
             assert_eq!(current, Path::new("/"));
-        } else {
-            env::set_current_dir(&original_dir).unwrap();
         }
     }
 
     #[test]
-    #[serial]
     fn cd_to_relative_path_multiple_segments() {
-        let original_dir = env::current_dir().unwrap();
-
         // Navigate to a multi-segment relative path
-        if Path::new("/usr").exists() {
-            env::set_current_dir("/usr").unwrap();
-
-            if Path::new("/usr/local/bin").exists() {
-                let cmd = parse_cmd("cd local/bin").unwrap();
-                let result = cmd.run();
-                let current = env::current_dir().unwrap();
-
-                env::set_current_dir(&original_dir).unwrap();
+        if Path::new("/usr/local/bin").exists() {
+            let (result, current) = cd_from("/usr", "cd local/bin");
 
-                assert!(result.is_ok());
-                assert_eq!(current, Path::new("/usr/local/bin"));
-            } else {
-                env::set_current_dir(&original_dir).unwrap();
-            }
-        } else {
-            env::set_current_dir(&original_dir).unwrap();
+            assert!(result.is_ok());
+            assert_eq!(current, Path::new("/usr/local/bin"));
         }
     }
 
     #[test]
-    #[serial]
     fn cd_to_home_with_tilde() {
-        let original_dir = env::current_dir().unwrap();
-
-        let cmd = parse_cmd("cd ~").unwrap();
-        let result = cmd.run();
-        let current = env::current_dir().unwrap();
-
-        env::set_current_dir(&original_dir).unwrap();
+        let (result, current) = cd_from("/", "cd ~");
 
         assert!(result.is_ok());
 
@@ -424,18 +425,8 @@ mod tests {
     }
 
     #[test]
-    #[serial]
     fn cd_with_no_args_goes_to_home() {
-        let original_dir = env::current_dir().unwrap();
-
-        // First cd somewhere else
-        env::set_current_dir("/tmp").unwrap();
-
-        let cmd = parse_cmd("cd").unwrap();
-        let result = cmd.run();
-        let current = env::current_dir().unwrap();
-
-        env::set_current_dir(&original_dir).unwrap();
+        let (result, current) = cd_from("/tmp", "cd");
 
         assert!(result.is_ok());
 
@@ -447,50 +438,117 @@ mod tests {
 
     #[test]
     fn cd_tilde_parsing() {
-        let cmd = parse_cmd("cd ~").unwrap();
+        let mut state = ShellState::default();
+        state.vars.set("HOME", "/usr");
+        let cmd = command("cd ~", &mut state).unwrap();
         assert!(matches!(cmd.type_, CommandType::Cd));
-        assert_eq!(cmd.args, vec!["cd", "~"]);
+        assert_eq!(cmd.args, vec!["cd", "/usr"]);
+        // Only the shell expands it, so quoted it's a directory name
+        assert_eq!(command("cd '~'", &mut state).unwrap().args, ["cd", "~"]);
     }
 
     #[test]
-    #[serial]
-    fn cd_to_home_from_different_directory() {
-        let original_dir = env::current_dir().unwrap();
-
-        // Start from a known directory
-        env::set_current_dir("/").unwrap();
-
-        let cmd = parse_cmd("cd ~").unwrap();
-        let result = cmd.run();
-        let current = env::current_dir().unwrap();
-
-        env::set_current_dir(&original_dir).unwrap();
-
-        assert!(result.is_ok());
-
-        // Verify we changed from / to home
-        if let Some(home) = env::home_dir() {
-            assert_eq!(current, home);
-            assert_ne!(current, Path::new("/"));
-        }
+    fn cd_home_follows_the_shell_variable() {
+        let mut state = ShellState::default();
+        state.vars.set("HOME", "/usr");
+        cd("cd", &mut state).unwrap();
+        assert_eq!(state.cwd.path(), Path::new("/usr"));
+
+        state.vars.set("HOME", "");
+        assert!(cd("cd", &mut state).is_err());
     }
 
     #[test]
-    #[serial]
     fn cd_tilde_multiple_times() {
-        let original_dir = env::current_dir().unwrap();
-
         // cd ~ should work multiple times
+        let mut state = ShellState::default();
         for _ in 0..3 {
-            let cmd = parse_cmd("cd ~").unwrap();
-            let result = cmd.run();
-            assert!(result.is_ok());
+            assert!(cd("cd ~", &mut state).is_ok());
 
             if let Some(home) = env::home_dir() {
-                assert_eq!(env::current_dir().unwrap(), home);
+                assert_eq!(state.cwd.path(), home);
             }
         }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn logical_and_physical_paths_through_a_symlink() {
+        let base = env::temp_dir().join("rush-cd-symlink-test");
+        std::fs::create_dir_all(base.join("real/inner")).unwrap();
+        std::os::unix::fs::symlink("real/inner", base.join("link")).ok();
+        let base = base.canonicalize().unwrap();
+
+        let mut state = ShellState::default();
+        state.cwd.set(base.clone());
+        let pwd = |state: &mut ShellState, args: &str| {
+            let mut out = Vec::new();
+            command(&format!("pwd {args}"), state)
+                .unwrap()
+                .run_with_output(state, &mut out)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        cd("cd link", &mut state).unwrap();
+        let logical = pwd(&mut state, "");
+        let physical = pwd(&mut state, "-P");
+        let oldpwd = state.vars.get("OLDPWD");
+
+        cd("cd ..", &mut state).unwrap();
+        let parent = state.cwd.path().to_owned();
+
+        cd("cd -P link", &mut state).unwrap();
+        let resolved = state.vars.get("PWD");
 
-        env::set_current_dir(&original_dir).unwrap();
+        assert_eq!(logical, format!("{}\n", base.join("link").display()));
+        assert_eq!(physical, format!("{}\n", base.join("real/inner").display()));
+        assert_eq!(oldpwd, Some(base.display().to_string()));
+        assert_eq!(parent, base);
+        assert_eq!(
+            resolved,
+            Some(base.join("real/inner").display().to_string())
+        );
+    }
+
+    #[test]
+    fn cdpath_is_searched_for_relative_names() {
+        let base = env::temp_dir().join("rush-cd-cdpath-test");
+        std::fs::create_dir_all(base.join("projects/rush")).unwrap();
+        let base = base.canonicalize().unwrap();
+
+        let mut state = ShellState::default();
+        state
+            .vars
+            .set("CDPATH", &format!(":{}", base.join("projects").display()));
+        state.cwd.set("/".into());
+        let mut out = Vec::new();
+        let found = command("cd rush", &mut state)
+            .unwrap()
+            .run_with_output(&mut state, &mut out);
+        let current = state.cwd.path().to_owned();
+        state.cwd.set("/".into());
+        let dotted = cd("cd ./rush", &mut state);
+
+        assert_eq!(found.unwrap(), ExitStatus::SUCCESS);
+        assert_eq!(current, base.join("projects/rush"));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("{}\n", base.join("projects/rush").display())
+        );
+        assert!(dotted.is_err());
+    }
+
+    #[test]
+    fn changing_directory_runs_the_chpwd_hook() {
+        let mut state = ShellState::default();
+        let line = "chpwd() { seen=\"$seen $PWD\"; cd /; }; other() { seen=\"$seen other\"; }; \
+            chpwd_functions=(other); cd /tmp; cd /nowhere; pushd /usr";
+        crate::eval::eval(line, &mut state).ok();
+
+        // The `cd` in the hook doesn't run it again
+        assert_eq!(state.cwd.path(), Path::new("/"));
+        assert_eq!(state.vars.get("seen").unwrap(), " /tmp other /usr other");
+        assert!(!state.dir_changed);
     }
 }
@@ -0,0 +1,41 @@
+use crate::{
+    command::{CommandType, Context, flags::parse_flags},
+    util::RushError,
+};
+
+/// Clears the terminal and moves the cursor to the top. What scrolled off
+/// the top is cleared too, unless `-x` says to keep it.
+pub(crate) fn handle_clear(args: &[String], ctx: &mut Context) -> Result<(), RushError> {
+    let (flags, _) = parse_flags(&CommandType::Clear, args, "x")?;
+
+    let scrollback = match flags.has('x') {
+        true => "",
+        false => "\x1b[3J",
+    };
+    write!(ctx.stdout, "\x1b[H\x1b[2J{scrollback}")
+        .and_then(|()| ctx.stdout.flush())
+        .map_err(|error| RushError::io(CommandType::Clear, error))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::command::handlers::test_support::command;
+    use crate::state::ShellState;
+
+    fn run(input: &str) -> String {
+        let mut state = ShellState::default();
+        let mut out = Vec::new();
+        command(input, &mut state)
+            .unwrap()
+            .run_with_output(&mut state, &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn clears_the_screen() {
+        assert_eq!(run("clear"), "\x1b[H\x1b[2J\x1b[3J");
+        assert_eq!(run("clear -x"), "\x1b[H\x1b[2J");
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{
+    command::{
+        CommandType, Context,
+        flags::{Flags, parse_flags},
+        handlers::{
+            alias::single_quote,
+            r#type::{Found, describe, find},
+        },
+    },
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// Shows how the shell would run each operand: with `-v`, as the path or
+/// name it would run, or with `-V`, in words like `type`.
+///
+/// `command name args` never gets here, since the command is classified as
+/// `name` itself. See [`command_target`].
+pub(crate) fn handle_command(
+    args: &[String],
+    state: &ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let (flags, operands) = parse_flags(&CommandType::Command, args, "vV")?;
+    let verbose = flags.last_of("vV") == Some('V');
+
+    let mut result = Ok(());
+    for name in operands {
+        let Some(found) = find(name, &Flags::default(), state)?.into_iter().next() else {
+            result = Err(match verbose {
+                true => ctx.complain(RushError::CommandError {
+                    type_: CommandType::Command,
+                    msg: format!("{name}: not found"),
+                    status: ExitStatus::FAILURE,
+                }),
+                false => RushError::Reported(ExitStatus::FAILURE),
+            });
+            continue;
+        };
+
+        let line = match found {
+            _ if verbose => describe(name, &found),
+            Found::Alias(value) => format!("alias {name}={}", single_quote(&value)),
+            Found::Function(_) | Found::Builtin => name.clone(),
+            Found::Hashed(path) | Found::File(path) => path,
+        };
+        writeln!(ctx.stdout, "{line}")
+            .map_err(|error| RushError::io(CommandType::Command, error))?;
+    }
+    result
+}
+
+/// The command `command` runs in place of itself, or `None` if it is only
+/// describing commands.
+///
+/// Aliases are already out of the way, since only the first word of a
+/// command is expanded.
+pub(crate) fn command_target(args: &[String]) -> Option<&[String]> {
+    let (flags, operands) = parse_flags(&CommandType::Command, args, "vV").ok()?;
+    match flags.has('v') || flags.has('V') || operands.is_empty() {
+        true => None,
+        false => Some(operands),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::handlers::test_support::{fail, run};
+    use crate::eval::eval;
+    use crate::state::ShellState;
+
+    #[test]
+    fn runs_the_named_command() {
+        let mut state = ShellState::default();
+        assert_eq!(run("command echo hi", &mut state).unwrap(), "hi\n");
+        assert_eq!(run("command -- echo -n", &mut state).unwrap(), "");
+
+        eval("alias rush_command_test='false'", &mut state).unwrap();
+        let status = eval("command rush_command_test", &mut state).unwrap();
+        assert_eq!(status.code(), 127);
+    }
+
+    #[test]
+    fn describes_commands() {
+        let mut state = ShellState::default();
+        state.aliases.set("ll", "ls -la");
+        assert_eq!(
+            run("command -v ll echo", &mut state).unwrap(),
+            "alias ll='ls -la'\necho\n"
+        );
+        assert_eq!(
+            run("command -V echo", &mut state).unwrap(),
+            "echo is a shell builtin\n"
+        );
+
+        assert_eq!(
+            fail("command -v rush_command_missing", &mut state),
+            (String::new(), 1)
+        );
+        assert_eq!(
+            fail("command -V rush_command_missing", &mut state),
+            ("command: rush_command_missing: not found\n".into(), 1)
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{
+    command::{CommandType, Context, flags::parse_flags, handlers::alias::single_quote},
+    editor::completion::CompletionSpec,
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// Registers where the arguments of each named command are completed from:
+/// the words of a `-W` list, or the `COMPREPLY` a `-F` function leaves.
+/// With `-r`, removes the specifications instead; with `-p` or no operands,
+/// shows them in a form that can be run again.
+pub(crate) fn handle_complete(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let (flags, operands) = parse_flags(&CommandType::Complete, args, "F:W:pr")?;
+    let write_error = |error| RushError::io(CommandType::Complete, error);
+    let missing = |name: &str| RushError::CommandError {
+        type_: CommandType::Complete,
+        msg: format!("{name}: no completion specification"),
+        status: ExitStatus::FAILURE,
+    };
+
+    if flags.has('r') {
+        if operands.is_empty() {
+            state.completions.clear();
+            return Ok(());
+        }
+        let mut result = Ok(());
+        for name in operands {
+            if !state.completions.remove(name) {
+                result = Err(ctx.complain(missing(name)));
+            }
+        }
+        return result;
+    }
+
+    let spec = CompletionSpec {
+        words: flags.value('W').map(str::to_owned),
+        function: flags.value('F').map(str::to_owned),
+    };
+    if flags.has('p') || operands.is_empty() {
+        if operands.is_empty() {
+            for (name, spec) in state.completions.iter() {
+                writeln!(ctx.stdout, "{}", specification(name, spec)).map_err(write_error)?;
+            }
+            return Ok(());
+        }
+        let mut result = Ok(());
+        for name in operands {
+            match state.completions.get(name) {
+                Some(spec) => {
+                    writeln!(ctx.stdout, "{}", specification(name, spec)).map_err(write_error)?
+                }
+                None => result = Err(ctx.complain(missing(name))),
+            }
+        }
+        return result;
+    }
+
+    for name in operands {
+        state.completions.set(name, spec.clone());
+    }
+    Ok(())
+}
+
+/// The `complete` command that registers `spec` for `name`.
+fn specification(name: &str, spec: &CompletionSpec) -> String {
+    let mut command = "complete".to_owned();
+    if let Some(words) = &spec.words {
+        command += &format!(" -W {}", single_quote(words));
+    }
+    if let Some(function) = &spec.function {
+        command += &format!(" -F {function}");
+    }
+    format!("{command} {name}")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::handlers::test_support::{fail, run};
+    use crate::state::ShellState;
+
+    #[test]
+    fn registers_and_shows_specifications() {
+        let mut state = ShellState::default();
+        run("complete -W 'start stop' svc ctl", &mut state).unwrap();
+        run("complete -F _git git", &mut state).unwrap();
+        assert_eq!(
+            run("complete", &mut state).unwrap(),
+            "complete -W 'start stop' ctl\n\
+             complete -F _git git\n\
+             complete -W 'start stop' svc\n"
+        );
+        assert_eq!(
+            run("complete -p git", &mut state).unwrap(),
+            "complete -F _git git\n"
+        );
+
+        assert_eq!(
+            fail("complete -p nope", &mut state),
+            ("complete: nope: no completion specification\n".into(), 1)
+        );
+    }
+
+    #[test]
+    fn removes_specifications() {
+        let mut state = ShellState::default();
+        run("complete -W 'a b' x y z", &mut state).unwrap();
+        run("complete -r x", &mut state).unwrap();
+        assert!(state.completions.get("x").is_none());
+        assert!(state.completions.get("y").is_some());
+        assert_eq!(fail("complete -r x", &mut state).1, 1);
+
+        run("complete -r", &mut state).unwrap();
+        assert_eq!(state.completions.iter().count(), 0);
+    }
+}
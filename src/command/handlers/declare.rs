@@ -0,0 +1,366 @@
+use std::io::Write;
+
+use crate::{
+    command::{
+        CommandType, Context,
+        flags::{Flags, parse_flags},
+        handlers::export::escape,
+    },
+    state::ShellState,
+    status::ExitStatus,
+    util::{RushError, is_name},
+    variables::{Array, Attributes},
+};
+
+/// Declares each `NAME` or `NAME=value` operand as a variable, local to the
+/// function running it if there is one.
+///
+/// `-i` makes values given to it evaluate as arithmetic, `-r` makes it
+/// read-only, `-x` exports it, `-a` makes it an indexed array, and `-A` an
+/// associative one, which `NAME=(...)` fills. `-p`
+/// shows each operand the way it was declared; without operands, the
+/// attribute flags instead show every variable that has them, or every
+/// variable at all. `-f` shows the definition of each function named, or of
+/// every function without operands, and `-F` only their names.
+pub(crate) fn handle_declare(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let (flags, operands) = parse_flags(&CommandType::Declare, args, "aAfFiprx")?;
+    let write_error = |error| RushError::io(CommandType::Declare, error);
+
+    if flags.has('f') || flags.has('F') {
+        return show_functions(&flags, operands, state, ctx.stdout);
+    }
+
+    if operands.is_empty() {
+        let wanted = Attributes {
+            integer: flags.has('i'),
+            readonly: flags.has('r'),
+            array: flags.has('a'),
+            associative: flags.has('A'),
+        };
+        for name in state.vars.names() {
+            let attributes = state.vars.attributes(&name);
+            let has_wanted = (wanted.integer && attributes.integer)
+                || (wanted.readonly && attributes.readonly)
+                || (wanted.array && attributes.array)
+                || (wanted.associative && attributes.associative)
+                || (flags.has('x') && state.vars.is_exported(&name));
+            if has_wanted || wanted == Attributes::default() && !flags.has('x') {
+                writeln!(ctx.stdout, "{}", declaration(&name, state)).map_err(write_error)?;
+            }
+        }
+        return Ok(());
+    }
+
+    // A bad operand doesn't stop the ones after it
+    let mut result = Ok(());
+    for operand in &join_arrays(operands) {
+        if flags.has('p') {
+            let declared = state.vars.get(operand).is_some()
+                || state.vars.array(operand).is_some()
+                || state.vars.is_exported(operand)
+                || state.vars.attributes(operand) != Attributes::default();
+            match declared {
+                true => {
+                    writeln!(ctx.stdout, "{}", declaration(operand, state)).map_err(write_error)?
+                }
+                false => result = Err(ctx.complain(failure(format!("{operand}: not found")))),
+            }
+            continue;
+        }
+
+        let (name, value) = match operand.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (operand.as_str(), None),
+        };
+        if !is_name(name) {
+            result = Err(ctx.complain(failure(format!("`{operand}': not a valid identifier"))));
+            continue;
+        }
+        if let Err(error) = declare(name, value, &flags, state) {
+            result = Err(ctx.complain(error));
+        }
+    }
+    result
+}
+
+/// Gives `name` the attributes `flags` ask for, then `value` if there is one.
+fn declare(
+    name: &str,
+    value: Option<&str>,
+    flags: &Flags,
+    state: &mut ShellState,
+) -> Result<(), RushError> {
+    state.vars.local(name);
+    let readonly = state.vars.attributes(name).readonly;
+    let changes = value.is_some() || flags.has('i') || flags.has('a') || flags.has('A');
+    if readonly && changes {
+        return Err(RushError::ReadonlyVariable(name.to_owned()));
+    }
+    let attributes = state.vars.attributes(name);
+    let associative = flags.has('A') || attributes.associative;
+    if flags.has('a') && associative || flags.has('A') && attributes.array {
+        return Err(failure(format!(
+            "{name}: cannot convert between array kinds"
+        )));
+    }
+
+    let attributes = state.vars.attributes_mut(name);
+    attributes.integer |= flags.has('i');
+    attributes.array |= flags.has('a');
+    attributes.associative |= flags.has('A');
+    let is_array = matches!(
+        (state.vars.array(name), associative),
+        (Some(Array::Indexed(_)), false) | (Some(Array::Associative(_)), true)
+    );
+    match value.and_then(|value| value.strip_prefix('(')?.strip_suffix(')')) {
+        Some(items) => state.vars.assign_array(name, array_items(items))?,
+        None => {
+            if (flags.has('a') || flags.has('A')) && !is_array {
+                let value = state.vars.get(name);
+                let items = value.map(|value| (Some("0".into()), value));
+                state.vars.assign_array(name, items.into_iter().collect())?;
+            }
+            if let Some(value) = value {
+                state.vars.assign(name, value)?;
+            }
+        }
+    }
+    state.vars.attributes_mut(name).readonly |= flags.has('r');
+    if flags.has('x') {
+        state.vars.export(name, None);
+    }
+    Ok(())
+}
+
+/// The `declare` command that would give `name` its attributes and value
+/// again.
+fn declaration(name: &str, state: &ShellState) -> String {
+    let attributes = state.vars.attributes(name);
+    let array = state.vars.array(name);
+    let mut letters: String = [
+        (matches!(array, Some(Array::Indexed(_))), 'a'),
+        (matches!(array, Some(Array::Associative(_))), 'A'),
+        (attributes.integer, 'i'),
+        (attributes.readonly, 'r'),
+        (state.vars.is_exported(name), 'x'),
+    ]
+    .into_iter()
+    .filter_map(|(has, letter)| has.then_some(letter))
+    .collect();
+    if letters.is_empty() {
+        letters.push('-');
+    }
+
+    if let Some(array) = array {
+        let elements: Vec<String> = (array.keys().into_iter())
+            .zip(array.values())
+            .map(|(key, value)| format!("[{key}]=\"{}\"", escape(&value)))
+            .collect();
+        return format!("declare -{letters} {name}=({})", elements.join(" "));
+    }
+    match state.vars.get(name) {
+        Some(value) => format!("declare -{letters} {name}=\"{}\"", escape(&value)),
+        None => format!("declare -{letters} {name}"),
+    }
+}
+
+/// Shows the functions named by `operands`, or every function.
+fn show_functions(
+    flags: &Flags,
+    operands: &[String],
+    state: &ShellState,
+    out: &mut dyn Write,
+) -> Result<(), RushError> {
+    let write_error = |error| RushError::io(CommandType::Declare, error);
+    let names_only = flags.last_of("fF") == Some('F');
+    if operands.is_empty() {
+        for function in state.functions.iter() {
+            match names_only {
+                true => writeln!(out, "declare -f {}", function.name),
+                false => writeln!(out, "{}", function.text),
+            }
+            .map_err(write_error)?;
+        }
+        return Ok(());
+    }
+
+    // Like a lookup that fails, a missing function only sets the status
+    let mut result = Ok(());
+    for name in operands {
+        match state.functions.get(name) {
+            Some(function) => match names_only {
+                true => writeln!(out, "{name}"),
+                false => writeln!(out, "{}", function.text),
+            }
+            .map_err(write_error)?,
+            None => result = Err(RushError::Reported(ExitStatus::FAILURE)),
+        }
+    }
+    result
+}
+
+/// Puts back together operands like `a=(x` and `y)`, which the shell split
+/// apart at the spaces between the elements.
+pub(crate) fn join_arrays(operands: &[String]) -> Vec<String> {
+    let mut joined: Vec<String> = Vec::new();
+    let mut open = false;
+    for operand in operands {
+        match joined.last_mut() {
+            Some(last) if open => {
+                last.push(' ');
+                last.push_str(operand);
+            }
+            _ => joined.push(operand.clone()),
+        }
+        let last = joined.last().expect("just pushed");
+        open = last.contains("=(") && !last.ends_with(')');
+    }
+    joined
+}
+
+/// The elements between the parentheses of `NAME=(...)`, each with its
+/// subscript if it was given as `[subscript]=value`.
+pub(crate) fn array_items(items: &str) -> Vec<(Option<String>, String)> {
+    items
+        .split_whitespace()
+        .map(|item| {
+            let subscript = item
+                .strip_prefix('[')
+                .and_then(|item| item.split_once("]="));
+            match subscript {
+                Some((subscript, value)) => (Some(subscript.to_owned()), value.to_owned()),
+                None => (None, item.to_owned()),
+            }
+        })
+        .collect()
+}
+
+fn failure(msg: String) -> RushError {
+    RushError::CommandError {
+        type_: CommandType::Declare,
+        msg,
+        status: ExitStatus::FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::handlers::test_support::{fail, run};
+    use crate::eval::eval;
+    use crate::state::ShellState;
+
+    #[test]
+    fn lists_functions() {
+        let mut state = ShellState::default();
+        eval("greet() { echo hi; }\nbye ()\n{\n  echo bye\n}", &mut state).unwrap();
+
+        assert_eq!(
+            run("declare -f", &mut state).unwrap(),
+            "bye ()\n{\n  echo bye\n}\ngreet() { echo hi; }\n"
+        );
+        assert_eq!(
+            run("declare -F", &mut state).unwrap(),
+            "declare -f bye\ndeclare -f greet\n"
+        );
+        assert_eq!(
+            run("declare -f greet", &mut state).unwrap(),
+            "greet() { echo hi; }\n"
+        );
+
+        assert_eq!(fail("declare -F greet nope", &mut state).1, 1);
+    }
+
+    #[test]
+    fn declares_locals_in_functions() {
+        let mut state = ShellState::default();
+        eval(
+            "RUSH_DECLARE_TEST=outer; rush_declare_test() { declare RUSH_DECLARE_TEST=inner; }",
+            &mut state,
+        )
+        .unwrap();
+        eval("rush_declare_test", &mut state).unwrap();
+        assert_eq!(state.vars.get("RUSH_DECLARE_TEST").unwrap(), "outer");
+
+        eval("declare RUSH_DECLARE_TEST=global", &mut state).unwrap();
+        assert_eq!(state.vars.get("RUSH_DECLARE_TEST").unwrap(), "global");
+    }
+
+    #[test]
+    fn attributes() {
+        let mut state = ShellState::default();
+        run("declare -i RUSH_DECLARE_TEST_INT=2*3+1", &mut state).unwrap();
+        eval("RUSH_DECLARE_TEST_INT=RUSH_DECLARE_TEST_INT+1", &mut state).unwrap();
+        assert_eq!(state.vars.get("RUSH_DECLARE_TEST_INT").unwrap(), "8");
+
+        run("declare -r RUSH_DECLARE_TEST_INT", &mut state).unwrap();
+        assert_eq!(
+            eval("RUSH_DECLARE_TEST_INT=1", &mut state).unwrap().code(),
+            1
+        );
+        let error = state.vars.assign("RUSH_DECLARE_TEST_INT", "1").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "RUSH_DECLARE_TEST_INT: readonly variable"
+        );
+        assert_eq!(
+            fail("declare RUSH_DECLARE_TEST_INT=1", &mut state),
+            ("RUSH_DECLARE_TEST_INT: readonly variable\n".into(), 1)
+        );
+
+        assert_eq!(
+            run("declare -p RUSH_DECLARE_TEST_INT", &mut state).unwrap(),
+            "declare -ir RUSH_DECLARE_TEST_INT=\"8\"\n"
+        );
+        assert_eq!(
+            run("declare -r", &mut state).unwrap(),
+            "declare -ir RUSH_DECLARE_TEST_INT=\"8\"\n"
+        );
+    }
+
+    #[test]
+    fn exports_and_prints() {
+        let mut state = ShellState::default();
+        run("declare -x RUSH_DECLARE_TEST_X='say \"hi\"'", &mut state).unwrap();
+        assert!(state.vars.is_exported("RUSH_DECLARE_TEST_X"));
+        assert_eq!(
+            run("declare -p RUSH_DECLARE_TEST_X", &mut state).unwrap(),
+            "declare -x RUSH_DECLARE_TEST_X=\"say \\\"hi\\\"\"\n"
+        );
+        state.vars.unexport("RUSH_DECLARE_TEST_X");
+
+        run("declare RUSH_DECLARE_TEST_PLAIN=1", &mut state).unwrap();
+        assert_eq!(
+            run("declare -p RUSH_DECLARE_TEST_PLAIN", &mut state).unwrap(),
+            "declare -- RUSH_DECLARE_TEST_PLAIN=\"1\"\n"
+        );
+        assert_eq!(
+            fail("declare -p RUSH_DECLARE_TEST_NOPE", &mut state),
+            ("declare: RUSH_DECLARE_TEST_NOPE: not found\n".into(), 1)
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        let mut state = ShellState::default();
+        run("declare -a RUSH_DECLARE_TEST_A=(x [3]=y z)", &mut state).unwrap();
+        assert_eq!(
+            run("declare -p RUSH_DECLARE_TEST_A", &mut state).unwrap(),
+            "declare -a RUSH_DECLARE_TEST_A=([0]=\"x\" [3]=\"y\" [4]=\"z\")\n"
+        );
+
+        run("declare -A RUSH_DECLARE_TEST_M", &mut state).unwrap();
+        eval("RUSH_DECLARE_TEST_M[key]=v", &mut state).unwrap();
+        assert_eq!(
+            run("declare -p RUSH_DECLARE_TEST_M", &mut state).unwrap(),
+            "declare -A RUSH_DECLARE_TEST_M=([key]=\"v\")\n"
+        );
+        assert_eq!(
+            fail("declare -a RUSH_DECLARE_TEST_M", &mut state).0,
+            "declare: RUSH_DECLARE_TEST_M: cannot convert between array kinds\n"
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use std::{
+    io::Write,
+    iter,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    command::{
+        CommandType, Context,
+        flags::{Flags, parse_flags_lenient},
+        handlers::{cd::change_dir, pwd::logical_dir},
+    },
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// Saves the current directory on the directory stack and changes to the
+/// operand. Without one, swaps the top two directories; with `+N` or `-N`,
+/// rotates the stack until that entry is on top. With `-n`, the operand is
+/// added below the top and the directory stays the same.
+pub(crate) fn handle_pushd(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let type_ = CommandType::Pushd;
+    let (flags, operands) = parse_flags_lenient(&type_, args, "n")?;
+    let mut stack = stack(state);
+
+    state.dirs = match operands.first() {
+        None => {
+            if stack.len() < 2 {
+                return Err(failure(type_, "no other directory".into()));
+            }
+            stack.swap(0, 1);
+            change_dir(type_, &stack[0], false, state)?;
+            stack.split_off(1)
+        }
+        Some(operand) if operand.starts_with(['+', '-']) => {
+            let n = index(&type_, operand, stack.len())?;
+            stack.rotate_left(n);
+            change_dir(type_, &stack[0], false, state)?;
+            stack.split_off(1)
+        }
+        Some(dir) => {
+            let dir = PathBuf::from(dir);
+            if flags.has('n') {
+                stack.insert(1, dir);
+                stack.split_off(1)
+            } else {
+                change_dir(type_, &dir, false, state)?;
+                stack
+            }
+        }
+    };
+    write_stack(
+        CommandType::Pushd,
+        state,
+        &Flags::default(),
+        None,
+        ctx.stdout,
+    )
+}
+
+/// Removes the top directory from the directory stack and changes to the one
+/// below it. With `+N` or `-N`, removes that entry instead, and with `-n`,
+/// the directory stays the same.
+pub(crate) fn handle_popd(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let type_ = CommandType::Popd;
+    let (flags, operands) = parse_flags_lenient(&type_, args, "n")?;
+    if state.dirs.is_empty() {
+        return Err(failure(type_, "directory stack empty".into()));
+    }
+
+    let n = match operands.first() {
+        Some(operand) => index(&type_, operand, state.dirs.len() + 1)?,
+        None => 0,
+    };
+    if n > 0 {
+        state.dirs.remove(n - 1);
+    } else {
+        if !flags.has('n') {
+            let top = state.dirs[0].clone();
+            change_dir(type_, &top, false, state)?;
+        }
+        state.dirs.remove(0);
+    }
+    write_stack(
+        CommandType::Popd,
+        state,
+        &Flags::default(),
+        None,
+        ctx.stdout,
+    )
+}
+
+/// Shows the directory stack, starting with the current directory, or only
+/// its `+N` or `-N` entry. With `-c`, clears it instead.
+///
+/// Directories under `$HOME` are shown with `~` unless `-l` is given. `-p`
+/// puts each on its own line, and `-v` numbers them as well.
+pub(crate) fn handle_dirs(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let type_ = CommandType::Dirs;
+    let (flags, operands) = parse_flags_lenient(&type_, args, "clpv")?;
+    if flags.has('c') {
+        state.dirs.clear();
+        return Ok(());
+    }
+    let only = match operands.first() {
+        Some(operand) => Some(index(&type_, operand, state.dirs.len() + 1)?),
+        None => None,
+    };
+    write_stack(type_, state, &flags, only, ctx.stdout)
+}
+
+/// The directory that `~+`, `~-`, or `~N` stands for, given what follows
+/// the `~`: the current directory, the previous one, or entry `N` of the
+/// directory stack as `dirs -v` numbers it. `~+N` counts from the top like
+/// `~N`, and `~-N` from the bottom. `None` if `reference` isn't one of
+/// them, or it is out of range.
+pub(crate) fn stack_reference(reference: &str, state: &ShellState) -> Option<PathBuf> {
+    let stack = stack(state);
+    match reference {
+        "+" => Some(stack[0].clone()),
+        "-" => state.vars.get("OLDPWD").map(PathBuf::from),
+        _ => {
+            let reference = match reference.starts_with(['+', '-']) {
+                true => reference.to_owned(),
+                false => format!("+{reference}"),
+            };
+            let n = index(&CommandType::Dirs, &reference, stack.len()).ok()?;
+            Some(stack[n].clone())
+        }
+    }
+}
+
+/// The whole directory stack, with the current directory on top.
+fn stack(state: &ShellState) -> Vec<PathBuf> {
+    let cwd = logical_dir(state);
+    iter::once(cwd).chain(state.dirs.iter().cloned()).collect()
+}
+
+/// Turns `+N`, counting from the top of the stack, or `-N`, counting from
+/// the bottom, into a position in a stack of `len` entries.
+fn index(type_: &CommandType, operand: &str, len: usize) -> Result<usize, RushError> {
+    let usage_error = |msg: String| RushError::CommandError {
+        type_: type_.clone(),
+        msg,
+        status: ExitStatus::USAGE,
+    };
+
+    let (sign, digits) = operand.split_at_checked(1).unwrap_or_default();
+    if sign != "+" && sign != "-" {
+        return Err(usage_error(format!("{operand}: invalid argument")));
+    }
+    let n: usize = match digits.bytes().all(|b| b.is_ascii_digit()) {
+        true => digits.parse().ok(),
+        false => None,
+    }
+    .ok_or_else(|| usage_error(format!("{operand}: invalid number")))?;
+
+    if n >= len {
+        return Err(failure(
+            type_.clone(),
+            format!("{operand}: directory stack index out of range"),
+        ));
+    }
+    Ok(if sign == "+" { n } else { len - 1 - n })
+}
+
+/// Writes the directory stack, or only its entry at `only`.
+fn write_stack(
+    type_: CommandType,
+    state: &ShellState,
+    flags: &Flags,
+    only: Option<usize>,
+    out: &mut dyn Write,
+) -> Result<(), RushError> {
+    let stack = stack(state);
+    let home = state.vars.get("HOME").filter(|home| !home.is_empty());
+
+    let entries: Vec<(usize, String)> = (stack.iter().enumerate())
+        .filter(|(i, _)| only.is_none_or(|only| *i == only))
+        .map(|(i, dir)| match &home {
+            Some(home) if !flags.has('l') => (i, abbreviate(dir, Path::new(home))),
+            _ => (i, dir.display().to_string()),
+        })
+        .collect();
+
+    let listing = if flags.has('v') {
+        (entries.iter())
+            .map(|(i, entry)| format!("{i:>2}  {entry}\n"))
+            .collect()
+    } else if flags.has('p') || only.is_some() {
+        (entries.iter())
+            .map(|(_, entry)| format!("{entry}\n"))
+            .collect()
+    } else {
+        let entries: Vec<&str> = entries.iter().map(|(_, entry)| entry.as_str()).collect();
+        format!("{}\n", entries.join(" "))
+    };
+    out.write_all(listing.as_bytes())
+        .map_err(|error| RushError::io(type_, error))
+}
+
+/// Shows `dir` relative to `home` as `~`, the way the shell would read it.
+pub(crate) fn abbreviate(dir: &Path, home: &Path) -> String {
+    match dir.strip_prefix(home) {
+        Ok(rest) if rest.as_os_str().is_empty() => "~".into(),
+        Ok(rest) => format!("~/{}", rest.display()),
+        Err(_) => dir.display().to_string(),
+    }
+}
+
+fn failure(type_: CommandType, msg: String) -> RushError {
+    RushError::CommandError {
+        type_,
+        msg,
+        status: ExitStatus::FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::command::handlers::test_support::command;
+    use crate::state::ShellState;
+    use crate::status::ExitStatus;
+
+    /// Runs `input`, giving what it wrote, or what it complained about and
+    /// the status it failed with.
+    fn run(input: &str, state: &mut ShellState) -> Result<String, (String, i32)> {
+        let mut out = Vec::new();
+        let command = command(input, state).unwrap();
+        let (result, errors) = command.run_with_errors(state, &mut out);
+        match result.unwrap() {
+            ExitStatus::SUCCESS => Ok(String::from_utf8(out).unwrap()),
+            status => Err((errors, status.code())),
+        }
+    }
+
+    /// Runs each line from `/`, with `$HOME` out of the way of the listing.
+    fn session(lines: &[&str]) -> Vec<Result<String, (String, i32)>> {
+        let mut state = ShellState::default();
+        state.cwd.set("/".into());
+        state.vars.set("HOME", "/nonexistent");
+
+        lines.iter().map(|line| run(line, &mut state)).collect()
+    }
+
+    #[test]
+    fn pushd_and_popd_walk_the_stack() {
+        let results = session(&["pushd /usr", "pushd /etc", "popd", "popd", "pwd"]);
+        let outputs: Vec<_> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            outputs,
+            ["/usr /\n", "/etc /usr /\n", "/usr /\n", "/\n", "/\n"]
+        );
+    }
+
+    #[test]
+    fn pushd_rotates_and_swaps() {
+        let results = session(&["pushd /usr", "pushd /etc", "pushd +2", "pushd", "dirs -v"]);
+        let outputs: Vec<_> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(outputs[2], "/ /etc /usr\n");
+        assert_eq!(outputs[3], "/etc / /usr\n");
+        assert_eq!(outputs[4], " 0  /etc\n 1  /\n 2  /usr\n");
+    }
+
+    #[test]
+    fn popd_removes_entries_by_index() {
+        let results = session(&[
+            "pushd /usr",
+            "pushd /etc",
+            "popd -1",
+            "popd -n",
+            "dirs -c",
+            "dirs",
+        ]);
+        let outputs: Vec<_> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(outputs[2], "/etc /\n");
+        assert_eq!(outputs[3], "/etc\n");
+        assert_eq!(outputs[5], "/etc\n");
+    }
+
+    #[test]
+    fn entries_are_selected_by_number() {
+        let results = session(&[
+            "pushd /usr",
+            "pushd /etc",
+            "dirs +1",
+            "dirs -0",
+            "dirs -v +2",
+            "cd ~2",
+            "pwd",
+            "cd ~-1/bin",
+            "pwd",
+            "pushd -n ~+",
+            "dirs",
+        ]);
+        let outputs: Vec<_> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(outputs[2], "/usr\n");
+        assert_eq!(outputs[3], "/\n");
+        assert_eq!(outputs[4], " 2  /\n");
+        assert_eq!(outputs[6], "/\n");
+        assert_eq!(outputs[8], "/usr/bin\n");
+        assert_eq!(outputs[10], "/usr/bin /usr/bin /usr /\n");
+    }
+
+    #[test]
+    fn reports_bad_operands() {
+        let results = session(&[
+            "popd",
+            "pushd",
+            "pushd +x",
+            "pushd /usr",
+            "popd +2",
+            "dirs +2",
+            "cd ~5",
+        ]);
+        let errors: Vec<_> = results.into_iter().filter_map(Result::err).collect();
+        assert_eq!(
+            errors,
+            [
+                ("popd: directory stack empty\n".into(), 1),
+                ("pushd: no other directory\n".into(), 1),
+                ("pushd: +x: invalid number\n".into(), 2),
+                ("popd: +2: directory stack index out of range\n".into(), 1),
+                ("dirs: +2: directory stack index out of range\n".into(), 1),
+                ("cd: ~5: No such file or directory\n".into(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted_references_are_directory_names() {
+        let dir = env::temp_dir().join(format!("rush-dirs-tilde-{}", std::process::id()));
+        fs::create_dir_all(dir.join("~1")).unwrap();
+        let mut state = ShellState::default();
+        state.cwd.set(dir.clone());
+        state.vars.set("HOME", "/nonexistent");
+
+        run("pushd /usr", &mut state).unwrap();
+        run("cd ~1", &mut state).unwrap();
+        assert_eq!(
+            run("pwd", &mut state).unwrap(),
+            format!("{}\n", dir.display())
+        );
+        run("cd \"~1\"", &mut state).unwrap();
+        assert_eq!(state.cwd.path(), dir.join("~1"));
+        assert_eq!(
+            run("echo ~1 '~1'", &mut state).unwrap(),
+            format!("{} ~1\n", dir.display())
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,77 +1,221 @@
-use crate::util::RushError;
+use crate::{
+    command::{CommandType, Context, flags::parse_flags_lenient},
+    util::RushError,
+};
 
-pub(crate) fn handle_echo(args: &[String]) -> Result<(), RushError> {
-    // Skip the first argument (command name)
-    let tokens = &args[1..];
+/// Prints its arguments separated by spaces, the way bash's `echo` does.
+///
+/// Leading arguments made up only of `-n`, `-e`, and `-E` letters are
+/// options: `-n` leaves off the trailing newline, and `-e` turns on the
+/// backslash escapes that `-E` turns back off. As for every builtin, `--`
+/// ends the options without being printed. Anything else is printed as is
+/// and ends them too.
+pub(crate) fn handle_echo(args: &[String], ctx: &mut Context) -> Result<(), RushError> {
+    let (flags, words) = parse_flags_lenient(&CommandType::Echo, args, "neE")?;
+    let mut newline = !flags.has('n');
+    let escapes = flags.last_of("eE") == Some('e');
 
-    if tokens.is_empty() {
-        return Ok(());
+    let text = words.join(" ");
+    let mut bytes = match escapes {
+        true => {
+            let (bytes, stopped) = unescape(&text);
+            newline &= !stopped;
+            bytes
+        }
+        false => text.into_bytes(),
+    };
+    if newline {
+        bytes.push(b'\n');
     }
 
-    println!("{}", tokens.join(" "));
-    Ok(())
+    ctx.stdout
+        .write_all(&bytes)
+        .map_err(|error| RushError::io(CommandType::Echo, error))
+}
+
+/// Replaces the backslash escapes `echo -e` understands with what they stand
+/// for. Returns whether a `\c` cut the text short, which also drops the
+/// trailing newline.
+fn unescape(text: &str) -> (Vec<u8>, bool) {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+
+    while let Some((&byte, after)) = rest.split_first() {
+        rest = after;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        let Some((&escape, after)) = rest.split_first() else {
+            bytes.push(b'\\');
+            break;
+        };
+        rest = after;
+        match escape {
+            b'a' => bytes.push(0x07),
+            b'b' => bytes.push(0x08),
+            b'c' => return (bytes, true),
+            b'e' | b'E' => bytes.push(0x1b),
+            b'f' => bytes.push(0x0c),
+            b'n' => bytes.push(b'\n'),
+            b'r' => bytes.push(b'\r'),
+            b't' => bytes.push(b'\t'),
+            b'v' => bytes.push(0x0b),
+            b'\\' => bytes.push(b'\\'),
+            // Up to three octal digits after the `0`
+            b'0' => bytes.push(number(&mut rest, 8, 3)),
+            // Up to two hex digits, but a `\x` with none is kept as is
+            b'x' if rest.first().is_some_and(u8::is_ascii_hexdigit) => {
+                bytes.push(number(&mut rest, 16, 2))
+            }
+            other => bytes.extend([b'\\', other]),
+        }
+    }
+
+    (bytes, false)
+}
+
+/// Reads up to `max` digits in `radix` off the front of `rest` as a byte.
+fn number(rest: &mut &[u8], radix: u32, max: usize) -> u8 {
+    let mut value: u32 = 0;
+    for _ in 0..max {
+        let Some(digit) = rest.first().and_then(|&b| (b as char).to_digit(radix)) else {
+            break;
+        };
+        value = value * radix + digit;
+        *rest = &rest[1..];
+    }
+    // Like bash, `\0400` wraps around rather than overflowing
+    value as u8
 }
 
 #[cfg(test)]
 mod tests {
     use crate::command::Command;
+    use crate::command::handlers::test_support::command;
+    use crate::state::ShellState;
+    use crate::status::ExitStatus;
     use crate::util::RushError;
-    use std::io;
 
     // Test helper to simplify command creation
     fn parse_cmd(input: &str) -> Result<Command, RushError> {
-        Command::new(io::Cursor::new(input))
+        command(input, &mut ShellState::default())
+    }
+
+    fn output(input: &str) -> Vec<u8> {
+        let mut state = ShellState::default();
+        let mut out = Vec::new();
+        command(input, &mut state)
+            .unwrap()
+            .run_with_output(&mut state, &mut out)
+            .unwrap();
+        out
     }
 
     #[test]
     fn no_args() {
         let cmd = parse_cmd("echo").unwrap();
-        assert!(cmd.run().is_ok());
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
     }
 
     #[test]
     fn single_arg() {
         let cmd = parse_cmd("echo hello").unwrap();
-        assert!(cmd.run().is_ok());
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
     }
 
     #[test]
     fn multiple_args() {
         let cmd = parse_cmd("echo hello world test").unwrap();
-        assert!(cmd.run().is_ok());
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
     }
 
     #[test]
     fn quoted_args() {
         let cmd = parse_cmd("echo \'hello world\' test").unwrap();
-        assert!(cmd.run().is_ok());
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
         assert_eq!(cmd.args, vec!["echo", "hello world", "test"]);
     }
 
     #[test]
     fn empty_quoted_string() {
         let cmd = parse_cmd("echo \'\'").unwrap();
-        assert!(cmd.run().is_ok());
-        assert_eq!(cmd.args, vec!["echo"]);
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
+        assert_eq!(cmd.args, vec!["echo", ""]);
     }
 
     #[test]
     fn special_characters() {
-        let cmd = parse_cmd("echo !@#$%^&*()").unwrap();
-        assert!(cmd.run().is_ok());
+        let cmd = parse_cmd("echo '!@#$%^&*()'").unwrap();
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
     }
 
     #[test]
     fn numbers() {
         let cmd = parse_cmd("echo 123 456").unwrap();
-        assert!(cmd.run().is_ok());
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
         assert_eq!(cmd.args, vec!["echo", "123", "456"]);
     }
 
+    #[test]
+    fn double_dash_and_dash_prefixed_args() {
+        assert_eq!(output("echo -- -n"), b"-n\n");
+        assert_eq!(output("echo -n -- x"), b"x");
+        // Where an operand is already expected, `--` is one
+        assert_eq!(output("echo -x -- y"), b"-x -- y\n");
+        assert_eq!(output("echo a -- b"), b"a -- b\n");
+    }
+
     #[test]
     fn with_leading_trailing_spaces() {
         let cmd = parse_cmd("   echo   hello   ").unwrap();
-        assert!(cmd.run().is_ok());
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
         assert_eq!(cmd.args, vec!["echo", "hello"]);
     }
+
+    #[test]
+    fn options_come_first() {
+        assert_eq!(output("echo"), b"\n");
+        assert_eq!(output("echo -n a b"), b"a b");
+        assert_eq!(output("echo -nE a"), b"a");
+        // Anything else ends the options and is printed
+        assert_eq!(output("echo -nx a"), b"-nx a\n");
+        assert_eq!(output("echo a -n"), b"a -n\n");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(output(r"echo -e 'a\tb\nc\\'"), b"a\tb\nc\\\n");
+        assert_eq!(output(r"echo -e '\x41\x4a2\0101\x' '\q'"), b"AJ2A\\x \\q\n");
+        assert_eq!(output(r"echo -e '\xff'"), b"\xff\n");
+        // `\c` stops everything after it, including the newline
+        assert_eq!(output(r"echo -e 'one\ctwo' three"), b"one");
+        // Escapes are left alone without `-e`, or when `-E` comes after it
+        assert_eq!(output(r"echo 'a\tb'"), b"a\\tb\n");
+        assert_eq!(output(r"echo -eE 'a\tb'"), b"a\\tb\n");
+    }
 }
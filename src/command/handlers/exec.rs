@@ -0,0 +1,110 @@
+use std::{io::Write, mem};
+
+use crate::{
+    command::{
+        CommandType,
+        flags::parse_flags,
+        handlers::executable::{Inherited, exec_executable, handle_executable},
+        path::Search,
+        redirect::Streams,
+    },
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// Runs the operand in place of the shell, which never gets control back.
+/// Without one, the command's redirections are applied to the shell itself,
+/// as with `exec >log` or `exec 3<input`, and stay for every later command.
+pub(crate) fn handle_exec(
+    args: &[String],
+    state: &mut ShellState,
+    streams: &mut Streams,
+    out: &mut dyn Write,
+) -> Result<(), RushError> {
+    let (_, operands) = parse_flags(&CommandType::Exec, args, "")?;
+    let error = |msg: String, status| RushError::CommandError {
+        type_: CommandType::Exec,
+        msg,
+        status,
+    };
+
+    let Some(name) = operands.first() else {
+        // A subshell shares the process with the shell, so its redirections
+        // can't outlive it
+        if state.subshell {
+            return Ok(());
+        }
+        return install(mem::take(streams)).map_err(|e| error(e.to_string(), ExitStatus::FAILURE));
+    };
+    let path = state
+        .hash
+        .lookup(name, &Search::new(state))
+        .ok_or_else(|| error(format!("{name}: not found"), ExitStatus::NOT_FOUND))?;
+
+    if state.subshell {
+        // Likewise, replacing the process would end the shell too, so the
+        // program runs as a child and the subshell ends with it
+        let inherited = Inherited::new(state, &[]);
+        let status = handle_executable(&path, name, operands, &inherited, streams, None, out)
+            .unwrap_or_else(|error| error.status());
+        return Err(RushError::Exit(status));
+    }
+
+    // The program takes the shell's place, so it's at the shell's level,
+    // not one inside it
+    let level = state.vars.get("SHLVL");
+    if let Some(level) = level.as_deref().and_then(|level| level.parse::<i64>().ok()) {
+        state.vars.set("SHLVL", &(level - 1).to_string());
+    }
+    let failure = exec_executable(&path, name, operands, &Inherited::new(state, &[]), streams);
+    if let Some(level) = level {
+        state.vars.set("SHLVL", &level);
+    }
+    let status = ExitStatus::from_spawn_error(&failure);
+    Err(error(format!("{name}: {failure}"), status))
+}
+
+#[cfg(unix)]
+fn install(streams: Streams) -> std::io::Result<()> {
+    streams.install()
+}
+
+#[cfg(not(unix))]
+fn install(_streams: Streams) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::command::handlers::test_support::command;
+    use crate::eval::eval;
+    use crate::state::ShellState;
+
+    #[test]
+    fn missing_commands_fail_without_replacing_the_shell() {
+        let mut state = ShellState::default();
+        let command = command("exec rush_exec_missing_12345", &mut state);
+        let (result, errors) = command
+            .unwrap()
+            .run_with_errors(&mut state, &mut io::sink());
+        assert_eq!(errors, "exec: rush_exec_missing_12345: not found\n");
+        assert_eq!(result.unwrap().code(), 127);
+        assert_eq!(
+            eval("exec rush_exec_missing_12345", &mut state)
+                .unwrap()
+                .code(),
+            127
+        );
+        assert_eq!(state.last_status.code(), 127);
+    }
+
+    #[test]
+    fn subshells_run_the_program_and_end() {
+        let mut state = ShellState::default();
+        eval("out=$(exec echo replaced; echo not reached)", &mut state).unwrap();
+        assert_eq!(state.vars.get("out").unwrap(), "replaced");
+    }
+}
@@ -1,78 +1,488 @@
-use std::{io, process};
+#[cfg(unix)]
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::{
+    io::{self, Read, Write},
+    path::PathBuf,
+    process,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use crate::{
+    command::{
+        CommandType,
+        redirect::{Sink, Streams, describe},
+    },
+    jobs::{self, ProcessGroup, Waited},
+    signals::Forwarding,
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// What a program gets from the shell that starts it, besides its streams.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Inherited {
+    /// The directory it starts in.
+    pub dir: PathBuf,
+    /// Its whole environment: the variables the shell exports, and the
+    /// assignments made for just this command on top.
+    pub env: Vec<(String, String)>,
+}
 
-use crate::{command::CommandType, util::RushError};
+impl Inherited {
+    pub(crate) fn new(state: &ShellState, assignments: &[(String, String)]) -> Self {
+        let mut env = state.vars.exported();
+        env.retain(|(name, _)| assignments.iter().all(|(assigned, _)| assigned != name));
+        env.extend(assignments.iter().cloned());
+        Self {
+            dir: state.cwd.path().to_owned(),
+            env,
+        }
+    }
+
+    fn apply(&self, command: &mut process::Command) {
+        command
+            .current_dir(&self.dir)
+            .env_clear()
+            .envs(self.env.iter().cloned());
+    }
+}
 
 pub(crate) fn handle_executable(
     path: &str,
     name: &str,
     args: &[String],
-) -> Result<Option<i32>, RushError> {
-    let into_rush_err = |error: io::Error| RushError::CommandError {
-        type_: CommandType::Executable {
-            path: path.into(),
-            name: name.into(),
-        },
-        msg: error.to_string(),
-        status: error.raw_os_error(),
+    inherited: &Inherited,
+    streams: &mut Streams,
+    group: Option<&ProcessGroup>,
+    out: &mut dyn Write,
+) -> Result<ExitStatus, RushError> {
+    let type_ = || CommandType::Executable {
+        path: path.into(),
+        name: name.into(),
+    };
+    let into_rush_err = |error: io::Error| RushError::io(type_(), error);
+
+    // Output meant for the shell's own stdout and stderr goes straight there,
+    // so a program can tell when it's writing to a terminal. Only output
+    // captured into a buffer, as by a command substitution, goes through a
+    // pipe drained below.
+    let (mut child_stdout, out_writer) = io::pipe().map_err(into_rush_err)?;
+    let out_is_stdout = streams.out_is_stdout;
+    let connect = |sink: &Sink| -> io::Result<process::Stdio> {
+        Ok(match sink {
+            Sink::Out if out_is_stdout => io::stdout().into(),
+            Sink::Out => out_writer.try_clone()?.into(),
+            Sink::Err => io::stderr().into(),
+            Sink::File(file) => file.try_clone()?.into(),
+            Sink::Pipe(pipe) => pipe.try_clone()?.into(),
+            // Closed once the program starts
+            Sink::Closed => process::Stdio::null(),
+        })
     };
 
-    let mut child = process::Command::new(name)
-        .args(&args[1..])
-        .stdout(process::Stdio::piped())
-        .stderr(process::Stdio::piped())
-        .spawn()
-        .map_err(into_rush_err)?;
-
-    // Take ownership of stdout and stderr
-    let mut child_stdout = child.stdout.take().expect("stdout was piped");
-    let mut child_stderr = child.stderr.take().expect("stderr was piped");
-
-    // Spawn threads to copy output in parallel
-    use std::thread;
-    let stdout_thread = thread::spawn(move || io::copy(&mut child_stdout, &mut io::stdout()));
-    let stderr_thread = thread::spawn(move || io::copy(&mut child_stderr, &mut io::stderr()));
-
-    let status = child.wait().map_err(into_rush_err)?;
-
-    // Wait for output threads to finish
-    stdout_thread
-        .join()
-        .expect("stdout thread panicked")
-        .map_err(into_rush_err)?;
-    stderr_thread
-        .join()
-        .expect("stderr thread panicked")
-        .map_err(into_rush_err)?;
-
-    if status.success() {
-        return Ok(status.code());
-    }
-
-    Err(RushError::CommandError {
-        type_: CommandType::Executable {
-            path: path.into(),
-            name: name.into(),
-        },
-        msg: match status.code() {
-            Some(code) => format!("process exited with code {}", code),
-            None => "process terminated by signal".into(),
-        },
-        status: status.code(),
+    // The program's stdin is handed to it by a copy, in case it has to be
+    // started a second time
+    #[cfg(unix)]
+    let closed = streams.closed_fds();
+    let stdin = streams.stdin.take();
+    let build = |program: &str, args: &[String]| -> io::Result<process::Command> {
+        let mut command = process::Command::new(program);
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::arg0(&mut command, &args[0]);
+        inherited.apply(&mut command);
+        command
+            .args(&args[1..])
+            .stdin(match &stdin {
+                Some(source) => source.try_clone()?.into(),
+                None => process::Stdio::inherit(),
+            })
+            .stdout(connect(&streams.stdout)?)
+            .stderr(connect(&streams.stderr)?);
+        #[cfg(unix)]
+        {
+            let (stdout, stderr) = (io::stdout(), io::stderr());
+            let out = match out_is_stdout {
+                true => stdout.as_fd(),
+                false => out_writer.as_fd(),
+            };
+            pass_descriptors(
+                &mut command,
+                streams.extra_fds(out, stderr.as_fd())?,
+                closed.clone(),
+            );
+        }
+        Ok(command)
+    };
+
+    // Anything the shell wrote first has to come out first
+    out.flush().ok();
+    let spawned = interpreting(path, args, |program, args| {
+        let mut command = build(program, args)?;
+        match group {
+            Some(group) => group.spawn(&mut command),
+            None => command.spawn(),
+        }
+    });
+    // Close our copy of the write end, so the pipe sees end of file as soon
+    // as the child exits
+    drop(stdin);
+    drop(out_writer);
+
+    let mut child = spawned.map_err(|error| spawn_error(type_(), path, &error))?;
+    // Signals meant to end the shell while it waits end the program too
+    let _forwarding = match group.and_then(ProcessGroup::pgid) {
+        Some(pgid) => Forwarding::group(pgid),
+        None => Forwarding::process(child.id()),
+    };
+
+    // Only a program in a process group apart from the shell's can be
+    // stopped by Ctrl-Z without stopping the shell
+    let waited = match group {
+        Some(_) => forward_until_stopped(child.id(), child_stdout, out),
+        None => {
+            let forwarded = forward(&mut child_stdout, out);
+            // If nobody is reading the rest, closing our end of the pipe
+            // stops any grandchildren still writing to it
+            drop(child_stdout);
+            forwarded.and_then(|()| child.wait().map(Waited::Exited))
+        }
+    };
+    let status = match waited {
+        Ok(Waited::Exited(status)) => status,
+        // The shell gets on without it, and its output carries on straight
+        // to the terminal once it's continued
+        #[cfg(unix)]
+        Ok(Waited::Stopped) => return Err(RushError::Stopped(child)),
+        Err(error) => {
+            // Nobody is reading the rest, so don't let the child block on a
+            // full pipe or keep producing output
+            child.kill().ok();
+            child.wait().ok();
+            return Err(into_rush_err(error));
+        }
+    };
+
+    if let Some(death) = death(status) {
+        eprintln!("{death}");
+    }
+    // A program that fails has its own say about why, so all that's left is
+    // its status
+    Ok(status.into())
+}
+
+/// What to say about a program that a signal killed with `status`, like
+/// `Terminated`. Like other shells, it says nothing for Ctrl-C, which the
+/// user saw happen, or for a broken pipe, the usual way for a program
+/// writing into a pipeline to end.
+fn death(status: process::ExitStatus) -> Option<String> {
+    #[cfg(unix)]
+    {
+        use crate::signals;
+        use std::os::unix::process::ExitStatusExt;
+        let signal = status.signal()?;
+        if signals::name(signal).is_some_and(|name| name == "INT" || name == "PIPE") {
+            return None;
+        }
+        let description = signals::describe(signal);
+        Some(match status.core_dumped() {
+            true => format!("{description} (core dumped)"),
+            false => description,
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        None
+    }
+}
+
+/// The error for program `path` that couldn't be started because of
+/// `error`: 127 if there's nothing there, or 126 if it can't be run, as for
+/// a directory or a file without execute permission.
+fn spawn_error(type_: CommandType, path: &str, error: &io::Error) -> RushError {
+    let (msg, status) = match std::path::Path::new(path).is_dir() {
+        true => ("Is a directory".into(), ExitStatus::NOT_EXECUTABLE),
+        false => (describe(error), ExitStatus::from_spawn_error(error)),
+    };
+    RushError::CommandError { type_, msg, status }
+}
+
+/// Starts the program at `path` with `args` by calling `start` with the
+/// program to run and its arguments, the first of which is the name it's
+/// told it was run by. A file the system doesn't know how to run, like a
+/// script without a `#!` line, is run by `/bin/sh` instead, as other shells
+/// do.
+fn interpreting<T>(
+    path: &str,
+    args: &[String],
+    mut start: impl FnMut(&str, &[String]) -> io::Result<T>,
+) -> io::Result<T> {
+    match start(path, args) {
+        Err(error) if cfg!(unix) && error.raw_os_error() == Some(libc::ENOEXEC) => {
+            let mut args = args.to_vec();
+            args.splice(..1, ["sh".into(), path.into()]);
+            start("/bin/sh", &args)
+        }
+        started => started,
+    }
+}
+
+/// Starts `name` without waiting for it, as a background job. Its output goes
+/// straight to the shell's own streams, since nothing is left to forward it
+/// once the shell moves on, and it reads from nothing unless redirected, so
+/// it can't steal input meant for the shell. It starts in `group`, if it's
+/// given one.
+pub(crate) fn spawn_executable(
+    path: &str,
+    name: &str,
+    args: &[String],
+    inherited: &Inherited,
+    streams: &mut Streams,
+    group: Option<&ProcessGroup>,
+) -> Result<process::Child, RushError> {
+    let type_ = || CommandType::Executable {
+        path: path.into(),
+        name: name.into(),
+    };
+    interpreting(path, args, |program, args| {
+        let mut command = prepare(program, args, inherited, streams, process::Stdio::null)?;
+        match group {
+            Some(group) => group.spawn(&mut command),
+            None => command.spawn(),
+        }
     })
+    .map_err(|error| spawn_error(type_(), path, &error))
+}
+
+/// Runs `path` in place of the shell, with its output going straight to the
+/// shell's own streams. It only returns if the program couldn't be started.
+#[cfg(unix)]
+pub(crate) fn exec_executable(
+    path: &str,
+    name: &str,
+    args: &[String],
+    inherited: &Inherited,
+    streams: &mut Streams,
+) -> io::Error {
+    use std::os::unix::process::CommandExt;
+
+    let mut command = match prepare(path, args, inherited, streams, process::Stdio::inherit) {
+        Ok(command) => command,
+        Err(error) => return error,
+    };
+    io::stdout().flush().ok();
+    command.arg0(name).exec()
+}
+
+/// Without a way to replace the shell, the program runs as a child and the
+/// shell exits with its status as soon as it finishes.
+#[cfg(not(unix))]
+pub(crate) fn exec_executable(
+    path: &str,
+    _name: &str,
+    args: &[String],
+    inherited: &Inherited,
+    streams: &mut Streams,
+) -> io::Error {
+    let status = prepare(path, args, inherited, streams, process::Stdio::inherit)
+        .and_then(|mut command| command.status());
+    match status {
+        Ok(status) => process::exit(status.code().unwrap_or(1)),
+        Err(error) => error,
+    }
+}
+
+/// Sets up `program` to run with `streams` and what it `inherited`, told it
+/// was run as the first of `args`, sending output meant for the shell's streams
+/// straight to them. Stdin comes from `stdin` unless it is redirected.
+fn prepare(
+    program: &str,
+    args: &[String],
+    inherited: &Inherited,
+    streams: &mut Streams,
+    stdin: fn() -> process::Stdio,
+) -> io::Result<process::Command> {
+    let connect = |sink: &Sink| -> io::Result<process::Stdio> {
+        Ok(match sink {
+            Sink::Out => io::stdout().into(),
+            Sink::Err => io::stderr().into(),
+            Sink::File(file) => file.try_clone()?.into(),
+            Sink::Pipe(pipe) => pipe.try_clone()?.into(),
+            // Closed once the program starts
+            Sink::Closed => process::Stdio::null(),
+        })
+    };
+    let stdout = connect(&streams.stdout)?;
+    let stderr = connect(&streams.stderr)?;
+
+    let mut command = process::Command::new(program);
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::arg0(&mut command, &args[0]);
+    #[cfg(unix)]
+    pass_descriptors(
+        &mut command,
+        streams.extra_fds(io::stdout().as_fd(), io::stderr().as_fd())?,
+        streams.closed_fds(),
+    );
+    inherited.apply(&mut command);
+    command
+        .args(&args[1..])
+        .stdin(match &streams.stdin {
+            Some(source) => source.try_clone()?.into(),
+            None => stdin(),
+        })
+        .stdout(stdout)
+        .stderr(stderr);
+    Ok(command)
+}
+
+/// Gives the child each of `fds` under the number it is paired with, and
+/// closes those `closed`. `process::Command` only sets up the standard
+/// streams, so the rest are copied into place in the child, just before it
+/// runs the program, once the standard ones are in place.
+#[cfg(unix)]
+fn pass_descriptors(command: &mut process::Command, fds: Vec<(u32, OwnedFd)>, closed: Vec<u32>) {
+    use std::{ffi::c_int, os::unix::process::CommandExt};
+
+    // Where the copies are parked, clear of any descriptor a script names
+    const PARKED: c_int = 64;
+
+    if fds.is_empty() && closed.is_empty() {
+        return;
+    }
+    let mut parked = vec![0; fds.len()];
+    // SAFETY: the hook runs in the child between fork and exec, so it only
+    // makes async-signal-safe calls and doesn't allocate
+    unsafe {
+        command.pre_exec(move || {
+            // Every descriptor is moved out of the way first, so copying one
+            // into place can't overwrite another that is yet to be copied
+            for ((_, fd), parked) in fds.iter().zip(&mut parked) {
+                *parked = libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD, PARKED);
+                if *parked < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            for ((target, _), &parked) in fds.iter().zip(&parked) {
+                if libc::dup2(parked, *target as c_int) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                libc::close(parked);
+            }
+            for &fd in &closed {
+                libc::close(fd as c_int);
+            }
+            Ok(())
+        });
+    }
+}
+
+/// What [`forward_until_stopped`] hears about, from the threads reading the
+/// child's output and waiting for it.
+enum Event {
+    Output(Vec<u8>),
+    Closed(io::Result<()>),
+    Waited(io::Result<Waited>),
+}
+
+/// Copies the output of process `pid` from `stdout` into `out` until it
+/// exits and closes its end, or until it's stopped. Reading and waiting
+/// happen on threads of their own, so that a stopped process can be left
+/// behind; any output it writes after that goes straight to the shell's
+/// standard output.
+fn forward_until_stopped(
+    pid: u32,
+    mut stdout: io::PipeReader,
+    out: &mut dyn Write,
+) -> io::Result<Waited> {
+    let (sender, events) = mpsc::channel();
+    let left_behind = Arc::new(AtomicBool::new(false));
+
+    let waiter = sender.clone();
+    thread::spawn(move || waiter.send(Event::Waited(jobs::wait_for(pid))).ok());
+    let detached = Arc::clone(&left_behind);
+    thread::spawn(move || {
+        let mut chunk = [0; 8192];
+        let closed = loop {
+            let n = match stdout.read(&mut chunk) {
+                Ok(0) => break Ok(()),
+                Ok(n) => n,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => break Err(error),
+            };
+            if sender.send(Event::Output(chunk[..n].to_vec())).is_err() {
+                if !detached.load(Ordering::Acquire) {
+                    return;
+                }
+                let mut terminal = io::stdout();
+                if terminal
+                    .write_all(&chunk[..n])
+                    .and_then(|()| terminal.flush())
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        };
+        sender.send(Event::Closed(closed)).ok();
+    });
+
+    let (mut status, mut closed) = (None, false);
+    while status.is_none() || !closed {
+        match events.recv() {
+            Ok(Event::Output(bytes)) => out.write_all(&bytes)?,
+            Ok(Event::Closed(result)) => {
+                result?;
+                closed = true;
+            }
+            Ok(Event::Waited(Ok(Waited::Exited(exited)))) => status = Some(exited),
+            #[cfg(unix)]
+            Ok(Event::Waited(Ok(Waited::Stopped))) => {
+                left_behind.store(true, Ordering::Release);
+                out.flush()?;
+                return Ok(Waited::Stopped);
+            }
+            Ok(Event::Waited(Err(error))) => return Err(error),
+            Err(_) => break,
+        }
+    }
+    out.flush()?;
+    Ok(Waited::Exited(status.unwrap_or_default()))
+}
+
+/// Copies `from` into `out` in fixed-size chunks until end of file.
+fn forward(from: &mut impl Read, out: &mut dyn Write) -> io::Result<()> {
+    let mut chunk = [0; 8192];
+    loop {
+        match from.read(&mut chunk) {
+            Ok(0) => return out.flush(),
+            Ok(n) => out.write_all(&chunk[..n])?,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::command::Command;
+    use crate::state::ShellState;
     use crate::util::RushError;
-    use std::{env, io};
+    use std::env;
 
-    use crate::command::path::find_in_path;
+    use crate::command::handlers::test_support::command;
+    use crate::command::path::{Search, find_in_path};
 
     // Test helper to simplify command creation
     fn parse_cmd(input: &str) -> Result<Command, RushError> {
-        Command::new(io::Cursor::new(input))
+        command(input, &mut ShellState::default())
     }
 
     // Helper to create a Command with an executable type
@@ -83,6 +493,8 @@ mod tests {
                 name: args[0].clone(),
             },
             args,
+            redirections: Vec::new(),
+            env: Vec::new(),
         }
     }
 
@@ -93,7 +505,7 @@ mod tests {
 
         let result = cmd.handle_executable("/usr/bin/true", "true");
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), Some(0));
+        assert_eq!(result.unwrap(), ExitStatus::SUCCESS);
     }
 
     #[test]
@@ -102,13 +514,7 @@ mod tests {
         let cmd = create_executable_command("/usr/bin/false", vec!["false".to_string()]);
 
         let result = cmd.handle_executable("/usr/bin/false", "false");
-        assert!(result.is_err());
-
-        if let Err(RushError::CommandError { status, .. }) = result {
-            assert_eq!(status, Some(1));
-        } else {
-            panic!("Expected CommandError");
-        }
+        assert_eq!(result.unwrap(), ExitStatus::FAILURE);
     }
 
     #[test]
@@ -119,8 +525,9 @@ mod tests {
         let result = cmd.handle_executable("/nonexistent/path/to/binary", "binary");
         assert!(result.is_err());
 
-        if let Err(RushError::CommandError { msg, .. }) = result {
+        if let Err(RushError::CommandError { msg, status, .. }) = result {
             assert!(msg.contains("No such file") || msg.contains("cannot find"));
+            assert_eq!(status, ExitStatus::NOT_FOUND);
         } else {
             panic!("Expected CommandError");
         }
@@ -144,7 +551,13 @@ mod tests {
         let cmd = create_executable_command(temp_file, vec!["rush_test_no_exec".to_string()]);
 
         let result = cmd.handle_executable(temp_file, "rush_test_no_exec");
-        assert!(result.is_err());
+        match result {
+            Err(RushError::CommandError { msg, status, .. }) => {
+                assert_eq!(msg, "Permission denied");
+                assert_eq!(status, ExitStatus::NOT_EXECUTABLE);
+            }
+            other => panic!("expected Permission denied, got {other:?}"),
+        }
 
         // Cleanup
         fs::remove_file(temp_file).ok();
@@ -159,36 +572,43 @@ mod tests {
         );
 
         let result = cmd.handle_executable("/bin/sh", "sh");
-        assert!(result.is_err());
+        assert_eq!(result.unwrap().code(), 42);
+    }
 
-        if let Err(RushError::CommandError { status, .. }) = result {
-            assert_eq!(status, Some(42));
-        } else {
-            panic!("Expected CommandError with exit code 42");
-        }
+    #[cfg(unix)]
+    #[test]
+    fn the_resolved_path_runs_under_the_name_typed() {
+        // The name isn't searched for again, so it needn't be on `PATH`
+        let args: Vec<String> = ["rush-sh", "-c", "echo $0"].map(String::from).into();
+        let mut out = Vec::new();
+        let status = handle_executable(
+            "/bin/sh",
+            "rush-sh",
+            &args,
+            &Inherited::new(&ShellState::default(), &[]),
+            &mut Streams::default(),
+            None,
+            &mut out,
+        );
+        assert_eq!(status.unwrap(), ExitStatus::SUCCESS);
+        assert_eq!(String::from_utf8_lossy(&out), "rush-sh\n");
     }
 
     #[cfg(unix)]
     #[test]
     fn test_signal_termination() {
-        if env::var_os("PATH").is_some() {
-            if let Ok(Some(ref shell_path)) = find_in_path("sh") {
-                let cmd = create_executable_command(
-                    shell_path,
-                    vec!["sh".to_string(), "-c".to_string(), "kill -9 $$".to_string()],
-                );
-
-                let result = cmd.handle_executable(shell_path, "sh");
-                assert!(result.is_err());
-
-                if let Err(RushError::CommandError { status, msg, .. }) = result {
-                    // When killed by signal, exit code is None
-                    assert_eq!(status, None);
-                    assert!(msg.contains("signal") || msg.contains("terminated"));
-                } else {
-                    panic!("Expected CommandError from signal");
-                }
-            }
+        if env::var_os("PATH").is_some()
+            && let Ok(Some(ref shell_path)) =
+                find_in_path("sh", &Search::new(&ShellState::default()))
+        {
+            let cmd = create_executable_command(
+                shell_path,
+                vec!["sh".to_string(), "-c".to_string(), "kill -9 $$".to_string()],
+            );
+
+            let result = cmd.handle_executable(shell_path, "sh");
+            // SIGKILL is reported as 128 + 9
+            assert_eq!(result.unwrap().code(), 137);
         }
     }
 
@@ -198,8 +618,8 @@ mod tests {
             let cmd = parse_cmd("true").unwrap();
             assert!(matches!(cmd.type_, CommandType::Executable { .. }));
 
-            let result = cmd.run();
-            assert!(result.is_ok());
+            let result = cmd.run(&mut ShellState::default());
+            assert_eq!(result.unwrap(), ExitStatus::SUCCESS);
         }
     }
 
@@ -207,7 +627,8 @@ mod tests {
     fn test_integration_executable_with_arguments() {
         if env::var_os("PATH").is_some() {
             // Use 'echo' from PATH (not the builtin, but /bin/echo)
-            if let Ok(Some(echo_path)) = find_in_path("echo") {
+            if let Ok(Some(echo_path)) = find_in_path("echo", &Search::new(&ShellState::default()))
+            {
                 // Skip this test if echo is not found as a separate executable
                 if echo_path.starts_with("/") {
                     let input = format!("{} hello world", echo_path);
@@ -220,8 +641,8 @@ mod tests {
                         panic!("Expected Executable type");
                     }
 
-                    let result = cmd.run();
-                    assert!(result.is_ok());
+                    let result = cmd.run(&mut ShellState::default());
+                    assert_eq!(result.unwrap(), ExitStatus::SUCCESS);
                 }
             }
         }
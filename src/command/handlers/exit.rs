@@ -0,0 +1,93 @@
+use std::io::{self, Write};
+
+use crate::{
+    command::{CommandType, flags::parse_flags_lenient},
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// Works out the status `exit` leaves the shell with: its operand folded into
+/// `0..=255`, or the status of the last command when there isn't one. An
+/// error means the shell should keep running.
+pub(crate) fn exit_status(args: &[String], state: &ShellState) -> Result<ExitStatus, RushError> {
+    status_operand(CommandType::Exit, args, state, &mut io::stderr())
+}
+
+/// Reads the status operand of `exit` or `return`, which defaults to the
+/// status of the last command. A bad one is complained about on `err`.
+pub(crate) fn status_operand(
+    type_: CommandType,
+    args: &[String],
+    state: &ShellState,
+    err: &mut dyn Write,
+) -> Result<ExitStatus, RushError> {
+    // Lenient so that `exit -1` reads as a number rather than an option
+    let (_, operands) = parse_flags_lenient(&type_, args, "")?;
+
+    match operands {
+        [] => Ok(state.last_status),
+        [code] => match code.trim().parse::<i64>() {
+            Ok(code) => Ok(ExitStatus::from_code(code as i32)),
+            // Like other shells, still finish, but report the bad argument
+            Err(_) => {
+                writeln!(err, "rush: {type_}: {code}: numeric argument required").ok();
+                Ok(ExitStatus::USAGE)
+            }
+        },
+        _ => Err(RushError::CommandError {
+            type_,
+            msg: "too many arguments".into(),
+            status: ExitStatus::FAILURE,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(input: &str, last: ExitStatus) -> Result<ExitStatus, RushError> {
+        let args: Vec<String> = input.split_whitespace().map(String::from).collect();
+        let state = ShellState {
+            last_status: last,
+            ..ShellState::default()
+        };
+        exit_status(&args, &state)
+    }
+
+    #[test]
+    fn defaults_to_last_status() {
+        let last = ExitStatus::from_code(3);
+        assert_eq!(status("exit", last).unwrap(), last);
+    }
+
+    #[test]
+    fn operand_is_normalized() {
+        for (input, expected) in [
+            ("exit 0", 0),
+            ("exit 7", 7),
+            ("exit 300", 44),
+            ("exit -1", 255),
+            ("exit -- 5", 5),
+        ] {
+            let code = status(input, ExitStatus::SUCCESS).unwrap().code();
+            assert_eq!(code, expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn non_numeric_operand() {
+        assert_eq!(
+            status("exit nope", ExitStatus::SUCCESS).unwrap(),
+            ExitStatus::USAGE
+        );
+    }
+
+    #[test]
+    fn too_many_operands() {
+        let error = status("exit 1 2", ExitStatus::SUCCESS).unwrap_err();
+        assert_eq!(error.to_string(), "exit: too many arguments");
+        assert_eq!(error.status(), ExitStatus::FAILURE);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{
+    command::{CommandType, Context, flags::parse_flags},
+    state::ShellState,
+    status::ExitStatus,
+    util::{RushError, is_name},
+};
+
+/// Exports each `NAME` or `NAME=value` operand to the programs the shell
+/// runs, or with `-n`, stops exporting it. Without operands, lists what is
+/// exported.
+pub(crate) fn handle_export(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let (flags, operands) = parse_flags(&CommandType::Export, args, "np")?;
+
+    if operands.is_empty() {
+        for (name, value) in state.vars.exported() {
+            writeln!(ctx.stdout, "declare -x {name}=\"{}\"", escape(&value))
+                .map_err(|error| RushError::io(CommandType::Export, error))?;
+        }
+        return Ok(());
+    }
+
+    // A bad name doesn't stop the ones after it from being exported
+    let mut result = Ok(());
+    for operand in operands {
+        let (name, value) = match operand.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (operand.as_str(), None),
+        };
+        if !is_name(name) {
+            result = Err(ctx.complain(RushError::CommandError {
+                type_: CommandType::Export,
+                msg: format!("`{operand}': not a valid identifier"),
+                status: ExitStatus::FAILURE,
+            }));
+            continue;
+        }
+
+        let value = match value.map(|value| state.vars.value_for(name, value)) {
+            Some(Ok(value)) => Some(value),
+            Some(Err(error)) => {
+                result = Err(ctx.complain(error));
+                continue;
+            }
+            None => None,
+        };
+        if flags.has('n') {
+            if let Some(value) = value {
+                state.vars.set(name, &value);
+            }
+            state.vars.unexport(name);
+        } else {
+            state.vars.export(name, value.as_deref());
+        }
+    }
+    result
+}
+
+/// Escapes the characters that are special inside double quotes, so the
+/// listing can be read back in.
+pub(crate) fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use crate::command::handlers::test_support::{fail, run};
+    use crate::state::ShellState;
+
+    #[test]
+    fn exports_to_the_environment() {
+        let mut state = ShellState::default();
+        state.vars.set("RUSH_EXPORT_TEST_A", "a");
+        run("export RUSH_EXPORT_TEST_A RUSH_EXPORT_TEST_B=b", &mut state).unwrap();
+        let exported = state.vars.exported();
+        assert!(exported.contains(&("RUSH_EXPORT_TEST_A".into(), "a".into())));
+        assert!(exported.contains(&("RUSH_EXPORT_TEST_B".into(), "b".into())));
+        assert!(env::var_os("RUSH_EXPORT_TEST_A").is_none());
+
+        run(
+            "export -n RUSH_EXPORT_TEST_A RUSH_EXPORT_TEST_B",
+            &mut state,
+        )
+        .unwrap();
+        assert!(!state.vars.is_exported("RUSH_EXPORT_TEST_A"));
+        assert_eq!(state.vars.get("RUSH_EXPORT_TEST_B").unwrap(), "b");
+    }
+
+    #[test]
+    fn lists_exported_variables() {
+        let mut state = ShellState::default();
+        run(r#"export RUSH_EXPORT_TEST_LIST='say "$hi"'"#, &mut state).unwrap();
+        let listing = run("export -p", &mut state).unwrap();
+        assert!(listing.contains("declare -x RUSH_EXPORT_TEST_LIST=\"say \\\"\\$hi\\\"\"\n"));
+    }
+
+    #[test]
+    fn rejects_bad_names() {
+        let mut state = ShellState::default();
+        assert_eq!(
+            fail("export 1x RUSH_EXPORT_TEST_OK=1 2y", &mut state),
+            (
+                "export: `1x': not a valid identifier\n\
+                 export: `2y': not a valid identifier\n"
+                    .into(),
+                1
+            )
+        );
+        // The good one is still exported
+        assert!(state.vars.is_exported("RUSH_EXPORT_TEST_OK"));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{
+    command::{CommandType, Context, flags::parse_flags, path::Search},
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// Looks up each operand on `PATH` and remembers where it is. Without
+/// operands, shows what is remembered; with `-r`, forgets all of it.
+pub(crate) fn handle_hash(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let (flags, operands) = parse_flags(&CommandType::Hash, args, "r")?;
+    let write_error = |error| RushError::io(CommandType::Hash, error);
+
+    if flags.has('r') {
+        state.hash.clear();
+    }
+    if operands.is_empty() {
+        if flags.has('r') {
+            return Ok(());
+        }
+        let mut entries = state.hash.iter().peekable();
+        if entries.peek().is_none() {
+            return writeln!(ctx.stdout, "hash: hash table empty").map_err(write_error);
+        }
+        writeln!(ctx.stdout, "hits\tcommand").map_err(write_error)?;
+        for (_, hashed) in entries {
+            writeln!(ctx.stdout, "{:>4}\t{}", hashed.hits, hashed.path).map_err(write_error)?;
+        }
+        return Ok(());
+    }
+
+    // A missing command doesn't stop the ones after it being remembered
+    let mut result = Ok(());
+    for name in operands {
+        if state.hash.remember(name, &Search::new(state)).is_none() {
+            result = Err(ctx.complain(RushError::CommandError {
+                type_: CommandType::Hash,
+                msg: format!("{name}: not found"),
+                status: ExitStatus::FAILURE,
+            }));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::handlers::test_support::{fail, run};
+    use crate::command::path::{Search, find_in_path};
+    use crate::eval::eval;
+    use crate::state::ShellState;
+
+    #[test]
+    fn remembers_commands_as_they_run() {
+        let Ok(Some(truth)) = find_in_path("true", &Search::new(&ShellState::default())) else {
+            return;
+        };
+        let mut state = ShellState::default();
+        assert_eq!(run("hash", &mut state).unwrap(), "hash: hash table empty\n");
+
+        eval("true", &mut state).unwrap();
+        eval("true", &mut state).unwrap();
+        run("hash true", &mut state).unwrap();
+        assert_eq!(
+            run("hash", &mut state).unwrap(),
+            format!("hits\tcommand\n   2\t{truth}\n")
+        );
+
+        run("hash -r", &mut state).unwrap();
+        assert_eq!(run("hash", &mut state).unwrap(), "hash: hash table empty\n");
+    }
+
+    #[test]
+    fn reports_missing_commands() {
+        let mut state = ShellState::default();
+        assert_eq!(
+            fail(
+                "hash rush_hash_missing_1 true rush_hash_missing_2",
+                &mut state
+            ),
+            (
+                "hash: rush_hash_missing_1: not found\n\
+                 hash: rush_hash_missing_2: not found\n"
+                    .into(),
+                1
+            )
+        );
+        // The one in between is still remembered
+        assert_eq!(state.hash.iter().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directories_on_path_are_never_hashed() {
+        use std::{env, fs, os::unix::fs::PermissionsExt};
+
+        let dir = env::temp_dir().join(format!("rush-hash-dirs-{}", std::process::id()));
+        fs::create_dir_all(dir.join("first/tool")).unwrap();
+        fs::create_dir_all(dir.join("second")).unwrap();
+        let tool = dir.join("second/tool");
+        fs::write(&tool, "exit 0\n").unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        let tool = tool.to_string_lossy().to_string();
+
+        // What `hash` remembers is what `type` reports and what runs
+        let mut state = ShellState::default();
+        let path = format!("{0}/first:{0}/second", dir.display());
+        state.vars.set("PATH", &path);
+        run("hash tool", &mut state).unwrap();
+        assert_eq!(
+            run("hash", &mut state).unwrap(),
+            format!("hits\tcommand\n   0\t{tool}\n")
+        );
+        assert_eq!(
+            run("type tool", &mut state).unwrap(),
+            format!("tool is hashed ({tool})\n")
+        );
+        eval("tool", &mut state).unwrap();
+        assert_eq!(state.hash.iter().next().unwrap().1.hits, 1);
+
+        let mut state = ShellState::default();
+        state.vars.set("PATH", &format!("{}/first", dir.display()));
+        assert_eq!(
+            fail("hash tool", &mut state),
+            ("hash: tool: not found\n".into(), 1)
+        );
+        assert_eq!(fail("type tool", &mut state).1, 1);
+        assert_eq!(state.hash.iter().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,429 @@
+use crate::{
+    command::{CommandType, Context, flags::parse_flags},
+    editor::terminal::Settings,
+    jobs::{self, Job, SpecError, Waited},
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// Lists the background jobs, including the ones that finished since the
+/// shell last said so. `-l` adds their process ids, and `-p` shows only
+/// those.
+pub(crate) fn handle_jobs(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let (flags, _) = parse_flags(&CommandType::Jobs, args, "lp")?;
+    let write_error = |error| RushError::io(CommandType::Jobs, error);
+
+    // A job that just finished is shown as it was, so it may still be the
+    // current one
+    let current = state.jobs.current().map(|job| job.id);
+    let previous = state.jobs.previous().map(|job| job.id);
+    let finished = state.jobs.reap();
+    let mut listed: Vec<_> = finished
+        .iter()
+        .map(|(job, status)| (job, Some(*status)))
+        .chain(state.jobs.iter().map(|job| (job, None)))
+        .collect();
+    listed.sort_by_key(|(job, _)| job.id);
+
+    for (job, status) in listed {
+        if flags.has('p') {
+            writeln!(ctx.stdout, "{}", job.pid()).map_err(write_error)?;
+            continue;
+        }
+        let mark = match Some(job.id) {
+            id if id == current => '+',
+            id if id == previous => '-',
+            _ => ' ',
+        };
+        writeln!(ctx.stdout, "{}", job.listing(mark, status, flags.has('l')))
+            .map_err(write_error)?;
+    }
+    Ok(())
+}
+
+/// Continues a stopped or background job, the one named or else the
+/// current one, and waits for it as if it had been run from the prompt. It
+/// finishes with the job's status, or is stopped again by another Ctrl-Z.
+pub(crate) fn handle_fg(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let type_ = CommandType::Fg;
+    let (_, operands) = parse_flags(&type_, args, "")?;
+    let id = job_id(&type_, operands.first().map(String::as_str), state)?;
+    let job = state.jobs.get_mut(id).expect("job_id finds a job");
+    writeln!(ctx.stdout, "{}", job.command).map_err(|error| RushError::io(type_.clone(), error))?;
+    ctx.stdout.flush().ok();
+
+    let failure = |error: std::io::Error| failure(type_.clone(), format!("%{id}: {error}"));
+    // Only a job with a process group of its own can have the terminal
+    let settings = Settings::save();
+    #[cfg(unix)]
+    jobs::hand_terminal(job.pgid()).ok();
+    let waited = job.resume().and_then(|()| job.wait());
+    #[cfg(unix)]
+    jobs::reclaim_terminal().ok();
+    let succeeded = matches!(&waited, Ok(Waited::Exited(status)) if status.success());
+    jobs::settle_terminal(settings.as_ref(), succeeded);
+    match waited.map_err(failure)? {
+        #[cfg(unix)]
+        Waited::Stopped => {
+            writeln!(ctx.stderr, "\n{}", job.listing('+', None, false)).ok();
+            Err(RushError::Reported(ExitStatus::stopped()))
+        }
+        Waited::Exited(status) => {
+            state.jobs.remove(id);
+            match status.into() {
+                ExitStatus::SUCCESS => Ok(()),
+                status => Err(RushError::Reported(status)),
+            }
+        }
+    }
+}
+
+/// Continues each stopped job named, or the current one, in the background.
+pub(crate) fn handle_bg(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let type_ = CommandType::Bg;
+    let (_, operands) = parse_flags(&type_, args, "")?;
+    let operands: Vec<Option<&str>> = match operands {
+        [] => vec![None],
+        operands => operands
+            .iter()
+            .map(|operand| Some(operand.as_str()))
+            .collect(),
+    };
+
+    let mut result = Ok(());
+    for operand in operands {
+        let id = match job_id(&type_, operand, state) {
+            Ok(id) => id,
+            Err(error) => {
+                result = Err(ctx.complain(error));
+                continue;
+            }
+        };
+        let job = state.jobs.get_mut(id).expect("job_id finds a job");
+        if !job.stopped {
+            writeln!(ctx.stderr, "{type_}: job {id} already in background").ok();
+            continue;
+        }
+        if let Err(error) = job.resume() {
+            result = Err(ctx.complain(failure(type_.clone(), format!("%{id}: {error}"))));
+            continue;
+        }
+        writeln!(ctx.stdout, "[{id}]+ {} &", job.command)
+            .map_err(|error| RushError::io(type_.clone(), error))?;
+    }
+    result
+}
+
+/// Waits for each job named, or process by its id, or else for every job,
+/// and finishes with the status of the last one named. A stopped job isn't
+/// waited for, and finishes it as if it had just been stopped.
+pub(crate) fn handle_wait(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let type_ = CommandType::Wait;
+    let (_, operands) = parse_flags(&type_, args, "")?;
+    if operands.is_empty() {
+        let ids: Vec<_> = state.jobs.iter().map(|job| job.id).collect();
+        for id in ids {
+            wait_for_job(&type_, id, state)?;
+        }
+        return Ok(());
+    }
+
+    let mut status = ExitStatus::SUCCESS;
+    for operand in operands {
+        let id = match operand.parse::<u32>() {
+            Ok(pid) => (state.jobs.iter())
+                .find(|job| job.pids().any(|id| id == pid))
+                .map(|job| job.id)
+                .ok_or_else(|| {
+                    failure(
+                        type_.clone(),
+                        format!("pid {pid} is not a child of this shell"),
+                    )
+                }),
+            Err(_) => find_job(&type_, operand, state).map(|job| job.id),
+        };
+        status = match id {
+            Ok(id) => wait_for_job(&type_, id, state)?,
+            Err(error) => {
+                ctx.complain(error);
+                ExitStatus::NOT_FOUND
+            }
+        };
+    }
+    match status {
+        ExitStatus::SUCCESS => Ok(()),
+        status => Err(RushError::Reported(status)),
+    }
+}
+
+/// Waits for job `id` to finish, forgetting it once it has, and gives back
+/// its status.
+fn wait_for_job(
+    type_: &CommandType,
+    id: usize,
+    state: &mut ShellState,
+) -> Result<ExitStatus, RushError> {
+    let job = state.jobs.get_mut(id).expect("waiting for a job there is");
+    if job.stopped {
+        return Ok(ExitStatus::stopped());
+    }
+    let waited = job.wait();
+    match waited.map_err(|error| failure(type_.clone(), format!("%{id}: {error}")))? {
+        #[cfg(unix)]
+        Waited::Stopped => Ok(ExitStatus::stopped()),
+        Waited::Exited(status) => {
+            state.jobs.remove(id);
+            Ok(status.into())
+        }
+    }
+}
+
+/// Forgets each job named, or the current one, so the shell no longer
+/// lists it or sends it `HUP` when it exits. `-a` forgets every job.
+pub(crate) fn handle_disown(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let type_ = CommandType::Disown;
+    let (flags, operands) = parse_flags(&type_, args, "a")?;
+    if flags.has('a') {
+        let ids: Vec<_> = state.jobs.iter().map(|job| job.id).collect();
+        for id in ids {
+            state.jobs.remove(id);
+        }
+        return Ok(());
+    }
+    let operands: Vec<Option<&str>> = match operands {
+        [] => vec![None],
+        operands => operands
+            .iter()
+            .map(|operand| Some(operand.as_str()))
+            .collect(),
+    };
+
+    let mut result = Ok(());
+    for operand in operands {
+        match job_id(&type_, operand, state) {
+            Ok(id) => {
+                state.jobs.remove(id);
+            }
+            Err(error) => result = Err(ctx.complain(error)),
+        }
+    }
+    result
+}
+
+/// The number of the job `operand` names, with or without its `%`, or of
+/// the current job without one.
+fn job_id(
+    type_: &CommandType,
+    operand: Option<&str>,
+    state: &ShellState,
+) -> Result<usize, RushError> {
+    match operand {
+        Some(operand) => find_job(type_, operand, state).map(|job| job.id),
+        None => (state.jobs.current())
+            .map(|job| job.id)
+            .ok_or_else(|| failure(type_.clone(), "current: no such job".into())),
+    }
+}
+
+/// The job a job specifier like `%1`, `%-` or `%make` names.
+pub(crate) fn find_job<'a>(
+    type_: &CommandType,
+    operand: &str,
+    state: &'a ShellState,
+) -> Result<&'a Job, RushError> {
+    let spec = operand.strip_prefix('%').unwrap_or(operand);
+    state.jobs.find(spec).map_err(|error| {
+        let msg = match error {
+            SpecError::NoSuchJob => format!("{operand}: no such job"),
+            // Like bash, this names just the text it looked for
+            SpecError::Ambiguous => {
+                let text = spec.strip_prefix('?').unwrap_or(spec);
+                format!("{text}: ambiguous job spec")
+            }
+        };
+        failure(type_.clone(), msg)
+    })
+}
+
+fn failure(type_: CommandType, msg: String) -> RushError {
+    RushError::CommandError {
+        type_,
+        msg,
+        status: ExitStatus::FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{process, thread, time::Duration};
+
+    #[cfg(unix)]
+    use super::jobs;
+    use crate::command::handlers::test_support::{command, fail, run, spawn};
+    use crate::state::ShellState;
+
+    #[test]
+    fn lists_running_and_finished_jobs() {
+        let mut state = ShellState::default();
+        state.jobs.add("sleep 5", vec![spawn("sleep 5")]);
+        state.jobs.add("true", vec![spawn("true")]);
+        let pids: Vec<_> = state.jobs.iter().map(|job| job.pid()).collect();
+        thread::sleep(Duration::from_millis(100));
+
+        assert_eq!(
+            run("jobs -p", &mut state).unwrap(),
+            format!("{}\n{}\n", pids[0], pids[1])
+        );
+        // The finished job was shown by the first listing, so it's gone now
+        assert_eq!(
+            run("jobs", &mut state).unwrap(),
+            "[1]+  Running                 sleep 5 &\n"
+        );
+        assert_eq!(fail("jobs -x", &mut state).1, 2);
+
+        process::Command::new("kill")
+            .arg(pids[0].to_string())
+            .status()
+            .unwrap();
+    }
+
+    /// A job for a process that stops itself, as Ctrl-Z would stop it,
+    /// and then exits with `status` once it's continued.
+    #[cfg(unix)]
+    fn stopped_job(state: &mut ShellState, status: i32) -> usize {
+        let child = spawn(&format!("kill -STOP $$; exit {status}"));
+        assert!(matches!(
+            jobs::wait_for(child.id()).unwrap(),
+            jobs::Waited::Stopped
+        ));
+        let pgid = child.id();
+        state.jobs.add_stopped("resumable", vec![child], pgid).id
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fg_waits_for_a_continued_job() {
+        let mut state = ShellState::default();
+        let id = stopped_job(&mut state, 3);
+        assert_eq!(
+            run("jobs", &mut state).unwrap(),
+            "[1]+  Stopped                 resumable\n"
+        );
+
+        let mut out = Vec::new();
+        let status = command(&format!("fg -- %{id}"), &mut state)
+            .unwrap()
+            .run_with_output(&mut state, &mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "resumable\n");
+        assert_eq!(status.code(), 3);
+        assert!(state.jobs.get(id).is_none());
+
+        assert_eq!(
+            fail("fg", &mut state),
+            ("fg: current: no such job\n".into(), 1)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bg_continues_jobs_in_the_background() {
+        let mut state = ShellState::default();
+        stopped_job(&mut state, 0);
+        assert_eq!(run("bg", &mut state).unwrap(), "[1]+ resumable &\n");
+        assert!(!state.jobs.get(1).unwrap().stopped);
+
+        assert_eq!(
+            fail("bg %2", &mut state),
+            ("bg: %2: no such job\n".into(), 1)
+        );
+        assert_eq!(
+            fail("bg -- x", &mut state),
+            ("bg: x: no such job\n".into(), 1)
+        );
+        stopped_job(&mut state, 0);
+        assert_eq!(
+            fail("bg %?resum", &mut state).0,
+            "bg: resum: ambiguous job spec\n"
+        );
+        assert_eq!(run("bg %+", &mut state).unwrap(), "[2]+ resumable &\n");
+        for _ in 0..200 {
+            state.jobs.reap();
+            if state.jobs.iter().next().is_none() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn wait_finishes_with_the_job_status() {
+        let mut state = ShellState::default();
+        state.jobs.add("exit 3", vec![spawn("exit 3")]);
+        state.jobs.add("exit 4", vec![spawn("exit 4")]);
+        let pid = state.jobs.get(2).unwrap().pid();
+        let status = command("wait %1", &mut state)
+            .unwrap()
+            .run(&mut state)
+            .unwrap();
+        assert_eq!(status.code(), 3);
+        assert!(state.jobs.get(1).is_none());
+        let status = command(&format!("wait {pid}"), &mut state)
+            .unwrap()
+            .run(&mut state)
+            .unwrap();
+        assert_eq!(status.code(), 4);
+
+        assert_eq!(
+            fail("wait %1", &mut state),
+            ("wait: %1: no such job\n".into(), 127)
+        );
+        assert_eq!(
+            fail("wait 1", &mut state),
+            ("wait: pid 1 is not a child of this shell\n".into(), 127)
+        );
+        state.jobs.add("true", vec![spawn("true")]);
+        assert_eq!(run("wait", &mut state).unwrap(), "");
+        assert!(state.jobs.iter().next().is_none());
+    }
+
+    #[test]
+    fn disown_forgets_jobs() {
+        let mut state = ShellState::default();
+        for _ in 0..3 {
+            state.jobs.add("true", vec![spawn("true")]);
+        }
+        run("disown %2", &mut state).unwrap();
+        let ids: Vec<_> = state.jobs.iter().map(|job| job.id).collect();
+        assert_eq!(ids, [1, 3]);
+        assert_eq!(
+            fail("disown %2", &mut state),
+            ("disown: %2: no such job\n".into(), 1)
+        );
+        run("disown", &mut state).unwrap();
+        assert_eq!(state.jobs.iter().count(), 1);
+        run("disown -a", &mut state).unwrap();
+        assert!(state.jobs.iter().next().is_none());
+    }
+}
@@ -0,0 +1,177 @@
+use std::io::Write;
+
+use crate::{
+    command::{CommandType, Context, handlers::jobs::find_job, redirect::describe},
+    signals,
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+const USAGE: &str =
+    "usage: kill [-s sigspec | -n signum | -sigspec] pid | jobspec ... or kill -l [sigspec]";
+
+/// Sends a signal, `TERM` unless another is named, to each process or `%`
+/// job. With `-l`, lists the signals, or translates between the names and
+/// numbers given.
+pub(crate) fn handle_kill(
+    args: &[String],
+    state: &ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let usage_error = || RushError::CommandError {
+        type_: CommandType::Kill,
+        msg: USAGE.into(),
+        status: ExitStatus::USAGE,
+    };
+
+    // Signal numbers look like options, so these are picked apart by hand
+    let mut signal = signals::number("TERM").expect("TERM is a signal");
+    let mut operands = &args[1..];
+    match operands.first().map(String::as_str) {
+        Some("-l" | "-L") => return list(&operands[1..], ctx.stdout),
+        Some("-s" | "-n") => {
+            signal = parse_signal(operands.get(1).ok_or_else(usage_error)?)?;
+            operands = &operands[2..];
+        }
+        Some("--") => operands = &operands[1..],
+        Some(arg) if arg.len() > 1 && arg.starts_with('-') => {
+            signal = parse_signal(&arg[1..])?;
+            operands = &operands[1..];
+        }
+        _ => {}
+    }
+    if operands.first().is_some_and(|arg| arg == "--") {
+        operands = &operands[1..];
+    }
+    if operands.is_empty() {
+        return Err(usage_error());
+    }
+
+    // A bad operand doesn't stop the signal reaching the ones after it
+    let mut result = Ok(());
+    for operand in operands {
+        let sent = pids(operand, state).and_then(|pids| {
+            pids.into_iter().try_for_each(|pid| {
+                signals::send(pid, signal)
+                    .map_err(|error| failure(format!("({pid}) - {}", describe(&error))))
+            })
+        });
+        if let Err(error) = sent {
+            result = Err(ctx.complain(error));
+        }
+    }
+    result
+}
+
+/// The processes `operand` names: a process id, or every process of a job
+/// like `%N`.
+fn pids(operand: &str, state: &ShellState) -> Result<Vec<u32>, RushError> {
+    match operand.starts_with('%') {
+        true => find_job(&CommandType::Kill, operand, state).map(|job| job.pids().collect()),
+        false => operand
+            .parse()
+            .map(|pid| vec![pid])
+            .map_err(|_| failure(format!("{operand}: arguments must be process or job IDs"))),
+    }
+}
+
+/// The number of the signal `spec` names, as a number or a name.
+fn parse_signal(spec: &str) -> Result<i32, RushError> {
+    let number = match spec.parse::<i32>() {
+        // Signal 0 checks the process exists without disturbing it
+        Ok(0) => Some(0),
+        Ok(number) => signals::name(number).map(|_| number),
+        Err(_) => signals::number(spec),
+    };
+    number.ok_or_else(|| failure(format!("{spec}: invalid signal specification")))
+}
+
+/// Shows every signal, or the name of each number given and the number of
+/// each name. A number above 128 is read as an exit status, as in `kill -l $?`.
+fn list(specs: &[String], out: &mut dyn Write) -> Result<(), RushError> {
+    let write_error = |error| RushError::io(CommandType::Kill, error);
+
+    if specs.is_empty() {
+        let entries: Vec<_> = signals::all()
+            .map(|(number, name)| format!("{number:>2}) SIG{name}"))
+            .collect();
+        for row in entries.chunks(5) {
+            writeln!(out, "{}", row.join("\t")).map_err(write_error)?;
+        }
+        return Ok(());
+    }
+
+    for spec in specs {
+        let line = match spec.parse::<i32>() {
+            Ok(number) => {
+                signals::name(number.checked_sub(128).filter(|n| *n > 0).unwrap_or(number))
+                    .map(str::to_owned)
+            }
+            Err(_) => signals::number(spec).map(|number| number.to_string()),
+        };
+        let line = line.ok_or_else(|| failure(format!("{spec}: invalid signal specification")))?;
+        writeln!(out, "{line}").map_err(write_error)?;
+    }
+    Ok(())
+}
+
+fn failure(msg: String) -> RushError {
+    RushError::CommandError {
+        type_: CommandType::Kill,
+        msg,
+        status: ExitStatus::FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(unix)]
+    use crate::command::handlers::test_support::spawn;
+    use crate::command::handlers::test_support::{fail, run};
+    use crate::state::ShellState;
+
+    #[test]
+    fn lists_signals() {
+        let mut state = ShellState::default();
+        let table = run("kill -l", &mut state).unwrap();
+        assert!(table.starts_with(" 1) SIGHUP\t 2) SIGINT\t"));
+        assert_eq!(
+            run("kill -l 9 TERM 130", &mut state).unwrap(),
+            "KILL\n15\nINT\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn signals_jobs_and_processes() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let mut state = ShellState::default();
+        state.jobs.add("sleep 5", vec![spawn("sleep 5")]);
+        run("kill -s KILL %1", &mut state).unwrap();
+
+        let mut child = spawn("sleep 5");
+        run(&format!("kill -INT {}", child.id()), &mut state).unwrap();
+        assert_eq!(child.wait().unwrap().signal(), Some(2));
+
+        assert_eq!(
+            fail("kill %9", &mut state),
+            ("kill: %9: no such job\n".into(), 1)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let mut state = ShellState::default();
+        assert_eq!(
+            fail("kill -s FOO 1", &mut state).0,
+            "kill: FOO: invalid signal specification\n"
+        );
+        assert_eq!(
+            fail("kill -9 abc", &mut state).0,
+            "kill: abc: arguments must be process or job IDs\n"
+        );
+        assert_eq!(fail("kill", &mut state).1, 2);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{
+    command::{
+        CommandType, Context,
+        flags::parse_flags,
+        handlers::declare::{array_items, join_arrays},
+    },
+    state::ShellState,
+    status::ExitStatus,
+    util::{RushError, is_name},
+};
+
+/// Makes each `NAME` or `NAME=value` operand a variable of the function
+/// running it, which gets its old value back once the function returns.
+/// Without a value, the variable starts out unset; `NAME=(...)` makes it an
+/// array.
+pub(crate) fn handle_local(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let (_, operands) = parse_flags(&CommandType::Local, args, "")?;
+    if !state.vars.in_function() {
+        return Err(RushError::CommandError {
+            type_: CommandType::Local,
+            msg: "can only be used in a function".into(),
+            status: ExitStatus::FAILURE,
+        });
+    }
+
+    // A bad name doesn't stop the ones after it
+    let mut result = Ok(());
+    for operand in &join_arrays(operands) {
+        let (name, value) = match operand.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (operand.as_str(), None),
+        };
+        if !is_name(name) {
+            result = Err(ctx.complain(RushError::CommandError {
+                type_: CommandType::Local,
+                msg: format!("`{operand}': not a valid identifier"),
+                status: ExitStatus::FAILURE,
+            }));
+            continue;
+        }
+
+        state.vars.local(name);
+        let items = value.and_then(|value| value.strip_prefix('(')?.strip_suffix(')'));
+        match (value, items) {
+            (_, Some(items)) => state.vars.assign_array(name, array_items(items))?,
+            (Some(value), None) => state.vars.set(name, value),
+            (None, None) => state.vars.unset(name),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::command::handlers::test_support::command;
+    use crate::eval::eval;
+    use crate::state::ShellState;
+
+    #[test]
+    fn only_works_in_a_function() {
+        let mut state = ShellState::default();
+        assert_eq!(
+            eval("local RUSH_LOCAL_TEST=1", &mut state).unwrap().code(),
+            1
+        );
+        assert_eq!(state.last_status.code(), 1);
+        assert!(state.vars.get("RUSH_LOCAL_TEST").is_none());
+    }
+
+    #[test]
+    fn rejects_bad_names() {
+        let mut state = ShellState::default();
+        eval(
+            "rush_local_test() { local 1x RUSH_LOCAL_TEST=1; }",
+            &mut state,
+        )
+        .unwrap();
+        let command = command("rush_local_test", &mut state).unwrap();
+        let (result, errors) = command.run_with_errors(&mut state, &mut io::sink());
+        assert_eq!(errors, "local: `1x': not a valid identifier\n");
+        assert_eq!(result.unwrap().code(), 1);
+        assert_eq!(state.vars.get("RUSH_LOCAL_TEST"), None);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{command::CommandType, state::ShellState, status::ExitStatus, util::RushError};
+
+/// Leaves the innermost loop, or as many of the loops around it as its
+/// operand says.
+pub(crate) fn handle_break(args: &[String], state: &ShellState) -> Result<(), RushError> {
+    let count = loop_count(CommandType::Break, args, state)?;
+    Err(RushError::Break(count))
+}
+
+/// Skips to the next turn of the innermost loop, or of the loop its operand
+/// counts out to, leaving the ones inside that.
+pub(crate) fn handle_continue(args: &[String], state: &ShellState) -> Result<(), RushError> {
+    let count = loop_count(CommandType::Continue, args, state)?;
+    Err(RushError::Continue(count))
+}
+
+/// Reads how many loops `break` or `continue` reaches out through, which is
+/// at most the number running.
+fn loop_count(type_: CommandType, args: &[String], state: &ShellState) -> Result<usize, RushError> {
+    let error = |msg: String, status| RushError::CommandError {
+        type_: type_.clone(),
+        msg,
+        status,
+    };
+    // Like bash, outside a loop it only complains
+    if state.loops == 0 {
+        return Err(error(
+            "only meaningful in a `while' or `until' loop".into(),
+            ExitStatus::SUCCESS,
+        ));
+    }
+
+    let count = match &args[1..] {
+        [] => 1,
+        [count] => match count.parse::<usize>() {
+            Ok(0) | Err(_) if count.parse::<i64>().is_ok() => {
+                return Err(error(
+                    format!("{count}: loop count out of range"),
+                    ExitStatus::FAILURE,
+                ));
+            }
+            Ok(count) => count,
+            Err(_) => {
+                return Err(error(
+                    format!("{count}: numeric argument required"),
+                    ExitStatus::USAGE,
+                ));
+            }
+        },
+        _ => return Err(error("too many arguments".into(), ExitStatus::FAILURE)),
+    };
+    Ok(count.min(state.loops))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(args: &str, loops: usize) -> Result<usize, RushError> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        let state = ShellState {
+            loops,
+            ..ShellState::default()
+        };
+        loop_count(CommandType::Break, &args, &state)
+    }
+
+    #[test]
+    fn counts_are_capped_at_the_loops_running() {
+        assert_eq!(count("break", 1).unwrap(), 1);
+        assert_eq!(count("break 2", 3).unwrap(), 2);
+        assert_eq!(count("break 5", 2).unwrap(), 2);
+    }
+
+    #[test]
+    fn bad_counts() {
+        for (args, status) in [
+            ("break", 0),
+            ("break 0", 1),
+            ("break -1", 1),
+            ("break x", 2),
+            ("break 1 2", 1),
+        ] {
+            let error = count(args, if status == 0 { 0 } else { 1 }).unwrap_err();
+            assert_eq!(error.status().code(), status, "{args}");
+        }
+    }
+}
@@ -1,11 +1,55 @@
+mod alias;
+mod bind;
 mod cd;
+mod clear;
+mod command;
+mod complete;
+mod declare;
+mod dirs;
 mod echo;
+mod exec;
 mod executable;
+mod exit;
+mod export;
+mod hash;
+mod jobs;
+mod kill;
+mod local;
+mod loops;
 mod pwd;
+mod read;
+mod r#return;
+mod set;
+mod shopt;
+mod stats;
+#[cfg(test)]
+pub(super) mod test_support;
 mod r#type;
+mod which;
 
+pub(crate) use alias::{handle_alias, handle_unalias, single_quote};
+pub(crate) use bind::handle_bind;
 pub(crate) use cd::handle_cd;
+pub(crate) use clear::handle_clear;
+pub(crate) use command::{command_target, handle_command};
+pub(crate) use complete::handle_complete;
+pub(crate) use declare::handle_declare;
+pub(crate) use dirs::{abbreviate, handle_dirs, handle_popd, handle_pushd, stack_reference};
 pub(crate) use echo::handle_echo;
-pub(crate) use executable::handle_executable;
-pub(crate) use pwd::handle_pwd;
+pub(crate) use exec::handle_exec;
+pub(crate) use executable::{Inherited, handle_executable, spawn_executable};
+pub(crate) use exit::exit_status;
+pub(crate) use export::handle_export;
+pub(crate) use hash::handle_hash;
+pub(crate) use jobs::{handle_bg, handle_disown, handle_fg, handle_jobs, handle_wait};
+pub(crate) use kill::handle_kill;
+pub(crate) use local::handle_local;
+pub(crate) use loops::{handle_break, handle_continue};
+pub(crate) use pwd::{handle_pwd, logical_dir};
+pub(crate) use read::handle_read;
+pub(crate) use r#return::handle_return;
+pub(crate) use set::handle_set;
+pub(crate) use shopt::handle_shopt;
+pub(crate) use stats::handle_stats;
 pub(crate) use r#type::handle_type;
+pub(crate) use which::handle_which;
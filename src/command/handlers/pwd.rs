@@ -1,31 +1,101 @@
-use std::env;
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
 
-use crate::{command::CommandType, util::RushError};
+use crate::{
+    command::{CommandType, Context, flags::parse_flags},
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
 
-pub(crate) fn handle_pwd(_args: &[String]) -> Result<(), RushError> {
-    let cwd = env::current_dir().map_err(|error| RushError::CommandError {
+/// Prints the current directory: as the shell reached it, through any
+/// symbolic links, or with `-P`, with them resolved.
+pub(crate) fn handle_pwd(
+    args: &[String],
+    state: &ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let (flags, _) = parse_flags(&CommandType::Pwd, args, "LP")?;
+
+    let cwd = match flags.last_of("LP") {
+        Some('P') => fs::canonicalize(state.cwd.path()),
+        _ => Ok(logical_dir(state)),
+    };
+    let cwd = cwd.map_err(|error| RushError::CommandError {
         type_: CommandType::Pwd,
         msg: error.to_string(),
-        status: error.raw_os_error(),
+        status: ExitStatus::FAILURE,
     })?;
-    println!("{}", cwd.display());
-    Ok(())
+    writeln!(ctx.stdout, "{}", cwd.display())
+        .map_err(|error| RushError::io(CommandType::Pwd, error))
+}
+
+/// The current directory as the shell reached it, which is `$PWD` as long as
+/// that still names the current directory.
+pub(crate) fn logical_dir(state: &ShellState) -> PathBuf {
+    let cwd = state.cwd.path();
+    let pwd = state.vars.get("PWD").map(PathBuf::from);
+    match pwd {
+        Some(pwd) if pwd.is_absolute() && same_file(&pwd, cwd) => pwd,
+        _ => cwd.to_owned(),
+    }
+}
+
+/// Removes `.` and resolves `..` in `path` without following symbolic
+/// links, so `..` leads back out of a link rather than to the parent of what
+/// it points to. Like bash, `None` when what comes before a `..` isn't a
+/// directory, since there'd be nothing to step back out of.
+pub(crate) fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if !normal.is_dir() => return None,
+            Component::ParentDir => {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    Some(normal)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::command::Command;
+    use crate::command::handlers::test_support::{command, fail};
+    use crate::state::ShellState;
+    use crate::status::ExitStatus;
     use crate::util::RushError;
-    use std::io;
 
     // Test helper to simplify command creation
     fn parse_cmd(input: &str) -> Result<Command, RushError> {
-        Command::new(io::Cursor::new(input))
+        command(input, &mut ShellState::default())
     }
 
     #[test]
     fn executes_successfully() {
         let cmd = parse_cmd("pwd").unwrap();
-        assert!(cmd.run().is_ok());
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_option() {
+        assert_eq!(
+            fail("pwd -x", &mut ShellState::default()),
+            ("pwd: -x: invalid option\n".into(), 2)
+        );
     }
 }
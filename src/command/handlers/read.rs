@@ -0,0 +1,137 @@
+use crate::{
+    command::{CommandType, flags::parse_flags, redirect::Streams},
+    state::ShellState,
+    status::ExitStatus,
+    util::{RushError, is_name},
+};
+
+/// Reads a line into the variables named, or `REPLY` without any, split
+/// into words at blanks, with the last variable getting the rest of the
+/// line. A backslash keeps the character after it from splitting unless
+/// `-r` is given. `-u fd` reads from descriptor `fd` rather than stdin. It
+/// fails at the end of the input, leaving the variables empty.
+pub(crate) fn handle_read(
+    args: &[String],
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<(), RushError> {
+    let type_ = CommandType::Read;
+    let (flags, operands) = parse_flags(&type_, args, "ru:")?;
+    let error = |msg: String| RushError::CommandError {
+        type_: type_.clone(),
+        msg,
+        status: ExitStatus::FAILURE,
+    };
+
+    let fd = match flags.value('u') {
+        None => 0,
+        Some(fd) => (fd.parse().ok())
+            .ok_or_else(|| error(format!("{fd}: invalid file descriptor specification")))?,
+    };
+    if let Some(name) = operands.iter().find(|name| !is_name(name)) {
+        return Err(error(format!("`{name}': not a valid identifier")));
+    }
+    let names = match operands {
+        [] => &["REPLY".to_owned()][..],
+        names => names,
+    };
+
+    let line = (streams.read_line_fd(fd))
+        .map_err(|io_error| error(format!("{fd}: invalid file descriptor: {io_error}")))?;
+    let words = split(
+        line.as_deref().unwrap_or_default(),
+        names.len(),
+        flags.has('r'),
+    );
+    for (i, name) in names.iter().enumerate() {
+        state
+            .vars
+            .assign(name, words.get(i).map_or("", String::as_str))?;
+    }
+    match line {
+        Some(_) => Ok(()),
+        None => Err(RushError::Reported(ExitStatus::FAILURE)),
+    }
+}
+
+/// Splits `line` into at most `count` words at blanks, the last of which is
+/// the rest of the line, with blanks around it trimmed. Unless `raw`, a
+/// backslash is removed and the character after it is kept as it is.
+fn split(line: &str, count: usize, raw: bool) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // How much of `word` was escaped or isn't blank, which trimming keeps
+    let mut kept = 0;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if !raw => {
+                word.extend(chars.next());
+                kept = word.len();
+            }
+            ' ' | '\t' if words.len() + 1 < count => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                    kept = 0;
+                }
+            }
+            ' ' | '\t' if word.is_empty() => {}
+            c => {
+                word.push(c);
+                if !matches!(c, ' ' | '\t') {
+                    kept = word.len();
+                }
+            }
+        }
+    }
+    word.truncate(kept);
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io};
+
+    use super::*;
+    use crate::command::handlers::test_support::command;
+
+    fn run(input: &str, state: &mut ShellState) -> ExitStatus {
+        let command = command(input, state).unwrap();
+        command.run_with_errors(state, &mut io::sink()).0.unwrap()
+    }
+
+    #[test]
+    fn splits_lines_into_the_variables_named() {
+        assert_eq!(split("  a b  c d  ", 2, false), ["a", "b  c d"]);
+        assert_eq!(split("a b", 3, false), ["a", "b"]);
+        assert_eq!(split(r"a\ b c\\", 2, false), ["a b", r"c\"]);
+        assert_eq!(split(r"a\ b", 2, true), [r"a\", "b"]);
+        assert!(split("   ", 1, false).is_empty());
+    }
+
+    #[test]
+    fn reads_from_a_descriptor() {
+        let dir = std::env::temp_dir().join(format!("rush-read-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("config");
+        fs::write(&file, "first line\nsecond\n").unwrap();
+        let mut state = ShellState::default();
+
+        let line = format!("read -u 3 a b 3<{}", file.display());
+        assert_eq!(run(&line, &mut state), ExitStatus::SUCCESS);
+        assert_eq!(state.vars.get("a").as_deref(), Some("first"));
+        assert_eq!(state.vars.get("b").as_deref(), Some("line"));
+
+        let line = format!("read 4<{} -u 4", file.display());
+        assert_eq!(run(&line, &mut state), ExitStatus::SUCCESS);
+        assert_eq!(state.vars.get("REPLY").as_deref(), Some("first line"));
+
+        assert_eq!(run("read -u 9 x", &mut state), ExitStatus::FAILURE);
+        assert_eq!(run("read -u x x", &mut state), ExitStatus::FAILURE);
+        assert_eq!(run("read 1x", &mut state), ExitStatus::FAILURE);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
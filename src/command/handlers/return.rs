@@ -0,0 +1,37 @@
+use crate::{
+    command::{CommandType, Context, handlers::exit::status_operand},
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// Ends the function running it with the status of its operand, or of the
+/// last command when there isn't one.
+pub(crate) fn handle_return(
+    args: &[String],
+    state: &ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    if !state.vars.in_function() {
+        return Err(RushError::CommandError {
+            type_: CommandType::Return,
+            msg: "can only `return' from a function".into(),
+            status: ExitStatus::FAILURE,
+        });
+    }
+    let status = status_operand(CommandType::Return, args, state, ctx.stderr)?;
+    Err(RushError::Return(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::eval;
+    use crate::state::ShellState;
+
+    #[test]
+    fn only_works_in_a_function() {
+        let mut state = ShellState::default();
+        assert_eq!(eval("return 4", &mut state).unwrap().code(), 1);
+        assert_eq!(state.last_status.code(), 1);
+    }
+}
@@ -0,0 +1,153 @@
+use std::io::Write;
+
+use crate::{
+    command::{CommandType, Context},
+    state::{Options, ShellState},
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// The options `set -o` knows, in the order it lists them.
+pub(crate) const OPTIONS: &[&str] = &["debug", "errexit", "ignoreeof", "noclobber", "xtrace"];
+
+/// Sets shell options with `-o name` and unsets them with `+o name`, the way
+/// `set` does in other shells. Whatever follows the options, or `--`, becomes
+/// the positional parameters.
+pub(crate) fn handle_set(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let usage_error = |msg: String| RushError::CommandError {
+        type_: CommandType::Set,
+        msg,
+        status: ExitStatus::USAGE,
+    };
+
+    let mut i = 1;
+    while let Some(arg) = args.get(i) {
+        if arg == "--" || arg == "-" {
+            i += 1;
+            break;
+        }
+        let enable = match arg.chars().next() {
+            Some('-') => true,
+            Some('+') => false,
+            _ => break,
+        };
+        if arg.len() == 1 {
+            break;
+        }
+
+        for c in arg[1..].chars() {
+            match c {
+                'C' => state.options.noclobber = enable,
+                'e' => state.options.errexit = enable,
+                'x' => state.options.xtrace = enable,
+                'o' => match args.get(i + 1) {
+                    Some(name) => {
+                        i += 1;
+                        let option = option(&mut state.options, name).ok_or_else(|| {
+                            RushError::CommandError {
+                                type_: CommandType::Set,
+                                msg: format!("{name}: invalid option name"),
+                                status: ExitStatus::FAILURE,
+                            }
+                        })?;
+                        *option = enable;
+                    }
+                    None => return list_options(&mut state.options, enable, ctx.stdout),
+                },
+                c => return Err(usage_error(format!("{}{c}: invalid option", &arg[..1]))),
+            }
+        }
+        i += 1;
+    }
+
+    if i < args.len() || args.get(i - 1).is_some_and(|arg| arg == "--") {
+        state.args = args[i..].to_vec();
+    }
+    Ok(())
+}
+
+pub(crate) fn option<'a>(options: &'a mut Options, name: &str) -> Option<&'a mut bool> {
+    match name {
+        "debug" => Some(&mut options.debug),
+        "errexit" => Some(&mut options.errexit),
+        "ignoreeof" => Some(&mut options.ignoreeof),
+        "noclobber" => Some(&mut options.noclobber),
+        "xtrace" => Some(&mut options.xtrace),
+        _ => None,
+    }
+}
+
+/// Shows every option: as a table for `set -o`, or as the commands that would
+/// restore them for `set +o`.
+fn list_options(options: &mut Options, table: bool, out: &mut dyn Write) -> Result<(), RushError> {
+    for name in OPTIONS {
+        let enabled = *option(options, name).expect("every listed option exists");
+        let line = match (table, enabled) {
+            (true, true) => format!("{name:<15}\ton"),
+            (true, false) => format!("{name:<15}\toff"),
+            (false, true) => format!("set -o {name}"),
+            (false, false) => format!("set +o {name}"),
+        };
+        writeln!(out, "{line}").map_err(|error| RushError::io(CommandType::Set, error))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::handlers::test_support::{fail, run};
+    use crate::state::ShellState;
+
+    #[test]
+    fn toggles_options() {
+        let mut state = ShellState::default();
+        run("set -o noclobber", &mut state).unwrap();
+        assert!(state.options.noclobber);
+        assert_eq!(
+            run("set -o", &mut state).unwrap(),
+            "debug          \toff\nerrexit        \toff\nignoreeof      \toff\nnoclobber      \ton\nxtrace         \toff\n"
+        );
+
+        run("set +C -o ignoreeof", &mut state).unwrap();
+        assert!(!state.options.noclobber);
+        assert!(state.options.ignoreeof);
+        assert_eq!(
+            run("set +o", &mut state).unwrap(),
+            "set +o debug\nset +o errexit\nset -o ignoreeof\nset +o noclobber\nset +o xtrace\n"
+        );
+    }
+
+    #[test]
+    fn sets_positional_parameters() {
+        let mut state = ShellState::default();
+        run("set -Ce a b", &mut state).unwrap();
+        assert!(state.options.noclobber);
+        assert!(state.options.errexit);
+        assert_eq!(state.args, ["a", "b"]);
+
+        // Options alone leave them be, but `--` clears them
+        run("set +C", &mut state).unwrap();
+        assert_eq!(state.args, ["a", "b"]);
+        run("set -- -x", &mut state).unwrap();
+        assert_eq!(state.args, ["-x"]);
+        run("set --", &mut state).unwrap();
+        assert!(state.args.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        let mut state = ShellState::default();
+        assert_eq!(
+            fail("set -o nonsense", &mut state),
+            ("set: nonsense: invalid option name\n".into(), 1)
+        );
+        assert_eq!(
+            fail("set +Z", &mut state),
+            ("set: +Z: invalid option\n".into(), 2)
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use std::io::Write;
+
+use crate::{
+    command::{
+        CommandType, Context,
+        flags::{Flags, parse_flags},
+        handlers::set::{OPTIONS, option},
+    },
+    state::{Options, ShellState},
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// The options `shopt` knows, in the order it lists them.
+const SHOPT_OPTIONS: &[&str] = &[
+    "autocd",
+    "globstar",
+    "histappend",
+    "huponexit",
+    "nocaseglob",
+];
+
+/// Finds the flag behind an option name, in one set of options or the other.
+type Lookup = for<'a> fn(&'a mut Options, &str) -> Option<&'a mut bool>;
+
+/// Turns each named option on with `-s` or off with `-u`. Without either,
+/// shows whether each is on, failing if any is off; `-q` only sets the
+/// status. Without names, lists every option, or with `-s` or `-u` only the
+/// ones on or off. `-o` works on the options of `set -o` instead.
+pub(crate) fn handle_shopt(
+    args: &[String],
+    state: &mut ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let (flags, operands) = parse_flags(&CommandType::Shopt, args, "opqsu")?;
+    let error = |msg: String, status| RushError::CommandError {
+        type_: CommandType::Shopt,
+        msg,
+        status,
+    };
+    if flags.has('s') && flags.has('u') {
+        return Err(error(
+            "cannot set and unset shell options simultaneously".into(),
+            ExitStatus::FAILURE,
+        ));
+    }
+    let (names, lookup): (&[&str], Lookup) = match flags.has('o') {
+        true => (OPTIONS, option),
+        false => (SHOPT_OPTIONS, shopt_option),
+    };
+    let setting = flags.last_of("su").map(|flag| flag == 's');
+
+    if operands.is_empty() {
+        for name in names {
+            let enabled = *lookup(&mut state.options, name).expect("every listed option exists");
+            if setting.is_none_or(|setting| setting == enabled) {
+                write_option(name, enabled, &flags, ctx.stdout)?;
+            }
+        }
+        return Ok(());
+    }
+
+    // A bad name doesn't stop the ones after it
+    let mut result = Ok(());
+    for name in operands {
+        let Some(enabled) = lookup(&mut state.options, name) else {
+            result = Err(ctx.complain(error(
+                format!("{name}: invalid shell option name"),
+                ExitStatus::FAILURE,
+            )));
+            continue;
+        };
+        match setting {
+            Some(setting) => *enabled = setting,
+            None => {
+                let enabled = *enabled;
+                if !flags.has('q') {
+                    write_option(name, enabled, &flags, ctx.stdout)?;
+                }
+                if !enabled && result.is_ok() {
+                    result = Err(RushError::Reported(ExitStatus::FAILURE));
+                }
+            }
+        }
+    }
+    result
+}
+
+fn shopt_option<'a>(options: &'a mut Options, name: &str) -> Option<&'a mut bool> {
+    match name {
+        "autocd" => Some(&mut options.autocd),
+        "globstar" => Some(&mut options.globstar),
+        "histappend" => Some(&mut options.histappend),
+        "huponexit" => Some(&mut options.huponexit),
+        "nocaseglob" => Some(&mut options.nocaseglob),
+        _ => None,
+    }
+}
+
+/// Shows one option: in a table, or with `-p` as the command that would
+/// restore it.
+fn write_option(
+    name: &str,
+    enabled: bool,
+    flags: &Flags,
+    out: &mut dyn Write,
+) -> Result<(), RushError> {
+    let line = match (flags.has('p'), flags.has('o'), enabled) {
+        (true, true, true) => format!("set -o {name}"),
+        (true, true, false) => format!("set +o {name}"),
+        (true, false, true) => format!("shopt -s {name}"),
+        (true, false, false) => format!("shopt -u {name}"),
+        (false, _, true) => format!("{name:<15}\ton"),
+        (false, _, false) => format!("{name:<15}\toff"),
+    };
+    writeln!(out, "{line}").map_err(|error| RushError::io(CommandType::Shopt, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::handlers::test_support::{fail, run};
+    use crate::command::{Command, CommandType};
+    use crate::state::ShellState;
+
+    #[test]
+    fn sets_and_lists_options() {
+        let mut state = ShellState::default();
+        run("shopt -s globstar nocaseglob", &mut state).unwrap();
+        assert!(state.options.globstar && state.options.nocaseglob);
+
+        assert_eq!(
+            run("shopt", &mut state).unwrap(),
+            "autocd         \toff\nglobstar       \ton\n\
+             histappend     \toff\nhuponexit      \toff\nnocaseglob     \ton\n"
+        );
+        assert_eq!(
+            run("shopt -s", &mut state).unwrap(),
+            "globstar       \ton\nnocaseglob     \ton\n"
+        );
+        assert_eq!(
+            run("shopt -pu", &mut state).unwrap(),
+            "shopt -u autocd\nshopt -u histappend\nshopt -u huponexit\n"
+        );
+
+        run("shopt -u globstar", &mut state).unwrap();
+        assert!(!state.options.globstar);
+    }
+
+    #[test]
+    fn querying_fails_when_an_option_is_off() {
+        let mut state = ShellState::default();
+        run("shopt -s autocd", &mut state).unwrap();
+        assert_eq!(
+            run("shopt autocd", &mut state).unwrap(),
+            "autocd         \ton\n"
+        );
+        run("shopt -q autocd", &mut state).unwrap();
+
+        assert_eq!(
+            fail("shopt -q autocd histappend", &mut state),
+            (String::new(), 1)
+        );
+    }
+
+    #[test]
+    fn works_on_set_options_with_o() {
+        let mut state = ShellState::default();
+        run("shopt -so noclobber", &mut state).unwrap();
+        assert!(state.options.noclobber);
+        assert_eq!(
+            run("shopt -po", &mut state).unwrap(),
+            "set +o debug\nset +o errexit\nset +o ignoreeof\nset -o noclobber\nset +o xtrace\n"
+        );
+
+        assert_eq!(
+            fail("shopt -s noclobber", &mut state),
+            ("shopt: noclobber: invalid shell option name\n".into(), 1)
+        );
+    }
+
+    #[test]
+    fn autocd_changes_to_a_named_directory() {
+        let mut state = ShellState::default();
+        assert!(!matches!(
+            Command::from_args(vec!["/".into()], &mut state),
+            Ok(Command {
+                type_: CommandType::Cd,
+                ..
+            })
+        ));
+
+        run("shopt -s autocd", &mut state).unwrap();
+        let command = Command::from_args(vec!["/".into()], &mut state).unwrap();
+        assert_eq!(command.args, ["cd", "--", "/"]);
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use crate::{
+    command::{CommandType, Context, flags::parse_flags},
+    state::ShellState,
+    stats::Usage,
+    status::ExitStatus,
+    style::table,
+    util::RushError,
+};
+
+const DEFAULT_TOP: usize = 10;
+
+pub(crate) fn handle_stats(
+    args: &[String],
+    state: &ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let error = |msg: String| RushError::CommandError {
+        type_: CommandType::Stats,
+        msg,
+        status: ExitStatus::FAILURE,
+    };
+
+    let (flags, operands) = parse_flags(&CommandType::Stats, args, "n:")?;
+
+    if !operands.is_empty() {
+        return Err(error("too many arguments".into()));
+    }
+
+    let top = match flags.value('n') {
+        Some(n) => n
+            .parse::<usize>()
+            .map_err(|_| error(format!("{n}: invalid count")))?,
+        None => DEFAULT_TOP,
+    };
+
+    let by_runs = state.stats.top_by_runs(top);
+    if by_runs.is_empty() {
+        return Ok(());
+    }
+
+    let report = format!(
+        "most run:\n{}\nmost time:\n{}",
+        usage_table(&by_runs),
+        usage_table(&state.stats.top_by_time(top))
+    );
+    ctx.stdout
+        .write_all(report.as_bytes())
+        .map_err(|error| RushError::io(CommandType::Stats, error))
+}
+
+fn usage_table(entries: &[(&str, Usage)]) -> String {
+    let mut rows = vec![vec!["command".into(), "runs".into(), "time".into()]];
+    rows.extend(entries.iter().map(|(name, usage)| {
+        vec![
+            name.to_string(),
+            usage.runs.to_string(),
+            format_duration(usage.time),
+        ]
+    }));
+    table(&rows)
+}
+
+fn format_duration(time: Duration) -> String {
+    format!("{:.3}s", time.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn stats_output(args: &str, state: &ShellState) -> Result<String, RushError> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let mut ctx = Context {
+            stdout: &mut out,
+            stderr: &mut err,
+        };
+        handle_stats(&args, state, &mut ctx)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn sample() -> ShellState {
+        let mut state = ShellState::default();
+        for _ in 0..3 {
+            state.stats.record("ls", Duration::from_millis(2));
+        }
+        state.stats.record("make", Duration::from_millis(1500));
+        state
+    }
+
+    #[test]
+    fn prints_both_rankings() {
+        let output = stats_output("stats", &sample()).unwrap();
+        assert_eq!(
+            output,
+            "most run:\n\
+             command  runs  time\n\
+             ls       3     0.006s\n\
+             make     1     1.500s\n\
+             \n\
+             most time:\n\
+             command  runs  time\n\
+             make     1     1.500s\n\
+             ls       3     0.006s\n"
+        );
+    }
+
+    #[test]
+    fn limits_to_top_n() {
+        let output = stats_output("stats -n 1", &sample()).unwrap();
+        assert!(output.contains("ls       3"));
+        assert_eq!(output.matches("make").count(), 1);
+    }
+
+    #[test]
+    fn empty_session_prints_nothing() {
+        let output = stats_output("stats", &ShellState::default()).unwrap();
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    fn rejects_bad_count() {
+        let error = stats_output("stats -n many", &sample()).unwrap_err();
+        assert_eq!(error.to_string(), "stats: many: invalid count");
+    }
+}
@@ -0,0 +1,37 @@
+use std::{io, process};
+
+use crate::{
+    command::{Command, redirect::Streams},
+    parser::SimpleCommand,
+    state::ShellState,
+    util::RushError,
+};
+
+/// Parses `input` as a simple command, expanding its words in `state` the
+/// way the shell would before running it.
+pub(crate) fn command(input: &str, state: &mut ShellState) -> Result<Command, RushError> {
+    Command::from_simple(&SimpleCommand::parse(input)?, state, &Streams::default())
+}
+
+/// Runs `input`, giving what it wrote.
+pub(crate) fn run(input: &str, state: &mut ShellState) -> Result<String, RushError> {
+    let mut out = Vec::new();
+    command(input, state)?.run_with_output(state, &mut out)?;
+    Ok(String::from_utf8(out).unwrap())
+}
+
+/// Runs `input`, giving what it complained about and the status it
+/// finished with.
+pub(crate) fn fail(input: &str, state: &mut ShellState) -> (String, i32) {
+    let command = command(input, state).unwrap();
+    let (result, errors) = command.run_with_errors(state, &mut io::sink());
+    (errors, result.unwrap().code())
+}
+
+/// Starts `script` under `sh`, as a process for a job to be made of.
+pub(crate) fn spawn(script: &str) -> process::Child {
+    process::Command::new("sh")
+        .args(["-c", script])
+        .spawn()
+        .unwrap()
+}
@@ -1,102 +1,211 @@
 use crate::{
-    command::{path::{find_in_path, is_builtin}, CommandType},
+    command::{
+        CommandType, Context,
+        flags::{Flags, parse_flags},
+        path::{Search, find_all_in_path, find_in_path, is_builtin},
+    },
+    state::ShellState,
+    status::ExitStatus,
     util::RushError,
 };
 
-pub(crate) fn handle_type(args: &[String]) -> Result<(), RushError> {
-    let Some(cmd_name) = args.get(1) else {
+/// One way the shell could run a name.
+pub(crate) enum Found {
+    Alias(String),
+    /// A function, with its definition.
+    Function(String),
+    Builtin,
+    /// A program whose location the shell already remembers.
+    Hashed(String),
+    File(String),
+}
+
+/// Describes how the shell would run each operand: as an alias, a function,
+/// a builtin, or a program on `PATH`.
+///
+/// `-t` prints just the kind of each, `-p` just the path of the program that
+/// would run, and `-P` searches `PATH` even for builtins. `-a` reports every
+/// match, including the ones shadowed by an earlier one.
+pub(crate) fn handle_type(
+    args: &[String],
+    state: &ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let write_error = |error| RushError::io(CommandType::Type, error);
+
+    let (flags, operands) = parse_flags(&CommandType::Type, args, "aptP")?;
+
+    if operands.is_empty() {
         return Err(RushError::CommandError {
             type_: CommandType::Type,
             msg: "missing argument".into(),
-            status: Some(1),
+            status: ExitStatus::FAILURE,
         });
-    };
+    }
+
+    let mut result = Ok(());
+    for name in operands {
+        let mut found = find(name, &flags, state)?;
+        if found.is_empty() {
+            // The terse forms leave a missing name to the exit status
+            result = Err(match flags.has('t') || flags.has('p') || flags.has('P') {
+                true => RushError::Reported(ExitStatus::FAILURE),
+                false => ctx.complain(RushError::CommandError {
+                    type_: CommandType::Unknown(name.into()),
+                    msg: "not found".into(),
+                    status: ExitStatus::FAILURE,
+                }),
+            });
+        }
+        if !flags.has('a') {
+            found.truncate(1);
+        }
 
-    if is_builtin(cmd_name) {
-        println!("{cmd_name} is a shell builtin");
-        return Ok(());
+        for found in found {
+            let line = match found {
+                _ if flags.has('t') => kind(&found).to_string(),
+                Found::Hashed(path) | Found::File(path) if flags.has('p') || flags.has('P') => path,
+                _ if flags.has('p') => continue,
+                found => describe(name, &found),
+            };
+            writeln!(ctx.stdout, "{line}").map_err(write_error)?;
+        }
     }
+    result
+}
 
-    match find_in_path(cmd_name)? {
-        Some(path) => {
-            println!("{} is {}", cmd_name, path);
-            Ok(())
+/// Every way the shell could run `name`, in the order it tries them.
+pub(crate) fn find(name: &str, flags: &Flags, state: &ShellState) -> Result<Vec<Found>, RushError> {
+    let mut found = Vec::new();
+    if !flags.has('P') {
+        if let Some(value) = state.aliases.get(name) {
+            found.push(Found::Alias(value.into()));
+        }
+        if let Some(function) = state.functions.get(name) {
+            found.push(Found::Function(function.text.clone()));
+        }
+        if is_builtin(name) {
+            found.push(Found::Builtin);
         }
-        None => Err(RushError::CommandError {
-            type_: CommandType::Unknown(cmd_name.into()),
-            msg: "not found".into(),
-            status: Some(1),
-        }),
+    }
+
+    let search = Search::new(state);
+    if flags.has('a') {
+        found.extend(find_all_in_path(name, &search).into_iter().map(Found::File));
+    } else if let Some(hashed) = state.hash.get(name, &search) {
+        found.push(Found::Hashed(hashed.path.clone()));
+    } else if let Some(path) = find_in_path(name, &search)? {
+        found.push(Found::File(path));
+    }
+    Ok(found)
+}
+
+/// Says in words how the shell would run `name`.
+pub(crate) fn describe(name: &str, found: &Found) -> String {
+    match found {
+        Found::Alias(value) => format!("{name} is aliased to `{value}'"),
+        Found::Function(text) => format!("{name} is a function\n{text}"),
+        Found::Builtin => format!("{name} is a shell builtin"),
+        Found::Hashed(path) => format!("{name} is hashed ({path})"),
+        Found::File(path) => format!("{name} is {path}"),
+    }
+}
+
+fn kind(found: &Found) -> &'static str {
+    match found {
+        Found::Alias(_) => "alias",
+        Found::Function(_) => "function",
+        Found::Builtin => "builtin",
+        Found::Hashed(_) | Found::File(_) => "file",
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::command::Command;
+    use crate::command::handlers::test_support::{command, fail, run};
+    use crate::command::path::{Search, find_in_path};
+    use crate::state::ShellState;
+    use crate::status::ExitStatus;
     use crate::util::RushError;
-    use std::{env, io};
-
-    use crate::command::CommandType;
+    use std::env;
 
     // Test helper to simplify command creation
     fn parse_cmd(input: &str) -> Result<Command, RushError> {
-        Command::new(io::Cursor::new(input))
+        command(input, &mut ShellState::default())
     }
 
     #[test]
     fn builtin_echo() {
         let cmd = parse_cmd("type echo").unwrap();
-        assert!(cmd.run().is_ok());
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
     }
 
     #[test]
     fn builtin_exit() {
         let cmd = parse_cmd("type exit").unwrap();
-        assert!(cmd.run().is_ok());
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
     }
 
     #[test]
     fn builtin_type_itself() {
         let cmd = parse_cmd("type type").unwrap();
-        assert!(cmd.run().is_ok());
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
     }
 
     #[test]
     fn no_args_fails() {
-        let cmd = parse_cmd("type").unwrap();
-        let result = cmd.run();
-        assert!(result.is_err());
-        assert!(matches!(
-            result.unwrap_err(),
-            RushError::CommandError {
-                type_: CommandType::Type,
-                msg: _m,
-                status: Some(1)
-            }
-        ));
+        let (errors, status) = fail("type", &mut ShellState::default());
+        assert!(errors.starts_with("type: "));
+        assert_eq!(status, ExitStatus::FAILURE.code());
     }
 
     #[test]
     fn no_args_error_message() {
-        let cmd = parse_cmd("type").unwrap();
-        let error = cmd.run().unwrap_err();
-        assert!(error.to_string().contains("missing argument"));
+        let (errors, _) = fail("type", &mut ShellState::default());
+        assert!(errors.contains("missing argument"));
     }
 
     #[test]
     fn unknown_command_fails() {
-        let cmd = parse_cmd("type nonexistent").unwrap();
-        let result = cmd.run();
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("not found"));
+        let (errors, status) = fail("type nonexistent", &mut ShellState::default());
+        assert!(errors.contains("not found"));
+        assert_eq!(status, 1);
     }
 
     #[test]
     fn unknown_command_error_contains_name() {
-        let cmd = parse_cmd("type nonexistent123").unwrap();
-        let error = cmd.run().unwrap_err();
-        let error_msg = error.to_string();
-        assert!(error_msg.contains("nonexistent123"));
+        let (errors, _) = fail("type nonexistent123", &mut ShellState::default());
+        assert!(errors.contains("nonexistent123"));
+    }
+
+    #[test]
+    fn reports_every_unknown_command() {
+        let mut state = ShellState::default();
+        assert_eq!(
+            fail("type nope1 echo nope2", &mut state),
+            ("nope1: not found\nnope2: not found\n".into(), 1)
+        );
+
+        // Each complaint lands in order with the output around it
+        let mut out = Vec::new();
+        command("type nope1 echo 2>&1", &mut state)
+            .unwrap()
+            .run_with_output(&mut state, &mut out)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "nope1: not found\necho is a shell builtin\n"
+        );
     }
 
     #[test]
@@ -104,15 +213,122 @@ mod tests {
         // Test with 'ls' which should exist on macOS/Unix
         if env::var_os("PATH").is_some() {
             let cmd = parse_cmd("type ls").unwrap();
-            let result = cmd.run();
-            assert!(result.is_ok());
+            let result = cmd.run(&mut ShellState::default());
+            assert_eq!(result.unwrap(), ExitStatus::SUCCESS);
         }
     }
 
+    #[test]
+    fn dash_prefixed_name_after_double_dash() {
+        let (errors, _) = fail("type -- -x", &mut ShellState::default());
+        assert_eq!(errors, "-x: not found\n");
+
+        let cmd = parse_cmd("type -- echo").unwrap();
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
+    }
+
     #[test]
     fn multiple_args_uses_first() {
         let cmd = parse_cmd("type echo exit").unwrap();
-        assert!(cmd.run().is_ok());
+        assert_eq!(
+            cmd.run(&mut ShellState::default()).unwrap(),
+            ExitStatus::SUCCESS
+        );
         assert_eq!(cmd.args, vec!["type", "echo", "exit"]);
     }
+
+    #[test]
+    fn terse_kinds() {
+        let mut state = ShellState::default();
+        state.aliases.set("ll", "ls -la");
+        assert_eq!(
+            run("type -t ll echo", &mut state).unwrap(),
+            "alias\nbuiltin\n"
+        );
+        assert_eq!(
+            run("type ll", &mut state).unwrap(),
+            "ll is aliased to `ls -la'\n"
+        );
+
+        assert_eq!(
+            fail("type -t rush_type_missing_12345", &mut state),
+            (String::new(), 1)
+        );
+    }
+
+    #[test]
+    fn paths_only() {
+        let Ok(Some(echo)) = find_in_path("echo", &Search::new(&ShellState::default())) else {
+            return;
+        };
+        let mut state = ShellState::default();
+        assert_eq!(run("type -p echo", &mut state).unwrap(), "");
+        assert_eq!(
+            run("type -P echo", &mut state).unwrap(),
+            format!("{echo}\n")
+        );
+        assert!(
+            run("type -a echo", &mut state)
+                .unwrap()
+                .starts_with(&format!("echo is a shell builtin\necho is {echo}\n"))
+        );
+        assert!(
+            run("type -at echo", &mut state)
+                .unwrap()
+                .starts_with("builtin\nfile\n")
+        );
+    }
+
+    #[test]
+    fn hashed_commands() {
+        let Ok(Some(ls)) = find_in_path("ls", &Search::new(&ShellState::default())) else {
+            return;
+        };
+        let mut state = ShellState::default();
+        state.hash.remember("ls", &Search::new(&state));
+        assert_eq!(
+            run("type ls", &mut state).unwrap(),
+            format!("ls is hashed ({ls})\n")
+        );
+        assert_eq!(run("type -t ls", &mut state).unwrap(), "file\n");
+    }
+
+    #[test]
+    fn paths_are_not_searched_for() {
+        let Ok(Some(ls)) = find_in_path("ls", &Search::new(&ShellState::default())) else {
+            return;
+        };
+        let mut state = ShellState::default();
+        state.cwd.set("/".into());
+        assert_eq!(
+            fail("type ./ls", &mut state),
+            ("./ls: not found\n".into(), 1)
+        );
+        assert_eq!(fail("command -v ./ls", &mut state), (String::new(), 1));
+        assert_eq!(
+            run(&format!("type -a {ls}"), &mut state).unwrap(),
+            format!("{ls} is {ls}\n")
+        );
+    }
+
+    #[test]
+    fn functions() {
+        let mut state = ShellState::default();
+        crate::eval::eval("echo() { :; }", &mut state).unwrap();
+        assert_eq!(
+            run("type echo", &mut state).unwrap(),
+            "echo is a function\necho() { :; }\n"
+        );
+        assert_eq!(
+            run("type -at echo", &mut state)
+                .unwrap()
+                .lines()
+                .take(2)
+                .collect::<Vec<_>>(),
+            ["function", "builtin"]
+        );
+    }
 }
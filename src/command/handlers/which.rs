@@ -0,0 +1,81 @@
+use crate::{
+    command::{
+        CommandType, Context,
+        flags::parse_flags,
+        path::{Search, find_all_in_path, find_in_path},
+    },
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// Prints where each operand is found on `PATH`, or with `-a`, every place
+/// it is found. Like `which` elsewhere, a missing command prints nothing and
+/// only shows in the exit status.
+pub(crate) fn handle_which(
+    args: &[String],
+    state: &ShellState,
+    ctx: &mut Context,
+) -> Result<(), RushError> {
+    let (flags, operands) = parse_flags(&CommandType::Which, args, "a")?;
+    let search = Search::new(state);
+
+    let mut found_all = !operands.is_empty();
+    for name in operands {
+        let paths = if flags.has('a') {
+            find_all_in_path(name, &search)
+        } else {
+            find_in_path(name, &search)?.into_iter().collect()
+        };
+
+        found_all &= !paths.is_empty();
+        for path in paths {
+            writeln!(ctx.stdout, "{path}")
+                .map_err(|error| RushError::io(CommandType::Which, error))?;
+        }
+    }
+
+    match found_all {
+        true => Ok(()),
+        false => Err(RushError::Reported(ExitStatus::FAILURE)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::command::handlers::test_support::command;
+    use crate::command::path::{Search, find_in_path};
+    use crate::state::ShellState;
+    use crate::util::RushError;
+
+    fn run(input: &str) -> Result<String, RushError> {
+        let mut state = ShellState::default();
+        let mut out = Vec::new();
+        command(input, &mut state)?.run_with_output(&mut state, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn fail(input: &str) -> (String, i32) {
+        let mut state = ShellState::default();
+        let command = command(input, &mut state).unwrap();
+        let (result, errors) = command.run_with_errors(&mut state, &mut io::sink());
+        (errors, result.unwrap().code())
+    }
+
+    #[test]
+    fn prints_the_first_match() {
+        let Ok(Some(sh)) = find_in_path("sh", &Search::new(&ShellState::default())) else {
+            return;
+        };
+        assert_eq!(run("which sh").unwrap(), format!("{sh}\n"));
+        assert!(run("which -a sh").unwrap().starts_with(&format!("{sh}\n")));
+    }
+
+    #[test]
+    fn fails_quietly_when_anything_is_missing() {
+        assert_eq!(fail("which rush_which_missing_12345"), (String::new(), 1));
+        assert_eq!(fail("which").1, 1);
+    }
+}
@@ -1,95 +1,580 @@
+pub(crate) mod flags;
 mod handlers;
 pub(crate) mod path;
+pub(crate) mod redirect;
 
-use std::io;
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    process,
+};
+
+use crate::{
+    eval::{leave_subshell, settle},
+    expand::{expand_assignments, expand_words},
+    functions,
+    jobs::{self, ProcessGroup},
+    parser::SimpleCommand,
+    state::ShellState,
+    status::ExitStatus,
+    trace,
+    util::RushError,
+    xtrace,
+};
 
-use crate::util::{RushError, Tokenizer};
+pub(crate) use self::handlers::{
+    abbreviate, exit_status, handle_bg, handle_break, handle_clear, handle_continue, handle_fg,
+    logical_dir, single_quote, stack_reference,
+};
 
 use self::{
-    handlers::{handle_cd, handle_echo, handle_executable, handle_pwd, handle_type},
-    path::find_in_path,
+    handlers::{
+        Inherited, command_target, handle_alias, handle_bind, handle_cd, handle_command,
+        handle_complete, handle_declare, handle_dirs, handle_disown, handle_echo, handle_exec,
+        handle_executable, handle_export, handle_hash, handle_jobs, handle_kill, handle_local,
+        handle_popd, handle_pushd, handle_pwd, handle_read, handle_return, handle_set,
+        handle_shopt, handle_stats, handle_type, handle_unalias, handle_wait, handle_which,
+        spawn_executable,
+    },
+    path::Search,
+    redirect::{Redirection, Streams, expand_redirects},
 };
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) enum CommandType {
+    Alias,
+    Bg,
+    Bind,
+    Break,
     Cd,
+    Clear,
+    /// `:`, which does nothing but expand its arguments.
+    Colon,
+    Command,
+    Complete,
+    Continue,
+    Declare,
+    Dirs,
+    Disown,
     Echo,
-    Executable { path: String, name: String },
+    Executable {
+        path: String,
+        name: String,
+    },
+    Exec,
+    /// `fg`, which brings a job back to the foreground.
+    Fg,
+    /// A function defined in the shell, named by it.
+    Function(String),
     Exit,
+    Export,
+    Hash,
+    Jobs,
+    Kill,
+    Local,
+    Popd,
+    Pushd,
     Pwd,
+    Read,
+    Return,
+    Set,
+    Shopt,
+    Stats,
     Type,
+    Unalias,
+    Wait,
+    Which,
     Unknown(String),
 }
 
 impl std::fmt::Display for CommandType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            CommandType::Alias => write!(f, "alias"),
+            CommandType::Bg => write!(f, "bg"),
+            CommandType::Bind => write!(f, "bind"),
+            CommandType::Break => write!(f, "break"),
             CommandType::Cd => write!(f, "cd"),
+            CommandType::Clear => write!(f, "clear"),
+            CommandType::Colon => write!(f, ":"),
+            CommandType::Command => write!(f, "command"),
+            CommandType::Complete => write!(f, "complete"),
+            CommandType::Continue => write!(f, "continue"),
+            CommandType::Declare => write!(f, "declare"),
+            CommandType::Dirs => write!(f, "dirs"),
+            CommandType::Disown => write!(f, "disown"),
             CommandType::Echo => write!(f, "echo"),
             CommandType::Executable { name, .. } => write!(f, "{}", name),
+            CommandType::Exec => write!(f, "exec"),
+            CommandType::Function(name) => write!(f, "{}", name),
             CommandType::Exit => write!(f, "exit"),
+            CommandType::Export => write!(f, "export"),
+            CommandType::Fg => write!(f, "fg"),
+            CommandType::Hash => write!(f, "hash"),
+            CommandType::Jobs => write!(f, "jobs"),
+            CommandType::Kill => write!(f, "kill"),
+            CommandType::Local => write!(f, "local"),
+            CommandType::Popd => write!(f, "popd"),
+            CommandType::Pushd => write!(f, "pushd"),
             CommandType::Pwd => write!(f, "pwd"),
+            CommandType::Read => write!(f, "read"),
+            CommandType::Return => write!(f, "return"),
+            CommandType::Set => write!(f, "set"),
+            CommandType::Shopt => write!(f, "shopt"),
+            CommandType::Stats => write!(f, "stats"),
             CommandType::Type => write!(f, "type"),
+            CommandType::Unalias => write!(f, "unalias"),
+            CommandType::Wait => write!(f, "wait"),
+            CommandType::Which => write!(f, "which"),
             CommandType::Unknown(cmd) => write!(f, "{}", cmd),
         }
     }
 }
 
+/// Every builtin rush provides, keyed by the name users type to invoke it.
+pub(crate) const BUILTINS: &[(&str, CommandType)] = &[
+    (":", CommandType::Colon),
+    ("alias", CommandType::Alias),
+    ("bg", CommandType::Bg),
+    ("bind", CommandType::Bind),
+    ("break", CommandType::Break),
+    ("cd", CommandType::Cd),
+    ("clear", CommandType::Clear),
+    ("command", CommandType::Command),
+    ("complete", CommandType::Complete),
+    ("continue", CommandType::Continue),
+    ("declare", CommandType::Declare),
+    ("dirs", CommandType::Dirs),
+    ("disown", CommandType::Disown),
+    ("echo", CommandType::Echo),
+    ("exec", CommandType::Exec),
+    ("exit", CommandType::Exit),
+    ("export", CommandType::Export),
+    ("fg", CommandType::Fg),
+    ("hash", CommandType::Hash),
+    ("jobs", CommandType::Jobs),
+    ("kill", CommandType::Kill),
+    ("local", CommandType::Local),
+    ("popd", CommandType::Popd),
+    ("pushd", CommandType::Pushd),
+    ("pwd", CommandType::Pwd),
+    ("read", CommandType::Read),
+    ("return", CommandType::Return),
+    ("set", CommandType::Set),
+    ("shopt", CommandType::Shopt),
+    ("stats", CommandType::Stats),
+    ("type", CommandType::Type),
+    ("unalias", CommandType::Unalias),
+    ("wait", CommandType::Wait),
+    ("which", CommandType::Which),
+];
+
 impl CommandType {
     pub(crate) fn from_str(s: &str) -> Self {
-        match s.trim() {
-            "cd" => CommandType::Cd,
-            "exit" => CommandType::Exit,
-            "echo" => CommandType::Echo,
-            "pwd" => CommandType::Pwd,
-            "type" => CommandType::Type,
-            unknown => CommandType::Unknown(unknown.to_string()),
+        let name = s.trim();
+        BUILTINS
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(_, type_)| type_.clone())
+            .unwrap_or_else(|| CommandType::Unknown(name.to_string()))
+    }
+}
+
+/// Where a builtin writes: its stdout and stderr, wherever the command's
+/// redirections sent them.
+pub(crate) struct Context<'a> {
+    pub stdout: &'a mut dyn Write,
+    pub stderr: &'a mut dyn Write,
+}
+
+impl Context<'_> {
+    /// Shows `error` on stderr for a builtin that carries on past it, so a
+    /// later failure can't hide it, and gives back the silent failure to
+    /// finish with. One that's already been shown, or that unwinds the
+    /// shell rather than reporting anything, is given back as it is.
+    pub(crate) fn complain(&mut self, error: RushError) -> RushError {
+        match error {
+            RushError::Nop | RushError::Reported(_) => error,
+            error if error.unwinds() => error,
+            error => {
+                writeln!(self.stderr, "{error}").ok();
+                RushError::Reported(error.status())
+            }
         }
     }
 }
 
+/// A builtin's stdout, which notes a write to a pipe nobody reads from any
+/// more, so the builtin ends as `PIPE` would end a program, even one that
+/// carried on past the failed write.
+struct Watched<'a> {
+    inner: Box<dyn Write + 'a>,
+    broken: bool,
+}
+
+impl Watched<'_> {
+    fn watch<T>(&mut self, result: io::Result<T>) -> io::Result<T> {
+        if let Err(error) = &result {
+            self.broken |= error.kind() == io::ErrorKind::BrokenPipe;
+        }
+        result
+    }
+}
+
+impl Write for Watched<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.inner.write(buf);
+        self.watch(result)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.inner.flush();
+        self.watch(result)
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct Command {
     pub type_: CommandType,
     pub args: Vec<String>,
+    pub redirections: Vec<Redirection>,
+    /// Variables set for this command alone, as in `FOO=bar cmd`.
+    pub env: Vec<(String, String)>,
 }
 
 impl Command {
-    pub(crate) fn new<R: io::BufRead>(reader: R) -> Result<Command, RushError> {
-        let mut tokenizer = Tokenizer::from(reader)?;
-        let args = tokenizer.tokenize()?;
+    /// Expands a parsed simple command into a command.
+    pub(crate) fn from_simple(
+        simple: &SimpleCommand,
+        state: &mut ShellState,
+        streams: &Streams,
+    ) -> Result<Command, RushError> {
+        let args = expand_words(&simple.words, state, streams)?;
+        let redirections = expand_redirects(&simple.redirects, state, streams)?;
+        let env = expand_assignments(&simple.assignments, state, streams)?;
+        xtrace::command(&env, &args, state);
+
+        let mut command = match Self::from_args(args.clone(), state) {
+            // Run it anyway, so the error goes wherever stderr is redirected,
+            // or so a handler can deal with it
+            Err(RushError::CommandNotFound(name))
+                if !redirections.is_empty() || handles_not_found(state) =>
+            {
+                Command {
+                    type_: CommandType::Unknown(name),
+                    args,
+                    redirections: Vec::new(),
+                    env: Vec::new(),
+                }
+            }
+            result => result?,
+        };
+        command.redirections = redirections;
+        command.env = env;
+        trace::expansion(&command, state);
+        Ok(command)
+    }
 
+    /// Classifies already-expanded arguments into a command.
+    pub(crate) fn from_args(
+        args: Vec<String>,
+        state: &mut ShellState,
+    ) -> Result<Command, RushError> {
+        // Functions come before the builtins and programs they share a name
+        // with
+        if let Some(name) = args.first()
+            && state.functions.get(name).is_some()
+        {
+            return Ok(Command {
+                type_: CommandType::Function(name.clone()),
+                args,
+                redirections: Vec::new(),
+                env: Vec::new(),
+            });
+        }
+        Self::from_args_skipping_functions(args, state)
+    }
+
+    /// Like [`Command::from_args`], but a function doesn't hide the builtin
+    /// or program of the same name, as with `command name`.
+    fn from_args_skipping_functions(
+        args: Vec<String>,
+        state: &mut ShellState,
+    ) -> Result<Command, RushError> {
         // Read the name of the command from the tokenized args
         let Some(name) = args.first() else {
             return Err(RushError::Nop);
         };
 
         let type_ = CommandType::from_str(name);
+        // `command name args` runs `name` itself
+        if type_ == CommandType::Command
+            && let Some(target) = command_target(&args)
+        {
+            return Self::from_args_skipping_functions(target.to_vec(), state);
+        }
+
         match type_ {
-            CommandType::Unknown(cmd) => match find_in_path(&cmd)? {
+            // With `autocd`, naming a directory on its own changes to it
+            CommandType::Unknown(dir)
+                if state.options.autocd && args.len() == 1 && state.cwd.resolve(&dir).is_dir() =>
+            {
+                Ok(Command {
+                    type_: CommandType::Cd,
+                    args: vec!["cd".into(), "--".into(), dir],
+                    redirections: Vec::new(),
+                    env: Vec::new(),
+                })
+            }
+            // A restricted shell only runs what it finds on `PATH`
+            CommandType::Unknown(cmd) if path::is_path(&cmd) && state.restricted => {
+                Err(RushError::CommandError {
+                    type_: CommandType::Unknown(cmd),
+                    msg: "restricted: cannot specify `/' in command names".into(),
+                    status: ExitStatus::FAILURE,
+                })
+            }
+            // A name with a `/` in it is run from there, if it can be
+            CommandType::Unknown(cmd) if path::is_path(&cmd) => {
+                let path = path::runnable_path(&cmd, &Search::new(state))?;
+                Ok(Command {
+                    type_: CommandType::Executable { path, name: cmd },
+                    args,
+                    redirections: Vec::new(),
+                    env: Vec::new(),
+                })
+            }
+            CommandType::Unknown(cmd) => match state.hash.lookup(&cmd, &Search::new(state)) {
                 Some(path) => Ok(Command {
                     type_: CommandType::Executable { path, name: cmd },
                     args,
+                    redirections: Vec::new(),
+                    env: Vec::new(),
                 }),
                 None => Err(RushError::CommandNotFound(cmd)),
             },
-            _ => Ok(Command { type_, args }),
+            _ => Ok(Command {
+                type_,
+                args,
+                redirections: Vec::new(),
+                env: Vec::new(),
+            }),
         }
     }
 
-    pub(crate) fn run(&self) -> Result<(), RushError> {
-        match self.type_ {
-            CommandType::Cd => handle_cd(&self.args),
-            CommandType::Echo => handle_echo(&self.args),
+    #[cfg(test)]
+    pub(crate) fn run(&self, state: &mut ShellState) -> Result<ExitStatus, RushError> {
+        self.run_with_output(state, &mut io::stdout())
+    }
+
+    /// Runs the command with its standard output sent to `out`.
+    #[cfg(test)]
+    pub(crate) fn run_with_output(
+        &self,
+        state: &mut ShellState,
+        out: &mut dyn Write,
+    ) -> Result<ExitStatus, RushError> {
+        self.run_in(state, &Streams::default(), out)
+    }
+
+    /// Runs the command with its standard output sent to `out`, giving back
+    /// what it wrote to its standard error alongside the result.
+    #[cfg(test)]
+    pub(crate) fn run_with_errors(
+        &self,
+        state: &mut ShellState,
+        out: &mut dyn Write,
+    ) -> (Result<ExitStatus, RushError>, String) {
+        use std::io::Read;
+
+        let (mut reader, writer) = io::pipe().unwrap();
+        let streams = Streams {
+            stderr: redirect::Sink::Pipe(writer),
+            ..Streams::default()
+        };
+        let result = self.run_in(state, &streams, out);
+        drop(streams);
+        let mut errors = String::new();
+        reader.read_to_string(&mut errors).unwrap();
+        (result, errors)
+    }
+
+    /// Runs the command with its redirections applied on top of `base`, the
+    /// streams of whatever it runs inside, such as a redirected group, and
+    /// returns the status it finished with. A command that fails still
+    /// finishes, so errors are left for what stops it from running at all,
+    /// like a redirection that can't be opened, and for what unwinds.
+    pub(crate) fn run_in(
+        &self,
+        state: &mut ShellState,
+        base: &Streams,
+        out: &mut dyn Write,
+    ) -> Result<ExitStatus, RushError> {
+        let mut streams = base.apply(&self.redirections)?;
+        trace::exec(self, false, state);
+
+        // A restricted shell can't leave its directory, or give way to
+        // another program
+        let leaves = match self.type_ {
+            CommandType::Cd | CommandType::Pushd | CommandType::Popd => true,
+            CommandType::Exec => self.args.len() > 1,
+            _ => false,
+        };
+        if state.restricted && leaves {
+            let error = RushError::CommandError {
+                type_: self.type_.clone(),
+                msg: "restricted".into(),
+                status: ExitStatus::FAILURE,
+            };
+            return settle(Err(error), &streams, out, state);
+        }
+
+        let result = match self.type_ {
             CommandType::Executable { ref path, ref name } => {
-                match handle_executable(&path, &name, &self.args) {
-                    Ok(_status) => Ok(()),
-                    Err(error) => Err(error),
+                run_program(path, name, &self.args, &self.env, state, &mut streams, out)
+            }
+            CommandType::Exec => with_env(&self.env, state, |state| {
+                handle_exec(&self.args, state, &mut streams, out).map(|()| ExitStatus::SUCCESS)
+            }),
+            CommandType::Read => with_env(&self.env, state, |state| {
+                handle_read(&self.args, state, &streams).map(|()| ExitStatus::SUCCESS)
+            }),
+            CommandType::Function(ref name) => match state.functions.get(name) {
+                Some(function) => with_env(&self.env, state, |state| {
+                    functions::call(&function, &self.args, state, &streams, out)
+                }),
+                // It was removed since the command was made
+                None => Err(RushError::CommandNotFound(name.clone())),
+            },
+            CommandType::Unknown(ref name) => self.run_not_found(name, state, &mut streams, out),
+            _ => {
+                // After `2>&1` both streams write to `out`, so they share it
+                let out: RefCell<&mut dyn Write> = RefCell::new(out);
+                let mut stdout = Watched {
+                    inner: streams.stdout.writer(&out),
+                    broken: false,
+                };
+                let mut stderr = streams.stderr.writer(&out);
+                let mut ctx = Context {
+                    stdout: &mut stdout,
+                    stderr: &mut stderr,
+                };
+                let result = with_env(&self.env, state, |state| self.run_builtin(state, &mut ctx));
+                match stdout.broken {
+                    true => Err(RushError::BrokenPipe),
+                    false => result.map(|()| ExitStatus::SUCCESS),
                 }
             }
+        };
+
+        // Whatever went wrong once the command started is its own failure,
+        // shown on its stderr, and only leaves a status behind
+        settle(result, &streams, out, state)
+    }
+
+    /// Runs a command that can't be found through the
+    /// `command_not_found_handle` function, as bash does, or else through
+    /// the program the config file names for it, with the command's name
+    /// and arguments as its own. That's where a system can offer to install
+    /// the package the command is in. Like bash, a function handling it runs
+    /// in a subshell.
+    fn run_not_found(
+        &self,
+        name: &str,
+        state: &mut ShellState,
+        streams: &mut Streams,
+        out: &mut dyn Write,
+    ) -> Result<ExitStatus, RushError> {
+        let not_found = || Err(RushError::CommandNotFound(name.into()));
+        if state.in_not_found {
+            return not_found();
+        }
+
+        let mut args = vec![NOT_FOUND_HANDLE.to_owned()];
+        args.extend(self.args.iter().cloned());
+        if let Some(function) = state.functions.get(NOT_FOUND_HANDLE) {
+            let mut subshell = ShellState {
+                in_not_found: true,
+                ..state.subshell()
+            };
+            return with_env(&self.env, &mut subshell, |subshell| {
+                leave_subshell(functions::call(&function, &args, subshell, streams, out))
+            });
+        }
+
+        let Some(handler) = state.hooks.command_not_found.clone() else {
+            return not_found();
+        };
+        let Some(path) = state.hash.lookup(&handler, &Search::new(state)) else {
+            return not_found();
+        };
+        args[0] = handler.clone();
+        state.in_not_found = true;
+        let result = run_program(&path, &handler, &args, &self.env, state, streams, out);
+        state.in_not_found = false;
+        result
+    }
+
+    /// Starts the command in the background, in `group` if it's given one,
+    /// returning the running process, or `None` if it is a builtin, which
+    /// has to run in the shell itself.
+    pub(crate) fn spawn(
+        &self,
+        state: &ShellState,
+        base: &Streams,
+        group: Option<&ProcessGroup>,
+    ) -> Result<Option<process::Child>, RushError> {
+        let CommandType::Executable { ref path, ref name } = self.type_ else {
+            return Ok(None);
+        };
+        let mut streams = base.apply(&self.redirections)?;
+        trace::exec(self, true, state);
+        let inherited = Inherited::new(state, &self.env);
+        spawn_executable(path, name, &self.args, &inherited, &mut streams, group)
+            .map(Some)
+            .map_err(|error| streams.report(error, &mut io::stdout()))
+    }
+
+    fn run_builtin(&self, state: &mut ShellState, ctx: &mut Context) -> Result<(), RushError> {
+        match self.type_ {
+            CommandType::Alias => handle_alias(&self.args, state, ctx),
+            CommandType::Bg => handle_bg(&self.args, state, ctx),
+            CommandType::Bind => handle_bind(&self.args, state, ctx),
+            CommandType::Break => handle_break(&self.args, state),
+            CommandType::Cd => handle_cd(&self.args, state, ctx),
+            CommandType::Clear => handle_clear(&self.args, ctx),
+            // Its arguments were expanded to make the command, which is all
+            // it's for
+            CommandType::Colon => Ok(()),
+            CommandType::Command => handle_command(&self.args, state, ctx),
+            CommandType::Complete => handle_complete(&self.args, state, ctx),
+            CommandType::Continue => handle_continue(&self.args, state),
+            CommandType::Declare => handle_declare(&self.args, state, ctx),
+            CommandType::Dirs => handle_dirs(&self.args, state, ctx),
+            CommandType::Disown => handle_disown(&self.args, state, ctx),
+            CommandType::Echo => handle_echo(&self.args, ctx),
+            CommandType::Executable { .. } => unreachable!("executables aren't builtins"),
+            CommandType::Exec => unreachable!("exec runs with the streams in run_in"),
+            CommandType::Function(_) => unreachable!("functions run with the streams in run_in"),
             CommandType::Exit => Ok(()),
-            CommandType::Pwd => handle_pwd(&self.args),
-            CommandType::Type => handle_type(&self.args),
+            CommandType::Export => handle_export(&self.args, state, ctx),
+            CommandType::Fg => handle_fg(&self.args, state, ctx),
+            CommandType::Hash => handle_hash(&self.args, state, ctx),
+            CommandType::Jobs => handle_jobs(&self.args, state, ctx),
+            CommandType::Kill => handle_kill(&self.args, state, ctx),
+            CommandType::Local => handle_local(&self.args, state, ctx),
+            CommandType::Popd => handle_popd(&self.args, state, ctx),
+            CommandType::Pushd => handle_pushd(&self.args, state, ctx),
+            CommandType::Pwd => handle_pwd(&self.args, state, ctx),
+            CommandType::Read => unreachable!("read runs with the streams in run_in"),
+            CommandType::Return => handle_return(&self.args, state, ctx),
+            CommandType::Set => handle_set(&self.args, state, ctx),
+            CommandType::Shopt => handle_shopt(&self.args, state, ctx),
+            CommandType::Stats => handle_stats(&self.args, state, ctx),
+            CommandType::Type => handle_type(&self.args, state, ctx),
+            CommandType::Unalias => handle_unalias(&self.args, state, ctx),
+            CommandType::Wait => handle_wait(&self.args, state, ctx),
+            CommandType::Which => handle_which(&self.args, state, ctx),
             CommandType::Unknown(ref cmd_name) => Err(RushError::CommandNotFound(cmd_name.into())),
         }
     }
@@ -99,20 +584,102 @@ impl Command {
         &self,
         path: &str,
         name: &str,
-    ) -> Result<Option<i32>, RushError> {
-        handle_executable(path, name, &self.args)
+    ) -> Result<crate::status::ExitStatus, RushError> {
+        handle_executable(
+            path,
+            name,
+            &self.args,
+            &Inherited::new(&ShellState::default(), &self.env),
+            &mut Streams::default(),
+            None,
+            &mut io::stdout(),
+        )
+    }
+}
+
+/// What bash calls the function that deals with commands it can't find.
+const NOT_FOUND_HANDLE: &str = "command_not_found_handle";
+
+/// Whether there's a handler to run a command that can't be found, instead
+/// of just reporting it.
+fn handles_not_found(state: &ShellState) -> bool {
+    !state.in_not_found
+        && (state.functions.get(NOT_FOUND_HANDLE).is_some()
+            || state.hooks.command_not_found.is_some())
+}
+
+/// Runs the program at `path`, called `name`, waiting for it to finish.
+/// `env` holds the assignments made for just this command.
+fn run_program(
+    path: &str,
+    name: &str,
+    args: &[String],
+    env: &[(String, String)],
+    state: &ShellState,
+    streams: &mut Streams,
+    out: &mut dyn Write,
+) -> Result<ExitStatus, RushError> {
+    // Only a command the person at the terminal is waiting on can be stopped
+    // with Ctrl-Z and picked up again later. It's put in a process group of
+    // its own for that, unless it's part of a pipeline, which shares one
+    let own = (state.group.is_none() && jobs::job_control(state)).then(ProcessGroup::foreground);
+    let result = handle_executable(
+        path,
+        name,
+        args,
+        &Inherited::new(state, env),
+        streams,
+        own.as_ref().or(state.group.as_ref()),
+        out,
+    );
+    if let Some(own) = own {
+        own.finish(matches!(result, Ok(ExitStatus::SUCCESS)));
+    }
+    result
+}
+
+/// Exports `env` while `run` runs, for a builtin or function that reads
+/// it, as `cd` reads `HOME`, and then puts back what was there before.
+fn with_env<T>(
+    env: &[(String, String)],
+    state: &mut ShellState,
+    run: impl FnOnce(&mut ShellState) -> T,
+) -> T {
+    let saved: Vec<_> = env
+        .iter()
+        .map(|(name, _)| (name, state.vars.get(name), state.vars.is_exported(name)))
+        .collect();
+    for (name, value) in env {
+        state.vars.export(name, Some(value));
+    }
+
+    let result = run(state);
+
+    for (name, value, exported) in saved.into_iter().rev() {
+        match value {
+            Some(value) => {
+                state.vars.set(name, &value);
+                if !exported {
+                    state.vars.unexport(name);
+                }
+            }
+            None => state.vars.unset(name),
+        }
     }
+    result
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::command::handlers::test_support::command;
+    use crate::state::ShellState;
     use crate::util::RushError;
     use std::io;
 
     // Test helper to simplify command creation
     fn parse_cmd(input: &str) -> Result<Command, RushError> {
-        Command::new(io::Cursor::new(input))
+        command(input, &mut ShellState::default())
     }
 
     mod command_type {
@@ -230,28 +797,6 @@ mod tests {
             assert!(matches!(result.unwrap_err(), RushError::Nop));
         }
 
-        #[test]
-        fn io_error_propagates() {
-            struct FailingReader;
-
-            impl io::Read for FailingReader {
-                fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
-                    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "EOF"))
-                }
-            }
-
-            impl io::BufRead for FailingReader {
-                fn fill_buf(&mut self) -> io::Result<&[u8]> {
-                    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "EOF"))
-                }
-                fn consume(&mut self, _amt: usize) {}
-            }
-
-            let result = Command::new(FailingReader);
-            assert!(result.is_err());
-            assert!(matches!(result.unwrap_err(), RushError::UnexpectedEOF));
-        }
-
         #[test]
         fn quoted_arguments_preserved() {
             let cmd = parse_cmd("echo \'hello world\'").unwrap();
@@ -265,20 +810,91 @@ mod tests {
         }
     }
 
+    mod end_of_options {
+        use super::*;
+
+        #[test]
+        fn every_builtin_accepts_double_dash() {
+            for (name, type_) in BUILTINS {
+                // `command` runs its operand, which isn't a real command here
+                if *type_ == CommandType::Command {
+                    continue;
+                }
+                let cmd = parse_cmd(&format!("{name} -- --something")).unwrap();
+                assert_eq!(&cmd.type_, type_);
+
+                let (result, errors) =
+                    cmd.run_with_errors(&mut ShellState::default(), &mut io::sink());
+                assert!(
+                    !errors.contains("invalid option"),
+                    "{name} rejected an operand after --: {errors}"
+                );
+                assert!(
+                    !errors.contains(": --:"),
+                    "{name} took -- for an operand: {errors}"
+                );
+                // Any usage error is about the operand, like `dirs` taking
+                // it for a bad number
+                let status = result.unwrap();
+                assert!(
+                    status != ExitStatus::USAGE || errors.contains("--something"),
+                    "{name} failed with {status:?}: {errors}"
+                );
+            }
+        }
+
+        #[test]
+        fn every_builtin_is_recognized() {
+            for (name, type_) in BUILTINS {
+                assert_eq!(&CommandType::from_str(name), type_);
+                assert_eq!(type_.to_string(), *name);
+            }
+        }
+    }
+
     mod exit_command {
         use super::*;
 
         #[test]
         fn executes_successfully() {
             let cmd = parse_cmd("exit").unwrap();
-            assert!(cmd.run().is_ok());
+            assert_eq!(
+                cmd.run(&mut ShellState::default()).unwrap(),
+                ExitStatus::SUCCESS
+            );
         }
 
         #[test]
         fn with_args_ignored() {
             let cmd = parse_cmd("exit 0").unwrap();
-            assert!(cmd.run().is_ok());
+            assert_eq!(
+                cmd.run(&mut ShellState::default()).unwrap(),
+                ExitStatus::SUCCESS
+            );
             assert_eq!(cmd.args, vec!["exit", "0"]);
         }
     }
+
+    mod closed_pipes {
+        use super::*;
+
+        #[test]
+        fn builtins_writing_to_them_end_quietly() {
+            for line in ["echo a", "type echo", "pwd"] {
+                let (reader, writer) = io::pipe().unwrap();
+                drop(reader);
+                let streams = Streams {
+                    stdout: redirect::Sink::Pipe(writer),
+                    ..Streams::default()
+                };
+                let mut state = ShellState::default();
+                let result = parse_cmd(line)
+                    .unwrap()
+                    .run_in(&mut state, &streams, &mut io::sink());
+                assert!(matches!(result, Err(RushError::BrokenPipe)), "{line}");
+            }
+            // As if `PIPE` had killed them
+            assert_eq!(RushError::BrokenPipe.status().code(), 141);
+        }
+    }
 }
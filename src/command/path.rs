@@ -1,53 +1,264 @@
-use std::{env, path::Path};
+use std::{
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+};
 
-use crate::{command::CommandType, util::RushError};
+use crate::{command::CommandType, state::ShellState, status::ExitStatus, util::RushError};
 
 #[cfg(unix)]
-pub(crate) fn is_executable(path: &Path) -> bool {
+fn is_executable(path: &Path) -> bool {
     use std::os::unix::fs::PermissionsExt;
     path.metadata()
         .map(|m| m.permissions().mode() & 0o111 != 0)
         .unwrap_or(false)
 }
 
+/// Without execute permissions, a file is a program if its extension is one
+/// of the ones `PATHEXT` lists.
 #[cfg(not(unix))]
-pub(crate) fn is_executable(_path: &Path) -> bool {
-    true // On non-Unix, just check existence
+fn is_executable(path: &Path, extensions: &[String]) -> bool {
+    let name = path.to_string_lossy();
+    path.is_file() && has_extension(&name, extensions)
+}
+
+/// The extensions that make a file a program on Windows, lowercased, from
+/// the shell's `PATHEXT`. Elsewhere nothing needs one.
+fn extensions(state: &ShellState) -> Vec<String> {
+    if !cfg!(windows) {
+        return Vec::new();
+    }
+    let pathext = (state.vars.get("PATHEXT")).unwrap_or_else(|| ".COM;.EXE;.BAT;.CMD".into());
+    pathext
+        .split(';')
+        .filter(|ext| !ext.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Whether `name` already ends with one of `extensions`, in any case.
+fn has_extension(name: &str, extensions: &[String]) -> bool {
+    let name = name.to_lowercase();
+    extensions.iter().any(|ext| name.ends_with(ext.as_str()))
+}
+
+/// The file names `cmd_name` may have been given as: itself, or if it lacks
+/// an extension that makes it a program, itself with each of those.
+fn candidates(cmd_name: &str, extensions: &[String]) -> Vec<String> {
+    match extensions.is_empty() || has_extension(cmd_name, extensions) {
+        true => vec![cmd_name.into()],
+        false => extensions
+            .iter()
+            .map(|ext| format!("{cmd_name}{ext}"))
+            .collect(),
+    }
+}
+
+/// Whether `cmd_name` is a path to a program rather than a name to search
+/// `PATH` for. On Windows that includes `\` separators and a drive letter.
+pub(crate) fn is_path(cmd_name: &str) -> bool {
+    let drive = |name: &str| {
+        let mut chars = name.chars();
+        chars.next().is_some_and(|c| c.is_ascii_alphabetic()) && chars.next() == Some(':')
+    };
+    cmd_name.contains('/') || cfg!(windows) && (cmd_name.contains('\\') || drive(cmd_name))
+}
+
+/// The key a command is remembered by. Windows finds files whatever the case
+/// of their names, so `Git` and `git` are the same command there.
+fn key(cmd_name: &str) -> String {
+    match cfg!(windows) {
+        true => cmd_name.to_lowercase(),
+        false => cmd_name.into(),
+    }
 }
 
 pub(crate) fn is_builtin(cmd_name: &str) -> bool {
-    matches!(
-        CommandType::from_str(cmd_name),
-        CommandType::Cd
-            | CommandType::Echo
-            | CommandType::Exit
-            | CommandType::Pwd
-            | CommandType::Type
-    )
-}
-
-pub(crate) fn find_in_path(cmd_name: &str) -> Result<Option<String>, RushError> {
-    let path_env = match env::var_os("PATH") {
-        Some(path) => path,
-        None => return Ok(None),
+    !matches!(CommandType::from_str(cmd_name), CommandType::Unknown(_))
+}
+
+/// Where a shell looks for programs: the directories on its `PATH`, and its
+/// working directory, which relative entries and command paths start from.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Search {
+    cwd: PathBuf,
+    /// `None` without a `PATH` at all, which finds nothing.
+    dirs: Option<Vec<PathBuf>>,
+    /// What a file's name has to end with to be a program, if anything.
+    extensions: Vec<String>,
+}
+
+impl Search {
+    pub(crate) fn new(state: &ShellState) -> Self {
+        let dirs = (state.vars.get("PATH")).map(|path| env::split_paths(&path).collect());
+        Self {
+            cwd: state.cwd.path().to_owned(),
+            dirs,
+            extensions: extensions(state),
+        }
+    }
+
+    /// The directories on `PATH`, in search order, as they're written there.
+    pub(crate) fn dirs(&self) -> &[PathBuf] {
+        self.dirs.as_deref().unwrap_or_default()
+    }
+
+    /// Whether `path` is a program, by its permissions, or on Windows by its
+    /// extension.
+    pub(crate) fn is_program(&self, path: &Path) -> bool {
+        #[cfg(unix)]
+        return is_executable(path);
+        #[cfg(not(unix))]
+        return is_executable(path, &self.extensions);
+    }
+
+    /// Where `path` is, taken from the working directory if it's relative.
+    pub(crate) fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.cwd.join(path)
+    }
+}
+
+pub(crate) fn find_in_path(cmd_name: &str, search: &Search) -> Result<Option<String>, RushError> {
+    Ok(find_all_in_path(cmd_name, search).into_iter().next())
+}
+
+/// Checks that `cmd_name`, a command given by its path rather than searched
+/// for on `PATH`, is a program that can be run, returning the file to run.
+/// Like other shells, nothing being there fails with 127, and a directory or
+/// a file without execute permission with 126.
+pub(crate) fn runnable_path(cmd_name: &str, search: &Search) -> Result<String, RushError> {
+    let file = candidates(cmd_name, &search.extensions)
+        .into_iter()
+        .map(|file| search.resolve(&file))
+        .find(|file| file.exists())
+        .unwrap_or_else(|| search.resolve(cmd_name));
+    let path = file.as_path();
+    let (msg, status) = if !path.exists() {
+        ("No such file or directory", ExitStatus::NOT_FOUND)
+    } else if path.is_dir() {
+        ("Is a directory", ExitStatus::NOT_EXECUTABLE)
+    } else if !search.is_program(path) {
+        ("Permission denied", ExitStatus::NOT_EXECUTABLE)
+    } else {
+        return Ok(file.to_string_lossy().into());
     };
+    Err(RushError::CommandError {
+        type_: CommandType::Unknown(cmd_name.into()),
+        msg: msg.into(),
+        status,
+    })
+}
 
-    for dir in env::split_paths(&path_env) {
-        let full_path = Path::new(&dir).join(cmd_name);
+/// Every executable named `cmd_name` on `PATH`, in search order, including
+/// the ones shadowed by an earlier match. A name with a `/` in it isn't
+/// searched for, and is found as it's written if it's a program that can be
+/// run.
+pub(crate) fn find_all_in_path(cmd_name: &str, search: &Search) -> Vec<String> {
+    if is_path(cmd_name) {
+        return runnable_path(cmd_name, search)
+            .map(|_| cmd_name.to_string())
+            .into_iter()
+            .collect();
+    }
+    let names = candidates(cmd_name, &search.extensions);
+    (search.dirs().iter())
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .filter(|full_path| {
+            let full_path = search.resolve(full_path);
+            full_path.is_file() && search.is_program(&full_path)
+        })
+        .map(|full_path| full_path.to_string_lossy().to_string())
+        .collect()
+}
 
-        // Check if file exists and is executable
-        if full_path.exists() && is_executable(&full_path) {
-            return Ok(Some(full_path.to_string_lossy().to_string()));
+/// Where commands were found on `PATH`, so running one again doesn't search
+/// every directory. Like bash's hash table, it starts over whenever `PATH`
+/// changes.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PathCache {
+    /// The `PATH` directories the entries were found in.
+    dirs: Option<Vec<PathBuf>>,
+    entries: BTreeMap<String, Hashed>,
+}
+
+/// A command's remembered location, and how many times it has run from
+/// there.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Hashed {
+    pub path: String,
+    pub hits: usize,
+}
+
+impl PathCache {
+    /// Finds `cmd_name` on `PATH` to run it, counting the hit. A name with a
+    /// `/` in it is a path to the program already, and isn't searched for.
+    /// Either way the program is given from the working directory, so it
+    /// can be started from anywhere.
+    pub(crate) fn lookup(&mut self, cmd_name: &str, search: &Search) -> Option<String> {
+        if is_path(cmd_name) {
+            return Some(search.resolve(cmd_name).to_string_lossy().into());
         }
+        let hashed = self.find(cmd_name, search)?;
+        hashed.hits += 1;
+        Some(search.resolve(&hashed.path).to_string_lossy().into())
+    }
+
+    /// Finds `cmd_name` on `PATH` and remembers it without running it.
+    pub(crate) fn remember(&mut self, cmd_name: &str, search: &Search) -> Option<&Hashed> {
+        self.find(cmd_name, search).map(|hashed| &*hashed)
+    }
+
+    /// The remembered location of `cmd_name`, without searching for it.
+    pub(crate) fn get(&self, cmd_name: &str, search: &Search) -> Option<&Hashed> {
+        let current = self.dirs == search.dirs;
+        self.entries.get(&key(cmd_name)).filter(|_| current)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
     }
 
-    Ok(None)
+    /// The remembered commands, sorted by name.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &Hashed)> {
+        self.entries
+            .iter()
+            .map(|(name, hashed)| (name.as_str(), hashed))
+    }
+
+    /// The entry for `cmd_name`, searching `PATH` only when the remembered
+    /// location is missing or no longer executable.
+    fn find(&mut self, cmd_name: &str, search: &Search) -> Option<&mut Hashed> {
+        if self.dirs != search.dirs {
+            self.entries.clear();
+            self.dirs = search.dirs.clone();
+        }
+
+        let cmd_key = key(cmd_name);
+        let stale = self.entries.get(&cmd_key).is_none_or(|hashed| {
+            let path = search.resolve(&hashed.path);
+            !path.is_file() || !search.is_program(&path)
+        });
+        if stale {
+            let path = find_in_path(cmd_name, search).ok().flatten();
+            match path {
+                Some(path) => self
+                    .entries
+                    .insert(cmd_key.clone(), Hashed { path, hits: 0 }),
+                None => self.entries.remove(&cmd_key),
+            };
+        }
+        self.entries.get_mut(&cmd_key)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn search() -> Search {
+        Search::new(&ShellState::default())
+    }
+
     #[test]
     fn is_builtin_recognizes_commands() {
         assert!(is_builtin("cd"));
@@ -67,7 +278,7 @@ mod tests {
 
     #[test]
     fn find_in_path_returns_none_for_nonexistent() {
-        let result = find_in_path("definitely_does_not_exist_12345");
+        let result = find_in_path("definitely_does_not_exist_12345", &search());
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), None);
     }
@@ -75,9 +286,151 @@ mod tests {
     #[test]
     fn find_in_path_finds_ls_on_unix() {
         if env::var_os("PATH").is_some() {
-            let result = find_in_path("ls");
+            let result = find_in_path("ls", &search());
             assert!(result.is_ok());
             assert!(result.unwrap().is_some());
         }
     }
+
+    #[test]
+    fn find_all_in_path_starts_with_the_first_match() {
+        assert!(find_all_in_path("definitely_does_not_exist_12345", &search()).is_empty());
+        if env::var_os("PATH").is_some() {
+            let first = find_in_path("ls", &search()).unwrap();
+            assert_eq!(find_all_in_path("ls", &search()).first(), first.as_ref());
+        }
+    }
+
+    #[test]
+    fn path_cache_counts_hits() {
+        let Ok(Some(ls)) = find_in_path("ls", &search()) else {
+            return;
+        };
+        let mut cache = PathCache::default();
+        assert_eq!(cache.remember("ls", &search()).unwrap().hits, 0);
+        assert_eq!(cache.lookup("ls", &search()), Some(ls.clone()));
+        assert_eq!(cache.lookup("ls", &search()), Some(ls.clone()));
+
+        let entries: Vec<_> = cache.iter().collect();
+        assert_eq!(entries, [("ls", &Hashed { path: ls, hits: 2 })]);
+        assert_eq!(
+            cache.lookup("definitely_does_not_exist_12345", &search()),
+            None
+        );
+
+        cache.clear();
+        assert_eq!(cache.iter().count(), 0);
+    }
+
+    #[test]
+    fn names_are_tried_with_each_extension() {
+        let extensions = [".exe".to_string(), ".bat".to_string()];
+        assert_eq!(candidates("git", &extensions), ["git.exe", "git.bat"]);
+        assert_eq!(candidates("Run.BAT", &extensions), ["Run.BAT"]);
+        assert_eq!(candidates("a.txt", &extensions), ["a.txt.exe", "a.txt.bat"]);
+        assert_eq!(candidates("ls", &[]), ["ls"]);
+    }
+
+    #[test]
+    fn names_with_a_separator_are_paths() {
+        assert!(is_path("./build.sh"));
+        assert!(is_path("/opt/tool/bin/x"));
+        assert!(!is_path("ls"));
+        assert_eq!(is_path(r"C:\tools\x.exe"), cfg!(windows));
+        assert_eq!(is_path("C:x"), cfg!(windows));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn paths_are_run_without_searching() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = env::temp_dir().join(format!("rush-paths-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("run.sh");
+        fs::write(&script, "exit 0\n").unwrap();
+        let script = script.to_string_lossy().to_string();
+        let status =
+            |name: &str| runnable_path(name, &search()).map_err(|error| error.status().code());
+
+        let mut cache = PathCache::default();
+        assert_eq!(cache.lookup(&script, &search()), Some(script.clone()));
+        assert_eq!(cache.iter().count(), 0);
+
+        assert_eq!(status(&script), Err(126));
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(status(&script), Ok(script.clone()));
+        assert_eq!(status(&dir.to_string_lossy()), Err(126));
+        assert_eq!(status(&format!("{}/missing", dir.display())), Err(127));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn relative_paths_start_from_the_working_directory() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = env::temp_dir().join(format!("rush-relative-{}", std::process::id()));
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        let tool = dir.join("bin/tool");
+        fs::write(&tool, "exit 0\n").unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut state = ShellState::default();
+        state.cwd.set(dir.clone());
+        state.vars.set("PATH", "bin");
+        let search = Search::new(&state);
+        let tool = tool.to_string_lossy().to_string();
+        assert_eq!(
+            find_in_path("tool", &search).unwrap(),
+            Some("bin/tool".into())
+        );
+        assert_eq!(
+            PathCache::default().lookup("tool", &search),
+            Some(tool.clone())
+        );
+        assert_eq!(runnable_path("bin/tool", &search).unwrap(), tool);
+        assert_eq!(
+            PathCache::default().lookup("./bin/tool", &search),
+            Some(dir.join("./bin/tool").to_string_lossy().into())
+        );
+
+        // A path isn't joined to the `PATH` entries, but taken as it is
+        assert_eq!(find_all_in_path("./bin/tool", &search), ["./bin/tool"]);
+        assert_eq!(find_all_in_path(&tool, &search), [tool.as_str()]);
+        assert!(find_all_in_path("./tool", &search).is_empty());
+
+        state.vars.unset("PATH");
+        assert_eq!(find_in_path("tool", &Search::new(&state)).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directories_on_path_arent_commands() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = env::temp_dir().join(format!("rush-path-dirs-{}", std::process::id()));
+        fs::create_dir_all(dir.join("first/ls")).unwrap();
+        fs::create_dir_all(dir.join("second")).unwrap();
+        let ls = dir.join("second/ls");
+        fs::write(&ls, "exit 0\n").unwrap();
+        fs::set_permissions(&ls, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut state = ShellState::default();
+        state
+            .vars
+            .set("PATH", &format!("{0}/first:{0}/second", dir.display()));
+        let search = Search::new(&state);
+        let ls = ls.to_string_lossy().to_string();
+        assert_eq!(find_all_in_path("ls", &search), [ls.as_str()]);
+        assert_eq!(find_in_path("ls", &search).unwrap(), Some(ls.clone()));
+        assert_eq!(PathCache::default().lookup("ls", &search), Some(ls));
+
+        state.vars.set("PATH", &format!("{}/first", dir.display()));
+        let search = Search::new(&state);
+        assert!(find_all_in_path("ls", &search).is_empty());
+        assert_eq!(find_in_path("ls", &search).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,1120 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, PipeReader, PipeWriter, Read, Write},
+    path::{Path, PathBuf},
+    process::Stdio,
+    thread,
+};
+#[cfg(unix)]
+use std::{
+    ffi::c_int,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+        unix::fs::OpenOptionsExt,
+    },
+};
+
+use crate::{
+    expand::{expand_unsplit, expand_words},
+    state::ShellState,
+    util::{Redirect, RedirectKind, RushError},
+};
+
+/// The descriptors above 2 that `exec` opened in the shell itself, which
+/// every later command can use. Descriptors belong to the whole process, so
+/// this does too.
+#[cfg(unix)]
+static SHELL_FDS: std::sync::Mutex<std::collections::BTreeSet<u32>> =
+    std::sync::Mutex::new(std::collections::BTreeSet::new());
+
+/// The lowest descriptor the shell keeps a file of its own at, such as the
+/// script it's reading, like bash's 255. Scripts name low numbers.
+#[cfg(unix)]
+const SHELL_OWN_FD: c_int = 255;
+
+/// Files a redirection creates get this mode, less the shell's umask.
+#[cfg(unix)]
+const CREATE_MODE: u32 = 0o666;
+
+/// A redirection with its target expanded into the path it opens.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Redirection {
+    pub fd: u32,
+    pub kind: RedirectKind,
+    /// The file to open, the descriptor to copy for `>&` and `<&`, or the
+    /// text of a here-document.
+    pub path: String,
+    /// The directory a relative `path` is opened from: the shell's current
+    /// directory when it was expanded.
+    pub dir: PathBuf,
+    /// Whether `>` refuses to overwrite an existing file, as it does while
+    /// the `noclobber` option is set.
+    pub noclobber: bool,
+}
+
+/// Expands the target of each redirection. A target has to expand to exactly
+/// one word, since it names a single file; a here-document body is expanded
+/// without being split at all.
+pub(crate) fn expand_redirects(
+    redirects: &[Redirect],
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<Vec<Redirection>, RushError> {
+    redirects
+        .iter()
+        .map(|redirect| {
+            if redirect.kind.is_here_doc() {
+                return Ok(Redirection {
+                    fd: redirect.fd,
+                    kind: redirect.kind,
+                    path: expand_unsplit(&redirect.target, state, streams)?,
+                    dir: PathBuf::new(),
+                    noclobber: state.options.noclobber,
+                });
+            }
+
+            let mut fields = expand_words(std::slice::from_ref(&redirect.target), state, streams)?;
+            match (fields.pop(), fields.is_empty()) {
+                (Some(path), true) if state.restricted && writes_file(redirect.kind) => {
+                    Err(RushError::RedirectError {
+                        path,
+                        msg: "restricted: cannot redirect output".into(),
+                    })
+                }
+                (Some(path), true) => Ok(Redirection {
+                    fd: redirect.fd,
+                    kind: redirect.kind,
+                    path,
+                    dir: state.cwd.path().to_owned(),
+                    noclobber: state.options.noclobber,
+                }),
+                _ => Err(RushError::AmbiguousRedirect(redirect.target.to_string())),
+            }
+        })
+        .collect()
+}
+
+/// Whether a redirection of `kind` writes to a file, rather than to a
+/// descriptor already open, as `>&2` does.
+fn writes_file(kind: RedirectKind) -> bool {
+    matches!(
+        kind,
+        RedirectKind::Output
+            | RedirectKind::Append
+            | RedirectKind::Clobber
+            | RedirectKind::OutputAll
+            | RedirectKind::AppendAll
+    )
+}
+
+/// Where one of a command's output streams goes.
+#[derive(Debug)]
+pub(crate) enum Sink {
+    /// The output the command was run with, which may be a capture buffer
+    /// rather than the shell's stdout.
+    Out,
+    /// The shell's stderr.
+    Err,
+    File(File),
+    /// The write end of a pipe to the next command of a pipeline.
+    Pipe(PipeWriter),
+    /// Nowhere, closed by `>&-`, so writing fails.
+    Closed,
+}
+
+impl Sink {
+    /// A writer for whatever this sink is, with `out` standing in for the
+    /// output the command was run with. `out` is borrowed a write at a time,
+    /// so stdout and stderr can both go to it and stay in order.
+    pub(crate) fn writer<'a>(&'a self, out: &'a RefCell<&mut dyn Write>) -> Box<dyn Write + 'a> {
+        match self {
+            Sink::Out => Box::new(Shared(out)),
+            Sink::Err => Box::new(io::stderr()),
+            Sink::File(file) => Box::new(file),
+            Sink::Pipe(pipe) => Box::new(pipe),
+            Sink::Closed => Box::new(Closed),
+        }
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(match self {
+            Sink::Out => Sink::Out,
+            Sink::Err => Sink::Err,
+            Sink::File(file) => Sink::File(file.try_clone()?),
+            Sink::Pipe(pipe) => Sink::Pipe(pipe.try_clone()?),
+            Sink::Closed => Sink::Closed,
+        })
+    }
+}
+
+/// Where a closed sink writes: nowhere, failing the way a descriptor that
+/// isn't open does.
+struct Closed;
+
+impl Write for Closed {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::from_raw_os_error(libc::EBADF))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A writer that several streams take turns with.
+struct Shared<'a, 'b>(&'a RefCell<&'b mut dyn Write>);
+
+impl Write for Shared<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// Where a command's stdin comes from when it isn't inherited.
+#[derive(Debug)]
+pub(crate) enum Source {
+    File(File),
+    /// The read end of a pipe fed with the text of a here-document.
+    Pipe(PipeReader),
+    /// Nothing, closed by `<&-`, so reading fails.
+    Closed,
+}
+
+impl Source {
+    pub(crate) fn try_clone(&self) -> io::Result<Self> {
+        Ok(match self {
+            Source::File(file) => Source::File(file.try_clone()?),
+            Source::Pipe(pipe) => Source::Pipe(pipe.try_clone()?),
+            Source::Closed => Source::Closed,
+        })
+    }
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Source::File(file) => file.read(buf),
+            Source::Pipe(pipe) => pipe.read(buf),
+            Source::Closed => Err(io::Error::from_raw_os_error(libc::EBADF)),
+        }
+    }
+}
+
+/// A closed source stands in as nothing, for the program to close once it
+/// starts. See [`Streams::closed_fds`].
+impl From<Source> for Stdio {
+    fn from(source: Source) -> Self {
+        match source {
+            Source::File(file) => file.into(),
+            Source::Pipe(pipe) => pipe.into(),
+            Source::Closed => Stdio::null(),
+        }
+    }
+}
+
+/// A descriptor above 2, opened by a redirection such as `3>log` or `4<in`,
+/// or closed by one such as `3>&-`.
+#[derive(Debug)]
+pub(crate) enum Descriptor {
+    Input(Source),
+    Output(Sink),
+}
+
+impl Descriptor {
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(match self {
+            Descriptor::Input(source) => Descriptor::Input(source.try_clone()?),
+            Descriptor::Output(sink) => Descriptor::Output(sink.try_clone()?),
+        })
+    }
+}
+
+/// Where a command's standard streams are connected once its redirections
+/// have been applied.
+#[derive(Debug)]
+pub(crate) struct Streams {
+    /// Where stdin reads from, if it isn't inherited from the shell.
+    pub stdin: Option<Source>,
+    pub stdout: Sink,
+    pub stderr: Sink,
+    /// The other descriptors opened by redirections, by number.
+    pub extra: BTreeMap<u32, Descriptor>,
+    /// Whether [`Sink::Out`] is the shell's own stdout, which a program can
+    /// be handed to write to directly, rather than a buffer like the one a
+    /// command substitution captures into.
+    pub out_is_stdout: bool,
+}
+
+impl Default for Streams {
+    fn default() -> Self {
+        Self {
+            stdin: None,
+            stdout: Sink::Out,
+            stderr: Sink::Err,
+            extra: BTreeMap::new(),
+            out_is_stdout: false,
+        }
+    }
+}
+
+impl Streams {
+    /// Opens the file of every redirection, in order, so a later one for the
+    /// same descriptor wins and `2>&1` copies wherever stdout goes by then.
+    #[cfg(test)]
+    pub(crate) fn open(redirections: &[Redirection]) -> Result<Self, RushError> {
+        Streams::default().apply(redirections)
+    }
+
+    /// Like [`Streams::open`], but starting from these streams rather than
+    /// the shell's own, as for a command inside a redirected `{ ...; }`.
+    pub(crate) fn apply(&self, redirections: &[Redirection]) -> Result<Self, RushError> {
+        let mut streams = self.try_clone().map_err(|error| RushError::RedirectError {
+            path: "redirection".into(),
+            msg: describe(&error),
+        })?;
+
+        for redirection in redirections {
+            let open_error = |error: io::Error| RushError::RedirectError {
+                path: redirection.path.clone(),
+                msg: describe(&error),
+            };
+            let file = redirection.dir.join(&redirection.path);
+            let write = |append: bool| {
+                creating()
+                    .write(true)
+                    .create(true)
+                    .append(append)
+                    .truncate(!append)
+                    .open(&file)
+                    .map(Sink::File)
+                    .map_err(open_error)
+            };
+            let truncate = || match redirection.noclobber {
+                true => create_new(&redirection.path, &file),
+                false => write(false),
+            };
+
+            match redirection.kind {
+                RedirectKind::Input => {
+                    let file = File::open(&file).map_err(open_error)?;
+                    streams.set_input(redirection.fd, Source::File(file))?;
+                }
+                RedirectKind::HereDoc | RedirectKind::HereDocStrip => {
+                    let pipe = here_doc(redirection.path.clone()).map_err(|error| {
+                        RushError::RedirectError {
+                            path: "here-document".into(),
+                            msg: describe(&error),
+                        }
+                    })?;
+                    streams.set_input(redirection.fd, Source::Pipe(pipe))?;
+                }
+                RedirectKind::Output => streams.set_output(redirection.fd, truncate()?)?,
+                RedirectKind::Clobber => streams.set_output(redirection.fd, write(false)?)?,
+                RedirectKind::Append => streams.set_output(redirection.fd, write(true)?)?,
+                RedirectKind::OutputAll | RedirectKind::AppendAll => {
+                    let sink = match redirection.kind {
+                        RedirectKind::AppendAll => write(true)?,
+                        _ => truncate()?,
+                    };
+                    streams.stderr = sink.try_clone().map_err(open_error)?;
+                    streams.stdout = sink;
+                }
+                // `>&-` and `<&-` close the descriptor instead of copying one
+                RedirectKind::Duplicate if redirection.path == "-" => {
+                    streams.set_output(redirection.fd, Sink::Closed)?;
+                }
+                RedirectKind::DuplicateInput if redirection.path == "-" => {
+                    streams.set_input(redirection.fd, Source::Closed)?;
+                }
+                RedirectKind::Duplicate => {
+                    let sink = streams
+                        .output_of(&redirection.path)
+                        .ok_or_else(|| bad_descriptor(&redirection.path))?;
+                    streams.set_output(redirection.fd, sink.map_err(open_error)?)?;
+                }
+                RedirectKind::DuplicateInput => {
+                    let source = streams
+                        .input_of(&redirection.path)
+                        .ok_or_else(|| bad_descriptor(&redirection.path))?;
+                    match source.map_err(open_error)? {
+                        Some(source) => streams.set_input(redirection.fd, source)?,
+                        // `<&0` while stdin is still the shell's own
+                        None if redirection.fd == 0 => streams.stdin = None,
+                        None => {
+                            let stdin = shell_stdin().map_err(open_error)?;
+                            streams.set_input(redirection.fd, Source::File(stdin))?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(streams)
+    }
+
+    /// The streams a command substitution run by a command with these
+    /// streams starts from: the same, but with stdout captured. A stderr
+    /// sent to the output the command was run with goes to the shell's own
+    /// instead, since that output is out of the substitution's reach.
+    pub(crate) fn substitution(&self) -> Result<Self, RushError> {
+        let mut streams = self.apply(&[])?;
+        streams.stdout = Sink::Out;
+        streams.out_is_stdout = false;
+        if matches!(streams.stderr, Sink::Out) {
+            streams.stderr = Sink::Err;
+        }
+        Ok(streams)
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            stdin: self.stdin.as_ref().map(Source::try_clone).transpose()?,
+            stdout: self.stdout.try_clone()?,
+            stderr: self.stderr.try_clone()?,
+            extra: self
+                .extra
+                .iter()
+                .map(|(&fd, descriptor)| Ok((fd, descriptor.try_clone()?)))
+                .collect::<io::Result<_>>()?,
+            out_is_stdout: self.out_is_stdout,
+        })
+    }
+
+    /// A copy of where the descriptor numbered `target` writes, or `None` if
+    /// it isn't open for writing.
+    fn output_of(&self, target: &str) -> Option<io::Result<Sink>> {
+        let open = |sink: &Sink| match sink {
+            Sink::Closed => None,
+            sink => Some(sink.try_clone()),
+        };
+        match target.parse().ok()? {
+            0 => match &self.stdin {
+                Some(Source::File(file)) => Some(file.try_clone().map(Sink::File)),
+                _ => None,
+            },
+            1 => open(&self.stdout),
+            2 => open(&self.stderr),
+            fd => match self.extra.get(&fd) {
+                None => shell_fd(fd).map(|file| file.map(Sink::File)),
+                Some(Descriptor::Output(sink)) => open(sink),
+                Some(Descriptor::Input(Source::File(file))) => {
+                    Some(file.try_clone().map(Sink::File))
+                }
+                Some(Descriptor::Input(Source::Pipe(_) | Source::Closed)) => None,
+            },
+        }
+    }
+
+    /// A copy of where the descriptor numbered `target` reads from, or `None`
+    /// if it isn't open for reading. Stdin that is still the shell's own
+    /// comes back as `Ok(None)`.
+    fn input_of(&self, target: &str) -> Option<io::Result<Option<Source>>> {
+        let file = |sink: &Sink| match sink {
+            Sink::File(file) => Some(file.try_clone().map(|file| Some(Source::File(file)))),
+            _ => None,
+        };
+        match target.parse().ok()? {
+            0 => match &self.stdin {
+                Some(Source::Closed) => None,
+                stdin => Some(stdin.as_ref().map(Source::try_clone).transpose()),
+            },
+            1 => file(&self.stdout),
+            2 => file(&self.stderr),
+            fd => match self.extra.get(&fd) {
+                None => shell_fd(fd).map(|file| file.map(|file| Some(Source::File(file)))),
+                Some(Descriptor::Input(Source::Closed)) => None,
+                Some(Descriptor::Input(source)) => Some(source.try_clone().map(Some)),
+                Some(Descriptor::Output(sink)) => file(sink),
+            },
+        }
+    }
+
+    /// Puts `source` at descriptor `fd`. Stdout and stderr can be opened for
+    /// reading too, as by `1<file`, but only a file can be both a source and
+    /// a sink.
+    fn set_input(&mut self, fd: u32, source: Source) -> Result<(), RushError> {
+        match (fd, source) {
+            (0, source) => self.stdin = Some(source),
+            (1 | 2, Source::File(file)) => self.set_output(fd, Sink::File(file))?,
+            (1 | 2, Source::Closed) => self.set_output(fd, Sink::Closed)?,
+            (1 | 2, Source::Pipe(_)) => return Err(wrong_direction(fd)),
+            (_, source) => {
+                self.extra.insert(fd, Descriptor::Input(source));
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts `sink` at descriptor `fd`. Stdin can be opened for writing too,
+    /// as by `0>file`, but only a file can be both a sink and a source.
+    fn set_output(&mut self, fd: u32, sink: Sink) -> Result<(), RushError> {
+        match (fd, sink) {
+            (0, Sink::File(file)) => self.stdin = Some(Source::File(file)),
+            (0, Sink::Closed) => self.stdin = Some(Source::Closed),
+            (0, _) => return Err(wrong_direction(fd)),
+            (1, sink) => self.stdout = sink,
+            (2, sink) => self.stderr = sink,
+            (_, sink) => {
+                self.extra.insert(fd, Descriptor::Output(sink));
+            }
+        }
+        Ok(())
+    }
+
+    /// The descriptors closed by `>&-` or `<&-`, for a program to close
+    /// once it starts, in place of the stand-ins it was given.
+    #[cfg(unix)]
+    pub(crate) fn closed_fds(&self) -> Vec<u32> {
+        let standard = [
+            (0, matches!(self.stdin, Some(Source::Closed))),
+            (1, matches!(self.stdout, Sink::Closed)),
+            (2, matches!(self.stderr, Sink::Closed)),
+        ];
+        let extra = self.extra.iter().map(|(&fd, descriptor)| {
+            let closed = matches!(
+                descriptor,
+                Descriptor::Input(Source::Closed) | Descriptor::Output(Sink::Closed)
+            );
+            (fd, closed)
+        });
+        standard
+            .into_iter()
+            .chain(extra)
+            .filter_map(|(fd, closed)| closed.then_some(fd))
+            .collect()
+    }
+
+    /// Copies of the descriptors above 2, for passing on to a program under
+    /// the same numbers. `out` and `err` stand in for the shell's own stdout
+    /// and stderr. Closed ones are left out; see [`Streams::closed_fds`].
+    #[cfg(unix)]
+    pub(crate) fn extra_fds(
+        &self,
+        out: BorrowedFd,
+        err: BorrowedFd,
+    ) -> io::Result<Vec<(u32, OwnedFd)>> {
+        self.extra
+            .iter()
+            .filter_map(|(&fd, descriptor)| {
+                let borrowed = match descriptor {
+                    Descriptor::Input(Source::File(file)) => file.as_fd(),
+                    Descriptor::Input(Source::Pipe(pipe)) => pipe.as_fd(),
+                    Descriptor::Output(Sink::Out) => out,
+                    Descriptor::Output(Sink::Err) => err,
+                    Descriptor::Output(Sink::File(file)) => file.as_fd(),
+                    Descriptor::Output(Sink::Pipe(pipe)) => pipe.as_fd(),
+                    Descriptor::Input(Source::Closed) | Descriptor::Output(Sink::Closed) => {
+                        return None;
+                    }
+                };
+                Some(borrowed.try_clone_to_owned().map(|owned| (fd, owned)))
+            })
+            .collect()
+    }
+
+    /// Makes these the shell's own streams, as `exec` does without a
+    /// command, so every later command starts from them.
+    #[cfg(unix)]
+    pub(crate) fn install(self) -> io::Result<()> {
+        let (out, err) = (io::stdout(), io::stderr());
+        // Everything is copied before anything moves, so `2>&1 >log` still
+        // sends stderr wherever stdout was
+        let mut fds = self.extra_fds(out.as_fd(), err.as_fd())?;
+        let closed = self.closed_fds();
+        let Streams {
+            stdin,
+            stdout,
+            stderr,
+            extra,
+            ..
+        } = self;
+        // The originals may already hold the numbers they are going to
+        drop(extra);
+        if let Some(stdin) = stdin {
+            let owned = match stdin {
+                Source::File(file) => Some(file.into()),
+                Source::Pipe(pipe) => Some(pipe.into()),
+                Source::Closed => None,
+            };
+            fds.extend(owned.map(|owned| (0, owned)));
+        }
+        for (fd, sink) in [(1, stdout), (2, stderr)] {
+            let owned = match sink {
+                Sink::Out if fd == 1 => continue,
+                Sink::Err if fd == 2 => continue,
+                Sink::Closed => continue,
+                Sink::Out => out.as_fd().try_clone_to_owned()?,
+                Sink::Err => err.as_fd().try_clone_to_owned()?,
+                Sink::File(file) => file.into(),
+                Sink::Pipe(pipe) => pipe.into(),
+            };
+            fds.push((fd, owned));
+        }
+
+        // The copies are then moved clear of any descriptor a script names,
+        // so putting one in place can't close another yet to be placed
+        const PARKED: c_int = 64;
+        let parked = fds
+            .into_iter()
+            .map(|(fd, owned)| {
+                // SAFETY: `owned` is open, and F_DUPFD_CLOEXEC only copies it
+                let parked =
+                    unsafe { libc::fcntl(owned.as_raw_fd(), libc::F_DUPFD_CLOEXEC, PARKED) };
+                if parked < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // SAFETY: the copy was just made, and nothing else owns it
+                Ok((fd, unsafe { OwnedFd::from_raw_fd(parked) }))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        out.lock().flush()?;
+        let mut shell_fds = SHELL_FDS.lock().unwrap_or_else(|error| error.into_inner());
+        for (fd, parked) in parked {
+            // SAFETY: both descriptors are open, and `fd` is one of the
+            // shell's standard streams or one a script asked for by number.
+            // dup2 leaves close-on-exec clear on `fd`, so every later
+            // command inherits it
+            if unsafe { libc::dup2(parked.as_raw_fd(), fd as c_int) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if fd > 2 {
+                shell_fds.insert(fd);
+            }
+        }
+        for fd in closed {
+            // SAFETY: the descriptor is one a script asked to close, so
+            // nothing of the shell's own is there. It may not be open, which
+            // is no matter
+            unsafe { libc::close(fd as c_int) };
+            shell_fds.remove(&fd);
+        }
+        Ok(())
+    }
+
+    /// Writes `text` wherever standard error goes.
+    pub(crate) fn write_stderr(&self, text: &str, out: &mut dyn Write) -> io::Result<()> {
+        match &self.stderr {
+            Sink::Err => io::stderr().write_all(text.as_bytes()),
+            Sink::Out => out.write_all(text.as_bytes()),
+            Sink::File(file) => (&*file).write_all(text.as_bytes()),
+            Sink::Pipe(pipe) => (&*pipe).write_all(text.as_bytes()),
+            Sink::Closed => Closed.write_all(text.as_bytes()),
+        }
+    }
+
+    /// Reads a line from stdin, without its newline, or `None` at the end of
+    /// the input. A redirected stdin is read a byte at a time, so whatever
+    /// follows the line is left for the commands after it.
+    pub(crate) fn read_line(&self) -> io::Result<Option<String>> {
+        let mut line = Vec::new();
+        match &self.stdin {
+            None => {
+                if io::stdin().lock().read_until(b'\n', &mut line)? == 0 {
+                    return Ok(None);
+                }
+            }
+            Some(Source::File(file)) => return read_line_from(&mut &*file),
+            Some(Source::Pipe(pipe)) => return read_line_from(&mut &*pipe),
+            Some(Source::Closed) => return Err(io::Error::from_raw_os_error(libc::EBADF)),
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+    }
+
+    /// Like [`Streams::read_line`], but from descriptor `fd`: one this
+    /// command's redirections opened, or else one `exec` opened in the
+    /// shell, as for `exec 3<config; read -u 3 line`.
+    pub(crate) fn read_line_fd(&self, fd: u32) -> io::Result<Option<String>> {
+        match (fd, self.extra.get(&fd)) {
+            (0, _) => self.read_line(),
+            (_, Some(Descriptor::Input(Source::File(file)))) => read_line_from(&mut &*file),
+            (_, Some(Descriptor::Input(Source::Pipe(pipe)))) => read_line_from(&mut &*pipe),
+            (_, Some(_)) => Err(io::Error::from_raw_os_error(libc::EBADF)),
+            (_, None) => match shell_fd(fd) {
+                Some(file) => read_line_from(&mut file?),
+                None => Err(io::Error::from_raw_os_error(libc::EBADF)),
+            },
+        }
+    }
+
+    /// Shows `error` on the command's stderr if that is redirected away from
+    /// the shell's, returning what the caller should report in its place.
+    pub(crate) fn report(&self, error: RushError, out: &mut dyn Write) -> RushError {
+        let mut file: &File;
+        let mut pipe: &PipeWriter;
+        let mut closed = Closed;
+        let writer: &mut dyn Write = match &self.stderr {
+            Sink::Err => return error,
+            Sink::Out => out,
+            Sink::Closed => &mut closed,
+            Sink::File(stderr) => {
+                file = stderr;
+                &mut file
+            }
+            Sink::Pipe(stderr) => {
+                pipe = stderr;
+                &mut pipe
+            }
+        };
+
+        match error {
+            RushError::Nop | RushError::Reported(_) => error,
+            error if error.unwinds() => error,
+            error => {
+                writeln!(writer, "{error}").ok();
+                RushError::Reported(error.status())
+            }
+        }
+    }
+}
+
+/// Reads a line from `reader` a byte at a time, so nothing past its newline
+/// is taken from whatever else reads there, returning it without the
+/// newline, or `None` at the end of the input.
+fn read_line_from(reader: &mut impl Read) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    loop {
+        let mut byte = [0];
+        match reader.read(&mut byte) {
+            Ok(0) if line.is_empty() => return Ok(None),
+            Ok(0) => break,
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) => line.push(byte[0]),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
+
+/// Returns a pipe that reads back `text`. It is written from another thread,
+/// so a body bigger than the pipe's buffer can't block the shell, and the
+/// writer gives up as soon as the command closes its end unread.
+fn here_doc(text: String) -> io::Result<PipeReader> {
+    let (reader, mut writer) = io::pipe()?;
+    thread::spawn(move || writer.write_all(text.as_bytes()));
+    Ok(reader)
+}
+
+/// Opens `file`, given as `path`, for `>` under `noclobber`: a new file is
+/// created, but one that already exists is only opened if it isn't a
+/// regular file, so `>/dev/null` still works.
+fn create_new(path: &str, file: &Path) -> Result<Sink, RushError> {
+    let open_error = |error: io::Error| RushError::RedirectError {
+        path: path.to_owned(),
+        msg: describe(&error),
+    };
+    let created = creating().write(true).create_new(true).open(file);
+    match created {
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+            if fs::metadata(file).is_ok_and(|metadata| metadata.is_file()) {
+                return Err(RushError::RedirectError {
+                    path: path.to_owned(),
+                    msg: "cannot overwrite existing file".into(),
+                });
+            }
+            OpenOptions::new()
+                .write(true)
+                .open(file)
+                .map(Sink::File)
+                .map_err(open_error)
+        }
+        created => created.map(Sink::File).map_err(open_error),
+    }
+}
+
+/// Options for opening a file a redirection may create, which gets
+/// [`CREATE_MODE`] as filtered by the umask, like every other shell's.
+fn creating() -> OpenOptions {
+    #[cfg(unix)]
+    {
+        let mut options = OpenOptions::new();
+        options.mode(CREATE_MODE);
+        options
+    }
+    #[cfg(not(unix))]
+    OpenOptions::new()
+}
+
+/// A copy of the shell's own stdin, for `3<&0`.
+#[cfg(unix)]
+fn shell_stdin() -> io::Result<File> {
+    Ok(io::stdin().as_fd().try_clone_to_owned()?.into())
+}
+
+#[cfg(not(unix))]
+fn shell_stdin() -> io::Result<File> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Moves a file the shell reads itself up to a descriptor clear of those a
+/// script names, so `exec 3>file` can't put something else in its place.
+/// Where there is no such descriptor, the file stays where it is.
+#[cfg(unix)]
+pub(crate) fn keep_clear(file: File) -> File {
+    // SAFETY: `file` is open, and F_DUPFD_CLOEXEC only copies it
+    let moved = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_DUPFD_CLOEXEC, SHELL_OWN_FD) };
+    match moved {
+        // SAFETY: the copy was just made, and nothing else owns it
+        0.. => unsafe { File::from_raw_fd(moved) },
+        _ => file,
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn keep_clear(file: File) -> File {
+    file
+}
+
+/// A copy of descriptor `fd` if `exec` opened it in the shell.
+#[cfg(unix)]
+fn shell_fd(fd: u32) -> Option<io::Result<File>> {
+    let shell_fds = SHELL_FDS.lock().unwrap_or_else(|error| error.into_inner());
+    if !shell_fds.contains(&fd) {
+        return None;
+    }
+    // SAFETY: `exec` put the descriptor in place, and nothing closes it
+    let borrowed = unsafe { BorrowedFd::borrow_raw(fd as c_int) };
+    Some(borrowed.try_clone_to_owned().map(File::from))
+}
+
+#[cfg(not(unix))]
+fn shell_fd(_fd: u32) -> Option<io::Result<File>> {
+    None
+}
+
+fn bad_descriptor(target: &str) -> RushError {
+    RushError::RedirectError {
+        path: target.to_owned(),
+        msg: "bad file descriptor".into(),
+    }
+}
+
+/// The error for opening stdin for writing, or stdout or stderr for reading,
+/// with something that only goes one way, like a pipe.
+fn wrong_direction(fd: u32) -> RushError {
+    RushError::RedirectError {
+        path: fd.to_string(),
+        msg: "can't be redirected that way".into(),
+    }
+}
+
+/// Describes `error` the way other shells do, without the errno suffix that
+/// `io::Error` adds.
+pub(crate) fn describe(error: &io::Error) -> String {
+    let msg = error.to_string();
+    match msg.find(" (os error") {
+        Some(end) => msg[..end].to_owned(),
+        None => msg,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::Read};
+
+    use super::*;
+    use crate::parser::SimpleCommand;
+
+    fn redirections(input: &str) -> Result<Vec<Redirection>, RushError> {
+        let command = SimpleCommand::parse(input)?;
+        expand_redirects(
+            &command.redirects,
+            &mut ShellState::default(),
+            &Streams::default(),
+        )
+    }
+
+    #[test]
+    fn targets_are_expanded() {
+        assert_eq!(
+            redirections("cat < in.txt <'a b'").unwrap(),
+            [
+                Redirection {
+                    fd: 0,
+                    kind: RedirectKind::Input,
+                    path: "in.txt".into(),
+                    dir: env::current_dir().unwrap(),
+                    noclobber: false,
+                },
+                Redirection {
+                    fd: 0,
+                    kind: RedirectKind::Input,
+                    path: "a b".into(),
+                    dir: env::current_dir().unwrap(),
+                    noclobber: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn target_must_be_one_word() {
+        let error = redirections("cat < {a,b}").unwrap_err();
+        assert_eq!(error.to_string(), "{a,b}: ambiguous redirect");
+    }
+
+    #[test]
+    fn opens_input_files() {
+        let path = env::temp_dir().join(format!("rush_redirect_in_{}", std::process::id()));
+        fs::write(&path, "contents").unwrap();
+
+        let streams = Streams::open(&[Redirection {
+            fd: 0,
+            kind: RedirectKind::Input,
+            path: path.to_string_lossy().into_owned(),
+            dir: PathBuf::new(),
+            noclobber: false,
+        }])
+        .unwrap();
+        let mut text = String::new();
+        streams.stdin.unwrap().read_to_string(&mut text).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(text, "contents");
+    }
+
+    fn output(fd: u32, kind: RedirectKind, path: &str) -> Redirection {
+        Redirection {
+            fd,
+            kind,
+            path: path.into(),
+            dir: PathBuf::new(),
+            noclobber: false,
+        }
+    }
+
+    #[test]
+    fn duplication_follows_the_order_written() {
+        let dir = env::temp_dir().join(format!("rush_redirect_dup_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("log").to_string_lossy().into_owned();
+
+        // `> log 2>&1` sends both streams to the file...
+        let streams = Streams::open(&[
+            output(1, RedirectKind::Output, &log),
+            output(2, RedirectKind::Duplicate, "1"),
+        ])
+        .unwrap();
+        assert!(matches!(streams.stdout, Sink::File(_)));
+        assert!(matches!(streams.stderr, Sink::File(_)));
+
+        // ...but `2>&1 > log` copies stdout before it moves
+        let streams = Streams::open(&[
+            output(2, RedirectKind::Duplicate, "1"),
+            output(1, RedirectKind::Output, &log),
+        ])
+        .unwrap();
+        assert!(matches!(streams.stdout, Sink::File(_)));
+        assert!(matches!(streams.stderr, Sink::Out));
+
+        let error = Streams::open(&[output(2, RedirectKind::Duplicate, "x")]).unwrap_err();
+        assert_eq!(error.to_string(), "x: bad file descriptor");
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn numbered_descriptors() {
+        let path = env::temp_dir().join(format!("rush_redirect_fds_{}", std::process::id()));
+        fs::write(&path, "contents").unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        // `4<file <&4 5>&1` reads the file and keeps a copy of stdout as 5
+        let streams = Streams::open(&[
+            output(4, RedirectKind::Input, &path),
+            output(0, RedirectKind::DuplicateInput, "4"),
+            output(5, RedirectKind::Duplicate, "1"),
+        ])
+        .unwrap();
+        let mut text = String::new();
+        streams.stdin.unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(text, "contents");
+        assert!(matches!(streams.extra[&4], Descriptor::Input(_)));
+        assert!(matches!(streams.extra[&5], Descriptor::Output(Sink::Out)));
+
+        // A pipe can't be written to through its read end
+        let error = Streams::open(&[
+            output(3, RedirectKind::HereDoc, "text\n"),
+            output(1, RedirectKind::Duplicate, "3"),
+        ])
+        .unwrap_err();
+        assert_eq!(error.to_string(), "3: bad file descriptor");
+
+        let error = Streams::open(&[output(0, RedirectKind::DuplicateInput, "6")]).unwrap_err();
+        assert_eq!(error.to_string(), "6: bad file descriptor");
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn closing_descriptors() {
+        let streams = Streams::open(&[
+            output(3, RedirectKind::Duplicate, "1"),
+            output(1, RedirectKind::Duplicate, "-"),
+            output(0, RedirectKind::DuplicateInput, "-"),
+            output(4, RedirectKind::Duplicate, "-"),
+        ])
+        .unwrap();
+        assert!(matches!(streams.stdout, Sink::Closed));
+        assert!(matches!(streams.stdin, Some(Source::Closed)));
+        #[cfg(unix)]
+        assert_eq!(streams.closed_fds(), [0, 1, 4]);
+
+        // What's closed can't be copied, and fails when used
+        let error = Streams::open(&[
+            output(1, RedirectKind::Duplicate, "-"),
+            output(2, RedirectKind::Duplicate, "1"),
+        ])
+        .unwrap_err();
+        assert_eq!(error.to_string(), "1: bad file descriptor");
+        let mut buffer = Vec::new();
+        let out = RefCell::new(&mut buffer as &mut dyn Write);
+        let error = streams.stdout.writer(&out).write(b"x").unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(libc::EBADF));
+    }
+
+    #[test]
+    fn standard_streams_open_either_way() {
+        let path = env::temp_dir().join(format!("rush_redirect_either_{}", std::process::id()));
+        fs::write(&path, "contents").unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        // `0>file` writes to stdin, and `1<file` reads from stdout
+        let streams = Streams::open(&[
+            output(0, RedirectKind::Output, &path),
+            output(1, RedirectKind::Input, &path),
+        ])
+        .unwrap();
+        assert!(matches!(streams.stdin, Some(Source::File(_))));
+        assert!(matches!(streams.stdout, Sink::File(_)));
+
+        // A here-document only reads
+        let error = Streams::open(&[output(1, RedirectKind::HereDoc, "text\n")]).unwrap_err();
+        assert_eq!(error.to_string(), "1: can't be redirected that way");
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn append_keeps_contents() {
+        let path = env::temp_dir().join(format!("rush_redirect_append_{}", std::process::id()));
+        fs::write(&path, "one\n").unwrap();
+        let path_str = path.to_string_lossy().into_owned();
+
+        for kind in [RedirectKind::Append, RedirectKind::AppendAll] {
+            let mut streams = Streams::open(&[output(1, kind, &path_str)]).unwrap();
+            let Sink::File(file) = &mut streams.stdout else {
+                panic!("Expected stdout to be redirected");
+            };
+            file.write_all(b"more\n").unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\nmore\nmore\n");
+
+        // Plain output truncates instead
+        Streams::open(&[output(1, RedirectKind::Output, &path_str)]).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn noclobber_keeps_existing_files() {
+        let path = env::temp_dir().join(format!("rush_redirect_noclobber_{}", std::process::id()));
+        fs::write(&path, "kept\n").unwrap();
+        let path_str = path.to_string_lossy().into_owned();
+        let noclobber = |kind| Redirection {
+            dir: PathBuf::new(),
+            noclobber: true,
+            ..output(1, kind, &path_str)
+        };
+
+        for kind in [RedirectKind::Output, RedirectKind::OutputAll] {
+            let error = Streams::open(&[noclobber(kind)]).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("{path_str}: cannot overwrite existing file")
+            );
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "kept\n");
+
+        // Appending is still allowed, and `>|` overwrites anyway
+        Streams::open(&[noclobber(RedirectKind::Append)]).unwrap();
+        Streams::open(&[noclobber(RedirectKind::Clobber)]).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        // New files and devices can still be written
+        fs::remove_file(&path).unwrap();
+        Streams::open(&[noclobber(RedirectKind::Output)]).unwrap();
+        assert!(path.exists());
+        Streams::open(&[Redirection {
+            dir: PathBuf::new(),
+            noclobber: true,
+            ..output(1, RedirectKind::Output, "/dev/null")
+        }])
+        .unwrap();
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn errors_go_to_redirected_stderr() {
+        let streams = Streams {
+            stderr: Sink::Out,
+            ..Streams::default()
+        };
+        let mut out = Vec::new();
+
+        let error = streams.report(RushError::CommandNotFound("nope".into()), &mut out);
+        assert!(matches!(error, RushError::Reported(status) if status.code() == 127));
+        assert_eq!(String::from_utf8(out).unwrap(), "nope: command not found\n");
+
+        // Left alone, the caller reports it as usual
+        let error = Streams::default().report(RushError::Nop, &mut Vec::new());
+        assert!(matches!(error, RushError::Nop));
+    }
+
+    #[test]
+    fn here_documents_feed_stdin() {
+        let streams = Streams::open(&[Redirection {
+            fd: 0,
+            kind: RedirectKind::HereDoc,
+            path: "line one\nline two\n".into(),
+            dir: PathBuf::new(),
+            noclobber: false,
+        }])
+        .unwrap();
+        let mut text = String::new();
+        streams.stdin.unwrap().read_to_string(&mut text).unwrap();
+
+        assert_eq!(text, "line one\nline two\n");
+    }
+
+    #[test]
+    fn here_document_bodies_are_not_split() {
+        let redirections = redirections("cat <<EOF\n  a   *  {b,c}\nEOF").unwrap();
+        assert_eq!(redirections[0].path, "  a   *  {b,c}\n");
+    }
+
+    #[test]
+    fn missing_input_file() {
+        let error = Streams::open(&[Redirection {
+            fd: 0,
+            kind: RedirectKind::Input,
+            path: "/nonexistent/rush/input".into(),
+            dir: PathBuf::new(),
+            noclobber: false,
+        }])
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "/nonexistent/rush/input: No such file or directory"
+        );
+        assert_eq!(error.status().code(), 1);
+    }
+}
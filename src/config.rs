@@ -0,0 +1,379 @@
+use std::{fmt, fs, path::PathBuf};
+
+use crate::state::{Hooks, Limits, Options, ShellState};
+
+/// A problem found while loading the config file. Loading carries on past
+/// errors so one typo doesn't discard the rest of the user's settings.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct ConfigError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Section {
+    Hooks,
+    Keys,
+    Limits,
+    Options,
+    Unknown,
+}
+
+/// Locates the config file from the shell's variables: `$RUSH_CONFIG`, then
+/// `$XDG_CONFIG_HOME/rush/config.toml`, then `~/.config/rush/config.toml`.
+pub(crate) fn config_path(state: &ShellState) -> Option<PathBuf> {
+    if let Some(path) = state.vars.get("RUSH_CONFIG") {
+        return Some(path.into());
+    }
+
+    let config_home = (state.vars.get("XDG_CONFIG_HOME"))
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| {
+            let home = state.vars.get("HOME").filter(|home| !home.is_empty())?;
+            Some(PathBuf::from(home).join(".config"))
+        })?;
+
+    Some(config_home.join("rush").join("config.toml"))
+}
+
+/// Loads the config file into `state`, returning every error encountered.
+/// A missing file is not an error.
+pub(crate) fn load(state: &mut ShellState) -> Vec<ConfigError> {
+    match config_path(state).map(fs::read_to_string) {
+        Some(Ok(text)) => apply(&text, state),
+        Some(Err(error)) if error.kind() != std::io::ErrorKind::NotFound => vec![ConfigError {
+            line: 0,
+            msg: error.to_string(),
+        }],
+        _ => Vec::new(),
+    }
+}
+
+/// Applies the settings in `text` to `state`.
+///
+/// The format is a small subset of TOML: `[section]` headers, `key = value`
+/// pairs where either side may be double-quoted, and `#` comments.
+pub(crate) fn apply(text: &str, state: &mut ShellState) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    let mut section = None;
+
+    for (index, raw) in text.lines().enumerate() {
+        let line = index + 1;
+        let mut error = |msg: String| errors.push(ConfigError { line, msg });
+
+        let content = strip_comment(raw).trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = content
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            section = Some(match name.trim() {
+                "hooks" => Section::Hooks,
+                "keys" => Section::Keys,
+                "limits" => Section::Limits,
+                "options" => Section::Options,
+                other => {
+                    error(format!("unknown section `{other}`"));
+                    Section::Unknown
+                }
+            });
+            continue;
+        }
+
+        let Some((key, value)) = content.split_once('=') else {
+            error(format!("expected `key = value`, found `{content}`"));
+            continue;
+        };
+        let (key, value) = (unquote(key.trim()), unquote(value.trim()));
+
+        match section {
+            Some(Section::Hooks) => {
+                if let Err(msg) = set_hook(&mut state.hooks, key, value) {
+                    error(msg);
+                }
+            }
+            Some(Section::Keys) => {
+                if let Err(msg) = state.keymap.bind_str(key, value) {
+                    error(msg);
+                }
+            }
+            Some(Section::Limits) => {
+                if let Err(msg) = set_limit(&mut state.limits, key, value) {
+                    error(msg);
+                }
+            }
+            Some(Section::Options) => {
+                if let Err(msg) = set_option(&mut state.options, key, value) {
+                    error(msg);
+                }
+            }
+            Some(Section::Unknown) => {}
+            None => error(format!("`{key}` is not inside a section")),
+        }
+    }
+
+    errors
+}
+
+fn set_hook(hooks: &mut Hooks, key: &str, value: &str) -> Result<(), String> {
+    // An empty value takes the hook away
+    let program = Some(value.to_owned()).filter(|program| !program.is_empty());
+
+    match key {
+        "command_not_found" => hooks.command_not_found = program,
+        _ => return Err(format!("unknown hook `{key}`")),
+    }
+
+    Ok(())
+}
+
+fn set_limit(limits: &mut Limits, key: &str, value: &str) -> Result<(), String> {
+    let bytes = || {
+        value
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| format!("`{value}` is not a positive number of bytes"))
+    };
+
+    match key {
+        "command_substitution_bytes" => limits.substitution_bytes = bytes()?,
+        _ => return Err(format!("unknown limit `{key}`")),
+    }
+
+    Ok(())
+}
+
+fn set_option(options: &mut Options, key: &str, value: &str) -> Result<(), String> {
+    let enabled = match value {
+        "true" => true,
+        "false" => false,
+        _ => return Err(format!("`{value}` is not `true` or `false`")),
+    };
+
+    match key {
+        "autocd" => options.autocd = enabled,
+        "globstar" => options.globstar = enabled,
+        "histappend" => options.histappend = enabled,
+        "huponexit" => options.huponexit = enabled,
+        "ignoreeof" => options.ignoreeof = enabled,
+        "nocaseglob" => options.nocaseglob = enabled,
+        "noclobber" => options.noclobber = enabled,
+        _ => return Err(format!("unknown option `{key}`")),
+    }
+
+    Ok(())
+}
+
+/// Removes a trailing `#` comment, ignoring any `#` inside double quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::keymap::{Action, Chord, Key};
+
+    #[test]
+    fn path_follows_the_shells_variables() {
+        let mut state = ShellState::default();
+        state.vars.unset("RUSH_CONFIG");
+        state.vars.set("XDG_CONFIG_HOME", "/xdg");
+        assert_eq!(
+            config_path(&state),
+            Some(PathBuf::from("/xdg/rush/config.toml"))
+        );
+
+        state.vars.unset("XDG_CONFIG_HOME");
+        state.vars.set("HOME", "/x");
+        assert_eq!(
+            config_path(&state),
+            Some(PathBuf::from("/x/.config/rush/config.toml"))
+        );
+
+        state.vars.set("RUSH_CONFIG", "/etc/rush.toml");
+        assert_eq!(config_path(&state), Some(PathBuf::from("/etc/rush.toml")));
+    }
+
+    #[test]
+    fn binds_keys() {
+        let mut state = ShellState::default();
+        let errors = apply(
+            "# my bindings\n[keys]\n\"ctrl-w\" = \"clear-screen\"\nf5 = complete # refresh\n",
+            &mut state,
+        );
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            state.keymap.lookup(&Chord::ctrl('w')),
+            Some(Action::ClearScreen)
+        );
+        assert_eq!(
+            state.keymap.lookup(&Chord::new(Key::Function(5))),
+            Some(Action::Complete)
+        );
+    }
+
+    #[test]
+    fn later_bindings_win() {
+        let mut state = ShellState::default();
+        let errors = apply("[keys]\nf5 = clear-screen\nf5 = accept-line\n", &mut state);
+        assert!(errors.is_empty());
+        assert_eq!(
+            state.keymap.lookup(&Chord::new(Key::Function(5))),
+            Some(Action::AcceptLine)
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        let mut state = ShellState::default();
+        let errors = apply(
+            "[keys]\nctrl-w = explode\nhyper-q = complete\nf2 = complete\njunk\n",
+            &mut state,
+        );
+        assert_eq!(
+            errors,
+            vec![
+                ConfigError {
+                    line: 2,
+                    msg: "explode: unknown action".into()
+                },
+                ConfigError {
+                    line: 3,
+                    msg: "hyper-q: invalid key chord".into()
+                },
+                ConfigError {
+                    line: 5,
+                    msg: "expected `key = value`, found `junk`".into()
+                },
+            ]
+        );
+        // Valid lines still apply
+        assert_eq!(
+            state.keymap.lookup(&Chord::new(Key::Function(2))),
+            Some(Action::Complete)
+        );
+        assert_eq!(
+            state.keymap.lookup(&Chord::ctrl('w')),
+            Some(Action::BackwardKillWord)
+        );
+    }
+
+    #[test]
+    fn unknown_sections_and_stray_keys() {
+        let mut state = ShellState::default();
+        let errors = apply("f1 = complete\n[colors]\nprompt = red\n", &mut state);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].to_string(),
+            "line 1: `f1` is not inside a section"
+        );
+        assert_eq!(errors[1].to_string(), "line 2: unknown section `colors`");
+    }
+
+    #[test]
+    fn sets_hooks() {
+        let mut state = ShellState::default();
+        let errors = apply(
+            "[hooks]
+command_not_found = \"/usr/lib/command-not-found\"
+prompt = x
+",
+            &mut state,
+        );
+        assert_eq!(
+            state.hooks.command_not_found.as_deref(),
+            Some("/usr/lib/command-not-found")
+        );
+        assert_eq!(
+            errors,
+            vec![ConfigError {
+                line: 3,
+                msg: "unknown hook `prompt`".into()
+            }]
+        );
+        apply(
+            "[hooks]
+command_not_found = \"\"
+",
+            &mut state,
+        );
+        assert_eq!(state.hooks.command_not_found, None);
+    }
+
+    #[test]
+    fn sets_limits() {
+        let mut state = ShellState::default();
+        let errors = apply(
+            "[limits]\ncommand_substitution_bytes = 4096\nwidgets = 3\ncommand_substitution_bytes = -1\n",
+            &mut state,
+        );
+        assert_eq!(state.limits.substitution_bytes, 4096);
+        assert_eq!(
+            errors,
+            vec![
+                ConfigError {
+                    line: 3,
+                    msg: "unknown limit `widgets`".into()
+                },
+                ConfigError {
+                    line: 4,
+                    msg: "`-1` is not a positive number of bytes".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn sets_options() {
+        let mut state = ShellState::default();
+        let errors = apply(
+            "[options]\nglobstar = true\nnullglob = true\nglobstar = yes\n",
+            &mut state,
+        );
+        assert!(state.options.globstar);
+        assert_eq!(
+            errors,
+            vec![
+                ConfigError {
+                    line: 3,
+                    msg: "unknown option `nullglob`".into()
+                },
+                ConfigError {
+                    line: 4,
+                    msg: "`yes` is not `true` or `false`".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn hash_inside_quotes_is_kept() {
+        assert_eq!(strip_comment("a = \"#\" # c"), "a = \"#\" ");
+    }
+}
@@ -0,0 +1,269 @@
+use crate::{
+    state::ShellState,
+    style::{Style, display_width},
+    util::{RushError, SyntaxError, SyntaxErrorKind},
+};
+
+/// How much help to attach when reporting an error to the user.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct DiagnosticMode {
+    pub interactive: bool,
+    pub posix: bool,
+}
+
+impl DiagnosticMode {
+    /// Detects the mode of the running shell: interactive when `state` is
+    /// prompting for commands, and posix when its `POSIXLY_CORRECT` is set.
+    pub(crate) fn detect(state: &ShellState) -> Self {
+        Self {
+            interactive: state.interactive,
+            posix: state.vars.get("POSIXLY_CORRECT").is_some(),
+        }
+    }
+
+    fn hints_enabled(&self) -> bool {
+        self.interactive && !self.posix
+    }
+}
+
+/// Prints `error` to stderr the way the interactive loop reports failures.
+pub(crate) fn report(error: RushError, state: &ShellState) {
+    match error {
+        RushError::Nop
+        | RushError::Reported(_)
+        | RushError::Exit(_)
+        | RushError::Return(_)
+        | RushError::Break(_)
+        | RushError::Continue(_)
+        | RushError::Overflowed
+        | RushError::BrokenPipe => {}
+        #[cfg(unix)]
+        RushError::Stopped(_) => {}
+        RushError::Syntax(error) => eprint!(
+            "{}",
+            render_syntax_error(&error, DiagnosticMode::detect(state), Style::stderr())
+        ),
+        error => eprintln!("{error}"),
+    }
+}
+
+/// One-line suggestions for the most common syntax errors.
+fn hint(error: &SyntaxError) -> Option<String> {
+    match &error.kind {
+        SyntaxErrorKind::UnterminatedQuote => {
+            // The span starts at the quote that was left open
+            let quote = (error.line.get(error.span.start..))
+                .and_then(|rest| rest.chars().next())
+                .unwrap_or('\'');
+            Some(format!("add a closing {quote} or escape it"))
+        }
+        SyntaxErrorKind::UnterminatedSubstitution => {
+            Some("add a closing ) to end the substitution".into())
+        }
+        SyntaxErrorKind::UnexpectedToken(token) | SyntaxErrorKind::UnfinishedCommand(token)
+            if token == "|" =>
+        {
+            Some("pipes need a command on both sides".into())
+        }
+        SyntaxErrorKind::BadSubstitution(_) => {
+            Some("variable names are letters, digits, and underscores".into())
+        }
+        SyntaxErrorKind::UnterminatedHereDoc(delimiter) => Some(format!(
+            "end the here-document with a line holding just {delimiter}"
+        )),
+        SyntaxErrorKind::UnterminatedGroup => Some("close the group with `; }`".into()),
+        SyntaxErrorKind::UnterminatedCompound(wanted) => {
+            Some(format!("add `{wanted}` to finish it"))
+        }
+        SyntaxErrorKind::UnfinishedCommand(token) => Some(format!("add a command after {token}")),
+        SyntaxErrorKind::UnexpectedToken(_) | SyntaxErrorKind::NestedTooDeeply => None,
+    }
+}
+
+/// Renders `error` as the offending line with the failing span underlined,
+/// followed by the error message and, in interactive mode, a hint. Of input
+/// spanning several lines, only the one the span starts on is shown.
+pub(crate) fn render_syntax_error(
+    error: &SyntaxError,
+    mode: DiagnosticMode,
+    style: Style,
+) -> String {
+    let input = &error.line;
+    // A span that doesn't fit the input is trimmed to the characters it does
+    // cover, rather than cutting one in half
+    let start = input.floor_char_boundary(error.span.start);
+    let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = input[start..].find('\n').map_or(input.len(), |i| start + i);
+    let line = &input[line_start..line_end];
+    let end = input.ceil_char_boundary(error.span.end.clamp(start, line_end));
+
+    // Columns are measured in display width so the caret lines up under wide
+    // and combining characters
+    let column = display_width(&input[line_start..start]);
+    let width = display_width(&input[start..end]).max(1);
+    let underline = format!("^{}", "~".repeat(width - 1));
+
+    let mut rendered = format!(
+        "{line}\n{}{}\n{} {}\n",
+        " ".repeat(column),
+        style.red(&underline),
+        style.red("error:"),
+        style.bold(&error.kind.to_string()),
+    );
+
+    if mode.hints_enabled()
+        && let Some(hint) = hint(error)
+    {
+        rendered.push_str(&format!("{} {hint}\n", style.cyan("hint:")));
+    }
+
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    const INTERACTIVE: DiagnosticMode = DiagnosticMode {
+        interactive: true,
+        posix: false,
+    };
+
+    fn syntax_error(input: &str) -> SyntaxError {
+        match parser::parse(input) {
+            Err(RushError::Syntax(error)) => error,
+            other => panic!("Expected a syntax error, got {other:?}"),
+        }
+    }
+
+    fn caret_column(rendered: &str) -> usize {
+        let underline = rendered.lines().nth(1).unwrap();
+        underline.find('^').unwrap()
+    }
+
+    #[test]
+    fn caret_under_unterminated_quote() {
+        let rendered =
+            render_syntax_error(&syntax_error("echo 'hello"), INTERACTIVE, Style::plain());
+        assert_eq!(
+            rendered,
+            "echo 'hello\n     ^~~~~~\nerror: unterminated quote\nhint: add a closing ' or escape it\n"
+        );
+    }
+
+    #[test]
+    fn caret_after_multibyte_prefix() {
+        // "日本" is 6 bytes but 4 columns wide
+        let rendered =
+            render_syntax_error(&syntax_error("echo 日本 'abc"), INTERACTIVE, Style::plain());
+        assert_eq!(caret_column(&rendered), 10);
+        assert!(rendered.lines().nth(1).unwrap().ends_with("^~~~"));
+    }
+
+    #[test]
+    fn span_inside_a_character_covers_all_of_it() {
+        let error = SyntaxError {
+            kind: SyntaxErrorKind::UnterminatedQuote,
+            line: "echo 日本".into(),
+            span: 6..7,
+        };
+        let rendered = render_syntax_error(&error, INTERACTIVE, Style::plain());
+        assert_eq!(caret_column(&rendered), 5);
+        assert!(rendered.lines().nth(1).unwrap().ends_with(" ^~"));
+    }
+
+    #[test]
+    fn caret_under_the_pipe_at_fault_after_a_multibyte_prefix() {
+        let Err(RushError::Syntax(error)) = crate::parser::parse("héé | |") else {
+            panic!("Expected a syntax error");
+        };
+        let rendered = render_syntax_error(&error, INTERACTIVE, Style::plain());
+        // "héé" is 5 bytes but 3 columns wide, and the second pipe is the
+        // one without a command before it
+        assert_eq!(caret_column(&rendered), 6);
+        assert!(rendered.starts_with("héé | |\n      ^\n"));
+    }
+
+    #[test]
+    fn caret_after_combining_characters() {
+        let rendered = render_syntax_error(
+            &syntax_error("echo e\u{301}e\u{301} |"),
+            INTERACTIVE,
+            Style::plain(),
+        );
+        assert_eq!(caret_column(&rendered), 8);
+    }
+
+    #[test]
+    fn stray_pipe_hint() {
+        let rendered = render_syntax_error(&syntax_error("| wc"), INTERACTIVE, Style::plain());
+        assert!(rendered.starts_with("| wc\n^\n"));
+        assert!(rendered.contains("hint: pipes need a command on both sides"));
+    }
+
+    #[test]
+    fn only_the_line_with_the_error_is_shown() {
+        let Err(RushError::Syntax(error)) = crate::parser::parse("echo a\necho b;;\necho c\n")
+        else {
+            panic!("Expected a syntax error");
+        };
+        let rendered = render_syntax_error(&error, INTERACTIVE, Style::plain());
+        assert_eq!(
+            rendered,
+            "echo b;;\n      ^~\nerror: syntax error near unexpected token `;;'\n"
+        );
+
+        let rendered = render_syntax_error(
+            &syntax_error("echo a\necho 'b\nc"),
+            INTERACTIVE,
+            Style::plain(),
+        );
+        assert!(rendered.starts_with("echo 'b\n     ^~\n"));
+    }
+
+    #[test]
+    fn hints_suppressed_when_not_interactive() {
+        let mode = DiagnosticMode {
+            interactive: false,
+            posix: false,
+        };
+        let rendered = render_syntax_error(&syntax_error("echo 'hello"), mode, Style::plain());
+        assert!(rendered.contains("error: unterminated quote"));
+        assert!(!rendered.contains("hint:"));
+    }
+
+    #[test]
+    fn hints_suppressed_in_posix_mode() {
+        let mode = DiagnosticMode {
+            interactive: true,
+            posix: true,
+        };
+        let rendered = render_syntax_error(&syntax_error("ls |"), mode, Style::plain());
+        assert!(rendered.contains("^"));
+        assert!(!rendered.contains("hint:"));
+    }
+
+    #[test]
+    fn posix_mode_follows_the_shells_own_variable() {
+        let mut state = ShellState {
+            interactive: true,
+            ..ShellState::default()
+        };
+        state.vars.unset("POSIXLY_CORRECT");
+        assert_eq!(DiagnosticMode::detect(&state), INTERACTIVE);
+        state.vars.set("POSIXLY_CORRECT", "1");
+        assert!(DiagnosticMode::detect(&state).posix);
+    }
+
+    #[test]
+    fn unknown_token_has_no_hint() {
+        let error = SyntaxError {
+            kind: SyntaxErrorKind::UnexpectedToken(";".into()),
+            line: "; ls".into(),
+            span: 0..1,
+        };
+        let rendered = render_syntax_error(&error, INTERACTIVE, Style::plain());
+        assert!(!rendered.contains("hint:"));
+    }
+}
@@ -0,0 +1,272 @@
+/// The line being edited, with the cursor as a byte index into it that is
+/// always on a character boundary.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct LineBuffer {
+    text: String,
+    cursor: usize,
+}
+
+impl LineBuffer {
+    pub(crate) fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub(crate) fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub(crate) fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    pub(crate) fn insert_str(&mut self, text: &str) {
+        self.text.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    /// Replaces the text from `start` up to the cursor with `text`, leaving
+    /// the cursor after it.
+    pub(crate) fn replace_before_cursor(&mut self, start: usize, text: &str) {
+        self.text.replace_range(start..self.cursor, text);
+        self.cursor = start + text.len();
+    }
+
+    /// Replaces the whole line with `text`, with the cursor at the byte
+    /// index `cursor`, or the nearest character boundary before it.
+    pub(crate) fn replace(&mut self, text: String, cursor: usize) {
+        let mut cursor = cursor.min(text.len());
+        while !text.is_char_boundary(cursor) {
+            cursor -= 1;
+        }
+        self.text = text;
+        self.cursor = cursor;
+    }
+
+    pub(crate) fn move_left(&mut self) {
+        self.cursor = self.previous_boundary();
+    }
+
+    pub(crate) fn move_right(&mut self) {
+        self.cursor = self.next_boundary();
+    }
+
+    pub(crate) fn move_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub(crate) fn move_end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    /// Deletes the character before the cursor, as Backspace does.
+    pub(crate) fn delete_backward(&mut self) {
+        let start = self.previous_boundary();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Deletes the character under the cursor.
+    pub(crate) fn delete_forward(&mut self) {
+        let end = self.next_boundary();
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    /// Removes the text from the cursor to the end of the line, as Ctrl-K
+    /// does, returning it.
+    pub(crate) fn kill_to_end(&mut self) -> String {
+        self.text.split_off(self.cursor)
+    }
+
+    /// Removes the text from the start of the line to the cursor, as Ctrl-U
+    /// does, returning it.
+    pub(crate) fn kill_to_start(&mut self) -> String {
+        let killed = self.text.drain(..self.cursor).collect();
+        self.cursor = 0;
+        killed
+    }
+
+    /// Removes the word before the cursor along with the whitespace after
+    /// it, as Ctrl-W does, returning it.
+    pub(crate) fn kill_word_backward(&mut self) -> String {
+        let before = self.text[..self.cursor].trim_end();
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| {
+            i + before[i..].chars().next().map_or(1, char::len_utf8)
+        });
+        let killed = self.text.drain(start..self.cursor).collect();
+        self.cursor = start;
+        killed
+    }
+
+    /// Moves back to the start of the word before the cursor, as Alt-B
+    /// does. Words are letters and digits, as readline's are.
+    pub(crate) fn move_word_left(&mut self) {
+        self.cursor = self.word_start();
+    }
+
+    /// Moves on to the end of the word after the cursor, as Alt-F does.
+    pub(crate) fn move_word_right(&mut self) {
+        self.cursor = self.word_end();
+    }
+
+    /// Removes the text from the cursor to the end of the word after it, as
+    /// Alt-D does, returning it.
+    pub(crate) fn kill_word_forward(&mut self) -> String {
+        let end = self.word_end();
+        self.text.drain(self.cursor..end).collect()
+    }
+
+    fn word_start(&self) -> usize {
+        let before = &self.text[..self.cursor];
+        let word_end = before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_alphanumeric())
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        before[..word_end]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !c.is_alphanumeric())
+            .map_or(0, |(i, c)| i + c.len_utf8())
+    }
+
+    fn word_end(&self) -> usize {
+        let after = &self.text[self.cursor..];
+        let word_start = after
+            .char_indices()
+            .find(|(_, c)| c.is_alphanumeric())
+            .map_or(after.len(), |(i, _)| i);
+        let len = after[word_start..]
+            .char_indices()
+            .find(|(_, c)| !c.is_alphanumeric())
+            .map_or(after.len(), |(i, _)| word_start + i);
+        self.cursor + len
+    }
+
+    fn previous_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .chars()
+            .next()
+            .map_or(self.cursor, |c| self.cursor + c.len_utf8())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> LineBuffer {
+        let mut buffer = LineBuffer::default();
+        buffer.insert_str(text);
+        buffer
+    }
+
+    #[test]
+    fn inserts_at_the_cursor() {
+        let mut line = buffer("ech");
+        line.insert('o');
+        line.move_home();
+        line.insert_str("  ");
+        assert_eq!(line.as_str(), "  echo");
+        assert_eq!(line.cursor(), 2);
+    }
+
+    #[test]
+    fn moves_by_characters() {
+        let mut line = buffer("héllo");
+        line.move_home();
+        line.move_right();
+        line.move_right();
+        assert_eq!(line.cursor(), 3);
+        line.move_left();
+        assert_eq!(line.cursor(), 1);
+        line.move_left();
+        line.move_left();
+        assert_eq!(line.cursor(), 0);
+        line.move_end();
+        line.move_right();
+        assert_eq!(line.cursor(), 6);
+    }
+
+    #[test]
+    fn deletes_characters() {
+        let mut line = buffer("añb");
+        line.delete_backward();
+        assert_eq!(line.as_str(), "añ");
+        line.move_home();
+        line.delete_backward();
+        line.delete_forward();
+        assert_eq!(line.as_str(), "ñ");
+        line.move_end();
+        line.delete_forward();
+        assert_eq!(line.as_str(), "ñ");
+    }
+
+    #[test]
+    fn kills_text() {
+        let mut line = buffer("git commit -m msg");
+        for _ in 0..4 {
+            line.move_left();
+        }
+        assert_eq!(line.kill_to_end(), " msg");
+        assert_eq!(line.kill_word_backward(), "-m");
+        assert_eq!(line.kill_word_backward(), "commit ");
+        assert_eq!(line.as_str(), "git ");
+        assert_eq!(line.kill_to_start(), "git ");
+        assert!(line.is_empty());
+    }
+
+    #[test]
+    fn moves_and_kills_by_words() {
+        let mut line = buffer("git  commit --amend é1");
+        line.move_word_left();
+        assert_eq!(line.cursor(), 20);
+        line.move_word_left();
+        line.move_word_left();
+        assert_eq!(line.cursor(), 5);
+        line.move_word_left();
+        line.move_word_left();
+        assert_eq!(line.cursor(), 0);
+
+        line.move_word_right();
+        assert_eq!(line.cursor(), 3);
+        assert_eq!(line.kill_word_forward(), "  commit");
+        assert_eq!(line.kill_word_forward(), " --amend");
+        line.move_word_right();
+        line.move_word_right();
+        assert_eq!(line.cursor(), line.as_str().len());
+        assert_eq!(line.kill_word_forward(), "");
+        assert_eq!(line.as_str(), "git é1");
+    }
+
+    #[test]
+    fn replaces_the_whole_line() {
+        let mut line = buffer("old");
+        line.replace("né".into(), 2);
+        assert_eq!((line.as_str(), line.cursor()), ("né", 1));
+        line.replace("new".into(), 99);
+        assert_eq!(line.cursor(), 3);
+    }
+
+    #[test]
+    fn replaces_the_word_being_completed() {
+        let mut line = buffer("cat sr other");
+        for _ in 0.."other".len() + 1 {
+            line.move_left();
+        }
+        line.replace_before_cursor(4, "src/");
+        assert_eq!(line.as_str(), "cat src/ other");
+        assert_eq!(line.cursor(), 8);
+    }
+}
@@ -0,0 +1,332 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use crate::{
+    command::{BUILTINS, path::Search, redirect::Streams},
+    expand::expand_words,
+    functions,
+    parser::{self, CommandNode},
+    state::ShellState,
+};
+
+/// Where `complete` said the arguments of a command come from.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct CompletionSpec {
+    /// The `-W` word list, expanded each time it's used.
+    pub words: Option<String>,
+    /// The `-F` function, which leaves its candidates in `COMPREPLY`.
+    pub function: Option<String>,
+}
+
+/// The completion specifications registered with `complete`, by command
+/// name.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Completions {
+    specs: BTreeMap<String, CompletionSpec>,
+}
+
+impl Completions {
+    pub(crate) fn get(&self, name: &str) -> Option<&CompletionSpec> {
+        self.specs.get(name)
+    }
+
+    pub(crate) fn set(&mut self, name: &str, spec: CompletionSpec) {
+        self.specs.insert(name.to_owned(), spec);
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) -> bool {
+        self.specs.remove(name).is_some()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.specs.clear();
+    }
+
+    /// Every specification, sorted by command name.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &CompletionSpec)> {
+        self.specs.iter().map(|(name, spec)| (name.as_str(), spec))
+    }
+}
+
+/// Completes the word of `line` that ends at the byte index `cursor`,
+/// returning where that word starts and the sorted candidates to replace it
+/// with.
+///
+/// A command name is completed from the builtins, functions, aliases, and
+/// `PATH`. An argument comes from the command's specification if
+/// `complete` gave it one. Otherwise it's a directory for `cd` and
+/// `pushd`, and a filename for anything else.
+pub(crate) fn complete(line: &str, cursor: usize, state: &mut ShellState) -> (usize, Vec<String>) {
+    let before = &line[..cursor];
+    let start = before.rfind(char::is_whitespace).map_or(0, |i| {
+        i + before[i..].chars().next().map_or(1, char::len_utf8)
+    });
+    let current = &before[start..];
+
+    // The words of the command the cursor is in, up to the current one
+    let command_start = before[..start].rfind([';', '|', '&']).map_or(0, |i| i + 1);
+    let words: Vec<&str> = before[command_start..start].split_whitespace().collect();
+
+    let mut candidates = match words.first() {
+        None if current.contains('/') => filenames(current, state),
+        None => commands(current, state),
+        Some(command) => match state.completions.get(command).cloned() {
+            Some(spec) => {
+                let previous = words.last().copied().unwrap_or_default();
+                from_spec(&spec, command, current, previous, line, cursor, state)
+            }
+            None if matches!(*command, "cd" | "pushd") => directories(current, state),
+            None => filenames(current, state),
+        },
+    };
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
+
+/// The candidates a specification gives for the word `current` of a
+/// `command` line.
+fn from_spec(
+    spec: &CompletionSpec,
+    command: &str,
+    current: &str,
+    previous: &str,
+    line: &str,
+    cursor: usize,
+    state: &mut ShellState,
+) -> Vec<String> {
+    let mut candidates = Vec::new();
+    if let Some(words) = &spec.words {
+        candidates.extend(
+            word_list(words, state)
+                .into_iter()
+                .filter(|word| word.starts_with(current)),
+        );
+    }
+    if let Some(name) = &spec.function
+        && let Some(function) = state.functions.get(name)
+    {
+        let words: Vec<String> = line.split_whitespace().map(str::to_owned).collect();
+        let cword = line[..cursor].split_whitespace().count() - usize::from(!current.is_empty());
+        let items = |values: Vec<String>| values.into_iter().map(|v| (None, v)).collect();
+        state.vars.assign_array("COMP_WORDS", items(words)).ok();
+        state.vars.set("COMP_CWORD", &cword.to_string());
+        state.vars.set("COMP_LINE", line);
+        state.vars.set("COMP_POINT", &cursor.to_string());
+        state.vars.unset("COMPREPLY");
+
+        // The candidates are whatever the function leaves, even if it fails
+        let args = [name, command, current, previous].map(str::to_owned);
+        functions::call(
+            &function,
+            &args,
+            state,
+            &Streams::default(),
+            &mut io::sink(),
+        )
+        .ok();
+        candidates.extend(state.vars.values("COMPREPLY"));
+    }
+    candidates
+}
+
+/// The words of a `-W` list once they're expanded, as a command's
+/// arguments would be.
+fn word_list(words: &str, state: &mut ShellState) -> Vec<String> {
+    let Ok(lists) = parser::parse(&format!(": {words}")) else {
+        return Vec::new();
+    };
+    let Some(CommandNode::Simple(command)) = lists
+        .first()
+        .and_then(|list| list.pipelines.first())
+        .and_then(|(_, pipeline)| pipeline.commands.first())
+    else {
+        return Vec::new();
+    };
+    expand_words(&command.words[1..], state, &Streams::default()).unwrap_or_default()
+}
+
+/// The builtins, functions, aliases, and programs on `PATH` whose names
+/// start with `prefix`.
+fn commands(prefix: &str, state: &ShellState) -> Vec<String> {
+    let mut names: Vec<String> = BUILTINS
+        .iter()
+        .map(|(name, _)| (*name).to_owned())
+        .collect();
+    names.extend(state.functions.iter().map(|function| function.name.clone()));
+    names.extend(state.aliases.iter().map(|(name, _)| name.to_owned()));
+    let search = Search::new(state);
+    for dir in search.dirs() {
+        let Ok(entries) = fs::read_dir(search.resolve(dir)) else {
+            continue;
+        };
+        names.extend(
+            entries
+                .flatten()
+                .filter(|entry| search.is_program(&entry.path()) && !entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned()),
+        );
+    }
+    names.retain(|name| name.starts_with(prefix));
+    names
+}
+
+/// The paths that start with `prefix`, with a `/` after each directory.
+/// Hidden files are left out unless `prefix` names them with a `.`.
+fn filenames(prefix: &str, state: &ShellState) -> Vec<String> {
+    paths_in(state.cwd.path(), prefix, false)
+}
+
+/// The directories that start with `prefix`, as `cd` would find them: from
+/// the current directory, or from one on `CDPATH` for a relative name.
+fn directories(prefix: &str, state: &ShellState) -> Vec<String> {
+    let mut dirs = paths_in(state.cwd.path(), prefix, true);
+    if !prefix.starts_with(['/', '.'])
+        && let Some(cdpath) = state.vars.get("CDPATH")
+    {
+        for base in cdpath.split(':').filter(|base| !base.is_empty()) {
+            dirs.extend(paths_in(&state.cwd.resolve(base), prefix, true));
+        }
+    }
+    dirs
+}
+
+/// The paths under `base` that start with `prefix`, written relative to
+/// it, with a `/` after each directory. Only directories are kept with
+/// `dirs_only`.
+fn paths_in(base: &Path, prefix: &str, dirs_only: bool) -> Vec<String> {
+    let (dir, name) = match prefix.rfind('/') {
+        Some(slash) => prefix.split_at(slash + 1),
+        None => ("", prefix),
+    };
+    let Ok(entries) = fs::read_dir(base.join(if dir.is_empty() { "." } else { dir })) else {
+        return Vec::new();
+    };
+
+    let mut paths = Vec::new();
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if !file_name.starts_with(name) || (file_name.starts_with('.') && !name.starts_with('.')) {
+            continue;
+        }
+        let mut path = format!("{dir}{file_name}");
+        match base.join(&path).is_dir() {
+            true => path.push('/'),
+            false if dirs_only => continue,
+            false => {}
+        }
+        paths.push(path);
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::eval::eval;
+
+    #[test]
+    fn command_names() {
+        let mut state = ShellState::default();
+        eval("rush_complete_test_fn() { :; }", &mut state).unwrap();
+        eval("alias rush_complete_test_alias=ls", &mut state).unwrap();
+
+        let (start, candidates) = complete("ech", 3, &mut state);
+        assert_eq!(start, 0);
+        assert!(candidates.contains(&"echo".to_owned()));
+
+        let (start, candidates) = complete("true; rush_complete_te", 22, &mut state);
+        assert_eq!(start, 6);
+        assert_eq!(
+            candidates,
+            ["rush_complete_test_alias", "rush_complete_test_fn"]
+        );
+    }
+
+    #[test]
+    fn word_lists() {
+        let mut state = ShellState::default();
+        state.vars.set("RUSH_COMPLETE_TEST_EXTRA", "stash");
+        state.completions.set(
+            "git",
+            CompletionSpec {
+                words: Some("status switch \"$RUSH_COMPLETE_TEST_EXTRA\" add".into()),
+                function: None,
+            },
+        );
+        let line = "git -C dir s";
+        let (start, candidates) = complete(line, line.len(), &mut state);
+        assert_eq!(start, 11);
+        assert_eq!(candidates, ["stash", "status", "switch"]);
+    }
+
+    #[test]
+    fn functions_fill_compreply() {
+        let mut state = ShellState::default();
+        eval(
+            "rush_complete_test() { \
+                 COMPREPLY=(\"$1-$2-$3\" \"$COMP_CWORD\" \"${COMP_WORDS[1]}\" \"$COMP_POINT\"); \
+             }",
+            &mut state,
+        )
+        .unwrap();
+        state.completions.set(
+            "tool",
+            CompletionSpec {
+                words: None,
+                function: Some("rush_complete_test".into()),
+            },
+        );
+        let line = "tool run fa";
+        let (_, candidates) = complete(line, line.len(), &mut state);
+        assert_eq!(candidates, ["11", "2", "run", "tool-fa-run"]);
+
+        // An empty word after a space is the next one
+        let (_, candidates) = complete("tool ", 5, &mut state);
+        assert!(candidates.contains(&"1".to_owned()));
+    }
+
+    #[test]
+    fn filenames_mark_directories() {
+        let dir = env::temp_dir().join(format!("rush-complete-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("subdir")).unwrap();
+        fs::write(dir.join("sub.txt"), "").unwrap();
+        fs::write(dir.join(".subhidden"), "").unwrap();
+
+        let mut state = ShellState::default();
+        let line = format!("cat {}/su", dir.display());
+        let (start, candidates) = complete(&line, line.len(), &mut state);
+        assert_eq!(start, 4);
+        assert_eq!(
+            candidates,
+            [
+                format!("{}/sub.txt", dir.display()),
+                format!("{}/subdir/", dir.display())
+            ]
+        );
+
+        // Relative names are completed from the shell's directory
+        state.cwd.set(dir.clone());
+        let (_, candidates) = complete("cat su", 6, &mut state);
+        assert_eq!(candidates, ["sub.txt", "subdir/"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cd_completes_directories() {
+        let dir = env::temp_dir().join(format!("rush-complete-cd-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("rush_cdpath_dir")).unwrap();
+        fs::write(dir.join("rush_cdpath_file"), "").unwrap();
+
+        let mut state = ShellState::default();
+        let line = format!("cd {}/rush_cdpath_", dir.display());
+        let (_, candidates) = complete(&line, line.len(), &mut state);
+        assert_eq!(candidates, [format!("{}/rush_cdpath_dir/", dir.display())]);
+
+        state.vars.set("CDPATH", &dir.display().to_string());
+        let (_, candidates) = complete("pushd rush_cdpath_", 18, &mut state);
+        assert_eq!(candidates, ["rush_cdpath_dir/"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
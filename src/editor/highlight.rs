@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+
+use crate::{
+    command::path::{Search, find_in_path, is_builtin, is_path},
+    lexer::{Lexer, Token, TokenKind},
+    state::ShellState,
+    style::Style,
+    util::{WordPart, is_name},
+};
+
+/// Colors the line being edited as it's typed. The line is only lexed again
+/// once its text changes, not when the cursor moves. Whether a name is found
+/// on `PATH` is remembered until `PATH` changes, and a name still being
+/// typed isn't searched for at all, so typing one doesn't search `PATH` for
+/// each of its prefixes.
+#[derive(Debug, Default)]
+pub(crate) struct Highlighter {
+    /// The last line colored, and how it was colored.
+    last: Option<(String, String)>,
+    /// Whether each name searched for on `PATH` was found there.
+    found: HashMap<String, bool>,
+    /// Where `found` was searched.
+    searched: Option<Search>,
+}
+
+impl Highlighter {
+    /// `line` with color sequences added.
+    pub(crate) fn highlight(&mut self, line: &str, state: &ShellState, style: Style) -> &str {
+        if self.last.as_ref().is_none_or(|(text, _)| text != line) {
+            let search = Search::new(state);
+            if self.searched.as_ref() != Some(&search) {
+                self.found.clear();
+                self.searched = Some(search.clone());
+            }
+            let found = &mut self.found;
+            let colored = colorize(line, style, |name, typing| {
+                resolves(name, state, &search, found, typing)
+            });
+            self.last = Some((line.to_owned(), colored));
+        }
+        let (_, colored) = self.last.as_ref().expect("colored just above");
+        colored
+    }
+
+    /// Forgets the last line colored, for a new one to be read. What was
+    /// found on `PATH` is kept, as the shell's own hash table keeps it.
+    pub(crate) fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Colors `line`: each command's name green if `runnable` says it names
+/// something to run and red if not, quoted strings yellow, operators and
+/// redirections cyan, and comments gray. A quote that isn't closed yet
+/// colors the rest of the line. `runnable` is also told whether the name is
+/// still being typed, at the end of the line, and a name it can't say yet
+/// is left plain.
+fn colorize(
+    line: &str,
+    style: Style,
+    mut runnable: impl FnMut(&str, bool) -> Option<bool>,
+) -> String {
+    let mut lexer = Lexer::at(line, 0);
+    let mut tokens = Vec::new();
+    // Lexing stops early at a syntax error, such as a quote that's still open
+    let end = loop {
+        match lexer.next_token() {
+            Ok(Some(token)) => tokens.push(token),
+            Ok(None) => break line.len(),
+            Err(_) => break tokens.last().map_or(0, |token: &Token| token.span.end),
+        }
+    };
+
+    let mut out = String::new();
+    let mut written = 0;
+
+    let mut command_position = true;
+    let mut after_redirect = false;
+    let mut i = 0;
+    while i < tokens.len() {
+        gap(&mut out, &line[written..tokens[i].span.start], style);
+        let token = &tokens[i];
+        match &token.kind {
+            TokenKind::Text(_) | TokenKind::Quoted(_) => {
+                // Text and quotes written next to each other are one word
+                let mut last = i;
+                while tokens
+                    .get(last + 1)
+                    .is_some_and(|next| is_word(next) && next.span.start == tokens[last].span.end)
+                {
+                    last += 1;
+                }
+                let word = &tokens[i..=last];
+                let span = word[0].span.start..word[last - i].span.end;
+                let text = &line[span.clone()];
+                match command_position && !after_redirect {
+                    true if is_reserved(text) => out.push_str(&style.cyan(text)),
+                    // What follows `case` is a word to match, and what follows
+                    // `select` a name, not a command
+                    true if matches!(text, "case" | "select") => {
+                        command_position = false;
+                        out.push_str(&style.cyan(text));
+                    }
+                    true if is_assignment(word) => out.push_str(&words(line, word, style)),
+                    true => {
+                        command_position = false;
+                        let typing = span.end == line.len();
+                        match literal(word).and_then(|name| runnable(&name, typing)) {
+                            Some(true) => out.push_str(&style.green(text)),
+                            Some(false) => out.push_str(&style.red(text)),
+                            None => out.push_str(&words(line, word, style)),
+                        }
+                    }
+                    false => out.push_str(&words(line, word, style)),
+                }
+                after_redirect = false;
+                written = span.end;
+                i = last + 1;
+                continue;
+            }
+            TokenKind::Blank => out.push_str(&line[token.span.clone()]),
+            TokenKind::Redirect { .. } => {
+                after_redirect = true;
+                out.push_str(&style.cyan(&line[token.span.clone()]));
+            }
+            TokenKind::Operator(_) | TokenKind::Newline => {
+                command_position = true;
+                after_redirect = false;
+                out.push_str(&style.cyan(&line[token.span.clone()]));
+            }
+        }
+        written = token.span.end;
+        i += 1;
+    }
+    gap(&mut out, &line[written..end], style);
+    if end < line.len() {
+        out.push_str(&style.yellow(&line[end..]));
+    }
+    out
+}
+
+/// Text the lexer skipped over between tokens, which is a comment if it's
+/// anything.
+fn gap(out: &mut String, text: &str, style: Style) {
+    match text.starts_with('#') {
+        true => out.push_str(&style.gray(text)),
+        false => out.push_str(text),
+    }
+}
+
+/// Whether `text` is a reserved word, like `{` or `then`, after which a
+/// command name is still to come.
+fn is_reserved(text: &str) -> bool {
+    matches!(
+        text,
+        "{" | "}"
+            | "!"
+            | "if"
+            | "then"
+            | "elif"
+            | "else"
+            | "fi"
+            | "esac"
+            | "while"
+            | "until"
+            | "do"
+            | "done"
+    )
+}
+
+fn is_word(token: &Token) -> bool {
+    matches!(token.kind, TokenKind::Text(_) | TokenKind::Quoted(_))
+}
+
+/// The tokens of a word that isn't a command name, with its quoted strings
+/// colored.
+fn words(line: &str, word: &[Token], style: Style) -> String {
+    word.iter()
+        .map(|token| {
+            let text = &line[token.span.clone()];
+            match token.kind {
+                TokenKind::Quoted(_) => style.yellow(text),
+                _ => text.to_owned(),
+            }
+        })
+        .collect()
+}
+
+/// Whether the word is a `NAME=value` assignment, which leaves the command
+/// name still to come.
+fn is_assignment(word: &[Token]) -> bool {
+    let Some(Token {
+        kind: TokenKind::Text(text),
+        ..
+    }) = word.first()
+    else {
+        return false;
+    };
+    match text.parts().first() {
+        Some(WordPart::Literal(text)) => text
+            .split_once('=')
+            .is_some_and(|(name, _)| is_name(name.split('[').next().unwrap_or(name))),
+        _ => false,
+    }
+}
+
+/// The word's text with its quotes removed, unless it has an expansion in
+/// it, which makes the command it names unknown until it runs.
+fn literal(word: &[Token]) -> Option<String> {
+    let mut name = String::new();
+    for token in word {
+        let (TokenKind::Text(text) | TokenKind::Quoted(text)) = &token.kind else {
+            return None;
+        };
+        for part in text.parts() {
+            match part {
+                WordPart::Literal(text) | WordPart::Quoted(text) => name.push_str(text),
+                _ => return None,
+            }
+        }
+    }
+    Some(name)
+}
+
+/// Whether `name` is a builtin, function, or alias, or a program that can
+/// be run from `PATH` or from where it says. `PATH` is only searched for a
+/// name the shell hasn't hashed and that isn't in `found` already, and not
+/// while it's still being `typing`, when this is `None`.
+fn resolves(
+    name: &str,
+    state: &ShellState,
+    search: &Search,
+    found: &mut HashMap<String, bool>,
+    typing: bool,
+) -> Option<bool> {
+    if is_builtin(name) || state.functions.get(name).is_some() || state.aliases.get(name).is_some()
+    {
+        return Some(true);
+    }
+    if is_path(name) {
+        let path = search.resolve(name);
+        return Some(search.is_program(&path) && !path.is_dir());
+    }
+    if state.hash.get(name, search).is_some() {
+        return Some(true);
+    }
+    if let Some(&found) = found.get(name) {
+        return Some(found);
+    }
+    if typing {
+        return None;
+    }
+    let on_path = find_in_path(name, search).is_ok_and(|path| path.is_some());
+    found.insert(name.to_owned(), on_path);
+    Some(on_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Marks the colors as tags, so the expectations stay readable.
+    fn tagged(line: &str) -> String {
+        colorize(line, Style::colored(), |name, _| Some(name == "echo"))
+            .replace("\x1b[32m", "<ok>")
+            .replace("\x1b[1;31m", "<bad>")
+            .replace("\x1b[33m", "<str>")
+            .replace("\x1b[36m", "<op>")
+            .replace("\x1b[90m", "<rem>")
+            .replace("\x1b[0m", "</>")
+    }
+
+    #[test]
+    fn command_names() {
+        assert_eq!(tagged("echo hi"), "<ok>echo</> hi");
+        assert_eq!(tagged("ehco hi"), "<bad>ehco</> hi");
+        assert_eq!(tagged("e'ch'o hi"), "<ok>e'ch'o</> hi");
+        assert_eq!(tagged("$cmd hi"), "$cmd hi");
+        assert_eq!(
+            tagged("A=1 echo >out x | nope"),
+            "A=1 <ok>echo</> <op>></>out x <op>|</> <bad>nope</>"
+        );
+        assert_eq!(tagged("! echo"), "<op>!</> <ok>echo</>");
+        assert_eq!(
+            tagged("case x in x) ;; esac"),
+            "<op>case</> x in x) <op>;;</> <op>esac</>"
+        );
+        assert_eq!(
+            tagged("if echo; then nope; fi"),
+            "<op>if</> <ok>echo</><op>;</> <op>then</> <bad>nope</><op>;</> <op>fi</>"
+        );
+    }
+
+    #[test]
+    fn strings_operators_and_comments() {
+        assert_eq!(
+            tagged("echo 'a b' x\"c\"&&echo # done"),
+            "<ok>echo</> <str>'a b'</> x<str>\"c\"</><op>&&</><ok>echo</> <rem># done</>"
+        );
+        // A quote still open colors the rest of the line
+        assert_eq!(tagged("echo \"abc $x"), "<ok>echo</> <str>\"abc $x</>");
+        assert_eq!(tagged(""), "");
+    }
+
+    #[test]
+    fn plain_without_color() {
+        let line = "ehco 'a' | wc";
+        assert_eq!(colorize(line, Style::plain(), |_, _| Some(false)), line);
+    }
+
+    #[test]
+    fn names_still_being_typed_are_left_plain() {
+        let unknown = |line| {
+            colorize(line, Style::colored(), |name, typing| {
+                (!typing).then_some(name == "echo")
+            })
+        };
+        assert_eq!(unknown("ech"), "ech");
+        assert_eq!(unknown("ech "), "\x1b[1;31mech\x1b[0m ");
+        assert_eq!(unknown("echo x"), "\x1b[32mecho\x1b[0m x");
+    }
+
+    #[test]
+    fn path_is_searched_once_per_name() {
+        let mut state = ShellState::default();
+        state.vars.set("PATH", "/definitely/not/a/dir");
+        let mut highlighter = Highlighter::default();
+        for line in ["e", "ec", "ech", "echx"] {
+            highlighter.highlight(line, &state, Style::plain());
+        }
+        assert!(highlighter.found.is_empty());
+        highlighter.highlight("echx ", &state, Style::plain());
+        assert_eq!(highlighter.found.get("echx"), Some(&false));
+        // Another PATH forgets what the last one didn't have
+        state.vars.set("PATH", "/another/missing/dir");
+        highlighter.highlight("x", &state, Style::plain());
+        assert!(highlighter.found.is_empty());
+    }
+}
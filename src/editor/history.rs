@@ -0,0 +1,110 @@
+/// The lines accepted so far, and which of them is being shown while the
+/// line being read is moved back through them.
+#[derive(Debug, Default)]
+pub(crate) struct History {
+    /// Oldest first.
+    lines: Vec<String>,
+    /// The line shown in place of the one being typed, if any.
+    position: Option<usize>,
+    /// The line as it was typed before moving back from it.
+    draft: String,
+}
+
+impl History {
+    pub(crate) fn push(&mut self, line: String) {
+        self.lines.push(line);
+    }
+
+    pub(crate) fn last(&self) -> Option<&str> {
+        self.lines.last().map(String::as_str)
+    }
+
+    /// Starts over from the newest line, for the next line read.
+    pub(crate) fn rewind(&mut self) {
+        self.position = None;
+        self.draft.clear();
+    }
+
+    /// Moves back to the nearest line before the one shown that `wanted`
+    /// accepts, returning it, or `None` if there isn't one. `current` is
+    /// the text being edited, kept to come back to.
+    pub(crate) fn previous(
+        &mut self,
+        current: &str,
+        wanted: impl Fn(&str) -> bool,
+    ) -> Option<String> {
+        let from = self.position.unwrap_or(self.lines.len());
+        let found = self.lines[..from].iter().rposition(|line| wanted(line))?;
+        if self.position.is_none() {
+            self.draft = current.to_owned();
+        }
+        self.position = Some(found);
+        Some(self.lines[found].clone())
+    }
+
+    /// Moves forward to the nearest line after the one shown that `wanted`
+    /// accepts, returning it. Past the newest, it's the line that was
+    /// being typed. `None` if nothing's being shown.
+    pub(crate) fn next(&mut self, wanted: impl Fn(&str) -> bool) -> Option<String> {
+        let from = self.position? + 1;
+        match self.lines[from..].iter().position(|line| wanted(line)) {
+            Some(found) => {
+                self.position = Some(from + found);
+                Some(self.lines[from + found].clone())
+            }
+            None => {
+                self.position = None;
+                Some(std::mem::take(&mut self.draft))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(lines: &[&str]) -> History {
+        let mut history = History::default();
+        for line in lines {
+            history.push(line.to_string());
+        }
+        history
+    }
+
+    #[test]
+    fn moves_through_every_line() {
+        let mut history = history(&["ls", "cd src", "ls"]);
+        let any = |_: &str| true;
+        assert_eq!(history.next(any), None);
+        assert_eq!(history.previous("typed", any).as_deref(), Some("ls"));
+        assert_eq!(history.previous("ls", any).as_deref(), Some("cd src"));
+        assert_eq!(history.previous("cd src", any).as_deref(), Some("ls"));
+        assert_eq!(history.previous("ls", any), None);
+        assert_eq!(history.next(any).as_deref(), Some("cd src"));
+        assert_eq!(history.next(any).as_deref(), Some("ls"));
+        assert_eq!(history.next(any).as_deref(), Some("typed"));
+        assert_eq!(history.next(any), None);
+    }
+
+    #[test]
+    fn searches_by_prefix() {
+        let mut history = history(&["git status", "ls", "git push", "git push"]);
+        let current = "git push";
+        let wanted = |line: &str| line.starts_with("git") && line != current;
+        assert_eq!(
+            history.previous("git", wanted).as_deref(),
+            Some("git status")
+        );
+        assert_eq!(history.previous("git status", wanted), None);
+        assert_eq!(history.next(wanted).as_deref(), Some("git"));
+
+        history.rewind();
+        assert_eq!(
+            history
+                .previous("g", |line| line.starts_with("g"))
+                .as_deref(),
+            Some("git push")
+        );
+    }
+}
@@ -0,0 +1,676 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use crate::editor::terminal::decode;
+
+/// A named line-editor operation that a key chord can be bound to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub(crate) enum Action {
+    AcceptLine,
+    BackwardChar,
+    BackwardDeleteChar,
+    BackwardKillWord,
+    BackwardWord,
+    BeginningOfLine,
+    ClearScreen,
+    Complete,
+    DeleteChar,
+    EndOfLine,
+    ForwardChar,
+    ForwardWord,
+    HistorySearchBackward,
+    HistorySearchForward,
+    InsertLastArgument,
+    KillLine,
+    KillWord,
+    NextHistory,
+    PreviousHistory,
+    UnixLineDiscard,
+    Yank,
+    YankPop,
+}
+
+/// Every action along with the name used for it in the config file and `bind`.
+const ACTIONS: &[(&str, Action)] = &[
+    ("accept-line", Action::AcceptLine),
+    ("backward-char", Action::BackwardChar),
+    ("backward-delete-char", Action::BackwardDeleteChar),
+    ("backward-kill-word", Action::BackwardKillWord),
+    ("backward-word", Action::BackwardWord),
+    ("beginning-of-line", Action::BeginningOfLine),
+    ("clear-screen", Action::ClearScreen),
+    ("complete", Action::Complete),
+    ("delete-char", Action::DeleteChar),
+    ("end-of-line", Action::EndOfLine),
+    ("forward-char", Action::ForwardChar),
+    ("forward-word", Action::ForwardWord),
+    ("history-search-backward", Action::HistorySearchBackward),
+    ("history-search-forward", Action::HistorySearchForward),
+    ("insert-last-argument", Action::InsertLastArgument),
+    ("kill-line", Action::KillLine),
+    ("kill-word", Action::KillWord),
+    ("next-history", Action::NextHistory),
+    ("previous-history", Action::PreviousHistory),
+    ("unix-line-discard", Action::UnixLineDiscard),
+    ("yank", Action::Yank),
+    ("yank-pop", Action::YankPop),
+];
+
+impl Action {
+    pub(crate) fn name(&self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(_, action)| action == self)
+            .map(|(name, _)| *name)
+            .expect("every action is named")
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ACTIONS
+            .iter()
+            .find(|(name, _)| *name == s.trim())
+            .map(|(_, action)| *action)
+            .ok_or_else(|| format!("{}: unknown action", s.trim()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub(crate) enum Key {
+    Char(char),
+    Function(u8),
+    Backspace,
+    Delete,
+    Down,
+    End,
+    Enter,
+    Escape,
+    Home,
+    Left,
+    Right,
+    Tab,
+    Up,
+}
+
+/// Named keys, as written in chord notation.
+const KEY_NAMES: &[(&str, Key)] = &[
+    ("backspace", Key::Backspace),
+    ("delete", Key::Delete),
+    ("down", Key::Down),
+    ("end", Key::End),
+    ("enter", Key::Enter),
+    ("escape", Key::Escape),
+    ("home", Key::Home),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("space", Key::Char(' ')),
+    ("tab", Key::Tab),
+    ("up", Key::Up),
+];
+
+/// A key along with the modifiers held while pressing it, written as e.g.
+/// `ctrl-w`, `alt-.`, `ctrl-alt-x`, or `f5`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub(crate) struct Chord {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub key: Key,
+}
+
+impl Chord {
+    pub(crate) const fn new(key: Key) -> Self {
+        Self {
+            ctrl: false,
+            alt: false,
+            key,
+        }
+    }
+
+    pub(crate) const fn ctrl(c: char) -> Self {
+        Self {
+            ctrl: true,
+            alt: false,
+            key: Key::Char(c),
+        }
+    }
+
+    pub(crate) const fn alt(c: char) -> Self {
+        Self::alt_key(Key::Char(c))
+    }
+
+    pub(crate) const fn ctrl_key(key: Key) -> Self {
+        Self {
+            ctrl: true,
+            alt: false,
+            key,
+        }
+    }
+
+    pub(crate) const fn alt_key(key: Key) -> Self {
+        Self {
+            ctrl: false,
+            alt: true,
+            key,
+        }
+    }
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{s}: invalid key chord");
+        let lower = s.trim().to_ascii_lowercase();
+        let mut rest = lower.as_str();
+        let (mut ctrl, mut alt) = (false, false);
+
+        // Modifiers come first; the remainder is the key itself, which may be
+        // a literal `-` as in `alt--`
+        loop {
+            if let Some(r) = rest.strip_prefix("ctrl-").filter(|r| !r.is_empty()) {
+                ctrl = true;
+                rest = r;
+            } else if let Some(r) = ["alt-", "meta-"]
+                .iter()
+                .find_map(|m| rest.strip_prefix(m))
+                .filter(|r| !r.is_empty())
+            {
+                alt = true;
+                rest = r;
+            } else {
+                break;
+            }
+        }
+
+        let mut chars = rest.chars();
+        let key = match (chars.next(), chars.next()) {
+            (Some(c), None) => Key::Char(c),
+            _ => KEY_NAMES
+                .iter()
+                .find(|(name, _)| *name == rest)
+                .map(|(_, key)| *key)
+                .or_else(|| {
+                    rest.strip_prefix('f')
+                        .and_then(|n| n.parse::<u8>().ok())
+                        .filter(|n| (1..=12).contains(n))
+                        .map(Key::Function)
+                })
+                .ok_or_else(invalid)?,
+        };
+
+        // Only letters and a handful of punctuation have control codes
+        if ctrl
+            && let Key::Char(c) = key
+            && !(c.is_ascii_lowercase() || "@[\\]^_ ".contains(c))
+        {
+            return Err(invalid());
+        }
+
+        Ok(Self { ctrl, alt, key })
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "ctrl-")?;
+        }
+        if self.alt {
+            write!(f, "alt-")?;
+        }
+        match self.key {
+            Key::Char(' ') => write!(f, "space"),
+            Key::Char(c) => write!(f, "{c}"),
+            Key::Function(n) => write!(f, "f{n}"),
+            key => {
+                let name = KEY_NAMES
+                    .iter()
+                    .find(|(_, k)| *k == key)
+                    .map(|(name, _)| *name)
+                    .expect("every named key is listed");
+                write!(f, "{name}")
+            }
+        }
+    }
+}
+
+/// Parses a key written as a chord like `ctrl-g`, a readline key sequence
+/// in quotes like `"\C-g"`, or a readline key name like `Control-g`.
+pub(crate) fn parse_key(key: &str) -> Result<Chord, String> {
+    let key = key.trim();
+    match key.strip_prefix('"').and_then(|seq| seq.strip_suffix('"')) {
+        Some(seq) => key_sequence(seq),
+        None => key.parse().or_else(|error| key_name(key).ok_or(error)),
+    }
+}
+
+/// Parses a binding in readline's syntax, `"keyseq": value` or
+/// `keyname: value`, into its key and whatever it's bound to.
+pub(crate) fn parse_readline(spec: &str) -> Result<(Chord, &str), String> {
+    let invalid = || format!("{spec}: expected \"keyseq\": action");
+    let spec = spec.trim();
+    let (key, value) = match spec.strip_prefix('"') {
+        // The closing quote is the first one that isn't escaped
+        Some(seq) => {
+            let mut escaped = false;
+            let end = seq
+                .char_indices()
+                .find(|&(_, c)| {
+                    let end = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    end
+                })
+                .map(|(i, _)| i)
+                .ok_or_else(invalid)?;
+            let value = seq[end + 1..].trim_start().strip_prefix(':');
+            (&spec[..end + 2], value.ok_or_else(invalid)?)
+        }
+        None => spec.split_once(':').ok_or_else(invalid)?,
+    };
+    Ok((parse_key(key)?, value.trim()))
+}
+
+/// The chord a readline key sequence like `\C-x`, `\M-.`, or `\e[A` sends,
+/// without its quotes.
+fn key_sequence(seq: &str) -> Result<Chord, String> {
+    let mut bytes = Vec::new();
+    let mut chars = seq.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        let escaped = chars.next().unwrap_or('\\');
+        let rest = chars.as_str();
+        match escaped {
+            'C' if rest.starts_with('-') && rest.len() > 1 => {
+                chars.next();
+                let key = chars.next().expect("checked above");
+                bytes.push(match key {
+                    '?' => 0x7f,
+                    key => key.to_ascii_uppercase() as u8 & 0x1f,
+                });
+            }
+            // Meta is sent as Escape before the key, which may be escaped
+            // itself, as in `\M-\C-x`
+            'M' if rest.starts_with('-') && rest.len() > 1 => {
+                chars.next();
+                bytes.push(0x1b);
+            }
+            'e' => bytes.push(0x1b),
+            'a' => bytes.push(0x07),
+            't' => bytes.push(b'\t'),
+            'n' => bytes.push(b'\n'),
+            'r' => bytes.push(b'\r'),
+            c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    match decode(&bytes) {
+        Some((Some(chord), len)) if len == bytes.len() => Ok(chord),
+        Some((Some(_), _)) => Err(format!("\"{seq}\": only a single key can be bound")),
+        _ => Err(format!("\"{seq}\": invalid key sequence")),
+    }
+}
+
+/// The chord for a readline key name like `Control-u`, `M-b`, or
+/// `Rubout`.
+fn key_name(name: &str) -> Option<Chord> {
+    let lower = name.to_ascii_lowercase();
+    let mut rest = lower.as_str();
+    let mut notation = String::new();
+    loop {
+        if let Some(r) = ["control-", "c-"].iter().find_map(|m| rest.strip_prefix(m)) {
+            notation.push_str("ctrl-");
+            rest = r;
+        } else if let Some(r) = ["meta-", "m-"].iter().find_map(|m| rest.strip_prefix(m)) {
+            notation.push_str("alt-");
+            rest = r;
+        } else {
+            break;
+        }
+    }
+    notation.push_str(match rest {
+        "rubout" => "backspace",
+        "del" => "delete",
+        "esc" => "escape",
+        "lfd" | "newline" | "ret" | "return" => "enter",
+        "spc" => "space",
+        rest => rest,
+    });
+    notation.parse().ok()
+}
+
+/// Maps key chords to the editor actions they trigger, or to shell
+/// commands to run from the editor.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Keymap {
+    bindings: BTreeMap<Chord, Action>,
+    /// The chords bound with `bind -x`.
+    commands: BTreeMap<Chord, String>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Self {
+            bindings: BTreeMap::new(),
+            commands: BTreeMap::new(),
+        };
+        for (chord, action) in [
+            (Chord::new(Key::Enter), Action::AcceptLine),
+            (Chord::new(Key::Tab), Action::Complete),
+            (Chord::new(Key::Left), Action::BackwardChar),
+            (Chord::new(Key::Right), Action::ForwardChar),
+            (Chord::new(Key::Home), Action::BeginningOfLine),
+            (Chord::new(Key::End), Action::EndOfLine),
+            (Chord::new(Key::Up), Action::PreviousHistory),
+            (Chord::new(Key::Down), Action::NextHistory),
+            (Chord::new(Key::Backspace), Action::BackwardDeleteChar),
+            (Chord::new(Key::Delete), Action::DeleteChar),
+            (Chord::ctrl('a'), Action::BeginningOfLine),
+            (Chord::ctrl('b'), Action::BackwardChar),
+            (Chord::ctrl('d'), Action::DeleteChar),
+            (Chord::ctrl('e'), Action::EndOfLine),
+            (Chord::ctrl('f'), Action::ForwardChar),
+            (Chord::ctrl('k'), Action::KillLine),
+            (Chord::ctrl('l'), Action::ClearScreen),
+            (Chord::ctrl('n'), Action::NextHistory),
+            (Chord::ctrl('p'), Action::PreviousHistory),
+            (Chord::ctrl('u'), Action::UnixLineDiscard),
+            (Chord::ctrl('w'), Action::BackwardKillWord),
+            (Chord::ctrl('y'), Action::Yank),
+            (Chord::alt('y'), Action::YankPop),
+            (Chord::alt('.'), Action::InsertLastArgument),
+            (Chord::alt('b'), Action::BackwardWord),
+            (Chord::alt('f'), Action::ForwardWord),
+            (Chord::alt('d'), Action::KillWord),
+            (Chord::ctrl_key(Key::Left), Action::BackwardWord),
+            (Chord::ctrl_key(Key::Right), Action::ForwardWord),
+            (Chord::alt_key(Key::Left), Action::BackwardWord),
+            (Chord::alt_key(Key::Right), Action::ForwardWord),
+            (Chord::alt_key(Key::Backspace), Action::BackwardKillWord),
+        ] {
+            keymap.bind(chord, action);
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    /// Binds `chord` to `action`, replacing any earlier binding for it.
+    pub(crate) fn bind(&mut self, chord: Chord, action: Action) {
+        self.commands.remove(&chord);
+        self.bindings.insert(chord, action);
+    }
+
+    /// Binds `chord` to run `command`, replacing any earlier binding for it.
+    pub(crate) fn bind_command(&mut self, chord: Chord, command: &str) {
+        self.bindings.remove(&chord);
+        self.commands.insert(chord, command.to_owned());
+    }
+
+    /// Removes whatever `chord` is bound to, returning whether it was bound.
+    pub(crate) fn unbind(&mut self, chord: &Chord) -> bool {
+        self.bindings.remove(chord).is_some() | self.commands.remove(chord).is_some()
+    }
+
+    /// Parses both halves of a binding in notation form before binding them.
+    pub(crate) fn bind_str(&mut self, chord: &str, action: &str) -> Result<(), String> {
+        let chord = chord.parse()?;
+        let action = action.parse()?;
+        self.bind(chord, action);
+        Ok(())
+    }
+
+    pub(crate) fn lookup(&self, chord: &Chord) -> Option<Action> {
+        self.bindings.get(chord).copied()
+    }
+
+    /// The command `chord` runs, if it was bound to one.
+    pub(crate) fn command(&self, chord: &Chord) -> Option<&str> {
+        self.commands.get(chord).map(String::as_str)
+    }
+
+    /// Returns every binding, ordered by chord.
+    pub(crate) fn bindings(&self) -> impl Iterator<Item = (&Chord, &Action)> {
+        self.bindings.iter()
+    }
+
+    /// Returns every command binding, ordered by chord.
+    pub(crate) fn commands(&self) -> impl Iterator<Item = (&Chord, &str)> {
+        self.commands
+            .iter()
+            .map(|(chord, command)| (chord, command.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(s: &str) -> Chord {
+        s.parse().unwrap()
+    }
+
+    mod chord_notation {
+        use super::*;
+
+        #[test]
+        fn plain_characters() {
+            assert_eq!(chord("a"), Chord::new(Key::Char('a')));
+            assert_eq!(chord("."), Chord::new(Key::Char('.')));
+        }
+
+        #[test]
+        fn modifiers() {
+            assert_eq!(chord("ctrl-w"), Chord::ctrl('w'));
+            assert_eq!(chord("alt-."), Chord::alt('.'));
+            assert_eq!(chord("meta-b"), Chord::alt('b'));
+            assert_eq!(
+                chord("ctrl-alt-x"),
+                Chord {
+                    ctrl: true,
+                    alt: true,
+                    key: Key::Char('x')
+                }
+            );
+        }
+
+        #[test]
+        fn dash_as_the_key() {
+            assert_eq!(chord("alt--"), Chord::alt('-'));
+            assert_eq!(chord("-"), Chord::new(Key::Char('-')));
+        }
+
+        #[test]
+        fn case_insensitive() {
+            assert_eq!(chord("Ctrl-W"), Chord::ctrl('w'));
+            assert_eq!(chord("F5"), Chord::new(Key::Function(5)));
+        }
+
+        #[test]
+        fn named_keys() {
+            assert_eq!(chord("f5"), Chord::new(Key::Function(5)));
+            assert_eq!(chord("f12"), Chord::new(Key::Function(12)));
+            assert_eq!(chord("enter"), Chord::new(Key::Enter));
+            assert_eq!(chord("tab"), Chord::new(Key::Tab));
+            assert_eq!(chord("alt-left").key, Key::Left);
+            assert_eq!(chord("ctrl-space"), Chord::ctrl(' '));
+        }
+
+        #[test]
+        fn invalid_chords() {
+            for invalid in ["", "ctrl-", "f13", "f0", "hyper-x", "ctrl-1", "enterr"] {
+                assert!(invalid.parse::<Chord>().is_err(), "{invalid:?}");
+            }
+        }
+
+        #[test]
+        fn display_round_trips() {
+            for notation in [
+                "ctrl-w",
+                "alt-.",
+                "ctrl-alt-x",
+                "f5",
+                "enter",
+                "alt--",
+                "space",
+            ] {
+                assert_eq!(chord(notation).to_string(), notation);
+            }
+        }
+    }
+
+    mod readline_syntax {
+        use super::*;
+
+        #[test]
+        fn key_sequences() {
+            assert_eq!(parse_key(r#""\C-g""#), Ok(Chord::ctrl('g')));
+            assert_eq!(parse_key(r#""\M-.""#), Ok(Chord::alt('.')));
+            assert_eq!(parse_key(r#""\e.""#), Ok(Chord::alt('.')));
+            assert_eq!(parse_key(r#""\e[A""#), Ok(Chord::new(Key::Up)));
+            assert_eq!(parse_key(r#""\t""#), Ok(Chord::new(Key::Tab)));
+            assert_eq!(parse_key(r#""\C-?""#), Ok(Chord::new(Key::Backspace)));
+            assert_eq!(
+                parse_key(r#""\M-\C-x""#),
+                Ok(Chord {
+                    ctrl: true,
+                    alt: true,
+                    key: Key::Char('x')
+                })
+            );
+            assert_eq!(
+                parse_key(r#""\C-x\C-e""#),
+                Err(r#""\C-x\C-e": only a single key can be bound"#.into())
+            );
+        }
+
+        #[test]
+        fn key_names() {
+            assert_eq!(parse_key("Control-u"), Ok(Chord::ctrl('u')));
+            assert_eq!(parse_key("C-u"), Ok(Chord::ctrl('u')));
+            assert_eq!(parse_key("M-b"), Ok(Chord::alt('b')));
+            assert_eq!(parse_key("Rubout"), Ok(Chord::new(Key::Backspace)));
+            assert_eq!(parse_key("ctrl-w"), Ok(Chord::ctrl('w')));
+            assert!(parse_key("Hyper-x").is_err());
+        }
+
+        #[test]
+        fn bindings() {
+            assert_eq!(
+                parse_readline(r#""\C-g": clear-screen"#),
+                Ok((Chord::ctrl('g'), "clear-screen"))
+            );
+            assert_eq!(
+                parse_readline(r#""\"":self-insert"#),
+                Ok((Chord::new(Key::Char('"')), "self-insert"))
+            );
+            assert_eq!(
+                parse_readline("Meta-.: insert-last-argument"),
+                Ok((Chord::alt('.'), "insert-last-argument"))
+            );
+            assert!(parse_readline(r#""\C-g" clear-screen"#).is_err());
+            assert!(parse_readline("clear-screen").is_err());
+        }
+    }
+
+    mod actions {
+        use super::*;
+
+        #[test]
+        fn names_round_trip() {
+            for (name, action) in ACTIONS {
+                assert_eq!(name.parse::<Action>().unwrap(), *action);
+                assert_eq!(action.name(), *name);
+            }
+        }
+
+        #[test]
+        fn unknown_action() {
+            let error = "self-destruct".parse::<Action>().unwrap_err();
+            assert_eq!(error, "self-destruct: unknown action");
+        }
+    }
+
+    mod bindings {
+        use super::*;
+
+        #[test]
+        fn defaults() {
+            let keymap = Keymap::default();
+            assert_eq!(
+                keymap.lookup(&Chord::ctrl('w')),
+                Some(Action::BackwardKillWord)
+            );
+            assert_eq!(
+                keymap.lookup(&Chord::alt('.')),
+                Some(Action::InsertLastArgument)
+            );
+            assert_eq!(
+                keymap.lookup(&Chord::ctrl('a')),
+                Some(Action::BeginningOfLine)
+            );
+            assert_eq!(
+                keymap.lookup(&Chord::new(Key::End)),
+                Some(Action::EndOfLine)
+            );
+            assert_eq!(
+                keymap.lookup(&Chord::ctrl_key(Key::Left)),
+                Some(Action::BackwardWord)
+            );
+            assert_eq!(keymap.lookup(&Chord::alt('d')), Some(Action::KillWord));
+            assert_eq!(keymap.lookup(&Chord::new(Key::Function(5))), None);
+        }
+
+        #[test]
+        fn later_bindings_override_earlier_ones() {
+            let mut keymap = Keymap::default();
+            keymap.bind_str("f5", "clear-screen").unwrap();
+            keymap.bind_str("F5", "complete").unwrap();
+            assert_eq!(
+                keymap.lookup(&Chord::new(Key::Function(5))),
+                Some(Action::Complete)
+            );
+            assert_eq!(
+                keymap
+                    .bindings()
+                    .filter(|(c, _)| c.to_string() == "f5")
+                    .count(),
+                1
+            );
+        }
+
+        #[test]
+        fn commands_replace_actions() {
+            let mut keymap = Keymap::default();
+            keymap.bind_command(Chord::ctrl('w'), "ls -l");
+            assert_eq!(keymap.lookup(&Chord::ctrl('w')), None);
+            assert_eq!(keymap.command(&Chord::ctrl('w')), Some("ls -l"));
+
+            keymap.bind(Chord::ctrl('w'), Action::Complete);
+            assert_eq!(keymap.command(&Chord::ctrl('w')), None);
+            assert!(keymap.unbind(&Chord::ctrl('w')));
+            assert!(!keymap.unbind(&Chord::ctrl('w')));
+            assert_eq!(keymap.lookup(&Chord::ctrl('w')), None);
+        }
+
+        #[test]
+        fn bad_bindings_leave_keymap_untouched() {
+            let mut keymap = Keymap::default();
+            assert!(keymap.bind_str("ctrl-w", "nope").is_err());
+            assert!(keymap.bind_str("nope-w", "complete").is_err());
+            assert_eq!(keymap, Keymap::default());
+        }
+
+        #[test]
+        fn bindings_are_ordered() {
+            let keymap = Keymap::default();
+            let chords: Vec<_> = keymap.bindings().map(|(c, _)| *c).collect();
+            let mut sorted = chords.clone();
+            sorted.sort();
+            assert_eq!(chords, sorted);
+        }
+    }
+}
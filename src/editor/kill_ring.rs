@@ -0,0 +1,102 @@
+/// How many kills are kept, as readline's `kill-ring-max` defaults to.
+const MAX_KILLS: usize = 10;
+
+/// Which way from the cursor text was killed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Direction {
+    Forward,
+    Backward,
+}
+
+/// The text cut from the line by the kill commands, for yanking back in,
+/// oldest first.
+#[derive(Debug, Default)]
+pub(crate) struct KillRing {
+    kills: Vec<String>,
+    /// Which kill the last yank put in, counting back from the newest.
+    yanked: usize,
+}
+
+impl KillRing {
+    /// Saves killed `text`. Kills in a row build up one piece of text, as
+    /// readline's do, so `joined` adds it to the newest kill, on the side
+    /// it was killed from.
+    pub(crate) fn kill(&mut self, text: String, direction: Direction, joined: bool) {
+        if text.is_empty() {
+            return;
+        }
+        match self.kills.last_mut() {
+            Some(last) if joined => match direction {
+                Direction::Forward => last.push_str(&text),
+                Direction::Backward => last.insert_str(0, &text),
+            },
+            _ => {
+                self.kills.push(text);
+                if self.kills.len() > MAX_KILLS {
+                    self.kills.remove(0);
+                }
+            }
+        }
+    }
+
+    /// The newest kill, to yank.
+    pub(crate) fn yank(&mut self) -> Option<&str> {
+        self.yanked = 0;
+        self.kills.last().map(String::as_str)
+    }
+
+    /// The kill before the one yanked last, to yank in its place. It goes
+    /// back around to the newest after the oldest.
+    pub(crate) fn yank_pop(&mut self) -> Option<&str> {
+        if self.kills.is_empty() {
+            return None;
+        }
+        self.yanked = (self.yanked + 1) % self.kills.len();
+        Some(&self.kills[self.kills.len() - 1 - self.yanked])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yanks_cycle_back_through_kills() {
+        let mut ring = KillRing::default();
+        assert_eq!(ring.yank(), None);
+        assert_eq!(ring.yank_pop(), None);
+
+        for kill in ["one", "two", "three"] {
+            ring.kill(kill.into(), Direction::Forward, false);
+        }
+        ring.kill(String::new(), Direction::Forward, false);
+        assert_eq!(ring.yank(), Some("three"));
+        assert_eq!(ring.yank_pop(), Some("two"));
+        assert_eq!(ring.yank_pop(), Some("one"));
+        assert_eq!(ring.yank_pop(), Some("three"));
+        assert_eq!(ring.yank(), Some("three"));
+    }
+
+    #[test]
+    fn kills_in_a_row_join_up() {
+        let mut ring = KillRing::default();
+        ring.kill("commit ".into(), Direction::Backward, false);
+        ring.kill("git ".into(), Direction::Backward, true);
+        ring.kill(" -m".into(), Direction::Forward, true);
+        assert_eq!(ring.yank(), Some("git commit  -m"));
+        assert_eq!(ring.yank_pop(), Some("git commit  -m"));
+    }
+
+    #[test]
+    fn only_the_newest_kills_are_kept() {
+        let mut ring = KillRing::default();
+        for i in 0..=MAX_KILLS {
+            ring.kill(i.to_string(), Direction::Forward, false);
+        }
+        assert_eq!(ring.yank(), Some("10"));
+        for _ in 1..MAX_KILLS {
+            ring.yank_pop();
+        }
+        assert_eq!(ring.yank_pop(), Some("10"));
+    }
+}
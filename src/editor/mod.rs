@@ -0,0 +1,524 @@
+pub(crate) mod buffer;
+pub(crate) mod completion;
+pub(crate) mod highlight;
+pub(crate) mod history;
+pub(crate) mod keymap;
+pub(crate) mod kill_ring;
+pub(crate) mod terminal;
+
+use std::io::{self, Write};
+
+use crate::{
+    diagnostics,
+    editor::{
+        buffer::LineBuffer,
+        completion::complete,
+        highlight::Highlighter,
+        history::History,
+        keymap::{Action, Chord, Key},
+        kill_ring::{Direction, KillRing},
+        terminal::{Input, Keys, RawMode},
+    },
+    eval::eval,
+    state::ShellState,
+    style::{Style, display_width},
+};
+
+/// Reads command lines from a terminal a key at a time, so they can be
+/// edited before they're run, with the keys bound to actions by the shell's
+/// keymap.
+#[derive(Debug, Default)]
+pub(crate) struct Editor {
+    keys: Keys,
+    history: History,
+    highlighter: Highlighter,
+    screen: Screen,
+    kill_ring: KillRing,
+    /// The action the last key performed on the line being read, if any.
+    previous: Option<Action>,
+    /// Where the text the last yank put in starts.
+    yank_start: usize,
+}
+
+/// What to do once a key has been handled.
+enum Outcome {
+    Continue,
+    Accept,
+    EndOfInput,
+}
+
+impl Editor {
+    /// Shows `prompt` and reads a line, without its newline. `None` once
+    /// input ends, or Ctrl-D is pressed on an empty line, unless the
+    /// `ignoreeof` option is set. Ctrl-C abandons the line with an
+    /// [`io::ErrorKind::Interrupted`] error.
+    pub(crate) fn read_line(
+        &mut self,
+        prompt: &str,
+        state: &mut ShellState,
+    ) -> io::Result<Option<String>> {
+        let mut raw = Some(RawMode::enable()?);
+        let mut out = io::stdout();
+        let mut line = LineBuffer::default();
+
+        // Only the prompt's last line is redrawn as the line changes
+        let (above, prompt) = match prompt.rsplit_once('\n') {
+            Some((above, last)) => (Some(above), last),
+            None => (None, prompt),
+        };
+        if let Some(above) = above {
+            write!(out, "{}\r\n", above.replace('\n', "\r\n"))?;
+        }
+        let mut ignored = 0;
+        self.highlighter.reset();
+        self.screen = Screen::new(terminal::width(state));
+        self.previous = None;
+        self.history.rewind();
+
+        loop {
+            self.render(&mut out, prompt, &line, state)?;
+            let chord = match self.keys.next()? {
+                Some(Input::Key(chord)) => chord,
+                Some(Input::Resized) => {
+                    self.screen.resize(terminal::width(state));
+                    continue;
+                }
+                None => {
+                    self.screen.leave(&mut out, "")?;
+                    return Ok(None);
+                }
+            };
+            if chord == Chord::ctrl('c') {
+                self.screen.leave(&mut out, "^C")?;
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+
+            if let Some(command) = state.keymap.command(&chord) {
+                let command = command.to_owned();
+                self.previous = None;
+                self.history.rewind();
+                self.screen.leave(&mut out, "")?;
+                run_bound(&command, &mut line, &mut raw, state)?;
+                // A bound `exit` ends the shell as the end of input would
+                if state.exited.is_some() {
+                    return Ok(None);
+                }
+                continue;
+            }
+
+            let outcome = match state.keymap.lookup(&chord) {
+                Some(action) => {
+                    let prompt = (above, prompt);
+                    let outcome = self.perform(action, &mut line, prompt, state, &mut out)?;
+                    self.previous = Some(action);
+                    outcome
+                }
+                None => {
+                    self.previous = None;
+                    self.history.rewind();
+                    if let Chord {
+                        ctrl: false,
+                        alt: false,
+                        key: Key::Char(c),
+                    } = chord
+                    {
+                        line.insert(c);
+                    }
+                    Outcome::Continue
+                }
+            };
+            match outcome {
+                Outcome::Continue => {}
+                Outcome::Accept => {
+                    self.screen.leave(&mut out, "")?;
+                    let line = line.as_str().to_owned();
+                    if !line.trim().is_empty() {
+                        self.history.push(line.clone());
+                    }
+                    return Ok(Some(line));
+                }
+                // With ignoreeof, it takes more than IGNOREEOF presses in a
+                // row to leave the shell
+                Outcome::EndOfInput if state.options.ignoreeof && ignored < eof_limit(state) => {
+                    ignored += 1;
+                    self.screen.leave(&mut out, "")?;
+                    write!(out, "Use \"exit\" to leave the shell.\r\n")?;
+                }
+                Outcome::EndOfInput => {
+                    self.screen.leave(&mut out, "")?;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn perform(
+        &mut self,
+        action: Action,
+        line: &mut LineBuffer,
+        (above, prompt): (Option<&str>, &str),
+        state: &mut ShellState,
+        out: &mut dyn Write,
+    ) -> io::Result<Outcome> {
+        match action {
+            Action::AcceptLine => return Ok(Outcome::Accept),
+            Action::BackwardChar => line.move_left(),
+            Action::ForwardChar => line.move_right(),
+            Action::BackwardWord => line.move_word_left(),
+            Action::ForwardWord => line.move_word_right(),
+            Action::BeginningOfLine => line.move_home(),
+            Action::EndOfLine => line.move_end(),
+            Action::BackwardDeleteChar => line.delete_backward(),
+            // Like Ctrl-D in a terminal, deleting from an empty line ends
+            // the input
+            Action::DeleteChar if line.is_empty() => return Ok(Outcome::EndOfInput),
+            Action::DeleteChar => line.delete_forward(),
+            Action::KillLine => {
+                let killed = line.kill_to_end();
+                self.kill(killed, Direction::Forward);
+            }
+            Action::UnixLineDiscard => {
+                let killed = line.kill_to_start();
+                self.kill(killed, Direction::Backward);
+            }
+            Action::BackwardKillWord => {
+                let killed = line.kill_word_backward();
+                self.kill(killed, Direction::Backward);
+            }
+            Action::KillWord => {
+                let killed = line.kill_word_forward();
+                self.kill(killed, Direction::Forward);
+            }
+            Action::Yank => {
+                if let Some(text) = self.kill_ring.yank() {
+                    self.yank_start = line.cursor();
+                    line.insert_str(text);
+                }
+            }
+            // Only straight after a yank, the text it put in is swapped for
+            // the kill before it
+            Action::YankPop if matches!(self.previous, Some(Action::Yank | Action::YankPop)) => {
+                if let Some(text) = self.kill_ring.yank_pop() {
+                    line.replace_before_cursor(self.yank_start, text);
+                }
+            }
+            Action::YankPop => write!(out, "\x07")?,
+            // The line is left as it is, to be drawn again at the top
+            Action::ClearScreen => {
+                write!(out, "\x1b[H\x1b[2J")?;
+                if let Some(above) = above {
+                    write!(out, "{}\r\n", above.replace('\n', "\r\n"))?;
+                }
+                self.screen = Screen::new(self.screen.width);
+            }
+            Action::InsertLastArgument => {
+                let last = (self.history.last()).and_then(|l| l.split_whitespace().last());
+                if let Some(last) = last {
+                    line.insert_str(last);
+                }
+            }
+            Action::PreviousHistory => {
+                if let Some(text) = self.history.previous(line.as_str(), |_| true) {
+                    line.replace(text, usize::MAX);
+                }
+            }
+            Action::NextHistory => {
+                if let Some(text) = self.history.next(|_| true) {
+                    line.replace(text, usize::MAX);
+                }
+            }
+            // The searches only stop at lines that start with what's before
+            // the cursor, which stays put, and skip ones like the line shown
+            Action::HistorySearchBackward | Action::HistorySearchForward => {
+                let (prefix, current) = (&line.as_str()[..line.cursor()], line.as_str());
+                let wanted = |entry: &str| entry.starts_with(prefix) && entry != current;
+                let found = match action {
+                    Action::HistorySearchBackward => self.history.previous(current, wanted),
+                    _ => self.history.next(wanted),
+                };
+                match found {
+                    Some(text) => line.replace(text, line.cursor()),
+                    None => write!(out, "\x07")?,
+                }
+            }
+            Action::Complete => {
+                let (start, candidates) = complete(line.as_str(), line.cursor(), state);
+                match candidates.as_slice() {
+                    [] => write!(out, "\x07")?,
+                    [only] => {
+                        let mut completed = only.clone();
+                        if !completed.ends_with('/') {
+                            completed.push(' ');
+                        }
+                        line.replace_before_cursor(start, &completed);
+                    }
+                    _ => {
+                        let prefix = common_prefix(&candidates);
+                        if prefix.len() > line.cursor() - start {
+                            line.replace_before_cursor(start, prefix);
+                        } else {
+                            // Nothing more in common, so show the choices
+                            // below and start the prompt over
+                            self.screen.leave(out, "")?;
+                            write!(out, "{}\r\n", candidates.join("  "))?;
+                            self.render(out, prompt, line, state)?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Outcome::Continue)
+    }
+
+    /// Saves `killed` text in the kill ring, joined to the last kill if
+    /// the key before was a kill too.
+    fn kill(&mut self, killed: String, direction: Direction) {
+        let joined = matches!(
+            self.previous,
+            Some(
+                Action::KillLine
+                    | Action::KillWord
+                    | Action::UnixLineDiscard
+                    | Action::BackwardKillWord
+            )
+        );
+        self.kill_ring.kill(killed, direction, joined);
+    }
+
+    /// Draws the line with its syntax colored.
+    fn render(
+        &mut self,
+        out: &mut dyn Write,
+        prompt: &str,
+        line: &LineBuffer,
+        state: &ShellState,
+    ) -> io::Result<()> {
+        let shown = (self.highlighter).highlight(line.as_str(), state, Style::stdout());
+        self.screen.draw(out, prompt, line, shown)
+    }
+}
+
+/// Where the line was last drawn, so it can be drawn over when it's
+/// longer than a row of the terminal and wraps onto the ones below.
+#[derive(Debug, Default)]
+struct Screen {
+    width: usize,
+    /// How many columns past the start of the prompt the cursor was left,
+    /// counting every row before its own.
+    cursor: usize,
+    /// How many columns the prompt and line took up.
+    end: usize,
+}
+
+impl Screen {
+    fn new(width: usize) -> Self {
+        Self {
+            width: width.max(1),
+            ..Self::default()
+        }
+    }
+
+    /// Which row the cursor is on, counting from the prompt's.
+    fn cursor_row(&self) -> usize {
+        self.cursor / self.width
+    }
+
+    /// Redraws `prompt` and `line` from the prompt's row, with the cursor
+    /// where it is in the line. The line is drawn as `shown`, which is its
+    /// text with colors added. Color sequences take up no room.
+    fn draw(
+        &mut self,
+        out: &mut dyn Write,
+        prompt: &str,
+        line: &LineBuffer,
+        shown: &str,
+    ) -> io::Result<()> {
+        if self.cursor_row() > 0 {
+            write!(out, "\x1b[{}A", self.cursor_row())?;
+        }
+        write!(out, "\r{prompt}{shown}\x1b[J")?;
+
+        let prompt_width = display_width(prompt);
+        self.end = prompt_width + display_width(line.as_str());
+        self.cursor = prompt_width + display_width(&line.as_str()[..line.cursor()]);
+        // A line that fills its last row leaves the cursor stuck past the
+        // edge until something else is written, so it's moved to the next
+        if self.end > 0 && self.end.is_multiple_of(self.width) {
+            write!(out, "\r\n")?;
+        }
+        let end_row = self.end / self.width;
+        if end_row > self.cursor_row() {
+            write!(out, "\x1b[{}A", end_row - self.cursor_row())?;
+        }
+        write!(out, "\r")?;
+        let column = self.cursor % self.width;
+        if column > 0 {
+            write!(out, "\x1b[{column}C")?;
+        }
+        out.flush()
+    }
+
+    /// Takes up a terminal `width` wide. Terminals rewrap what's on them
+    /// when they're resized, so the cursor is now on whatever row its
+    /// place in the line falls on at the new width.
+    fn resize(&mut self, width: usize) {
+        self.width = width.max(1);
+    }
+
+    /// Moves the cursor to the end of the line, writes `note` there, and
+    /// starts a new row below it for whatever comes next.
+    fn leave(&mut self, out: &mut dyn Write, note: &str) -> io::Result<()> {
+        let end_row = self.end / self.width;
+        if end_row > self.cursor_row() {
+            write!(out, "\x1b[{}B", end_row - self.cursor_row())?;
+        }
+        write!(out, "\r")?;
+        let column = self.end % self.width;
+        if column > 0 {
+            write!(out, "\x1b[{column}C")?;
+        }
+        write!(out, "{note}\r\n")?;
+        *self = Self::new(self.width);
+        out.flush()
+    }
+}
+
+/// Runs a command bound with `bind -x` below the line being edited, with
+/// the terminal as it was before the editor took it over. The command sees
+/// the line and the cursor's byte index in `READLINE_LINE` and
+/// `READLINE_POINT`, and whatever it leaves there becomes the line.
+fn run_bound(
+    command: &str,
+    line: &mut LineBuffer,
+    raw: &mut Option<RawMode>,
+    state: &mut ShellState,
+) -> io::Result<()> {
+    *raw = None;
+
+    state.vars.set("READLINE_LINE", line.as_str());
+    state.vars.set("READLINE_POINT", &line.cursor().to_string());
+    if let Err(error) = eval(command, state) {
+        diagnostics::report(error, state);
+    }
+    let text = state.vars.get("READLINE_LINE").unwrap_or_default();
+    let point = (state.vars.get("READLINE_POINT"))
+        .and_then(|point| point.parse().ok())
+        .unwrap_or(text.len());
+    line.replace(text, point);
+    state.vars.unset("READLINE_LINE");
+    state.vars.unset("READLINE_POINT");
+
+    *raw = Some(RawMode::enable()?);
+    Ok(())
+}
+
+/// How many Ctrl-Ds in a row `ignoreeof` ignores: `IGNOREEOF`, or 10 if
+/// that isn't a number.
+fn eof_limit(state: &ShellState) -> usize {
+    (state.vars.get("IGNOREEOF"))
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(10)
+}
+
+/// The longest prefix every one of `words` starts with.
+fn common_prefix(words: &[String]) -> &str {
+    let Some((first, rest)) = words.split_first() else {
+        return "";
+    };
+    let mut len = first.len();
+    for word in rest {
+        len = first
+            .char_indices()
+            .zip(word.chars())
+            .take_while(|((i, a), b)| *i < len && a == b)
+            .map(|((i, a), _)| i + a.len_utf8())
+            .last()
+            .unwrap_or(0);
+    }
+    &first[..len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_prefixes() {
+        let words = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        assert_eq!(common_prefix(&words(&["status", "stash", "stat"])), "sta");
+        assert_eq!(common_prefix(&words(&["héllo", "hélp"])), "hél");
+        assert_eq!(common_prefix(&words(&["a", "b"])), "");
+        assert_eq!(common_prefix(&words(&["only"])), "only");
+        assert_eq!(common_prefix(&[]), "");
+    }
+
+    fn draw(screen: &mut Screen, prompt: &str, line: &LineBuffer, shown: &str) -> String {
+        let mut out = Vec::new();
+        screen.draw(&mut out, prompt, line, shown).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn renders_the_cursor_position() {
+        let mut line = LineBuffer::default();
+        line.insert_str("echo hi");
+        line.move_left();
+        let mut screen = Screen::new(80);
+        assert_eq!(
+            draw(&mut screen, "$ ", &line, line.as_str()),
+            "\r$ echo hi\x1b[J\r\x1b[8C"
+        );
+        assert_eq!(
+            draw(
+                &mut screen,
+                "\x1b[32m~\x1b[0m$ ",
+                &line,
+                "\x1b[32mecho\x1b[0m hi"
+            ),
+            "\r\x1b[32m~\x1b[0m$ \x1b[32mecho\x1b[0m hi\x1b[J\r\x1b[9C"
+        );
+    }
+
+    #[test]
+    fn wraps_long_lines() {
+        let mut line = LineBuffer::default();
+        line.insert_str("echo abcdef");
+        let mut screen = Screen::new(5);
+        // 13 columns take three rows, and the cursor ends on the last
+        assert_eq!(
+            draw(&mut screen, "$ ", &line, line.as_str()),
+            "\r$ echo abcdef\x1b[J\r\x1b[3C"
+        );
+
+        // Redrawing starts back on the prompt's row
+        line.move_home();
+        assert_eq!(
+            draw(&mut screen, "$ ", &line, line.as_str()),
+            "\x1b[2A\r$ echo abcdef\x1b[J\x1b[2A\r\x1b[2C"
+        );
+
+        // Filling the last row exactly moves onto the next one
+        line.move_end();
+        line.delete_backward();
+        line.delete_backward();
+        line.delete_backward();
+        assert_eq!(
+            draw(&mut screen, "$ ", &line, line.as_str()),
+            "\r$ echo abc\x1b[J\r\n\r"
+        );
+
+        let mut out = Vec::new();
+        screen.leave(&mut out, "^C").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\r^C\r\n");
+    }
+
+    #[test]
+    fn resizing_rewraps_the_line() {
+        let mut line = LineBuffer::default();
+        line.insert_str("echo abcdef");
+        let mut screen = Screen::new(80);
+        draw(&mut screen, "$ ", &line, line.as_str());
+        screen.resize(5);
+        assert!(draw(&mut screen, "$ ", &line, line.as_str()).starts_with("\x1b[2A\r"));
+    }
+}
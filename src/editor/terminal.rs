@@ -0,0 +1,323 @@
+use std::io::{self, Read};
+
+use crate::{
+    editor::keymap::{Chord, Key},
+    signals,
+    state::ShellState,
+};
+
+/// Puts the terminal on standard input in raw mode, where each key press is
+/// read as it happens and nothing is echoed, until it's dropped.
+pub(crate) struct RawMode {
+    #[cfg(unix)]
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    pub(crate) fn enable() -> io::Result<Self> {
+        // SAFETY: a termios is plain data, which tcgetattr fills in
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = saved;
+        // SAFETY: raw holds the settings tcgetattr filled in, which
+        // cfmakeraw changes and tcsetattr only reads
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Self { saved })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: saved holds the settings tcgetattr filled in
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.saved) };
+    }
+}
+
+#[cfg(not(unix))]
+impl RawMode {
+    pub(crate) fn enable() -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// The settings of the terminal on standard input, saved before a job
+/// takes it over, to put back if the job leaves them in a mess, as a
+/// full-screen program that crashes does.
+#[derive(Clone, Debug)]
+pub(crate) struct Settings {
+    #[cfg(unix)]
+    saved: libc::termios,
+}
+
+impl Settings {
+    /// The terminal's settings now, or `None` without a terminal.
+    pub(crate) fn save() -> Option<Self> {
+        #[cfg(unix)]
+        {
+            // SAFETY: a termios is plain data, which tcgetattr fills in
+            let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+            match unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } {
+                0 => Some(Self { saved }),
+                _ => None,
+            }
+        }
+        #[cfg(not(unix))]
+        None
+    }
+
+    /// Puts the settings back, once output written so far has been sent.
+    pub(crate) fn restore(&self) {
+        // SAFETY: saved holds the settings tcgetattr filled in
+        #[cfg(unix)]
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.saved)
+        };
+    }
+}
+
+/// How many columns wide the terminal on standard output is: what it says,
+/// or else the shell's `COLUMNS`, or else 80.
+pub(crate) fn width(state: &ShellState) -> usize {
+    #[cfg(unix)]
+    {
+        // SAFETY: a winsize is plain data, which TIOCGWINSZ fills in
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+            && size.ws_col > 0
+        {
+            return usize::from(size.ws_col);
+        }
+    }
+    (state.vars.get("COLUMNS"))
+        .and_then(|columns| columns.parse().ok())
+        .filter(|&columns| columns > 0)
+        .unwrap_or(80)
+}
+
+/// Something that happened at the terminal.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Input {
+    Key(Chord),
+    /// The window changed size, as `WINCH` says.
+    Resized,
+}
+
+/// Reads key presses from standard input in raw mode.
+#[derive(Debug, Default)]
+pub(crate) struct Keys {
+    /// Bytes read but not yet decoded.
+    pending: Vec<u8>,
+}
+
+impl Keys {
+    /// The next key pressed, or `None` once input ends. Sequences the
+    /// editor doesn't know are skipped. A resize is noticed while waiting
+    /// for a key if the shell is watching for `WINCH`.
+    pub(crate) fn next(&mut self) -> io::Result<Option<Input>> {
+        let winch = signals::number("WINCH").unwrap_or(28);
+        loop {
+            if let Some((chord, len)) = decode(&self.pending) {
+                self.pending.drain(..len);
+                match chord {
+                    Some(chord) => return Ok(Some(Input::Key(chord))),
+                    None => continue,
+                }
+            }
+            if signals::arrived(winch) {
+                return Ok(Some(Input::Resized));
+            }
+            match wait_for_input() {
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                result => result?,
+            }
+            // The terminal sends all of an escape sequence at once, so a
+            // read only stops partway through a character
+            let mut buf = [0; 64];
+            match io::stdin().lock().read(&mut buf)? {
+                0 => return Ok(None),
+                n => self.pending.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+}
+
+/// Waits until there's input to read. Unlike a read, this is interrupted by
+/// a signal even when the handler asks for calls to be restarted.
+#[cfg(unix)]
+fn wait_for_input() -> io::Result<()> {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: fd is the one pollfd poll is told about
+    match unsafe { libc::poll(&mut fd, 1, -1) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn wait_for_input() -> io::Result<()> {
+    Ok(())
+}
+
+/// Decodes the key at the start of `bytes`, returning it along with how
+/// many bytes it took. The key is `None` for a sequence that names no key
+/// the editor knows; the result is `None` if `bytes` ends partway through a
+/// character.
+pub(super) fn decode(bytes: &[u8]) -> Option<(Option<Chord>, usize)> {
+    let (&first, rest) = bytes.split_first()?;
+    let chord = |key| Some(Chord::new(key));
+    let control = |c: char| Some(Chord::ctrl(c));
+    match first {
+        0x1b => match rest.first() {
+            None => Some((chord(Key::Escape), 1)),
+            Some(b'[' | b'O') => {
+                let (chord, len) = decode_sequence(rest);
+                Some((chord, 1 + len))
+            }
+            // Escape before a key is how terminals send it with Alt held
+            Some(_) => {
+                let (chord, len) = decode(rest)?;
+                Some((chord.map(|chord| Chord { alt: true, ..chord }), 1 + len))
+            }
+        },
+        b'\r' | b'\n' => Some((chord(Key::Enter), 1)),
+        b'\t' => Some((chord(Key::Tab), 1)),
+        0x7f | 0x08 => Some((chord(Key::Backspace), 1)),
+        0 => Some((control(' '), 1)),
+        1..=26 => Some((control(char::from(b'a' + first - 1)), 1)),
+        0x1c..=0x1f => Some((control(char::from(first + 0x40)), 1)),
+        _ => {
+            let len = match first {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            let bytes = bytes.get(..len)?;
+            let c = std::str::from_utf8(bytes)
+                .ok()
+                .and_then(|s| s.chars().next());
+            Some((c.map(|c| Chord::new(Key::Char(c))), len))
+        }
+    }
+}
+
+/// Decodes a `CSI` or `SS3` sequence, starting from the `[` or `O` after
+/// the escape, like `[A` for Up or `[3;5~` for Ctrl-Delete.
+fn decode_sequence(bytes: &[u8]) -> (Option<Chord>, usize) {
+    let end = (bytes.iter().skip(1))
+        .position(|b| (0x40..=0x7e).contains(b))
+        .map_or(bytes.len(), |i| i + 2);
+    let params = std::str::from_utf8(&bytes[1..end.saturating_sub(1).max(1)]).unwrap_or("");
+    let final_byte = bytes.get(end - 1).copied().filter(|_| end > 1);
+
+    let mut params = params.split(';').map(|n| n.parse::<u8>().unwrap_or(1));
+    let number = params.next().unwrap_or(1);
+    // The modifier is 1 plus 1 for Shift, 2 for Alt, 4 for Ctrl, and 8 for
+    // Meta, which is taken as Alt too
+    let mut modifier = params.next().unwrap_or(1).saturating_sub(1);
+    if modifier & 8 != 0 {
+        modifier |= 2;
+    }
+
+    let key = match final_byte {
+        Some(b'A') => Key::Up,
+        Some(b'B') => Key::Down,
+        Some(b'C') => Key::Right,
+        Some(b'D') => Key::Left,
+        Some(b'H') => Key::Home,
+        Some(b'F') => Key::End,
+        Some(c @ b'P'..=b'S') => Key::Function(c - b'P' + 1),
+        // rxvt sends Ctrl with the arrows as SS3 and a lowercase letter
+        Some(c @ b'a'..=b'd') if bytes[0] == b'O' => {
+            let key = [Key::Up, Key::Down, Key::Right, Key::Left][usize::from(c - b'a')];
+            return (Some(Chord::ctrl_key(key)), end);
+        }
+        Some(b'~') => match number {
+            1 | 7 => Key::Home,
+            4 | 8 => Key::End,
+            3 => Key::Delete,
+            15 => Key::Function(5),
+            17..=21 => Key::Function(number - 11),
+            23 | 24 => Key::Function(number - 12),
+            _ => return (None, end),
+        },
+        _ => return (None, end),
+    };
+    let chord = Chord {
+        ctrl: modifier & 4 != 0,
+        alt: modifier & 2 != 0,
+        key,
+    };
+    (Some(chord), end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(bytes: &[u8]) -> Option<Chord> {
+        let (chord, len) = decode(bytes).expect("a whole key");
+        assert_eq!(len, bytes.len(), "{bytes:?}");
+        chord
+    }
+
+    #[test]
+    fn characters_and_controls() {
+        assert_eq!(key(b"a"), Some(Chord::new(Key::Char('a'))));
+        assert_eq!(key("é".as_bytes()), Some(Chord::new(Key::Char('é'))));
+        assert_eq!(key(b"\x01"), Some(Chord::ctrl('a')));
+        assert_eq!(key(b"\x17"), Some(Chord::ctrl('w')));
+        assert_eq!(key(b"\r"), Some(Chord::new(Key::Enter)));
+        assert_eq!(key(b"\t"), Some(Chord::new(Key::Tab)));
+        assert_eq!(key(b"\x7f"), Some(Chord::new(Key::Backspace)));
+        assert_eq!(key(b"\x1b"), Some(Chord::new(Key::Escape)));
+        assert_eq!(key(b"\x1b."), Some(Chord::alt('.')));
+    }
+
+    #[test]
+    fn escape_sequences() {
+        assert_eq!(key(b"\x1b[A"), Some(Chord::new(Key::Up)));
+        assert_eq!(key(b"\x1b[D"), Some(Chord::new(Key::Left)));
+        assert_eq!(key(b"\x1bOH"), Some(Chord::new(Key::Home)));
+        assert_eq!(key(b"\x1b[4~"), Some(Chord::new(Key::End)));
+        assert_eq!(key(b"\x1b[3~"), Some(Chord::new(Key::Delete)));
+        assert_eq!(key(b"\x1bOP"), Some(Chord::new(Key::Function(1))));
+        assert_eq!(key(b"\x1b[15~"), Some(Chord::new(Key::Function(5))));
+        assert_eq!(key(b"\x1b[24~"), Some(Chord::new(Key::Function(12))));
+        assert_eq!(
+            key(b"\x1b[1;5C"),
+            Some(Chord {
+                ctrl: true,
+                alt: false,
+                key: Key::Right
+            })
+        );
+        assert_eq!(key(b"\x1b[1;5D"), Some(Chord::ctrl_key(Key::Left)));
+        assert_eq!(key(b"\x1b[1;3C"), Some(Chord::alt_key(Key::Right)));
+        assert_eq!(key(b"\x1b[1;9D"), Some(Chord::alt_key(Key::Left)));
+        assert_eq!(key(b"\x1bOc"), Some(Chord::ctrl_key(Key::Right)));
+        assert_eq!(key(b"\x1b\x7f"), Some(Chord::alt_key(Key::Backspace)));
+        assert_eq!(key(b"\x1b[200~"), None);
+    }
+
+    #[test]
+    fn partial_input() {
+        assert_eq!(decode(&"é".as_bytes()[..1]), None);
+        assert_eq!(decode(b""), None);
+        let (chord, len) = decode(b"ab").unwrap();
+        assert_eq!((chord, len), (Some(Chord::new(Key::Char('a'))), 1));
+    }
+}
@@ -0,0 +1,1286 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    mem,
+    process::Child,
+    thread,
+    time::Instant,
+};
+
+#[cfg(unix)]
+use crate::signals;
+use crate::{
+    command::{
+        Command, CommandType, exit_status,
+        redirect::{Sink, Source, Streams, expand_redirects},
+    },
+    diagnostics,
+    expand::{expand_array, expand_name, expand_pattern, expand_unsplit, expand_words},
+    functions, glob,
+    jobs::{self, Job, ProcessGroup},
+    parser::{
+        self, AndOrList, Case, CommandNode, Connector, If, Pipeline, Select, SimpleCommand, While,
+    },
+    startup,
+    state::ShellState,
+    status::ExitStatus,
+    times::Timer,
+    trace,
+    util::RushError,
+    xtrace,
+};
+
+/// Parses and runs one command line, the way it would run at the prompt, and
+/// records the status it finished with.
+///
+/// A line may hold several pipelines joined by `;`, `&`, `&&`, and `||`.
+/// Each runs in turn unless its connector says otherwise, and a failure is
+/// reported as it happens without stopping the ones after it; the status of
+/// the last pipeline that ran is returned. A pipeline ended by `&` is started
+/// without waiting for it. Only a line that doesn't parse, or `exit`, is an
+/// error.
+pub(crate) fn eval(line: &str, state: &mut ShellState) -> Result<ExitStatus, RushError> {
+    let lists = match parser::parse_with_aliases(line, &state.aliases, state.vars.line()) {
+        Ok(lists) => lists,
+        Err(error) => {
+            state.last_status = error.status();
+            return Err(error);
+        }
+    };
+    trace::parse(line, &lists, state);
+    let streams = Streams {
+        out_is_stdout: true,
+        ..Streams::default()
+    };
+    run_lists(&lists, state, &streams, &mut io::stdout())
+}
+
+/// Runs `lists` with `streams` as the standard streams their commands start
+/// from, and returns the status of the last pipeline that ran. This is
+/// where `$?` is kept up to date. In a subshell, `exit` skips whatever is
+/// left, as `return` does in a function.
+pub(crate) fn run_lists(
+    lists: &[AndOrList],
+    state: &mut ShellState,
+    streams: &Streams,
+    out: &mut dyn Write,
+) -> Result<ExitStatus, RushError> {
+    for list in lists {
+        let last = list.pipelines.len() - 1;
+        for (i, (connector, pipeline)) in list.pipelines.iter().enumerate() {
+            if !should_run(*connector, state.last_status) {
+                continue;
+            }
+            // Only the last pipeline goes in the background, like the
+            // command before a lone `&` did
+            let background = list.background && i == last;
+            state.vars.set_line(pipeline.line);
+            let timer = (pipeline.time.is_some() && !background).then(Timer::start);
+            // `set -e` lets a pipeline fail on the left of `&&` or `||`, or
+            // after `!`, since its status is being tested
+            let tested = i < last || pipeline.negated;
+            state.errexit_ignored += usize::from(tested);
+            state.piped.clear();
+            let result = run_pipeline(pipeline, background, state, streams, out);
+            state.errexit_ignored -= usize::from(tested);
+            let mut status = match settle(result, streams, out, state) {
+                Ok(status) => status,
+                // Words that expanded to nothing leave the status as it was
+                Err(RushError::Nop) => state.last_status,
+                Err(error) => {
+                    state.last_status = error.status();
+                    return Err(error);
+                }
+            };
+            record_pipestatus(status, state);
+            if pipeline.negated && !background {
+                status = negate(status);
+            }
+            if let (Some(timer), Some(format)) = (timer, pipeline.time) {
+                streams.write_stderr(&timer.stop().report(format), out).ok();
+            }
+            state.last_status = status;
+            if !tested && errexit(pipeline, status, state) {
+                return Err(exit_shell(status, state));
+            }
+        }
+    }
+
+    Ok(state.last_status)
+}
+
+/// Settles the result of a command that ran with `streams`. A failure is
+/// reported on its stderr, and the command finishes with the status the
+/// failure carries. What unwinds, or still has to be dealt with, like a
+/// program stopped by Ctrl-Z, is passed on.
+pub(crate) fn settle(
+    result: Result<ExitStatus, RushError>,
+    streams: &Streams,
+    out: &mut dyn Write,
+    state: &ShellState,
+) -> Result<ExitStatus, RushError> {
+    match result {
+        Err(error @ RushError::Nop) => Err(error),
+        #[cfg(unix)]
+        Err(error @ RushError::Stopped(_)) => Err(error),
+        Err(error) if error.unwinds() => Err(error),
+        Err(error) => {
+            let status = error.status();
+            diagnostics::report(streams.report(error, out), state);
+            Ok(status)
+        }
+        Ok(status) => Ok(status),
+    }
+}
+
+/// Whether a pipeline joined by `connector` runs, given the status the
+/// pipeline before it finished with.
+pub(crate) fn should_run(connector: Connector, last_status: ExitStatus) -> bool {
+    match connector {
+        Connector::Always => true,
+        Connector::And => last_status == ExitStatus::SUCCESS,
+        Connector::Or => last_status != ExitStatus::SUCCESS,
+    }
+}
+
+/// Whether `set -e` ends the shell now that `pipeline` finished with
+/// `status`. A compound command isn't held to it, as the commands in it
+/// already were.
+fn errexit(pipeline: &Pipeline, status: ExitStatus, state: &ShellState) -> bool {
+    let compound =
+        matches!(&pipeline.commands[..], [command] if !matches!(command, CommandNode::Simple(_)));
+    state.options.errexit
+        && state.errexit_ignored == 0
+        && !compound
+        && status != ExitStatus::SUCCESS
+}
+
+/// Ends the shell with `status`, as `exit` or the end of input asks, unless
+/// it's interactive and would leave jobs behind. Then, like bash, it only
+/// warns, and goes ahead if asked again on the next command line.
+pub(crate) fn leave(status: ExitStatus, state: &mut ShellState) -> RushError {
+    if state.interactive
+        && !state.subshell
+        && !state.exit_warned
+        && let Some(warning) = state.jobs.exit_warning()
+    {
+        eprintln!("{warning}");
+        state.exit_warned = true;
+        return RushError::Reported(ExitStatus::FAILURE);
+    }
+    exit_shell(status, state)
+}
+
+/// Ends the shell with `status`, returning the error that unwinds whatever
+/// is running. Only a subshell ends when it's the one exiting; the shell
+/// itself remembers the status in `state.exited` first, so whatever reads
+/// its commands stops.
+fn exit_shell(status: ExitStatus, state: &mut ShellState) -> RushError {
+    if state.subshell {
+        return RushError::Exit(status);
+    }
+    startup::logout(state);
+    if state.options.huponexit {
+        state.jobs.hang_up();
+    }
+    io::stdout().flush().ok();
+    // `exit` in the logout file decides the status, as it did the moment
+    // it ran
+    RushError::Exit(*state.exited.get_or_insert(status))
+}
+
+/// Turns the result of a subshell into the result of the command that
+/// started it, since `exit` in a subshell only ends the subshell, as does
+/// `return` run there. So does a write to a pipe nobody reads from any
+/// more, which ends it quietly, the way `PIPE` ends a program.
+pub(crate) fn leave_subshell(
+    result: Result<ExitStatus, RushError>,
+) -> Result<ExitStatus, RushError> {
+    match result {
+        Err(RushError::Exit(status) | RushError::Return(status)) => Ok(status),
+        // The loops they'd leave are in the shell that started it
+        Err(RushError::Break(_) | RushError::Continue(_)) => Ok(ExitStatus::SUCCESS),
+        Err(RushError::BrokenPipe) => Ok(ExitStatus::broken_pipe()),
+        result => result,
+    }
+}
+
+/// Sets `PIPESTATUS` to the status of each command of the pipeline that
+/// just ran, the last of them being `status`, before any `!` inverts it.
+fn record_pipestatus(status: ExitStatus, state: &mut ShellState) {
+    let mut statuses = mem::take(&mut state.piped);
+    statuses.push(status);
+    let items = statuses
+        .into_iter()
+        .map(|status| (None, status.to_string()))
+        .collect();
+    state.vars.assign_array("PIPESTATUS", items).ok();
+}
+
+/// Inverts the status of a pipeline that started with `!`.
+fn negate(status: ExitStatus) -> ExitStatus {
+    match status {
+        ExitStatus::SUCCESS => ExitStatus::FAILURE,
+        _ => ExitStatus::SUCCESS,
+    }
+}
+
+fn run_pipeline(
+    pipeline: &Pipeline,
+    background: bool,
+    state: &mut ShellState,
+    streams: &Streams,
+    out: &mut dyn Write,
+) -> Result<ExitStatus, RushError> {
+    if let [command] = &pipeline.commands[..] {
+        return run_command(command, &pipeline.text, background, state, streams, out);
+    }
+
+    if background {
+        let text = &pipeline.text;
+        let Some(commands) = expand_stages(pipeline, state, streams)? else {
+            return run_in_background(text, state, streams, out, |state, streams, out| {
+                run_pipeline(pipeline, false, state, streams, out)
+            });
+        };
+        if commands
+            .iter()
+            .all(|command| matches!(command.type_, CommandType::Executable { .. }))
+        {
+            let group = jobs::job_control(state).then(ProcessGroup::background);
+            let children = spawn_stages(state, &commands, streams, group.as_ref())?;
+            announce(state.jobs.add(text, children));
+            return Ok(ExitStatus::SUCCESS);
+        }
+        // Builtins can't run apart from the shell, so the whole pipeline
+        // runs in a copy of it
+        return run_in_background(text, state, streams, out, |state, streams, out| {
+            run_stages(&commands, text, state, streams, out, run_expanded)
+        });
+    }
+
+    run_stages(
+        &pipeline.commands,
+        &pipeline.text,
+        state,
+        streams,
+        out,
+        |command, state, streams, out| {
+            run_command(command, &pipeline.text, false, state, streams, out)
+        },
+    )
+}
+
+/// Runs each of `stages` with `run`, with the output of each piped into the
+/// next, and waits for all of them. The pipeline finishes with the status
+/// of its last command, and the statuses of the others are kept in
+/// `state.piped`.
+///
+/// Every command runs in its own copy of the shell, so a builtin like `cd`
+/// in a pipeline doesn't change the shell itself. All but the last run on
+/// threads of their own and report their own errors. Once the command
+/// reading from one has exited, the one writing to it ends at its next
+/// write, loops and all, as a program would.
+///
+/// With job control, the programs they start share a process group, and
+/// if Ctrl-Z stops them, the pipeline waits in the job table as `text`.
+fn run_stages<T: Sync>(
+    stages: &[T],
+    text: &str,
+    state: &mut ShellState,
+    streams: &Streams,
+    out: &mut dyn Write,
+    run: impl Fn(&T, &mut ShellState, &Streams, &mut dyn Write) -> Result<ExitStatus, RushError> + Sync,
+) -> Result<ExitStatus, RushError> {
+    let (last, rest) = stages.split_last().expect("a pipeline has commands");
+    trace::pipeline(stages.len(), text, state);
+    let group = jobs::job_control(state).then(ProcessGroup::foreground);
+    let shell = &*state;
+    let subshell = || ShellState {
+        group: group.clone().or_else(|| shell.group.clone()),
+        ..shell.subshell()
+    };
+
+    let (result, statuses) = thread::scope(|scope| {
+        let mut input = None;
+        let mut threads = Vec::new();
+        for stage in rest {
+            let (reader, writer) = io::pipe().map_err(pipe_error)?;
+            let mut stage_streams = streams.apply(&[])?;
+            if let Some(source) = input.replace(Source::Pipe(reader)) {
+                stage_streams.stdin = Some(source);
+            }
+            stage_streams.stdout = Sink::Pipe(writer);
+
+            let mut state = subshell();
+            let run = &run;
+            threads.push(scope.spawn(move || {
+                let result = run(stage, &mut state, &stage_streams, &mut io::stdout());
+                leave_subshell(result).unwrap_or_else(|error| {
+                    let status = error.status();
+                    diagnostics::report(error, &state);
+                    status
+                })
+            }));
+        }
+
+        let mut last_streams = streams.apply(&[])?;
+        if let Some(source) = input {
+            last_streams.stdin = Some(source);
+        }
+        let result = run(last, &mut subshell(), &last_streams, out);
+        // Close the last pipe, so a command still writing to it stops
+        drop(last_streams);
+        let statuses = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap_or(ExitStatus::FAILURE))
+            .collect();
+        Ok::<_, RushError>((leave_subshell(result), statuses))
+    })?;
+    state.piped = statuses;
+
+    let Some(group) = group else {
+        return result;
+    };
+    group.finish(matches!(result, Ok(ExitStatus::SUCCESS)));
+    match group.take_stopped() {
+        stopped if stopped.is_empty() => result,
+        stopped => {
+            let pgid = group.pgid().expect("a stopped group has a leader");
+            Ok(suspend(text, stopped, pgid, state))
+        }
+    }
+}
+
+/// Expands every command of a pipeline ahead of running it, or returns
+/// `None` if any of them is a group or a function definition.
+fn expand_stages(
+    pipeline: &Pipeline,
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<Option<Vec<Command>>, RushError> {
+    let mut commands = Vec::new();
+    for command in &pipeline.commands {
+        let CommandNode::Simple(simple) = command else {
+            return Ok(None);
+        };
+        commands.push(simple);
+    }
+    commands
+        .into_iter()
+        .map(|simple| Command::from_simple(simple, state, streams))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// Starts every one of `commands` without waiting for them, with the output
+/// of each piped into the next, and all of them in `group` if there is one.
+fn spawn_stages(
+    state: &ShellState,
+    commands: &[Command],
+    streams: &Streams,
+    group: Option<&ProcessGroup>,
+) -> Result<Vec<Child>, RushError> {
+    let mut children = Vec::new();
+    let mut input = None;
+
+    for (i, command) in commands.iter().enumerate() {
+        let mut stage_streams = streams.apply(&[])?;
+        if let Some(source) = input.take() {
+            stage_streams.stdin = Some(source);
+        }
+        if i + 1 < commands.len() {
+            let (reader, writer) = io::pipe().map_err(pipe_error)?;
+            stage_streams.stdout = Sink::Pipe(writer);
+            input = Some(Source::Pipe(reader));
+        }
+        let child = command.spawn(state, &stage_streams, group)?;
+        children.push(child.expect("only executables are spawned"));
+    }
+
+    Ok(children)
+}
+
+fn pipe_error(error: io::Error) -> RushError {
+    RushError::RedirectError {
+        path: "pipe".into(),
+        msg: error.to_string(),
+    }
+}
+
+fn run_command(
+    command: &CommandNode,
+    text: &str,
+    background: bool,
+    state: &mut ShellState,
+    streams: &Streams,
+    out: &mut dyn Write,
+) -> Result<ExitStatus, RushError> {
+    // Only a program can be put in the background as it is, so anything
+    // else runs in a copy of the shell
+    let simple = match command {
+        CommandNode::Simple(simple) if !background || !simple.words.is_empty() => simple,
+        _ if background => {
+            return run_in_background(text, state, streams, out, |state, streams, out| {
+                run_command(command, text, false, state, streams, out)
+            });
+        }
+        CommandNode::Group(group) => {
+            let redirections = expand_redirects(&group.redirects, state, streams)?;
+            let streams = streams.apply(&redirections)?;
+            return run_lists(&group.body, state, &streams, out);
+        }
+        CommandNode::If(if_) => {
+            let redirections = expand_redirects(&if_.redirects, state, streams)?;
+            let streams = streams.apply(&redirections)?;
+            return run_if(if_, state, &streams, out);
+        }
+        CommandNode::While(while_) => {
+            let redirections = expand_redirects(&while_.redirects, state, streams)?;
+            let streams = streams.apply(&redirections)?;
+            state.loops += 1;
+            let result = run_while(while_, state, &streams, out);
+            state.loops -= 1;
+            return result;
+        }
+        CommandNode::Select(select) => {
+            let redirections = expand_redirects(&select.redirects, state, streams)?;
+            let streams = streams.apply(&redirections)?;
+            state.loops += 1;
+            let result = run_select(select, state, &streams, out);
+            state.loops -= 1;
+            return result.map_err(|error| streams.report(error, out));
+        }
+        CommandNode::Case(case) => {
+            let redirections = expand_redirects(&case.redirects, state, streams)?;
+            let streams = streams.apply(&redirections)?;
+            return run_case(case, state, &streams, out)
+                .map_err(|error| streams.report(error, out));
+        }
+        CommandNode::Function(function) => {
+            state.functions.set(function.clone());
+            return Ok(ExitStatus::SUCCESS);
+        }
+        CommandNode::Simple(simple) => simple,
+    };
+    if simple.words.is_empty() && !simple.assignments.is_empty() {
+        return assign(simple, state, streams);
+    }
+
+    let cmd = Command::from_simple(simple, state, streams)?;
+    // Once it's done, `$_` is the command's last argument, as in
+    // `mkdir dir && cd $_`
+    let last = cmd.args.last().cloned().unwrap_or_default();
+
+    if background {
+        let group = jobs::job_control(state).then(ProcessGroup::background);
+        let result = match cmd.spawn(state, streams, group.as_ref())? {
+            Some(child) => {
+                announce(state.jobs.add(text, vec![child]));
+                Ok(ExitStatus::SUCCESS)
+            }
+            // A builtin or a function would change the shell itself
+            None => run_in_background(text, state, streams, out, |state, streams, out| {
+                run_expanded(&cmd, state, streams, out)
+            }),
+        };
+        state.vars.set("_", &last);
+        return result;
+    }
+
+    let result = run_expanded(&cmd, state, streams, out);
+    state.vars.set("_", &last);
+    match result {
+        // Ctrl-Z stopped it, so it waits in the job table for `fg` or `bg`,
+        // along with the rest of the pipeline it's part of
+        #[cfg(unix)]
+        Err(RushError::Stopped(child)) => match &state.group {
+            Some(group) => {
+                group.stopped(child);
+                Ok(ExitStatus::stopped())
+            }
+            None => {
+                let pgid = child.id();
+                Ok(suspend(text, vec![child], pgid, state))
+            }
+        },
+        result => result,
+    }
+}
+
+/// Runs `run` in a copy of the shell forked into the background, which is
+/// put in the job table as `text`, for a command started with `&` that
+/// isn't a program. Where the shell can't fork, or its output is being
+/// captured, it runs in the foreground instead; a command substitution
+/// waits for the output to end either way.
+fn run_in_background(
+    text: &str,
+    state: &mut ShellState,
+    streams: &Streams,
+    out: &mut dyn Write,
+    run: impl FnOnce(&mut ShellState, &Streams, &mut dyn Write) -> Result<ExitStatus, RushError>,
+) -> Result<ExitStatus, RushError> {
+    #[cfg(unix)]
+    let captured = [&streams.stdout, &streams.stderr]
+        .into_iter()
+        .any(|sink| matches!(sink, Sink::Out) && !streams.out_is_stdout);
+    #[cfg(unix)]
+    if !captured {
+        let group = jobs::job_control(state).then(ProcessGroup::background);
+        // Or the copy would write it again
+        out.flush().ok();
+        let forked = jobs::fork(group.as_ref(), || {
+            signals::end_by_forwarded();
+            state.subshell = true;
+            state.group = None;
+            // Its jobs are the shell's, which it can't wait for
+            state.jobs = state.jobs.snapshot();
+            let result = leave_subshell(run(state, streams, out));
+            let status = result.unwrap_or_else(|error| {
+                let status = error.status();
+                diagnostics::report(streams.report(error, out), state);
+                status
+            });
+            out.flush().ok();
+            status.code()
+        });
+        let pid = forked.map_err(|error| RushError::RedirectError {
+            path: "fork".into(),
+            msg: error.to_string(),
+        })?;
+        announce(state.jobs.add_shell(text, pid));
+        return Ok(ExitStatus::SUCCESS);
+    }
+    #[cfg(not(unix))]
+    let _ = text;
+    run(state, streams, out)
+}
+
+/// Puts `children`, the processes of a pipeline Ctrl-Z stopped in process
+/// group `pgid`, in the job table to wait for `fg` or `bg`. The pipeline
+/// finishes with the status of a stopped command.
+fn suspend(text: &str, children: Vec<Child>, pgid: u32, state: &mut ShellState) -> ExitStatus {
+    let job = state.jobs.add_stopped(text, children, pgid);
+    eprintln!("\n{}", job.listing('+', None, false));
+    ExitStatus::stopped()
+}
+
+/// Runs the body of the first clause of `if_` whose condition succeeds, or
+/// its `else` body if none does. A condition that fails only chooses the
+/// next clause; with no body run, the `if` succeeds.
+fn run_if(
+    if_: &If,
+    state: &mut ShellState,
+    streams: &Streams,
+    out: &mut dyn Write,
+) -> Result<ExitStatus, RushError> {
+    for (condition, body) in &if_.clauses {
+        if run_condition(condition, state, streams, out)? == ExitStatus::SUCCESS {
+            return run_lists(body, state, streams, out);
+        }
+    }
+    match &if_.else_body {
+        Some(body) => run_lists(body, state, streams, out),
+        None => Ok(ExitStatus::SUCCESS),
+    }
+}
+
+/// Runs the condition of an `if` or a loop, which `set -e` lets fail.
+fn run_condition(
+    condition: &[AndOrList],
+    state: &mut ShellState,
+    streams: &Streams,
+    out: &mut dyn Write,
+) -> Result<ExitStatus, RushError> {
+    state.errexit_ignored += 1;
+    let result = run_lists(condition, state, streams, out);
+    state.errexit_ignored -= 1;
+    result
+}
+
+/// Runs the body of `while_` for as long as its condition succeeds, or with
+/// `until`, for as long as it fails. The loop finishes with the status of
+/// the last body run, or succeeds if none was.
+fn run_while(
+    while_: &While,
+    state: &mut ShellState,
+    streams: &Streams,
+    out: &mut dyn Write,
+) -> Result<ExitStatus, RushError> {
+    let mut status = ExitStatus::SUCCESS;
+    loop {
+        let result = run_condition(&while_.condition, state, streams, out);
+        match next_turn(result) {
+            Turn::Next => {}
+            Turn::Skip => continue,
+            Turn::Leave(result) => return result,
+        }
+        if (state.last_status == ExitStatus::SUCCESS) == while_.until {
+            break;
+        }
+
+        let result = run_lists(&while_.body, state, streams, out);
+        match next_turn(result) {
+            Turn::Leave(result) => return result,
+            Turn::Next | Turn::Skip => status = state.last_status,
+        }
+    }
+
+    Ok(status)
+}
+
+/// Runs the body of the first clause of `case` with a pattern that matches
+/// its word. Patterns are expanded in turn only until one matches, and the
+/// `case` succeeds if none does or the body is empty.
+fn run_case(
+    case: &Case,
+    state: &mut ShellState,
+    streams: &Streams,
+    out: &mut dyn Write,
+) -> Result<ExitStatus, RushError> {
+    let word = expand_unsplit(&case.word, state, streams)?;
+    for (patterns, body) in &case.clauses {
+        for pattern in patterns {
+            if !glob::matches(&expand_pattern(pattern, state, streams)?, &word) {
+                continue;
+            }
+            return match body.is_empty() {
+                true => Ok(ExitStatus::SUCCESS),
+                false => run_lists(body, state, streams, out),
+            };
+        }
+    }
+    Ok(ExitStatus::SUCCESS)
+}
+
+/// Runs the body of `select` with its variable set to each word picked from
+/// its menu, which is shown on stderr along with `$PS3` as a prompt. A line
+/// that isn't a number on the menu sets the variable empty, and an empty
+/// one shows the menu again. The line read is kept in `REPLY`. It goes on
+/// until `break` or the end of the input, which it fails on.
+fn run_select(
+    select: &Select,
+    state: &mut ShellState,
+    streams: &Streams,
+    out: &mut dyn Write,
+) -> Result<ExitStatus, RushError> {
+    let words = match &select.words {
+        Some(words) => expand_words(words, state, streams)?,
+        None => state.args.clone(),
+    };
+    if words.is_empty() {
+        return Ok(ExitStatus::SUCCESS);
+    }
+    let width = words.len().to_string().len();
+    let menu: String = (words.iter().enumerate())
+        .map(|(i, word)| format!("{:>width$}) {word}\n", i + 1))
+        .collect();
+
+    let mut show_menu = true;
+    loop {
+        let prompt = state.vars.get("PS3").unwrap_or_else(|| "#? ".into());
+        let shown = match show_menu {
+            true => format!("{menu}{prompt}"),
+            false => prompt,
+        };
+        streams.write_stderr(&shown, out).ok();
+
+        let read = streams
+            .read_line()
+            .map_err(|error| RushError::RedirectError {
+                path: "select".into(),
+                msg: error.to_string(),
+            });
+        let Some(reply) = read? else {
+            streams.write_stderr("\n", out).ok();
+            return Ok(ExitStatus::FAILURE);
+        };
+        show_menu = reply.is_empty();
+        state.vars.assign("REPLY", &reply)?;
+        if show_menu {
+            continue;
+        }
+        let picked = (reply.trim().parse::<usize>().ok())
+            .and_then(|choice| words.get(choice.checked_sub(1)?));
+        state
+            .vars
+            .assign(&select.name, picked.map_or("", String::as_str))?;
+
+        let result = run_lists(&select.body, state, streams, out);
+        if let Turn::Leave(result) = next_turn(result) {
+            return result;
+        }
+    }
+}
+
+/// What a loop does after one of its lists ran.
+enum Turn {
+    /// Carries on with the next list.
+    Next,
+    /// Starts the next turn, after `continue`.
+    Skip,
+    /// Stops, finishing with this result.
+    Leave(Result<ExitStatus, RushError>),
+}
+
+/// Works out what a loop does after one of its lists ran with `result`.
+/// `break` and `continue` reaching further out are passed on with one
+/// loop fewer to go, and a command interrupted by Ctrl-C stops the loop
+/// too, so it isn't run again straight away. Otherwise the loop carries on,
+/// whatever the status.
+fn next_turn(result: Result<ExitStatus, RushError>) -> Turn {
+    match result {
+        Err(RushError::Break(1)) => Turn::Leave(Ok(ExitStatus::SUCCESS)),
+        Err(RushError::Break(count)) => Turn::Leave(Err(RushError::Break(count - 1))),
+        Err(RushError::Continue(1)) => Turn::Skip,
+        Err(RushError::Continue(count)) => Turn::Leave(Err(RushError::Continue(count - 1))),
+        Err(error) => Turn::Leave(Err(error)),
+        Ok(status) if status == ExitStatus::from_signal(libc::SIGINT) => Turn::Leave(Ok(status)),
+        Ok(_) => Turn::Next,
+    }
+}
+
+/// Runs a command made of nothing but assignments, which set variables in
+/// the shell itself. Its redirections still open their files. Its status is
+/// that of the last command substitution in it, as in `x=$(false)`, or
+/// success if there's none.
+fn assign(
+    simple: &SimpleCommand,
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<ExitStatus, RushError> {
+    state.substituted = None;
+    let redirections = expand_redirects(&simple.redirects, state, streams)?;
+    streams.apply(&redirections)?;
+
+    // Each is set before the next is expanded, so `A=a B=$A` sees the new `A`
+    for assignment in &simple.assignments {
+        let name = expand_name(&assignment.name, state, streams)?;
+        match &assignment.array {
+            Some(elements) => {
+                let items = expand_array(elements, state, streams)?;
+                xtrace::array(&name, &items, state);
+                state.vars.assign_array(&name, items)?;
+            }
+            None => {
+                let value = expand_unsplit(&assignment.value, state, streams)?;
+                xtrace::command(&[(name.clone(), value.clone())], &[], state);
+                state.vars.assign(&name, &value)?;
+            }
+        }
+    }
+    Ok(state.substituted.take().unwrap_or(ExitStatus::SUCCESS))
+}
+
+/// Shows the number and process id of a job that was just started.
+fn announce(job: &Job) {
+    if io::stdin().is_terminal() {
+        eprintln!("[{}] {}", job.id, job.pid());
+    }
+}
+
+fn run_expanded(
+    cmd: &Command,
+    state: &mut ShellState,
+    streams: &Streams,
+    out: &mut dyn Write,
+) -> Result<ExitStatus, RushError> {
+    if let CommandType::Exit = cmd.type_ {
+        let status = exit_status(&cmd.args, state)?;
+        return Err(leave(status, state));
+    }
+
+    let started = Instant::now();
+    let result = cmd.run_in(state, streams, out);
+
+    // Everything that needs to know a command finished hooks in here
+    state
+        .stats
+        .record(&cmd.type_.to_string(), started.elapsed());
+    if mem::take(&mut state.dir_changed) {
+        run_chpwd(state, streams, out);
+    }
+
+    result
+}
+
+/// Runs the `chpwd` function, then each function named in the
+/// `chpwd_functions` array, after a command changed directory, as zsh does.
+/// What they fail with is reported, but the command that changed directory
+/// finishes with its own status.
+fn run_chpwd(state: &mut ShellState, streams: &Streams, out: &mut dyn Write) {
+    let mut names = vec!["chpwd".to_owned()];
+    if let Some(array) = state.vars.array("chpwd_functions") {
+        names.extend(array.values());
+    }
+
+    state.in_chpwd = true;
+    for name in names {
+        let Some(function) = state.functions.get(&name) else {
+            continue;
+        };
+        let result = functions::call(&function, &[name], state, streams, out);
+        if let Err(error) = result {
+            diagnostics::report(streams.report(error, out), state);
+        }
+    }
+    state.in_chpwd = false;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn counts_each_command_run() {
+        let mut state = ShellState::default();
+        for line in ["echo a", "pwd", "echo b", "", "true", "echo c", "false"] {
+            eval(line, &mut state).ok();
+        }
+
+        assert_eq!(state.stats.get("echo").unwrap().runs, 3);
+        assert_eq!(state.stats.get("pwd").unwrap().runs, 1);
+        // Failing commands still ran
+        assert_eq!(state.stats.get("false").unwrap().runs, 1);
+
+        let top: Vec<_> = state.stats.top_by_runs(2).iter().map(|(n, _)| *n).collect();
+        assert_eq!(top, vec!["echo", "false"]);
+    }
+
+    #[test]
+    fn commands_that_never_ran_are_not_counted() {
+        let mut state = ShellState::default();
+        assert_eq!(
+            eval("definitely_not_a_command_5112", &mut state).unwrap(),
+            ExitStatus::NOT_FOUND
+        );
+        assert!(eval("echo 'unterminated", &mut state).is_err());
+
+        assert!(state.stats.top_by_runs(10).is_empty());
+    }
+
+    #[test]
+    fn records_last_status() {
+        let mut state = ShellState::default();
+        for (line, expected) in [
+            ("sh -c 'exit 300'", 44),
+            ("true", 0),
+            ("sh -c 'kill -9 $$'", 137),
+            ("   ", 137),
+            ("definitely_not_a_command_5112", 127),
+            ("echo 'unterminated", 2),
+            ("cd -z", 2),
+        ] {
+            if let Ok(status) = eval(line, &mut state) {
+                assert_eq!(status.code(), expected, "{line}");
+            }
+            assert_eq!(state.last_status.code(), expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn runs_each_command_in_a_list() {
+        let mut state = ShellState::default();
+        eval("echo a; false; echo b", &mut state).unwrap();
+        assert_eq!(state.stats.get("echo").unwrap().runs, 2);
+        assert_eq!(state.stats.get("false").unwrap().runs, 1);
+        assert_eq!(state.last_status.code(), 0);
+
+        // The status is the last command's, even when an earlier one failed
+        let status = eval("true; definitely_not_a_command_5112", &mut state).unwrap();
+        assert_eq!(status, ExitStatus::NOT_FOUND);
+        assert_eq!(state.last_status.code(), 127);
+        let status = eval("false; sh -c 'exit 3'", &mut state).unwrap();
+        assert_eq!(status.code(), 3);
+    }
+
+    #[test]
+    fn and_or_lists_short_circuit() {
+        for (line, echoes, status) in [
+            ("true && echo a", 1, 0),
+            ("false && echo a", 0, 1),
+            ("false || echo a", 1, 0),
+            ("true || echo a", 0, 0),
+            // Left to right: the `||` sees the status of the skipped `&&`'s left side
+            ("false && echo a || echo b", 1, 0),
+            ("true && false || echo b", 1, 0),
+            ("true || false && echo b", 1, 0),
+        ] {
+            let mut state = ShellState::default();
+            eval(line, &mut state).ok();
+            let runs = state.stats.get("echo").map_or(0, |usage| usage.runs);
+            assert_eq!(runs, echoes, "{line}");
+            assert_eq!(state.last_status.code(), status, "{line}");
+        }
+    }
+
+    #[test]
+    fn background_commands_dont_block() {
+        let mut state = ShellState::default();
+        let started = Instant::now();
+        eval("sleep 0.3 & false", &mut state).ok();
+        assert!(started.elapsed().as_millis() < 300);
+        assert_eq!(state.last_status.code(), 1);
+
+        // `&` itself succeeds, whatever the job does later
+        eval("false &", &mut state).unwrap();
+        assert_eq!(state.last_status.code(), 0);
+
+        let mut finished = Vec::new();
+        while finished.len() < 2 && started.elapsed().as_secs() < 5 {
+            finished.extend(state.jobs.reap());
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let mut ids: Vec<_> = finished.iter().map(|(job, _)| job.id).collect();
+        ids.sort();
+        assert_eq!(ids, [1, 2]);
+    }
+
+    #[test]
+    fn groups_share_their_redirections() {
+        let dir = std::env::temp_dir().join(format!("rush_eval_group_{}", process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("out").display().to_string();
+        let err = dir.join("err").display().to_string();
+
+        let mut state = ShellState::default();
+        let line = format!(
+            "{{ echo a; sh -c 'echo b >&2'; echo c > {err}.c; nonexistent_5112; }} > {out} 2> {err}"
+        );
+        assert_eq!(eval(&line, &mut state).unwrap(), ExitStatus::NOT_FOUND);
+        assert_eq!(state.last_status.code(), 127);
+
+        let read = |path: &str| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(&out), "a\n");
+        assert_eq!(read(&err), "b\nnonexistent_5112: command not found\n");
+        // A command's own redirection still wins over the group's
+        assert_eq!(read(&format!("{err}.c")), "c\n");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn time_reports_to_stderr() {
+        let path = std::env::temp_dir().join(format!("rush_eval_time_{}", process::id()));
+        let path = path.display().to_string();
+        let mut state = ShellState::default();
+        eval(&format!("{{ time -p sleep 0.05; }} 2> {path}"), &mut state).unwrap();
+
+        let report = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = report.lines().collect();
+        assert_eq!(lines.len(), 3, "{report:?}");
+        let real: f64 = lines[0].strip_prefix("real ").unwrap().parse().unwrap();
+        assert!(real >= 0.05, "{report:?}");
+        assert!(lines[1].starts_with("user ") && lines[2].starts_with("sys "));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn groups_run_in_the_current_shell() {
+        let mut state = ShellState::default();
+        eval("{ false; true; } && { echo a; false; }", &mut state).ok();
+        assert_eq!(state.stats.get("echo").unwrap().runs, 1);
+        assert_eq!(state.last_status.code(), 1);
+    }
+
+    #[test]
+    fn if_runs_the_first_clause_that_succeeds() {
+        for (line, pwds, status) in [
+            ("if true; then pwd; fi", 1, 0),
+            ("if false; then pwd; fi", 0, 0),
+            (
+                "if false; then echo; elif true; then pwd; pwd; else echo; fi",
+                2,
+                0,
+            ),
+            (
+                "if false; then echo; elif false; then echo; else pwd; false; fi",
+                1,
+                1,
+            ),
+            ("if ! pwd; then pwd; fi", 1, 0),
+            ("if true; then false; fi || pwd", 1, 0),
+        ] {
+            let mut state = ShellState::default();
+            eval(line, &mut state).ok();
+            let runs = state.stats.get("pwd").map_or(0, |usage| usage.runs);
+            assert_eq!(runs, pwds, "{line}");
+            assert!(state.stats.get("echo").is_none(), "{line}");
+            assert_eq!(state.last_status.code(), status, "{line}");
+        }
+    }
+
+    #[test]
+    fn loops_run_until_their_condition_says() {
+        let mut state = ShellState::default();
+        eval("until false; do pwd; break; done", &mut state).unwrap();
+        eval("while false; do echo; done", &mut state).unwrap();
+        assert_eq!(state.stats.get("pwd").unwrap().runs, 1);
+        assert!(state.stats.get("echo").is_none());
+
+        // The loop finishes with the status of the last body run
+        eval("while true; do false; break 2; done", &mut state).unwrap();
+        assert_eq!(state.last_status.code(), 0);
+        eval(
+            "until pwd; do true; done; while pwd; do false; break; done",
+            &mut state,
+        )
+        .unwrap();
+        assert_eq!(state.stats.get("pwd").unwrap().runs, 3);
+        assert_eq!(state.loops, 0);
+    }
+
+    #[test]
+    fn break_and_continue_reach_out_through_loops() {
+        let mut state = ShellState::default();
+        // `continue 2` skips what's after the inner loop each time round,
+        // until `break 2` leaves both
+        let line = "i=a; while true; do until false; do pwd; \
+            if [ $i = aa ]; then break 2; fi; i=a$i; continue 2; done; echo; done";
+        eval(line, &mut state).unwrap();
+        assert_eq!(state.stats.get("pwd").unwrap().runs, 2);
+        assert!(state.stats.get("echo").is_none());
+
+        // A function can't leave the loop it was called from
+        let line = "while true; do f() { break; }; f; pwd; break; done";
+        eval(line, &mut state).ok();
+        assert_eq!(state.stats.get("pwd").unwrap().runs, 3);
+        assert_eq!(eval("continue", &mut state).unwrap(), ExitStatus::SUCCESS);
+        assert_eq!(state.last_status.code(), 0);
+    }
+
+    #[test]
+    fn errexit_ends_on_untested_failures() {
+        for (line, status) in [
+            ("false; pwd", Some(1)),
+            ("false || false; pwd", Some(1)),
+            ("f() { return 2; }; f; pwd", Some(2)),
+            ("f() { false && true; }; f; pwd", Some(1)),
+            ("true | false; pwd", Some(1)),
+            ("false && true; pwd", None),
+            ("! pwd; pwd", None),
+            ("false | true; pwd", None),
+            ("{ false && true; }; pwd", None),
+            ("if false; then :; fi; pwd", None),
+            ("until false && true; do break; done; pwd", None),
+            ("f() { false; pwd; }; f || true", None),
+        ] {
+            // In a subshell it leaves an error to see instead of exiting
+            let mut state = ShellState {
+                subshell: true,
+                ..ShellState::default()
+            };
+            state.options.errexit = true;
+            let result = eval(line, &mut state);
+            match status {
+                Some(status) => {
+                    assert!(
+                        matches!(result, Err(RushError::Exit(s)) if s.code() == status),
+                        "{line}"
+                    );
+                    assert!(state.stats.get("pwd").is_none(), "{line}");
+                }
+                None => assert!(state.stats.get("pwd").is_some(), "{line}"),
+            }
+            assert_eq!(state.errexit_ignored, 0, "{line}");
+        }
+    }
+
+    #[test]
+    fn case_runs_the_first_clause_that_matches() {
+        for (line, pwds, status) in [
+            ("case abc in a) echo;; a*|x) pwd;; *) echo;; esac", 1, 0),
+            ("case abc in 'a*') echo;; esac", 0, 0),
+            ("false; case abc in x) echo;; esac", 0, 0),
+            ("false; case abc in abc) ;; esac", 0, 0),
+            ("p='?b?'; case abc in $p) pwd; false;; esac", 1, 1),
+            ("case \"$HOME\" in \"$HOME\") pwd;; esac", 1, 0),
+        ] {
+            let mut state = ShellState::default();
+            eval(line, &mut state).ok();
+            let runs = state.stats.get("pwd").map_or(0, |usage| usage.runs);
+            assert_eq!(runs, pwds, "{line}");
+            assert!(state.stats.get("echo").is_none(), "{line}");
+            assert_eq!(state.last_status.code(), status, "{line}");
+        }
+    }
+
+    #[test]
+    fn select_runs_with_each_choice() {
+        let mut state = ShellState::default();
+        let line = "select x in a b c; do pwd; [ \"$x\" = c ] && break; done 2>/dev/null <<EOF\n\
+            2\n\nnope\n3\nEOF";
+        eval(line, &mut state).unwrap();
+        assert_eq!(state.stats.get("pwd").unwrap().runs, 3);
+        assert_eq!(state.vars.get("x").as_deref(), Some("c"));
+        assert_eq!(state.vars.get("REPLY").as_deref(), Some("3"));
+
+        // The end of the input ends it
+        let line = "select x in a; do pwd; done 2>/dev/null <<EOF\n1\nEOF";
+        assert_eq!(eval(line, &mut state).unwrap(), ExitStatus::FAILURE);
+        assert_eq!(state.stats.get("pwd").unwrap().runs, 4);
+        assert_eq!(state.last_status.code(), 1);
+    }
+
+    #[test]
+    fn pipelines_connect_their_commands() {
+        let dir = std::env::temp_dir().join(format!("rush_eval_pipeline_{}", process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("out").display().to_string();
+
+        let mut state = ShellState::default();
+        let line = format!("printf 'b\\na\\n' | sort | {{ cat; echo c; }} > {out}");
+        eval(&line, &mut state).unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "a\nb\nc\n");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn pipelines_finish_with_their_last_status() {
+        for (line, status) in [
+            ("false | true", 0),
+            ("true | false", 1),
+            ("true | definitely_not_a_command_5112", 127),
+            // `exit` only ends its own side of the pipe
+            ("exit 3 | true", 0),
+            ("true | exit 3", 3),
+            ("yes | head -1 >/dev/null", 0),
+        ] {
+            let mut state = ShellState::default();
+            eval(line, &mut state).ok();
+            assert_eq!(state.last_status.code(), status, "{line}");
+        }
+    }
+
+    #[test]
+    fn pipestatus_holds_every_status_of_a_pipeline() {
+        for (line, statuses) in [
+            ("true | false | sh -c 'exit 3'", vec!["0", "1", "3"]),
+            ("exit 4 | definitely_not_a_command_5112", vec!["4", "127"]),
+            // before `!` inverts the last of them
+            ("! false | true", vec!["1", "0"]),
+            ("false", vec!["1"]),
+        ] {
+            let mut state = ShellState::default();
+            eval(line, &mut state).ok();
+            let pipestatus = state.vars.array("PIPESTATUS").unwrap().values();
+            assert_eq!(pipestatus, statuses, "{line}");
+        }
+    }
+
+    #[test]
+    fn handlers_run_commands_that_cant_be_found() {
+        let dir = std::env::temp_dir().join(format!("rush_eval_not_found_{}", process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("out").display().to_string();
+
+        // The function runs in a subshell, and can't find commands itself
+        let mut state = ShellState::default();
+        let line = format!(
+            "command_not_found_handle() {{ seen=1; echo \"$*\"; nope_5112 2>/dev/null; return 3; }}; nope_5112 a b > {out}"
+        );
+        eval(&line, &mut state).ok();
+        assert_eq!(state.last_status.code(), 3);
+        assert_eq!(state.vars.get("seen"), None);
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "nope_5112 a b\n");
+
+        // Without the function, the program the config names runs instead
+        let mut state = ShellState::default();
+        state.hooks.command_not_found = Some("echo".into());
+        eval(&format!("nope_5112 c | cat > {out}"), &mut state).unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "nope_5112 c\n");
+
+        state.hooks.command_not_found = Some("nope_handler_5112".into());
+        eval("nope_5112 2>/dev/null", &mut state).ok();
+        assert_eq!(state.last_status.code(), 127);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn background_pipelines_are_one_job() {
+        let mut state = ShellState::default();
+        let started = Instant::now();
+        eval("sleep 0.3 | sh -c 'exit 4' &", &mut state).unwrap();
+        assert!(started.elapsed().as_millis() < 300);
+
+        let mut finished = Vec::new();
+        while finished.is_empty() && started.elapsed().as_secs() < 5 {
+            finished.extend(state.jobs.reap());
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        // It waits for every command, but takes the status of the last
+        assert!(started.elapsed().as_millis() >= 300);
+        let (job, status) = &finished[0];
+        assert_eq!(job.command, "sleep 0.3 | sh -c 'exit 4'");
+        assert_eq!(status.code(), 4);
+    }
+
+    #[test]
+    fn assignments_before_a_command_are_its_own() {
+        let mut state = ShellState::default();
+        eval("RUSH_EVAL_TEST_PREFIX=1 true", &mut state).unwrap();
+        assert!(state.vars.get("RUSH_EVAL_TEST_PREFIX").is_none());
+
+        // Without a command, they set a shell variable that programs don't
+        // see until it is exported
+        eval(
+            "RUSH_EVAL_TEST_SET=a RUSH_EVAL_TEST_SET=b$RUSH_EVAL_TEST_SET",
+            &mut state,
+        )
+        .unwrap();
+        assert_eq!(state.vars.get("RUSH_EVAL_TEST_SET").unwrap(), "ba");
+        assert!(!state.vars.is_exported("RUSH_EVAL_TEST_SET"));
+    }
+
+    #[test]
+    fn assignments_take_the_status_of_their_last_substitution() {
+        for (line, status) in [
+            ("x=$(false)", 1),
+            ("x=$(exit 3)", 3),
+            ("x=$(exit 3) y=$(true)", 0),
+            ("x=$(true) y=$(exit 4)", 4),
+            ("false; x=1", 0),
+            ("x=$(false); y=1", 0),
+        ] {
+            let mut state = ShellState::default();
+            eval(line, &mut state).ok();
+            assert_eq!(state.last_status.code(), status, "{line}");
+        }
+    }
+
+    #[test]
+    fn bang_inverts_the_status() {
+        for (line, status) in [
+            ("! true", 1),
+            ("! false", 0),
+            ("! true | false", 0),
+            ("! definitely_not_a_command_5112", 0),
+            ("! false && echo a", 0),
+            ("! true || echo a", 0),
+            ("! { true; false; }", 0),
+        ] {
+            let mut state = ShellState::default();
+            eval(line, &mut state).ok();
+            assert_eq!(state.last_status.code(), status, "{line}");
+        }
+    }
+
+    #[test]
+    fn list_syntax_errors_run_nothing() {
+        let mut state = ShellState::default();
+        assert!(eval("echo a;; echo b", &mut state).is_err());
+        assert_eq!(state.last_status.code(), 2);
+        assert!(state.stats.top_by_runs(10).is_empty());
+    }
+
+    #[test]
+    fn time_is_accumulated() {
+        let mut state = ShellState::default();
+        eval("sleep 0.05", &mut state).unwrap();
+        eval("true", &mut state).unwrap();
+
+        let top: Vec<_> = state.stats.top_by_time(1).iter().map(|(n, _)| *n).collect();
+        assert_eq!(top, vec!["sleep"]);
+        assert!(state.stats.get("sleep").unwrap().time.as_millis() >= 50);
+    }
+}
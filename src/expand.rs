@@ -0,0 +1,1201 @@
+use std::{
+    io::{self, Write},
+    process,
+};
+
+use crate::{
+    arith, braces,
+    command::{redirect::Streams, stack_reference},
+    diagnostics, eval, glob, lexer,
+    parser::{self, Assignment},
+    state::ShellState,
+    transform,
+    util::{Modifier, ModifierKind, RushError, Word, WordPart, is_name},
+    variables::split_subscript,
+};
+
+/// Expands `words` into the arguments a command runs with. Each word may
+/// produce zero or more arguments once braces are expanded, substitution
+/// results are split, and patterns are matched against filenames.
+pub(crate) fn expand_words(
+    words: &[Word],
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<Vec<String>, RushError> {
+    let mut fields = Vec::new();
+
+    for word in words.iter().flat_map(braces::expand) {
+        expand_word(&word, state, streams, &mut fields)?;
+    }
+
+    let mut args = Vec::new();
+    for field in fields {
+        // A pattern that matches nothing is passed on as written
+        let paths = match field.glob {
+            true => glob::expand(&field.pattern, state.cwd.path(), &state.options),
+            false => Vec::new(),
+        };
+        match paths.is_empty() {
+            true => args.push(field.text),
+            false => args.extend(paths),
+        }
+    }
+
+    Ok(args)
+}
+
+/// An argument being built. Alongside its text it keeps the pattern it's
+/// matched against filenames with, where quoted glob characters are escaped.
+#[derive(Debug, Default)]
+struct Field {
+    text: String,
+    pattern: String,
+    /// Whether an unquoted part of the field has a glob character.
+    glob: bool,
+}
+
+impl Field {
+    fn push(&mut self, text: &str, quoted: bool) {
+        self.text.push_str(text);
+        if !quoted {
+            self.glob |= text.contains(['*', '?', '[']);
+            self.pattern.push_str(text);
+            return;
+        }
+
+        for c in text.chars() {
+            if glob::is_special(c) {
+                self.pattern.push('\\');
+            }
+            self.pattern.push(c);
+        }
+    }
+}
+
+fn expand_word(
+    word: &Word,
+    state: &mut ShellState,
+    streams: &Streams,
+    fields: &mut Vec<Field>,
+) -> Result<(), RushError> {
+    // The field being built, if any. An unquoted substitution that expands to
+    // nothing doesn't produce an (empty) argument.
+    let mut current: Option<Field> = None;
+
+    let parts = word.parts();
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            WordPart::Literal(text) if i == 0 && text.starts_with('~') => {
+                let field = current.get_or_insert_default();
+                match tilde(text, parts.len() == 1, state) {
+                    Some((dir, rest)) => {
+                        field.push(&dir, true);
+                        field.push(rest, false);
+                    }
+                    None => field.push(text, false),
+                }
+            }
+            WordPart::Literal(text) => current.get_or_insert_default().push(text, false),
+            WordPart::Quoted(text) => current.get_or_insert_default().push(text, true),
+            WordPart::CommandSubstitution { command, quoted } => {
+                let output = substitute(command, state, streams)?;
+                push_expansion(&output, *quoted, &mut current, fields);
+            }
+            // Each positional parameter becomes an argument of its own, with
+            // the text around `"$@"` joining the first and last of them. So
+            // does each element of `"${a[@]}"`, even once transformed.
+            WordPart::Parameter {
+                name,
+                quoted: true,
+                modifier,
+            } if modifier
+                .as_ref()
+                .is_none_or(|modifier| modifier.kind.transforms())
+                && (name == "@"
+                    || split_subscript(name)
+                        .is_some_and(|(name, sub)| is_name(name) && sub == "@")) =>
+            {
+                let mut values = list(name, state).expect("a list of values");
+                if let Some(modifier) = modifier {
+                    values = transform_list(name, values, modifier, state, streams)?;
+                }
+                for (i, arg) in values.iter().enumerate() {
+                    if i > 0 {
+                        fields.extend(current.take());
+                    }
+                    current.get_or_insert_default().push(arg, true);
+                }
+            }
+            WordPart::Parameter {
+                name,
+                quoted,
+                modifier,
+            } => {
+                let value = parameter(name, modifier.as_deref(), state, streams)?;
+                push_expansion(&value, *quoted, &mut current, fields);
+            }
+        }
+    }
+
+    fields.extend(current);
+    Ok(())
+}
+
+/// Splits the tilde prefix, from `~` up to the first `/`, off `text` at the
+/// start of an unquoted word, giving the directory it stands for and the
+/// rest of `text`. `~` is `$HOME`, and `~+`, `~-` and `~N` are the current,
+/// previous, and stacked directories `dirs` shows. Unless `text` is the
+/// whole word, a prefix that runs into quoted text or an expansion is left
+/// as it's written, as is one that doesn't name a directory.
+fn tilde<'t>(text: &'t str, whole: bool, state: &ShellState) -> Option<(String, &'t str)> {
+    let end = text.find('/').or(whole.then_some(text.len()))?;
+    let (prefix, rest) = text.split_at(end);
+    let dir = match &prefix[1..] {
+        "" => state.vars.get("HOME")?,
+        reference => stack_reference(reference, state)?
+            .to_string_lossy()
+            .into_owned(),
+    };
+    Some((dir, rest))
+}
+
+/// Expands `word` into a single string without splitting it, as needed for
+/// the word of a `${NAME:-word}` modifier or the body of a here-document.
+pub(crate) fn expand_unsplit(
+    word: &Word,
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<String, RushError> {
+    let mut value = String::new();
+
+    for part in word.parts() {
+        match part {
+            WordPart::Literal(text) | WordPart::Quoted(text) => value.push_str(text),
+            WordPart::CommandSubstitution { command, .. } => {
+                value.push_str(&substitute(command, state, streams)?)
+            }
+            WordPart::Parameter { name, modifier, .. } => {
+                value.push_str(&parameter(name, modifier.as_deref(), state, streams)?)
+            }
+        }
+    }
+
+    Ok(value)
+}
+
+/// Expands the value of each assignment, which like a here-document body is
+/// never split into fields.
+pub(crate) fn expand_assignments(
+    assignments: &[Assignment],
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<Vec<(String, String)>, RushError> {
+    assignments
+        .iter()
+        .map(|assignment| {
+            let name = expand_name(&assignment.name, state, streams)?;
+            // An array can't be passed on, so its elements are joined
+            let value = match &assignment.array {
+                Some(elements) => expand_words(elements, state, streams)?.join(" "),
+                None => expand_unsplit(&assignment.value, state, streams)?,
+            };
+            let value = state.vars.value_for(&name, &value)?;
+            Ok((name, value))
+        })
+        .collect()
+}
+
+/// Expands the elements of an array assignment like `a=(x [5]=y)`, each
+/// with the subscript it was given, if any. Only an element without a
+/// subscript is split into fields and matched against filenames.
+pub(crate) fn expand_array(
+    elements: &[Word],
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<Vec<(Option<String>, String)>, RushError> {
+    let mut items = Vec::new();
+    for element in elements {
+        let Some((subscript, value)) = split_element(element) else {
+            let values = expand_words(std::slice::from_ref(element), state, streams)?;
+            items.extend(values.into_iter().map(|value| (None, value)));
+            continue;
+        };
+        let subscript = expand_subscript(&subscript, state, streams)?;
+        let value = expand_unsplit(&value, state, streams)?;
+        items.push((Some(subscript), value));
+    }
+    Ok(items)
+}
+
+/// Splits an array element like `[$k]=v` into its subscript, as written,
+/// and its value.
+fn split_element<'a>(element: &Word<'a>) -> Option<(String, Word<'a>)> {
+    let [WordPart::Literal(first), ..] = element.parts() else {
+        return None;
+    };
+    first.strip_prefix('[')?;
+
+    let mut subscript = String::new();
+    for (i, part) in element.parts().iter().enumerate() {
+        let WordPart::Literal(text) = part else {
+            subscript.push_str(&Word::from_parts(vec![part.clone()]).to_string());
+            continue;
+        };
+        let Some((before, value)) = text.split_once("]=") else {
+            subscript.push_str(text);
+            continue;
+        };
+        subscript.push_str(before);
+
+        let mut parts = vec![WordPart::Literal(value.to_owned().into())];
+        parts.extend_from_slice(&element.parts()[i + 1..]);
+        return Some((subscript[1..].to_owned(), Word::from_parts(parts)));
+    }
+    None
+}
+
+/// Expands the subscript of a name like `a[$i]`, leaving any other name as
+/// it is.
+pub(crate) fn expand_name(
+    name: &str,
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<String, RushError> {
+    match split_subscript(name) {
+        Some((name, subscript)) => {
+            let subscript = expand_subscript(subscript, state, streams)?;
+            Ok(format!("{name}[{subscript}]"))
+        }
+        None => Ok(name.to_owned()),
+    }
+}
+
+fn expand_subscript(
+    subscript: &str,
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<String, RushError> {
+    expand_unsplit(&lexer::subscript_word(subscript)?, state, streams)
+}
+
+/// Looks up the variable `name`, applying `modifier` if there is one.
+fn parameter(
+    name: &str,
+    modifier: Option<&Modifier>,
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<String, RushError> {
+    if let Some(modifier) = modifier
+        && modifier.kind.transforms()
+    {
+        return match list(name, state) {
+            Some(values) => Ok(transform_list(name, values, modifier, state, streams)?.join(" ")),
+            None => {
+                let value = lookup(name, state, streams)?.unwrap_or_default();
+                transform(&value, modifier, state, streams)
+            }
+        };
+    }
+
+    let value = lookup(name, state, streams)?;
+    let Some(modifier) = modifier else {
+        return Ok(value.unwrap_or_default());
+    };
+
+    // With a colon, a variable that is set but empty counts as unset
+    let set = value
+        .as_ref()
+        .is_some_and(|value| !(modifier.colon && value.is_empty()));
+
+    match (modifier.kind, set) {
+        (ModifierKind::Default, false) => expand_unsplit(&modifier.word, state, streams),
+        (ModifierKind::Assign, false) => {
+            let word = expand_unsplit(&modifier.word, state, streams)?;
+            let name = expand_name(name, state, streams)?;
+            state.vars.assign(&name, &word)?;
+            Ok(lookup(&name, state, streams)?.unwrap_or_default())
+        }
+        (ModifierKind::Alternate, true) => expand_unsplit(&modifier.word, state, streams),
+        (ModifierKind::Alternate, false) => Ok(String::new()),
+        (ModifierKind::Error, false) => {
+            let msg = expand_unsplit(&modifier.word, state, streams)?;
+            let error = RushError::UnsetParameter {
+                name: name.to_owned(),
+                msg: match msg.is_empty() {
+                    true => "parameter null or not set".into(),
+                    false => msg,
+                },
+            };
+            // Only a shell that's prompting carries on; a script ends here
+            if state.interactive {
+                return Err(error);
+            }
+            let status = error.status();
+            diagnostics::report(error, state);
+            Err(eval::leave(status, state))
+        }
+        (_, _) => Ok(value.unwrap_or_default()),
+    }
+}
+
+/// The values that `@`, `*`, or an array subscripted with either stand
+/// for, which a modifier changes one by one.
+fn list(name: &str, state: &ShellState) -> Option<Vec<String>> {
+    match split_subscript(name) {
+        Some((name, "@" | "*")) if is_name(name) => Some(state.vars.values(name)),
+        None if name == "@" || name == "*" => Some(state.args.clone()),
+        _ => None,
+    }
+}
+
+/// Applies `modifier` to each of `values`, except that `:offset:length`
+/// takes some of the values instead. The positional parameters count from
+/// 1, so `${@:2}` starts at `$2`.
+fn transform_list(
+    name: &str,
+    mut values: Vec<String>,
+    modifier: &Modifier,
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<Vec<String>, RushError> {
+    if modifier.kind != ModifierKind::Substring {
+        return values
+            .iter()
+            .map(|value| transform(value, modifier, state, streams))
+            .collect();
+    }
+
+    let (mut offset, length) = substring_bounds(modifier, state, streams)?;
+    if split_subscript(name).is_none() && offset > 0 {
+        offset -= 1;
+    }
+    let range = transform::substring(values.len(), offset, length)
+        .ok_or_else(|| negative_substring(length))?;
+    Ok(values.drain(range).collect())
+}
+
+/// Applies one of the modifiers that change a value, rather than stand in
+/// for a missing one.
+fn transform(
+    value: &str,
+    modifier: &Modifier,
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<String, RushError> {
+    let pattern = expand_pattern(&modifier.word, state, streams)?;
+    Ok(match modifier.kind {
+        ModifierKind::RemovePrefix { longest } => {
+            transform::remove_prefix(value, &pattern, longest)
+        }
+        ModifierKind::RemoveSuffix { longest } => {
+            transform::remove_suffix(value, &pattern, longest)
+        }
+        ModifierKind::Replace(which) => {
+            let string = match &modifier.second {
+                Some(second) => expand_unsplit(second, state, streams)?,
+                None => String::new(),
+            };
+            transform::replace(value, &pattern, &string, which)
+        }
+        ModifierKind::Upper { all } => transform::change_case(value, &pattern, true, all),
+        ModifierKind::Lower { all } => transform::change_case(value, &pattern, false, all),
+        ModifierKind::Substring => {
+            let (offset, length) = substring_bounds(modifier, state, streams)?;
+            let chars: Vec<char> = value.chars().collect();
+            let range = transform::substring(chars.len(), offset, length)
+                .ok_or_else(|| negative_substring(length))?;
+            chars[range].iter().collect()
+        }
+        ModifierKind::Default
+        | ModifierKind::Assign
+        | ModifierKind::Alternate
+        | ModifierKind::Error => value.to_owned(),
+    })
+}
+
+/// Evaluates the offset and length of `${NAME:offset:length}`.
+fn substring_bounds(
+    modifier: &Modifier,
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<(i64, Option<i64>), RushError> {
+    let mut evaluate = |word: &Word| {
+        let expr = expand_unsplit(word, state, streams)?;
+        arith::evaluate(&expr, &state.vars).map_err(|msg| RushError::Arithmetic { expr, msg })
+    };
+    let offset = evaluate(&modifier.word)?;
+    let length = match &modifier.second {
+        Some(second) => Some(evaluate(second)?),
+        None => None,
+    };
+    Ok((offset, length))
+}
+
+fn negative_substring(length: Option<i64>) -> RushError {
+    RushError::Arithmetic {
+        expr: length.unwrap_or_default().to_string(),
+        msg: "substring expression < 0".into(),
+    }
+}
+
+/// Expands a pattern like that of `${NAME#pattern}` or a `case` clause,
+/// escaping whatever was quoted so it only matches itself.
+pub(crate) fn expand_pattern(
+    word: &Word,
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<String, RushError> {
+    let mut field = Field::default();
+    for part in word.parts() {
+        match part {
+            WordPart::Literal(text) => field.push(text, false),
+            WordPart::Quoted(text) => field.push(text, true),
+            WordPart::CommandSubstitution { command, quoted } => {
+                field.push(&substitute(command, state, streams)?, *quoted)
+            }
+            WordPart::Parameter {
+                name,
+                quoted,
+                modifier,
+            } => field.push(
+                &parameter(name, modifier.as_deref(), state, streams)?,
+                *quoted,
+            ),
+        }
+    }
+    Ok(field.pattern)
+}
+
+/// The value of the variable `name`, or of a special parameter like `?`.
+/// With a subscript, it is one element of an array, or all of them joined
+/// by spaces for `@` or `*`. `#name` is the length of the value, or the
+/// number of elements or positional parameters.
+fn lookup(
+    name: &str,
+    state: &mut ShellState,
+    streams: &Streams,
+) -> Result<Option<String>, RushError> {
+    if let Some(name) = name.strip_prefix('#')
+        && !name.is_empty()
+    {
+        let length = match split_subscript(name) {
+            Some((name, "@" | "*")) => state.vars.values(name).len(),
+            None if name == "@" || name == "*" => state.args.len(),
+            _ => lookup(name, state, streams)?
+                .unwrap_or_default()
+                .chars()
+                .count(),
+        };
+        return Ok(Some(length.to_string()));
+    }
+    if let Some((name, subscript)) = split_subscript(name) {
+        return match subscript {
+            "@" | "*" => {
+                let values = state.vars.values(name);
+                Ok((!values.is_empty()).then(|| values.join(" ")))
+            }
+            _ => {
+                let subscript = expand_subscript(subscript, state, streams)?;
+                state.vars.element(name, &subscript)
+            }
+        };
+    }
+
+    Ok(match name {
+        "?" => Some(state.last_status.to_string()),
+        "$" => Some(process::id().to_string()),
+        "!" => state.jobs.last_pid().map(|pid| pid.to_string()),
+        "#" => Some(state.args.len().to_string()),
+        // Outside of `"$@"`, the positional parameters are joined by spaces
+        "@" | "*" => Some(state.args.join(" ")),
+        _ if name.starts_with(|c: char| c.is_ascii_digit()) => name
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|n| state.args.get(n))
+            .cloned(),
+        _ => state.vars.get(name),
+    })
+}
+
+/// Adds the result of an expansion to the field in progress. Quoted results
+/// are kept whole, even when empty; unquoted ones are split into fields.
+fn push_expansion(value: &str, quoted: bool, current: &mut Option<Field>, fields: &mut Vec<Field>) {
+    if quoted {
+        current.get_or_insert_default().push(value, true);
+    } else {
+        split_fields(value, current, fields);
+    }
+}
+
+/// Splits unquoted expansion results on whitespace. The first piece joins
+/// the field in progress unless the output starts with whitespace, and the
+/// last piece stays open for whatever follows unless the output ends with it.
+fn split_fields(output: &str, current: &mut Option<Field>, fields: &mut Vec<Field>) {
+    let is_separator = |c: char| matches!(c, ' ' | '\t' | '\n');
+
+    if output.starts_with(is_separator) {
+        fields.extend(current.take());
+    }
+
+    for (i, piece) in output
+        .split(is_separator)
+        .filter(|piece| !piece.is_empty())
+        .enumerate()
+    {
+        if i > 0 {
+            fields.extend(current.take());
+        }
+        current.get_or_insert_default().push(piece, false);
+    }
+
+    if output.ends_with(is_separator) {
+        fields.extend(current.take());
+    }
+}
+
+/// Runs `cmd` and returns what it wrote to stdout, minus trailing newlines.
+///
+/// The command runs against a copy of the shell state so it can't change the
+/// parent shell, and with the rest of the `streams` of the command it's part
+/// of. Its output is capped at the configured limit, which fails the
+/// substitution, for the command to report. NUL bytes can't be passed in
+/// arguments, so they are dropped with a warning on the command's stderr.
+fn substitute(cmd: &str, state: &mut ShellState, streams: &Streams) -> Result<String, RushError> {
+    let limit = state.limits.substitution_bytes;
+    let mut subshell = state.subshell();
+    let mut capture = Capture::new(limit);
+
+    // A pipeline ended by `&` runs in the foreground here, which makes no
+    // difference: the output isn't complete until it finishes either way
+    let mut lists = parser::parse_with_aliases(cmd, &state.aliases, state.vars.line())?;
+    for list in &mut lists {
+        list.background = false;
+    }
+    subshell.substitutions += 1;
+    let streams = streams.substitution()?;
+    let result = eval::run_lists(&lists, &mut subshell, &streams, &mut capture);
+
+    if capture.overflowed {
+        return Err(RushError::SubstitutionTooLarge(limit));
+    }
+
+    // Like any other command, a failure inside the substitution is reported
+    // without aborting the command that uses its output
+    state.substituted = Some(match eval::leave_subshell(result) {
+        Ok(status) => status,
+        Err(error) => {
+            let status = error.status();
+            diagnostics::report(streams.report(error, &mut io::sink()), state);
+            status
+        }
+    });
+
+    let mut bytes = capture.buf;
+    if bytes.contains(&0) {
+        let warning = "rush: warning: command substitution: ignored null byte in input\n";
+        streams.write_stderr(warning, &mut io::sink()).ok();
+        bytes.retain(|&b| b != 0);
+    }
+
+    let mut output = String::from_utf8_lossy(&bytes).into_owned();
+    output.truncate(output.trim_end_matches('\n').len());
+    Ok(output)
+}
+
+/// What writing past the limit of a [`Capture`] fails with.
+#[derive(Debug, thiserror::Error)]
+#[error("command substitution output too large")]
+struct Overflow;
+
+/// Whether `error` is from writing past the limit of a [`Capture`], which
+/// ends the command substitution it's capturing for.
+pub(crate) fn overflowed(error: &io::Error) -> bool {
+    error.get_ref().is_some_and(|error| error.is::<Overflow>())
+}
+
+/// An output sink that refuses to grow past `limit` bytes. Writes that would
+/// cross the limit fail without buffering anything, so a runaway command can't
+/// exhaust memory before the limit is noticed.
+pub(crate) struct Capture {
+    buf: Vec<u8>,
+    limit: usize,
+    overflowed: bool,
+}
+
+impl Capture {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            limit,
+            overflowed: false,
+        }
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.buf.len() + data.len() > self.limit {
+            self.overflowed = true;
+            return Err(io::Error::other(Overflow));
+        }
+
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::{parser::SimpleCommand, status::ExitStatus};
+
+    fn expand(input: &str, state: &mut ShellState) -> Result<Vec<String>, RushError> {
+        expand_with(input, state, &Streams::default())
+    }
+
+    fn expand_with(
+        input: &str,
+        state: &mut ShellState,
+        streams: &Streams,
+    ) -> Result<Vec<String>, RushError> {
+        let command = SimpleCommand::parse(input)?;
+        expand_words(&command.words, state, streams)
+    }
+
+    mod substitution {
+        use super::*;
+
+        #[test]
+        fn builtin_output() {
+            let args = expand("echo $(echo hello)", &mut ShellState::default()).unwrap();
+            assert_eq!(args, vec!["echo", "hello"]);
+        }
+
+        #[test]
+        fn external_output() {
+            let args = expand("x$(printf abc)y", &mut ShellState::default()).unwrap();
+            assert_eq!(args, vec!["xabcy"]);
+        }
+
+        #[test]
+        fn trailing_newlines_removed() {
+            let args = expand("$(printf 'a\\n\\n\\n')", &mut ShellState::default()).unwrap();
+            assert_eq!(args, vec!["a"]);
+        }
+
+        #[test]
+        fn output_is_split_into_fields() {
+            let args = expand("echo a$(echo b c)d", &mut ShellState::default()).unwrap();
+            assert_eq!(args, vec!["echo", "ab", "cd"]);
+        }
+
+        #[test]
+        fn leading_and_trailing_whitespace_separate_fields() {
+            let args = expand("a$(printf ' b ')c", &mut ShellState::default()).unwrap();
+            assert_eq!(args, vec!["a", "b", "c"]);
+        }
+
+        #[test]
+        fn empty_output_produces_no_argument() {
+            let args = expand("echo $(true)", &mut ShellState::default()).unwrap();
+            assert_eq!(args, vec!["echo"]);
+        }
+
+        #[test]
+        fn nested_substitution() {
+            let args = expand("echo $(echo $(echo deep))", &mut ShellState::default()).unwrap();
+            assert_eq!(args, vec!["echo", "deep"]);
+        }
+
+        #[test]
+        fn command_list() {
+            let args = expand(
+                "echo $(echo a; echo b) $(false && echo c || echo d)",
+                &mut ShellState::default(),
+            )
+            .unwrap();
+            assert_eq!(args, vec!["echo", "a", "b", "d"]);
+        }
+
+        #[test]
+        fn braces_expand_first() {
+            let args = expand("echo {a,b}$(echo x y)", &mut ShellState::default()).unwrap();
+            assert_eq!(args, vec!["echo", "ax", "y", "bx", "y"]);
+        }
+
+        #[test]
+        fn quoted_substitution_is_literal() {
+            let args = expand("echo '$(echo no)'", &mut ShellState::default()).unwrap();
+            assert_eq!(args, vec!["echo", "$(echo no)"]);
+        }
+
+        #[test]
+        fn failing_command_yields_its_output() {
+            let args = expand(
+                "echo $(definitely_not_a_command_3301)",
+                &mut ShellState::default(),
+            )
+            .unwrap();
+            assert_eq!(args, vec!["echo"]);
+        }
+    }
+
+    mod parameters {
+        use super::*;
+
+        #[test]
+        fn expands_from_the_environment() {
+            let home = env::var("HOME").unwrap_or_default();
+            let args = expand("echo ${HOME}", &mut ShellState::default()).unwrap();
+            assert_eq!(args, vec!["echo".to_string(), home]);
+        }
+
+        #[test]
+        fn unset_variables_expand_to_nothing() {
+            let args = expand(
+                "echo $RUSH_SURELY_UNSET_4411 \"$RUSH_SURELY_UNSET_4411\" x",
+                &mut ShellState::default(),
+            )
+            .unwrap();
+            // Unquoted it disappears; quoted it is still an (empty) argument
+            assert_eq!(args, vec!["echo", "", "x"]);
+        }
+
+        #[test]
+        fn last_status() {
+            let mut state = ShellState {
+                last_status: ExitStatus::from_code(137),
+                ..ShellState::default()
+            };
+            let args = expand("echo $? \"${?}\" ${?:+set}", &mut state).unwrap();
+            assert_eq!(args, ["echo", "137", "137", "set"]);
+        }
+
+        #[test]
+        fn positional_parameters() {
+            let mut state = ShellState {
+                args: ["a b", "c", "", "d"].map(String::from).to_vec(),
+                ..ShellState::default()
+            };
+            let args = expand("$# $1 ${2} $5 ${4:-x}", &mut state).unwrap();
+            assert_eq!(args, ["4", "a", "b", "c", "d"]);
+
+            // Unquoted, every parameter is split; quoted `$*` is one argument
+            let args = expand("$@ \"$*\"", &mut state).unwrap();
+            assert_eq!(args, ["a", "b", "c", "d", "a b c  d"]);
+        }
+
+        #[test]
+        fn quoted_at_keeps_each_parameter_whole() {
+            let mut state = ShellState {
+                args: ["a b", "", "c"].map(String::from).to_vec(),
+                ..ShellState::default()
+            };
+            let args = expand("x\"<$@>\"y", &mut state).unwrap();
+            assert_eq!(args, ["x<a b", "", "c>y"]);
+
+            // With no parameters, `"$@"` produces no argument at all
+            state.args.clear();
+            assert_eq!(expand("a \"$@\" b", &mut state).unwrap(), ["a", "b"]);
+            assert_eq!(expand("\"-$@-\"", &mut state).unwrap(), ["--"]);
+        }
+
+        #[test]
+        fn process_ids() {
+            let mut state = ShellState::default();
+            let pid = process::id().to_string();
+            assert_eq!(
+                expand("$$ ${!-none}", &mut state).unwrap(),
+                [pid, "none".into()]
+            );
+
+            crate::eval::eval("true &", &mut state).unwrap();
+            let last = state.jobs.last_pid().unwrap().to_string();
+            assert_eq!(expand("\"$!\"", &mut state).unwrap(), [last]);
+        }
+
+        #[test]
+        fn only_unquoted_values_are_split() {
+            let mut state = ShellState::default();
+            state.vars.set("RUSH_EXPAND_TEST_SPLIT", "a  b");
+            let args = expand(
+                "x$RUSH_EXPAND_TEST_SPLIT \"$RUSH_EXPAND_TEST_SPLIT\"",
+                &mut state,
+            )
+            .unwrap();
+            assert_eq!(args, vec!["xa", "b", "a  b"]);
+        }
+
+        #[test]
+        fn default_and_alternate() {
+            let mut state = ShellState::default();
+            state.vars.set("RUSH_EXPAND_TEST_EMPTY", "");
+            let args = expand(
+                "${RUSH_EXPAND_TEST_EMPTY:-a} ${RUSH_EXPAND_TEST_EMPTY-b}x \
+                 ${RUSH_SURELY_UNSET_4411:-c d} \"${RUSH_SURELY_UNSET_4411:-e  f}\" \
+                 ${RUSH_EXPAND_TEST_EMPTY+g} ${RUSH_EXPAND_TEST_EMPTY:+h}y",
+                &mut state,
+            )
+            .unwrap();
+            assert_eq!(args, vec!["a", "x", "c", "d", "e  f", "g", "y"]);
+        }
+
+        #[test]
+        fn assign_sets_the_variable() {
+            let mut state = ShellState::default();
+            let args = expand(
+                "${RUSH_EXPAND_TEST_ASSIGN:=first} ${RUSH_EXPAND_TEST_ASSIGN:=second}",
+                &mut state,
+            )
+            .unwrap();
+            assert_eq!(args, vec!["first", "first"]);
+            assert_eq!(state.vars.get("RUSH_EXPAND_TEST_ASSIGN").unwrap(), "first");
+        }
+
+        #[test]
+        fn error_when_unset() {
+            let mut state = ShellState {
+                interactive: true,
+                ..ShellState::default()
+            };
+            let error =
+                expand("echo ${RUSH_SURELY_UNSET_4411:?is required}", &mut state).unwrap_err();
+            assert_eq!(error.to_string(), "RUSH_SURELY_UNSET_4411: is required");
+            assert_eq!(error.status().code(), 1);
+
+            let error = expand("${RUSH_SURELY_UNSET_4411?}", &mut state).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "RUSH_SURELY_UNSET_4411: parameter null or not set"
+            );
+            assert_eq!(state.exited, None);
+        }
+
+        #[test]
+        fn error_when_unset_ends_a_script() {
+            let mut state = ShellState::default();
+            let error = expand("${RUSH_SURELY_UNSET_4411:?}", &mut state).unwrap_err();
+            assert!(matches!(error, RushError::Exit(status) if status.code() == 1));
+            assert_eq!(state.exited.map(|status| status.code()), Some(1));
+
+            // Only a subshell ends in one
+            let mut state = ShellState::default();
+            let args = expand("$(echo ${RUSH_SURELY_UNSET_4411:?}) after", &mut state).unwrap();
+            assert_eq!(args, ["after"]);
+            assert_eq!(state.exited, None);
+        }
+
+        #[test]
+        fn quoted_substitution_is_not_split() {
+            let args = expand("echo \"$(printf 'a   b')\"", &mut ShellState::default()).unwrap();
+            assert_eq!(args, vec!["echo", "a   b"]);
+        }
+    }
+
+    mod globbing {
+        use super::*;
+        use std::fs;
+
+        #[test]
+        fn only_unquoted_patterns_match() {
+            let dir = env::temp_dir().join(format!("rush_expand_glob_{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            for file in ["a.txt", "b.txt", "*.log"] {
+                fs::write(dir.join(file), "").unwrap();
+            }
+            let dir = dir.to_string_lossy();
+
+            let args = expand(
+                &format!("{dir}/?.txt '{dir}/*.log' \"{dir}\"/*.md"),
+                &mut ShellState::default(),
+            )
+            .unwrap();
+            assert_eq!(
+                args,
+                vec![
+                    format!("{dir}/a.txt"),
+                    format!("{dir}/b.txt"),
+                    format!("{dir}/*.log"),
+                    format!("{dir}/*.md"),
+                ]
+            );
+        }
+    }
+
+    /// Streams with stderr sent to a file named for `test`, and the file.
+    fn stderr_file(test: &str) -> (Streams, std::path::PathBuf) {
+        let path = env::temp_dir().join(format!("rush_{test}_{}", process::id()));
+        let streams = Streams::open(&[crate::command::redirect::Redirection {
+            fd: 2,
+            kind: crate::util::RedirectKind::Output,
+            path: path.to_string_lossy().into_owned(),
+            dir: std::path::PathBuf::new(),
+            noclobber: false,
+        }])
+        .unwrap();
+        (streams, path)
+    }
+
+    mod limits {
+        use super::*;
+
+        fn limited(bytes: usize) -> ShellState {
+            let mut state = ShellState::default();
+            state.limits.substitution_bytes = bytes;
+            state
+        }
+
+        #[test]
+        fn external_output_over_the_limit() {
+            let error =
+                expand("echo $(sh -c 'yes | head -c 100000')", &mut limited(1000)).unwrap_err();
+            assert!(matches!(error, RushError::SubstitutionTooLarge(1000)));
+            assert!(
+                error
+                    .to_string()
+                    .contains("command substitution output too large")
+            );
+        }
+
+        #[test]
+        fn builtin_output_over_the_limit() {
+            let error = expand("echo $(echo 0123456789)", &mut limited(5)).unwrap_err();
+            assert!(matches!(error, RushError::SubstitutionTooLarge(5)));
+        }
+
+        #[test]
+        fn overflow_is_left_to_the_substitution_to_report() {
+            let (streams, path) = stderr_file("overflow_stderr");
+            let error = expand_with(
+                "echo $(echo 0123456789; echo more >&2)",
+                &mut limited(5),
+                &streams,
+            )
+            .unwrap_err();
+            let stderr = std::fs::read_to_string(&path);
+            std::fs::remove_file(&path).ok();
+
+            assert!(matches!(error, RushError::SubstitutionTooLarge(5)));
+            // Neither echo said anything, and nothing ran past the overflow
+            assert_eq!(stderr.unwrap(), "");
+        }
+
+        #[test]
+        fn output_at_the_limit() {
+            // "abcd" plus echo's newline is exactly 5 bytes
+            let args = expand("echo $(echo abcd)", &mut limited(5)).unwrap();
+            assert_eq!(args, vec!["echo", "abcd"]);
+        }
+
+        #[test]
+        fn capture_rejects_writes_past_the_limit() {
+            let mut capture = Capture::new(4);
+            assert!(capture.write_all(b"abc").is_ok());
+            assert!(capture.write_all(b"de").is_err());
+            assert!(capture.overflowed);
+            assert_eq!(capture.buf, b"abc");
+        }
+    }
+
+    mod binary_output {
+        use super::*;
+
+        #[test]
+        fn nul_bytes_are_removed() {
+            let args = expand("$(printf 'a\\000b\\000c')", &mut ShellState::default()).unwrap();
+            assert_eq!(args, vec!["abc"]);
+        }
+
+        #[test]
+        fn nul_warning_goes_to_the_commands_stderr() {
+            let (streams, path) = stderr_file("nul_stderr");
+            let args = expand_with("$(printf 'a\\000b')", &mut ShellState::default(), &streams);
+            let stderr = std::fs::read_to_string(&path);
+            std::fs::remove_file(&path).ok();
+
+            assert_eq!(args.unwrap(), vec!["ab"]);
+            assert_eq!(
+                stderr.unwrap(),
+                "rush: warning: command substitution: ignored null byte in input\n"
+            );
+        }
+
+        #[test]
+        fn substitution_shares_the_commands_stderr() {
+            let (streams, path) = stderr_file("substitution_stderr");
+            let args = expand_with("x$(echo oops >&2)", &mut ShellState::default(), &streams);
+            let stderr = std::fs::read_to_string(&path);
+            std::fs::remove_file(&path).ok();
+
+            assert_eq!(args.unwrap(), vec!["x"]);
+            assert_eq!(stderr.unwrap(), "oops\n");
+        }
+
+        #[test]
+        fn invalid_utf8_is_replaced() {
+            let args = expand("$(printf 'a\\377b')", &mut ShellState::default()).unwrap();
+            assert_eq!(args, vec!["a\u{FFFD}b"]);
+        }
+    }
+
+    mod arrays {
+        use super::*;
+
+        fn with_array() -> ShellState {
+            let mut state = ShellState::default();
+            eval::eval("RUSH_EXPAND_TEST_A=(a 'b c' [5]=d)", &mut state).unwrap();
+            state
+        }
+
+        #[test]
+        fn subscripts_pick_elements() {
+            let mut state = with_array();
+            state.vars.set("RUSH_EXPAND_TEST_I", "1");
+            let args = expand(
+                "${RUSH_EXPAND_TEST_A[RUSH_EXPAND_TEST_I]} ${RUSH_EXPAND_TEST_A[-1]} \
+                 ${RUSH_EXPAND_TEST_A[2]:-none} $RUSH_EXPAND_TEST_A",
+                &mut state,
+            )
+            .unwrap();
+            assert_eq!(args, vec!["b", "c", "d", "none", "a"]);
+        }
+
+        #[test]
+        fn quoted_at_keeps_each_element_whole() {
+            let mut state = with_array();
+            let args = expand("x\"${RUSH_EXPAND_TEST_A[@]}\"y", &mut state).unwrap();
+            assert_eq!(args, vec!["xa", "b c", "dy"]);
+            let args = expand("\"${RUSH_EXPAND_TEST_A[*]}\"", &mut state).unwrap();
+            assert_eq!(args, vec!["a b c d"]);
+        }
+
+        #[test]
+        fn lengths() {
+            let mut state = with_array();
+            let args = expand(
+                "${#RUSH_EXPAND_TEST_A[@]} ${#RUSH_EXPAND_TEST_A[1]} ${#RUSH_EXPAND_TEST_A}",
+                &mut state,
+            )
+            .unwrap();
+            assert_eq!(args, vec!["3", "3", "1"]);
+        }
+
+        #[test]
+        fn associative_keys_are_expanded_but_not_evaluated() {
+            let mut state = ShellState::default();
+            eval::eval(
+                "declare -A RUSH_EXPAND_TEST_M; RUSH_EXPAND_TEST_K='a b'\n\
+                 RUSH_EXPAND_TEST_M=([$RUSH_EXPAND_TEST_K]=1 [1+1]=2)",
+                &mut state,
+            )
+            .unwrap();
+            let args = expand(
+                "${RUSH_EXPAND_TEST_M[a b]} ${RUSH_EXPAND_TEST_M[\"1+1\"]}",
+                &mut state,
+            )
+            .unwrap();
+            assert_eq!(args, vec!["1", "2"]);
+        }
+    }
+
+    mod transformations {
+        use super::*;
+
+        #[test]
+        fn apply_to_values() {
+            let mut state = ShellState::default();
+            state.vars.set("RUSH_EXPAND_TEST_P", "a/b.c.d");
+            let args = expand(
+                "${RUSH_EXPAND_TEST_P##*/} ${RUSH_EXPAND_TEST_P%.*} \
+                 ${RUSH_EXPAND_TEST_P//./-} ${RUSH_EXPAND_TEST_P:2:3} ${RUSH_EXPAND_TEST_P^^}",
+                &mut state,
+            )
+            .unwrap();
+            assert_eq!(args, vec!["b.c.d", "a/b.c", "a/b-c-d", "b.c", "A/B.C.D"]);
+        }
+
+        #[test]
+        fn quoted_pattern_characters_are_literal() {
+            let mut state = ShellState::default();
+            state.vars.set("RUSH_EXPAND_TEST_P", "*.x.y");
+            state.vars.set("RUSH_EXPAND_TEST_Q", "*.");
+            let args = expand(
+                "${RUSH_EXPAND_TEST_P#\"$RUSH_EXPAND_TEST_Q\"} ${RUSH_EXPAND_TEST_P#$RUSH_EXPAND_TEST_Q}",
+                &mut state,
+            )
+            .unwrap();
+            assert_eq!(args, vec!["x.y", "x.y"]);
+            let args =
+                expand("\"${RUSH_EXPAND_TEST_P##$RUSH_EXPAND_TEST_Q}\"", &mut state).unwrap();
+            assert_eq!(args, vec!["y"]);
+        }
+
+        #[test]
+        fn apply_to_each_element() {
+            let mut state = ShellState {
+                args: vec!["a1".into(), "b2".into(), "c3".into()],
+                ..ShellState::default()
+            };
+            let args = expand("\"${@%?}\"", &mut state).unwrap();
+            assert_eq!(args, vec!["a", "b", "c"]);
+            let args = expand("\"${@:2}\"", &mut state).unwrap();
+            assert_eq!(args, vec!["b2", "c3"]);
+            let args = expand("\"${*:1:2}\"", &mut state).unwrap();
+            assert_eq!(args, vec!["a1 b2"]);
+        }
+
+        #[test]
+        fn negative_length_past_the_offset_fails() {
+            let mut state = ShellState::default();
+            state.vars.set("RUSH_EXPAND_TEST_P", "abc");
+            let error = expand("${RUSH_EXPAND_TEST_P:2:-2}", &mut state).unwrap_err();
+            assert_eq!(error.to_string(), "-2: substring expression < 0");
+        }
+    }
+
+    mod tilde {
+        use super::*;
+
+        fn state() -> ShellState {
+            let mut state = ShellState::default();
+            state.cwd.set("/".into());
+            state.vars.set("HOME", "/home/rush");
+            state.vars.set("OLDPWD", "/old");
+            state.dirs = vec!["/usr".into(), "/etc".into()];
+            state
+        }
+
+        #[test]
+        fn starts_unquoted_words() {
+            let args = expand("~ ~/bin a~ x=~ ~root", &mut state()).unwrap();
+            assert_eq!(args, ["/home/rush", "/home/rush/bin", "a~", "x=~", "~root"]);
+        }
+
+        #[test]
+        fn names_the_directory_stack() {
+            let args = expand("~+ ~- ~1 ~+2/bin ~-0 ~3", &mut state()).unwrap();
+            assert_eq!(args, ["/", "/old", "/usr", "/etc/bin", "/etc", "~3"]);
+        }
+
+        #[test]
+        fn quoting_any_of_the_prefix_keeps_it() {
+            let args = expand("'~' \"~1\" ~\"\" ~'/x' ~$X \"\"~", &mut state()).unwrap();
+            assert_eq!(args, ["~", "~1", "~", "~/x", "~", "~"]);
+        }
+
+        #[test]
+        fn directories_are_neither_split_nor_matched() {
+            let mut state = state();
+            state.vars.set("HOME", "/a b/*");
+            let args = expand("~ ~/*", &mut state).unwrap();
+            assert_eq!(args, ["/a b/*", "/a b/*/*"]);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::{collections::BTreeMap, io::Write, mem, sync::Arc};
+
+use crate::{
+    command::redirect::{Streams, expand_redirects},
+    eval::run_lists,
+    parser::Function,
+    state::ShellState,
+    status::ExitStatus,
+    util::RushError,
+};
+
+/// The functions defined so far, which run in place of any builtin or
+/// program of the same name.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Functions {
+    functions: BTreeMap<String, Arc<Function>>,
+}
+
+impl Functions {
+    pub(crate) fn get(&self, name: &str) -> Option<Arc<Function>> {
+        self.functions.get(name).cloned()
+    }
+
+    /// Defines `function`, replacing any function of the same name.
+    pub(crate) fn set(&mut self, function: Function) {
+        self.functions
+            .insert(function.name.clone(), Arc::new(function));
+    }
+
+    /// Every function, sorted by name.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Function> {
+        self.functions.values().map(|function| &**function)
+    }
+}
+
+/// Runs `function` with the operands of `args` as its positional
+/// parameters, which are put back once it returns, along with any variable
+/// it made `local`. `return` ends it early.
+pub(crate) fn call(
+    function: &Function,
+    args: &[String],
+    state: &mut ShellState,
+    streams: &Streams,
+    out: &mut dyn Write,
+) -> Result<ExitStatus, RushError> {
+    let redirections = expand_redirects(&function.body.redirects, state, streams)?;
+    let streams = streams.apply(&redirections)?;
+
+    let saved = mem::replace(&mut state.args, args[1..].to_vec());
+    // Loops around the call are out of reach of `break` in the body
+    let loops = mem::take(&mut state.loops);
+    state.vars.push_scope();
+    let result = run_lists(&function.body.body, state, &streams, out);
+    state.vars.pop_scope();
+    state.loops = loops;
+    state.args = saved;
+
+    match result {
+        Err(RushError::Return(status)) => Ok(status),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::eval;
+    use crate::state::ShellState;
+
+    #[test]
+    fn functions_take_their_own_arguments() {
+        let mut state = ShellState {
+            args: vec!["outer".into()],
+            ..ShellState::default()
+        };
+        eval(
+            "rush_fn_test() { RUSH_FN_TEST_SEEN=\"$# $1 $2\"; }; rush_fn_test a b",
+            &mut state,
+        )
+        .unwrap();
+        assert_eq!(state.vars.get("RUSH_FN_TEST_SEEN").unwrap(), "2 a b");
+        assert_eq!(state.args, ["outer"]);
+    }
+
+    #[test]
+    fn return_sets_the_status_and_stops_the_function() {
+        let mut state = ShellState::default();
+        eval(
+            "rush_fn_test() { return 3; RUSH_FN_TEST_AFTER=1; }",
+            &mut state,
+        )
+        .unwrap();
+        assert_eq!(eval("rush_fn_test", &mut state).unwrap().code(), 3);
+        assert_eq!(state.last_status.code(), 3);
+        assert!(state.vars.get("RUSH_FN_TEST_AFTER").is_none());
+
+        // Without an operand, it returns the status of the last command
+        let status = eval(
+            "rush_fn_test() { false; return; }; rush_fn_test",
+            &mut state,
+        );
+        assert_eq!(status.unwrap().code(), 1);
+        assert_eq!(state.last_status.code(), 1);
+    }
+
+    #[test]
+    fn locals_disappear_when_the_function_returns() {
+        let mut state = ShellState::default();
+        eval(
+            "RUSH_FN_TEST_LOCAL=outer\n\
+             rush_fn_test() {\n\
+                 local RUSH_FN_TEST_LOCAL=inner RUSH_FN_TEST_NEW\n\
+                 RUSH_FN_TEST_NEW=new\n\
+                 RUSH_FN_TEST_INSIDE=$RUSH_FN_TEST_LOCAL\n\
+             }\n\
+             rush_fn_test",
+            &mut state,
+        )
+        .unwrap();
+        assert_eq!(state.vars.get("RUSH_FN_TEST_INSIDE").unwrap(), "inner");
+        assert_eq!(state.vars.get("RUSH_FN_TEST_LOCAL").unwrap(), "outer");
+        assert!(state.vars.get("RUSH_FN_TEST_NEW").is_none());
+    }
+
+    #[test]
+    fn functions_recurse() {
+        let mut state = ShellState::default();
+        eval(
+            "rush_fn_test() { RUSH_FN_TEST_SEEN=$RUSH_FN_TEST_SEEN$1; \
+             [ -z \"$2\" ] || rush_fn_test $2 $3 $4; }",
+            &mut state,
+        )
+        .unwrap();
+        eval("rush_fn_test a b c", &mut state).unwrap();
+        assert_eq!(state.vars.get("RUSH_FN_TEST_SEEN").unwrap(), "abc");
+    }
+}
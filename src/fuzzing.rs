@@ -0,0 +1,83 @@
+use std::ops::Range;
+
+use crate::{
+    diagnostics::{self, DiagnosticMode},
+    lexer::Lexer,
+    parser,
+    style::Style,
+    util::RushError,
+};
+
+/// Reads `line` as tokens, giving back the byte range each was read from, or
+/// the syntax error that stopped it, shown as the shell would show it.
+pub fn tokenize_line(line: &str) -> Result<Vec<Range<usize>>, String> {
+    let mut lexer = Lexer::at(line, 0);
+    let mut spans = Vec::new();
+    while let Some(token) = lexer.next_token().map_err(render)? {
+        spans.push(token.span);
+    }
+    Ok(spans)
+}
+
+/// Parses `line` into the pipelines it holds, giving back the text of each,
+/// or the syntax error that stopped it, shown as the shell would show it.
+pub fn parse_line(line: &str) -> Result<Vec<String>, String> {
+    let lists = parser::parse(line).map_err(render)?;
+    Ok(lists
+        .into_iter()
+        .flat_map(|list| list.pipelines)
+        .map(|(_, pipeline)| pipeline.text)
+        .collect())
+}
+
+/// Renders `error` with its hint, so rendering is exercised along with the
+/// parser.
+fn render(error: RushError) -> String {
+    let mode = DiagnosticMode {
+        interactive: true,
+        posix: false,
+    };
+    match error {
+        RushError::Syntax(error) => diagnostics::render_syntax_error(&error, mode, Style::plain()),
+        error => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_are_byte_ranges_of_the_line() {
+        let line = "echo 日本 'é'|wc";
+        let spans = tokenize_line(line).unwrap();
+        let tokens: Vec<_> = spans.into_iter().map(|span| &line[span]).collect();
+        assert_eq!(tokens, ["echo", " ", "日本", " ", "'é'", "|", "wc"]);
+    }
+
+    #[test]
+    fn parses_pipelines() {
+        assert_eq!(parse_line("a | b && c; d &").unwrap(), ["a | b", "c", "d"]);
+        assert!(
+            parse_line("echo 日本 'abc")
+                .unwrap_err()
+                .contains("unterminated quote")
+        );
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let groups = "{ ".repeat(100) + "a" + &"; }".repeat(100);
+        assert!(
+            parse_line(&groups)
+                .unwrap_err()
+                .contains("nested too deeply")
+        );
+        let expansions = "${x:-".repeat(100) + &"}".repeat(100);
+        assert!(
+            tokenize_line(&expansions)
+                .unwrap_err()
+                .contains("nested too deeply")
+        );
+    }
+}
@@ -0,0 +1,437 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::state::Options;
+
+/// Whether `c` means something in a pattern, and so has to be escaped with a
+/// backslash to be matched literally.
+pub(crate) fn is_special(c: char) -> bool {
+    matches!(c, '*' | '?' | '[' | '\\')
+}
+
+/// Expands `pattern` into the paths it matches, sorted. A pattern that
+/// matches nothing yields no paths; the caller decides what to do then.
+///
+/// Each `/`-separated component is matched against the entries of the
+/// directories matched so far. Hidden entries only match a component that
+/// starts with a literal `.`. With the `globstar` option, a component that is
+/// exactly `**` matches any number of directories, including none, and with
+/// `nocaseglob`, letters match regardless of case. A relative pattern is
+/// matched from `cwd`, and yields relative paths.
+pub(crate) fn expand(pattern: &str, cwd: &Path, options: &Options) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_owned()], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    let components: Vec<_> = rest.split('/').collect();
+    for (i, component) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        paths = paths
+            .iter()
+            .flat_map(|path| match *component {
+                // A trailing or doubled slash only matches directories
+                "" => match on_disk(cwd, path).is_dir() {
+                    true => vec![format!("{path}/")],
+                    false => Vec::new(),
+                },
+                "**" if options.globstar => descendants(cwd, path, last),
+                component if has_wildcards(component) => {
+                    entries(cwd, path, component, options.nocaseglob)
+                }
+                component => {
+                    let path = join(path, &unescape(component));
+                    match fs::symlink_metadata(on_disk(cwd, &path)) {
+                        Ok(_) => vec![path],
+                        Err(_) => Vec::new(),
+                    }
+                }
+            })
+            .collect();
+    }
+
+    paths.retain(|path| !path.is_empty());
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Whether `component` has an unescaped wildcard, so has to be matched
+/// against directory entries rather than looked up directly.
+fn has_wildcards(component: &str) -> bool {
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn unescape(component: &str) -> String {
+    let mut text = String::new();
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        text.push(match c {
+            '\\' => chars.next().unwrap_or('\\'),
+            c => c,
+        });
+    }
+    text
+}
+
+fn join(path: &str, name: &str) -> String {
+    match path.is_empty() || path.ends_with('/') {
+        true => format!("{path}{name}"),
+        false => format!("{path}/{name}"),
+    }
+}
+
+/// Where the matched `path` is, with `cwd` standing for the empty path.
+fn on_disk(cwd: &Path, path: &str) -> PathBuf {
+    cwd.join(path)
+}
+
+/// Reads the names in the directory `path`, or none if it can't be read.
+fn names(cwd: &Path, path: &str) -> Vec<(String, bool)> {
+    let Ok(entries) = fs::read_dir(on_disk(cwd, path)) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Symlinks aren't followed, so `**` can't loop forever
+            let dir = entry.file_type().is_ok_and(|ty| ty.is_dir());
+            Some((name, dir))
+        })
+        .collect()
+}
+
+/// The entries of the directory `path` whose names match `component`,
+/// ignoring case if `nocase` is set.
+fn entries(cwd: &Path, path: &str, component: &str, nocase: bool) -> Vec<String> {
+    let dotted = component.starts_with('.') || component.starts_with("\\.");
+    let lowered = component.to_lowercase();
+    names(cwd, path)
+        .into_iter()
+        .filter(|(name, _)| dotted || !name.starts_with('.'))
+        .filter(|(name, _)| match nocase {
+            true => matches(&lowered, &name.to_lowercase()),
+            false => matches(component, name),
+        })
+        .map(|(name, _)| join(path, &name))
+        .collect()
+}
+
+/// What `**` matches under `path`: as the last component, everything below
+/// it; otherwise `path` itself and every directory below it.
+fn descendants(cwd: &Path, path: &str, last: bool) -> Vec<String> {
+    let mut found = Vec::new();
+    if !last {
+        found.push(path.to_owned());
+    }
+
+    let mut pending = vec![path.to_owned()];
+    while let Some(dir) = pending.pop() {
+        for (name, is_dir) in names(cwd, &dir) {
+            if name.starts_with('.') {
+                continue;
+            }
+            let child = join(&dir, &name);
+            if is_dir {
+                pending.push(child.clone());
+            }
+            if is_dir || last {
+                found.push(child);
+            }
+        }
+    }
+    found
+}
+
+/// Whether `name` matches the single-component `pattern`, which may use `*`,
+/// `?`, bracket expressions like `[a-z]` or `[!0-9]`, and backslash escapes.
+pub(crate) fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches_from(&pattern, &name)
+}
+
+fn matches_from(pattern: &[char], name: &[char]) -> bool {
+    let Some((&first, rest)) = pattern.split_first() else {
+        return name.is_empty();
+    };
+
+    match first {
+        '*' => (0..=name.len()).any(|skip| matches_from(rest, &name[skip..])),
+        '?' => !name.is_empty() && matches_from(rest, &name[1..]),
+        '[' => match (bracket(rest), name.first()) {
+            (Some((set, len)), Some(&c)) => {
+                set.contains(c) && matches_from(&rest[len..], &name[1..])
+            }
+            (Some(_), None) => false,
+            // An unclosed bracket is just a `[`
+            (None, _) => name.first() == Some(&'[') && matches_from(rest, &name[1..]),
+        },
+        '\\' if !rest.is_empty() => {
+            name.first() == Some(&rest[0]) && matches_from(&rest[1..], &name[1..])
+        }
+        c => name.first() == Some(&c) && matches_from(rest, &name[1..]),
+    }
+}
+
+/// The characters a bracket expression accepts.
+struct Bracket {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl Bracket {
+    fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+}
+
+/// Parses the bracket expression that follows a `[`, returning it with the
+/// number of characters it took up, including the closing `]`.
+fn bracket(pattern: &[char]) -> Option<(Bracket, usize)> {
+    let mut i = 0;
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let start = i;
+    while i < pattern.len() {
+        let mut c = pattern[i];
+        // A `]` right after the opening bracket is literal
+        if c == ']' && i > start {
+            return Some((Bracket { negated, ranges }, i + 1));
+        }
+        if c == '\\' && i + 1 < pattern.len() {
+            i += 1;
+            c = pattern[i];
+        }
+
+        match (pattern.get(i + 1), pattern.get(i + 2)) {
+            (Some('-'), Some(&hi)) if hi != ']' => {
+                ranges.push((c, hi));
+                i += 3;
+            }
+            _ => {
+                ranges.push((c, c));
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    mod matching {
+        use super::*;
+
+        #[test]
+        fn wildcards() {
+            for (pattern, name, expected) in [
+                ("*.rs", "main.rs", true),
+                ("*.rs", "main.rc", false),
+                ("a*b*c", "aXbYc", true),
+                ("a*b*c", "aXbY", false),
+                ("?", "x", true),
+                ("?", "", false),
+                ("*", "", true),
+            ] {
+                assert_eq!(matches(pattern, name), expected, "{pattern} {name}");
+            }
+        }
+
+        #[test]
+        fn brackets() {
+            for (pattern, name, expected) in [
+                ("[abc]", "b", true),
+                ("[a-c]x", "cx", true),
+                ("[!a-c]", "b", false),
+                ("[^a-c]", "d", true),
+                ("[]]", "]", true),
+                ("[a-]", "-", true),
+                ("[ab", "[ab", true),
+            ] {
+                assert_eq!(matches(pattern, name), expected, "{pattern} {name}");
+            }
+        }
+
+        #[test]
+        fn escapes() {
+            assert!(matches("\\*", "*"));
+            assert!(!matches("\\*", "a"));
+            assert!(matches("a\\?", "a?"));
+        }
+    }
+
+    mod expansion {
+        use super::*;
+
+        /// Builds a scratch tree and returns its path, which never contains
+        /// pattern characters.
+        fn tree(name: &str) -> String {
+            let root = env::temp_dir().join(format!("rush_glob_{name}_{}", std::process::id()));
+            fs::remove_dir_all(&root).ok();
+            for dir in ["src/bin", "src/util/deep", ".hidden"] {
+                fs::create_dir_all(root.join(dir)).unwrap();
+            }
+            for file in [
+                "a.rs",
+                "b.txt",
+                ".dot.rs",
+                "src/main.rs",
+                "src/bin/tool.rs",
+                "src/util/deep/x.rs",
+                ".hidden/h.rs",
+            ] {
+                fs::write(root.join(file), "").unwrap();
+            }
+            root.to_string_lossy().into_owned()
+        }
+
+        fn with_globstar() -> Options {
+            Options {
+                globstar: true,
+                ..Options::default()
+            }
+        }
+
+        fn relative(root: &str, paths: Vec<String>) -> Vec<String> {
+            paths
+                .into_iter()
+                .map(|path| path[root.len() + 1..].to_owned())
+                .collect()
+        }
+
+        #[test]
+        fn single_directory() {
+            let root = tree("single");
+            assert_eq!(
+                relative(
+                    &root,
+                    expand(&format!("{root}/*.rs"), Path::new("/"), &Options::default())
+                ),
+                ["a.rs"]
+            );
+            assert_eq!(
+                relative(
+                    &root,
+                    expand(&format!("{root}/.*"), Path::new("/"), &Options::default())
+                ),
+                [".dot.rs", ".hidden"]
+            );
+            assert!(
+                expand(&format!("{root}/*.md"), Path::new("/"), &Options::default()).is_empty()
+            );
+        }
+
+        #[test]
+        fn several_components() {
+            let root = tree("several");
+            assert_eq!(
+                relative(
+                    &root,
+                    expand(
+                        &format!("{root}/s*/*/*.rs"),
+                        Path::new("/"),
+                        &Options::default()
+                    )
+                ),
+                ["src/bin/tool.rs"]
+            );
+            assert_eq!(
+                relative(
+                    &root,
+                    expand(
+                        &format!("{root}/src/*/"),
+                        Path::new("/"),
+                        &Options::default()
+                    )
+                ),
+                ["src/bin/", "src/util/"]
+            );
+        }
+
+        #[test]
+        fn globstar() {
+            let root = tree("globstar");
+            let pattern = format!("{root}/src/**/*.rs");
+            assert_eq!(
+                relative(&root, expand(&pattern, Path::new("/"), &with_globstar())),
+                ["src/bin/tool.rs", "src/main.rs", "src/util/deep/x.rs"]
+            );
+            // Without the option, `**` is just `*`
+            assert_eq!(
+                relative(&root, expand(&pattern, Path::new("/"), &Options::default())),
+                ["src/bin/tool.rs"]
+            );
+
+            assert_eq!(
+                relative(
+                    &root,
+                    expand(&format!("{root}/src/**"), Path::new("/"), &with_globstar())
+                ),
+                [
+                    "src/bin",
+                    "src/bin/tool.rs",
+                    "src/main.rs",
+                    "src/util",
+                    "src/util/deep",
+                    "src/util/deep/x.rs"
+                ]
+            );
+        }
+
+        #[test]
+        fn relative_to_the_working_directory() {
+            let root = tree("relative");
+            let options = Options::default();
+            assert_eq!(
+                expand("src/*.rs", Path::new(&root), &options),
+                ["src/main.rs"]
+            );
+            assert_eq!(expand("*/", Path::new(&root), &options), ["src/"]);
+            assert_eq!(expand("a.rs", Path::new(&root), &options), ["a.rs"]);
+            assert!(expand("a.rs", Path::new("/"), &options).is_empty());
+        }
+
+        #[test]
+        fn nocaseglob() {
+            let root = tree("nocase");
+            fs::write(format!("{root}/UPPER.RS"), "").unwrap();
+            let pattern = format!("{root}/*.rs");
+            assert_eq!(
+                relative(&root, expand(&pattern, Path::new("/"), &Options::default())),
+                ["a.rs"]
+            );
+
+            let options = Options {
+                nocaseglob: true,
+                ..Options::default()
+            };
+            assert_eq!(
+                relative(&root, expand(&pattern, Path::new("/"), &options)),
+                ["UPPER.RS", "a.rs"]
+            );
+        }
+    }
+}
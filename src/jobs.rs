@@ -0,0 +1,915 @@
+use std::{
+    io::{self, IsTerminal},
+    mem,
+    process::{self, Child},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{editor::terminal::Settings, signals, state::ShellState, status::ExitStatus};
+
+/// A pipeline started with `&`, or stopped with Ctrl-Z, that the shell
+/// hasn't seen finish yet.
+#[derive(Debug)]
+pub(crate) struct Job {
+    /// The number it is listed under, as in `[1]`.
+    pub id: usize,
+    /// The command line as written, without the `&`.
+    pub command: String,
+    /// Whether it was stopped, and hasn't been continued since.
+    pub stopped: bool,
+    /// The process id of each of `children`, in order.
+    pids: Vec<u32>,
+    /// A process for each command of the pipeline, in order, or one copy of
+    /// the shell running all of it. A copy of the job in a copy of the
+    /// shell has none, as only the shell that started them can wait for
+    /// them.
+    children: Vec<Process>,
+    /// The process group its processes were started in.
+    pgid: u32,
+    /// How many of `children`, from the first, `fg` has seen exit.
+    waited: usize,
+    /// The signal that killed its last process, once it has finished, which
+    /// it's listed by rather than its status.
+    killed_by: Option<i32>,
+}
+
+/// One of the processes of a job: a program the shell started, or a copy of
+/// the shell it forked to run a command that isn't one, like a loop.
+#[derive(Debug)]
+enum Process {
+    Program(Child),
+    #[cfg(unix)]
+    Shell {
+        pid: u32,
+        /// What it exited with, once it has been waited for.
+        status: Option<process::ExitStatus>,
+    },
+}
+
+impl Process {
+    fn id(&self) -> u32 {
+        match self {
+            Self::Program(child) => child.id(),
+            #[cfg(unix)]
+            Self::Shell { pid, .. } => *pid,
+        }
+    }
+
+    /// What the process exited with, without waiting if it hasn't yet.
+    fn try_wait(&mut self) -> io::Result<Option<process::ExitStatus>> {
+        match self {
+            Self::Program(child) => child.try_wait(),
+            #[cfg(unix)]
+            Self::Shell {
+                status: Some(status),
+                ..
+            } => Ok(Some(*status)),
+            #[cfg(unix)]
+            Self::Shell { pid, status } => {
+                *status = poll(*pid)?;
+                Ok(*status)
+            }
+        }
+    }
+}
+
+/// Why a job specifier like `%make` didn't name a job.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum SpecError {
+    NoSuchJob,
+    /// More than one job's command matches.
+    Ambiguous,
+}
+
+/// What became of a process the shell waited for in the foreground.
+#[derive(Debug)]
+pub(crate) enum Waited {
+    Exited(process::ExitStatus),
+    /// Stopped by a signal like the `TSTP` Ctrl-Z sends, to be continued
+    /// later.
+    #[cfg(unix)]
+    Stopped,
+}
+
+impl Job {
+    /// The process id of the last command of the pipeline, which is the one
+    /// whose status the job finishes with.
+    pub(crate) fn pid(&self) -> u32 {
+        *self.pids.last().expect("a job has at least one process")
+    }
+
+    /// The process id of every command of the pipeline.
+    pub(crate) fn pids(&self) -> impl Iterator<Item = u32> {
+        self.pids.iter().copied()
+    }
+
+    /// A copy of the job to list, and send signals to, from a copy of the
+    /// shell, without the processes to wait for.
+    fn snapshot(&self) -> Self {
+        Self {
+            id: self.id,
+            command: self.command.clone(),
+            stopped: self.stopped,
+            pids: self.pids.clone(),
+            children: Vec::new(),
+            pgid: self.pgid,
+            waited: self.waited,
+            killed_by: self.killed_by,
+        }
+    }
+
+    /// Whether the job was copied from the shell that started it, which it
+    /// can't wait for.
+    fn is_snapshot(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// The process group of a job that was started with one of its own,
+    /// which is numbered after the process that leads it.
+    pub(crate) fn pgid(&self) -> u32 {
+        self.pgid
+    }
+
+    /// Continues the job's processes if they were stopped. A job in a
+    /// process group of its own is continued as a whole, so the programs
+    /// its commands started carry on too.
+    pub(crate) fn resume(&mut self) -> io::Result<()> {
+        self.signal(signals::number("CONT").expect("every platform has SIGCONT"))?;
+        self.stopped = false;
+        Ok(())
+    }
+
+    /// Sends `signal` to the job's process group, or to each of its
+    /// processes if it doesn't have one of its own.
+    fn signal(&self, signal: i32) -> io::Result<()> {
+        if signals::send_group(self.pgid(), signal).is_err() {
+            for pid in self.pids() {
+                signals::send(pid, signal)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for the job in the foreground, until its last process exits
+    /// or any of them is stopped again.
+    pub(crate) fn wait(&mut self) -> io::Result<Waited> {
+        if self.is_snapshot() {
+            return Err(io::Error::other("not a child of this shell"));
+        }
+        let mut last = None;
+        while let Some(child) = self.children.get(self.waited) {
+            match wait_for(child.id())? {
+                #[cfg(unix)]
+                Waited::Stopped => {
+                    self.stopped = true;
+                    return Ok(Waited::Stopped);
+                }
+                Waited::Exited(status) => last = Some(status),
+            }
+            self.waited += 1;
+        }
+        Ok(Waited::Exited(last.unwrap_or_default()))
+    }
+
+    /// The line announcing that the job finished with `status`, in the
+    /// format bash uses, like `[1]+  Done                    make`.
+    pub(crate) fn finished(&self, status: ExitStatus) -> String {
+        self.listing('+', Some(status), false)
+    }
+
+    /// The line `jobs` shows for the job, with `status` if it has finished.
+    /// `mark` is `+` for the current job, `-` for the one before it, and a
+    /// space otherwise. `long` adds the process id.
+    pub(crate) fn listing(&self, mark: char, status: Option<ExitStatus>, long: bool) -> String {
+        let (state, suffix) = match status.map(ExitStatus::code) {
+            None if self.stopped => ("Stopped".to_owned(), ""),
+            None => ("Running".to_owned(), " &"),
+            Some(0) => ("Done".to_owned(), ""),
+            Some(_) if let Some(signal) = self.killed_by => (signals::describe(signal), ""),
+            Some(code) => (format!("Exit {code}"), ""),
+        };
+        let pid = match long {
+            true => format!(" {} ", self.pid()),
+            false => "  ".to_owned(),
+        };
+        format!(
+            "[{}]{mark}{pid}{state:<24}{}{suffix}",
+            self.id, self.command
+        )
+    }
+}
+
+/// The background jobs of the shell, in the order they were started.
+#[derive(Debug, Default)]
+pub(crate) struct Jobs {
+    jobs: Vec<Job>,
+    /// The process id of the job started most recently, for `$!`. It is
+    /// kept after the job finishes.
+    last_pid: Option<u32>,
+}
+
+impl Jobs {
+    /// A copy of the jobs for a copy of the shell, like the one a command
+    /// substitution runs in. It can't wait for the original's jobs, but it
+    /// still lists them as they were, so `jobs -p | xargs kill` works. It
+    /// sees the same `$!` too.
+    pub(crate) fn snapshot(&self) -> Self {
+        Self {
+            jobs: self.jobs.iter().map(Job::snapshot).collect(),
+            last_pid: self.last_pid,
+        }
+    }
+
+    /// Adds a pipeline whose processes were just started, returning the job
+    /// it became. The first of them leads its process group, if it has one.
+    pub(crate) fn add(&mut self, command: &str, children: Vec<Child>) -> &Job {
+        self.last_pid = children.last().map(Child::id);
+        let pgid = children.first().map_or(0, Child::id);
+        let children = children.into_iter().map(Process::Program).collect();
+        self.push(command, children, pgid, false)
+    }
+
+    /// Adds a command started in copy `pid` of the shell, which leads its
+    /// process group if it has one, returning the job it became.
+    #[cfg(unix)]
+    pub(crate) fn add_shell(&mut self, command: &str, pid: u32) -> &Job {
+        self.last_pid = Some(pid);
+        let shell = Process::Shell { pid, status: None };
+        self.push(command, vec![shell], pid, false)
+    }
+
+    /// Adds a pipeline that was stopped in process group `pgid` while the
+    /// shell waited for it, returning the job it became.
+    pub(crate) fn add_stopped(&mut self, command: &str, children: Vec<Child>, pgid: u32) -> &Job {
+        let children = children.into_iter().map(Process::Program).collect();
+        self.push(command, children, pgid, true)
+    }
+
+    fn push(&mut self, command: &str, children: Vec<Process>, pgid: u32, stopped: bool) -> &Job {
+        // Like other shells, numbering starts over once the jobs above a
+        // number have finished
+        let id = self.jobs.last().map_or(1, |job| job.id + 1);
+        self.jobs.push(Job {
+            id,
+            command: command.trim().to_owned(),
+            stopped,
+            pids: children.iter().map(Process::id).collect(),
+            children,
+            pgid,
+            waited: 0,
+            killed_by: None,
+        });
+        self.jobs.last().expect("just pushed")
+    }
+
+    /// The job numbered `id`, if it is still running.
+    pub(crate) fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub(crate) fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    /// The job `fg` and `bg` act on when they aren't given one: the last
+    /// one stopped, or else the last one started.
+    pub(crate) fn current(&self) -> Option<&Job> {
+        (self.jobs.iter().rev().find(|job| job.stopped)).or(self.jobs.last())
+    }
+
+    /// The job `%-` names, which would be the current one without it.
+    pub(crate) fn previous(&self) -> Option<&Job> {
+        let current = self.current()?.id;
+        let others = || self.jobs.iter().rev().filter(|job| job.id != current);
+        others().find(|job| job.stopped).or_else(|| others().next())
+    }
+
+    /// The job a job specifier names, given without its `%`: nothing, `%`
+    /// or `+` for the current job, `-` for the previous one, its number,
+    /// the start of its command, or `?` and some text in its command.
+    pub(crate) fn find(&self, spec: &str) -> Result<&Job, SpecError> {
+        let only = |matches: &dyn Fn(&Job) -> bool| {
+            let mut found = self.jobs.iter().filter(|job| matches(job));
+            match (found.next(), found.next()) {
+                (Some(job), None) => Ok(job),
+                (Some(_), Some(_)) => Err(SpecError::Ambiguous),
+                (None, _) => Err(SpecError::NoSuchJob),
+            }
+        };
+        let found = match spec {
+            "" | "%" | "+" => self.current(),
+            "-" => self.previous(),
+            _ => match (spec.parse(), spec.strip_prefix('?')) {
+                (Ok(id), _) => self.get(id),
+                (Err(_), Some(text)) => return only(&|job| job.command.contains(text)),
+                (Err(_), None) => return only(&|job| job.command.starts_with(spec)),
+            },
+        };
+        found.ok_or(SpecError::NoSuchJob)
+    }
+
+    /// Takes job `id` out of the table, as once it has finished.
+    pub(crate) fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    /// The jobs still running, oldest first.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    pub(crate) fn last_pid(&self) -> Option<u32> {
+        self.last_pid
+    }
+
+    /// What to tell someone leaving the shell while it has jobs: that some
+    /// are stopped, or else that some are still running.
+    pub(crate) fn exit_warning(&mut self) -> Option<&'static str> {
+        if self.jobs.iter().any(|job| job.stopped) {
+            return Some("There are stopped jobs.");
+        }
+        let running = self.jobs.iter_mut().any(|job| {
+            let waited = job.waited;
+            let children = job.children.get_mut(waited..).unwrap_or_default();
+            (children.iter_mut()).any(|child| matches!(child.try_wait(), Ok(None)))
+        });
+        running.then_some("There are running jobs.")
+    }
+
+    /// Sends `HUP` to every job as the shell exits with `huponexit` on,
+    /// continuing the stopped ones so they can act on it.
+    pub(crate) fn hang_up(&self) {
+        let hup = signals::number("HUP").expect("every platform has SIGHUP");
+        let cont = signals::number("CONT").expect("every platform has SIGCONT");
+        for job in &self.jobs {
+            job.signal(hup).ok();
+            if job.stopped {
+                job.signal(cont).ok();
+            }
+        }
+    }
+
+    /// Removes the jobs that have finished since the last call, returning
+    /// each with the status it finished with.
+    pub(crate) fn reap(&mut self) -> Vec<(Job, ExitStatus)> {
+        let mut finished = Vec::new();
+        let mut running = Vec::new();
+
+        for mut job in self.jobs.drain(..) {
+            // There's no telling when a copied job is done, so it's kept as
+            // it was
+            if job.is_snapshot() {
+                running.push(job);
+                continue;
+            }
+            // A job is done once every process in it is, and finishes with
+            // the status of the last
+            let mut done = Some(ExitStatus::SUCCESS);
+            for child in &mut job.children[job.waited..] {
+                done = match child.try_wait() {
+                    Ok(Some(status)) => {
+                        job.killed_by = killed_by(status);
+                        done.and(Some(status.into()))
+                    }
+                    Ok(None) => None,
+                    // It can't be waited for, so there's nothing left to track
+                    Err(_) => done.and(Some(ExitStatus::FAILURE)),
+                };
+            }
+            match done {
+                Some(status) => finished.push((job, status)),
+                None => running.push(job),
+            }
+        }
+
+        self.jobs = running;
+        finished
+    }
+}
+
+/// The signal that killed a process that finished with `status`, if one did.
+fn killed_by(status: process::ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        None
+    }
+}
+
+/// Waits for process `pid` to exit or be stopped. Unlike [`Child::wait`],
+/// this returns when Ctrl-Z stops it, so the shell can get on without it.
+#[cfg(unix)]
+pub(crate) fn wait_for(pid: u32) -> io::Result<Waited> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let pid =
+        libc::pid_t::try_from(pid).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut status = 0;
+    loop {
+        // SAFETY: waitpid only writes to status
+        if unsafe { libc::waitpid(pid, &mut status, libc::WUNTRACED) } >= 0 {
+            break;
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    match libc::WIFSTOPPED(status) {
+        true => Ok(Waited::Stopped),
+        false => Ok(Waited::Exited(process::ExitStatus::from_raw(status))),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn wait_for(_pid: u32) -> io::Result<Waited> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// What process `pid` exited with, if it has, without waiting for it.
+#[cfg(unix)]
+fn poll(pid: u32) -> io::Result<Option<process::ExitStatus>> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let pid =
+        libc::pid_t::try_from(pid).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut status = 0;
+    // SAFETY: waitpid only writes to status
+    match unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } {
+        0 => Ok(None),
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(Some(process::ExitStatus::from_raw(status))),
+    }
+}
+
+/// Forks a copy of the shell that calls `run` and exits with the code it
+/// returns, without running anything else on the way out, returning the
+/// copy's process id. With a `group`, the copy joins it, leading it if it's
+/// the first, and the programs it starts stay in it.
+#[cfg(unix)]
+pub(crate) fn fork(group: Option<&ProcessGroup>, run: impl FnOnce() -> i32) -> io::Result<u32> {
+    let members = group.map(ProcessGroup::lock);
+    let pgid = match members.as_ref().and_then(|members| members.pgid) {
+        Some(pgid) => {
+            libc::pid_t::try_from(pgid).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?
+        }
+        None => 0,
+    };
+    // SAFETY: only this thread carries on in the copy, and it never returns
+    // from here. What other threads had locked stays locked in the copy,
+    // but they're only ever the stages of a pipeline, which share nothing
+    // with it but the streams it was given
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => {
+            let joins = members.is_some();
+            drop(members);
+            if joins {
+                join_group(pgid, false).ok();
+            }
+            let code = run();
+            // SAFETY: ends the copy without the exit handlers, or anything
+            // the shell does on its way out, like putting back the terminal
+            unsafe { libc::_exit(code) }
+        }
+        pid => {
+            if let Some(mut members) = members {
+                let pgid = *members.pgid.get_or_insert(pid as u32);
+                // SAFETY: setpgid only changes the process it's given; as
+                // with spawn, it's done here too in case the copy hasn't
+                // got that far
+                unsafe { libc::setpgid(pid, pgid as libc::pid_t) };
+            }
+            Ok(pid as u32)
+        }
+    }
+}
+
+/// Makes process group `pgid` the foreground one on the terminal on
+/// standard input, which is where the terminal sends the signals for keys
+/// like Ctrl-Z. Fails if there's no terminal. It only makes calls that are
+/// safe between `fork` and `exec`.
+#[cfg(unix)]
+pub(crate) fn hand_terminal(pgid: u32) -> io::Result<()> {
+    let pgid =
+        libc::pid_t::try_from(pgid).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // SAFETY: a process outside the foreground is stopped by TTOU for
+    // taking the terminal unless it ignores the signal, so it's ignored
+    // just for the call and then put back as it was
+    let result = unsafe {
+        let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+        let result = libc::tcsetpgrp(libc::STDIN_FILENO, pgid);
+        libc::signal(libc::SIGTTOU, previous);
+        result
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Gives the terminal back to the shell's own process group, once the job
+/// it was handed to has finished or stopped.
+#[cfg(unix)]
+pub(crate) fn reclaim_terminal() -> io::Result<()> {
+    // SAFETY: getpgrp takes nothing and can't fail
+    let pgid = unsafe { libc::getpgrp() };
+    hand_terminal(pgid as u32)
+}
+
+/// Puts back the terminal `settings` from before a job ran in the
+/// foreground, unless it `succeeded`. Like bash, a program that exits
+/// successfully keeps the settings it left, so `stty` works, but one that
+/// failed or was stopped may have left the terminal unusable.
+pub(crate) fn settle_terminal(settings: Option<&Settings>, succeeded: bool) {
+    if let Some(settings) = settings.filter(|_| !succeeded) {
+        settings.restore();
+    }
+}
+
+/// Run in a child just before it runs its program: puts it in process
+/// group `pgid`, or one of its own if that's 0 or gone, and makes that the
+/// terminal's foreground group if it's to be.
+#[cfg(unix)]
+fn join_group(pgid: libc::pid_t, foreground: bool) -> io::Result<()> {
+    // SAFETY: setpgid only changes the calling process, and getpgrp can't
+    // fail
+    unsafe {
+        // A group is gone once every process in it has exited, so the
+        // child leads a new one instead
+        if libc::setpgid(0, pgid) != 0 && libc::setpgid(0, 0) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Without a terminal, there's nothing to stop it from anyway
+        if foreground {
+            hand_terminal(libc::getpgrp() as u32).ok();
+        }
+    }
+    Ok(())
+}
+
+/// Whether the shell controls jobs, putting each pipeline it starts in a
+/// process group of its own. An interactive shell does, with a terminal to
+/// share between them, but not a copy of it running a subshell.
+pub(crate) fn job_control(state: &ShellState) -> bool {
+    cfg!(unix) && state.interactive && !state.subshell && io::stdin().is_terminal()
+}
+
+/// The process group the programs of one pipeline share, so the signals the
+/// terminal sends for keys like Ctrl-C and Ctrl-Z reach all of them and not
+/// the shell. The first program started leads it. A foreground group has
+/// the terminal from then until it finishes or is stopped.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ProcessGroup {
+    members: Arc<Mutex<Members>>,
+    #[cfg(unix)]
+    foreground: bool,
+    /// The terminal's settings from before a foreground group had it.
+    #[cfg(unix)]
+    terminal: Option<Settings>,
+}
+
+#[derive(Debug, Default)]
+struct Members {
+    pgid: Option<u32>,
+    /// The processes Ctrl-Z stopped, which the pipeline's job is made of.
+    stopped: Vec<Child>,
+}
+
+impl ProcessGroup {
+    /// A group for a pipeline the shell waits for.
+    pub(crate) fn foreground() -> Self {
+        Self {
+            #[cfg(unix)]
+            foreground: true,
+            #[cfg(unix)]
+            terminal: Settings::save(),
+            ..Self::default()
+        }
+    }
+
+    /// A group for a pipeline started with `&`, which doesn't get the
+    /// terminal.
+    pub(crate) fn background() -> Self {
+        Self::default()
+    }
+
+    /// Starts `command` in the group, leading it if it's the first.
+    pub(crate) fn spawn(&self, command: &mut process::Command) -> io::Result<Child> {
+        let mut members = self.lock();
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            let pgid = members.pgid.map_or(Ok(0), libc::pid_t::try_from);
+            let pgid = pgid.map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+            let foreground = self.foreground;
+            // SAFETY: the hook only makes calls that are safe after fork
+            unsafe { command.pre_exec(move || join_group(pgid, foreground)) };
+        }
+        let child = command.spawn()?;
+        let pgid = *members.pgid.get_or_insert(child.id());
+        #[cfg(not(unix))]
+        let _ = pgid;
+        // Also done here, in case the child hasn't got that far yet, since
+        // the next one joins its group and it may read from the terminal as
+        // soon as it starts. Once it has run its program, it's too late to
+        // move it, but then it has done so itself.
+        #[cfg(unix)]
+        if let (Ok(pid), Ok(group)) = (
+            libc::pid_t::try_from(child.id()),
+            libc::pid_t::try_from(pgid),
+        ) {
+            // SAFETY: setpgid only changes the process it's given
+            unsafe { libc::setpgid(pid, group) };
+            if self.foreground {
+                hand_terminal(pgid).ok();
+            }
+        }
+        Ok(child)
+    }
+
+    /// The group's number, once a program has been started in it.
+    pub(crate) fn pgid(&self) -> Option<u32> {
+        self.lock().pgid
+    }
+
+    /// Gives the terminal back to the shell once the whole group is done
+    /// with it, whether it finished or was stopped. See [`settle_terminal`]
+    /// for the settings it's left with.
+    pub(crate) fn finish(&self, succeeded: bool) {
+        #[cfg(unix)]
+        if self.foreground && self.pgid().is_some() {
+            reclaim_terminal().ok();
+            settle_terminal(self.terminal.as_ref(), succeeded);
+        }
+        #[cfg(not(unix))]
+        let _ = succeeded;
+    }
+
+    /// Keeps `child`, one of the group's programs that Ctrl-Z stopped, for
+    /// the job the pipeline becomes.
+    #[cfg(unix)]
+    pub(crate) fn stopped(&self, child: Child) {
+        self.lock().stopped.push(child);
+    }
+
+    /// The programs of the group that Ctrl-Z stopped.
+    pub(crate) fn take_stopped(&self) -> Vec<Child> {
+        mem::take(&mut self.lock().stopped)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Members> {
+        self.members.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{process::Command, thread, time::Duration};
+
+    use super::*;
+
+    fn spawn(script: &str) -> Child {
+        Command::new("sh").args(["-c", script]).spawn().unwrap()
+    }
+
+    fn kill(job: &mut Job) {
+        match &mut job.children[0] {
+            Process::Program(child) => child.kill().unwrap(),
+            #[cfg(unix)]
+            Process::Shell { pid, .. } => {
+                signals::send(*pid, signals::number("KILL").unwrap()).unwrap();
+            }
+        }
+    }
+
+    fn reap_all(jobs: &mut Jobs) -> Vec<(Job, ExitStatus)> {
+        let mut finished = Vec::new();
+        for _ in 0..200 {
+            finished.extend(jobs.reap());
+            if jobs.jobs.is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        finished
+    }
+
+    #[test]
+    fn jobs_are_numbered_from_one() {
+        let mut jobs = Jobs::default();
+        assert_eq!(jobs.add("true &", vec![spawn("true")]).id, 1);
+        let second = jobs.add(" exit 3 ", vec![spawn("exit 3")]);
+        assert_eq!(second.id, 2);
+        assert_eq!(second.command, "exit 3");
+
+        let mut finished = reap_all(&mut jobs);
+        finished.sort_by_key(|(job, _)| job.id);
+        let statuses: Vec<_> = finished.iter().map(|(_, s)| s.code()).collect();
+        assert_eq!(statuses, [0, 3]);
+
+        // With every job gone, numbering starts over
+        assert_eq!(jobs.add("true", vec![spawn("true")]).id, 1);
+        reap_all(&mut jobs);
+    }
+
+    #[test]
+    fn last_pid_outlives_the_job() {
+        let mut jobs = Jobs::default();
+        assert_eq!(jobs.last_pid(), None);
+        let pid = jobs.add("true", vec![spawn("true")]).pid();
+        reap_all(&mut jobs);
+
+        assert_eq!(jobs.last_pid(), Some(pid));
+        assert_eq!(jobs.snapshot().last_pid(), Some(pid));
+    }
+
+    #[test]
+    fn copies_list_the_jobs_they_cant_wait_for() {
+        let mut jobs = Jobs::default();
+        let pid = jobs.add("sleep 5", vec![spawn("sleep 5")]).pid();
+
+        let mut copy = jobs.snapshot();
+        let job = copy.get(1).unwrap();
+        assert_eq!((job.pid(), job.command.as_str()), (pid, "sleep 5"));
+        assert_eq!(
+            job.listing('+', None, true),
+            jobs.get(1).unwrap().listing('+', None, true)
+        );
+        assert!(copy.get_mut(1).unwrap().wait().is_err());
+        assert!(copy.reap().is_empty());
+        assert_eq!(copy.iter().count(), 1);
+
+        kill(&mut jobs.jobs[0]);
+        reap_all(&mut jobs);
+    }
+
+    #[test]
+    fn running_jobs_are_kept() {
+        let mut jobs = Jobs::default();
+        jobs.add("sleep 5", vec![spawn("sleep 5")]);
+        assert!(jobs.reap().is_empty());
+        assert_eq!(jobs.jobs.len(), 1);
+        kill(&mut jobs.jobs[0]);
+        reap_all(&mut jobs);
+    }
+
+    #[test]
+    fn pipelines_finish_with_their_last_process() {
+        let mut jobs = Jobs::default();
+        let children = vec![spawn("sleep 5"), spawn("exit 4")];
+        let pid = children[1].id();
+        assert_eq!(jobs.add("sleep 5 | exit 4", children).pid(), pid);
+
+        // Still running while any process of it is
+        thread::sleep(Duration::from_millis(50));
+        assert!(jobs.reap().is_empty());
+        kill(&mut jobs.jobs[0]);
+
+        let finished = reap_all(&mut jobs);
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].1.code(), 4);
+    }
+
+    #[test]
+    fn finished_notices() {
+        let mut jobs = Jobs::default();
+        jobs.add("make -j4", vec![spawn("true")]);
+        let job = &jobs.jobs[0];
+        assert_eq!(
+            job.finished(ExitStatus::SUCCESS),
+            "[1]+  Done                    make -j4"
+        );
+        assert_eq!(
+            job.finished(ExitStatus::from_code(2)),
+            "[1]+  Exit 2                  make -j4"
+        );
+        assert_eq!(
+            job.listing('-', None, false),
+            "[1]-  Running                 make -j4 &"
+        );
+        let long = job.listing(' ', None, true);
+        assert_eq!(
+            long,
+            format!("[1]  {} Running                 make -j4 &", job.pid())
+        );
+        reap_all(&mut jobs);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn jobs_killed_by_a_signal_are_listed_by_it() {
+        let mut jobs = Jobs::default();
+        jobs.add("sleep 5", vec![spawn("kill -TERM $$")]);
+        jobs.add("exit 143", vec![spawn("exit 143")]);
+        let mut finished = reap_all(&mut jobs);
+        finished.sort_by_key(|(job, _)| job.id);
+        let listed: Vec<_> = finished
+            .iter()
+            .map(|(job, status)| job.listing(' ', Some(*status), false))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                "[1]   Terminated              sleep 5",
+                "[2]   Exit 143                exit 143"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_group_is_led_by_its_first_process() {
+        let group = ProcessGroup::background();
+        let mut first = group.spawn(Command::new("sleep").arg("5")).unwrap();
+        let second = group
+            .spawn(
+                Command::new("sh")
+                    .args(["-c", "ps -o pgid= -p $$"])
+                    .stdout(process::Stdio::piped()),
+            )
+            .unwrap();
+        let output = second.wait_with_output().unwrap();
+        first.kill().unwrap();
+        first.wait().unwrap();
+
+        assert_eq!(group.pgid(), Some(first.id()));
+        let pgid = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(pgid.trim(), first.id().to_string());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn forked_shells_are_jobs_too() {
+        let mut jobs = Jobs::default();
+        let pid = fork(None, || 3).unwrap();
+        assert_eq!(jobs.add_shell("f", pid).pid(), pid);
+        assert_eq!(jobs.last_pid(), Some(pid));
+
+        let finished = reap_all(&mut jobs);
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].1.code(), 3);
+    }
+
+    #[test]
+    fn specifiers_name_one_job() {
+        let mut jobs = Jobs::default();
+        jobs.add("sleep 5", vec![spawn("sleep 5")]);
+        jobs.add("sleep 6", vec![spawn("sleep 6")]);
+        jobs.add("cat", vec![spawn("sleep 7")]);
+        fn id(jobs: &Jobs, spec: &str) -> Result<usize, SpecError> {
+            jobs.find(spec).map(|job| job.id)
+        }
+
+        assert_eq!(id(&jobs, ""), Ok(3));
+        assert_eq!(id(&jobs, "%"), Ok(3));
+        assert_eq!(id(&jobs, "+"), Ok(3));
+        assert_eq!(id(&jobs, "-"), Ok(2));
+        assert_eq!(id(&jobs, "1"), Ok(1));
+        assert_eq!(id(&jobs, "4"), Err(SpecError::NoSuchJob));
+        assert_eq!(id(&jobs, "ca"), Ok(3));
+        assert_eq!(id(&jobs, "sl"), Err(SpecError::Ambiguous));
+        assert_eq!(id(&jobs, "?6"), Ok(2));
+        assert_eq!(id(&jobs, "?e"), Err(SpecError::Ambiguous));
+        assert_eq!(id(&jobs, "?x"), Err(SpecError::NoSuchJob));
+
+        // A stopped job is current, and the last one started is previous
+        jobs.jobs[0].stopped = true;
+        assert_eq!(id(&jobs, "+"), Ok(1));
+        assert_eq!(id(&jobs, "-"), Ok(3));
+
+        for job in &mut jobs.jobs {
+            kill(job);
+        }
+        reap_all(&mut jobs);
+        assert_eq!(id(&jobs, "+"), Err(SpecError::NoSuchJob));
+        assert_eq!(id(&jobs, "-"), Err(SpecError::NoSuchJob));
+    }
+
+    #[test]
+    fn exiting_warns_of_jobs_left() {
+        let mut jobs = Jobs::default();
+        assert_eq!(jobs.exit_warning(), None);
+        jobs.add("sleep 5", vec![spawn("exec sleep 5")]);
+        assert_eq!(jobs.exit_warning(), Some("There are running jobs."));
+        jobs.add("true", vec![spawn("true")]);
+        jobs.jobs[1].stopped = true;
+        assert_eq!(jobs.exit_warning(), Some("There are stopped jobs."));
+
+        jobs.hang_up();
+        let finished = reap_all(&mut jobs);
+        let hup = signals::number("HUP").unwrap();
+        assert!(
+            finished
+                .iter()
+                .any(|(job, status)| job.id == 1 && *status == ExitStatus::from_signal(hup))
+        );
+        assert_eq!(jobs.exit_warning(), None);
+    }
+}
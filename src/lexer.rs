@@ -0,0 +1,945 @@
+use std::{borrow::Cow, ops::Range};
+
+use crate::util::{
+    Modifier, ModifierKind, RedirectKind, RushError, SyntaxError, SyntaxErrorKind, Word, WordPart,
+    is_name, is_special_parameter,
+};
+
+/// How many `${...}` may be nested inside one another, so a word can't run
+/// the lexer out of stack.
+const MAX_DEPTH: usize = 64;
+
+/// An operator that joins commands together, rather than redirecting one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Operator {
+    /// `|`
+    Pipe,
+    /// `||`
+    Or,
+    /// `&`
+    Ampersand,
+    /// `&&`
+    And,
+    /// `;`
+    Semicolon,
+    /// `;;`, ending a clause of a `case`.
+    DoubleSemicolon,
+    /// `(` before a `case` pattern, which is only an operator there.
+    OpenParen,
+    /// `)` after a `case` pattern, which is only an operator there.
+    CloseParen,
+}
+
+impl Operator {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Operator::Pipe => "|",
+            Operator::Or => "||",
+            Operator::Ampersand => "&",
+            Operator::And => "&&",
+            Operator::Semicolon => ";",
+            Operator::DoubleSemicolon => ";;",
+            Operator::OpenParen => "(",
+            Operator::CloseParen => ")",
+        }
+    }
+}
+
+/// What a token is. The text of a word borrows from the input unless an
+/// escape had to be taken out of it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum TokenKind<'a> {
+    /// Unquoted spaces between words.
+    Blank,
+    /// Unquoted text, along with any expansions in it.
+    Text(Word<'a>),
+    /// A quoted string, without its quotes.
+    Quoted(Word<'a>),
+    /// A redirection operator, with the descriptor written right before it,
+    /// as in `2>`.
+    Redirect {
+        fd: Option<u32>,
+        kind: RedirectKind,
+    },
+    Operator(Operator),
+    /// An unquoted line break.
+    Newline,
+}
+
+/// A token along with the byte range of the input it was read from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Token<'a> {
+    pub kind: TokenKind<'a>,
+    pub span: Range<usize>,
+}
+
+impl Token<'_> {
+    /// The same token, no longer borrowing from the input.
+    pub(crate) fn into_owned(self) -> Token<'static> {
+        let kind = match self.kind {
+            TokenKind::Text(word) => TokenKind::Text(word.into_owned()),
+            TokenKind::Quoted(word) => TokenKind::Quoted(word.into_owned()),
+            TokenKind::Blank => TokenKind::Blank,
+            TokenKind::Redirect { fd, kind } => TokenKind::Redirect { fd, kind },
+            TokenKind::Operator(operator) => TokenKind::Operator(operator),
+            TokenKind::Newline => TokenKind::Newline,
+        };
+        Token {
+            kind,
+            span: self.span,
+        }
+    }
+}
+
+/// Reads the tokens of a command line one at a time.
+///
+/// Positions are byte indices into the input, so spans can be used to slice
+/// it directly and point at the offending text in an error. Comments are
+/// skipped. Here-document bodies aren't tokens: whoever reads a `Newline`
+/// after a `<<` reads the bodies that follow it with [`Lexer::here_doc`].
+#[derive(Debug)]
+pub(crate) struct Lexer<'a> {
+    input: &'a str,
+    /// The byte index of the next character to read.
+    pos: usize,
+    /// Whether a `case` pattern is being read, where `(` and `)` are
+    /// operators rather than part of a word.
+    patterns: bool,
+}
+
+impl<'a> Lexer<'a> {
+    #[cfg(test)]
+    pub(crate) fn new(input: &'a str) -> Self {
+        Self::at(input, 0)
+    }
+
+    /// A lexer that starts reading at the byte index `pos` of `input`.
+    pub(crate) fn at(input: &'a str, pos: usize) -> Self {
+        Self {
+            input,
+            pos,
+            patterns: false,
+        }
+    }
+
+    /// The byte index of the next character to read.
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// Reads the next token of a `case` pattern, like [`Lexer::next_token`]
+    /// does but with `(` and `)` read as operators.
+    pub(crate) fn next_pattern_token(&mut self) -> Result<Option<Token<'a>>, RushError> {
+        self.patterns = true;
+        let token = self.next_token();
+        self.patterns = false;
+        token
+    }
+
+    /// Reads the next token, or `None` at the end of the input.
+    pub(crate) fn next_token(&mut self) -> Result<Option<Token<'a>>, RushError> {
+        self.skip_comment();
+        let start = self.pos;
+        let Some(c) = self.peek() else {
+            return Ok(None);
+        };
+
+        let kind = match c {
+            ' ' | '\t' => {
+                while self.eat(' ') || self.eat('\t') {}
+                TokenKind::Blank
+            }
+            '\n' => {
+                self.pos += 1;
+                TokenKind::Newline
+            }
+            '\'' | '"' => TokenKind::Quoted(self.quoted(c)?),
+            '<' | '>' => self.redirect(None),
+            '&' if self.input[start + 1..].starts_with('>') => {
+                self.pos += 2;
+                let kind = match self.eat('>') {
+                    true => RedirectKind::AppendAll,
+                    false => RedirectKind::OutputAll,
+                };
+                TokenKind::Redirect { fd: None, kind }
+            }
+            '|' | '&' | ';' => {
+                self.pos += 1;
+                TokenKind::Operator(match c {
+                    '|' if self.eat('|') => Operator::Or,
+                    '|' => Operator::Pipe,
+                    '&' if self.eat('&') => Operator::And,
+                    '&' => Operator::Ampersand,
+                    ';' if self.eat(';') => Operator::DoubleSemicolon,
+                    _ => Operator::Semicolon,
+                })
+            }
+            '(' | ')' if self.patterns => {
+                self.pos += 1;
+                TokenKind::Operator(match c {
+                    '(' => Operator::OpenParen,
+                    _ => Operator::CloseParen,
+                })
+            }
+            _ => {
+                let text = self.text()?;
+                // Digits written right before a redirection operator name the
+                // descriptor it redirects, as in `2>errors.log`
+                let fd = match text.parts() {
+                    [WordPart::Literal(digits)]
+                        if digits.bytes().all(|b| b.is_ascii_digit())
+                            && matches!(self.peek(), Some('<' | '>')) =>
+                    {
+                        digits.parse().ok()
+                    }
+                    _ => None,
+                };
+                match fd {
+                    Some(fd) => self.redirect(Some(fd)),
+                    None => TokenKind::Text(text),
+                }
+            }
+        };
+
+        Ok(Some(Token {
+            kind,
+            span: start..self.pos,
+        }))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    /// Moves past `c` if it is the next character.
+    fn eat(&mut self, c: char) -> bool {
+        let next = self.peek() == Some(c);
+        if next {
+            self.pos += c.len_utf8();
+        }
+        next
+    }
+
+    /// Whether a `#` here would start a comment rather than be part of a word.
+    fn at_word_start(&self) -> bool {
+        self.pos == 0 || self.input[..self.pos].ends_with([' ', '\t'])
+    }
+
+    /// Skips a comment, up to but not including the end of its line, since
+    /// here-document bodies may still follow it.
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') && self.at_word_start() {
+            self.pos = self.input[self.pos..]
+                .find('\n')
+                .map_or(self.input.len(), |len| self.pos + len);
+        }
+    }
+
+    /// Reads unquoted text up to the next blank, quote, or operator.
+    fn text(&mut self) -> Result<Word<'a>, RushError> {
+        let mut word = Word::default();
+
+        loop {
+            let patterns = self.patterns;
+            word.push_str(self.run(|before, c| match c {
+                ' ' | '\t' | '\n' | '\'' | '"' | '<' | '>' | '|' | '&' | ';' | '$' => true,
+                '(' | ')' => patterns,
+                '#' => before.is_empty() || before.ends_with([' ', '\t']),
+                _ => false,
+            }));
+            match self.peek() {
+                Some('$') => self.dollar(false, &mut word)?,
+                _ => break,
+            }
+        }
+
+        Ok(word)
+    }
+
+    /// Moves past the characters before the first one that `stops`, given
+    /// the input before it, and gives them back as a slice of the input.
+    fn run(&mut self, stops: impl Fn(&str, char) -> bool) -> &'a str {
+        let start = self.pos;
+        for (i, c) in self.input[start..].char_indices() {
+            if stops(&self.input[..start + i], c) {
+                self.pos = start + i;
+                return &self.input[start..self.pos];
+            }
+        }
+        self.pos = self.input.len();
+        &self.input[start..]
+    }
+
+    /// Reads the string quoted with `quote`, which starts at the current
+    /// position.
+    fn quoted(&mut self, quote: char) -> Result<Word<'a>, RushError> {
+        let start = self.pos;
+        let mut word = Word::default();
+        self.pos += 1;
+
+        loop {
+            word.push_str(self.quoted_text(quote));
+            match self.peek() {
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('$') => self.dollar(true, &mut word)?,
+                _ => {
+                    return Err(self.syntax_error(
+                        SyntaxErrorKind::UnterminatedQuote,
+                        start..self.input.len(),
+                    ));
+                }
+            }
+        }
+
+        word.quote();
+        // `''` is still an argument, just an empty one
+        if word.parts().is_empty() {
+            word.push_part(WordPart::Quoted(Cow::Borrowed("")));
+        }
+        Ok(word)
+    }
+
+    /// Reads the text inside `quote` up to the closing quote, or up to an
+    /// expansion inside double quotes. It is borrowed from the input unless
+    /// a backslash escape has to be taken out of it.
+    fn quoted_text(&mut self, quote: char) -> Cow<'a, str> {
+        if quote == '\'' {
+            return Cow::Borrowed(self.run(|_, c| c == '\''));
+        }
+        let mut text = Cow::Borrowed(self.run(|_, c| matches!(c, '"' | '$' | '\\')));
+        // Inside double quotes, a backslash only escapes the characters that
+        // would otherwise be special there
+        while self.eat('\\') {
+            match self.peek().filter(|c| matches!(c, '$' | '`' | '"' | '\\')) {
+                Some(escaped) => {
+                    text.to_mut().push(escaped);
+                    self.pos += 1;
+                }
+                None => text.to_mut().push('\\'),
+            }
+            text.to_mut()
+                .push_str(self.run(|_, c| matches!(c, '"' | '$' | '\\')));
+        }
+        text
+    }
+
+    /// Reads the expansion introduced by the `$` at the current position
+    /// into `word`, or the `$` itself if it doesn't start one.
+    fn dollar(&mut self, quoted: bool, word: &mut Word<'a>) -> Result<(), RushError> {
+        match self.parse_dollar(self.pos, quoted, 0)? {
+            Some((part, end)) => {
+                word.push_part(part);
+                self.pos = end + 1;
+            }
+            None => {
+                word.push('$');
+                self.pos += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the redirection operator at the current position.
+    fn redirect(&mut self, fd: Option<u32>) -> TokenKind<'a> {
+        let kind = match self.eat('<') {
+            true if self.eat('<') => match self.eat('-') {
+                true => RedirectKind::HereDocStrip,
+                false => RedirectKind::HereDoc,
+            },
+            true if self.eat('&') => RedirectKind::DuplicateInput,
+            true => RedirectKind::Input,
+            false => {
+                // Past the `>`
+                self.pos += 1;
+                if self.eat('>') {
+                    RedirectKind::Append
+                } else if self.eat('&') {
+                    RedirectKind::Duplicate
+                } else if self.eat('|') {
+                    RedirectKind::Clobber
+                } else {
+                    RedirectKind::Output
+                }
+            }
+        };
+        TokenKind::Redirect { fd, kind }
+    }
+
+    /// Reads the body of the here-document introduced at `at` and ended by
+    /// `delimiter`, which starts at the next line of input, and moves past
+    /// its delimiter line.
+    ///
+    /// Expansions in the body are recognized unless any part of the
+    /// delimiter was quoted, but it is never split into words.
+    pub(crate) fn here_doc(
+        &mut self,
+        delimiter: &Word,
+        kind: RedirectKind,
+        at: usize,
+    ) -> Result<Word<'a>, RushError> {
+        let literal = delimiter
+            .parts()
+            .iter()
+            .any(|part| matches!(part, WordPart::Quoted(_)));
+        let delimiter = delimiter.to_string();
+        let mut body = Word::default();
+        let mut line_start = self.pos;
+
+        for line in self.input[self.pos..].split_inclusive('\n') {
+            let end = line_start + line.len();
+            let tabs = match kind {
+                RedirectKind::HereDocStrip => line.len() - line.trim_start_matches('\t').len(),
+                _ => 0,
+            };
+            let text = &line[tabs..];
+            if text.strip_suffix('\n').unwrap_or(text) == delimiter {
+                self.pos = end;
+                return Ok(body);
+            }
+
+            match literal {
+                true => body.push_part(WordPart::Quoted(Cow::Borrowed(text))),
+                false => self.parse_here_doc_line(line_start + tabs..end, &mut body)?,
+            }
+            line_start = end;
+        }
+
+        // Show only the line the operator is on, since the body may be long
+        let line_end = self.input[at..]
+            .find('\n')
+            .map_or(self.input.len(), |len| at + len);
+        Err(RushError::Syntax(SyntaxError {
+            kind: SyntaxErrorKind::UnterminatedHereDoc(delimiter),
+            line: self.input[..line_end].to_owned(),
+            span: at..at + kind.as_str().len(),
+        }))
+    }
+
+    /// Parses one line of a here-document body whose expansions are
+    /// recognized, much like the inside of double quotes except that `"` is
+    /// an ordinary character.
+    fn parse_here_doc_line(
+        &self,
+        range: Range<usize>,
+        body: &mut Word<'a>,
+    ) -> Result<(), RushError> {
+        let end = range.end;
+        let mut chars = self.input[range.clone()]
+            .char_indices()
+            .map(|(i, c)| (range.start + i, c))
+            .peekable();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next_if(|&(_, c)| matches!(c, '$' | '`' | '\\' | '\n')) {
+                    // An escaped newline joins the next line onto this one
+                    Some((_, '\n')) => {}
+                    Some((_, escaped)) => body.push_quoted(escaped),
+                    None => body.push_quoted('\\'),
+                },
+                '$' => match self.parse_dollar(i, true, 0)? {
+                    Some((part, part_end)) if part_end < end => {
+                        body.push_part(part);
+                        while chars.next_if(|&(j, _)| j <= part_end).is_some() {}
+                    }
+                    _ => body.push_quoted('$'),
+                },
+                c => body.push_quoted(c),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses the expansion introduced by the `$` at `start`, returning it
+    /// along with the byte index of its last character, or `None` if the `$`
+    /// doesn't start an expansion. `depth` is how many `${...}` it is inside.
+    fn parse_dollar(
+        &self,
+        start: usize,
+        quoted: bool,
+        depth: usize,
+    ) -> Result<Option<(WordPart<'a>, usize)>, RushError> {
+        let rest = &self.input[start + 1..];
+        match rest.chars().next() {
+            Some('(') => {
+                let end = self.find_substitution_end(start)?;
+                let command = self.input[start + 2..end].to_owned();
+                Ok(Some((
+                    WordPart::CommandSubstitution { command, quoted },
+                    end,
+                )))
+            }
+            Some('{') => {
+                let end = self.find_brace_end(start)?;
+                let part = self.parse_braced(start, end, quoted, depth)?;
+                Ok(Some((part, end)))
+            }
+            Some(c) if is_special_parameter(c) => {
+                let part = WordPart::Parameter {
+                    name: c.into(),
+                    quoted,
+                    modifier: None,
+                };
+                Ok(Some((part, start + c.len_utf8())))
+            }
+            Some(c) if c == '_' || c.is_ascii_alphabetic() => {
+                let len = rest
+                    .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len());
+                let part = WordPart::Parameter {
+                    name: rest[..len].to_owned(),
+                    quoted,
+                    modifier: None,
+                };
+                Ok(Some((part, start + len)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Parses `${...}` spanning `start..=end`: a name, optionally followed by
+    /// a modifier operator and the word it applies. A name may have an array
+    /// subscript, as in `${a[1]}`, and `${#name}` is kept as the name
+    /// `#name`, for the length of its value.
+    fn parse_braced(
+        &self,
+        start: usize,
+        end: usize,
+        quoted: bool,
+        depth: usize,
+    ) -> Result<WordPart<'a>, RushError> {
+        if depth >= MAX_DEPTH {
+            return Err(self.syntax_error(SyntaxErrorKind::NestedTooDeeply, start..start + 2));
+        }
+        let bad_substitution = || {
+            self.syntax_error(
+                SyntaxErrorKind::BadSubstitution(self.input[start..=end].to_owned()),
+                start..end + 1,
+            )
+        };
+
+        let content = &self.input[start + 2..end];
+        if let Some(name) = content.strip_prefix('#')
+            && !name.is_empty()
+            && parameter_name_len(name) == Some(name.len())
+        {
+            return Ok(WordPart::Parameter {
+                name: content.to_owned(),
+                quoted,
+                modifier: None,
+            });
+        }
+        let name_len = parameter_name_len(content).ok_or_else(bad_substitution)?;
+        let name = &content[..name_len];
+
+        let rest = &content[name_len..];
+        if rest.is_empty() {
+            return Ok(WordPart::Parameter {
+                name: name.to_owned(),
+                quoted,
+                modifier: None,
+            });
+        }
+
+        let (colon, op) = match rest.strip_prefix(':') {
+            Some(op) => (true, op),
+            None => (false, rest),
+        };
+        let (kind, op_len) = ModifierKind::from_prefix(op, colon).ok_or_else(bad_substitution)?;
+        if kind == ModifierKind::Substring && op.is_empty() {
+            return Err(bad_substitution());
+        }
+
+        // The word starts right after the operator. Patterns are matched
+        // the same inside double quotes as out, so they aren't quoted there.
+        let word_start = end - (op.len() - op_len);
+        let pattern_quoted = quoted && !kind.transforms();
+        let separator = match kind {
+            ModifierKind::Replace(_) => self.find_separator(word_start, end, '/'),
+            ModifierKind::Substring => self.find_separator(word_start, end, ':'),
+            _ => None,
+        };
+        let depth = depth + 1;
+        let word =
+            self.parse_modifier_word(word_start, separator.unwrap_or(end), pattern_quoted, depth)?;
+        let second = match separator {
+            Some(separator) => Some(self.parse_modifier_word(separator + 1, end, quoted, depth)?),
+            None => None,
+        };
+
+        Ok(WordPart::Parameter {
+            name: name.to_owned(),
+            quoted,
+            modifier: Some(Box::new(Modifier {
+                kind,
+                colon: colon && !kind.transforms(),
+                word,
+                second,
+            })),
+        })
+    }
+
+    /// Finds the first `separator` in `start..end` that isn't quoted,
+    /// escaped, or inside a nested expansion.
+    fn find_separator(&self, start: usize, end: usize, separator: char) -> Option<usize> {
+        let mut quote: Option<char> = None;
+        let mut depth = 0;
+        let mut chars = self.input[start..end].char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' if quote != Some('\'') => {
+                    chars.next();
+                }
+                '\'' | '"' if quote.is_none() => quote = Some(c),
+                c if quote == Some(c) => quote = None,
+                _ if quote.is_some() => {}
+                '$' if chars.next_if(|&(_, c)| c == '{' || c == '(').is_some() => depth += 1,
+                '{' | '(' if depth > 0 => depth += 1,
+                '}' | ')' if depth > 0 => depth -= 1,
+                c if c == separator && depth == 0 => return Some(start + i),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Parses the word of a `${NAME:-word}` modifier spanning `start..end`.
+    /// Quotes are removed and expansions recognized, but whitespace is kept
+    /// rather than splitting the word.
+    fn parse_modifier_word(
+        &self,
+        start: usize,
+        end: usize,
+        quoted: bool,
+        depth: usize,
+    ) -> Result<Word<'a>, RushError> {
+        let mut word = Word::default();
+        let mut quote: Option<char> = None;
+        let mut chars = self.input[start..end]
+            .char_indices()
+            .map(|(i, c)| (start + i, c))
+            .peekable();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                // Inside double quotes, single quotes are ordinary characters
+                '\'' if quoted && quote.is_none() => word.push_quoted(c),
+                '\'' | '"' if quote.is_none() => quote = Some(c),
+                c if quote == Some(c) => quote = None,
+                '\\' if quote == Some('"') => {
+                    match chars.next_if(|&(_, c)| matches!(c, '$' | '`' | '"' | '\\')) {
+                        Some((_, escaped)) => word.push_quoted(escaped),
+                        None => word.push_quoted('\\'),
+                    }
+                }
+                '$' if quote != Some('\'') => {
+                    match self.parse_dollar(i, quoted || quote.is_some(), depth)? {
+                        Some((part, part_end)) => {
+                            word.push_part(part);
+                            while chars.next_if(|&(j, _)| j <= part_end).is_some() {}
+                        }
+                        None if quoted || quote.is_some() => word.push_quoted('$'),
+                        None => word.push('$'),
+                    }
+                }
+                c if quoted || quote.is_some() => word.push_quoted(c),
+                c => word.push(c),
+            }
+        }
+
+        Ok(word)
+    }
+
+    /// Returns the byte index of the `)` closing the `$(` at `start`, skipping
+    /// over nested parens and quoted text.
+    fn find_substitution_end(&self, start: usize) -> Result<usize, RushError> {
+        self.find_closing(start, '(', ')').ok_or_else(|| {
+            self.syntax_error(
+                SyntaxErrorKind::UnterminatedSubstitution,
+                start..self.input.len(),
+            )
+        })
+    }
+
+    /// Returns the byte index of the `}` closing the `${` at `start`.
+    fn find_brace_end(&self, start: usize) -> Result<usize, RushError> {
+        self.find_closing(start, '{', '}').ok_or_else(|| {
+            self.syntax_error(
+                SyntaxErrorKind::BadSubstitution(self.input[start..].to_owned()),
+                start..self.input.len(),
+            )
+        })
+    }
+
+    /// Finds the `close` matching the two-character opener (`$` then `open`)
+    /// at `start`, counting nested pairs and skipping quoted text.
+    fn find_closing(&self, start: usize, open: char, close: char) -> Option<usize> {
+        let mut depth = 0;
+        let mut quote: Option<char> = None;
+
+        for (i, c) in self.input[start + 2..].char_indices() {
+            match c {
+                '\'' | '"' if quote.is_none() => quote = Some(c),
+                c if quote == Some(c) => quote = None,
+                _ if quote.is_some() => {}
+                c if c == open => depth += 1,
+                c if c == close && depth == 0 => return Some(start + 2 + i),
+                c if c == close => depth -= 1,
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    pub(crate) fn syntax_error(&self, kind: SyntaxErrorKind, span: Range<usize>) -> RushError {
+        RushError::Syntax(SyntaxError {
+            kind,
+            line: self.input.to_owned(),
+            span,
+        })
+    }
+}
+
+/// The length of the parameter name `content` starts with: a special
+/// parameter, the digits of a positional one, or a variable name along with
+/// any `[subscript]` after it. `None` if it starts with none of those.
+fn parameter_name_len(content: &str) -> Option<usize> {
+    let special = content.chars().next().filter(|&c| is_special_parameter(c));
+    let name_len = match special {
+        Some(c) if c.is_ascii_digit() => content
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(content.len()),
+        Some(c) => c.len_utf8(),
+        None => content
+            .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+            .unwrap_or(content.len()),
+    };
+    if special.is_none() && !is_name(&content[..name_len]) {
+        return None;
+    }
+    match content[name_len..].strip_prefix('[') {
+        Some(rest) if special.is_none() => rest.find(']').map(|len| name_len + len + 2),
+        _ => Some(name_len),
+    }
+}
+
+/// Reads the subscript of `name[subscript]` as a word, with its quotes
+/// removed and its expansions recognized, but without splitting it.
+pub(crate) fn subscript_word(subscript: &str) -> Result<Word<'_>, RushError> {
+    Lexer::at(subscript, 0).parse_modifier_word(0, subscript.len(), false, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Token<'_>> {
+        let mut lexer = Lexer::new(input);
+        let mut tokens = Vec::new();
+        while let Some(token) = lexer.next_token().unwrap() {
+            tokens.push(token);
+        }
+        tokens
+    }
+
+    fn kinds(input: &str) -> Vec<TokenKind<'_>> {
+        tokens(input).into_iter().map(|token| token.kind).collect()
+    }
+
+    fn text(s: &str) -> TokenKind<'_> {
+        TokenKind::Text(Word::from_parts(vec![WordPart::Literal(s.into())]))
+    }
+
+    #[test]
+    fn spans_are_byte_ranges() {
+        let input = "echo 日本 'a b'";
+        let spans: Vec<_> = tokens(input)
+            .into_iter()
+            .map(|token| &input[token.span])
+            .collect();
+        assert_eq!(spans, ["echo", " ", "日本", " ", "'a b'"]);
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            kinds("a|b||c&d&&e;"),
+            [
+                text("a"),
+                TokenKind::Operator(Operator::Pipe),
+                text("b"),
+                TokenKind::Operator(Operator::Or),
+                text("c"),
+                TokenKind::Operator(Operator::Ampersand),
+                text("d"),
+                TokenKind::Operator(Operator::And),
+                text("e"),
+                TokenKind::Operator(Operator::Semicolon),
+            ]
+        );
+        assert_eq!(
+            kinds("a;;b"),
+            [
+                text("a"),
+                TokenKind::Operator(Operator::DoubleSemicolon),
+                text("b")
+            ]
+        );
+    }
+
+    #[test]
+    fn parens_are_operators_only_in_patterns() {
+        assert_eq!(kinds("(a)"), [text("(a)")]);
+
+        let mut lexer = Lexer::new("(a|$(b))c");
+        let mut kinds = Vec::new();
+        while let Some(token) = lexer.next_pattern_token().unwrap() {
+            kinds.push(token.kind);
+        }
+        assert_eq!(kinds[0], TokenKind::Operator(Operator::OpenParen));
+        assert_eq!(kinds[1], text("a"));
+        assert_eq!(kinds[2], TokenKind::Operator(Operator::Pipe));
+        assert!(matches!(&kinds[3], TokenKind::Text(word) if word.parts().len() == 1));
+        assert_eq!(kinds[4], TokenKind::Operator(Operator::CloseParen));
+        assert_eq!(kinds[5], text("c"));
+    }
+
+    #[test]
+    fn redirections() {
+        let redirect = |fd, kind| TokenKind::Redirect { fd, kind };
+        assert_eq!(
+            kinds("2>&1 3<&0 &>>log >|f x2<in"),
+            [
+                redirect(Some(2), RedirectKind::Duplicate),
+                text("1"),
+                TokenKind::Blank,
+                redirect(Some(3), RedirectKind::DuplicateInput),
+                text("0"),
+                TokenKind::Blank,
+                redirect(None, RedirectKind::AppendAll),
+                text("log"),
+                TokenKind::Blank,
+                redirect(None, RedirectKind::Clobber),
+                text("f"),
+                TokenKind::Blank,
+                // Only digits standing alone name a descriptor
+                text("x2"),
+                redirect(None, RedirectKind::Input),
+                text("in"),
+            ]
+        );
+
+        let spans: Vec<_> = tokens("a 10<<-EOF").into_iter().map(|t| t.span).collect();
+        assert_eq!(spans, [0..1, 1..2, 2..7, 7..10]);
+    }
+
+    #[test]
+    fn tabs_are_blanks() {
+        assert_eq!(
+            kinds("a\tb \t c\t#d"),
+            [
+                text("a"),
+                TokenKind::Blank,
+                text("b"),
+                TokenKind::Blank,
+                text("c"),
+                TokenKind::Blank,
+            ]
+        );
+        let spans: Vec<_> = tokens("a \t\tb").into_iter().map(|t| t.span).collect();
+        assert_eq!(spans, [0..1, 1..4, 4..5]);
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        assert_eq!(
+            kinds("a#b # c\nd"),
+            [text("a#b"), TokenKind::Blank, TokenKind::Newline, text("d")]
+        );
+    }
+
+    #[test]
+    fn here_document_bodies_follow_the_newline() {
+        let mut lexer = Lexer::new("cat <<EOF; echo\nbody\nEOF\nrest");
+        let mut delimiter = None;
+        while let Some(token) = lexer.next_token().unwrap() {
+            match token.kind {
+                TokenKind::Text(word) if word.to_string() == "EOF" => delimiter = Some(word),
+                TokenKind::Newline => break,
+                _ => {}
+            }
+        }
+
+        let body = lexer
+            .here_doc(&delimiter.unwrap(), RedirectKind::HereDoc, 4)
+            .unwrap();
+        assert_eq!(body.to_string(), "body\n");
+        assert_eq!(lexer.next_token().unwrap().unwrap().kind, text("rest"));
+    }
+
+    #[test]
+    fn expansions_nest_only_so_deep() {
+        let nested = |depth| "${x:-".repeat(depth) + &"}".repeat(depth);
+        assert!(Lexer::new(&nested(MAX_DEPTH)).next_token().is_ok());
+        let error = match Lexer::new(&nested(MAX_DEPTH + 1)).next_token() {
+            Err(RushError::Syntax(error)) => error,
+            other => panic!("Expected a syntax error, got {other:?}"),
+        };
+        assert_eq!(error.kind, SyntaxErrorKind::NestedTooDeeply);
+        assert_eq!(error.span, 5 * MAX_DEPTH..5 * MAX_DEPTH + 2);
+    }
+
+    #[test]
+    fn quoted_text_is_borrowed_unless_escaped() {
+        let mut lexer = Lexer::at("'a \\b' \"日本 $x\" \"a\\\"b\\c\"", 1);
+        assert!(matches!(lexer.quoted_text('\''), Cow::Borrowed("a \\b")));
+
+        lexer.pos += 3;
+        assert!(matches!(lexer.quoted_text('"'), Cow::Borrowed("日本 ")));
+        assert_eq!(lexer.peek(), Some('$'));
+
+        lexer.pos += 5;
+        let text = lexer.quoted_text('"');
+        assert!(matches!(text, Cow::Owned(_)));
+        assert_eq!(text, "a\"b\\c");
+    }
+
+    #[test]
+    fn words_borrow_from_the_input_unless_joined_or_escaped() {
+        let borrowed = |input| match &kinds(input)[0] {
+            TokenKind::Text(word) | TokenKind::Quoted(word) => word.parts().iter().all(|part| {
+                !matches!(
+                    part,
+                    WordPart::Literal(Cow::Owned(_)) | WordPart::Quoted(Cow::Owned(_))
+                )
+            }),
+            other => panic!("Expected a word, got {other:?}"),
+        };
+
+        for input in ["echo", "'a \\b'", "\"c $x d\"", "e$y", "x${#y}z", "''"] {
+            assert!(borrowed(input), "{input}");
+        }
+        // A `$` that starts no expansion is joined onto the text before it
+        assert!(!borrowed("a$"));
+        assert!(!borrowed("\"a\\\"b\""));
+    }
+
+    #[test]
+    fn unterminated_quote_spans_from_the_quote() {
+        let mut lexer = Lexer::new("echo 日本 \"a $b");
+        let error = loop {
+            match lexer.next_token() {
+                Ok(Some(_)) => {}
+                Err(RushError::Syntax(error)) => break error,
+                other => panic!("Expected a syntax error, got {other:?}"),
+            }
+        };
+        assert_eq!(error.kind, SyntaxErrorKind::UnterminatedQuote);
+        assert_eq!(error.span, 12..17);
+    }
+}
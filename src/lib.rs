@@ -0,0 +1,38 @@
+mod aliases;
+mod arith;
+mod braces;
+mod command;
+mod config;
+mod diagnostics;
+mod editor;
+mod eval;
+mod expand;
+mod functions;
+#[cfg(feature = "fuzzing")]
+mod fuzzing;
+mod glob;
+mod jobs;
+mod lexer;
+mod parser;
+mod prompt;
+mod shell;
+mod signals;
+mod startup;
+mod state;
+mod stats;
+mod status;
+mod style;
+mod times;
+mod trace;
+mod transform;
+mod util;
+mod variables;
+mod xtrace;
+
+pub use crate::{
+    shell::{Config, Shell, forward_signals},
+    status::ExitStatus,
+};
+
+#[cfg(feature = "fuzzing")]
+pub use crate::fuzzing::{parse_line, tokenize_line};
@@ -1,33 +1,167 @@
-use crate::{
-    command::{Command, CommandType},
-    util::RushError,
-};
-use std::io::{self, Write};
+use rush::{Config, ExitStatus, Shell};
+use std::{env, process};
 
-mod command;
-mod util;
+/// How to use rush, for `--help` and after a bad option.
+const USAGE: &str = "\
+usage: rush [option ...] [script [argument ...]]
 
-fn rush() -> Result<(), RushError> {
-    print!("$ ");
-    io::stdout().flush().map_err(|_| RushError::UnexpectedEOF)?;
+options:
+  -i, --interactive  prompt for commands even if stdin isn't a terminal
+  -l, --login        act as a login shell, sourcing the profile files
+  -r, --restricted   run restricted, for accounts limited to what's on PATH
+      --norc         don't source ~/.rushrc
+      --help         show this help and exit
+      --version      show the version and exit
+";
 
-    let stdin = io::stdin().lock();
-    let cmd = Command::new(stdin)?;
+/// What rush was asked to do on its command line.
+#[derive(Debug, Eq, PartialEq)]
+enum Action {
+    Run(Invocation),
+    Help,
+    Version,
+}
+
+/// How to run the shell, from the options given it.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Invocation {
+    interactive: bool,
+    login: bool,
+    restricted: bool,
+    norc: bool,
+    /// The script to run instead of reading stdin.
+    script: Option<String>,
+    /// The positional parameters the script starts with.
+    args: Vec<String>,
+}
 
-    if let CommandType::Exit = cmd.type_ {
-        std::process::exit(0);
+/// Reads rush's own options from `args`, which don't include the program
+/// name. Options end at the first argument that isn't one, or after `--`;
+/// that argument names the script, and the rest are its arguments.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Action, String> {
+    let mut invocation = Invocation::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" => return Ok(Action::Help),
+            "--version" => return Ok(Action::Version),
+            "--interactive" => invocation.interactive = true,
+            "--login" => invocation.login = true,
+            "--restricted" => invocation.restricted = true,
+            "--norc" => invocation.norc = true,
+            "--" => {
+                invocation.script = args.next();
+                break;
+            }
+            flags if flags.starts_with('-') && flags.len() > 1 => {
+                if flags.starts_with("--") {
+                    return Err(format!("{flags}: invalid option"));
+                }
+                for flag in flags[1..].chars() {
+                    match flag {
+                        'i' => invocation.interactive = true,
+                        'l' => invocation.login = true,
+                        'r' => invocation.restricted = true,
+                        flag => return Err(format!("-{flag}: invalid option")),
+                    }
+                }
+            }
+            _ => {
+                invocation.script = Some(arg);
+                break;
+            }
+        }
     }
 
-    cmd.run()
+    invocation.args = args.collect();
+    Ok(Action::Run(invocation))
 }
 
 fn main() {
-    loop {
-        if let Err(error) = rush() {
-            match error {
-                RushError::Nop => {}
-                error => eprintln!("{error}"),
-            }
+    let mut args = env::args();
+    let name = args.next().unwrap_or_default();
+    // Like other shells, rush is a login shell when the program that
+    // started it put `-` before its name, as `login` does, and restricted
+    // when it's called `rrush`, as bash is when it's called `rbash`
+    let dashed = name.starts_with('-');
+    let restricted = name.trim_start_matches('-').rsplit('/').next() == Some("rrush");
+    let invocation = match parse_args(args) {
+        Ok(Action::Run(invocation)) => invocation,
+        Ok(Action::Help) => {
+            print!("{USAGE}");
+            return;
+        }
+        Ok(Action::Version) => {
+            println!("rush {}", env!("CARGO_PKG_VERSION"));
+            return;
         }
+        Err(msg) => {
+            eprint!("rush: {msg}\n{USAGE}");
+            process::exit(ExitStatus::USAGE.code());
+        }
+    };
+
+    // Signals sent to end the shell end the program it's waiting for too
+    rush::forward_signals();
+
+    // A script runs with what follows its name as its arguments, and without
+    // the rc file, which is for setting up an interactive shell
+    let mut shell = Shell::new(Config {
+        interactive: invocation.interactive,
+        login: dashed || invocation.login,
+        restricted: restricted || invocation.restricted,
+        rc: !invocation.norc,
+        args: invocation.args,
+    });
+    let status = match invocation.script {
+        Some(path) => shell.run_script(&path),
+        None => shell.run_interactive(),
+    };
+    process::exit(status.code());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Action, String> {
+        parse_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn options_come_before_the_script() {
+        assert_eq!(parse(""), Ok(Action::Run(Invocation::default())));
+        assert_eq!(
+            parse("-ilr --norc run.sh -l a"),
+            Ok(Action::Run(Invocation {
+                interactive: true,
+                login: true,
+                restricted: true,
+                norc: true,
+                script: Some("run.sh".into()),
+                args: vec!["-l".into(), "a".into()],
+            }))
+        );
+        assert_eq!(
+            parse("--login -- -script"),
+            Ok(Action::Run(Invocation {
+                login: true,
+                script: Some("-script".into()),
+                ..Invocation::default()
+            }))
+        );
+        assert_eq!(parse("-i --help"), Ok(Action::Help));
+        assert_eq!(parse("--version --nonsense"), Ok(Action::Version));
+    }
+
+    #[test]
+    fn unknown_options_are_errors() {
+        assert_eq!(parse("-x"), Err("-x: invalid option".into()));
+        assert_eq!(parse("-lq"), Err("-q: invalid option".into()));
+        assert_eq!(
+            parse("--nonsense"),
+            Err("--nonsense: invalid option".into())
+        );
     }
 }
@@ -0,0 +1,1960 @@
+use std::{mem, ops::Range};
+
+use crate::{
+    aliases::Aliases,
+    lexer::{Lexer, Operator, Token, TokenKind},
+    times::TimeFormat,
+    util::{
+        Redirect, RedirectKind, RushError, SyntaxError, SyntaxErrorKind, Word, WordPart, Words,
+        is_name,
+    },
+    variables::split_subscript,
+};
+
+/// How many compound commands may be nested inside one another, so a line
+/// can't run the parser out of stack.
+const MAX_DEPTH: usize = 64;
+
+/// How a pipeline in an and-or list is joined to the one before it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Connector {
+    /// The first pipeline: it always runs.
+    Always,
+    /// After `&&`: it runs only if the previous pipeline succeeded.
+    And,
+    /// After `||`: it runs only if the previous pipeline failed.
+    Or,
+}
+
+/// A command name and its arguments, along with the redirections written
+/// among them.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct SimpleCommand {
+    /// The `NAME=value` words before the command name.
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word<'static>>,
+    pub redirects: Vec<Redirect>,
+}
+
+impl SimpleCommand {
+    /// Makes a command of `words`, taking the assignments at the start of
+    /// them apart from the rest. An array assignment like `a=(x y)` takes
+    /// every word up to its closing parenthesis.
+    pub(crate) fn new(words: Vec<Word<'static>>, redirects: Vec<Redirect>) -> Self {
+        let mut words = words.into_iter().peekable();
+        let mut assignments = Vec::new();
+        while let Some(mut assignment) = words.peek().and_then(Assignment::parse) {
+            words.next();
+            if let Some(mut word) = trim_paren(&assignment.value, '(') {
+                let mut elements = Vec::new();
+                loop {
+                    if let Some(last) = trim_paren(&word, ')') {
+                        elements.push(last);
+                        break;
+                    }
+                    elements.push(word);
+                    match words.next() {
+                        Some(next) => word = next,
+                        None => break,
+                    }
+                }
+                elements.retain(|element| !element.parts().is_empty());
+                assignment.value = Word::default();
+                assignment.array = Some(elements);
+            }
+            assignments.push(assignment);
+        }
+        Self {
+            assignments,
+            words: words.collect(),
+            redirects,
+        }
+    }
+
+    /// The one simple command `line` parses into, or one with no words if
+    /// it has no command, for tests of what becomes of it.
+    #[cfg(test)]
+    pub(crate) fn parse(line: &str) -> Result<Self, RushError> {
+        let mut commands = parse(line)?
+            .into_iter()
+            .flat_map(|list| list.pipelines)
+            .flat_map(|(_, pipeline)| pipeline.commands);
+        match (commands.next(), commands.next()) {
+            (None, _) => Ok(Self::default()),
+            (Some(CommandNode::Simple(command)), None) => Ok(command),
+            _ => panic!("Expected one simple command in {line:?}"),
+        }
+    }
+}
+
+/// Removes an unquoted `paren` from the start of `word` if it is `(`, or from
+/// the end if it is `)`. `None` if it isn't there.
+fn trim_paren<'a>(word: &Word<'a>, paren: char) -> Option<Word<'a>> {
+    let mut parts = word.parts().to_vec();
+    let part = match paren {
+        '(' => parts.first_mut(),
+        _ => parts.last_mut(),
+    };
+    let Some(WordPart::Literal(text)) = part else {
+        return None;
+    };
+    *text = match paren {
+        '(' => text.strip_prefix(paren)?,
+        _ => text.strip_suffix(paren)?,
+    }
+    .to_owned()
+    .into();
+    parts.retain(|part| !matches!(part, WordPart::Literal(text) if text.is_empty()));
+    Some(Word::from_parts(parts))
+}
+
+/// A `NAME=value` word, which sets a variable rather than naming a command.
+/// `NAME` may have a subscript, setting one element of an array.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Assignment {
+    pub name: String,
+    pub value: Word<'static>,
+    /// The elements of `NAME=(...)`, which makes `NAME` an array.
+    pub array: Option<Vec<Word<'static>>>,
+}
+
+impl Assignment {
+    /// Reads `word` as an assignment, if it starts with a valid name and an
+    /// unquoted `=`. Only a subscript may have expansions before the `=`.
+    fn parse(word: &Word<'static>) -> Option<Self> {
+        let [WordPart::Literal(_), ..] = word.parts() else {
+            return None;
+        };
+        let mut name = String::new();
+        for (i, part) in word.parts().iter().enumerate() {
+            let text = match part {
+                WordPart::Literal(text) => text,
+                _ if name.contains('[') => {
+                    name.push_str(&Word::from_parts(vec![part.clone()]).to_string());
+                    continue;
+                }
+                _ => return None,
+            };
+            let Some((before, value)) = text.split_once('=') else {
+                name.push_str(text);
+                continue;
+            };
+            name.push_str(before);
+            let valid = match split_subscript(&name) {
+                Some((array, _)) => is_name(array),
+                None => is_name(&name),
+            };
+            if !valid {
+                return None;
+            }
+
+            let mut parts = vec![WordPart::Literal(value.to_owned().into())];
+            parts.extend_from_slice(&word.parts()[i + 1..]);
+            parts.retain(|part| !matches!(part, WordPart::Literal(text) if text.is_empty()));
+            return Some(Self {
+                name,
+                value: Word::from_parts(parts),
+                array: None,
+            });
+        }
+        None
+    }
+}
+
+/// A `{ ...; }` group: a list run in the current shell, with the
+/// redirections after it applying to every command in it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Group {
+    pub body: Vec<AndOrList>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// A `name() { ...; }` definition, after which `name` runs the group as a
+/// command of its own.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Function {
+    pub name: String,
+    pub body: Group,
+    /// The definition as written, for `declare -f` to show.
+    pub text: String,
+}
+
+/// An `if cond; then ...; elif ...; else ...; fi` command: the body of the
+/// first clause whose condition succeeds runs, or the `else` body if none
+/// does.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct If {
+    /// The `if` clause and each `elif` after it, as a condition and the body
+    /// it guards.
+    pub clauses: Vec<(Vec<AndOrList>, Vec<AndOrList>)>,
+    pub else_body: Option<Vec<AndOrList>>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// A `while cond; do ...; done` loop, which runs its body for as long as its
+/// condition succeeds, or an `until` loop, which runs it until the condition
+/// does.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct While {
+    pub condition: Vec<AndOrList>,
+    pub body: Vec<AndOrList>,
+    /// Whether it's an `until` loop.
+    pub until: bool,
+    pub redirects: Vec<Redirect>,
+}
+
+/// A `case word in pattern) ...;; esac` command, which runs the body of the
+/// first clause with a pattern matching the word.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Case {
+    pub word: Word<'static>,
+    /// The patterns of each clause, and the list it runs, which may be
+    /// empty.
+    pub clauses: Vec<(Vec<Word<'static>>, Vec<AndOrList>)>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// A `select name in words; do ...; done` loop, which shows the words as a
+/// numbered menu and runs its body with `name` set to each one picked.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Select {
+    pub name: String,
+    /// The words to pick from, or `None` for the positional parameters.
+    pub words: Option<Vec<Word<'static>>>,
+    pub body: Vec<AndOrList>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// One command of a pipeline.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum CommandNode {
+    Simple(SimpleCommand),
+    Group(Group),
+    Function(Function),
+    If(If),
+    While(While),
+    Case(Case),
+    Select(Select),
+}
+
+/// The reserved words that end part of a compound command, which can't
+/// start a command of their own.
+const CLOSERS: [&str; 8] = ["}", "then", "elif", "else", "fi", "do", "done", "esac"];
+
+/// Commands joined by `|`, each reading what the one before it writes.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Pipeline {
+    pub commands: Vec<CommandNode>,
+    /// Whether it started with `!`, which inverts the status it finishes
+    /// with.
+    pub negated: bool,
+    /// How to report the time it took, if it started with `time`.
+    pub time: Option<TimeFormat>,
+    /// The pipeline as written, to name it by when it runs as a job.
+    pub text: String,
+    /// The line of input it starts on, for `$LINENO`.
+    pub line: usize,
+}
+
+/// Pipelines joined by `&&` and `||`, ended by `;`, `&`, or a newline.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct AndOrList {
+    pub pipelines: Vec<(Connector, Pipeline)>,
+    /// Whether it ended with `&`, so the shell doesn't wait for it.
+    pub background: bool,
+}
+
+/// Parses a command line into the and-or lists it holds, so
+/// `make && ./run | tee log; ls` becomes two lists, the first of which runs
+/// a pipeline only if `make` succeeds.
+///
+/// The body of a here-document follows the line its `<<` is on, even when
+/// other commands come after it on that line, and becomes the target of its
+/// redirection. A trailing `;` or `&` is allowed, but an operator with no
+/// command before it, or a `&&`, `||`, or `|` with none after it, is an
+/// error.
+pub(crate) fn parse(input: &str) -> Result<Vec<AndOrList>, RushError> {
+    parse_with_aliases(input, &Aliases::default(), 1)
+}
+
+/// Like [`parse`], but a command name that is one of `aliases` is replaced
+/// with its value before the command is parsed. The value is parsed as if
+/// it had been typed in place of the name, so it may hold several commands,
+/// and a value ending in a blank has the word after it expanded too.
+/// `input` starts on line `line` of whatever it was read from.
+pub(crate) fn parse_with_aliases(
+    input: &str,
+    aliases: &Aliases,
+    line: usize,
+) -> Result<Vec<AndOrList>, RushError> {
+    let mut parser = Parser {
+        input: input.trim_end().to_owned(),
+        pos: 0,
+        aliases,
+        expansions: Vec::new(),
+        alias_next: None,
+        peeked: None,
+        end: 0,
+        patterns: false,
+        pending: Vec::new(),
+        bodies: Vec::new(),
+        line,
+        depth: 0,
+    };
+
+    let mut lists = parser.list(&[])?;
+
+    // A body that never started is as unfinished as one that never ended
+    if let Some(here_doc) = parser.pending.first() {
+        return Err(here_doc.unterminated(&parser.input));
+    }
+
+    let mut bodies = parser.bodies.into_iter();
+    fill_here_docs(&mut lists, &mut bodies);
+    Ok(lists)
+}
+
+/// A here-document whose body hasn't been read yet.
+#[derive(Debug)]
+struct PendingHereDoc {
+    /// The span of its operator and delimiter.
+    span: Range<usize>,
+    delimiter: Word<'static>,
+    kind: RedirectKind,
+}
+
+impl PendingHereDoc {
+    /// The error for a body that never ends, shown on `line`.
+    fn unterminated(&self, line: &str) -> RushError {
+        RushError::Syntax(SyntaxError {
+            kind: SyntaxErrorKind::UnterminatedHereDoc(self.delimiter.to_string()),
+            line: line.to_owned(),
+            span: self.span.clone(),
+        })
+    }
+}
+
+struct Parser<'a> {
+    /// The input, with the value of every alias expanded so far in place of
+    /// its name.
+    input: String,
+    /// Where the next token starts.
+    pos: usize,
+    aliases: &'a Aliases,
+    /// The text each alias was expanded into, in which that alias isn't
+    /// expanded again, so `alias ls='ls -F'` doesn't expand forever.
+    expansions: Vec<(Range<usize>, String)>,
+    /// Where the value of an alias that ended in a blank ends, so the word
+    /// after it is expanded too.
+    alias_next: Option<usize>,
+    peeked: Option<Token<'static>>,
+    /// Where the last token read that wasn't blank ended.
+    end: usize,
+    /// Whether a `case` pattern is being read, where `(` and `)` are
+    /// operators.
+    patterns: bool,
+    /// The here-documents whose bodies start after the current line.
+    pending: Vec<PendingHereDoc>,
+    /// The bodies read so far, in the order of their operators.
+    bodies: Vec<Word<'static>>,
+    /// The line the input starts on.
+    line: usize,
+    /// How many lists are being read, one inside another.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn lexer(&self) -> Lexer<'_> {
+        Lexer::at(&self.input, self.pos)
+    }
+
+    fn read_token(&mut self) -> Result<Option<Token<'static>>, RushError> {
+        let mut lexer = self.lexer();
+        let token = match self.patterns {
+            true => lexer.next_pattern_token()?,
+            false => lexer.next_token()?,
+        };
+        // The input changes as aliases are expanded, so tokens can't keep
+        // borrowing it
+        let token = token.map(Token::into_owned);
+        self.pos = lexer.pos();
+        Ok(token)
+    }
+
+    fn peek(&mut self) -> Result<Option<&Token<'static>>, RushError> {
+        if self.peeked.is_none() {
+            self.peeked = self.read_token()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    fn peek_kind(&mut self) -> Result<Option<&TokenKind<'static>>, RushError> {
+        Ok(self.peek()?.map(|token| &token.kind))
+    }
+
+    /// Moves past the next token. Moving past a newline reads the bodies of
+    /// the here-documents on the line it ends.
+    fn next(&mut self) -> Result<Option<Token<'static>>, RushError> {
+        let token = match self.peeked.take() {
+            Some(token) => Some(token),
+            None => self.read_token()?,
+        };
+
+        match &token {
+            Some(Token {
+                kind: TokenKind::Newline,
+                span,
+            }) => self.read_bodies(span.start)?,
+            Some(Token {
+                kind: TokenKind::Blank,
+                ..
+            })
+            | None => {}
+            Some(token) => self.end = token.span.end,
+        }
+        Ok(token)
+    }
+
+    /// Skips blanks, and newlines too if `newlines` is set.
+    fn skip(&mut self, newlines: bool) -> Result<(), RushError> {
+        loop {
+            match self.peek_kind()? {
+                Some(TokenKind::Blank) => {}
+                Some(TokenKind::Newline) if newlines => {}
+                _ => return Ok(()),
+            }
+            self.next()?;
+        }
+    }
+
+    /// Reads the body of every pending here-document, the first starting
+    /// right after the newline at `newline`.
+    fn read_bodies(&mut self, newline: usize) -> Result<(), RushError> {
+        for here_doc in mem::take(&mut self.pending) {
+            let mut lexer = self.lexer();
+            let body = (lexer.here_doc(&here_doc.delimiter, here_doc.kind, here_doc.span.start))
+                .map(Word::into_owned);
+            self.pos = lexer.pos();
+            match body {
+                Ok(body) => self.bodies.push(body),
+                // Shown on the line the operator is on, since the body may
+                // be long
+                Err(RushError::Syntax(SyntaxError {
+                    kind: SyntaxErrorKind::UnterminatedHereDoc(_),
+                    ..
+                })) => return Err(here_doc.unterminated(&self.input[..newline])),
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses and-or lists up to the end of the input, or up to the first
+    /// of the reserved words in `ends` that starts a command, like the `}`
+    /// closing a group, or of the operators in it, like a `;;`.
+    fn list(&mut self, ends: &[&str]) -> Result<Vec<AndOrList>, RushError> {
+        // The body of every compound command is a list of its own
+        if self.depth == MAX_DEPTH {
+            let error = SyntaxErrorKind::NestedTooDeeply;
+            return Err(self.lexer().syntax_error(error, self.end..self.end));
+        }
+        self.depth += 1;
+        let mut lists = Vec::new();
+
+        loop {
+            self.skip(true)?;
+            let Some(token) = self.peek()?.cloned() else {
+                break;
+            };
+            let ended = match &token.kind {
+                TokenKind::Operator(operator) => ends.contains(&operator.as_str()),
+                _ => ends.iter().any(|end| self.is_reserved(&token, end)),
+            };
+            if ended {
+                break;
+            }
+            if let TokenKind::Operator(operator) = token.kind {
+                return Err(self.unexpected(operator.as_str(), token.span));
+            }
+
+            let pipelines = self.and_or()?;
+            self.skip(false)?;
+            let background = match self.peek_kind()? {
+                Some(TokenKind::Operator(Operator::Ampersand)) => true,
+                Some(TokenKind::Operator(Operator::Semicolon)) => false,
+                // Ended by a newline or the end of the input
+                _ => {
+                    lists.push(AndOrList {
+                        pipelines,
+                        background: false,
+                    });
+                    continue;
+                }
+            };
+            self.next()?;
+            lists.push(AndOrList {
+                pipelines,
+                background,
+            });
+        }
+
+        self.depth -= 1;
+        Ok(lists)
+    }
+
+    fn and_or(&mut self) -> Result<Vec<(Connector, Pipeline)>, RushError> {
+        let mut pipelines = vec![(Connector::Always, self.pipeline()?)];
+
+        loop {
+            self.skip(false)?;
+            let connector = match self.peek_kind()? {
+                Some(TokenKind::Operator(Operator::And)) => Connector::And,
+                Some(TokenKind::Operator(Operator::Or)) => Connector::Or,
+                _ => return Ok(pipelines),
+            };
+            let operator = self.next()?.expect("just peeked");
+            self.command_after(&operator)?;
+            pipelines.push((connector, self.pipeline()?));
+        }
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, RushError> {
+        let start = self.peek()?.expect("callers check").span.start;
+        let time = self.time()?;
+        let bang = self.peek()?.cloned().expect("time leaves a command");
+        let negated = is_literal(&bang, "!")
+            && (self.input[bang.span.end..].chars().next())
+                .is_none_or(|c| matches!(c, ' ' | '\t' | '\n'));
+        if negated {
+            self.next()?;
+            self.skip(false)?;
+            match self.peek()?.cloned() {
+                Some(Token {
+                    kind: TokenKind::Operator(operator),
+                    span,
+                }) => return Err(self.unexpected(operator.as_str(), span)),
+                None
+                | Some(Token {
+                    kind: TokenKind::Newline,
+                    ..
+                }) => return Err(self.unexpected("newline", bang.span)),
+                Some(_) => {}
+            }
+        }
+        let mut commands = vec![self.command()?];
+
+        loop {
+            self.skip(false)?;
+            if self.peek_kind()? != Some(&TokenKind::Operator(Operator::Pipe)) {
+                break;
+            }
+            let pipe = self.next()?.expect("just peeked");
+            self.command_after(&pipe)?;
+            commands.push(self.command()?);
+        }
+
+        Ok(Pipeline {
+            commands,
+            negated,
+            time,
+            text: self.input[start..self.end].to_owned(),
+            line: self.line + self.input[..start].matches('\n').count(),
+        })
+    }
+
+    /// Reads the `time` keyword and its `-p` flag, if the pipeline starts
+    /// with them. Without a pipeline after it, `time` is an ordinary command.
+    fn time(&mut self) -> Result<Option<TimeFormat>, RushError> {
+        let Some(token) = self.peek()?.cloned() else {
+            return Ok(None);
+        };
+        let rest = self.input[token.span.end..].trim_start_matches([' ', '\t']);
+        let (format, rest) = match rest.strip_prefix("-p") {
+            Some(after) if after.chars().next().is_none_or(|c| " \t\n;&|".contains(c)) => {
+                (TimeFormat::Posix, after.trim_start_matches([' ', '\t']))
+            }
+            _ => (TimeFormat::Default, rest),
+        };
+        let pipeline_follows =
+            !rest.starts_with(['\n', ';', '&', '|', '#', ')']) && !rest.is_empty();
+        if !is_literal(&token, "time") || token.span.end == self.input.len() || !pipeline_follows {
+            return Ok(None);
+        }
+
+        self.next()?;
+        self.skip(false)?;
+        if format == TimeFormat::Posix {
+            self.next()?;
+            self.skip(false)?;
+        }
+        Ok(Some(format))
+    }
+
+    /// Skips ahead to the command that has to follow `operator`, which may
+    /// be on the next line.
+    fn command_after(&mut self, operator: &Token) -> Result<(), RushError> {
+        self.skip(true)?;
+        let Some(next) = self.peek()?.cloned() else {
+            let TokenKind::Operator(operator_kind) = operator.kind else {
+                unreachable!("only operators need a command after them");
+            };
+            return Err(self.lexer().syntax_error(
+                SyntaxErrorKind::UnfinishedCommand(operator_kind.as_str().into()),
+                operator.span.clone(),
+            ));
+        };
+
+        // The operator where a command should be is the one at fault, as in
+        // `ls | |`, where it's the second pipe
+        match next.kind {
+            TokenKind::Operator(next_operator) => {
+                Err(self.unexpected(next_operator.as_str(), next.span))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn command(&mut self) -> Result<CommandNode, RushError> {
+        let token = self.peek()?.cloned().expect("callers check");
+        if self.is_open(&token) {
+            self.next()?;
+            return self.group(token).map(CommandNode::Group);
+        }
+        if self.is_reserved(&token, "if") {
+            self.next()?;
+            return self.if_command(token).map(CommandNode::If);
+        }
+        if self.is_reserved(&token, "while") || self.is_reserved(&token, "until") {
+            self.next()?;
+            return self.while_loop(token).map(CommandNode::While);
+        }
+        if self.is_reserved(&token, "case") {
+            self.next()?;
+            return self.case_command(token).map(CommandNode::Case);
+        }
+        if self.is_reserved(&token, "select") {
+            self.next()?;
+            return self.select_loop(token).map(CommandNode::Select);
+        }
+        if let Some(closer) = CLOSERS.iter().find(|word| self.is_reserved(&token, word)) {
+            return Err(self.unexpected(closer, token.span));
+        }
+        if let Some(name) = self.function_name(&token) {
+            return self.function(name, token).map(CommandNode::Function);
+        }
+
+        let (command, _) = self.simple_command()?;
+        Ok(CommandNode::Simple(command))
+    }
+
+    /// Parses the rest of the group opened by `open`. Only redirections may
+    /// follow the closing brace, and the group can't be empty.
+    fn group(&mut self, open: Token) -> Result<Group, RushError> {
+        let body = self.list(&["}"])?;
+        let Some(close) = self.next()? else {
+            return Err(self.lexer().syntax_error(
+                SyntaxErrorKind::UnterminatedGroup,
+                open.span.start..self.input.len(),
+            ));
+        };
+        if body.is_empty() {
+            return Err(self.unexpected("}", close.span));
+        }
+
+        Ok(Group {
+            body,
+            redirects: self.redirects_after()?,
+        })
+    }
+
+    /// Parses the rest of the `if` command opened by `open`, through its
+    /// `fi`. Like a group, only redirections may follow it.
+    fn if_command(&mut self, open: Token) -> Result<If, RushError> {
+        let mut clauses = Vec::new();
+        let mut else_body = None;
+
+        loop {
+            let (condition, _) = self.compound_list(&open, &["then"])?;
+            let (body, end) = self.compound_list(&open, &["elif", "else", "fi"])?;
+            clauses.push((condition, body));
+            match end.as_str() {
+                "elif" => {}
+                "else" => {
+                    else_body = Some(self.compound_list(&open, &["fi"])?.0);
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        Ok(If {
+            clauses,
+            else_body,
+            redirects: self.redirects_after()?,
+        })
+    }
+
+    /// Parses the rest of the `while` or `until` loop opened by `open`,
+    /// through its `done`.
+    fn while_loop(&mut self, open: Token) -> Result<While, RushError> {
+        let (condition, _) = self.compound_list(&open, &["do"])?;
+        let (body, _) = self.compound_list(&open, &["done"])?;
+        Ok(While {
+            condition,
+            body,
+            until: is_literal(&open, "until"),
+            redirects: self.redirects_after()?,
+        })
+    }
+
+    /// Parses the rest of the `case` command opened by `open`, through its
+    /// `esac`. The `;;` after the last clause may be left out.
+    fn case_command(&mut self, open: Token) -> Result<Case, RushError> {
+        self.skip(false)?;
+        let word = self.compound_word(&open, "in")?;
+        self.skip(true)?;
+        match self.next()? {
+            Some(token) if self.is_reserved(&token, "in") => {}
+            token => return Err(self.missing(token, &open, "in")),
+        }
+
+        let mut clauses = Vec::new();
+        loop {
+            self.skip(true)?;
+            match self.peek()?.cloned() {
+                Some(token) if self.is_reserved(&token, "esac") => {
+                    self.next()?;
+                    break;
+                }
+                None => return Err(self.missing(None, &open, "esac")),
+                Some(_) => {}
+            }
+            let patterns = self.case_patterns(&open)?;
+            let body = self.list(&[";;", "esac"])?;
+            clauses.push((patterns, body));
+            if self.peek_kind()? == Some(&TokenKind::Operator(Operator::DoubleSemicolon)) {
+                self.next()?;
+            }
+        }
+
+        Ok(Case {
+            word,
+            clauses,
+            redirects: self.redirects_after()?,
+        })
+    }
+
+    /// Parses the rest of the `select` loop opened by `open`, through its
+    /// `done`. Without `in` and its words, it picks from the positional
+    /// parameters.
+    fn select_loop(&mut self, open: Token) -> Result<Select, RushError> {
+        self.skip(false)?;
+        let end = self.input.len();
+        let start = self.peek()?.map_or(end, |token| token.span.start);
+        let name = match self.compound_word(&open, "do")?.parts() {
+            [WordPart::Literal(name)] if is_name(name) => name.to_string(),
+            _ => return Err(self.unexpected(&self.input[start..self.end], start..self.end)),
+        };
+
+        self.skip(true)?;
+        let mut words = None;
+        if let Some(token) = self.peek()?.cloned()
+            && self.is_reserved(&token, "in")
+        {
+            self.next()?;
+            let words = words.insert(Vec::new());
+            loop {
+                self.skip(false)?;
+                match self.peek_kind()? {
+                    Some(TokenKind::Text(_) | TokenKind::Quoted(_)) => {
+                        words.push(self.compound_word(&open, "do")?);
+                    }
+                    _ => break,
+                }
+            }
+        }
+        if self.peek_kind()? == Some(&TokenKind::Operator(Operator::Semicolon)) {
+            self.next()?;
+        }
+
+        self.skip(true)?;
+        match self.next()? {
+            Some(token) if self.is_reserved(&token, "do") => {}
+            token => return Err(self.missing(token, &open, "do")),
+        }
+        let (body, _) = self.compound_list(&open, &["done"])?;
+        Ok(Select {
+            name,
+            words,
+            body,
+            redirects: self.redirects_after()?,
+        })
+    }
+
+    /// Parses the patterns of a `case` clause, separated by `|` and ended by
+    /// `)`, with an optional `(` before them.
+    fn case_patterns(&mut self, open: &Token) -> Result<Vec<Word<'static>>, RushError> {
+        // The next token was read as if it started a command, so it's read
+        // again as part of a pattern
+        if let Some(token) = self.peeked.take() {
+            self.pos = token.span.start;
+        }
+        self.patterns = true;
+        let patterns = self.pattern_list(open);
+        self.patterns = false;
+        patterns
+    }
+
+    fn pattern_list(&mut self, open: &Token) -> Result<Vec<Word<'static>>, RushError> {
+        if self.peek_kind()? == Some(&TokenKind::Operator(Operator::OpenParen)) {
+            self.next()?;
+        }
+        let mut patterns = Vec::new();
+        loop {
+            self.skip(false)?;
+            patterns.push(self.compound_word(open, ")")?);
+            self.skip(false)?;
+            match self.next()? {
+                Some(Token {
+                    kind: TokenKind::Operator(Operator::Pipe),
+                    ..
+                }) => {}
+                Some(Token {
+                    kind: TokenKind::Operator(Operator::CloseParen),
+                    ..
+                }) => return Ok(patterns),
+                token => return Err(self.missing(token, open, ")")),
+            }
+        }
+    }
+
+    /// Reads the word made of the text and quoted strings written together
+    /// at the next token, like the word or a pattern of a `case`. `wanted` is
+    /// what the compound command opened by `open` waits for if the input
+    /// ends first.
+    fn compound_word(&mut self, open: &Token, wanted: &str) -> Result<Word<'static>, RushError> {
+        let mut word = None;
+        while let Some(TokenKind::Text(_) | TokenKind::Quoted(_)) = self.peek_kind()? {
+            let token = self.next()?.expect("just peeked");
+            if let TokenKind::Text(part) | TokenKind::Quoted(part) = token.kind {
+                word.get_or_insert_with(Word::default).append(part);
+            }
+        }
+        match word {
+            Some(word) => Ok(word),
+            None => {
+                let token = self.next()?;
+                Err(self.missing(token, open, wanted))
+            }
+        }
+    }
+
+    /// The error for `token` coming where `wanted` should have. Without a
+    /// token, the compound command opened by `open` is unfinished.
+    fn missing(&self, token: Option<Token>, open: &Token, wanted: &str) -> RushError {
+        let Some(token) = token else {
+            return self.lexer().syntax_error(
+                SyntaxErrorKind::UnterminatedCompound(wanted.into()),
+                open.span.start..self.input.len(),
+            );
+        };
+        let text = match token.kind {
+            TokenKind::Operator(operator) => operator.as_str(),
+            TokenKind::Newline => "newline",
+            _ => &self.input[token.span.clone()],
+        };
+        self.unexpected(text, token.span)
+    }
+
+    /// Parses the non-empty list of one part of the compound command opened
+    /// by `open`, up to and past the reserved word in `ends` that ends it,
+    /// which is returned with it. The last of `ends` is the one wanted when
+    /// the input ends first.
+    fn compound_list(
+        &mut self,
+        open: &Token,
+        ends: &[&str],
+    ) -> Result<(Vec<AndOrList>, String), RushError> {
+        let body = self.list(ends)?;
+        let Some(end) = self.next()? else {
+            let wanted = ends.last().expect("a part has an end");
+            return Err(self.lexer().syntax_error(
+                SyntaxErrorKind::UnterminatedCompound((*wanted).into()),
+                open.span.start..self.input.len(),
+            ));
+        };
+        let word = self.input[end.span.clone()].to_owned();
+        if body.is_empty() {
+            return Err(self.unexpected(&word, end.span));
+        }
+        Ok((body, word))
+    }
+
+    /// The redirections after the end of a compound command, which is all
+    /// that may follow it.
+    fn redirects_after(&mut self) -> Result<Vec<Redirect>, RushError> {
+        let (command, spans) = self.simple_command()?;
+        if let Some(span) = spans.first() {
+            return Err(self.unexpected(&self.input[span.clone()], span.clone()));
+        }
+        Ok(command.redirects)
+    }
+
+    /// The name `token` defines a function by, if `()` follows it, as in
+    /// `name()` or `name ()`. The `{` of the body may follow straight on, as
+    /// in `name(){`.
+    fn function_name(&self, token: &Token) -> Option<String> {
+        let TokenKind::Text(word) = &token.kind else {
+            return None;
+        };
+        let [WordPart::Literal(text)] = word.parts() else {
+            return None;
+        };
+        let after = self.input[token.span.end..].trim_start_matches([' ', '\t']);
+        let name = match text.strip_suffix("()").or(text.strip_suffix("(){")) {
+            Some(name) => name,
+            None if after.starts_with("()") => text,
+            None => return None,
+        };
+        is_name(name).then(|| name.to_owned())
+    }
+
+    /// Parses the definition of the function `name`, starting at `token`.
+    /// Its body is a group, which may start on a later line.
+    fn function(&mut self, name: String, token: Token) -> Result<Function, RushError> {
+        self.next()?;
+        let joined = |token: &Token, before: &str| {
+            is_literal(token, &format!("{before}()")) || is_literal(token, &format!("{before}(){{"))
+        };
+        let parens = match joined(&token, &name) {
+            true => token.span.clone(),
+            false => {
+                self.skip(false)?;
+                let parens = self.next()?.expect("function_name checked");
+                if !joined(&parens, "") {
+                    return Err(self.unexpected(&self.input[parens.span.clone()], parens.span));
+                }
+                parens.span
+            }
+        };
+        // A `{` read along with the `()` is read again as the group's own
+        if self.input[parens.clone()].ends_with('{') {
+            self.pos = parens.end - 1;
+        }
+
+        self.skip(true)?;
+        let Some(open) = self.peek()?.cloned() else {
+            return Err(self.lexer().syntax_error(
+                SyntaxErrorKind::UnterminatedGroup,
+                token.span.start..self.input.len(),
+            ));
+        };
+        if !self.is_open(&open) {
+            let text = match open.kind {
+                TokenKind::Operator(operator) => operator.as_str(),
+                _ => &self.input[open.span.clone()],
+            };
+            return Err(self.unexpected(text, open.span));
+        }
+        self.next()?;
+
+        let body = self.group(open)?;
+        Ok(Function {
+            name,
+            body,
+            text: self.input[token.span.start..self.end].to_owned(),
+        })
+    }
+
+    /// Parses the words and redirections of a simple command, up to the
+    /// operator or newline that ends it. Where each word other than a
+    /// redirection target starts is returned too.
+    fn simple_command(&mut self) -> Result<(SimpleCommand, Vec<Range<usize>>), RushError> {
+        let mut words = Words::default();
+        let mut spans = Vec::new();
+        // Whether the token being read continues a word, and whether that
+        // word names the target of a redirection rather than an argument
+        let mut in_word = false;
+        let mut target = false;
+        // Whether the next word is in the place of the command name, which
+        // is after any assignments
+        let mut command_word = true;
+
+        loop {
+            let ends = matches!(
+                self.peek()?,
+                None | Some(Token {
+                    kind: TokenKind::Newline | TokenKind::Operator(_),
+                    ..
+                })
+            );
+            if ends {
+                break;
+            }
+            let token = self.peeked.take().expect("just peeked");
+            if !in_word && !target && token.kind != TokenKind::Blank {
+                let after_alias = self.alias_next.is_some_and(|end| token.span.start >= end);
+                if after_alias {
+                    self.alias_next = None;
+                }
+                // The first word of a value is checked wherever it ends up
+                let starts_value = (self.expansions.last())
+                    .is_some_and(|(text, _)| text.start == token.span.start);
+                if (command_word || after_alias || starts_value) && self.expand_alias(&token) {
+                    continue;
+                }
+                command_word &= match &token.kind {
+                    TokenKind::Text(word) => Assignment::parse(word).is_some(),
+                    TokenKind::Redirect { .. } => true,
+                    _ => false,
+                };
+            }
+            self.peeked = Some(token);
+            let token = self.next()?.expect("just peeked");
+
+            match token.kind {
+                TokenKind::Blank => in_word = false,
+                TokenKind::Redirect { kind, .. } if kind.is_here_doc() => {
+                    let at = token.span.end - kind.as_str().len();
+                    words.push(token);
+                    self.here_doc_delimiter(&mut words, at, kind)?;
+                    in_word = true;
+                    continue;
+                }
+                TokenKind::Redirect { .. } => {
+                    in_word = false;
+                    target = true;
+                }
+                _ if in_word => {}
+                _ => {
+                    if !target {
+                        spans.push(token.span.clone());
+                    }
+                    in_word = true;
+                    target = false;
+                }
+            }
+            words.push(token);
+        }
+
+        let (words, redirects) = words.finish(&self.lexer())?;
+        Ok((SimpleCommand::new(words, redirects), spans))
+    }
+
+    /// Replaces `token` with the value of the alias it names, if it is an
+    /// unquoted word of its own that names one, and returns whether it did.
+    /// The parser then reads the value as if it had been written instead.
+    fn expand_alias(&mut self, token: &Token) -> bool {
+        let TokenKind::Text(word) = &token.kind else {
+            return false;
+        };
+        let [WordPart::Literal(name)] = word.parts() else {
+            return false;
+        };
+        // Quoted text right after it makes it part of a longer word
+        if self.input[token.span.end..].starts_with(['\'', '"']) {
+            return false;
+        }
+        let span = token.span.clone();
+        let expanding = self
+            .expansions
+            .iter()
+            .any(|(text, alias)| alias == name && text.contains(&span.start));
+        let Some(value) = self.aliases.get(name).filter(|_| !expanding) else {
+            return false;
+        };
+
+        let end = span.start + value.len();
+        self.input.replace_range(span.clone(), value);
+        // Values this one is part of grow or shrink along with it
+        for (text, _) in &mut self.expansions {
+            if text.start <= span.start && span.end <= text.end {
+                text.end = text.end + value.len() - span.len();
+            }
+        }
+        self.expansions.push((span.start..end, name.to_string()));
+        self.alias_next = value.ends_with([' ', '\t']).then_some(end);
+        self.pos = span.start;
+        self.peeked = None;
+        true
+    }
+
+    /// Reads the delimiter after the here-document operator at `at`, so its
+    /// body can be read once the line ends.
+    fn here_doc_delimiter(
+        &mut self,
+        words: &mut Words,
+        at: usize,
+        kind: RedirectKind,
+    ) -> Result<(), RushError> {
+        let mut delimiter = Word::default();
+        let mut end = None;
+
+        loop {
+            let token = match self.peek_kind()? {
+                Some(TokenKind::Blank) if end.is_none() => self.next()?,
+                Some(TokenKind::Text(_) | TokenKind::Quoted(_)) => self.next()?,
+                _ => break,
+            };
+            let token = token.expect("just peeked");
+            if let TokenKind::Text(word) | TokenKind::Quoted(word) = &token.kind {
+                delimiter.append(word.clone());
+                end = Some(token.span.end);
+            }
+            words.push(token);
+        }
+
+        // Without a delimiter, the redirection has no target, which is
+        // reported once the command ends
+        if let Some(end) = end {
+            self.pending.push(PendingHereDoc {
+                span: at..end,
+                delimiter,
+                kind,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether `token` is a `{` opening a group, which is a word of its own.
+    /// One that ends the input opens a group the lines after it finish.
+    fn is_open(&self, token: &Token) -> bool {
+        let rest = &self.input[token.span.end..];
+        is_literal(token, "{") && (rest.is_empty() || rest.starts_with([' ', '\t', '\n']))
+    }
+
+    /// Whether `token` is the reserved `word`, like a `}` that could close a
+    /// group or the `fi` ending an `if`. Quotes right after it make it part
+    /// of a longer word.
+    fn is_reserved(&self, token: &Token, word: &str) -> bool {
+        is_literal(token, word) && !self.input[token.span.end..].starts_with(['\'', '"'])
+    }
+
+    fn unexpected(&self, token: &str, span: Range<usize>) -> RushError {
+        self.lexer()
+            .syntax_error(SyntaxErrorKind::UnexpectedToken(token.into()), span)
+    }
+}
+
+/// Whether `token` is unquoted text that reads exactly `text`.
+fn is_literal(token: &Token, text: &str) -> bool {
+    match &token.kind {
+        TokenKind::Text(word) => {
+            matches!(word.parts(), [WordPart::Literal(literal)] if literal == text)
+        }
+        _ => false,
+    }
+}
+
+/// Makes each here-document's body the target of its redirection, taking
+/// the bodies in the order their operators appear.
+fn fill_here_docs(lists: &mut [AndOrList], bodies: &mut impl Iterator<Item = Word<'static>>) {
+    let pipelines = lists.iter_mut().flat_map(|list| &mut list.pipelines);
+    for (_, pipeline) in pipelines {
+        for command in &mut pipeline.commands {
+            let redirects = match command {
+                CommandNode::Simple(command) => &mut command.redirects,
+                // The group's body comes before the redirections after it
+                CommandNode::Group(group) | CommandNode::Function(Function { body: group, .. }) => {
+                    fill_here_docs(&mut group.body, bodies);
+                    &mut group.redirects
+                }
+                CommandNode::If(if_) => {
+                    for (condition, body) in &mut if_.clauses {
+                        fill_here_docs(condition, bodies);
+                        fill_here_docs(body, bodies);
+                    }
+                    if let Some(body) = &mut if_.else_body {
+                        fill_here_docs(body, bodies);
+                    }
+                    &mut if_.redirects
+                }
+                CommandNode::Case(case) => {
+                    for (_, body) in &mut case.clauses {
+                        fill_here_docs(body, bodies);
+                    }
+                    &mut case.redirects
+                }
+                CommandNode::Select(select) => {
+                    fill_here_docs(&mut select.body, bodies);
+                    &mut select.redirects
+                }
+                CommandNode::While(while_) => {
+                    fill_here_docs(&mut while_.condition, bodies);
+                    fill_here_docs(&mut while_.body, bodies);
+                    &mut while_.redirects
+                }
+            };
+            for redirect in redirects.iter_mut().filter(|r| r.kind.is_here_doc()) {
+                redirect.target = bodies.next().expect("every here-document has a body");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The pipelines of every list, with their connectors, as written.
+    fn pipelines(line: &str) -> Vec<(Connector, String, bool)> {
+        parse(line)
+            .unwrap()
+            .into_iter()
+            .flat_map(|list| {
+                let background = list.background;
+                let last = list.pipelines.len() - 1;
+                list.pipelines
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(i, (connector, pipeline))| {
+                        (connector, pipeline.text, background && i == last)
+                    })
+            })
+            .collect()
+    }
+
+    fn texts(line: &str) -> Vec<String> {
+        pipelines(line)
+            .into_iter()
+            .map(|(_, text, _)| text)
+            .collect()
+    }
+
+    fn words(command: &CommandNode) -> Vec<String> {
+        let CommandNode::Simple(command) = command else {
+            panic!("Expected a simple command");
+        };
+        command.words.iter().map(Word::to_string).collect()
+    }
+
+    fn syntax_error(line: &str) -> SyntaxError {
+        match parse(line) {
+            Err(RushError::Syntax(error)) => error,
+            result => panic!("Expected a syntax error for {line:?}, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn splits_on_semicolons() {
+        assert_eq!(
+            texts("echo a; echo b;echo c"),
+            ["echo a", "echo b", "echo c"]
+        );
+        assert_eq!(texts("echo a;\n"), ["echo a"]);
+        assert_eq!(texts("echo a\necho b"), ["echo a", "echo b"]);
+        assert!(texts("   \n").is_empty());
+    }
+
+    #[test]
+    fn and_or_connectors() {
+        assert_eq!(
+            pipelines("make && ./run || echo failed; ls"),
+            [
+                (Connector::Always, "make".into(), false),
+                (Connector::And, "./run".into(), false),
+                (Connector::Or, "echo failed".into(), false),
+                (Connector::Always, "ls".into(), false),
+            ]
+        );
+        // The command after a connector may be on the next line
+        assert_eq!(texts("a &&\n\n b"), ["a", "b"]);
+    }
+
+    #[test]
+    fn pipelines_know_the_line_they_start_on() {
+        let lists = parse_with_aliases("a; b\n\nc &&\n d", &Aliases::default(), 5).unwrap();
+        let lines: Vec<_> = (lists.iter())
+            .flat_map(|list| &list.pipelines)
+            .map(|(_, pipeline)| pipeline.line)
+            .collect();
+        assert_eq!(lines, [5, 5, 7, 8]);
+    }
+
+    #[test]
+    fn pipes_join_commands() {
+        let lists = parse("a | b 1 |c||d").unwrap();
+        let pipelines = &lists[0].pipelines;
+        assert_eq!(pipelines.len(), 2);
+        let commands: Vec<_> = pipelines[0].1.commands.iter().map(words).collect();
+        assert_eq!(commands, [vec!["a"], vec!["b", "1"], vec!["c"]]);
+        assert_eq!(pipelines[0].1.text, "a | b 1 |c");
+    }
+
+    #[test]
+    fn operators_need_no_spaces() {
+        assert_eq!(texts("a;b&&c||d&e"), ["a", "b", "c", "d", "e"]);
+
+        let lists = parse("ls|wc -l").unwrap();
+        let commands: Vec<_> = lists[0].pipelines[0].1.commands.iter().map(words).collect();
+        assert_eq!(commands, [vec!["ls"], vec!["wc", "-l"]]);
+
+        let lists = parse("echo hi>out.txt 2>>err<in").unwrap();
+        let CommandNode::Simple(echo) = &lists[0].pipelines[0].1.commands[0] else {
+            panic!("Expected a simple command");
+        };
+        assert_eq!(words(&lists[0].pipelines[0].1.commands[0]), ["echo", "hi"]);
+        let redirects: Vec<_> = echo
+            .redirects
+            .iter()
+            .map(|r| (r.fd, r.kind, r.target.to_string()))
+            .collect();
+        assert_eq!(
+            redirects,
+            [
+                (1, RedirectKind::Output, "out.txt".into()),
+                (2, RedirectKind::Append, "err".into()),
+                (0, RedirectKind::Input, "in".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn leading_assignments_are_split_off() {
+        let lists = parse("A=1 B=x$C'y z' >out cmd D=2").unwrap();
+        let CommandNode::Simple(command) = &lists[0].pipelines[0].1.commands[0] else {
+            panic!("Expected a simple command");
+        };
+        let assignments: Vec<_> = command
+            .assignments
+            .iter()
+            .map(|a| (a.name.as_str(), a.value.to_string()))
+            .collect();
+        assert_eq!(assignments, [("A", "1".into()), ("B", "x$Cy z".into())]);
+        assert_eq!(words(&lists[0].pipelines[0].1.commands[0]), ["cmd", "D=2"]);
+
+        // Only an unquoted `=` after a valid name makes an assignment
+        for line in ["'A=1' cmd", "1A=2 cmd", "=x cmd", "A-B=1 cmd"] {
+            let lists = parse(line).unwrap();
+            let CommandNode::Simple(command) = &lists[0].pipelines[0].1.commands[0] else {
+                panic!("Expected a simple command");
+            };
+            assert!(command.assignments.is_empty(), "{line}");
+        }
+    }
+
+    #[test]
+    fn array_assignments_take_their_elements() {
+        for (line, elements) in [
+            ("A=(x y) cmd", vec!["x", "y"]),
+            ("A=( x \"y z\" ) cmd", vec!["x", "y z"]),
+            ("A=() cmd", vec![]),
+            ("A=([1]=$x) cmd", vec!["[1]=$x"]),
+        ] {
+            let lists = parse(line).unwrap();
+            let CommandNode::Simple(command) = &lists[0].pipelines[0].1.commands[0] else {
+                panic!("Expected a simple command");
+            };
+            let array = command.assignments[0].array.as_ref().unwrap();
+            let array: Vec<_> = array.iter().map(Word::to_string).collect();
+            assert_eq!(array, elements, "{line}");
+            assert_eq!(words(&lists[0].pipelines[0].1.commands[0]), ["cmd"]);
+        }
+
+        let lists = parse("A[$i+1]=x").unwrap();
+        let CommandNode::Simple(command) = &lists[0].pipelines[0].1.commands[0] else {
+            panic!("Expected a simple command");
+        };
+        assert_eq!(command.assignments[0].name, "A[$i+1]");
+    }
+
+    #[test]
+    fn time_prefixes_a_pipeline() {
+        let lists = parse("time a | b; time -p ! c; a time; time; time -p").unwrap();
+        let timed: Vec<_> = lists
+            .iter()
+            .flat_map(|list| &list.pipelines)
+            .map(|(_, pipeline)| {
+                (
+                    pipeline.time,
+                    pipeline.negated,
+                    words(&pipeline.commands[0]),
+                )
+            })
+            .collect();
+        assert_eq!(
+            timed,
+            [
+                (Some(TimeFormat::Default), false, vec!["a".to_owned()]),
+                (Some(TimeFormat::Posix), true, vec!["c".to_owned()]),
+                (None, false, vec!["a".to_owned(), "time".to_owned()]),
+                (None, false, vec!["time".to_owned()]),
+                (None, false, vec!["time".to_owned(), "-p".to_owned()]),
+            ]
+        );
+        assert_eq!(texts("time a | b"), ["time a | b"]);
+    }
+
+    #[test]
+    fn bang_negates_a_pipeline() {
+        let lists = parse("! a | b && !c; ! { d; }").unwrap();
+        let negated: Vec<_> = lists
+            .iter()
+            .flat_map(|list| &list.pipelines)
+            .map(|(_, pipeline)| (pipeline.negated, pipeline.commands.len()))
+            .collect();
+        assert_eq!(negated, [(true, 2), (false, 1), (true, 1)]);
+        assert_eq!(texts("! a | b"), ["! a | b"]);
+
+        for (line, token, span) in [
+            ("!  ", "newline", 0..1),
+            ("!\necho", "newline", 0..1),
+            ("! && a", "&&", 2..4),
+        ] {
+            let error = syntax_error(line);
+            assert_eq!(error.kind, SyntaxErrorKind::UnexpectedToken(token.into()));
+            assert_eq!(error.span, span, "{line:?}");
+        }
+    }
+
+    #[test]
+    fn ampersand_runs_in_the_background() {
+        assert_eq!(
+            pipelines("sleep 1 & echo a; echo b &"),
+            [
+                (Connector::Always, "sleep 1".into(), true),
+                (Connector::Always, "echo a".into(), false),
+                (Connector::Always, "echo b".into(), true),
+            ]
+        );
+        // Redirections keep their `&`
+        assert_eq!(texts("cmd 2>&1 &>out <&0"), ["cmd 2>&1 &>out <&0"]);
+    }
+
+    #[test]
+    fn quoted_and_nested_operators_are_kept() {
+        for line in [
+            "echo 'a;b'",
+            "echo \"a;b\"",
+            "echo $(echo a; echo b)",
+            "echo $(echo (a); echo b)",
+            "echo ${X:-a;b}",
+            "echo '&&' \"||\" '|'",
+        ] {
+            assert_eq!(texts(line), [line], "{line}");
+        }
+        assert_eq!(texts("echo a # b; c"), ["echo a"]);
+
+        // Closing a substitution inside quotes returns to the quotes
+        assert_eq!(
+            texts(r#"echo "$(echo ")")" && echo "${A}"; echo b"#),
+            [r#"echo "$(echo ")")""#, r#"echo "${A}""#, "echo b"]
+        );
+    }
+
+    #[test]
+    fn here_document_bodies_stay_with_their_command() {
+        let lists = parse("cat <<EOF; echo 'a;b'\nx && y\nEOF\n").unwrap();
+        let CommandNode::Simple(cat) = &lists[0].pipelines[0].1.commands[0] else {
+            panic!("Expected a simple command");
+        };
+        assert_eq!(cat.redirects[0].target.to_string(), "x && y\n");
+        assert_eq!(words(&lists[1].pipelines[0].1.commands[0]), ["echo", "a;b"]);
+
+        let lists = parse("cat <<-'A' <<B # c\n\tA\nb;\nB").unwrap();
+        let CommandNode::Simple(cat) = &lists[0].pipelines[0].1.commands[0] else {
+            panic!("Expected a simple command");
+        };
+        let targets: Vec<_> = cat.redirects.iter().map(|r| r.target.to_string()).collect();
+        assert_eq!(targets, ["", "b;\n"]);
+    }
+
+    #[test]
+    fn unterminated_here_documents() {
+        for (line, delimiter, span) in [
+            ("cat <<EOF", "EOF", 4..9),
+            ("cat << 'E F'\nbody\n", "E F", 4..12),
+            ("cat <<A <<B\nA\n", "B", 8..11),
+        ] {
+            let error = syntax_error(line);
+            assert_eq!(
+                error.kind,
+                SyntaxErrorKind::UnterminatedHereDoc(delimiter.into())
+            );
+            assert_eq!(error.span, span, "{line:?}");
+        }
+    }
+
+    mod aliases {
+        use super::*;
+
+        fn expanded(line: &str) -> Vec<String> {
+            let mut aliases = Aliases::default();
+            aliases.set("ll", "ls -l");
+            aliases.set("ls", "ls -F");
+            aliases.set("two", "echo a; echo b");
+            aliases.set("sudo", "sudo ");
+            aliases.set("loop", "loop");
+            parse_with_aliases(line, &aliases, 1)
+                .unwrap()
+                .iter()
+                .flat_map(|list| &list.pipelines)
+                .flat_map(|(_, pipeline)| &pipeline.commands)
+                .map(|command| words(command).join(" "))
+                .collect()
+        }
+
+        #[test]
+        fn command_names_are_expanded() {
+            assert_eq!(expanded("ll /tmp"), ["ls -F -l /tmp"]);
+            assert_eq!(expanded("echo ll | ll"), ["echo ll", "ls -F -l"]);
+            assert_eq!(expanded("X=1 ll; two"), ["ls -F -l", "echo a", "echo b"]);
+        }
+
+        #[test]
+        fn an_alias_isnt_expanded_inside_itself() {
+            assert_eq!(expanded("loop; ls ls"), ["loop", "ls -F ls"]);
+        }
+
+        #[test]
+        fn a_trailing_blank_expands_the_next_word() {
+            assert_eq!(expanded("sudo ll x"), ["sudo ls -F -l x"]);
+            assert_eq!(expanded("sudo x ll"), ["sudo x ll"]);
+        }
+
+        #[test]
+        fn quoted_names_are_not_expanded() {
+            assert_eq!(expanded("'ll'; ll''; l\"l\""), ["ll", "ll", "ll"]);
+        }
+    }
+
+    mod groups {
+        use super::*;
+
+        fn group(line: &str) -> Group {
+            let mut lists = parse(line).unwrap();
+            let (_, mut pipeline) = lists.remove(0).pipelines.remove(0);
+            match pipeline.commands.remove(0) {
+                CommandNode::Group(group) => group,
+                command => panic!("Expected a group, got {command:?}"),
+            }
+        }
+
+        fn targets(redirects: &[Redirect]) -> Vec<String> {
+            redirects.iter().map(|r| r.target.to_string()).collect()
+        }
+
+        #[test]
+        fn separators_inside_are_kept() {
+            assert_eq!(
+                texts("{ a; b && c & } > out; d"),
+                ["{ a; b && c & } > out", "d"]
+            );
+            assert_eq!(texts("{ a; { b; }; } || c"), ["{ a; { b; }; }", "c"]);
+            assert_eq!(group("{ a; { b; }; }").body.len(), 2);
+        }
+
+        #[test]
+        fn braces_inside_words_are_literal() {
+            for line in ["echo {a,b}; x", "echo { a; x", "echo a}; x", "echo '{' ; x"] {
+                assert_eq!(texts(line).len(), 2, "{line}");
+            }
+            let lists = parse("{a,b}").unwrap();
+            assert_eq!(words(&lists[0].pipelines[0].1.commands[0]), ["{a,b}"]);
+        }
+
+        #[test]
+        fn body_and_redirections() {
+            let group = group("  { echo a; echo b; } > out 2>err");
+            assert_eq!(group.body.len(), 2);
+            assert_eq!(targets(&group.redirects), ["out", "err"]);
+
+            let group = self::group("{ cat <<A; } <<B\na\nA\nb\nB");
+            let CommandNode::Simple(cat) = &group.body[0].pipelines[0].1.commands[0] else {
+                panic!("Expected a simple command");
+            };
+            assert_eq!(targets(&cat.redirects), ["a\n"]);
+            assert_eq!(targets(&group.redirects), ["b\n"]);
+        }
+
+        #[test]
+        fn errors() {
+            for (line, kind, span) in [
+                ("{ echo a }", SyntaxErrorKind::UnterminatedGroup, 0..10),
+                (
+                    "{ echo a; } b c",
+                    SyntaxErrorKind::UnexpectedToken("b".into()),
+                    12..13,
+                ),
+                (
+                    "{ echo a; } >out c",
+                    SyntaxErrorKind::UnexpectedToken("c".into()),
+                    17..18,
+                ),
+                ("{ }", SyntaxErrorKind::UnexpectedToken("}".into()), 2..3),
+                (
+                    "echo a; }",
+                    SyntaxErrorKind::UnexpectedToken("}".into()),
+                    8..9,
+                ),
+            ] {
+                let error = syntax_error(line);
+                assert_eq!(error.kind, kind, "{line:?}");
+                assert_eq!(error.span, span, "{line:?}");
+            }
+        }
+
+        #[test]
+        fn nesting_is_limited() {
+            let nested = |depth| "{ ".repeat(depth) + "a" + &"; }".repeat(depth);
+            assert!(parse(&nested(MAX_DEPTH - 1)).is_ok());
+            let error = syntax_error(&nested(MAX_DEPTH));
+            assert_eq!(error.kind, SyntaxErrorKind::NestedTooDeeply);
+            assert_eq!(error.span, 2 * MAX_DEPTH - 1..2 * MAX_DEPTH - 1);
+        }
+    }
+
+    mod ifs {
+        use super::*;
+
+        fn if_(line: &str) -> If {
+            let mut lists = parse(line).unwrap();
+            let (_, mut pipeline) = lists.remove(0).pipelines.remove(0);
+            match pipeline.commands.remove(0) {
+                CommandNode::If(if_) => if_,
+                command => panic!("Expected an if, got {command:?}"),
+            }
+        }
+
+        #[test]
+        fn clauses_and_else() {
+            let if_ = if_("if a; then b; c; elif d\nthen e\nelse f; fi > out");
+            let lens: Vec<_> = (if_.clauses.iter())
+                .map(|(condition, body)| (condition.len(), body.len()))
+                .collect();
+            assert_eq!(lens, [(1, 2), (1, 1)]);
+            assert_eq!(if_.else_body.map(|body| body.len()), Some(1));
+            assert_eq!(if_.redirects.len(), 1);
+
+            assert_eq!(
+                texts("if a; then if b; then c; fi; fi && d"),
+                ["if a; then if b; then c; fi; fi", "d"]
+            );
+            assert_eq!(self::if_("if a\nthen b\nfi").else_body, None);
+        }
+
+        #[test]
+        fn reserved_words_only_start_commands() {
+            assert_eq!(texts("echo if then fi; x").len(), 2);
+            let if_ = self::if_("if echo then; then echo fi 'fi'; fi");
+            assert_eq!(
+                words(&if_.clauses[0].0[0].pipelines[0].1.commands[0]),
+                ["echo", "then"]
+            );
+            assert_eq!(if_.clauses[0].1.len(), 1);
+        }
+
+        #[test]
+        fn errors() {
+            for (line, kind, span) in [
+                (
+                    "if a; then b",
+                    SyntaxErrorKind::UnterminatedCompound("fi".into()),
+                    0..12,
+                ),
+                (
+                    "if a\n",
+                    SyntaxErrorKind::UnterminatedCompound("then".into()),
+                    0..4,
+                ),
+                (
+                    "if then b; fi",
+                    SyntaxErrorKind::UnexpectedToken("then".into()),
+                    3..7,
+                ),
+                (
+                    "if a; then fi",
+                    SyntaxErrorKind::UnexpectedToken("fi".into()),
+                    11..13,
+                ),
+                (
+                    "if a; then b; fi c",
+                    SyntaxErrorKind::UnexpectedToken("c".into()),
+                    17..18,
+                ),
+                (
+                    "echo a; fi",
+                    SyntaxErrorKind::UnexpectedToken("fi".into()),
+                    8..10,
+                ),
+                (
+                    "if a; then b; else c; elif d; then e; fi",
+                    SyntaxErrorKind::UnexpectedToken("elif".into()),
+                    22..26,
+                ),
+            ] {
+                let error = syntax_error(line);
+                assert_eq!(error.kind, kind, "{line:?}");
+                assert_eq!(error.span, span, "{line:?}");
+            }
+        }
+    }
+
+    mod loops {
+        use super::*;
+
+        fn while_(line: &str) -> While {
+            let mut lists = parse(line).unwrap();
+            let (_, mut pipeline) = lists.remove(0).pipelines.remove(0);
+            match pipeline.commands.remove(0) {
+                CommandNode::While(while_) => while_,
+                command => panic!("Expected a loop, got {command:?}"),
+            }
+        }
+
+        #[test]
+        fn condition_body_and_redirections() {
+            let while_ = while_("while a; b\ndo c; done < in");
+            assert_eq!((while_.condition.len(), while_.body.len()), (2, 1));
+            assert!(!while_.until);
+            assert_eq!(while_.redirects.len(), 1);
+
+            assert!(self::while_("until a; do b; done").until);
+            assert_eq!(
+                texts("while a; do while b; do c; done; done & d"),
+                ["while a; do while b; do c; done; done", "d"]
+            );
+        }
+
+        #[test]
+        fn select_words() {
+            let command = |line| {
+                let (_, mut pipeline) = parse(line).unwrap().remove(0).pipelines.remove(0);
+                match pipeline.commands.remove(0) {
+                    CommandNode::Select(select) => select,
+                    command => panic!("Expected a select, got {command:?}"),
+                }
+            };
+            let select = command("select x in a 'b c'$d; do e; done");
+            assert_eq!(select.name, "x");
+            let words: Vec<_> = (select.words.unwrap().iter())
+                .map(Word::to_string)
+                .collect();
+            assert_eq!(words, ["a", "b c$d"]);
+            assert_eq!(select.body.len(), 1);
+
+            assert_eq!(command("select x\ndo e; done").words, None);
+            assert_eq!(command("select x in; do e; done").words, Some(vec![]));
+        }
+
+        #[test]
+        fn errors() {
+            for (line, kind, span) in [
+                (
+                    "select x in a",
+                    SyntaxErrorKind::UnterminatedCompound("do".into()),
+                    0..13,
+                ),
+                (
+                    "select 1x; do a; done",
+                    SyntaxErrorKind::UnexpectedToken("1x".into()),
+                    7..9,
+                ),
+                (
+                    "while a; do b",
+                    SyntaxErrorKind::UnterminatedCompound("done".into()),
+                    0..13,
+                ),
+                (
+                    "until a",
+                    SyntaxErrorKind::UnterminatedCompound("do".into()),
+                    0..7,
+                ),
+                (
+                    "while a; do done",
+                    SyntaxErrorKind::UnexpectedToken("done".into()),
+                    12..16,
+                ),
+                (
+                    "if a; then while b; do c; fi; done",
+                    SyntaxErrorKind::UnexpectedToken("fi".into()),
+                    26..28,
+                ),
+            ] {
+                let error = syntax_error(line);
+                assert_eq!(error.kind, kind, "{line:?}");
+                assert_eq!(error.span, span, "{line:?}");
+            }
+        }
+    }
+
+    mod cases {
+        use super::*;
+
+        fn case(line: &str) -> Case {
+            let mut lists = parse(line).unwrap();
+            let (_, mut pipeline) = lists.remove(0).pipelines.remove(0);
+            match pipeline.commands.remove(0) {
+                CommandNode::Case(case) => case,
+                command => panic!("Expected a case, got {command:?}"),
+            }
+        }
+
+        /// The patterns of each clause as written, with how long its body is.
+        fn clauses(case: &Case) -> Vec<(Vec<String>, usize)> {
+            (case.clauses.iter())
+                .map(|(patterns, body)| {
+                    let patterns = patterns.iter().map(Word::to_string).collect();
+                    (patterns, body.len())
+                })
+                .collect()
+        }
+
+        #[test]
+        fn patterns_and_bodies() {
+            let case = case("case $x in (*.rs|a) b; c;; 'd'e) ;; f)g\nesac >out");
+            assert_eq!(
+                clauses(&case),
+                [
+                    (vec!["*.rs".into(), "a".into()], 2),
+                    (vec!["de".into()], 0),
+                    (vec!["f".into()], 1),
+                ]
+            );
+            assert_eq!(case.redirects.len(), 1);
+
+            let case = self::case("case x\nin\n  (x | y )\n    echo\n    ;;\nesac");
+            assert_eq!(clauses(&case), [(vec!["x".into(), "y".into()], 1)]);
+            assert!(self::case("case x in esac").clauses.is_empty());
+            assert_eq!(
+                texts("case x in x) case y in y) z;; esac;; esac; w"),
+                ["case x in x) case y in y) z;; esac;; esac", "w"]
+            );
+        }
+
+        #[test]
+        fn errors() {
+            for (line, kind, span) in [
+                (
+                    "case x in x) y;;",
+                    SyntaxErrorKind::UnterminatedCompound("esac".into()),
+                    0..16,
+                ),
+                (
+                    "case x",
+                    SyntaxErrorKind::UnterminatedCompound("in".into()),
+                    0..6,
+                ),
+                (
+                    "case x in ;; esac",
+                    SyntaxErrorKind::UnexpectedToken(";;".into()),
+                    10..12,
+                ),
+                (
+                    "case x in x y) z;; esac",
+                    SyntaxErrorKind::UnexpectedToken("y".into()),
+                    12..13,
+                ),
+                (
+                    "case x y",
+                    SyntaxErrorKind::UnexpectedToken("y".into()),
+                    7..8,
+                ),
+            ] {
+                let error = syntax_error(line);
+                assert_eq!(error.kind, kind, "{line:?}");
+                assert_eq!(error.span, span, "{line:?}");
+            }
+        }
+    }
+
+    mod functions {
+        use super::*;
+
+        fn function(line: &str) -> Function {
+            let mut lists = parse(line).unwrap();
+            let (_, mut pipeline) = lists.remove(0).pipelines.remove(0);
+            match pipeline.commands.remove(0) {
+                CommandNode::Function(function) => function,
+                command => panic!("Expected a function, got {command:?}"),
+            }
+        }
+
+        #[test]
+        fn definitions() {
+            for line in [
+                "f() { a; b; }",
+                "f () { a; b; }",
+                "f()\n\n{\n a\n b\n}",
+                "f(){ a; b; }",
+                "f (){ a; b; }",
+            ] {
+                let function = function(line);
+                assert_eq!(function.name, "f", "{line:?}");
+                assert_eq!(function.body.body.len(), 2, "{line:?}");
+                assert_eq!(function.text, line, "{line:?}");
+            }
+
+            let function = function("f() { cat; } > out; f");
+            assert_eq!(function.text, "f() { cat; } > out");
+            assert_eq!(texts("f() { cat; } > out; f").len(), 2);
+        }
+
+        #[test]
+        fn only_a_name_can_be_defined() {
+            let lists = parse("a-b() x").unwrap();
+            assert_eq!(words(&lists[0].pipelines[0].1.commands[0]), ["a-b()", "x"]);
+        }
+
+        #[test]
+        fn errors() {
+            for (line, kind, span) in [
+                (
+                    "f() echo a",
+                    SyntaxErrorKind::UnexpectedToken("echo".into()),
+                    4..8,
+                ),
+                (
+                    "f() ; { a; }",
+                    SyntaxErrorKind::UnexpectedToken(";".into()),
+                    4..5,
+                ),
+                (
+                    "f ()x { a; }",
+                    SyntaxErrorKind::UnexpectedToken("()x".into()),
+                    2..5,
+                ),
+                ("f()", SyntaxErrorKind::UnterminatedGroup, 0..3),
+                ("f() {\n a", SyntaxErrorKind::UnterminatedGroup, 4..8),
+            ] {
+                let error = syntax_error(line);
+                assert_eq!(error.kind, kind, "{line:?}");
+                assert_eq!(error.span, span, "{line:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn empty_commands_are_errors() {
+        for (line, token, span) in [
+            (";", ";", 0..1),
+            // `;;` ends a `case` clause, and is out of place anywhere else
+            ("echo a;; echo b", ";;", 6..8),
+            (" ; echo", ";", 1..2),
+            ("&& echo", "&&", 0..2),
+            ("echo a && ; b", ";", 10..11),
+            ("& echo", "&", 0..1),
+            ("echo a & ; b", ";", 9..10),
+            ("| echo", "|", 0..1),
+            ("echo a | ; b", ";", 9..10),
+            ("echo a | | b", "|", 9..10),
+        ] {
+            let error = syntax_error(line);
+            assert_eq!(error.kind, SyntaxErrorKind::UnexpectedToken(token.into()));
+            assert_eq!(error.span, span, "{line:?}");
+        }
+    }
+
+    #[test]
+    fn operators_at_the_end_wait_for_a_command() {
+        for (line, token, span) in [
+            ("echo a ||", "||", 7..9),
+            ("echo a |", "|", 7..8),
+            ("echo a &&\n\n", "&&", 7..9),
+        ] {
+            let error = syntax_error(line);
+            assert_eq!(error.kind, SyntaxErrorKind::UnfinishedCommand(token.into()));
+            assert_eq!(error.span, span, "{line:?}");
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use std::{path::Path, time::Duration};
+
+use crate::{
+    command::{abbreviate, logical_dir},
+    state::ShellState,
+    times,
+};
+
+/// The prompt shown when neither `RUSH_PROMPT` nor `PS1` is set.
+const DEFAULT_PROMPT: &str = "$ ";
+
+/// The prompt shown for a continuation line when `PS2` isn't set.
+const DEFAULT_CONTINUATION: &str = "> ";
+
+/// How long, in seconds, a command line runs before the shell says how long
+/// it took, unless `RUSH_TOOK_SECONDS` says otherwise.
+const DEFAULT_TOOK_SECONDS: f64 = 5.0;
+
+/// The prompt to show before reading a command: `RUSH_PROMPT` if it's set,
+/// then `PS1`, with their escapes expanded.
+pub(crate) fn primary(state: &ShellState) -> String {
+    let template = (state.vars.get("RUSH_PROMPT"))
+        .or_else(|| state.vars.get("PS1"))
+        .unwrap_or_else(|| DEFAULT_PROMPT.to_owned());
+    expand(&template, state)
+}
+
+/// The prompt to show before reading the rest of a command that went on
+/// past the end of its line: `PS2`, with its escapes expanded.
+pub(crate) fn secondary(state: &ShellState) -> String {
+    let template = (state.vars.get("PS2")).unwrap_or_else(|| DEFAULT_CONTINUATION.to_owned());
+    expand(&template, state)
+}
+
+/// The note shown after a command line that ran for at least
+/// `RUSH_TOOK_SECONDS`, like `took 3.2s`.
+pub(crate) fn took(state: &ShellState) -> Option<String> {
+    let threshold = (state.vars.get("RUSH_TOOK_SECONDS"))
+        .and_then(|seconds| seconds.trim().parse::<f64>().ok())
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .unwrap_or(Duration::from_secs_f64(DEFAULT_TOOK_SECONDS));
+    (state.last_duration >= threshold)
+        .then(|| format!("took {}", times::brief(state.last_duration)))
+}
+
+/// Expands the backslash escapes of a prompt template the way bash does for
+/// `PS1`:
+///
+/// - `\u` the user, `\h` the host up to its first `.`, and `\H` all of it
+/// - `\w` the current directory with `$HOME` as `~`, and `\W` its last part
+/// - `\$` a `#` for root and a `$` for anyone else
+/// - `\?` the last command's exit status, and `\D` how long its line took
+/// - `\t` the time as `HH:MM:SS`, `\A` as `HH:MM`, and `\d` the date as
+///   `Tue May 26`
+/// - `\s` the shell's name, `\n` a newline, `\a` a bell, `\\` a backslash
+/// - `\e` or `\033` an escape, to start a color sequence like `\e[32m`;
+///   any character can be written as three octal digits
+/// - `\[` and `\]` around characters that take up no room, which are left
+///   out, since the editor skips escape sequences when it measures the
+///   prompt anyway
+///
+/// Any other backslash is kept as it is.
+pub(crate) fn expand(template: &str, state: &ShellState) -> String {
+    let mut prompt = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            prompt.push(c);
+            continue;
+        }
+        let Some(escape) = chars.next() else {
+            prompt.push('\\');
+            break;
+        };
+        match escape {
+            'u' => prompt.push_str(&user(state)),
+            'h' => prompt.push_str(host(state).split('.').next().unwrap_or_default()),
+            'H' => prompt.push_str(&host(state)),
+            'w' => prompt.push_str(&working_dir(state)),
+            'W' => {
+                let dir = working_dir(state);
+                let base = match dir.as_str() {
+                    "/" | "~" => &dir,
+                    _ => dir.rsplit('/').next().unwrap_or(&dir),
+                };
+                prompt.push_str(base);
+            }
+            '$' => prompt.push(if is_root() { '#' } else { '$' }),
+            '?' => prompt.push_str(&state.last_status.code().to_string()),
+            'D' => prompt.push_str(&times::brief(state.last_duration)),
+            't' | 'A' | 'd' => {
+                let now = clock::now();
+                match escape {
+                    't' => prompt += &format!("{:02}:{:02}:{:02}", now.hour, now.min, now.sec),
+                    'A' => prompt += &format!("{:02}:{:02}", now.hour, now.min),
+                    _ => prompt += &now.date(),
+                }
+            }
+            's' => prompt.push_str("rush"),
+            'n' => prompt.push('\n'),
+            'a' => prompt.push('\x07'),
+            'e' => prompt.push('\x1b'),
+            '\\' => prompt.push('\\'),
+            '[' | ']' => {}
+            '0'..='7' => {
+                let mut code = escape.to_digit(8).unwrap_or_default();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                prompt.extend(char::from_u32(code));
+            }
+            other => {
+                prompt.push('\\');
+                prompt.push(other);
+            }
+        }
+    }
+    prompt
+}
+
+fn user(state: &ShellState) -> String {
+    ["USER", "LOGNAME", "USERNAME"]
+        .into_iter()
+        .find_map(|name| state.vars.get(name))
+        .unwrap_or_default()
+}
+
+fn working_dir(state: &ShellState) -> String {
+    let dir = logical_dir(state);
+    match state.vars.get("HOME").filter(|home| !home.is_empty()) {
+        Some(home) => abbreviate(&dir, Path::new(&home)),
+        None => dir.display().to_string(),
+    }
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    // SAFETY: geteuid takes nothing and can't fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn host(_state: &ShellState) -> String {
+    let mut name = [0u8; 256];
+    // SAFETY: name has room for the length given, less one so the name
+    // always ends in a nul
+    if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len() - 1) } != 0 {
+        return String::new();
+    }
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..len]).into_owned()
+}
+
+#[cfg(not(unix))]
+fn host(state: &ShellState) -> String {
+    state.vars.get("COMPUTERNAME").unwrap_or_default()
+}
+
+/// The local time of day, for the prompt's clock escapes.
+mod clock {
+    pub(super) struct Now {
+        pub sec: i32,
+        pub min: i32,
+        pub hour: i32,
+        pub mday: i32,
+        pub mon: i32,
+        pub wday: i32,
+    }
+
+    impl Now {
+        /// The date as `date` shows it, like `Tue May 26`.
+        pub(super) fn date(&self) -> String {
+            const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+            const MONTHS: [&str; 12] = [
+                "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+            ];
+            let day = DAYS.get(self.wday as usize).unwrap_or(&"");
+            let month = MONTHS.get(self.mon as usize).unwrap_or(&"");
+            format!("{day} {month} {:02}", self.mday)
+        }
+    }
+
+    #[cfg(unix)]
+    pub(super) fn now() -> Now {
+        // SAFETY: a tm is plain data, which localtime_r fills in
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        // SAFETY: time accepts a null pointer, and localtime_r only fills
+        // in tm
+        unsafe {
+            let now = libc::time(std::ptr::null_mut());
+            libc::localtime_r(&now, &mut tm);
+        }
+        Now {
+            sec: tm.tm_sec,
+            min: tm.tm_min,
+            hour: tm.tm_hour,
+            mday: tm.tm_mday,
+            mon: tm.tm_mon,
+            wday: tm.tm_wday,
+        }
+    }
+
+    /// Without the C library's time zones, the time is shown in UTC.
+    #[cfg(not(unix))]
+    pub(super) fn now() -> Now {
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let days = secs / 86_400;
+        let of_day = (secs % 86_400) as i32;
+        // The civil date from days since 1970, as Howard Hinnant works it
+        let z = days as i64 + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let mday = (doy - (153 * mp + 2) / 5 + 1) as i32;
+        let mon = if mp < 10 { mp + 2 } else { mp - 10 } as i32;
+        Now {
+            sec: of_day % 60,
+            min: of_day / 60 % 60,
+            hour: of_day / 3600,
+            mday,
+            mon,
+            wday: ((days + 4) % 7) as i32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::ExitStatus;
+
+    fn state_with(vars: &[(&str, &str)]) -> ShellState {
+        let mut state = ShellState::default();
+        for (name, value) in vars {
+            state.vars.set(name, value);
+        }
+        state
+    }
+
+    #[test]
+    fn prompt_variables() {
+        assert_eq!(primary(&ShellState::default()), "$ ");
+        assert_eq!(primary(&state_with(&[("PS1", "> ")])), "> ");
+        assert_eq!(
+            primary(&state_with(&[("PS1", "> "), ("RUSH_PROMPT", "rush\\$ ")])),
+            format!("rush{} ", if is_root() { '#' } else { '$' })
+        );
+
+        assert_eq!(secondary(&ShellState::default()), "> ");
+        assert_eq!(secondary(&state_with(&[("PS2", "\\s.. ")])), "rush.. ");
+    }
+
+    #[test]
+    fn escapes() {
+        let state = ShellState::default();
+        assert_eq!(expand("\\s\\n\\\\ \\q", &state), "rush\n\\ \\q");
+        assert_eq!(
+            expand("\\[\\e[32m\\]ok\\[\\033[0m\\]", &state),
+            "\x1b[32mok\x1b[0m"
+        );
+        assert_eq!(expand("\\101\\0", &state), "A\0");
+        assert_eq!(expand("trailing\\", &state), "trailing\\");
+        assert_eq!(expand("\\u", &state_with(&[("USER", "ada")])), "ada");
+    }
+
+    #[test]
+    fn last_command() {
+        let mut state = ShellState {
+            last_status: ExitStatus::from_code(3),
+            last_duration: Duration::from_millis(3_210),
+            ..Default::default()
+        };
+        assert_eq!(expand("[\\?] \\D", &state), "[3] 3.2s");
+
+        assert_eq!(took(&state), None);
+        state.vars.set("RUSH_TOOK_SECONDS", "1.5");
+        assert_eq!(took(&state).as_deref(), Some("took 3.2s"));
+        state.vars.set("RUSH_TOOK_SECONDS", "5");
+        state.last_duration = Duration::from_secs(65);
+        assert_eq!(took(&state).as_deref(), Some("took 1m5s"));
+    }
+
+    #[test]
+    fn clock() {
+        let state = ShellState::default();
+        let time = expand("\\t", &state);
+        assert_eq!(time.len(), 8);
+        assert_eq!(time.matches(':').count(), 2);
+        assert_eq!(expand("\\A", &state).len(), 5);
+        assert_eq!(expand("\\d", &state).split(' ').count(), 3);
+    }
+
+    #[test]
+    fn working_directory() {
+        let dir = logical_dir(&ShellState::default());
+        let parent = dir.parent().unwrap().display().to_string();
+        let state = state_with(&[("HOME", &parent)]);
+
+        let name = dir.file_name().unwrap().to_string_lossy();
+        assert_eq!(expand("\\w", &state), format!("~/{name}"));
+        assert_eq!(expand("\\W", &state), name);
+
+        let state = state_with(&[("HOME", &dir.display().to_string())]);
+        assert_eq!(expand("\\w \\W", &state), "~ ~");
+    }
+}
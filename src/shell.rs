@@ -0,0 +1,305 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    time::Instant,
+};
+
+use crate::{
+    command::redirect, diagnostics, editor::Editor, eval, prompt, signals, startup,
+    state::ShellState, status::ExitStatus, util, util::RushError,
+};
+
+/// How a [`Shell`] starts out.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Config {
+    /// Whether [`Shell::run_interactive`] prompts with the line editor even
+    /// if stdin isn't a terminal.
+    pub interactive: bool,
+    /// Whether this is a login shell, which sources the profile files as it
+    /// starts and the logout file as it exits.
+    pub login: bool,
+    /// Whether the shell is restricted, for accounts limited to what's on
+    /// `PATH`, once it's set up.
+    pub restricted: bool,
+    /// Whether [`Shell::run_interactive`] sources `~/.rushrc`.
+    pub rc: bool,
+    /// The positional parameters, `$1` on.
+    pub args: Vec<String>,
+}
+
+/// A shell, with everything it remembers from one command line to the next.
+/// It starts in the process's working directory and environment, but keeps
+/// copies of its own, so `cd` and `export` in it leave the process as it
+/// was. What `exec` redirects and how signals are handled still belong to
+/// the whole process.
+#[derive(Debug)]
+pub struct Shell {
+    config: Config,
+    state: ShellState,
+}
+
+impl Shell {
+    /// Starts a shell as `config` asks, sourcing the profile files if it's a
+    /// login shell.
+    pub fn new(config: Config) -> Self {
+        let mut state = ShellState::default();
+        startup::export_environment(&mut state);
+        // `$_` starts out as the shell itself, not whatever the program that
+        // started it left there
+        state.vars.unset("_");
+        if let Ok(shell) = env::current_exe() {
+            state.vars.set("_", &shell.to_string_lossy());
+        }
+        state.args = config.args.clone();
+        if config.login {
+            startup::login(&mut state);
+        }
+        Self { config, state }
+    }
+
+    /// Runs one command line, reporting any failures on stderr, and returns
+    /// the status it finished with. Once the shell has exited, nothing more
+    /// runs, and the status it exited with is returned instead.
+    pub fn run_line(&mut self, line: &str) -> ExitStatus {
+        if let Some(status) = self.state.exited {
+            return status;
+        }
+        self.restrict();
+        // Jobs aren't announced, as they would be before a prompt
+        self.state.jobs.reap();
+        if let Err(error) = self.eval(line) {
+            diagnostics::report(error, &self.state);
+        }
+        self.state.exited.unwrap_or(self.state.last_status)
+    }
+
+    /// Reads commands from stdin and runs them until the shell exits,
+    /// returning the status it exits with. The config file and `~/.rushrc`
+    /// are loaded first, and commands typed at a terminal are read with the
+    /// line editor.
+    pub fn run_interactive(&mut self) -> ExitStatus {
+        if self.state.exited.is_none() {
+            startup::initialize(&mut self.state, self.config.rc);
+        }
+        self.restrict();
+        // Without a terminal, an editor falls back on showing the prompt and
+        // reading the line as it's typed
+        let interactive = self.config.interactive || io::stdin().is_terminal();
+        let editor = interactive.then(Editor::default);
+        self.state.interactive = editor.is_some();
+        if editor.is_some() {
+            // Ctrl-Z stops the command being waited for, not the shell
+            signals::catch(signals::number("TSTP").unwrap_or(20)).ok();
+            // The line being edited is redrawn to fit when the window resizes
+            signals::watch(signals::number("WINCH").unwrap_or(28)).ok();
+            // TERM sent to end what's running leaves the shell prompting
+            signals::outlast_forwarded();
+        }
+        self.run(Input::Stdin(editor.map(Box::new)))
+    }
+
+    /// Runs the script at `path` until it ends or exits, returning the
+    /// status the shell exits with. A script that can't be opened fails the
+    /// way a command that can't be found or run does.
+    pub fn run_script(&mut self, path: &str) -> ExitStatus {
+        self.restrict();
+        match open_script(path) {
+            Ok(input) => self.run(input),
+            Err(status) => status,
+        }
+    }
+
+    /// Restricts the shell if `config` asks for it. It's left until the
+    /// shell is set up, so the startup files can still set `PATH`.
+    fn restrict(&mut self) {
+        if self.config.restricted && !self.state.restricted {
+            startup::restrict(&mut self.state);
+        }
+    }
+
+    fn run(&mut self, mut input: Input) -> ExitStatus {
+        loop {
+            if let Some(status) = self.state.exited {
+                return status;
+            }
+            if let Err(error) = self.next(&mut input) {
+                diagnostics::report(error, &self.state);
+            }
+        }
+    }
+
+    /// Reads the next command line from `input` and runs it.
+    fn next(&mut self, input: &mut Input) -> Result<(), RushError> {
+        let state = &mut self.state;
+        let interactive = matches!(input, Input::Stdin(Some(_)));
+
+        // Background jobs that finished while the last line ran are announced
+        // before the next prompt
+        for (job, status) in state.jobs.reap() {
+            if interactive {
+                eprintln!("{}", job.finished(status));
+            }
+        }
+
+        let mut line = String::new();
+        let prompt = match interactive {
+            true => prompt::primary(state),
+            false => String::new(),
+        };
+
+        // A warning about jobs only holds off exiting until the next line
+        let warned = state.exit_warned;
+
+        // End of input ends the shell with the status of the last command,
+        // unless a command bound to a key already has
+        if read_line(&mut line, &prompt, input, state)? == 0 {
+            return Err(match state.exited {
+                Some(status) => RushError::Exit(status),
+                None => eval::leave(state.last_status, state),
+            });
+        }
+
+        // A quote left open, or an operator waiting for its command, carries
+        // on onto the next line
+        while util::is_incomplete(&line) {
+            let prompt = match interactive {
+                true => prompt::secondary(state),
+                false => String::new(),
+            };
+            if read_line(&mut line, &prompt, input, state)? == 0 {
+                break;
+            }
+        }
+
+        let result = self.eval(&line);
+        let state = &mut self.state;
+        if warned {
+            state.exit_warned = false;
+        }
+        if interactive && let Some(took) = prompt::took(state) {
+            eprintln!("{took}");
+        }
+        result.map(|_status| ())
+    }
+
+    /// Runs `line`, timing it for the prompt.
+    fn eval(&mut self, line: &str) -> Result<ExitStatus, RushError> {
+        let state = &mut self.state;
+        // Commands are numbered by the line they start on, for `$LINENO`
+        state.vars.set_line(state.lines_read + 1);
+        state.lines_read += line.lines().count();
+        let started = Instant::now();
+        let result = eval::eval(line, state);
+        state.last_duration = started.elapsed();
+        result
+    }
+}
+
+/// Where the shell reads its commands from.
+enum Input {
+    /// Standard input, typed at the line editor if it's a terminal.
+    Stdin(Option<Box<Editor>>),
+    /// A script named on the command line.
+    Script {
+        path: String,
+        reader: BufReader<File>,
+    },
+}
+
+/// Appends the next line of input to `line`, returning how many bytes were
+/// read. With an editor, a person is typing it, so `prompt` is shown first;
+/// scripts run silently. Ctrl-C in the editor abandons the command being
+/// typed, as if it had been interrupted.
+fn read_line(
+    line: &mut String,
+    prompt: &str,
+    input: &mut Input,
+    state: &mut ShellState,
+) -> Result<usize, RushError> {
+    let editor = match input {
+        Input::Stdin(editor) => editor.as_deref_mut(),
+        // A script that can't be read any further ends there
+        Input::Script { path, reader } => loop {
+            match reader.read_line(line) {
+                Ok(len) => return Ok(len),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => {
+                    eprintln!("rush: {path}: {error}");
+                    return Ok(0);
+                }
+            }
+        },
+    };
+    if let Some(editor) = editor {
+        match editor.read_line(prompt, state) {
+            Ok(Some(text)) => {
+                line.push_str(&text);
+                line.push('\n');
+                return Ok(text.len() + 1);
+            }
+            Ok(None) => return Ok(0),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {
+                state.last_status = ExitStatus::from_signal(signals::number("INT").unwrap_or(2));
+                return Err(RushError::Nop);
+            }
+            // Without a terminal to edit on, read the line as it's typed
+            Err(_) => {
+                print!("{prompt}");
+                io::stdout().flush().map_err(|_| RushError::UnexpectedEOF)?;
+            }
+        }
+    }
+    loop {
+        match io::stdin().lock().read_line(line) {
+            Ok(len) => return Ok(len),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            // A line that isn't text is skipped over
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                return Err(RushError::UnexpectedEOF);
+            }
+            // Input that can't be read any more, like a terminal that was
+            // closed, ends the shell just as the end of input does
+            Err(_) => return Ok(0),
+        }
+    }
+}
+
+/// Opens the script at `path` to run, or reports why it can't be and
+/// returns the status the shell exits with, the same as for running a
+/// command that can't be found or run.
+fn open_script(path: &str) -> Result<Input, ExitStatus> {
+    let file = File::open(path).and_then(|file| match file.metadata()?.is_dir() {
+        true => Err(io::Error::new(
+            io::ErrorKind::IsADirectory,
+            "is a directory",
+        )),
+        false => Ok(file),
+    });
+    match file {
+        Ok(file) => Ok(Input::Script {
+            path: path.to_owned(),
+            reader: BufReader::new(redirect::keep_clear(file)),
+        }),
+        Err(error) => {
+            eprintln!("rush: {path}: {error}");
+            Err(match error.kind() {
+                io::ErrorKind::NotFound => ExitStatus::NOT_FOUND,
+                _ => ExitStatus::NOT_EXECUTABLE,
+            })
+        }
+    }
+}
+
+/// Passes TERM, HUP, and QUIT on to the program the shell is waiting for,
+/// so a signal sent to end the shell ends that program too, and then the
+/// shell, unless it's interactive. Signals are
+/// handled for the whole process, so a program running a shell decides
+/// whether to.
+pub fn forward_signals() {
+    for name in ["TERM", "HUP", "QUIT"] {
+        if let Some(number) = signals::number(name) {
+            signals::forward(number).ok();
+        }
+    }
+}
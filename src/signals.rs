@@ -0,0 +1,418 @@
+use std::{
+    io,
+    sync::atomic::{AtomicBool, AtomicI32, Ordering},
+};
+
+/// The signals `kill` knows by name, with the numbers this platform gives
+/// them.
+#[cfg(unix)]
+const SIGNALS: &[(i32, &str)] = &[
+    (libc::SIGHUP, "HUP"),
+    (libc::SIGINT, "INT"),
+    (libc::SIGQUIT, "QUIT"),
+    (libc::SIGILL, "ILL"),
+    (libc::SIGTRAP, "TRAP"),
+    (libc::SIGABRT, "ABRT"),
+    #[cfg(any(
+        target_vendor = "apple",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "illumos",
+        target_os = "solaris",
+        all(
+            target_os = "linux",
+            any(
+                target_arch = "mips",
+                target_arch = "mips32r6",
+                target_arch = "mips64",
+                target_arch = "mips64r6",
+                target_arch = "sparc",
+                target_arch = "sparc64",
+            )
+        ),
+    ))]
+    (libc::SIGEMT, "EMT"),
+    (libc::SIGBUS, "BUS"),
+    (libc::SIGFPE, "FPE"),
+    (libc::SIGKILL, "KILL"),
+    (libc::SIGUSR1, "USR1"),
+    (libc::SIGSEGV, "SEGV"),
+    (libc::SIGUSR2, "USR2"),
+    (libc::SIGPIPE, "PIPE"),
+    (libc::SIGALRM, "ALRM"),
+    (libc::SIGTERM, "TERM"),
+    #[cfg(any(
+        target_os = "android",
+        all(
+            target_os = "linux",
+            not(any(
+                target_arch = "mips",
+                target_arch = "mips32r6",
+                target_arch = "mips64",
+                target_arch = "mips64r6",
+                target_arch = "sparc",
+                target_arch = "sparc64",
+            ))
+        ),
+    ))]
+    (libc::SIGSTKFLT, "STKFLT"),
+    (libc::SIGCHLD, "CHLD"),
+    (libc::SIGCONT, "CONT"),
+    (libc::SIGSTOP, "STOP"),
+    (libc::SIGTSTP, "TSTP"),
+    (libc::SIGTTIN, "TTIN"),
+    (libc::SIGTTOU, "TTOU"),
+    (libc::SIGURG, "URG"),
+    (libc::SIGXCPU, "XCPU"),
+    (libc::SIGXFSZ, "XFSZ"),
+    (libc::SIGVTALRM, "VTALRM"),
+    (libc::SIGPROF, "PROF"),
+    (libc::SIGWINCH, "WINCH"),
+    (libc::SIGIO, "IO"),
+    #[cfg(any(
+        target_vendor = "apple",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    ))]
+    (libc::SIGINFO, "INFO"),
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "illumos",
+        target_os = "solaris",
+    ))]
+    (libc::SIGPWR, "PWR"),
+    (libc::SIGSYS, "SYS"),
+];
+
+/// Elsewhere, only the signals the C standard names exist.
+#[cfg(not(unix))]
+const SIGNALS: &[(i32, &str)] = &[
+    (libc::SIGINT, "INT"),
+    (libc::SIGILL, "ILL"),
+    (libc::SIGABRT, "ABRT"),
+    (libc::SIGFPE, "FPE"),
+    (libc::SIGSEGV, "SEGV"),
+    (libc::SIGTERM, "TERM"),
+];
+
+/// What the signals that end a process by default are called when one does,
+/// by name.
+const DESCRIPTIONS: &[(&str, &str)] = &[
+    ("HUP", "Hangup"),
+    ("INT", "Interrupt"),
+    ("QUIT", "Quit"),
+    ("ILL", "Illegal instruction"),
+    ("TRAP", "Trace/breakpoint trap"),
+    ("ABRT", "Aborted"),
+    ("BUS", "Bus error"),
+    ("EMT", "EMT trap"),
+    ("FPE", "Floating point exception"),
+    ("KILL", "Killed"),
+    ("USR1", "User defined signal 1"),
+    ("SEGV", "Segmentation fault"),
+    ("USR2", "User defined signal 2"),
+    ("PIPE", "Broken pipe"),
+    ("ALRM", "Alarm clock"),
+    ("TERM", "Terminated"),
+    ("STKFLT", "Stack fault"),
+    ("XCPU", "CPU time limit exceeded"),
+    ("XFSZ", "File size limit exceeded"),
+    ("VTALRM", "Virtual timer expired"),
+    ("PROF", "Profiling timer expired"),
+    ("IO", "I/O possible"),
+    ("PWR", "Power failure"),
+    ("SYS", "Bad system call"),
+];
+
+/// Every signal with its name, in order.
+pub(crate) fn all() -> impl Iterator<Item = (i32, &'static str)> {
+    let mut signals = SIGNALS.to_vec();
+    signals.sort_unstable();
+    signals.into_iter()
+}
+
+/// The number of the signal called `name`, with or without the `SIG` in
+/// front, in any case.
+pub(crate) fn number(name: &str) -> Option<i32> {
+    let name = name.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    SIGNALS
+        .iter()
+        .find(|(_, known)| *known == name)
+        .map(|(number, _)| *number)
+}
+
+/// The name of signal `number`, without the `SIG`.
+pub(crate) fn name(number: i32) -> Option<&'static str> {
+    SIGNALS
+        .iter()
+        .find(|(known, _)| *known == number)
+        .map(|(_, name)| *name)
+}
+
+/// How signal `number` is described when it kills a process, like
+/// `Terminated` for `TERM`.
+pub(crate) fn description(number: i32) -> Option<&'static str> {
+    let name = name(number)?;
+    DESCRIPTIONS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, description)| *description)
+}
+
+/// How a process killed by signal `number` is described, like `Terminated`,
+/// or by its number if it has no description.
+pub(crate) fn describe(number: i32) -> String {
+    description(number).map_or_else(|| format!("Signal {number}"), String::from)
+}
+
+/// Sends `signal` to process `pid`.
+#[cfg(unix)]
+pub(crate) fn send(pid: u32, signal: i32) -> io::Result<()> {
+    let pid = i32::try_from(pid).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    send_raw(pid, signal)
+}
+
+#[cfg(unix)]
+fn send_raw(pid: i32, signal: i32) -> io::Result<()> {
+    // SAFETY: kill only reads its arguments
+    match unsafe { libc::kill(pid, signal) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn send(_pid: u32, _signal: i32) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Sends `signal` to every process in process group `pgid`.
+#[cfg(unix)]
+pub(crate) fn send_group(pgid: u32, signal: i32) -> io::Result<()> {
+    let pgid = i32::try_from(pgid).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // kill sends to a whole group when given its number negated
+    send_raw(-pgid, signal)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn send_group(_pgid: u32, _signal: i32) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Keeps signal `number` from affecting the shell itself, the way an interactive
+/// shell isn't stopped by the `TSTP` that Ctrl-Z sends. Unlike ignoring it,
+/// this leaves it working in the programs the shell runs, since a handler
+/// doesn't outlive `exec`.
+#[cfg(unix)]
+pub(crate) fn catch(number: i32) -> io::Result<()> {
+    extern "C" fn ignore(_signal: std::ffi::c_int) {}
+    // SAFETY: the handler does nothing, which is always async-signal-safe
+    unsafe { install(number, ignore) }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn catch(_number: i32) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Whether each signal being watched has arrived since it was last checked,
+/// by number.
+static ARRIVED: [AtomicBool; 65] = [const { AtomicBool::new(false) }; 65];
+
+/// Starts noting each time signal `number` arrives, for [`arrived`] to
+/// check, rather than letting it do what it would.
+#[cfg(unix)]
+pub(crate) fn watch(number: i32) -> io::Result<()> {
+    extern "C" fn note(signal: std::ffi::c_int) {
+        if let Some(arrived) = usize::try_from(signal).ok().and_then(|i| ARRIVED.get(i)) {
+            arrived.store(true, Ordering::Relaxed);
+        }
+    }
+    // SAFETY: storing to an atomic is async-signal-safe
+    unsafe { install(number, note) }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn watch(_number: i32) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Whether signal `number` has arrived since this was last asked, if it's
+/// being watched.
+pub(crate) fn arrived(number: i32) -> bool {
+    usize::try_from(number)
+        .ok()
+        .and_then(|i| ARRIVED.get(i))
+        .is_some_and(|arrived| arrived.swap(false, Ordering::Relaxed))
+}
+
+/// Where [`forward`]ed signals go: a process, a process group if negated, or
+/// nowhere while it's 0.
+static FORWARD_TO: AtomicI32 = AtomicI32::new(0);
+
+/// The last signal passed on to a program while the shell waited for it,
+/// for the shell to end by once the program has, or 0 if there's none.
+#[cfg(unix)]
+static FORWARDED: AtomicI32 = AtomicI32::new(0);
+
+/// Whether the shell carries on after a signal it passed on, as an
+/// interactive one does, rather than ending by it too.
+static OUTLASTS: AtomicBool = AtomicBool::new(false);
+
+/// Passes signal `number` on to the program the shell is waiting for, if
+/// there is one, the way `TERM` sent to the shell ends what it's running too.
+/// Unless it [`outlast_forwarded`] signals, the shell then ends by it once
+/// the program is gone. Otherwise the signal does to the shell what it would
+/// have.
+#[cfg(unix)]
+pub(crate) fn forward(number: i32) -> io::Result<()> {
+    extern "C" fn pass_on(number: std::ffi::c_int) {
+        // SAFETY: kill, and what end_by uses, are async-signal-safe
+        unsafe {
+            match FORWARD_TO.load(Ordering::Relaxed) {
+                0 => end_by(number),
+                target => {
+                    libc::kill(target, number);
+                    if !OUTLASTS.load(Ordering::Relaxed) {
+                        FORWARDED.store(number, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+    }
+    // SAFETY: see above
+    unsafe { install(number, pass_on) }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn forward(_number: i32) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Keeps the shell going after a signal it [`forward`]s, for an interactive
+/// shell, which only means to end what it's running.
+pub(crate) fn outlast_forwarded() {
+    OUTLASTS.store(true, Ordering::Relaxed);
+}
+
+/// Ends the shell by the signals it [`forward`]s after all, for a copy of
+/// an interactive one running in the background, which has no prompt to
+/// go back to.
+#[cfg(unix)]
+pub(crate) fn end_by_forwarded() {
+    OUTLASTS.store(false, Ordering::Relaxed);
+}
+
+/// Ends the process by signal `number`, the way it would have if nothing
+/// had been done about it. Raised from a handler, it waits until the handler
+/// returns.
+///
+/// # Safety
+///
+/// Only async-signal-safe functions are used, so this is safe in a signal
+/// handler too.
+#[cfg(unix)]
+unsafe fn end_by(number: i32) {
+    // SAFETY: both only take numbers
+    unsafe {
+        libc::signal(number, libc::SIG_DFL);
+        libc::raise(number);
+    }
+}
+
+/// Sends [`forward`]ed signals to a program the shell waits for, until it's
+/// dropped, once the program is done with. Then if one was sent, and there's
+/// nothing else to forward to, the shell ends by it too.
+pub(crate) struct Forwarding {
+    target: i32,
+    previous: i32,
+}
+
+impl Forwarding {
+    /// Forwards signals to process `pid`.
+    pub(crate) fn process(pid: u32) -> Self {
+        Self::to(i32::try_from(pid).unwrap_or(0))
+    }
+
+    /// Forwards signals to every process in process group `pgid`.
+    pub(crate) fn group(pgid: u32) -> Self {
+        Self::to(i32::try_from(pgid).map_or(0, |pgid| -pgid))
+    }
+
+    fn to(target: i32) -> Self {
+        let previous = FORWARD_TO.swap(target, Ordering::Relaxed);
+        Self { target, previous }
+    }
+}
+
+impl Drop for Forwarding {
+    fn drop(&mut self) {
+        // The stages of a pipeline come and go in any order, so one that
+        // took over since is left be
+        let restored = FORWARD_TO
+            .compare_exchange(
+                self.target,
+                self.previous,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok();
+        if restored && self.previous == 0 {
+            #[cfg(unix)]
+            match FORWARDED.swap(0, Ordering::Relaxed) {
+                0 => {}
+                // SAFETY: see end_by
+                number => unsafe { end_by(number) },
+            }
+        }
+    }
+}
+
+/// Runs `handler` whenever signal `number` arrives.
+///
+/// # Safety
+///
+/// The handler can only do what's safe in a signal handler.
+#[cfg(unix)]
+unsafe fn install(number: i32, handler: extern "C" fn(std::ffi::c_int)) -> io::Result<()> {
+    // SAFETY: the caller vouches for the handler
+    match unsafe { libc::signal(number, handler as libc::sighandler_t) } {
+        libc::SIG_ERR => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn names_and_numbers() {
+        assert_eq!(number("TERM"), Some(libc::SIGTERM));
+        assert_eq!(number("sigkill"), Some(libc::SIGKILL));
+        assert_eq!(number("WINCH"), Some(libc::SIGWINCH));
+        assert_eq!(number("NOPE"), None);
+        assert_eq!(name(libc::SIGINT), Some("INT"));
+        assert_eq!(name(0), None);
+        assert_eq!(description(libc::SIGKILL), Some("Killed"));
+        assert_eq!(description(number("TERM").unwrap()), Some("Terminated"));
+        assert_eq!(description(number("CHLD").unwrap()), None);
+        assert_eq!(describe(libc::SIGTERM), "Terminated");
+        assert_eq!(describe(libc::SIGCHLD), format!("Signal {}", libc::SIGCHLD));
+    }
+
+    #[test]
+    fn listed_in_order() {
+        let numbers: Vec<_> = all().map(|(number, _)| number).collect();
+        assert!(numbers.is_sorted());
+        #[cfg(target_os = "linux")]
+        assert_eq!(numbers.len(), 31);
+    }
+}
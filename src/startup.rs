@@ -0,0 +1,334 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    mem,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    command::logical_dir, config, diagnostics, eval::eval, state::ShellState, style::Style, util,
+};
+
+/// Private variable counting how many rush instances are running inside each other.
+const NESTING_DEPTH_VAR: &str = "__RUSH_NESTING_DEPTH";
+/// Private variable naming the rc file a parent shell was sourcing when it
+/// started us, so a runaway nested shell can point at the likely culprit.
+const SOURCING_RC_VAR: &str = "__RUSH_SOURCING_RC";
+/// User-facing override for the nesting threshold.
+const MAX_NESTING_VAR: &str = "RUSH_MAX_NESTING";
+const DEFAULT_MAX_NESTING: u32 = 50;
+
+const RC_FILE: &str = ".rushrc";
+/// What a login shell sources before anything else, system-wide then the
+/// user's own.
+const SYSTEM_PROFILE: &str = "/etc/profile";
+const PROFILE_FILE: &str = ".rush_profile";
+/// What a login shell sources as it exits.
+const LOGOUT_FILE: &str = ".rush_logout";
+
+/// The `SHLVL` past which the count starts over, as in bash.
+const MAX_SHELL_LEVEL: i64 = 1000;
+
+/// How deeply this shell is nested inside other rush instances.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct Nesting {
+    depth: u32,
+    limit: u32,
+    /// The rc file the parent shell was sourcing when it started this one.
+    culprit: Option<String>,
+}
+
+impl Nesting {
+    fn from_vars(depth: Option<&str>, limit: Option<&str>, culprit: Option<&str>) -> Self {
+        let parent_depth = depth.and_then(|d| d.trim().parse::<u32>().ok());
+        Self {
+            depth: parent_depth.unwrap_or(0).saturating_add(1),
+            limit: limit
+                .and_then(|l| l.trim().parse().ok())
+                .unwrap_or(DEFAULT_MAX_NESTING),
+            culprit: culprit.filter(|c| !c.is_empty()).map(Into::into),
+        }
+    }
+
+    fn detect(state: &ShellState) -> Self {
+        let var = |name| state.vars.get(name);
+        Self::from_vars(
+            var(NESTING_DEPTH_VAR).as_deref(),
+            var(MAX_NESTING_VAR).as_deref(),
+            var(SOURCING_RC_VAR).as_deref(),
+        )
+    }
+
+    fn is_runaway(&self) -> bool {
+        self.depth > self.limit
+    }
+
+    fn warning(&self, style: Style) -> String {
+        let prefix = style.red("rush: warning:");
+        let culprit = match &self.culprit {
+            Some(rc) => format!("{rc} appears to start a new shell unconditionally"),
+            None => "a startup file or alias appears to start a new shell unconditionally".into(),
+        };
+
+        format!(
+            "{prefix} {}\n{prefix} {culprit}\n{prefix} {}\n",
+            style.bold(&format!(
+                "shell nesting depth {} exceeds the limit of {} (set {MAX_NESTING_VAR} to change it)",
+                self.depth, self.limit
+            )),
+            "skipping startup files; fix your configuration, then start a new shell",
+        )
+    }
+}
+
+/// Prepares the shell before the first prompt: tracks how deeply rush is
+/// nested, loads the config file, and sources the rc file if `rc` says to,
+/// unless nesting has run away, in which case the user is dropped at a bare
+/// prompt so they can repair their config.
+pub(crate) fn initialize(state: &mut ShellState, rc: bool) {
+    let nesting = Nesting::detect(state);
+    (state.vars).export(NESTING_DEPTH_VAR, Some(&nesting.depth.to_string()));
+    state.vars.unset(SOURCING_RC_VAR);
+
+    if nesting.is_runaway() {
+        eprint!("{}", nesting.warning(Style::stderr()));
+        return;
+    }
+
+    let errors = config::load(state);
+    if !errors.is_empty() {
+        let path = config::config_path(state).unwrap_or_default();
+        for error in errors {
+            eprintln!("rush: {}: {error}", path.display());
+        }
+    }
+
+    if let Some(rc) = home_file(state, RC_FILE).filter(|path| rc && path.is_file()) {
+        source_rc(state, &rc);
+    }
+}
+
+/// Restricts the shell, for accounts meant to run only what's on the
+/// `PATH` they're given. `PATH`, and the variables naming what other shells
+/// run, become read-only.
+pub(crate) fn restrict(state: &mut ShellState) {
+    state.restricted = true;
+    for name in ["PATH", "SHELL", "ENV"] {
+        state.vars.attributes_mut(name).readonly = true;
+    }
+}
+
+/// Makes this a login shell, sourcing the profile files, unless nesting has
+/// run away, which [`initialize`] warns about for an interactive shell.
+pub(crate) fn login(state: &mut ShellState) {
+    state.login = true;
+    if Nesting::detect(state).is_runaway() {
+        return;
+    }
+    let profiles = [
+        Some(PathBuf::from(SYSTEM_PROFILE)),
+        home_file(state, PROFILE_FILE),
+    ];
+    for profile in profiles.into_iter().flatten().filter(|path| path.is_file()) {
+        source_rc(state, &profile);
+    }
+}
+
+/// Sources the logout file as a login shell exits. It's only run once, even
+/// if it runs `exit` itself.
+pub(crate) fn logout(state: &mut ShellState) {
+    if !mem::take(&mut state.login) {
+        return;
+    }
+    if let Some(path) = home_file(state, LOGOUT_FILE).filter(|path| path.is_file())
+        && let Err(error) = source_file(state, &path)
+    {
+        eprintln!("rush: {}: {error}", path.display());
+    }
+}
+
+/// Sets up the variables every shell keeps for the programs it starts,
+/// whether it's interactive or runs a script: `PWD`, naming the current
+/// directory, and `SHLVL`, counting this shell as one level inside the shell
+/// that started it.
+pub(crate) fn export_environment(state: &mut ShellState) {
+    // An inherited `PWD` is kept only if it still leads here
+    let dir = logical_dir(state);
+    state.vars.export("PWD", Some(&dir.to_string_lossy()));
+
+    let mut level = shell_level(state.vars.get("SHLVL").as_deref());
+    if level >= MAX_SHELL_LEVEL {
+        eprintln!("rush: warning: shell level ({level}) too high, resetting to 1");
+        level = 1;
+    }
+    state.vars.export("SHLVL", Some(&level.to_string()));
+}
+
+/// The `SHLVL` of a shell started from one whose `SHLVL` is `parent`. One
+/// that isn't a count is taken as no shell at all.
+fn shell_level(parent: Option<&str>) -> i64 {
+    let parent = parent.and_then(|level| level.trim().parse::<i64>().ok());
+    parent.unwrap_or(0).max(0) + 1
+}
+
+/// The file called `name` in the directory the shell's `HOME` names.
+fn home_file(state: &ShellState, name: &str) -> Option<PathBuf> {
+    let home = state.vars.get("HOME").filter(|home| !home.is_empty())?;
+    Some(PathBuf::from(home).join(name))
+}
+
+/// Sources `rc`, advertising it to any shells it starts.
+fn source_rc(state: &mut ShellState, rc: &Path) {
+    (state.vars).export(SOURCING_RC_VAR, Some(&rc.to_string_lossy()));
+
+    if let Err(error) = source_file(state, rc) {
+        eprintln!("rush: {}: {error}", rc.display());
+    }
+
+    state.vars.unset(SOURCING_RC_VAR);
+}
+
+/// Runs the commands in `path` in turn, reporting failures as they happen.
+/// A command carries on over as many lines as it needs, as at the prompt.
+pub(crate) fn source_file(state: &mut ShellState, path: &Path) -> io::Result<()> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut command = String::new();
+    let mut first_line = 1;
+
+    for (i, line) in reader.lines().enumerate() {
+        if command.is_empty() {
+            first_line = i + 1;
+        }
+        command.push_str(&line?);
+        command.push('\n');
+        if util::is_incomplete(&command) {
+            continue;
+        }
+        state.vars.set_line(first_line);
+        if let Err(error) = eval(&mem::take(&mut command), state) {
+            diagnostics::report(error, state);
+        }
+        if state.exited.is_some() {
+            return Ok(());
+        }
+    }
+
+    // What's left never finished, which running it reports
+    if !command.is_empty() {
+        state.vars.set_line(first_line);
+        if let Err(error) = eval(&command, state) {
+            diagnostics::report(error, state);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_level_shell_has_depth_one() {
+        let nesting = Nesting::from_vars(None, None, None);
+        assert_eq!(nesting.depth, 1);
+        assert_eq!(nesting.limit, DEFAULT_MAX_NESTING);
+        assert!(!nesting.is_runaway());
+    }
+
+    #[test]
+    fn depth_increments_from_parent() {
+        let nesting = Nesting::from_vars(Some("7"), None, None);
+        assert_eq!(nesting.depth, 8);
+    }
+
+    #[test]
+    fn garbage_depth_is_treated_as_top_level() {
+        let nesting = Nesting::from_vars(Some("lots"), Some("many"), None);
+        assert_eq!(nesting.depth, 1);
+        assert_eq!(nesting.limit, DEFAULT_MAX_NESTING);
+    }
+
+    #[test]
+    fn runaway_past_the_limit() {
+        assert!(!Nesting::from_vars(Some("2"), Some("3"), None).is_runaway());
+        assert!(Nesting::from_vars(Some("3"), Some("3"), None).is_runaway());
+        assert!(Nesting::from_vars(Some("50"), None, None).is_runaway());
+    }
+
+    #[test]
+    fn warning_names_the_suspected_rc_file() {
+        let nesting = Nesting::from_vars(Some("3"), Some("3"), Some("/home/me/.rushrc"));
+        let warning = nesting.warning(Style::plain());
+        assert!(warning.contains("nesting depth 4 exceeds the limit of 3"));
+        assert!(warning.contains("/home/me/.rushrc appears to start a new shell"));
+        assert!(warning.contains("skipping startup files"));
+    }
+
+    #[test]
+    fn warning_without_known_rc_file() {
+        let nesting = Nesting::from_vars(Some("60"), None, Some(""));
+        let warning = nesting.warning(Style::plain());
+        assert!(warning.contains("a startup file or alias appears"));
+    }
+
+    #[test]
+    fn shell_level_counts_up_from_the_parent() {
+        assert_eq!(shell_level(None), 1);
+        assert_eq!(shell_level(Some("2")), 3);
+        assert_eq!(shell_level(Some("-5")), 1);
+        assert_eq!(shell_level(Some("deep")), 1);
+    }
+
+    #[test]
+    fn home_files_follow_the_shells_home() {
+        let mut state = ShellState::default();
+        state.vars.set("HOME", "/x");
+        assert_eq!(
+            home_file(&state, RC_FILE),
+            Some(Path::new("/x").join(RC_FILE))
+        );
+        state.vars.set("HOME", "");
+        assert_eq!(home_file(&state, RC_FILE), None);
+    }
+
+    #[test]
+    fn source_file_runs_each_line() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("rush_source_test_{}", std::process::id()));
+        let out = dir.join(format!("rush_source_test_{}.out", std::process::id()));
+        let out_name = out.display();
+        let script = format!(
+            "x=1\n\
+             echo one > {out_name}\n\
+             \n   \n\
+             nonexistent_cmd_4821\n\
+             y=2\n\
+             if true; then\n  z=3\nfi\n\
+             echo two >> {out_name}\n"
+        );
+        std::fs::write(&path, script).unwrap();
+
+        let mut state = ShellState::default();
+        let result = source_file(&mut state, &path);
+        let written = std::fs::read_to_string(&out);
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(&out).ok();
+
+        assert!(result.is_ok());
+        assert_eq!(state.vars.get("x").as_deref(), Some("1"));
+        assert_eq!(state.vars.get("y").as_deref(), Some("2"));
+        assert_eq!(state.vars.get("z").as_deref(), Some("3"));
+        assert_eq!(written.unwrap(), "one\ntwo\n");
+    }
+
+    #[test]
+    fn source_missing_file_fails() {
+        assert!(
+            source_file(
+                &mut ShellState::default(),
+                Path::new("/nonexistent/rush/rc")
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    aliases::Aliases,
+    command::path::PathCache,
+    editor::{completion::Completions, keymap::Keymap},
+    functions::Functions,
+    jobs::{Jobs, ProcessGroup},
+    stats::Stats,
+    status::ExitStatus,
+    variables::Variables,
+};
+
+/// Everything the shell remembers from one command to the next.
+#[derive(Debug, Default)]
+pub(crate) struct ShellState {
+    pub aliases: Aliases,
+    pub completions: Completions,
+    pub functions: Functions,
+    pub hash: PathCache,
+    pub hooks: Hooks,
+    pub jobs: Jobs,
+    pub keymap: Keymap,
+    pub limits: Limits,
+    pub options: Options,
+    pub stats: Stats,
+    pub vars: Variables,
+    pub cwd: WorkingDir,
+    /// The positional parameters `$1`, `$2`, and so on, bound for a script
+    /// or function.
+    pub args: Vec<String>,
+    /// The directories `pushd` saved, most recent first. The current
+    /// directory is the top of the stack, so it isn't kept here.
+    pub dirs: Vec<PathBuf>,
+    /// The status of the most recently run command.
+    pub last_status: ExitStatus,
+    /// How long the most recently run command line took on the clock.
+    pub last_duration: Duration,
+    /// Whether this is a copy of the shell running one side of a pipeline
+    /// or a command substitution, which `exit` ends instead of the shell.
+    pub subshell: bool,
+    /// Whether this is a login shell, which sources the logout file as it
+    /// exits.
+    pub login: bool,
+    /// Whether the shell is restricted, as with `rush -r`: it can't change
+    /// directory, `PATH`, or the files it writes to, or run a program other
+    /// than by looking it up on `PATH`.
+    pub restricted: bool,
+    /// Whether the shell reads commands typed at a prompt, rather than from
+    /// a script.
+    pub interactive: bool,
+    /// Whether the last command line tried to exit but was warned about
+    /// jobs instead, so exiting again goes ahead.
+    pub exit_warned: bool,
+    /// The status the shell exited with, once `exit` or the end of input
+    /// ends it. Whatever was running unwinds, and nothing more is read.
+    pub exited: Option<ExitStatus>,
+    /// How many loops are running around the current command, which
+    /// `break` and `continue` can reach out through.
+    pub loops: usize,
+    /// How many places around the current command `set -e` lets fail, like
+    /// the condition of an `if` or the left of `&&`.
+    pub errexit_ignored: usize,
+    /// How many command substitutions the current command runs inside.
+    pub substitutions: usize,
+    /// The status of the last command substitution run, which a command of
+    /// nothing but assignments takes as its own.
+    pub substituted: Option<ExitStatus>,
+    /// The statuses of all but the last command of the pipeline that just
+    /// ran, which go ahead of its own status in `PIPESTATUS`.
+    pub piped: Vec<ExitStatus>,
+    /// How many lines of input the shell has read, which numbers the line
+    /// each command starts on.
+    pub lines_read: usize,
+    /// Whether the command running changed directory, so the `chpwd` hook
+    /// runs once it finishes.
+    pub dir_changed: bool,
+    /// Whether the `chpwd` hook is running, in which changing directory
+    /// doesn't run it again.
+    pub in_chpwd: bool,
+    /// Whether a command that couldn't be found is being handled, in which
+    /// another that can't be found is only reported.
+    pub in_not_found: bool,
+    /// The process group of the pipeline the current command is part of,
+    /// which the programs it starts join.
+    pub group: Option<ProcessGroup>,
+}
+
+impl ShellState {
+    /// A copy of the shell to run a subshell in, like one side of a pipeline
+    /// or a command substitution, which has the shell's jobs only as a
+    /// [`Jobs::snapshot`].
+    pub(crate) fn subshell(&self) -> Self {
+        Self {
+            aliases: self.aliases.clone(),
+            completions: self.completions.clone(),
+            functions: self.functions.clone(),
+            hash: self.hash.clone(),
+            hooks: self.hooks.clone(),
+            jobs: self.jobs.snapshot(),
+            keymap: self.keymap.clone(),
+            limits: self.limits.clone(),
+            options: self.options.clone(),
+            stats: self.stats.clone(),
+            vars: self.vars.clone(),
+            cwd: self.cwd.clone(),
+            args: self.args.clone(),
+            dirs: self.dirs.clone(),
+            last_status: self.last_status,
+            last_duration: self.last_duration,
+            subshell: true,
+            login: self.login,
+            restricted: self.restricted,
+            interactive: self.interactive,
+            exit_warned: self.exit_warned,
+            exited: self.exited,
+            loops: self.loops,
+            errexit_ignored: self.errexit_ignored,
+            substitutions: self.substitutions,
+            substituted: self.substituted,
+            piped: self.piped.clone(),
+            lines_read: self.lines_read,
+            dir_changed: self.dir_changed,
+            in_chpwd: self.in_chpwd,
+            in_not_found: self.in_not_found,
+            group: self.group.clone(),
+        }
+    }
+}
+
+/// The directory the shell is in, which relative paths are taken from and
+/// the programs it starts run in. The process's own current directory is
+/// left where it was, so each copy of the shell, like a subshell, can `cd`
+/// without moving the shell it was copied from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct WorkingDir(PathBuf);
+
+impl WorkingDir {
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// `path` as seen from this directory, which leaves an absolute one as
+    /// it is.
+    pub(crate) fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// Moves to `dir`, which the caller has made sure can be entered.
+    pub(crate) fn set(&mut self, dir: PathBuf) {
+        self.0 = dir;
+    }
+}
+
+/// A shell starts out in the process's current directory, or if that's
+/// gone, wherever `.` still leads.
+impl Default for WorkingDir {
+    fn default() -> Self {
+        Self(env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
+    }
+}
+
+/// Programs the config file has the shell run when something happens.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Hooks {
+    /// What runs in place of a command that can't be found, given its name
+    /// and arguments, unless a `command_not_found_handle` function is
+    /// defined.
+    pub command_not_found: Option<String>,
+}
+
+/// Resource caps that protect the shell from runaway commands.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Limits {
+    /// The most output a single command substitution may capture.
+    pub substitution_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            substitution_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// Switches that change how the shell behaves, like bash's `shopt` options.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Options {
+    /// Whether a command that names a directory changes to it, like `cd`.
+    pub autocd: bool,
+    /// Whether every category of the debug trace is shown, as if
+    /// `$RUSH_DEBUG` were `all`.
+    pub debug: bool,
+    /// Whether a command that fails, outside a condition, ends the shell.
+    pub errexit: bool,
+    /// Whether `**` in a pattern matches any number of directories.
+    pub globstar: bool,
+    /// Whether the history file is added to on exit rather than replaced.
+    pub histappend: bool,
+    /// Whether the jobs still running when the shell exits are hung up on.
+    pub huponexit: bool,
+    /// Whether Ctrl-D on an empty line leaves an interactive shell open.
+    pub ignoreeof: bool,
+    /// Whether patterns match file names regardless of case.
+    pub nocaseglob: bool,
+    /// Whether `>` refuses to overwrite a file that already exists.
+    pub noclobber: bool,
+    /// Whether each command is shown on stderr, after `$PS4`, before it runs.
+    pub xtrace: bool,
+}
@@ -0,0 +1,107 @@
+use std::{collections::HashMap, time::Duration};
+
+/// Per-command usage counters for the current session.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Stats {
+    commands: HashMap<String, Usage>,
+}
+
+/// How often a command ran and how long it kept the shell busy in total.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Usage {
+    pub runs: u64,
+    pub time: Duration,
+}
+
+impl Stats {
+    /// Counts one run of `name` that took `elapsed` in the foreground.
+    pub(crate) fn record(&mut self, name: &str, elapsed: Duration) {
+        let usage = match self.commands.get_mut(name) {
+            Some(usage) => usage,
+            None => self.commands.entry(name.to_owned()).or_default(),
+        };
+        usage.runs += 1;
+        usage.time += elapsed;
+    }
+
+    #[cfg(test)]
+    pub(crate) fn get(&self, name: &str) -> Option<Usage> {
+        self.commands.get(name).copied()
+    }
+
+    /// Returns the `n` most frequently run commands, most frequent first.
+    pub(crate) fn top_by_runs(&self, n: usize) -> Vec<(&str, Usage)> {
+        self.top(n, |usage| usage.runs)
+    }
+
+    /// Returns the `n` commands with the most cumulative time, longest first.
+    pub(crate) fn top_by_time(&self, n: usize) -> Vec<(&str, Usage)> {
+        self.top(n, |usage| usage.time)
+    }
+
+    fn top<K: Ord>(&self, n: usize, key: impl Fn(&Usage) -> K) -> Vec<(&str, Usage)> {
+        let mut entries: Vec<_> = self
+            .commands
+            .iter()
+            .map(|(name, usage)| (name.as_str(), *usage))
+            .collect();
+
+        // Ties are broken by name so the output is stable between calls
+        entries.sort_by(|(a_name, a), (b_name, b)| {
+            key(b).cmp(&key(a)).then_with(|| a_name.cmp(b_name))
+        });
+        entries.truncate(n);
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn record_accumulates() {
+        let mut stats = Stats::default();
+        stats.record("ls", ms(5));
+        stats.record("ls", ms(7));
+
+        assert_eq!(
+            stats.get("ls"),
+            Some(Usage {
+                runs: 2,
+                time: ms(12)
+            })
+        );
+        assert_eq!(stats.get("cd"), None);
+    }
+
+    #[test]
+    fn top_by_runs_orders_and_truncates() {
+        let mut stats = Stats::default();
+        for (name, times) in [("git", 3), ("ls", 5), ("cd", 3), ("make", 1)] {
+            for _ in 0..times {
+                stats.record(name, ms(1));
+            }
+        }
+
+        let names: Vec<_> = stats.top_by_runs(3).iter().map(|(n, _)| *n).collect();
+        assert_eq!(names, vec!["ls", "cd", "git"]);
+    }
+
+    #[test]
+    fn top_by_time_orders_by_cumulative_time() {
+        let mut stats = Stats::default();
+        stats.record("make", ms(900));
+        stats.record("ls", ms(10));
+        stats.record("ls", ms(10));
+        stats.record("cargo", ms(400));
+        stats.record("cargo", ms(400));
+
+        let names: Vec<_> = stats.top_by_time(10).iter().map(|(n, _)| *n).collect();
+        assert_eq!(names, vec!["make", "cargo", "ls"]);
+    }
+}
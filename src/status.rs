@@ -0,0 +1,128 @@
+use std::{fmt, io, process};
+
+/// A command's exit status as the user sees it, always in `0..=255`.
+///
+/// Every producer of a status goes through one of the constructors here, so
+/// raw values like errno numbers, `exit(300)`, or signal deaths are folded
+/// into shell conventions before anything can observe them.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct ExitStatus(u8);
+
+impl ExitStatus {
+    pub const SUCCESS: Self = Self(0);
+    pub const FAILURE: Self = Self(1);
+    /// A builtin was invoked incorrectly, or the line failed to parse.
+    pub const USAGE: Self = Self(2);
+    /// The command was found but couldn't be executed.
+    pub const NOT_EXECUTABLE: Self = Self(126);
+    /// The command wasn't found.
+    pub const NOT_FOUND: Self = Self(127);
+
+    /// Folds an arbitrary exit code into `0..=255` the way `exit` does,
+    /// keeping only the low 8 bits: `exit(256)` is 0 and `exit(-1)` is 255.
+    pub(crate) fn from_code(code: i32) -> Self {
+        Self(code as u8)
+    }
+
+    /// The status of a process killed by `signal`: 128 plus the signal number.
+    pub(crate) fn from_signal(signal: i32) -> Self {
+        Self::from_code(128 + signal)
+    }
+
+    /// The status of a command stopped by Ctrl-Z, which sends `TSTP`.
+    pub(crate) fn stopped() -> Self {
+        Self::from_signal(crate::signals::number("TSTP").unwrap_or(20))
+    }
+
+    /// The status of a command that wrote to a pipe nobody reads from, as
+    /// if `PIPE` had killed it.
+    pub(crate) fn broken_pipe() -> Self {
+        Self::from_signal(crate::signals::number("PIPE").unwrap_or(13))
+    }
+
+    /// The status for a command that couldn't be started because of `error`.
+    pub(crate) fn from_spawn_error(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::NotFound => Self::NOT_FOUND,
+            _ => Self::NOT_EXECUTABLE,
+        }
+    }
+
+    pub fn code(self) -> i32 {
+        self.0.into()
+    }
+}
+
+impl From<process::ExitStatus> for ExitStatus {
+    fn from(status: process::ExitStatus) -> Self {
+        if let Some(code) = status.code() {
+            return Self::from_code(code);
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = status.signal() {
+                return Self::from_signal(signal);
+            }
+        }
+
+        Self::FAILURE
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_wrap_into_a_byte() {
+        for (raw, expected) in [
+            (0, 0),
+            (1, 1),
+            (42, 42),
+            (255, 255),
+            (256, 0),
+            (300, 44),
+            (-1, 255),
+            (-256, 0),
+        ] {
+            assert_eq!(ExitStatus::from_code(raw).code(), expected, "exit({raw})");
+        }
+    }
+
+    #[test]
+    fn signals_are_offset_by_128() {
+        // SIGINT, SIGKILL, SIGTERM
+        for (signal, expected) in [(2, 130), (9, 137), (15, 143)] {
+            assert_eq!(ExitStatus::from_signal(signal).code(), expected);
+        }
+    }
+
+    #[test]
+    fn spawn_errors_use_reserved_statuses() {
+        // errno 2 is ENOENT and 13 is EACCES; neither leaks through as-is
+        let not_found = io::Error::from_raw_os_error(2);
+        let denied = io::Error::from_raw_os_error(13);
+        assert_eq!(ExitStatus::from_spawn_error(&not_found).code(), 127);
+        assert_eq!(ExitStatus::from_spawn_error(&denied).code(), 126);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_process_status() {
+        use std::os::unix::process::ExitStatusExt;
+
+        // Raw wait statuses: exit code in the high byte, signal in the low bits
+        for (raw, expected) in [(0, 0), (3 << 8, 3), (255 << 8, 255), (9, 137), (15, 143)] {
+            let status = process::ExitStatus::from_raw(raw);
+            assert_eq!(ExitStatus::from(status).code(), expected, "raw {raw:#x}");
+        }
+    }
+}
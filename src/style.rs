@@ -0,0 +1,223 @@
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
+
+/// ANSI styling for text written to the terminal. Styling is disabled when the
+/// destination isn't a terminal or when `NO_COLOR` is set, in which case every
+/// helper returns its input unchanged.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Style {
+    enabled: bool,
+}
+
+impl Style {
+    pub(crate) fn stderr() -> Self {
+        Self {
+            enabled: io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        }
+    }
+
+    pub(crate) fn stdout() -> Self {
+        Self {
+            enabled: io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        }
+    }
+
+    #[cfg(any(test, feature = "fuzzing"))]
+    pub(crate) fn plain() -> Self {
+        Self { enabled: false }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn colored() -> Self {
+        Self { enabled: true }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_owned()
+        }
+    }
+
+    pub(crate) fn bold(&self, text: &str) -> String {
+        self.paint("1", text)
+    }
+
+    pub(crate) fn red(&self, text: &str) -> String {
+        self.paint("1;31", text)
+    }
+
+    pub(crate) fn cyan(&self, text: &str) -> String {
+        self.paint("36", text)
+    }
+
+    pub(crate) fn green(&self, text: &str) -> String {
+        self.paint("32", text)
+    }
+
+    pub(crate) fn yellow(&self, text: &str) -> String {
+        self.paint("33", text)
+    }
+
+    pub(crate) fn gray(&self, text: &str) -> String {
+        self.paint("90", text)
+    }
+}
+
+/// Returns the number of terminal columns `text` occupies. Escape sequences
+/// like the ones [`Style`] writes take up none.
+pub(crate) fn display_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // A CSI sequence runs to its final byte, in `@` to `~`
+            if chars.next() == Some('[') {
+                chars.find(|c| ('@'..='~').contains(c));
+            }
+            continue;
+        }
+        width += char_width(c);
+    }
+    width
+}
+
+/// Lays out `rows` as left-aligned columns separated by two spaces. Columns
+/// are sized by display width so wide characters still line up, and the last
+/// column isn't padded.
+pub(crate) fn table(rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            let width = display_width(cell);
+            match widths.get_mut(i) {
+                Some(max) => *max = (*max).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for row in rows {
+        let mut line = String::new();
+        for (i, cell) in row.iter().enumerate() {
+            if i > 0 {
+                line.push_str("  ");
+            }
+            line.push_str(cell);
+            if i + 1 < row.len() {
+                line.push_str(&" ".repeat(widths[i] - display_width(cell)));
+            }
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+/// Returns the number of terminal columns `c` occupies: 0 for combining marks
+/// and zero-width characters, 2 for East Asian wide characters and emoji, and
+/// 1 for everything else.
+pub(crate) fn char_width(c: char) -> usize {
+    const ZERO_WIDTH: &[(u32, u32)] = &[
+        (0x0300, 0x036F),
+        (0x1AB0, 0x1AFF),
+        (0x1DC0, 0x1DFF),
+        (0x200B, 0x200F),
+        (0x20D0, 0x20FF),
+        (0xFE00, 0xFE0F),
+        (0xFE20, 0xFE2F),
+    ];
+    const WIDE: &[(u32, u32)] = &[
+        (0x1100, 0x115F),
+        (0x2E80, 0x303E),
+        (0x3041, 0x33FF),
+        (0x3400, 0x4DBF),
+        (0x4E00, 0x9FFF),
+        (0xA000, 0xA4CF),
+        (0xAC00, 0xD7A3),
+        (0xF900, 0xFAFF),
+        (0xFE30, 0xFE4F),
+        (0xFF00, 0xFF60),
+        (0xFFE0, 0xFFE6),
+        (0x1F300, 0x1F64F),
+        (0x1F900, 0x1F9FF),
+        (0x20000, 0x2FFFD),
+        (0x30000, 0x3FFFD),
+    ];
+
+    let in_ranges = |ranges: &[(u32, u32)]| {
+        ranges
+            .iter()
+            .any(|&(start, end)| (start..=end).contains(&(c as u32)))
+    };
+
+    if c.is_control() || in_ranges(ZERO_WIDTH) {
+        0
+    } else if in_ranges(WIDE) {
+        2
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_width() {
+        assert_eq!(display_width("echo hello"), 10);
+        assert_eq!(display_width(""), 0);
+    }
+
+    #[test]
+    fn wide_characters() {
+        assert_eq!(display_width("日本"), 4);
+        assert_eq!(display_width("한글"), 4);
+        assert_eq!(display_width("🦀"), 2);
+    }
+
+    #[test]
+    fn zero_width_characters() {
+        // 'e' followed by a combining acute accent renders as one column
+        assert_eq!(display_width("e\u{301}"), 1);
+        assert_eq!(display_width("a\u{200B}b"), 2);
+    }
+
+    #[test]
+    fn escape_sequences_take_no_room() {
+        assert_eq!(display_width("\x1b[1;31mx\x1b[0m"), 1);
+        assert_eq!(display_width("\x1b[32m日本\x1b[0m$ "), 6);
+    }
+
+    #[test]
+    fn table_aligns_columns() {
+        let rows = vec![
+            vec!["name".into(), "runs".into(), "time".into()],
+            vec!["日本".into(), "3".into(), "0.100s".into()],
+            vec!["ls".into(), "12".into(), "0.002s".into()],
+        ];
+        assert_eq!(
+            table(&rows),
+            "name  runs  time\n日本  3     0.100s\nls    12    0.002s\n"
+        );
+    }
+
+    #[test]
+    fn plain_style_is_passthrough() {
+        let style = Style::plain();
+        assert_eq!(style.red("x"), "x");
+        assert_eq!(style.bold("x"), "x");
+        assert_eq!(style.cyan("x"), "x");
+    }
+
+    #[test]
+    fn enabled_style_wraps_in_escapes() {
+        let style = Style { enabled: true };
+        assert_eq!(style.red("x"), "\x1b[1;31mx\x1b[0m");
+    }
+}
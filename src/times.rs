@@ -0,0 +1,151 @@
+use std::time::{Duration, Instant};
+
+/// How `time` shows what it measured.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum TimeFormat {
+    /// Minutes and seconds to the millisecond, after a blank line.
+    Default,
+    /// Seconds to the hundredth, as `time -p` shows them.
+    Posix,
+}
+
+/// The time a pipeline took: on the clock, and on the CPU in user and
+/// kernel mode, counting the shell and every child it waited for.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Times {
+    pub real: Duration,
+    pub user: Duration,
+    pub sys: Duration,
+}
+
+/// Measures from when it was started until [`Timer::stop`].
+pub(crate) struct Timer {
+    started: Instant,
+    cpu: (Duration, Duration),
+}
+
+impl Timer {
+    pub(crate) fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            cpu: cpu_times(),
+        }
+    }
+
+    pub(crate) fn stop(&self) -> Times {
+        let (user, sys) = cpu_times();
+        Times {
+            real: self.started.elapsed(),
+            user: user.saturating_sub(self.cpu.0),
+            sys: sys.saturating_sub(self.cpu.1),
+        }
+    }
+}
+
+impl Times {
+    /// The report `time` writes to standard error.
+    pub(crate) fn report(&self, format: TimeFormat) -> String {
+        let lines = [("real", self.real), ("user", self.user), ("sys", self.sys)];
+        match format {
+            TimeFormat::Default => lines
+                .iter()
+                .map(|(name, time)| {
+                    let millis = time.as_millis();
+                    let (minutes, millis) = (millis / 60_000, millis % 60_000);
+                    format!(
+                        "{name}\t{minutes}m{}.{:03}s\n",
+                        millis / 1000,
+                        millis % 1000
+                    )
+                })
+                .fold("\n".to_owned(), |report, line| report + &line),
+            TimeFormat::Posix => lines
+                .iter()
+                .map(|(name, time)| {
+                    let hundredths = time.as_millis() / 10;
+                    format!("{name} {}.{:02}\n", hundredths / 100, hundredths % 100)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A short form of `time` for people to read, like `3.2s` or `1h2m5s`,
+/// to the tenth of a second under a minute and to the second after that.
+pub(crate) fn brief(time: Duration) -> String {
+    if time < Duration::from_secs(60) {
+        return format!("{:.1}s", time.as_secs_f64());
+    }
+    let secs = time.as_secs();
+    let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    match hours {
+        0 => format!("{minutes}m{secs}s"),
+        _ => format!("{hours}h{minutes}m{secs}s"),
+    }
+}
+
+/// The user and system CPU time used so far by the shell and the children
+/// it has waited for.
+#[cfg(unix)]
+fn cpu_times() -> (Duration, Duration) {
+    let duration = |time: &libc::timeval| {
+        Duration::from_secs(u64::try_from(time.tv_sec).unwrap_or(0))
+            + Duration::from_micros(u64::try_from(time.tv_usec).unwrap_or(0))
+    };
+    let (mut user, mut sys) = (Duration::ZERO, Duration::ZERO);
+    for who in [libc::RUSAGE_SELF, libc::RUSAGE_CHILDREN] {
+        // SAFETY: an rusage is plain data, which getrusage fills in
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(who, &mut usage) } == 0 {
+            user += duration(&usage.ru_utime);
+            sys += duration(&usage.ru_stime);
+        }
+    }
+    (user, sys)
+}
+
+#[cfg(not(unix))]
+fn cpu_times() -> (Duration, Duration) {
+    (Duration::ZERO, Duration::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports() {
+        let times = Times {
+            real: Duration::from_millis(61_234),
+            user: Duration::from_millis(5),
+            sys: Duration::ZERO,
+        };
+        assert_eq!(
+            times.report(TimeFormat::Default),
+            "\nreal\t1m1.234s\nuser\t0m0.005s\nsys\t0m0.000s\n"
+        );
+        assert_eq!(
+            times.report(TimeFormat::Posix),
+            "real 61.23\nuser 0.00\nsys 0.00\n"
+        );
+    }
+
+    #[test]
+    fn brief_durations() {
+        assert_eq!(brief(Duration::from_millis(3_240)), "3.2s");
+        assert_eq!(brief(Duration::from_millis(59_900)), "59.9s");
+        assert_eq!(brief(Duration::from_secs(65)), "1m5s");
+        assert_eq!(brief(Duration::from_secs(3_725)), "1h2m5s");
+    }
+
+    #[test]
+    fn counts_children() {
+        let timer = Timer::start();
+        std::process::Command::new("sh")
+            .args(["-c", "i=0; while [ $i -lt 100000 ]; do i=$((i+1)); done"])
+            .status()
+            .unwrap();
+        let times = timer.stop();
+        assert!(times.user + times.sys > Duration::ZERO);
+    }
+}
@@ -0,0 +1,191 @@
+use std::{fs::OpenOptions, io::Write};
+
+use crate::{
+    command::{Command, CommandType},
+    lexer::Lexer,
+    parser::AndOrList,
+    state::ShellState,
+};
+
+/// What the debug trace can show, each turned on by naming it in
+/// `$RUSH_DEBUG`, as in `RUSH_DEBUG=parse,exec`, or all at once with `all`
+/// or `set -o debug`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Category {
+    /// The tokens a line is read as, and the commands parsed from them.
+    Parse,
+    /// The words, assignments, and redirections of a command once expanded.
+    Expand,
+    /// What runs each command: a builtin, a function, or a program.
+    Exec,
+}
+
+impl Category {
+    fn name(self) -> &'static str {
+        match self {
+            Category::Parse => "parse",
+            Category::Expand => "expand",
+            Category::Exec => "exec",
+        }
+    }
+}
+
+/// Whether `category` is being traced.
+pub(crate) fn enabled(category: Category, state: &ShellState) -> bool {
+    state.options.debug
+        || state.vars.get("RUSH_DEBUG").is_some_and(|names| {
+            names
+                .split(',')
+                .map(str::trim)
+                .any(|name| name == "all" || name == category.name())
+        })
+}
+
+/// Shows the tokens of `line` and the lists parsed from it. The tokens are
+/// those of the line as typed, before any alias in it is expanded.
+pub(crate) fn parse(line: &str, lists: &[AndOrList], state: &ShellState) {
+    if !enabled(Category::Parse, state) {
+        return;
+    }
+    let mut lexer = Lexer::at(line, 0);
+    loop {
+        match lexer.next_token() {
+            Ok(Some(token)) => log(
+                Category::Parse,
+                &format!("token {:?} {:?}", &line[token.span], token.kind),
+                state,
+            ),
+            Ok(None) => break,
+            Err(error) => {
+                log(Category::Parse, &format!("token error: {error}"), state);
+                break;
+            }
+        }
+    }
+    for list in lists {
+        log(Category::Parse, &format!("list {list:?}"), state);
+    }
+}
+
+/// Shows what a simple command expanded into.
+pub(crate) fn expansion(command: &Command, state: &ShellState) {
+    if !enabled(Category::Expand, state) {
+        return;
+    }
+    for (name, value) in &command.env {
+        log(Category::Expand, &format!("assign {name}={value:?}"), state);
+    }
+    log(
+        Category::Expand,
+        &format!("words {:?}", command.args),
+        state,
+    );
+    for redirection in &command.redirections {
+        let message = format!(
+            "redirect {}{} {:?}",
+            redirection.fd,
+            redirection.kind.as_str(),
+            redirection.path
+        );
+        log(Category::Expand, &message, state);
+    }
+}
+
+/// Shows what is about to run `command`, and whether the shell waits for it.
+pub(crate) fn exec(command: &Command, background: bool, state: &ShellState) {
+    if !enabled(Category::Exec, state) {
+        return;
+    }
+    let what = match &command.type_ {
+        CommandType::Executable { path, .. } => format!("program {path}"),
+        CommandType::Function(name) => format!("function {name}"),
+        CommandType::Unknown(name) => format!("not found {name}"),
+        type_ => format!("builtin {type_}"),
+    };
+    let when = match background {
+        true => "in the background",
+        false => "in the foreground",
+    };
+    log(Category::Exec, &format!("{what} {when}"), state);
+}
+
+/// Shows a pipeline of `stages` commands being started.
+pub(crate) fn pipeline(stages: usize, text: &str, state: &ShellState) {
+    if enabled(Category::Exec, state) {
+        log(
+            Category::Exec,
+            &format!("pipeline of {stages} commands: {text}"),
+            state,
+        );
+    }
+}
+
+/// Writes one line of the trace to the end of the file named by
+/// `$RUSH_DEBUG_FILE`, or to stderr if there isn't one or it can't be
+/// opened.
+fn log(category: Category, message: &str, state: &ShellState) {
+    let line = format!("rush[{}]: {message}", category.name());
+    let file = state
+        .vars
+        .get("RUSH_DEBUG_FILE")
+        .filter(|path| !path.is_empty())
+        .and_then(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(state.cwd.resolve(&path))
+                .ok()
+        });
+    match file {
+        Some(mut file) => {
+            writeln!(file, "{line}").ok();
+        }
+        None => eprintln!("{line}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    #[test]
+    fn categories_are_named_in_rush_debug() {
+        let mut state = ShellState::default();
+        assert!(!enabled(Category::Parse, &state));
+
+        state.vars.set("RUSH_DEBUG", "parse, exec");
+        assert!(enabled(Category::Parse, &state));
+        assert!(!enabled(Category::Expand, &state));
+        assert!(enabled(Category::Exec, &state));
+
+        state.vars.set("RUSH_DEBUG", "all");
+        assert!(enabled(Category::Expand, &state));
+
+        state.vars.set("RUSH_DEBUG", "");
+        state.options.debug = true;
+        assert!(enabled(Category::Expand, &state));
+    }
+
+    #[test]
+    fn writes_to_rush_debug_file() {
+        let path = env::temp_dir().join(format!("rush_trace_{}", process::id()));
+        let mut state = ShellState::default();
+        state.vars.set("RUSH_DEBUG", "parse,expand,exec");
+        state.vars.set("RUSH_DEBUG_FILE", &path.to_string_lossy());
+
+        crate::eval::eval("echo a'b' > /dev/null | cat", &mut state).unwrap();
+        let trace = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(trace.contains("rush[parse]: token \"echo\" Text("));
+        assert!(trace.contains("rush[parse]: token \"|\" Operator(Pipe)\n"));
+        assert!(trace.contains("rush[parse]: list AndOrList"));
+        assert!(trace.contains("rush[expand]: words [\"echo\", \"ab\"]\n"));
+        assert!(trace.contains("rush[expand]: redirect 1> \"/dev/null\"\n"));
+        assert!(
+            trace.contains("rush[exec]: pipeline of 2 commands: echo a'b' > /dev/null | cat\n")
+        );
+        assert!(trace.contains("rush[exec]: builtin echo in the foreground\n"));
+    }
+}
@@ -0,0 +1,170 @@
+use std::ops::Range;
+
+use crate::{glob, util::Replace};
+
+/// The byte index of each character boundary of `value`, including its end.
+fn boundaries(value: &str) -> Vec<usize> {
+    let mut boundaries: Vec<usize> = value.char_indices().map(|(i, _)| i).collect();
+    boundaries.push(value.len());
+    boundaries
+}
+
+/// Removes the shortest prefix of `value` matching `pattern`, or the
+/// longest, as `${NAME#pattern}` and `${NAME##pattern}` do.
+pub(crate) fn remove_prefix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut ends = boundaries(value);
+    if longest {
+        ends.reverse();
+    }
+    match ends
+        .into_iter()
+        .find(|&end| glob::matches(pattern, &value[..end]))
+    {
+        Some(end) => value[end..].to_owned(),
+        None => value.to_owned(),
+    }
+}
+
+/// Removes the shortest suffix of `value` matching `pattern`, or the
+/// longest, as `${NAME%pattern}` and `${NAME%%pattern}` do.
+pub(crate) fn remove_suffix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut starts = boundaries(value);
+    if !longest {
+        starts.reverse();
+    }
+    match starts
+        .into_iter()
+        .find(|&start| glob::matches(pattern, &value[start..]))
+    {
+        Some(start) => value[..start].to_owned(),
+        None => value.to_owned(),
+    }
+}
+
+/// Replaces the longest matches of `pattern` in `value` with `string`, as
+/// `${NAME/pattern/string}` does. An empty pattern matches nothing.
+pub(crate) fn replace(value: &str, pattern: &str, string: &str, which: Replace) -> String {
+    if pattern.is_empty() {
+        return value.to_owned();
+    }
+    let boundaries = boundaries(value);
+    let longest_from = |start: usize| {
+        (boundaries.iter().rev())
+            .find(|&&end| end >= start && glob::matches(pattern, &value[start..end]))
+            .copied()
+    };
+
+    match which {
+        Replace::Prefix => match longest_from(0) {
+            Some(end) => format!("{string}{}", &value[end..]),
+            None => value.to_owned(),
+        },
+        Replace::Suffix => {
+            let start = (boundaries.iter())
+                .find(|&&start| glob::matches(pattern, &value[start..]))
+                .copied();
+            match start {
+                Some(start) => format!("{}{string}", &value[..start]),
+                None => value.to_owned(),
+            }
+        }
+        Replace::First | Replace::All => {
+            let mut replaced = String::new();
+            let mut copied = 0;
+            for &start in &boundaries {
+                if start < copied {
+                    continue;
+                }
+                // An empty match would replace between every character
+                let Some(end) = longest_from(start).filter(|&end| end > start) else {
+                    continue;
+                };
+                replaced.push_str(&value[copied..start]);
+                replaced.push_str(string);
+                copied = end;
+                if which == Replace::First {
+                    break;
+                }
+            }
+            replaced.push_str(&value[copied..]);
+            replaced
+        }
+    }
+}
+
+/// Changes the case of the first character of `value`, or of every one,
+/// if it matches `pattern`, as `${NAME^pattern}` and `${NAME,,pattern}` do.
+/// An empty pattern matches any character.
+pub(crate) fn change_case(value: &str, pattern: &str, upper: bool, all: bool) -> String {
+    let mut changed = String::new();
+    for (i, c) in value.chars().enumerate() {
+        let wanted = (all || i == 0)
+            && (pattern.is_empty() || glob::matches(pattern, c.encode_utf8(&mut [0; 4])));
+        match (wanted, upper) {
+            (true, true) => changed.extend(c.to_uppercase()),
+            (true, false) => changed.extend(c.to_lowercase()),
+            (false, _) => changed.push(c),
+        }
+    }
+    changed
+}
+
+/// The range of `len` items that `${NAME:offset:length}` takes. A negative
+/// offset counts back from the end, and a negative length leaves that many
+/// items off the end. `None` if that would end before it starts.
+pub(crate) fn substring(len: usize, offset: i64, length: Option<i64>) -> Option<Range<usize>> {
+    let from_end = |n: i64| len.saturating_sub(n.unsigned_abs() as usize);
+    let start = match offset < 0 {
+        true if offset.unsigned_abs() as usize > len => return Some(0..0),
+        true => from_end(offset),
+        false => (offset as usize).min(len),
+    };
+    let end = match length {
+        None => len,
+        Some(length) if length < 0 => from_end(length),
+        Some(length) => start.saturating_add(length as usize).min(len),
+    };
+    (end >= start).then_some(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_prefixes_and_suffixes() {
+        let path = "dir/sub/file.tar.gz";
+        assert_eq!(remove_prefix(path, "*/", false), "sub/file.tar.gz");
+        assert_eq!(remove_prefix(path, "*/", true), "file.tar.gz");
+        assert_eq!(remove_suffix(path, ".*", false), "dir/sub/file.tar");
+        assert_eq!(remove_suffix(path, ".*", true), "dir/sub/file");
+        assert_eq!(remove_prefix(path, "x*", true), path);
+    }
+
+    #[test]
+    fn replaces_matches() {
+        assert_eq!(replace("a-b-c", "-", "+", Replace::First), "a+b-c");
+        assert_eq!(replace("a-b-c", "-", "+", Replace::All), "a+b+c");
+        assert_eq!(replace("a-b-c", "a", "", Replace::Prefix), "-b-c");
+        assert_eq!(replace("a-b-c", "b", "", Replace::Suffix), "a-b-c");
+        assert_eq!(replace("aaXbb", "a*X", "_", Replace::First), "_bb");
+        assert_eq!(replace("héllo", "?l", "L", Replace::All), "hLlo");
+    }
+
+    #[test]
+    fn changes_case() {
+        assert_eq!(change_case("hello", "", true, false), "Hello");
+        assert_eq!(change_case("hello", "[lo]", true, true), "heLLO");
+        assert_eq!(change_case("ÉCOLE", "", false, true), "école");
+    }
+
+    #[test]
+    fn substrings() {
+        assert_eq!(substring(5, 1, Some(2)), Some(1..3));
+        assert_eq!(substring(5, -2, None), Some(3..5));
+        assert_eq!(substring(5, 1, Some(-1)), Some(1..4));
+        assert_eq!(substring(5, 9, None), Some(5..5));
+        assert_eq!(substring(5, -9, None), Some(0..0));
+        assert!(substring(5, 3, Some(-4)).is_none());
+    }
+}
@@ -1,7 +1,14 @@
+use std::borrow::Cow;
 use std::io::{self};
+use std::ops::Range;
 use std::vec::Vec;
 
-use crate::command::CommandType;
+use crate::{
+    command::CommandType,
+    expand,
+    lexer::{self, Lexer, Token},
+    status::ExitStatus,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum RushError {
@@ -9,191 +16,681 @@ pub enum RushError {
     CommandError {
         type_: CommandType,
         msg: String,
-        status: Option<i32>,
+        status: ExitStatus,
     },
     #[error("{0}: command not found")]
     CommandNotFound(String),
     #[error("")]
     Nop,
+    #[error("error: {0}")]
+    Syntax(SyntaxError),
+    #[error("command substitution output too large (limit is {0} bytes)")]
+    SubstitutionTooLarge(usize),
+    /// A write past the limit of the command substitution capturing it,
+    /// which leaves the commands around it up to the substitution, where it
+    /// becomes [`RushError::SubstitutionTooLarge`].
+    #[error("")]
+    Overflowed,
+    /// A write to a pipe nobody reads from any more, which leaves the
+    /// commands around it up to the stage of the pipeline writing it, as
+    /// `PIPE` would end a program.
+    #[error("")]
+    BrokenPipe,
+    /// Raised by `${NAME:?message}` when `NAME` is unset.
+    #[error("{name}: {msg}")]
+    UnsetParameter { name: String, msg: String },
+    /// An assignment to a variable declared read-only.
+    #[error("{0}: readonly variable")]
+    ReadonlyVariable(String),
+    /// A value given to an integer variable that isn't valid arithmetic.
+    #[error("{expr}: {msg}")]
+    Arithmetic { expr: String, msg: String },
+    /// An array subscript that names no element that could exist.
+    #[error("{0}: bad array subscript")]
+    BadSubscript(String),
+    #[error("{0}: ambiguous redirect")]
+    AmbiguousRedirect(String),
+    /// A file named in a redirection couldn't be opened.
+    #[error("{path}: {msg}")]
+    RedirectError { path: String, msg: String },
+    /// An error that was already shown on a redirected stderr, kept for the
+    /// status it leaves behind.
+    #[error("")]
+    Reported(ExitStatus),
+    /// `exit` run in a subshell, which ends the subshell rather than the
+    /// shell itself.
+    #[error("")]
+    Exit(ExitStatus),
+    /// `return`, which ends the function running it.
+    #[error("")]
+    Return(ExitStatus),
+    /// `break`, which leaves the given number of loops around it.
+    #[error("")]
+    Break(usize),
+    /// `continue`, which ends the turn of the loop the given number of loops
+    /// out, leaving the ones inside it.
+    #[error("")]
+    Continue(usize),
     #[error("error reading input: unexpected EOF")]
     UnexpectedEOF,
-    #[error("error: unterminated quote")]
+    /// A command that was stopped while the shell waited for it, to be put
+    /// in the job table by whatever knows the line it was run from.
+    #[cfg(unix)]
+    #[error("")]
+    Stopped(std::process::Child),
+}
+
+impl RushError {
+    /// Whether it leaves the commands around it rather than failing, as
+    /// `exit`, `return`, `break`, and `continue` do.
+    pub(crate) fn unwinds(&self) -> bool {
+        matches!(
+            self,
+            RushError::Exit(_)
+                | RushError::Return(_)
+                | RushError::Break(_)
+                | RushError::Continue(_)
+                | RushError::Overflowed
+                | RushError::BrokenPipe
+        )
+    }
+
+    /// Wraps an I/O failure hit while running `type_`, such as a write to a
+    /// full disk. A write past the limit of a command substitution ends it
+    /// instead, for the substitution to report, as a write to a pipe with
+    /// no reader ends the stage of the pipeline making it.
+    pub(crate) fn io(type_: CommandType, error: io::Error) -> Self {
+        if expand::overflowed(&error) {
+            return RushError::Overflowed;
+        }
+        if error.kind() == io::ErrorKind::BrokenPipe {
+            return RushError::BrokenPipe;
+        }
+        RushError::CommandError {
+            type_,
+            msg: error.to_string(),
+            status: ExitStatus::FAILURE,
+        }
+    }
+
+    /// The exit status a command that failed with this error finishes with.
+    pub(crate) fn status(&self) -> ExitStatus {
+        match self {
+            RushError::CommandError { status, .. } => *status,
+            RushError::CommandNotFound(_) => ExitStatus::NOT_FOUND,
+            RushError::Nop | RushError::Break(_) | RushError::Continue(_) => ExitStatus::SUCCESS,
+            RushError::Reported(status) | RushError::Exit(status) | RushError::Return(status) => {
+                *status
+            }
+            RushError::Syntax(_) => ExitStatus::USAGE,
+            #[cfg(unix)]
+            RushError::Stopped(_) => ExitStatus::stopped(),
+            RushError::BrokenPipe => ExitStatus::broken_pipe(),
+            RushError::SubstitutionTooLarge(_)
+            | RushError::Overflowed
+            | RushError::UnsetParameter { .. }
+            | RushError::ReadonlyVariable(_)
+            | RushError::Arithmetic { .. }
+            | RushError::BadSubscript(_)
+            | RushError::AmbiguousRedirect(_)
+            | RushError::RedirectError { .. }
+            | RushError::UnexpectedEOF => ExitStatus::FAILURE,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SyntaxErrorKind {
     UnterminatedQuote,
+    UnterminatedSubstitution,
+    UnexpectedToken(String),
+    /// A `${...}` whose contents aren't a valid parameter expansion.
+    BadSubstitution(String),
+    /// A here-document whose delimiter line never came.
+    UnterminatedHereDoc(String),
+    /// A `{` group without its closing `}`.
+    UnterminatedGroup,
+    /// A compound command like `if` that ended before the reserved word it
+    /// was waiting for, such as `then` or `fi`.
+    UnterminatedCompound(String),
+    /// An operator like `|` or `&&` at the end of the input, still waiting
+    /// for the command after it.
+    UnfinishedCommand(String),
+    /// Compound commands or expansions nested deeper than the shell reads.
+    NestedTooDeeply,
 }
 
-#[derive(Debug)]
-enum TokenKind {
-    Literal(String),
-    Quoted(String),
-    Space,
+impl std::fmt::Display for SyntaxErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyntaxErrorKind::UnterminatedQuote => write!(f, "unterminated quote"),
+            SyntaxErrorKind::UnterminatedSubstitution => {
+                write!(f, "unterminated command substitution")
+            }
+            SyntaxErrorKind::UnexpectedToken(token) | SyntaxErrorKind::UnfinishedCommand(token) => {
+                write!(f, "syntax error near unexpected token `{token}'")
+            }
+            SyntaxErrorKind::BadSubstitution(text) => write!(f, "{text}: bad substitution"),
+            SyntaxErrorKind::UnterminatedGroup => write!(f, "unterminated group"),
+            SyntaxErrorKind::NestedTooDeeply => write!(f, "nested too deeply"),
+            SyntaxErrorKind::UnterminatedCompound(wanted) => {
+                write!(f, "unexpected end of file (wanted `{wanted}')")
+            }
+            SyntaxErrorKind::UnterminatedHereDoc(delimiter) => {
+                write!(
+                    f,
+                    "here-document delimited by end-of-file (wanted `{delimiter}')"
+                )
+            }
+        }
+    }
 }
 
-#[derive(Debug)]
-pub struct Tokenizer {
-    input: String,
-    tokens: Vec<TokenKind>,
+/// A parse failure along with the line it happened on and the byte range of
+/// the offending construct within that line.
+#[derive(thiserror::Error, Clone, Debug, Eq, PartialEq)]
+#[error("{kind}")]
+pub struct SyntaxError {
+    pub kind: SyntaxErrorKind,
+    pub line: String,
+    pub span: Range<usize>,
 }
 
-impl Tokenizer {
-    pub fn from<R>(mut reader: R) -> Result<Self, RushError>
-    where
-        R: io::BufRead,
-    {
-        let mut input = String::new();
-        reader
-            .read_line(&mut input)
-            .map_err(|_| RushError::UnexpectedEOF)?;
-
-        Ok(Self {
-            input: input.trim().to_owned(),
-            tokens: Vec::new(),
-        })
-    }
-
-    pub fn tokenize(&mut self) -> Result<Vec<String>, RushError> {
-        let buf = &mut String::new();
-        let mut quote_count = 0;
-        let mut has_seen_literal = false;
-
-        for (i, char) in self.input.chars().enumerate() {
-            match char {
-                '\'' => {
-                    quote_count += 1;
-
-                    if quote_count == 1 {
-                        // If there's content in buf, push it as a Literal before
-                        // starting the quoted string
-                        if !buf.trim().is_empty() {
-                            has_seen_literal = true;
-                            self.tokens.push(TokenKind::Literal(buf.trim().into()));
-                        }
-                        buf.clear();
-                        continue;
-                    }
+/// A piece of a shell word: either text taken literally, or a construct the
+/// expansion phase replaces before the command runs.
+///
+/// Text is borrowed from the line it was read from, and only owned once an
+/// escape is taken out of it or more text is joined onto it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WordPart<'a> {
+    Literal(Cow<'a, str>),
+    /// Text that was inside quotes, so it's never brace-expanded or split.
+    Quoted(Cow<'a, str>),
+    /// The command line inside `$(...)`.
+    CommandSubstitution {
+        command: String,
+        quoted: bool,
+    },
+    /// A variable reference written as `$NAME`, `${NAME}`, or with a
+    /// modifier such as `${NAME:-word}`.
+    Parameter {
+        name: String,
+        quoted: bool,
+        modifier: Option<Box<Modifier<'a>>>,
+    },
+}
 
-                    if quote_count == 2 {
-                        // Ignore empty quoted tokens
-                        if buf.trim().len() == 0 {
-                            buf.clear();
-                            quote_count = 0;
-                            continue;
-                        }
-
-                        // Concatenate consecutive tokens (only if last token is NOT Space)
-                        if !matches!(self.tokens.last(), Some(TokenKind::Space)) {
-                            match self.tokens.last_mut() {
-                                Some(TokenKind::Quoted(last_token)) => {
-                                    last_token.push_str(&buf.clone());
-                                    buf.clear();
-                                    quote_count = 0;
-                                    continue;
-                                }
-                                Some(TokenKind::Literal(last_token)) => {
-                                    last_token.push_str(&buf.clone());
-                                    // Convert the Literal to a Quoted since it now contains quoted content
-                                    let combined = last_token.clone();
-                                    self.tokens.pop();
-                                    self.tokens.push(TokenKind::Quoted(combined));
-                                    buf.clear();
-                                    quote_count = 0;
-                                    continue;
-                                }
-                                _ => {}
-                            }
-                        } else {
-                            // There's a Space before this quoted string, so pop it before adding the new token
-                            self.tokens.pop();
-                        }
-
-                        self.tokens.push(TokenKind::Quoted(buf.clone()));
-
-                        buf.clear();
-                        quote_count = 0;
-                    }
-                }
-                ' ' => {
-                    if quote_count == 0 {
-                        // Skip over empty tokens
-                        if buf.trim().is_empty() {
-                            buf.clear();
-                            // Push Space token after Literals, OR after Quoted if we've seen a literal before
-                            // This allows pure quoted strings to concatenate, but separates tokens when literals are involved
-                            if matches!(self.tokens.last(), Some(TokenKind::Literal(_))) {
-                                self.tokens.push(TokenKind::Space);
-                            } else if has_seen_literal
-                                && matches!(self.tokens.last(), Some(TokenKind::Quoted(_)))
-                            {
-                                self.tokens.push(TokenKind::Space);
-                            }
-                            continue;
-                        }
-
-                        // Since we aren't processing a quoted string, push the buf into
-                        // self.tokens as a Literal token
-                        has_seen_literal = true;
-                        self.tokens.push(TokenKind::Literal(buf.trim().into()));
-                        // Push a Space token after the Literal token to help the state machine
-                        // determine whether to concatenate or not
-                        self.tokens.push(TokenKind::Space);
-
-                        buf.clear();
-                        continue;
-                    }
+impl WordPart<'_> {
+    /// The same part, with its text no longer borrowed from the line.
+    pub(crate) fn into_owned(self) -> WordPart<'static> {
+        match self {
+            WordPart::Literal(text) => WordPart::Literal(Cow::Owned(text.into_owned())),
+            WordPart::Quoted(text) => WordPart::Quoted(Cow::Owned(text.into_owned())),
+            WordPart::CommandSubstitution { command, quoted } => {
+                WordPart::CommandSubstitution { command, quoted }
+            }
+            WordPart::Parameter {
+                name,
+                quoted,
+                modifier,
+            } => WordPart::Parameter {
+                name,
+                quoted,
+                modifier: modifier.map(|modifier| {
+                    Box::new(Modifier {
+                        kind: modifier.kind,
+                        colon: modifier.colon,
+                        word: modifier.word.into_owned(),
+                        second: modifier.second.map(Word::into_owned),
+                    })
+                }),
+            },
+        }
+    }
+}
 
-                    // We push a space into buf if we're processing a quoted string
-                    buf.push(' ');
-                }
-                char => {
-                    // At the end, an odd num of quotes means a quote wasn't terminated
-                    if i == self.input.len() - 1 && quote_count % 2 == 1 {
-                        return Err(RushError::UnterminatedQuote);
-                    }
+/// What `${NAME<op>word}` does. The first four only act when `NAME` is
+/// unset, or null if the operator has a colon; the rest change its value.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ModifierKind {
+    /// `-`: use `word` instead.
+    Default,
+    /// `=`: assign `word` to `NAME`, then use it.
+    Assign,
+    /// `+`: use `word` only when `NAME` *is* set.
+    Alternate,
+    /// `?`: fail with `word` as the message.
+    Error,
+    /// `#` or `##`: remove the shortest or longest prefix matching the
+    /// pattern `word`.
+    RemovePrefix { longest: bool },
+    /// `%` or `%%`: remove the shortest or longest suffix matching the
+    /// pattern `word`.
+    RemoveSuffix { longest: bool },
+    /// `/pattern/string`: replace what matches the pattern `word`.
+    Replace(Replace),
+    /// `:offset:length`: take the characters from `offset`, evaluated as
+    /// arithmetic, up to `length` of them if given.
+    Substring,
+    /// `^` or `^^`: uppercase the first character, or every one, if it
+    /// matches the pattern `word`, or any character without one.
+    Upper { all: bool },
+    /// `,` or `,,`: lowercase them the same way.
+    Lower { all: bool },
+}
 
-                    // Push the current char into buf
-                    buf.push(char);
-                }
+/// Which matches of `${NAME/pattern/string}` are replaced.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Replace {
+    /// `/`: the first.
+    First,
+    /// `//`: every one.
+    All,
+    /// `/#`: one at the start of the value.
+    Prefix,
+    /// `/%`: one at the end of the value.
+    Suffix,
+}
+
+impl ModifierKind {
+    /// The operator `op` starts with, along with its length. The operators
+    /// that take a colon are the only ones that may follow one.
+    pub(crate) fn from_prefix(op: &str, colon: bool) -> Option<(Self, usize)> {
+        let kind = match op.get(..2).unwrap_or(op) {
+            _ if colon => match op.chars().next()? {
+                '-' => Self::Default,
+                '=' => Self::Assign,
+                '+' => Self::Alternate,
+                '?' => Self::Error,
+                _ => return Some((Self::Substring, 0)),
+            },
+            "##" => Self::RemovePrefix { longest: true },
+            "%%" => Self::RemoveSuffix { longest: true },
+            "//" => Self::Replace(Replace::All),
+            "/#" => Self::Replace(Replace::Prefix),
+            "/%" => Self::Replace(Replace::Suffix),
+            "^^" => Self::Upper { all: true },
+            ",," => Self::Lower { all: true },
+            _ => match op.chars().next()? {
+                '-' => Self::Default,
+                '=' => Self::Assign,
+                '+' => Self::Alternate,
+                '?' => Self::Error,
+                '#' => Self::RemovePrefix { longest: false },
+                '%' => Self::RemoveSuffix { longest: false },
+                '/' => Self::Replace(Replace::First),
+                '^' => Self::Upper { all: false },
+                ',' => Self::Lower { all: false },
+                _ => return None,
+            },
+        };
+        Some((kind, kind.as_str().len()))
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Default => "-",
+            Self::Assign => "=",
+            Self::Alternate => "+",
+            Self::Error => "?",
+            Self::RemovePrefix { longest: false } => "#",
+            Self::RemovePrefix { longest: true } => "##",
+            Self::RemoveSuffix { longest: false } => "%",
+            Self::RemoveSuffix { longest: true } => "%%",
+            Self::Replace(Replace::First) => "/",
+            Self::Replace(Replace::All) => "//",
+            Self::Replace(Replace::Prefix) => "/#",
+            Self::Replace(Replace::Suffix) => "/%",
+            Self::Substring => ":",
+            Self::Upper { all: false } => "^",
+            Self::Upper { all: true } => "^^",
+            Self::Lower { all: false } => ",",
+            Self::Lower { all: true } => ",,",
+        }
+    }
+
+    /// Whether it changes the value, rather than standing in for a missing
+    /// one.
+    pub(crate) fn transforms(self) -> bool {
+        !matches!(
+            self,
+            Self::Default | Self::Assign | Self::Alternate | Self::Error
+        )
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Modifier<'a> {
+    pub kind: ModifierKind,
+    /// Whether a set but empty variable counts as unset, as in `${NAME:-word}`.
+    pub colon: bool,
+    pub word: Word<'a>,
+    /// The string of `${NAME/pattern/string}` or the length of
+    /// `${NAME:offset:length}`, if given.
+    pub second: Option<Word<'a>>,
+}
+
+/// A single shell word as written, before expansion. Its text borrows from
+/// the line it was read from until [`Word::into_owned`] lets it outlive it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Word<'a> {
+    parts: Vec<WordPart<'a>>,
+}
+
+impl<'a> Word<'a> {
+    pub fn parts(&self) -> &[WordPart<'a>] {
+        &self.parts
+    }
+
+    pub(crate) fn from_parts(parts: Vec<WordPart<'a>>) -> Self {
+        let mut word = Word::default();
+        for part in parts {
+            word.push_part(part);
+        }
+        word
+    }
+
+    pub(crate) fn push(&mut self, c: char) {
+        match self.parts.last_mut() {
+            Some(WordPart::Literal(text)) => text.to_mut().push(c),
+            _ => self.parts.push(WordPart::Literal(Cow::Owned(c.into()))),
+        }
+    }
+
+    /// Adds `text` to the literal text at the end of the word. It is kept
+    /// borrowed unless there already is literal text to join it onto.
+    pub(crate) fn push_str(&mut self, text: impl Into<Cow<'a, str>>) {
+        let text = text.into();
+        match self.parts.last_mut() {
+            _ if text.is_empty() => {}
+            Some(WordPart::Literal(last)) => last.to_mut().push_str(&text),
+            _ => self.parts.push(WordPart::Literal(text)),
+        }
+    }
+
+    pub(crate) fn push_quoted(&mut self, c: char) {
+        match self.parts.last_mut() {
+            Some(WordPart::Quoted(text)) => text.to_mut().push(c),
+            _ => self.parts.push(WordPart::Quoted(Cow::Owned(c.into()))),
+        }
+    }
+
+    /// Pushes `part`, merging it into the last part if both are text of the
+    /// same kind.
+    pub(crate) fn push_part(&mut self, part: WordPart<'a>) {
+        match (self.parts.last_mut(), part) {
+            (Some(WordPart::Literal(text)), WordPart::Literal(more))
+            | (Some(WordPart::Quoted(text)), WordPart::Quoted(more)) => {
+                text.to_mut().push_str(&more)
             }
+            (_, part) => self.parts.push(part),
         }
+    }
 
-        // Push remaining chars into self.tokens
-        if buf.len() > 0 {
-            // Concatenate with the last token if it's a Literal or Quoted (no Space between)
-            match self.tokens.last_mut() {
-                Some(TokenKind::Literal(last_token)) => {
-                    last_token.push_str(buf.trim());
-                }
-                Some(TokenKind::Quoted(last_token)) => {
-                    last_token.push_str(buf.trim());
+    pub(crate) fn append(&mut self, other: Word<'a>) {
+        for part in other.parts {
+            self.push_part(part);
+        }
+    }
+
+    /// Marks the literal text of a word that was read between quotes.
+    pub(crate) fn quote(&mut self) {
+        for part in &mut self.parts {
+            if let WordPart::Literal(text) = part {
+                *part = WordPart::Quoted(std::mem::take(text));
+            }
+        }
+    }
+
+    /// The same word, with its text no longer borrowed from the line, so it
+    /// can be kept once the line is gone.
+    pub(crate) fn into_owned(self) -> Word<'static> {
+        Word {
+            parts: self.parts.into_iter().map(WordPart::into_owned).collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for Word<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for part in &self.parts {
+            match part {
+                WordPart::Literal(text) | WordPart::Quoted(text) => write!(f, "{text}")?,
+                WordPart::CommandSubstitution { command, .. } => write!(f, "$({command})")?,
+                // A subscript or length only reads back inside braces
+                WordPart::Parameter {
+                    name,
+                    modifier: None,
+                    ..
+                } if name.contains('[') || name.len() > 1 && name.starts_with('#') => {
+                    write!(f, "${{{name}}}")?
                 }
-                _ => {
-                    self.tokens.push(TokenKind::Literal(buf.trim().into()));
+                WordPart::Parameter {
+                    name,
+                    modifier: None,
+                    ..
+                } => write!(f, "${name}")?,
+                WordPart::Parameter {
+                    name,
+                    modifier: Some(modifier),
+                    ..
+                } => {
+                    let colon = if modifier.colon { ":" } else { "" };
+                    let op = modifier.kind.as_str();
+                    write!(f, "${{{name}{colon}{op}{}", modifier.word)?;
+                    if let Some(second) = &modifier.second {
+                        let separator = match modifier.kind {
+                            ModifierKind::Substring => ':',
+                            _ => '/',
+                        };
+                        write!(f, "{separator}{second}")?;
+                    }
+                    write!(f, "}}")?
                 }
             }
         }
+        Ok(())
+    }
+}
 
-        let mut tokens = Vec::<String>::new();
+/// A word or redirection of the command being put together from the tokens
+/// of its line.
+#[derive(Debug)]
+enum Piece<'a> {
+    Word(Word<'a>),
+    /// A redirection operator starting at byte `at`, still waiting for the
+    /// word that names its target.
+    Redirect {
+        fd: u32,
+        kind: RedirectKind,
+        at: usize,
+    },
+}
 
-        for token in &self.tokens {
-            match token {
-                TokenKind::Literal(literal) => tokens.push(literal.to_owned()),
-                TokenKind::Quoted(quoted) => tokens.push(quoted.to_owned()),
-                TokenKind::Space => { /* state machine hint */ }
+/// What a redirection does with the file it names.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RedirectKind {
+    /// `<`: read from the file.
+    Input,
+    /// `>`: write to the file, truncating it first.
+    Output,
+    /// `>>`: write to the end of the file.
+    Append,
+    /// `>|`: like `>`, but truncates the file even with `noclobber` set.
+    Clobber,
+    /// `>&`: write wherever the descriptor named by the target goes.
+    Duplicate,
+    /// `<&`: read from wherever the descriptor named by the target reads.
+    DuplicateInput,
+    /// `&>`: send both stdout and stderr to the file, truncating it first.
+    OutputAll,
+    /// `&>>`: send both stdout and stderr to the end of the file.
+    AppendAll,
+    /// `<<`: read from the here-document that follows the command.
+    HereDoc,
+    /// `<<-`: like `<<`, but leading tabs are stripped from the body and the
+    /// delimiter line.
+    HereDocStrip,
+}
+
+impl RedirectKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RedirectKind::Input => "<",
+            RedirectKind::Output => ">",
+            RedirectKind::Append => ">>",
+            RedirectKind::Clobber => ">|",
+            RedirectKind::Duplicate => ">&",
+            RedirectKind::DuplicateInput => "<&",
+            RedirectKind::OutputAll => "&>",
+            RedirectKind::AppendAll => "&>>",
+            RedirectKind::HereDoc => "<<",
+            RedirectKind::HereDocStrip => "<<-",
+        }
+    }
+
+    pub(crate) fn is_here_doc(self) -> bool {
+        matches!(self, RedirectKind::HereDoc | RedirectKind::HereDocStrip)
+    }
+}
+
+/// A redirection as written, such as `< input.txt`, before its target is
+/// expanded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Redirect {
+    /// The file descriptor being redirected.
+    pub fd: u32,
+    pub kind: RedirectKind,
+    /// The file to open, or for a here-document, its body.
+    pub target: Word<'static>,
+}
+
+/// Puts the words and redirections of a simple command together from the
+/// tokens of its line. Text and quoted strings written with no blank between
+/// them make up one word, and a quoted string makes a word even when it's
+/// empty.
+///
+/// The target of a here-document is left as its delimiter, since the body
+/// can only be read once the line naming it ends.
+#[derive(Debug, Default)]
+pub(crate) struct Words<'a> {
+    pieces: Vec<Piece<'a>>,
+    /// The word being read, once a token of it has been.
+    word: Option<Word<'a>>,
+}
+
+impl<'a> Words<'a> {
+    pub(crate) fn push(&mut self, token: Token<'a>) {
+        match token.kind {
+            lexer::TokenKind::Text(text) | lexer::TokenKind::Quoted(text) => {
+                self.word.get_or_insert_default().append(text)
+            }
+            lexer::TokenKind::Blank | lexer::TokenKind::Newline => self.end_word(),
+            lexer::TokenKind::Redirect { fd, kind } => {
+                self.end_word();
+                let input = matches!(kind, RedirectKind::Input | RedirectKind::DuplicateInput)
+                    || kind.is_here_doc();
+                let fd = fd.unwrap_or(if input { 0 } else { 1 });
+                let at = token.span.end - kind.as_str().len();
+                self.pieces.push(Piece::Redirect { fd, kind, at });
+            }
+            lexer::TokenKind::Operator(operator) => self.push_text(operator.as_str()),
+        }
+    }
+
+    /// Adds `text` to the word in progress as if it were unquoted text.
+    fn push_text(&mut self, text: &'a str) {
+        self.word.get_or_insert_default().push_str(text);
+    }
+
+    /// Returns the words and redirections, with each redirection paired up
+    /// with the word naming its target, owned so they outlive the line.
+    pub(crate) fn finish(
+        mut self,
+        lexer: &Lexer,
+    ) -> Result<(Vec<Word<'static>>, Vec<Redirect>), RushError> {
+        self.end_word();
+        let mut words = Vec::new();
+        let mut redirects = Vec::new();
+        let mut pieces = self.pieces.into_iter();
+
+        while let Some(piece) = pieces.next() {
+            match piece {
+                Piece::Word(word) => words.push(word.into_owned()),
+                Piece::Redirect { fd, kind, at } => match pieces.next() {
+                    Some(Piece::Word(target)) => redirects.push(Redirect {
+                        fd,
+                        kind,
+                        target: target.into_owned(),
+                    }),
+                    next => {
+                        let token = match next {
+                            Some(Piece::Redirect { kind, .. }) => kind.as_str(),
+                            _ => "newline",
+                        };
+                        return Err(lexer.syntax_error(
+                            SyntaxErrorKind::UnexpectedToken(token.into()),
+                            at..at + kind.as_str().len(),
+                        ));
+                    }
+                },
             }
         }
 
-        Ok(tokens)
+        Ok((words, redirects))
     }
+
+    /// Ends the word in progress, if any, because a blank or an operator
+    /// follows it.
+    fn end_word(&mut self) {
+        self.pieces.extend(self.word.take().map(Piece::Word));
+    }
+}
+
+/// Whether `input` ends inside a quoted string, a group, or a compound
+/// command like `if`, before the end of a here-document, or after an
+/// operator like `|` or `&&`, so the line it was typed on has to be continued
+/// before it can run.
+pub(crate) fn is_incomplete(input: &str) -> bool {
+    matches!(
+        crate::parser::parse(input),
+        Err(RushError::Syntax(SyntaxError {
+            kind: SyntaxErrorKind::UnterminatedQuote
+                | SyntaxErrorKind::UnterminatedHereDoc(_)
+                | SyntaxErrorKind::UnterminatedGroup
+                | SyntaxErrorKind::UnterminatedCompound(_)
+                | SyntaxErrorKind::UnfinishedCommand(_),
+            ..
+        }))
+    )
+}
+
+/// Whether `c` names a special parameter, like the `?` in `$?`, whose value
+/// the shell keeps itself rather than in a variable. A single digit names a
+/// positional parameter; more need braces, as in `${10}`.
+pub(crate) fn is_special_parameter(c: char) -> bool {
+    matches!(c, '?' | '$' | '!' | '@' | '*' | '#') || c.is_ascii_digit()
+}
+
+/// Whether `s` is a valid variable name: a letter or underscore followed by
+/// letters, digits, and underscores.
+pub(crate) fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::{self, BufRead};
+    use crate::parser::SimpleCommand;
 
-    // Shared test helper
+    /// The words of the command `input` parses into, as written.
     fn parse(input: &str) -> Result<Vec<String>, RushError> {
-        let mut state_machine = Tokenizer::from(io::Cursor::new(input))?;
-        state_machine.tokenize()
+        let command = SimpleCommand::parse(input)?;
+        Ok(command.words.iter().map(Word::to_string).collect())
     }
 
     mod basic_tokenization {
@@ -252,10 +749,19 @@ mod tests {
         }
 
         #[test]
-        fn consecutive_quoted_strings_are_concatenated() {
+        fn quoted_strings_apart_are_separate_words() {
             assert_eq!(
                 parse("\'first\' \'second\' \'third\'\n").unwrap(),
-                vec!["firstsecondthird"]
+                vec!["first", "second", "third"]
+            );
+        }
+
+        #[test]
+        fn quoted_and_unquoted_text_together_is_one_word() {
+            assert_eq!(parse("echo \'a\'b\'c\'\n").unwrap(), vec!["echo", "abc"]);
+            assert_eq!(
+                parse("echo \"a\"b c\"d\"\n").unwrap(),
+                vec!["echo", "ab", "cd"]
             );
         }
 
@@ -277,9 +783,9 @@ mod tests {
 
         #[test]
         fn empty_quoted_strings() {
-            assert_eq!(parse("\'\'\n").unwrap(), Vec::<&str>::new());
-            assert_eq!(parse("echo \'\'\n").unwrap(), vec!["echo"]);
-            assert_eq!(parse("\'\' \'\' \'\'\n").unwrap(), Vec::<&str>::new());
+            assert_eq!(parse("\'\'\n").unwrap(), vec![""]);
+            assert_eq!(parse("echo \'\'\n").unwrap(), vec!["echo", ""]);
+            assert_eq!(parse("\'\' \"\" \'\'\n").unwrap(), vec!["", "", ""]);
         }
 
         #[test]
@@ -312,7 +818,7 @@ mod tests {
 
         #[test]
         fn consecutive_quotes() {
-            assert_eq!(parse("\'\'\'\' \n").unwrap(), Vec::<&str>::new());
+            assert_eq!(parse("\'\'\'\' \n").unwrap(), vec![""]);
             assert_eq!(parse("\'a\'\'b\'\n").unwrap(), vec!["ab"]);
         }
 
@@ -339,7 +845,7 @@ mod tests {
 
         #[test]
         fn single_char_quoted() {
-            assert_eq!(parse("\'a\' \'b\' \'c\'\n").unwrap(), vec!["abc"]);
+            assert_eq!(parse("\'a\' \'b\' \'c\'\n").unwrap(), vec!["a", "b", "c"]);
         }
 
         #[test]
@@ -410,22 +916,495 @@ mod tests {
         }
     }
 
+    mod comments {
+        use super::*;
+
+        #[test]
+        fn rest_of_line_is_dropped() {
+            assert_eq!(parse("echo hi # a comment").unwrap(), vec!["echo", "hi"]);
+            assert_eq!(parse("echo hi #'unterminated").unwrap(), vec!["echo", "hi"]);
+            assert!(parse("# only a comment").unwrap().is_empty());
+        }
+
+        #[test]
+        fn hash_inside_a_word_is_literal() {
+            assert_eq!(parse("echo a#b").unwrap(), vec!["echo", "a#b"]);
+            assert_eq!(parse("echo $#x").unwrap(), vec!["echo", "$#x"]);
+        }
+
+        #[test]
+        fn quoted_hash_is_literal() {
+            assert_eq!(
+                parse("echo '#not a comment' \"# nor this\"").unwrap(),
+                vec!["echo", "#not a comment", "# nor this"]
+            );
+        }
+    }
+
+    mod redirects {
+        use super::*;
+
+        fn command(input: &str) -> Result<(Vec<String>, Vec<Redirect>), RushError> {
+            let command = SimpleCommand::parse(input)?;
+            let words = command.words.iter().map(Word::to_string).collect();
+            Ok((words, command.redirects))
+        }
+
+        fn input(target: &str) -> Redirect {
+            Redirect {
+                fd: 0,
+                kind: RedirectKind::Input,
+                target: Word::from_parts(vec![WordPart::Literal(target.to_owned().into())]),
+            }
+        }
+
+        #[test]
+        fn input_redirection() {
+            for line in ["sort < in.txt -r", "sort <in.txt -r", "sort -r<in.txt"] {
+                let (words, redirects) = command(line).unwrap();
+                assert_eq!(words, ["sort", "-r"], "{line}");
+                assert_eq!(redirects, [input("in.txt")], "{line}");
+            }
+        }
+
+        #[test]
+        fn output_operators() {
+            let (words, redirects) =
+                command("make >out 2>> err.log 2>&1 &>all &>>more 1<in <&3 4<&0").unwrap();
+            assert_eq!(words, ["make"]);
+            let found: Vec<_> = redirects
+                .iter()
+                .map(|r| (r.fd, r.kind, r.target.to_string()))
+                .collect();
+            assert_eq!(
+                found,
+                [
+                    (1, RedirectKind::Output, "out".into()),
+                    (2, RedirectKind::Append, "err.log".into()),
+                    (2, RedirectKind::Duplicate, "1".into()),
+                    (1, RedirectKind::OutputAll, "all".into()),
+                    (1, RedirectKind::AppendAll, "more".into()),
+                    (1, RedirectKind::Input, "in".into()),
+                    (0, RedirectKind::DuplicateInput, "3".into()),
+                    (4, RedirectKind::DuplicateInput, "0".into()),
+                ]
+            );
+        }
+
+        #[test]
+        fn digits_only_name_a_descriptor_when_attached() {
+            let (words, redirects) = command("echo 2 >f a2>g").unwrap();
+            assert_eq!(words, ["echo", "2", "a2"]);
+            assert_eq!(redirects[0].fd, 1);
+            assert_eq!(redirects[1].fd, 1);
+        }
+
+        #[test]
+        fn quoted_operator_is_a_word() {
+            let (words, redirects) = command("echo '<' \"<\"").unwrap();
+            assert_eq!(words, ["echo", "<", "<"]);
+            assert!(redirects.is_empty());
+        }
+
+        #[test]
+        fn missing_target() {
+            for (line, token) in [("cat <", "newline"), ("cat < < x", "<")] {
+                let RushError::Syntax(error) = command(line).unwrap_err() else {
+                    panic!("Expected a syntax error for {line:?}");
+                };
+                assert_eq!(error.kind, SyntaxErrorKind::UnexpectedToken(token.into()));
+                assert_eq!(error.span, 4..5, "{line:?}");
+            }
+        }
+    }
+
+    mod here_docs {
+        use super::*;
+
+        fn here_doc(input: &str) -> Result<Redirect, RushError> {
+            Ok(SimpleCommand::parse(input)?.redirects.remove(0))
+        }
+
+        #[test]
+        fn body_follows_the_line() {
+            let SimpleCommand {
+                words, redirects, ..
+            } = SimpleCommand::parse("cat <<EOF -n\na b\nEOF\n").unwrap();
+            assert_eq!(words.len(), 2);
+            assert_eq!(redirects[0].fd, 0);
+            assert_eq!(redirects[0].kind, RedirectKind::HereDoc);
+            assert_eq!(
+                redirects[0].target.parts(),
+                [WordPart::Quoted("a b\n".into())]
+            );
+        }
+
+        #[test]
+        fn expansions_are_recognized() {
+            let redirect = here_doc("cat <<EOF\n\"$HOME\" \\$X \\q\nEOF").unwrap();
+            assert_eq!(
+                redirect.target.parts(),
+                [
+                    WordPart::Quoted("\"".into()),
+                    WordPart::Parameter {
+                        name: "HOME".into(),
+                        quoted: true,
+                        modifier: None,
+                    },
+                    WordPart::Quoted("\" $X \\q\n".into()),
+                ]
+            );
+        }
+
+        #[test]
+        fn quoted_delimiter_keeps_the_body_literal() {
+            for input in [
+                "cat <<'EOF'\n$HOME \\$\nEOF",
+                "cat <<E\"O\"F\n$HOME \\$\nEOF",
+            ] {
+                let redirect = here_doc(input).unwrap();
+                assert_eq!(
+                    redirect.target.parts(),
+                    [WordPart::Quoted("$HOME \\$\n".into())],
+                    "{input}"
+                );
+            }
+        }
+
+        #[test]
+        fn leading_tabs_are_stripped() {
+            let redirect = here_doc("cat <<-EOF\n\t\tone\n  two\n\tEOF").unwrap();
+            assert_eq!(redirect.kind, RedirectKind::HereDocStrip);
+            assert_eq!(redirect.target.to_string(), "one\n  two\n");
+
+            // Without `-`, the tabbed line isn't the delimiter
+            assert!(here_doc("cat <<EOF\n\tEOF").is_err());
+        }
+
+        #[test]
+        fn several_bodies_in_order() {
+            let command = SimpleCommand::parse("cat <<A - <<B # two\n1\nA\n2\nB").unwrap();
+            let bodies: Vec<_> = (command.redirects.iter())
+                .map(|r| r.target.to_string())
+                .collect();
+            assert_eq!(bodies, ["1\n", "2\n"]);
+        }
+
+        #[test]
+        fn missing_delimiter() {
+            let Err(RushError::Syntax(error)) = here_doc("cat <<END x\nbody") else {
+                panic!("Expected a syntax error");
+            };
+            assert_eq!(
+                error.kind,
+                SyntaxErrorKind::UnterminatedHereDoc("END".into())
+            );
+            assert_eq!(error.line, "cat <<END x");
+            assert_eq!(error.span, 4..9);
+        }
+    }
+
+    mod command_substitution {
+        use super::*;
+
+        fn words(input: &str) -> Vec<Word<'static>> {
+            SimpleCommand::parse(input).unwrap().words
+        }
+
+        #[test]
+        fn substitution_is_its_own_part() {
+            let words = words("echo a$(ls -l)b");
+            assert_eq!(
+                words[1].parts(),
+                [
+                    WordPart::Literal("a".into()),
+                    WordPart::CommandSubstitution {
+                        command: "ls -l".into(),
+                        quoted: false
+                    },
+                    WordPart::Literal("b".into()),
+                ]
+            );
+        }
+
+        #[test]
+        fn nested_and_quoted_parens() {
+            let words = words("echo $(echo $(pwd) ')')");
+            assert_eq!(
+                words[1].parts(),
+                [WordPart::CommandSubstitution {
+                    command: "echo $(pwd) ')'".into(),
+                    quoted: false
+                }]
+            );
+        }
+
+        #[test]
+        fn single_quotes_keep_it_literal() {
+            assert_eq!(parse("echo '$(pwd)'").unwrap(), vec!["echo", "$(pwd)"]);
+        }
+
+        #[test]
+        fn dollar_without_paren_is_literal() {
+            assert_eq!(parse("echo $ a$b").unwrap(), vec!["echo", "$", "a$b"]);
+        }
+
+        #[test]
+        fn unterminated_substitution() {
+            let RushError::Syntax(error) = parse("echo $(ls").unwrap_err() else {
+                panic!("Expected a syntax error");
+            };
+            assert_eq!(error.kind, SyntaxErrorKind::UnterminatedSubstitution);
+            assert_eq!(error.span, 5..9);
+        }
+    }
+
+    mod double_quotes {
+        use super::*;
+
+        #[test]
+        fn preserves_spaces() {
+            assert_eq!(
+                parse("echo \"hello   world\"").unwrap(),
+                vec!["echo", "hello   world"]
+            );
+        }
+
+        #[test]
+        fn other_quote_is_literal() {
+            assert_eq!(parse("echo \"it's\"").unwrap(), vec!["echo", "it's"]);
+            assert_eq!(
+                parse("echo 'say \"hi\"'").unwrap(),
+                vec!["echo", "say \"hi\""]
+            );
+        }
+
+        #[test]
+        fn backslash_escapes() {
+            assert_eq!(
+                parse(r#"echo "a\"b \$c \\d \e""#).unwrap(),
+                vec!["echo", r#"a"b $c \d \e"#]
+            );
+        }
+
+        #[test]
+        fn unterminated_double_quote() {
+            let RushError::Syntax(error) = parse("echo \"oops").unwrap_err() else {
+                panic!("Expected a syntax error");
+            };
+            assert_eq!(error.kind, SyntaxErrorKind::UnterminatedQuote);
+            assert_eq!(error.span, 5..10);
+        }
+    }
+
+    mod parameters {
+        use super::*;
+
+        fn parts(input: &str) -> Vec<WordPart<'static>> {
+            let words = SimpleCommand::parse(input).unwrap().words;
+            words[0].parts().to_vec()
+        }
+
+        fn param(name: &str, quoted: bool) -> WordPart<'static> {
+            WordPart::Parameter {
+                name: name.into(),
+                quoted,
+                modifier: None,
+            }
+        }
+
+        fn modifier(input: &str) -> Modifier<'static> {
+            match &parts(input)[0] {
+                WordPart::Parameter {
+                    modifier: Some(modifier),
+                    ..
+                } => (**modifier).clone(),
+                other => panic!("Expected a modified parameter, got {other:?}"),
+            }
+        }
+
+        #[test]
+        fn bare_and_braced_names() {
+            assert_eq!(
+                parts("$HOME/bin:${PATH}x"),
+                [
+                    param("HOME", false),
+                    WordPart::Literal("/bin:".into()),
+                    param("PATH", false),
+                    WordPart::Literal("x".into()),
+                ]
+            );
+        }
+
+        #[test]
+        fn special_parameters() {
+            assert_eq!(
+                parts("$?x${?}"),
+                [
+                    param("?", false),
+                    WordPart::Literal("x".into()),
+                    param("?", false),
+                ]
+            );
+            assert_eq!(modifier("${?:-none}").kind, ModifierKind::Default);
+            assert!(parse("echo ${?x}").is_err());
+        }
+
+        #[test]
+        fn positional_parameters() {
+            // Without braces only one digit is part of the name
+            assert_eq!(
+                parts("$10${10}$#"),
+                [
+                    param("1", false),
+                    WordPart::Literal("0".into()),
+                    param("10", false),
+                    param("#", false),
+                ]
+            );
+            assert_eq!(parts("\"$@\""), [param("@", true)]);
+            assert!(parse("echo ${1a}").is_err());
+        }
+
+        #[test]
+        fn name_stops_at_first_non_name_character() {
+            assert_eq!(
+                parts("$A_1-b"),
+                [param("A_1", false), WordPart::Literal("-b".into())]
+            );
+        }
+
+        #[test]
+        fn double_quotes_mark_expansions_quoted() {
+            assert_eq!(
+                parts("\"x $USER $(id)\""),
+                [
+                    WordPart::Quoted("x ".into()),
+                    param("USER", true),
+                    WordPart::Quoted(" ".into()),
+                    WordPart::CommandSubstitution {
+                        command: "id".into(),
+                        quoted: true
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn single_quotes_suppress_expansion() {
+            assert_eq!(
+                parts("'$HOME ${X}'"),
+                [WordPart::Quoted("$HOME ${X}".into())]
+            );
+        }
+
+        #[test]
+        fn modifiers() {
+            let m = modifier("${A:-x y}");
+            assert_eq!((m.kind, m.colon), (ModifierKind::Default, true));
+            assert_eq!(m.word.parts(), [WordPart::Literal("x y".into())]);
+
+            let m = modifier("${A-$B}");
+            assert_eq!((m.kind, m.colon), (ModifierKind::Default, false));
+            assert_eq!(m.word.parts(), [param("B", false)]);
+
+            for (input, kind) in [
+                ("${A:=x}", ModifierKind::Assign),
+                ("${A:+x}", ModifierKind::Alternate),
+                ("${A?x}", ModifierKind::Error),
+            ] {
+                assert_eq!(modifier(input).kind, kind, "{input}");
+            }
+        }
+
+        #[test]
+        fn string_modifiers() {
+            for (input, kind) in [
+                ("${A#x}", ModifierKind::RemovePrefix { longest: false }),
+                ("${A##x}", ModifierKind::RemovePrefix { longest: true }),
+                ("${A%%x}", ModifierKind::RemoveSuffix { longest: true }),
+                ("${A/#x/y}", ModifierKind::Replace(Replace::Prefix)),
+                ("${A^^}", ModifierKind::Upper { all: true }),
+                ("${A,x}", ModifierKind::Lower { all: false }),
+                ("${A: -1}", ModifierKind::Substring),
+            ] {
+                assert_eq!(modifier(input).kind, kind, "{input}");
+                assert_eq!(Word::from_parts(parts(input)).to_string(), input, "{input}");
+            }
+
+            // The second word starts at the first separator outside quotes
+            // and nested expansions
+            let m = modifier("${A//'/'${B/x/y}/\"/\"}");
+            assert_eq!(m.word.to_string(), "/${B/x/y}");
+            assert_eq!(m.second.unwrap().to_string(), "/");
+            let m = modifier("${A:1:$((2))}");
+            assert_eq!(m.word.to_string(), "1");
+            assert_eq!(m.second.unwrap().to_string(), "$((2))");
+
+            // A pattern is still a pattern inside double quotes
+            let m = modifier("\"${A#*}\"");
+            assert_eq!(m.word.parts(), [WordPart::Literal("*".into())]);
+        }
+
+        #[test]
+        fn length_and_subscripts() {
+            assert_eq!(parts("${#A}${#}"), [param("#A", false), param("#", false)]);
+            assert_eq!(parts("${A[$i]}")[0], param("A[$i]", false));
+            assert_eq!(modifier("${A[@]:-x}").kind, ModifierKind::Default);
+            assert!(parse("echo ${#A:-x}").is_err());
+        }
+
+        #[test]
+        fn modifier_word_quotes_are_removed() {
+            let m = modifier("${A:-'a  }'\"b\"}");
+            assert_eq!(m.word.to_string(), "a  }b");
+        }
+
+        #[test]
+        fn bad_substitution() {
+            for (input, span) in [
+                ("echo ${1x}", 5..10),
+                ("echo ${}", 5..8),
+                ("echo ${A", 5..8),
+                ("echo ${A:}", 5..10),
+                ("echo ${A!x}", 5..11),
+            ] {
+                let RushError::Syntax(error) = parse(input).unwrap_err() else {
+                    panic!("Expected a syntax error for {input:?}");
+                };
+                assert!(
+                    matches!(error.kind, SyntaxErrorKind::BadSubstitution(_)),
+                    "{input:?}"
+                );
+                assert_eq!(error.span, span, "{input:?}");
+            }
+        }
+    }
+
     mod error_handling {
         use super::*;
 
         #[test]
         fn unterminated_quote_at_end() {
+            let error = parse("echo \'hello world\n").unwrap_err();
             assert!(matches!(
-                parse("echo \'hello world\n").unwrap_err(),
-                RushError::UnterminatedQuote
+                error,
+                RushError::Syntax(SyntaxError {
+                    kind: SyntaxErrorKind::UnterminatedQuote,
+                    ..
+                })
             ));
+            assert_eq!(error.to_string(), "error: unterminated quote");
         }
 
         #[test]
         fn unterminated_quote_at_start() {
             assert!(matches!(
                 parse("\'unterminated\n").unwrap_err(),
-                RushError::UnterminatedQuote
+                RushError::Syntax(SyntaxError {
+                    kind: SyntaxErrorKind::UnterminatedQuote,
+                    ..
+                })
             ));
         }
 
@@ -433,33 +1412,91 @@ mod tests {
         fn unterminated_quote_after_valid_quotes() {
             assert!(matches!(
                 parse("cmd \'arg1\' \'unterminated\n").unwrap_err(),
-                RushError::UnterminatedQuote
+                RushError::Syntax(SyntaxError {
+                    kind: SyntaxErrorKind::UnterminatedQuote,
+                    ..
+                })
             ));
         }
 
-        struct ErrReader;
+        #[test]
+        fn unterminated_quote_span_starts_at_opening_quote() {
+            let RushError::Syntax(error) = parse("echo \'hello world\n").unwrap_err() else {
+                panic!("Expected a syntax error");
+            };
+            assert_eq!(error.line, "echo \'hello world");
+            assert_eq!(error.span, 5..17);
+        }
 
-        impl io::Read for ErrReader {
-            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
-                Err(io::Error::new(io::ErrorKind::UnexpectedEof, "read error"))
-            }
+        #[test]
+        fn unterminated_quote_after_multibyte_text() {
+            let RushError::Syntax(error) = parse("echo 日本 \'abc\n").unwrap_err() else {
+                panic!("Expected a syntax error");
+            };
+            assert_eq!(error.kind, SyntaxErrorKind::UnterminatedQuote);
+            assert_eq!(&error.line[error.span.clone()], "\'abc");
+        }
+
+        #[test]
+        fn lone_opening_quote_is_unterminated() {
+            assert!(matches!(
+                parse("echo \'\n").unwrap_err(),
+                RushError::Syntax(SyntaxError {
+                    kind: SyntaxErrorKind::UnterminatedQuote,
+                    ..
+                })
+            ));
         }
 
-        impl BufRead for ErrReader {
-            fn fill_buf(&mut self) -> io::Result<&[u8]> {
-                Err(io::Error::new(
-                    io::ErrorKind::UnexpectedEof,
-                    "fill_buf error",
-                ))
+        #[test]
+        fn stray_pipe() {
+            let unexpected = SyntaxErrorKind::UnexpectedToken("|".into());
+            let unfinished = SyntaxErrorKind::UnfinishedCommand("|".into());
+            for (input, kind, offset) in [
+                ("| wc", &unexpected, 0),
+                ("   |", &unexpected, 3),
+                ("ls |", &unfinished, 3),
+            ] {
+                let RushError::Syntax(error) = parse(input).unwrap_err() else {
+                    panic!("Expected a syntax error for {input:?}");
+                };
+                assert_eq!(&error.kind, kind, "{input:?}");
+                assert_eq!(error.span, offset..offset + 1, "{input:?}");
             }
-            fn consume(&mut self, _n: usize) {}
         }
 
         #[test]
-        fn io_read_error_returns_unexpected_eof() {
-            let reader = ErrReader;
-            let err = Tokenizer::from(reader).unwrap_err();
-            assert!(matches!(err, RushError::UnexpectedEOF));
+        fn quoted_pipe_is_not_an_error() {
+            assert_eq!(parse("echo \'|\'\n").unwrap(), vec!["echo", "|"]);
+        }
+
+        #[test]
+        fn open_quotes_are_incomplete() {
+            assert!(is_incomplete("echo 'a\n"));
+            assert!(is_incomplete("echo \"a\nb\n"));
+            assert!(!is_incomplete("echo 'a\nb'\n"));
+            assert!(!is_incomplete("echo $(\n"));
+        }
+
+        #[test]
+        fn open_here_documents_are_incomplete() {
+            assert!(is_incomplete("cat <<EOF\n"));
+            assert!(is_incomplete("cat <<EOF; echo done\nbody\n"));
+            assert!(!is_incomplete("cat <<EOF; echo done\nit's\nEOF\n"));
+        }
+
+        #[test]
+        fn operators_continue_onto_the_next_line() {
+            assert!(is_incomplete("echo a |\n"));
+            assert!(is_incomplete("true &&\n"));
+            assert!(is_incomplete("false ||\n\n"));
+            assert!(!is_incomplete("echo a |\nwc -l\n"));
+            assert!(!is_incomplete("echo a | |\n"));
+        }
+
+        #[test]
+        fn quotes_keep_newlines() {
+            assert_eq!(parse("echo 'a\nb' c\n").unwrap(), vec!["echo", "a\nb", "c"]);
         }
     }
 }
@@ -0,0 +1,642 @@
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet},
+    env, process,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{arith, util::RushError};
+
+/// The shell's variables.
+///
+/// Exported variables make up the environment every program the shell
+/// starts is given. The rest only the shell itself sees, and arrays can't
+/// be exported at all. Both are kept here rather than in the process
+/// environment, so a copy of the shell, like a subshell, can change them
+/// without touching the shell it was copied from.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Variables {
+    shell: BTreeMap<String, String>,
+    environment: Environment,
+    arrays: BTreeMap<String, Array>,
+    /// Names marked for export before they had a value, as with `export A`
+    /// while `A` is unset.
+    pending: BTreeSet<String>,
+    /// What `declare` said about each variable, for those it said anything
+    /// about.
+    attributes: BTreeMap<String, Attributes>,
+    /// One scope for each function running, innermost last, holding what
+    /// each of its `local` variables was before the function hid it.
+    scopes: Vec<BTreeMap<String, Saved>>,
+    /// Where `$RANDOM` is up to.
+    random: Random,
+    /// When `$SECONDS` counts from.
+    clock: Clock,
+    /// The line of input the command running starts on, for `$LINENO`.
+    line: usize,
+}
+
+/// What a variable was declared as, beyond its value.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Attributes {
+    /// Whether values given to it are evaluated as arithmetic, as with
+    /// `declare -i`.
+    pub integer: bool,
+    /// Whether it refuses to change, as with `declare -r`.
+    pub readonly: bool,
+    /// Whether it was declared an indexed array, as with `declare -a`.
+    pub array: bool,
+    /// Whether it was declared an associative array, as with `declare -A`.
+    pub associative: bool,
+}
+
+/// The elements of an array variable.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Array {
+    /// Elements by number, which may leave gaps, as in `a=([3]=x)`.
+    Indexed(BTreeMap<usize, String>),
+    /// Elements by name, as with `declare -A`.
+    Associative(BTreeMap<String, String>),
+}
+
+impl Array {
+    /// Every element, in order of index or name.
+    pub(crate) fn values(&self) -> Vec<String> {
+        match self {
+            Array::Indexed(elements) => elements.values().cloned().collect(),
+            Array::Associative(elements) => elements.values().cloned().collect(),
+        }
+    }
+
+    /// The index or name of every element, in order.
+    pub(crate) fn keys(&self) -> Vec<String> {
+        match self {
+            Array::Indexed(elements) => elements.keys().map(usize::to_string).collect(),
+            Array::Associative(elements) => elements.keys().cloned().collect(),
+        }
+    }
+}
+
+/// The numbers `$RANDOM` gives, from 0 to 32767, as bash's are. It's seeded
+/// the first time it's read, or by assigning to `RANDOM`, and a copy of the
+/// shell, like a subshell, starts over from a seed of its own so it doesn't
+/// repeat what the shell it was copied from is about to give.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Random(Cell<Option<u32>>);
+
+impl Random {
+    fn next(&self) -> u32 {
+        let seed = self.0.get().unwrap_or_else(Self::seed);
+        let seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        self.0.set(Some(seed));
+        (seed >> 16) & 0x7fff
+    }
+
+    /// A seed that differs from run to run, and from copy to copy.
+    fn seed() -> u32 {
+        static COPIES: AtomicU32 = AtomicU32::new(0);
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        now.subsec_nanos()
+            ^ (now.as_secs() as u32)
+            ^ process::id().rotate_left(16)
+            ^ COPIES
+                .fetch_add(1, Ordering::Relaxed)
+                .wrapping_mul(0x9e37_79b9)
+    }
+}
+
+impl Clone for Random {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+/// The exported variables, which start out as the environment the process
+/// was given.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Environment(BTreeMap<String, String>);
+
+impl Default for Environment {
+    fn default() -> Self {
+        let vars = env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        });
+        Self(vars.collect())
+    }
+}
+
+/// The time `$SECONDS` counts from: when the shell started, unless it has
+/// been assigned since.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Clock(Instant);
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self(Instant::now())
+    }
+}
+
+/// A variable as it was before a function made it `local`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Saved {
+    value: Option<String>,
+    array: Option<Array>,
+    attributes: Attributes,
+}
+
+/// Splits `name[subscript]` into the name and the subscript.
+pub(crate) fn split_subscript(name: &str) -> Option<(&str, &str)> {
+    let (name, rest) = name.split_once('[')?;
+    Some((name, rest.strip_suffix(']')?))
+}
+
+impl Variables {
+    /// The value of `name`, wherever it lives. The value of an array is its
+    /// first element.
+    pub(crate) fn get(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.dynamic(name) {
+            return Some(value);
+        }
+        if let Some(array) = self.arrays.get(name) {
+            return match array {
+                Array::Indexed(elements) => elements.get(&0).cloned(),
+                Array::Associative(elements) => elements.get("0").cloned(),
+            };
+        }
+        match self.shell.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.environment.0.get(name).cloned(),
+        }
+    }
+
+    /// The value of one of the variables the shell works out each time
+    /// it's read, if `name` is one.
+    fn dynamic(&self, name: &str) -> Option<String> {
+        match name {
+            "RANDOM" => Some(self.random.next().to_string()),
+            "SECONDS" => Some(self.clock.0.elapsed().as_secs().to_string()),
+            "LINENO" => Some(self.line.to_string()),
+            _ => None,
+        }
+    }
+
+    /// The line of input the command running starts on.
+    pub(crate) fn line(&self) -> usize {
+        self.line
+    }
+
+    pub(crate) fn set_line(&mut self, line: usize) {
+        self.line = line;
+    }
+
+    /// Sets `name` to `value`, keeping it exported if it already was. For
+    /// an array, that sets its first element. Setting `RANDOM` seeds it and
+    /// setting `SECONDS` starts it counting from `value`, as in bash, while
+    /// `LINENO` keeps following the input.
+    pub(crate) fn set(&mut self, name: &str, value: &str) {
+        match name {
+            "RANDOM" => return self.random.0.set(value.parse().ok()),
+            "SECONDS" => {
+                let seconds = Duration::from_secs(value.parse().unwrap_or(0));
+                let now = Instant::now();
+                self.clock = Clock(now.checked_sub(seconds).unwrap_or(now));
+                return;
+            }
+            "LINENO" => return,
+            _ => {}
+        }
+        if let Some(array) = self.arrays.get_mut(name) {
+            match array {
+                Array::Indexed(elements) => elements.insert(0, value.to_owned()),
+                Array::Associative(elements) => elements.insert("0".into(), value.to_owned()),
+            };
+        } else if self.is_exported(name) {
+            self.pending.remove(name);
+            self.environment.0.insert(name.to_owned(), value.to_owned());
+        } else {
+            self.shell.insert(name.to_owned(), value.to_owned());
+        }
+    }
+
+    /// Sets `name` to `value` the way an assignment in a script does, which
+    /// fails if it is read-only. A name like `a[1]` sets one element of an
+    /// array.
+    pub(crate) fn assign(&mut self, name: &str, value: &str) -> Result<(), RushError> {
+        if let Some((name, subscript)) = split_subscript(name) {
+            return self.assign_element(name, subscript, value);
+        }
+        let value = self.value_for(name, value)?;
+        self.set(name, &value);
+        Ok(())
+    }
+
+    /// The value `name` would actually hold if `value` were assigned to it,
+    /// which for an integer is the result of evaluating it.
+    pub(crate) fn value_for(&self, name: &str, value: &str) -> Result<String, RushError> {
+        let attributes = self.attributes(name);
+        if attributes.readonly {
+            return Err(RushError::ReadonlyVariable(name.to_owned()));
+        }
+        if !attributes.integer {
+            return Ok(value.to_owned());
+        }
+        arith::evaluate(value, self)
+            .map(|value| value.to_string())
+            .map_err(|msg| RushError::Arithmetic {
+                expr: value.to_owned(),
+                msg,
+            })
+    }
+
+    pub(crate) fn array(&self, name: &str) -> Option<&Array> {
+        self.arrays.get(name)
+    }
+
+    /// Replaces `name` with an array of `items`, as `name=(a b c)` does.
+    /// An item with a subscript, as in `[2]=b`, goes at that index or key,
+    /// and the items after it follow on from there.
+    pub(crate) fn assign_array(
+        &mut self,
+        name: &str,
+        items: Vec<(Option<String>, String)>,
+    ) -> Result<(), RushError> {
+        let attributes = self.attributes(name);
+        if attributes.readonly {
+            return Err(RushError::ReadonlyVariable(name.to_owned()));
+        }
+
+        let mut array = match attributes.associative {
+            true => Array::Associative(BTreeMap::new()),
+            false => Array::Indexed(BTreeMap::new()),
+        };
+        let mut next = 0;
+        for (subscript, value) in items {
+            let value = self.value_for(name, &value)?;
+            match &mut array {
+                Array::Associative(elements) => {
+                    let key = subscript.ok_or_else(|| RushError::BadSubscript(value.clone()))?;
+                    elements.insert(key, value);
+                }
+                Array::Indexed(elements) => {
+                    if let Some(subscript) = subscript {
+                        next = self.index(name, &subscript, elements)?;
+                    }
+                    elements.insert(next, value);
+                    next += 1;
+                }
+            }
+        }
+
+        self.remove_value(name);
+        self.arrays.insert(name.to_owned(), array);
+        Ok(())
+    }
+
+    /// Sets one element of the array `name`, making it an array if it
+    /// isn't one yet, with any value it had as its first element.
+    fn assign_element(
+        &mut self,
+        name: &str,
+        subscript: &str,
+        value: &str,
+    ) -> Result<(), RushError> {
+        let value = self.value_for(name, value)?;
+        if !self.arrays.contains_key(name) {
+            let array = match self.attributes(name).associative {
+                true => Array::Associative(BTreeMap::new()),
+                false => {
+                    Array::Indexed(self.get(name).map(|value| (0, value)).into_iter().collect())
+                }
+            };
+            self.remove_value(name);
+            self.arrays.insert(name.to_owned(), array);
+        }
+
+        let index = match &self.arrays[name] {
+            Array::Indexed(elements) => Some(self.index(name, subscript, elements)?),
+            Array::Associative(_) => None,
+        };
+        match self.arrays.get_mut(name).expect("just made") {
+            Array::Indexed(elements) => elements.insert(index.expect("indexed"), value),
+            Array::Associative(elements) => elements.insert(subscript.to_owned(), value),
+        };
+        Ok(())
+    }
+
+    /// The element of `name` that `subscript` names: a key of an
+    /// associative array, or an index evaluated as arithmetic, counting
+    /// back from the end if it is negative. Any variable is an array of one
+    /// at index 0.
+    pub(crate) fn element(&self, name: &str, subscript: &str) -> Result<Option<String>, RushError> {
+        match self.arrays.get(name) {
+            Some(Array::Associative(elements)) => Ok(elements.get(subscript).cloned()),
+            Some(Array::Indexed(elements)) => {
+                let index = self.index(name, subscript, elements)?;
+                Ok(elements.get(&index).cloned())
+            }
+            None => match self.index(name, subscript, &BTreeMap::new())? {
+                0 => Ok(self.get(name)),
+                _ => Ok(None),
+            },
+        }
+    }
+
+    /// Every element of `name`, or its value alone if it isn't an array.
+    pub(crate) fn values(&self, name: &str) -> Vec<String> {
+        match self.arrays.get(name) {
+            Some(array) => array.values(),
+            None => self.get(name).into_iter().collect(),
+        }
+    }
+
+    /// Evaluates `subscript` as an index into `elements`.
+    fn index(
+        &self,
+        name: &str,
+        subscript: &str,
+        elements: &BTreeMap<usize, String>,
+    ) -> Result<usize, RushError> {
+        let index = arith::evaluate(subscript, self).map_err(|msg| RushError::Arithmetic {
+            expr: subscript.to_owned(),
+            msg,
+        })?;
+        let end = elements.keys().next_back().map_or(0, |last| last + 1);
+        let index = match index < 0 {
+            true => end.checked_sub(index.unsigned_abs() as usize),
+            false => Some(index as usize),
+        };
+        index.ok_or_else(|| RushError::BadSubscript(format!("{name}[{subscript}]")))
+    }
+
+    pub(crate) fn attributes(&self, name: &str) -> Attributes {
+        self.attributes.get(name).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn attributes_mut(&mut self, name: &str) -> &mut Attributes {
+        self.attributes.entry(name.to_owned()).or_default()
+    }
+
+    /// The name of every variable, set or only declared, sorted.
+    pub(crate) fn names(&self) -> Vec<String> {
+        let mut names: BTreeSet<String> = self.shell.keys().cloned().collect();
+        names.extend(self.arrays.keys().cloned());
+        names.extend(self.environment.0.keys().cloned());
+        names.extend(self.attributes.keys().cloned());
+        names.into_iter().collect()
+    }
+
+    /// Whether `name` is exported, or will be once it has a value.
+    pub(crate) fn is_exported(&self, name: &str) -> bool {
+        self.pending.contains(name) || self.environment.0.contains_key(name)
+    }
+
+    /// Moves `name` into the environment, setting it to `value` if given. A
+    /// variable without a value is exported as soon as it gets one.
+    pub(crate) fn export(&mut self, name: &str, value: Option<&str>) {
+        let value = value.map(str::to_owned).or_else(|| self.shell.remove(name));
+        self.shell.remove(name);
+        match value {
+            Some(value) => {
+                self.pending.remove(name);
+                self.environment.0.insert(name.to_owned(), value);
+            }
+            None if !self.environment.0.contains_key(name) => {
+                self.pending.insert(name.to_owned());
+            }
+            None => {}
+        }
+    }
+
+    /// Moves `name` out of the environment, so it stays a shell variable
+    /// that programs no longer inherit.
+    pub(crate) fn unexport(&mut self, name: &str) {
+        self.pending.remove(name);
+        if let Some(value) = self.environment.0.remove(name) {
+            self.shell.insert(name.to_owned(), value);
+        }
+    }
+
+    /// Removes `name`, wherever it lives, along with its attributes.
+    pub(crate) fn unset(&mut self, name: &str) {
+        self.attributes.remove(name);
+        self.remove_value(name);
+    }
+
+    /// Removes the value of `name`, scalar or array, but not its
+    /// attributes.
+    fn remove_value(&mut self, name: &str) {
+        self.shell.remove(name);
+        self.arrays.remove(name);
+        self.pending.remove(name);
+        self.environment.0.remove(name);
+    }
+
+    /// Starts the scope of a function call, whose `local` variables are put
+    /// back by the matching [`Variables::pop_scope`].
+    pub(crate) fn push_scope(&mut self) {
+        self.scopes.push(BTreeMap::new());
+    }
+
+    /// Ends the innermost function call, giving each of its `local`
+    /// variables back what it had before.
+    pub(crate) fn pop_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        for (name, saved) in scope {
+            self.unset(&name);
+            if let Some(value) = saved.value {
+                self.set(&name, &value);
+            }
+            if let Some(array) = saved.array {
+                self.arrays.insert(name.clone(), array);
+            }
+            self.attributes.insert(name, saved.attributes);
+        }
+    }
+
+    /// Whether a function is running, so there is a scope to declare
+    /// `local` variables in.
+    pub(crate) fn in_function(&self) -> bool {
+        !self.scopes.is_empty()
+    }
+
+    /// Makes `name` local to the innermost function call, so it gets its
+    /// current value and attributes back once the function returns. Until
+    /// then, it has none of those attributes. Returns whether a function is
+    /// running.
+    pub(crate) fn local(&mut self, name: &str) -> bool {
+        let array = self.arrays.get(name).cloned();
+        let saved = Saved {
+            value: array.is_none().then(|| self.get(name)).flatten(),
+            array,
+            attributes: self.attributes(name),
+        };
+        let Some(scope) = self.scopes.last_mut() else {
+            return false;
+        };
+        if !scope.contains_key(name) {
+            scope.insert(name.to_owned(), saved);
+            self.attributes.remove(name);
+            self.arrays.remove(name);
+        }
+        true
+    }
+
+    /// Every exported variable with its value, sorted by name.
+    pub(crate) fn exported(&self) -> Vec<(String, String)> {
+        let vars = &self.environment.0;
+        vars.iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_variables_stay_out_of_the_environment() {
+        let mut vars = Variables::default();
+        let exported = |vars: &Variables| {
+            let exported = vars.exported().into_iter().collect::<BTreeMap<_, _>>();
+            exported.get("RUSH_VARIABLES_TEST_SHELL").cloned()
+        };
+        vars.set("RUSH_VARIABLES_TEST_SHELL", "one");
+        assert_eq!(vars.get("RUSH_VARIABLES_TEST_SHELL").unwrap(), "one");
+        assert_eq!(exported(&vars), None);
+
+        vars.export("RUSH_VARIABLES_TEST_SHELL", None);
+        assert_eq!(exported(&vars).unwrap(), "one");
+
+        // Once exported, it stays exported when set again
+        vars.set("RUSH_VARIABLES_TEST_SHELL", "two");
+        assert_eq!(exported(&vars).unwrap(), "two");
+
+        vars.unexport("RUSH_VARIABLES_TEST_SHELL");
+        assert_eq!(exported(&vars), None);
+        assert_eq!(vars.get("RUSH_VARIABLES_TEST_SHELL").unwrap(), "two");
+        // None of it reaches the process's own environment
+        assert!(env::var_os("RUSH_VARIABLES_TEST_SHELL").is_none());
+    }
+
+    #[test]
+    fn exporting_an_unset_variable_waits_for_its_value() {
+        let mut vars = Variables::default();
+        vars.export("RUSH_VARIABLES_TEST_PENDING", None);
+        assert!(vars.get("RUSH_VARIABLES_TEST_PENDING").is_none());
+
+        vars.set("RUSH_VARIABLES_TEST_PENDING", "now");
+        assert!(vars.is_exported("RUSH_VARIABLES_TEST_PENDING"));
+        assert!((vars.exported()).contains(&("RUSH_VARIABLES_TEST_PENDING".into(), "now".into())));
+    }
+
+    #[test]
+    fn locals_are_put_back_when_their_scope_ends() {
+        let mut vars = Variables::default();
+        vars.set("RUSH_VARIABLES_TEST_OUTER", "outer");
+        assert!(!vars.local("RUSH_VARIABLES_TEST_OUTER"));
+
+        vars.push_scope();
+        assert!(vars.local("RUSH_VARIABLES_TEST_OUTER"));
+        vars.set("RUSH_VARIABLES_TEST_OUTER", "inner");
+        vars.local("RUSH_VARIABLES_TEST_NEW");
+        vars.set("RUSH_VARIABLES_TEST_NEW", "new");
+
+        // A second `local` of the same name keeps the first saved value
+        vars.local("RUSH_VARIABLES_TEST_OUTER");
+        vars.pop_scope();
+        assert_eq!(vars.get("RUSH_VARIABLES_TEST_OUTER").unwrap(), "outer");
+        assert!(vars.get("RUSH_VARIABLES_TEST_NEW").is_none());
+    }
+
+    #[test]
+    fn dynamic_variables_are_worked_out_when_read() {
+        let mut vars = Variables::default();
+        vars.set("RANDOM", "7");
+        let first = vars.get("RANDOM").unwrap();
+        assert_ne!(vars.get("RANDOM").unwrap(), first);
+        vars.set("RANDOM", "7");
+        assert_eq!(vars.get("RANDOM").unwrap(), first);
+        assert!(first.parse::<u32>().unwrap() < 32768);
+
+        assert_eq!(vars.get("SECONDS").unwrap(), "0");
+        vars.set("SECONDS", "90");
+        assert_eq!(vars.get("SECONDS").unwrap(), "90");
+
+        vars.set_line(12);
+        vars.set("LINENO", "1");
+        assert_eq!(vars.get("LINENO").unwrap(), "12");
+    }
+
+    #[test]
+    fn attributes_shape_assignments() {
+        let mut vars = Variables::default();
+        vars.attributes_mut("RUSH_VARIABLES_TEST_INT").integer = true;
+        vars.assign("RUSH_VARIABLES_TEST_INT", "2 * 3").unwrap();
+        assert_eq!(vars.get("RUSH_VARIABLES_TEST_INT").unwrap(), "6");
+        let error = vars.assign("RUSH_VARIABLES_TEST_INT", "1 /").unwrap_err();
+        assert_eq!(error.to_string(), "1 /: syntax error: operand expected");
+
+        vars.attributes_mut("RUSH_VARIABLES_TEST_INT").readonly = true;
+        let error = vars.assign("RUSH_VARIABLES_TEST_INT", "7").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "RUSH_VARIABLES_TEST_INT: readonly variable"
+        );
+        assert_eq!(vars.get("RUSH_VARIABLES_TEST_INT").unwrap(), "6");
+    }
+
+    #[test]
+    fn arrays() {
+        let mut vars = Variables::default();
+        let items = [(None, "a"), (Some("3"), "d"), (None, "e")]
+            .map(|(key, value)| (key.map(String::from), value.to_owned()));
+        vars.assign_array("RUSH_VARIABLES_TEST_ARRAY", items.to_vec())
+            .unwrap();
+        assert_eq!(vars.values("RUSH_VARIABLES_TEST_ARRAY"), ["a", "d", "e"]);
+        assert_eq!(vars.get("RUSH_VARIABLES_TEST_ARRAY").unwrap(), "a");
+
+        let element = |vars: &Variables, subscript| {
+            vars.element("RUSH_VARIABLES_TEST_ARRAY", subscript)
+                .unwrap()
+        };
+        assert_eq!(element(&vars, "1 + 2").unwrap(), "d");
+        assert_eq!(element(&vars, "-1").unwrap(), "e");
+        assert!(element(&vars, "1").is_none());
+        assert!(vars.element("RUSH_VARIABLES_TEST_ARRAY", "-9").is_err());
+
+        // A scalar becomes the first element of the array it turns into
+        vars.set("RUSH_VARIABLES_TEST_SCALAR", "x");
+        vars.assign("RUSH_VARIABLES_TEST_SCALAR[2]", "z").unwrap();
+        assert_eq!(vars.values("RUSH_VARIABLES_TEST_SCALAR"), ["x", "z"]);
+        assert_eq!(
+            vars.array("RUSH_VARIABLES_TEST_SCALAR").unwrap().keys(),
+            ["0", "2"]
+        );
+    }
+
+    #[test]
+    fn associative_arrays() {
+        let mut vars = Variables::default();
+        vars.attributes_mut("RUSH_VARIABLES_TEST_MAP").associative = true;
+        vars.assign("RUSH_VARIABLES_TEST_MAP[b c]", "1").unwrap();
+        vars.assign("RUSH_VARIABLES_TEST_MAP[a]", "2").unwrap();
+        assert_eq!(vars.values("RUSH_VARIABLES_TEST_MAP"), ["2", "1"]);
+        assert_eq!(
+            vars.element("RUSH_VARIABLES_TEST_MAP", "b c")
+                .unwrap()
+                .unwrap(),
+            "1"
+        );
+
+        let error = vars
+            .assign_array("RUSH_VARIABLES_TEST_MAP", vec![(None, "v".into())])
+            .unwrap_err();
+        assert_eq!(error.to_string(), "v: bad array subscript");
+    }
+}
@@ -0,0 +1,80 @@
+use std::borrow::Cow;
+
+use crate::{command::single_quote, state::ShellState};
+
+/// Shows a command about to run with `set -x` on: its variable assignments,
+/// one to a line, then its expanded words, each quoted if it needs to be to
+/// read back the same.
+pub(crate) fn command(assignments: &[(String, String)], words: &[String], state: &ShellState) {
+    if !state.options.xtrace {
+        return;
+    }
+    for (name, value) in assignments {
+        eprintln!("{}{name}={}", prefix(state), quote(value));
+    }
+    if !words.is_empty() {
+        let words: Vec<_> = words.iter().map(|word| quote(word)).collect();
+        eprintln!("{}{}", prefix(state), words.join(" "));
+    }
+}
+
+/// Shows an array assignment about to be made with `set -x` on, with the
+/// subscripts given for any of its items.
+pub(crate) fn array(name: &str, items: &[(Option<String>, String)], state: &ShellState) {
+    if state.options.xtrace {
+        let items: Vec<_> = items
+            .iter()
+            .map(|(subscript, value)| match subscript {
+                Some(subscript) => format!("[{subscript}]={}", quote(value)),
+                None => quote(value).into_owned(),
+            })
+            .collect();
+        eprintln!("{}{name}=({})", prefix(state), items.join(" "));
+    }
+}
+
+/// `$PS4`, with its first character repeated once more for each command
+/// substitution the command runs inside, like bash.
+fn prefix(state: &ShellState) -> String {
+    let ps4 = state.vars.get("PS4").unwrap_or_else(|| "+ ".into());
+    let nesting = ps4
+        .chars()
+        .next()
+        .map(|c| c.to_string().repeat(state.substitutions))
+        .unwrap_or_default();
+    nesting + &ps4
+}
+
+fn quote(word: &str) -> Cow<'_, str> {
+    let plain = |c: char| c.is_alphanumeric() || "%+,-./:=@_^".contains(c);
+    match !word.is_empty() && word.chars().all(plain) {
+        true => Cow::Borrowed(word),
+        false => Cow::Owned(single_quote(word)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_only_words_that_need_it() {
+        assert_eq!(quote("a=b/c.txt"), "a=b/c.txt");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("d e"), "'d e'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote("$x*"), "'$x*'");
+    }
+
+    #[test]
+    fn nesting_repeats_the_first_character() {
+        let mut state = ShellState::default();
+        assert_eq!(prefix(&state), "+ ");
+        state.substitutions = 2;
+        assert_eq!(prefix(&state), "+++ ");
+        state.vars.set("PS4", ">> ");
+        assert_eq!(prefix(&state), ">>>> ");
+        state.vars.set("PS4", "");
+        assert_eq!(prefix(&state), "");
+    }
+}
@@ -0,0 +1,255 @@
+//! Runs every case in `tests/conformance/` through rush and compares the
+//! result with the recorded expectations.
+//!
+//! A case is a `.case` file made of `--- section` headers:
+//!
+//! ```text
+//! --- script
+//! echo 'a  b'
+//! --- stdout
+//! a  b
+//! --- status
+//! 0
+//! ```
+//!
+//! `script` is written to a file that rush runs as a script, the way
+//! `rush path` does, with nothing on stdin. `stdout` and `status` are always
+//! checked; `stderr` is only checked when the section is present, since the
+//! wording of error messages differs between shells. A `divergent` section
+//! holds the reason rush knowingly differs from the reference shell, in which
+//! case a mismatch is reported but doesn't fail the run.
+//!
+//! Run with `REFERENCE=1` to regenerate the expectations of every case from
+//! `/bin/sh` instead of checking rush against them.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+const RUSH: &str = env!("CARGO_BIN_EXE_codecrafters-shell");
+const REFERENCE_SHELL: &str = "/bin/sh";
+
+#[derive(Debug, Default, PartialEq)]
+struct Case {
+    script: String,
+    stdout: String,
+    stderr: Option<String>,
+    status: i32,
+    divergent: Option<String>,
+}
+
+impl Case {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut case = Case::default();
+        let mut sections: Vec<(&str, String)> = Vec::new();
+
+        for line in text.split_inclusive('\n') {
+            if let Some(name) = line.strip_prefix("--- ") {
+                sections.push((name.trim(), String::new()));
+            } else if let Some((_, body)) = sections.last_mut() {
+                body.push_str(line);
+            } else if !line.trim().is_empty() {
+                return Err(format!("text before the first section: {line:?}"));
+            }
+        }
+
+        for (name, body) in sections {
+            match name {
+                "script" => case.script = body,
+                "stdout" => case.stdout = body,
+                "stderr" => case.stderr = Some(body),
+                "status" => {
+                    case.status = body
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("bad status {:?}", body.trim()))?
+                }
+                "divergent" => case.divergent = Some(body.trim().to_owned()),
+                other => return Err(format!("unknown section `{other}`")),
+            }
+        }
+
+        Ok(case)
+    }
+
+    fn render(&self) -> String {
+        let mut text = format!("--- script\n{}--- stdout\n{}", self.script, self.stdout);
+        if let Some(stderr) = &self.stderr {
+            text.push_str(&format!("--- stderr\n{stderr}"));
+        }
+        text.push_str(&format!("--- status\n{}\n", self.status));
+        if let Some(reason) = &self.divergent {
+            text.push_str(&format!("--- divergent\n{reason}\n"));
+        }
+        text
+    }
+}
+
+struct Outcome {
+    stdout: String,
+    stderr: String,
+    status: i32,
+}
+
+/// Runs `script` through `shell` in a scratch directory that is also HOME, so
+/// the user's own startup files can't leak into the results. The script
+/// itself is kept outside it, out of the way of anything that lists it.
+fn run(shell: &str, script: &str, name: &str) -> Outcome {
+    let scratch = env::temp_dir().join(format!("rush_conformance_{name}_{}", std::process::id()));
+    fs::create_dir_all(&scratch).unwrap();
+    let path = scratch.with_extension("sh");
+    fs::write(&path, script).unwrap();
+
+    let output = Command::new(shell)
+        .arg(&path)
+        .current_dir(&scratch)
+        .env("HOME", &scratch)
+        .env("RUSH_CONFIG", scratch.join("no-config.toml"))
+        .env_remove("__RUSH_NESTING_DEPTH")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&scratch).ok();
+    fs::remove_file(&path).ok();
+
+    Outcome {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        status: output.status.code().unwrap_or(-1),
+    }
+}
+
+/// Renders a line-by-line comparison, marking lines only in the expectation
+/// with `-` and lines only in the actual output with `+`.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<_> = expected.lines().collect();
+    let actual: Vec<_> = actual.lines().collect();
+
+    // Longest common subsequence table, filled from the end
+    let mut lcs = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut out = String::new();
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            out.push_str(&format!("      {}\n", expected[i]));
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == actual.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push_str(&format!("    - {}\n", expected[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("    + {}\n", actual[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+/// Describes every way `outcome` differs from `case`, or `None` if it matches.
+fn compare(case: &Case, outcome: &Outcome) -> Option<String> {
+    let mut problems = String::new();
+
+    if outcome.stdout != case.stdout {
+        problems.push_str(&format!(
+            "  stdout differs:\n{}",
+            diff(&case.stdout, &outcome.stdout)
+        ));
+    }
+    if let Some(stderr) = &case.stderr
+        && outcome.stderr != *stderr
+    {
+        problems.push_str(&format!(
+            "  stderr differs:\n{}",
+            diff(stderr, &outcome.stderr)
+        ));
+    }
+    if outcome.status != case.status {
+        problems.push_str(&format!(
+            "  status: expected {}, got {}\n",
+            case.status, outcome.status
+        ));
+    }
+
+    (!problems.is_empty()).then_some(problems)
+}
+
+fn corpus() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let mut cases: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "case"))
+        .collect();
+    cases.sort();
+    cases
+}
+
+fn case_name(path: &Path) -> String {
+    path.file_stem().unwrap().to_string_lossy().into_owned()
+}
+
+#[test]
+fn conformance() {
+    let regenerate = env::var_os("REFERENCE").is_some_and(|v| v == "1");
+    let mut failures = Vec::new();
+
+    for path in corpus() {
+        let name = case_name(&path);
+        let mut case = Case::parse(&fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|error| panic!("{name}: {error}"));
+
+        if regenerate {
+            let outcome = run(REFERENCE_SHELL, &case.script, &name);
+            case.stdout = outcome.stdout;
+            case.stderr = case.stderr.map(|_| outcome.stderr);
+            case.status = outcome.status;
+            fs::write(&path, case.render()).unwrap();
+            continue;
+        }
+
+        let outcome = run(RUSH, &case.script, &name);
+        match (compare(&case, &outcome), &case.divergent) {
+            (Some(problems), None) => failures.push(format!("{name}:\n{problems}")),
+            (Some(_), Some(reason)) => eprintln!("{name}: known divergence: {reason}"),
+            (None, Some(_)) => eprintln!("{name}: matches now; remove its `divergent` section"),
+            (None, None) => {}
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} conformance case(s) failed:\n\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn case_format_round_trips() {
+    let text =
+        "--- script\necho hi\n--- stdout\nhi\n--- stderr\n--- status\n0\n--- divergent\nbecause\n";
+    let case = Case::parse(text).unwrap();
+    assert_eq!(case.stderr.as_deref(), Some(""));
+    assert_eq!(case.divergent.as_deref(), Some("because"));
+    assert_eq!(case.render(), text);
+}
+
+#[test]
+fn diff_marks_changed_lines() {
+    assert_eq!(
+        diff("a\nb\nc\n", "a\nx\nc\n"),
+        "      a\n    - b\n    + x\n      c\n"
+    );
+}
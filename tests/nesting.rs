@@ -0,0 +1,73 @@
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+const RUSH: &str = env!("CARGO_BIN_EXE_codecrafters-shell");
+
+/// Creates a fresh HOME directory whose rc file starts rush again.
+fn poisoned_home(name: &str) -> PathBuf {
+    let home = env::temp_dir().join(format!("rush_nesting_{name}_{}", std::process::id()));
+    fs::create_dir_all(&home).unwrap();
+    fs::write(home.join(".rushrc"), format!("{RUSH}\nexit\n")).unwrap();
+    home
+}
+
+fn run_rush(home: &PathBuf, max_nesting: &str, stdin: &str) -> Output {
+    let mut child = Command::new(RUSH)
+        .env("HOME", home)
+        .env("RUSH_MAX_NESTING", max_nesting)
+        .env_remove("__RUSH_NESTING_DEPTH")
+        .env_remove("__RUSH_SOURCING_RC")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn runaway_rc_drops_to_a_bare_prompt() {
+    let home = poisoned_home("runaway");
+    let output = run_rush(&home, "3", "echo safe-prompt\nexit\n");
+    fs::remove_dir_all(&home).ok();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success());
+    assert!(
+        stderr.contains("nesting depth 4 exceeds the limit of 3"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!(
+            "{} appears to start",
+            home.join(".rushrc").display()
+        )),
+        "{stderr}"
+    );
+    // Only the innermost shell reaches a prompt and runs the user's input
+    assert_eq!(stdout.matches("safe-prompt").count(), 1, "{stdout}");
+}
+
+#[test]
+fn warning_is_printed_once() {
+    let home = poisoned_home("once");
+    let output = run_rush(&home, "2", "exit\n");
+    fs::remove_dir_all(&home).ok();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("exceeds the limit").count(), 1, "{stderr}");
+}
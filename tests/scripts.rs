@@ -0,0 +1,421 @@
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+const RUSH: &str = env!("CARGO_BIN_EXE_codecrafters-shell");
+
+/// Writes `text` to a fresh script file named after the test.
+fn script(name: &str, text: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("rush_script_{name}_{}.sh", std::process::id()));
+    fs::write(&path, text).unwrap();
+    path
+}
+
+fn run_rush(args: &[&str]) -> Output {
+    Command::new(RUSH)
+        .args(args)
+        .env_remove("__RUSH_NESTING_DEPTH")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn runs_every_line_and_exits_with_the_last_status() {
+    let path = script(
+        "lines",
+        "#!/usr/bin/env rush\necho \"$# $1 $2\"\necho 'multi\nline' &&\n  echo done\nfalse\n",
+    );
+    let output = run_rush(&[path.to_str().unwrap(), "a", "b c"]);
+    fs::remove_file(&path).ok();
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "2 a b c\nmulti\nline\ndone\n"
+    );
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn exit_ends_the_script() {
+    let path = script("exit", "echo before\nexit 7\necho after\n");
+    let output = run_rush(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).ok();
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "before\n");
+    assert_eq!(output.status.code(), Some(7));
+}
+
+#[test]
+fn scripts_that_cannot_be_read() {
+    let missing = env::temp_dir().join("rush_script_missing.sh");
+    let output = run_rush(&[missing.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(127));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("rush: "), "{stderr}");
+
+    let output = run_rush(&[env::temp_dir().to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(126));
+}
+
+#[test]
+fn xtrace_shows_commands_as_they_run() {
+    let path = script(
+        "xtrace",
+        "set -x\nx=$(echo \"d e\") echo \"$x\" \"it's\"\nPS4='> '\necho $(true)\nset +x\necho off\n",
+    );
+    let output = run_rush(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).ok();
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), " it's\n\noff\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "++ echo 'd e'\n+ x='d e'\n+ echo '' 'it'\\''s'\n+ PS4='> '\n>> true\n> echo\n> set +x\n"
+    );
+}
+
+#[test]
+fn children_see_the_shell_level_and_directory() {
+    let path = script(
+        "environment",
+        "sh -c 'echo $SHLVL $PWD'\ncd /\nsh -c 'echo $PWD $OLDPWD'\n",
+    );
+    let dir = env::temp_dir().canonicalize().unwrap();
+    let output = Command::new(RUSH)
+        .arg(&path)
+        .current_dir(&dir)
+        .env("SHLVL", "3")
+        .env("PWD", "/nowhere")
+        .output()
+        .unwrap();
+    fs::remove_file(&path).ok();
+
+    let dir = dir.display();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("4 {dir}\n/ {dir}\n")
+    );
+}
+
+#[test]
+fn login_shells_source_the_profile_and_logout_files() {
+    let home = env::temp_dir().join(format!("rush_login_home_{}", std::process::id()));
+    fs::create_dir_all(&home).unwrap();
+    fs::write(
+        home.join(".rush_profile"),
+        "if true\nthen\n  greet=hi\nfi\n",
+    )
+    .unwrap();
+    fs::write(home.join(".rush_logout"), "echo bye $greet\nexit 3\n").unwrap();
+    let path = script("login", "echo $greet\n");
+
+    let run = |args: &[&std::ffi::OsStr]| {
+        Command::new(RUSH)
+            .args(args)
+            .env("HOME", &home)
+            .env_remove("__RUSH_NESTING_DEPTH")
+            .output()
+            .unwrap()
+    };
+    let login = run(&["-l".as_ref(), path.as_os_str()]);
+    let plain = run(&[path.as_os_str()]);
+    fs::remove_file(&path).ok();
+    fs::remove_dir_all(&home).ok();
+
+    // Only the end is checked, since `/etc/profile` may have its say first
+    let stdout = String::from_utf8_lossy(&login.stdout);
+    assert!(stdout.ends_with("hi\nbye hi\n"), "{stdout}");
+    assert_eq!(login.status.code(), Some(3));
+    assert_eq!(String::from_utf8_lossy(&plain.stdout), "\n");
+    assert_eq!(plain.status.code(), Some(0));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn programs_write_straight_to_the_shells_stdout() {
+    let path = script(
+        "stdout",
+        "readlink /proc/self/fd/1 /proc/self/fd/2\necho \"$(readlink /proc/self/fd/1)\"\n",
+    );
+    let log = env::temp_dir().join(format!("rush_stdout_{}.log", std::process::id()));
+    let status = Command::new(RUSH)
+        .arg(&path)
+        .stdout(fs::File::create(&log).unwrap())
+        .stderr(fs::File::create(&log).unwrap())
+        .status()
+        .unwrap();
+    let written = fs::read_to_string(&log).unwrap();
+    fs::remove_file(&path).ok();
+    fs::remove_file(&log).ok();
+
+    assert!(status.success());
+    let lines: Vec<_> = written.lines().collect();
+    // A command substitution still captures what it runs through a pipe
+    assert_eq!(lines[..2], [log.to_str().unwrap(); 2]);
+    assert!(lines[2].starts_with("pipe:"), "{written}");
+}
+
+#[cfg(unix)]
+#[test]
+fn scripts_without_an_interpreter_line_run_with_sh() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let legacy = script("legacy", "echo legacy \"$1\"\nexit 4\n");
+    fs::set_permissions(&legacy, fs::Permissions::from_mode(0o755)).unwrap();
+    let legacy = legacy.to_str().unwrap();
+    let path = script(
+        "runs_legacy",
+        &format!("{legacy} a\necho $?\n{legacy} b | cat\n"),
+    );
+    let output = run_rush(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).ok();
+    fs::remove_file(legacy).ok();
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "legacy a\n4\nlegacy b\n"
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn signals_sent_to_the_shell_reach_the_program_it_waits_for() {
+    use std::{os::unix::process::ExitStatusExt, process::Stdio, thread, time::Duration};
+
+    let path = script("forward", "sleep 5\necho $?\n");
+    let child = Command::new(RUSH)
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Only once it's waiting for `sleep` does the shell have it to pass on
+    // to, a moment after the child shows up
+    let children = format!("/proc/{0}/task/{0}/children", child.id());
+    while fs::read_to_string(&children)
+        .unwrap_or_default()
+        .trim()
+        .is_empty()
+    {
+        thread::sleep(Duration::from_millis(10));
+    }
+    thread::sleep(Duration::from_millis(200));
+    Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&path).ok();
+
+    // Once `sleep` is gone, the shell ends by the signal too, without going
+    // on with the script
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "Terminated\n");
+    assert_eq!(output.status.signal(), Some(15));
+}
+
+#[test]
+fn the_last_argument_is_kept_in_underscore() {
+    let dir = env::temp_dir().join(format!("rush_underscore_{}", std::process::id()));
+    let path = script(
+        "underscore",
+        &format!(
+            "echo \"$_\"\ntrue y; echo $_ z\nf() {{ echo \"[$_]\"; }}\nf arg\nmkdir {0} && cd $_ && pwd\n",
+            dir.display()
+        ),
+    );
+    let output = run_rush(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).ok();
+    fs::remove_dir(&dir).ok();
+
+    // It starts out as the shell, and isn't passed on as it was inherited
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{RUSH}\ny z\n[z]\n{}\n", dir.display())
+    );
+}
+
+#[test]
+fn restricted_shells_stay_where_they_are_put() {
+    let path = script(
+        "restricted",
+        "cd /\nPATH=/nowhere\n/bin/echo path\necho file > /dev/null\nexec true\necho \"$? $PWD\" >&2\nls -d /\n",
+    );
+    let dir = env::temp_dir().canonicalize().unwrap();
+    let output = Command::new(RUSH)
+        .args(["-r".as_ref(), path.as_os_str()])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).ok();
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "/\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!(
+            "cd: restricted\nPATH: readonly variable\n\
+             /bin/echo: restricted: cannot specify `/' in command names\n\
+             /dev/null: restricted: cannot redirect output\nexec: restricted\n1 {}\n",
+            dir.display()
+        )
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn redirections_honor_the_umask_and_leave_no_stray_descriptors() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = env::temp_dir().join(format!("rush_umask_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = script(
+        "umask",
+        "exec 4>kept\necho file >made\nls /proc/self/fd <<EOF | cat\nbody\nEOF\n",
+    );
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!("umask 027 && exec {RUSH} {}", path.display()))
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).ok();
+    let modes = ["kept", "made"].map(|name| {
+        let metadata = fs::metadata(dir.join(name)).unwrap();
+        metadata.permissions().mode() & 0o777
+    });
+    fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(modes, [0o640, 0o640]);
+    // ls's own listing of the directory is 3, and `exec` gave every command 4
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "0\n1\n2\n3\n4\n");
+}
+
+#[test]
+fn scripts_can_open_any_low_descriptor() {
+    let dir = env::temp_dir().join(format!("rush_fd3_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = script(
+        "fd3",
+        "exec 3>out\necho to-3 >&3\nexec 3>&-\necho after\ncat out\necho gone >&3\n",
+    );
+    let output = Command::new(RUSH)
+        .arg(&path)
+        .current_dir(&dir)
+        .env_remove("__RUSH_NESTING_DEPTH")
+        .output()
+        .unwrap();
+    fs::remove_file(&path).ok();
+    fs::remove_dir_all(&dir).ok();
+
+    // Once closed, 3 can't be written to, but the script carries on
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "3: bad file descriptor\n"
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "after\nto-3\n");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn read_takes_lines_from_a_descriptor_exec_opened() {
+    let dir = env::temp_dir().join(format!("rush_read_fd_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("config"), "name=rush\nlevel 2\n").unwrap();
+    let path = script(
+        "read_fd",
+        "exec 3<config\nread -u 3 line\nread -u 3 key value\necho \"$line|$key|$value\"\nread -u 3 || echo end\n",
+    );
+    let output = Command::new(RUSH)
+        .arg(&path)
+        .current_dir(&dir)
+        .env_remove("__RUSH_NESTING_DEPTH")
+        .output()
+        .unwrap();
+    fs::remove_file(&path).ok();
+    fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "name=rush|level|2\nend\n"
+    );
+}
+
+#[test]
+fn subshells_see_the_jobs_of_the_shell() {
+    let path = script(
+        "jobs",
+        "sleep 5 &\njobs -p | wc -l\ntest \"$(jobs -p)\" = \"$!\" && echo same\nkill %1\n",
+    );
+    let output = run_rush(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).ok();
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\nsame\n");
+}
+
+#[test]
+fn anything_can_be_put_in_the_background() {
+    use std::time::{Duration, Instant};
+
+    let path = script(
+        "background",
+        "f() { sleep 5; }
+{ sleep 5; } > /dev/null &
+while true; do sleep 1; done > /dev/null &
+f > /dev/null &
+echo started
+jobs | wc -l
+kill %1 %2 %3
+wait
+{ echo group; } &
+wait
+x=1 &
+wait
+echo \"x=$x\"
+",
+    );
+    let started = Instant::now();
+    let output = run_rush(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).ok();
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "started\n3\ngroup\nx=\n"
+    );
+    assert!(started.elapsed() < Duration::from_secs(4));
+}
+
+#[test]
+fn stages_end_once_nothing_reads_what_they_write() {
+    use std::{
+        process::Stdio,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    let path = script(
+        "broken_pipe",
+        "while true; do echo y; done | head -2\necho \"${PIPESTATUS[@]}\"\necho a | echo b\n",
+    );
+    let mut child = Command::new(RUSH)
+        .arg(&path)
+        .env_remove("__RUSH_NESTING_DEPTH")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let started = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if started.elapsed() > Duration::from_secs(5) {
+            child.kill().ok();
+            panic!("the loop never stopped");
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&path).ok();
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "y\ny\n141 0\nb\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
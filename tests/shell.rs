@@ -0,0 +1,62 @@
+use rush::{Config, ExitStatus, Shell};
+
+#[test]
+fn lines_run_in_one_shell_until_it_exits() {
+    let mut shell = Shell::new(Config::default());
+    assert_eq!(shell.run_line("greeting=hi; false"), ExitStatus::FAILURE);
+    assert_eq!(
+        shell.run_line("test \"$greeting\" = hi"),
+        ExitStatus::SUCCESS
+    );
+    assert_eq!(
+        shell.run_line("no_such_command_5317"),
+        ExitStatus::NOT_FOUND
+    );
+
+    // Exiting ends the shell, not the program running it
+    assert_eq!(shell.run_line("f() { exit 3; }; f; true").code(), 3);
+    assert_eq!(shell.run_line("true").code(), 3);
+}
+
+#[test]
+fn restricted_shells_are_restricted_from_the_first_line() {
+    let mut shell = Shell::new(Config {
+        restricted: true,
+        ..Config::default()
+    });
+    assert_eq!(shell.run_line("PATH=/nowhere"), ExitStatus::FAILURE);
+    assert_eq!(shell.run_line("cd /"), ExitStatus::FAILURE);
+}
+
+#[test]
+fn shells_keep_their_own_directory_and_environment() {
+    let dir = std::env::temp_dir().join(format!("rush_shell_state_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("sub/file"), "").unwrap();
+    let original = std::env::current_dir().unwrap();
+
+    let mut first = Shell::new(Config::default());
+    let mut second = Shell::new(Config::default());
+    first.run_line(&format!(
+        "cd '{}'; export RUSH_SHELL_TEST=first",
+        dir.display()
+    ));
+    second.run_line("cd /");
+
+    // Relative paths, programs, and their environments follow each shell
+    let check = "test -f sub/file && sh -c 'test \"$RUSH_SHELL_TEST\" = first'";
+    assert_eq!(first.run_line(check), ExitStatus::SUCCESS);
+    assert_eq!(
+        first.run_line("cd sub; ls file >/dev/null"),
+        ExitStatus::SUCCESS
+    );
+    assert_eq!(second.run_line(check), ExitStatus::FAILURE);
+    assert_eq!(std::env::current_dir().unwrap(), original);
+    assert!(std::env::var_os("RUSH_SHELL_TEST").is_none());
+
+    // Nor does a subshell's change reach the shell it came from
+    let isolated = "x=$(cd /; export RUSH_SUBSHELL_TEST=1); true | cd /; \
+        test -f file && test -z \"$RUSH_SUBSHELL_TEST\"";
+    assert_eq!(first.run_line(isolated), ExitStatus::SUCCESS);
+    std::fs::remove_dir_all(&dir).ok();
+}